@@ -0,0 +1,72 @@
+/*
+ * The C API of the Rusty Hog scanner library, built with `cargo build --release --features ffi`.
+ * See src/ffi.rs for how to use it.
+ */
+
+#ifndef RUSTY_HOG_H
+#define RUSTY_HOG_H
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct RustyHogScanner RustyHogScanner;
+typedef struct RustyHogFindings RustyHogFindings;
+
+/* Where a found string is: the line of its first character and of its last, starting at 1, its
+ * first column and the column after its last, counted in characters from 1, and its byte offsets
+ * in the buffer, the end exclusive. */
+typedef struct RustyHogSpan {
+    size_t line;
+    size_t line_end;
+    size_t column_start;
+    size_t column_end;
+    size_t byte_start;
+    size_t byte_end;
+} RustyHogSpan;
+
+/* A finding. The pointers are owned by the RustyHogFindings it came from. */
+typedef struct RustyHogFinding {
+    const char *reason;
+    const char *diff;
+    const char *const *strings_found;
+    size_t strings_found_len;
+    /* The line number, starting at 1 */
+    size_t line;
+    /* The byte offset of the start of the line in the buffer */
+    size_t offset;
+    /* Where each of the strings found is in the buffer, spans_len of them */
+    const RustyHogSpan *spans;
+    size_t spans_len;
+} RustyHogFinding;
+
+/* Creates a scanner from a rule pack JSON string, or with the built-in rules if rules_json is
+ * NULL. Returns NULL if the rule pack is invalid. */
+RustyHogScanner *rusty_hog_scanner_new(const char *rules_json);
+
+/* Frees a scanner. Does nothing if scanner is NULL. */
+void rusty_hog_scanner_free(RustyHogScanner *scanner);
+
+/* Scans len bytes at data, also reporting high entropy strings if entropy is true. Returns NULL
+ * if scanner is NULL or the scan failed. */
+RustyHogFindings *rusty_hog_scan(const RustyHogScanner *scanner, const uint8_t *data, size_t len,
+                                 bool entropy);
+
+/* The number of findings in a list, 0 if findings is NULL. */
+size_t rusty_hog_findings_len(const RustyHogFindings *findings);
+
+/* The finding at index in a list, NULL if findings is NULL or index is out of range. */
+const RustyHogFinding *rusty_hog_findings_get(const RustyHogFindings *findings, size_t index);
+
+/* Frees a list of findings and their strings. Does nothing if findings is NULL. */
+void rusty_hog_findings_free(RustyHogFindings *findings);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* RUSTY_HOG_H */
@@ -0,0 +1,174 @@
+//! Adaptive concurrency for scanners that talk to an API.
+//!
+//! A fixed number of concurrent requests is either too many for a rate-limited SaaS tenant or too
+//! few for a large self-hosted instance. `AdaptiveLimiter` tunes the limit while scanning, the way
+//! TCP tunes its congestion window (additive increase, multiplicative decrease):
+//!
+//! * every request that completes within `ConcurrencyBounds::target_latency` raises the limit by
+//!   `1 / limit`, so roughly one more request is allowed after each full round of requests
+//! * a request that is slower than the target, fails, or is rate limited halves the limit. Only
+//!   requests started after the last decrease can trigger another one, so a burst of slow
+//!   responses to the same round of requests halves the limit once rather than collapsing it.
+//!
+//! The limit starts at `ConcurrencyBounds::min` and always stays within the bounds. Clones share
+//! the same limit, so one limiter can be handed to every worker thread.
+//!
+//! # Examples
+//!
+//! ```
+//! use rusty_hogs::adaptive_concurrency::{AdaptiveLimiter, ConcurrencyBounds};
+//! use std::time::Duration;
+//!
+//! let limiter = AdaptiveLimiter::new(ConcurrencyBounds {
+//!     min: 1,
+//!     max: 8,
+//!     target_latency: Duration::from_secs(1),
+//! });
+//! assert_eq!(limiter.limit(), 1);
+//! for _ in 0..10 {
+//!     let permit = limiter.acquire();
+//!     // ... send a request that returns quickly ...
+//!     permit.success();
+//! }
+//! let raised = limiter.limit();
+//! assert!(raised > 1);
+//! limiter.acquire().overloaded();
+//! assert!(limiter.limit() < raised);
+//! ```
+
+use log::{self, debug};
+use std::hash::{Hash, Hasher};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+/// The range the concurrency limit is tuned within, and the latency it aims for
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub struct ConcurrencyBounds {
+    /// The starting and lowest limit, at least 1
+    pub min: usize,
+    /// The highest limit
+    pub max: usize,
+    /// Requests slower than this lower the limit
+    pub target_latency: Duration,
+}
+
+#[derive(Debug)]
+struct State {
+    limit: f64,
+    in_flight: usize,
+    last_decrease: Instant,
+}
+
+#[derive(Debug)]
+struct Shared {
+    bounds: ConcurrencyBounds,
+    state: Mutex<State>,
+    released: Condvar,
+}
+
+/// Limits the number of concurrent requests, adapting the limit to their latency and errors (see
+/// the module documentation). Clones share the same limit.
+#[derive(Debug, Clone)]
+pub struct AdaptiveLimiter {
+    shared: Arc<Shared>,
+}
+
+/// Permission to send one request, returned by `AdaptiveLimiter::acquire`. Report how the request
+/// went with `success` or `overloaded`; a permit that is just dropped leaves the limit unchanged.
+#[derive(Debug)]
+pub struct Permit {
+    limiter: AdaptiveLimiter,
+    started: Instant,
+}
+
+impl AdaptiveLimiter {
+    /// Create a limiter starting at `bounds.min` concurrent requests
+    pub fn new(bounds: ConcurrencyBounds) -> Self {
+        let min = bounds.min.max(1);
+        let bounds = ConcurrencyBounds {
+            min,
+            max: bounds.max.max(min),
+            ..bounds
+        };
+        Self {
+            shared: Arc::new(Shared {
+                bounds,
+                state: Mutex::new(State {
+                    limit: min as f64,
+                    in_flight: 0,
+                    last_decrease: Instant::now(),
+                }),
+                released: Condvar::new(),
+            }),
+        }
+    }
+
+    /// The current number of requests allowed at once
+    pub fn limit(&self) -> usize {
+        self.shared.state.lock().unwrap().limit as usize
+    }
+
+    /// Blocks until fewer than `limit()` requests are in flight, then returns a permit for one more
+    pub fn acquire(&self) -> Permit {
+        let mut state = self.shared.state.lock().unwrap();
+        while state.in_flight >= state.limit as usize {
+            state = self.shared.released.wait(state).unwrap();
+        }
+        state.in_flight += 1;
+        Permit {
+            limiter: self.clone(),
+            started: Instant::now(),
+        }
+    }
+
+    /// Adjusts the limit for a request that started at `started`
+    fn adjust(&self, started: Instant, overloaded: bool) {
+        let bounds = self.shared.bounds;
+        let mut state = self.shared.state.lock().unwrap();
+        if overloaded || started.elapsed() > bounds.target_latency {
+            if started >= state.last_decrease {
+                state.limit = (state.limit / 2.0).max(bounds.min as f64);
+                state.last_decrease = Instant::now();
+                debug!("Lowered the concurrency limit to {}", state.limit as usize);
+            }
+        } else {
+            state.limit = (state.limit + 1.0 / state.limit).min(bounds.max as f64);
+        }
+    }
+}
+
+/// Limiters are equal if they share the same limit
+impl PartialEq for AdaptiveLimiter {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.shared, &other.shared)
+    }
+}
+
+impl Eq for AdaptiveLimiter {}
+
+impl Hash for AdaptiveLimiter {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        (&*self.shared as *const Shared).hash(state);
+    }
+}
+
+impl Permit {
+    /// The request completed. The limit is raised if it took less than the target latency and
+    /// lowered otherwise.
+    pub fn success(self) {
+        self.limiter.adjust(self.started, false);
+    }
+
+    /// The request failed or was rate limited, so the limit is lowered
+    pub fn overloaded(self) {
+        self.limiter.adjust(self.started, true);
+    }
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        let shared = &self.limiter.shared;
+        shared.state.lock().unwrap().in_flight -= 1;
+        shared.released.notify_all();
+    }
+}
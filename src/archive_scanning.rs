@@ -0,0 +1,275 @@
+//! Opening the archives found in object stores and scanning the files inside them.
+//!
+//! Build artifacts and backups are often uploaded as zip, jar or (gzipped) tar archives, whose
+//! compressed content no regex can match. With a `max_depth` above 0, `expand` opens such an
+//! archive and returns each regular file in it, opening the archives it contains in turn up to
+//! `max_depth` levels deep. A file is named after the archive it came from and its path in it,
+//! joined by `!`, such as `build.zip!config/settings.xml`, and nested archives add a `!` per level.
+//!
+//! Archives are recognised by their content rather than their name: zip archives (jar, war and
+//! wheel files included) by their local file header, tar archives by the `ustar` magic of their
+//! first header, and tarballs compressed with gzip, zstd or bzip2 by looking at the start of the
+//! decompressed stream. Other compressed files are left to `SecretScanner::preprocess`.
+//!
+//! `ArchiveLimits` keeps a zip bomb from exhausting memory: at most `max_size` bytes are extracted
+//! from an archive, the archives nested in it included, and at most `MAX_ENTRIES` files. The file
+//! that crosses the limit is cut short and the rest of the archive is skipped with a warning. An
+//! archive that can't be read is scanned as it is.
+//!
+//! # Examples
+//!
+//! ```
+//! use flate2::write::GzEncoder;
+//! use rusty_hogs::archive_scanning::{expand, ArchiveLimits};
+//! use std::io::{Cursor, Write};
+//! use zip::write::{FileOptions, ZipWriter};
+//!
+//! let env = b"DATABASE_PASSWORD=hunter2\n";
+//! let mut header = tar::Header::new_gnu();
+//! header.set_size(env.len() as u64);
+//! header.set_mode(0o644);
+//! let mut tarball = tar::Builder::new(GzEncoder::new(Vec::new(), flate2::Compression::default()));
+//! tarball.append_data(&mut header, ".env", &env[..]).unwrap();
+//! let tarball = tarball.into_inner().unwrap().finish().unwrap();
+//!
+//! let mut build = ZipWriter::new(Cursor::new(Vec::new()));
+//! build.start_file("config/settings.xml", FileOptions::default()).unwrap();
+//! build.write_all(b"<password>hunter2</password>").unwrap();
+//! build.start_file("deploy.tgz", FileOptions::default()).unwrap();
+//! build.write_all(&tarball).unwrap();
+//! let build = build.finish().unwrap().into_inner();
+//!
+//! let limits = ArchiveLimits { max_depth: 2, ..Default::default() };
+//! let files = expand("build.zip", build.clone(), limits);
+//! let paths: Vec<&str> = files.iter().map(|f| f.path.as_str()).collect();
+//! assert_eq!(paths, vec!["build.zip!config/settings.xml", "build.zip!deploy.tgz!.env"]);
+//! assert_eq!(files[1].data, env.to_vec());
+//!
+//! // one level deep, the tarball is scanned as it is
+//! let limits = ArchiveLimits { max_depth: 1, ..Default::default() };
+//! assert_eq!(expand("build.zip", build.clone(), limits)[1].path, "build.zip!deploy.tgz");
+//!
+//! // the file that crosses the size limit is cut short, and the rest is skipped
+//! let limits = ArchiveLimits { max_depth: 2, max_size: 10 };
+//! let files = expand("build.zip", build.clone(), limits);
+//! assert_eq!(files.len(), 1);
+//! assert_eq!(&files[0].data[..], &b"<password>"[..]);
+//!
+//! // archives aren't opened by default
+//! let files = expand("build.zip", build.clone(), ArchiveLimits::default());
+//! assert_eq!((files[0].path.as_str(), &files[0].data), ("build.zip", &build));
+//! ```
+
+use crate::decoding::{self, ZIP_MAGIC};
+use log::{self, debug, warn};
+use std::io::{Cursor, Read};
+use tar::Archive;
+use zip::ZipArchive;
+
+/// The most files that are extracted from an archive, the archives nested in it included
+pub const MAX_ENTRIES: usize = 100_000;
+
+/// Separates the path of an archive from the path of a file inside it
+pub const SEPARATOR: &str = "!";
+
+/// How much of a compressed stream is decompressed to tell whether it is a tarball
+const SNIFF_LEN: u64 = 512;
+
+/// Caps what `expand` extracts. Archives are opened up to `max_depth` levels deep (0 scans them
+/// as they are), and at most `max_size` bytes are extracted from each.
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub struct ArchiveLimits {
+    pub max_depth: usize,
+    pub max_size: u64,
+}
+
+impl Default for ArchiveLimits {
+    /// Archives aren't opened, and up to 1 GiB is extracted from each when they are
+    fn default() -> Self {
+        Self {
+            max_depth: 0,
+            max_size: 1 << 30,
+        }
+    }
+}
+
+/// A file to scan: a file that isn't an archive, or a file extracted from one
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+pub struct ArchiveEntry {
+    /// The path of the file, inside the archives it came from (see `SEPARATOR`)
+    pub path: String,
+    pub data: Vec<u8>,
+}
+
+/// Returns the files to scan for the file at `path` with content `data`: the file itself if it
+/// isn't an archive (or `limits` doesn't open archives), otherwise the files inside it
+pub fn expand(path: &str, data: Vec<u8>, limits: ArchiveLimits) -> Vec<ArchiveEntry> {
+    let mut expansion = Expansion {
+        limits,
+        remaining: limits.max_size,
+        entries: 0,
+        exhausted: false,
+        files: Vec::new(),
+    };
+    expansion.add(path.to_string(), data, 0);
+    expansion.files
+}
+
+/// The kinds of archive `expand` opens
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+enum ArchiveKind {
+    Zip,
+    Tar,
+    CompressedTar(decoding::Compression),
+}
+
+/// Returns the kind of archive `data` is, if any
+fn archive_kind(path: &str, data: &[u8]) -> Option<ArchiveKind> {
+    if data.starts_with(ZIP_MAGIC) {
+        return Some(ArchiveKind::Zip);
+    }
+    if is_tar(path, data) {
+        return Some(ArchiveKind::Tar);
+    }
+    let compression = decoding::detect_compression(data)?;
+    let mut header: Vec<u8> = Vec::new();
+    let reader = decoding::decompressing_reader(data, compression).ok()?;
+    // a corrupt stream still yields the bytes before the corruption
+    let _ = reader.take(SNIFF_LEN).read_to_end(&mut header);
+    if is_tar(path, &header) {
+        Some(ArchiveKind::CompressedTar(compression))
+    } else {
+        None
+    }
+}
+
+/// Returns true if `header` starts with a POSIX or GNU tar header. Old tar archives without the
+/// magic are only recognised by their name.
+fn is_tar(path: &str, header: &[u8]) -> bool {
+    header.get(257..262) == Some(&b"ustar"[..]) || path.to_ascii_lowercase().ends_with(".tar")
+}
+
+/// The state of one call of `expand`: the budget left and the files found so far
+struct Expansion {
+    limits: ArchiveLimits,
+    remaining: u64,
+    entries: usize,
+    exhausted: bool,
+    files: Vec<ArchiveEntry>,
+}
+
+impl Expansion {
+    /// Adds the file at `path`, found `depth` archives deep, or the files inside it if it is an
+    /// archive that may be opened
+    fn add(&mut self, path: String, data: Vec<u8>, depth: usize) {
+        let kind = if depth < self.limits.max_depth {
+            archive_kind(&path, &data)
+        } else {
+            None
+        };
+        let found = self.files.len();
+        let result = match kind {
+            None => {
+                self.files.push(ArchiveEntry { path, data });
+                return;
+            }
+            Some(ArchiveKind::Zip) => self.add_zip(&path, &data, depth),
+            Some(ArchiveKind::Tar) => self.add_tar(&path, Cursor::new(&data), depth),
+            Some(ArchiveKind::CompressedTar(compression)) => {
+                match decoding::decompressing_reader(&data[..], compression) {
+                    Ok(reader) => self.add_tar(&path, reader, depth),
+                    Err(e) => Err(e.to_string()),
+                }
+            }
+        };
+        if let Err(e) = result {
+            if self.files.len() == found {
+                debug!("Failed to open {}: {}, scanning it as it is", path, e);
+                self.files.push(ArchiveEntry { path, data });
+            } else {
+                debug!("Failed to read the rest of {}: {}", path, e);
+            }
+        }
+    }
+
+    /// Adds the files of a zip archive
+    fn add_zip(&mut self, path: &str, data: &[u8], depth: usize) -> Result<(), String> {
+        let mut archive = ZipArchive::new(Cursor::new(data)).map_err(|e| e.to_string())?;
+        for i in 0..archive.len() {
+            if self.exhausted {
+                break;
+            }
+            let file = match archive.by_index(i) {
+                Ok(f) => f,
+                Err(e) => {
+                    // an encrypted file or an unsupported compression method
+                    debug!("Skipping file {} of {}: {}", i, path, e);
+                    continue;
+                }
+            };
+            if file.is_dir() {
+                continue;
+            }
+            let name = file.name().to_string();
+            self.add_entry(path, &name, file, depth);
+        }
+        Ok(())
+    }
+
+    /// Adds the regular files of a tar archive
+    fn add_tar<R: Read>(&mut self, path: &str, reader: R, depth: usize) -> Result<(), String> {
+        let mut archive = Archive::new(reader);
+        for entry in archive.entries().map_err(|e| e.to_string())? {
+            if self.exhausted {
+                break;
+            }
+            let entry = entry.map_err(|e| e.to_string())?;
+            if !entry.header().entry_type().is_file() {
+                continue;
+            }
+            let name = match entry.path() {
+                Ok(p) => p.to_string_lossy().trim_start_matches("./").to_string(),
+                Err(_) => continue,
+            };
+            self.add_entry(path, &name, entry, depth);
+        }
+        Ok(())
+    }
+
+    /// Extracts the file `name` of the archive at `path` within the budget left, and adds it
+    fn add_entry<R: Read>(&mut self, path: &str, name: &str, reader: R, depth: usize) {
+        let inner_path = format!("{}{}{}", path, SEPARATOR, name);
+        if self.entries >= MAX_ENTRIES {
+            warn!(
+                "{} has more than {} files, skipping the rest",
+                inner_path, MAX_ENTRIES
+            );
+            self.exhausted = true;
+            return;
+        }
+        self.entries += 1;
+        let mut data: Vec<u8> = Vec::new();
+        // read one byte past the budget to tell a file that fits exactly from one that doesn't
+        if let Err(e) = reader
+            .take(self.remaining.saturating_add(1))
+            .read_to_end(&mut data)
+        {
+            debug!("Failed to extract {}: {}", inner_path, e);
+            if data.is_empty() {
+                return;
+            }
+        }
+        if data.len() as u64 > self.remaining {
+            warn!(
+                "{} expands past the archive size limit of {} bytes, skipping the rest of the archive",
+                inner_path, self.limits.max_size
+            );
+            data.truncate(self.remaining as usize);
+            self.exhausted = true;
+        }
+        self.remaining -= data.len() as u64;
+        if !data.is_empty() {
+            self.add(inner_path, data, depth + 1);
+        }
+    }
+}
@@ -49,15 +49,78 @@
 //! let results = s3s.scan_s3_file(bucket, "s3://testbucket1/727463.json").unwrap();
 //! assert_eq!(results.len(), 0);
 //! ```
+//!
+//! Objects can be selected by key while they are listed with an `S3KeyFilter`, so that objects
+//! that would be skipped are never downloaded. Globs without a `/` match the file name, the others
+//! match the whole key.
+//!
+//! ```
+//! use rusty_hogs::aws_scanning::S3KeyFilter;
+//!
+//! let filter = S3KeyFilter::new(&["*.json", "config/**"], &["*.gz"]).unwrap();
+//! assert!(filter.matches("logs/2024/app.json"));
+//! assert!(filter.matches("config/prod/app.yaml"));
+//! assert!(!filter.matches("config/prod/dump.gz"));
+//! assert!(!filter.matches("logs/2024/app.log"));
+//! ```
+//!
+//! Instead of listing a bucket, new objects can be scanned as they arrive by long-polling an SQS
+//! queue that receives the bucket's
+//! [event notifications](https://docs.aws.amazon.com/AmazonS3/latest/userguide/EventNotifications.html),
+//! directly, through SNS, or as EventBridge events. `receive_s3_events` returns the messages of
+//! the queue with the objects they announce; a message should be deleted with `delete_message`
+//! once its objects are scanned, so that failed scans are retried when it becomes visible again.
+//!
+//! ```
+//! use rusty_hogs::aws_scanning::parse_s3_events;
+//!
+//! let body = r#"{"Records": [{"eventName": "ObjectCreated:Put", "awsRegion": "us-east-1",
+//!     "s3": {"bucket": {"name": "uploads"}, "object": {"key": "incoming/app+config.json", "size": 120}}}]}"#;
+//! let events = parse_s3_events(body);
+//! assert_eq!(events.len(), 1);
+//! assert_eq!(events[0].bucket, "uploads");
+//! assert_eq!(events[0].key, "incoming/app config.json");
+//! assert_eq!(events[0].region, "us-east-1");
+//! ```
 
-use crate::SecretScanner;
-use encoding::all::ASCII;
-use encoding::{DecoderTrap, Encoding};
-use log::{self, trace};
+use crate::archive_scanning;
+use crate::chunked_scanning;
+use crate::decoding;
+use crate::network;
+use crate::progress;
+use crate::retry;
+use crate::shutdown;
+use crate::structured_scanning::{self, StructuredFormat};
+use crate::{SecretScanner, Span};
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use log::{self, debug, error, info, trace};
+use regex::Regex;
+use reqwest::blocking::RequestBuilder;
 use s3::bucket::Bucket;
+use s3::credentials::Credentials;
+use s3::region::Region;
+use s3::serde_types::Object;
 use serde_derive::{Deserialize, Serialize};
-use simple_error::SimpleError;
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use simple_error::{require_with, try_with, SimpleError};
+use std::io::Read;
+use std::panic::{self, AssertUnwindSafe};
 use std::str;
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+use url::{form_urlencoded, Url};
+
+/// How many times a throttled AWS API call is retried before giving up
+const MAX_RETRIES: u32 = 5;
+
+/// The longest an SQS ReceiveMessage call can wait for messages, in seconds
+pub const SQS_MAX_WAIT_SECONDS: u64 = 20;
+
+/// The most messages an SQS ReceiveMessage call can return
+const SQS_MAX_MESSAGES: u64 = 10;
 
 #[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Hash, Clone, Default)]
 /// `serde_json` object that represents a single found secret - finding
@@ -66,9 +129,548 @@ pub struct S3Finding {
     #[serde(rename = "stringsFound")]
     pub strings_found: Vec<String>,
     pub bucket: String,
+    /// The key of the object, followed by the path of the file inside it for archives, e.g.
+    /// `builds/build.zip!config/settings.xml` (see the `archive_scanning` module)
     pub key: String,
     pub region: String,
     pub reason: String,
+    #[serde(rename = "keyPath", default, skip_serializing_if = "Option::is_none")]
+    pub key_path: Option<String>,
+    #[serde(rename = "versionId", default, skip_serializing_if = "Option::is_none")]
+    pub version_id: Option<String>,
+    /// Where each of `strings_found` is in the object, see `Span` (none with a `key_path`)
+    #[serde(default)]
+    pub spans: Vec<Span>,
+}
+
+/// A version of an S3 object, as returned by ListObjectVersions
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+pub struct S3ObjectVersion {
+    pub key: String,
+    pub version_id: String,
+    pub is_latest: bool,
+    pub last_modified: String,
+}
+
+/// An object announced by an S3 event notification
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Default)]
+pub struct S3Event {
+    pub bucket: String,
+    /// The key, decoded from the URL encoding of the notification
+    pub key: String,
+    /// The region of the bucket
+    pub region: String,
+    /// The version the event created, in versioned buckets
+    pub version_id: Option<String>,
+    pub size: Option<u64>,
+}
+
+/// A message received from an SQS queue, with the objects it announces
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+pub struct S3EventMessage {
+    pub message_id: String,
+    /// Identifies this receipt of the message, to delete it
+    pub receipt_handle: String,
+    /// The objects created, empty for test events and other notifications
+    pub events: Vec<S3Event>,
+}
+
+/// Selects objects by key with include and exclude globs. `*` and `?` match within a path
+/// segment and `**` matches across them. Globs without a `/` are matched against the file name,
+/// the others against the whole key.
+#[derive(Debug, Clone, Default)]
+pub struct S3KeyFilter {
+    include: Vec<(Regex, bool)>,
+    exclude: Vec<(Regex, bool)>,
+}
+
+impl S3KeyFilter {
+    pub fn new(include: &[&str], exclude: &[&str]) -> Result<Self, SimpleError> {
+        let compile = |globs: &[&str]| -> Result<Vec<(Regex, bool)>, SimpleError> {
+            globs
+                .iter()
+                .map(|g| Ok((glob_regex(g)?, g.contains('/'))))
+                .collect()
+        };
+        Ok(Self {
+            include: compile(include)?,
+            exclude: compile(exclude)?,
+        })
+    }
+
+    /// True if the key matches an include glob (or there are none) and no exclude glob
+    pub fn matches(&self, key: &str) -> bool {
+        let name = key.rsplit('/').next().unwrap_or(key);
+        let is_match =
+            |(re, whole_key): &(Regex, bool)| re.is_match(if *whole_key { key } else { name });
+        (self.include.is_empty() || self.include.iter().any(is_match))
+            && !self.exclude.iter().any(is_match)
+    }
+}
+
+/// Percent-encodes everything but the unreserved characters (and `/` if `keep_slash`), as
+/// Signature Version 4 expects
+fn uri_encode(s: &str, keep_slash: bool) -> String {
+    s.bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (b as char).to_string()
+            }
+            b'/' if keep_slash => String::from("/"),
+            b => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+fn hmac_sha256(key: &[u8], data: &str) -> Result<Vec<u8>, SimpleError> {
+    let mut mac =
+        Hmac::<Sha256>::new_varkey(key).map_err(|_| SimpleError::new("Invalid signing key"))?;
+    mac.input(data.as_bytes());
+    Ok(mac.result().code().to_vec())
+}
+
+/// Builds a request to an AWS service signed with Signature Version 4, see
+/// <https://docs.aws.amazon.com/general/latest/gr/sigv4_signing.html>. The request is a POST of
+/// `body` if there is one, a GET otherwise. Without credentials the request is left unsigned, for
+/// public buckets.
+fn signed_request(
+    credentials: &Credentials,
+    service: &str,
+    region: &str,
+    url: (&str, &str),
+    path: &str,
+    query: &[(&str, &str)],
+    body: Option<Vec<u8>>,
+) -> Result<RequestBuilder, SimpleError> {
+    let (scheme, host) = url;
+    let mut params: Vec<String> = query
+        .iter()
+        .map(|(name, value)| format!("{}={}", uri_encode(name, false), uri_encode(value, false)))
+        .collect();
+    params.sort();
+    let query = params.join("&");
+
+    let now = Utc::now();
+    let datetime = now.format("%Y%m%dT%H%M%SZ").to_string();
+    let date = now.format("%Y%m%d").to_string();
+    let method = if body.is_some() { "POST" } else { "GET" };
+    let payload_hash = hex::encode(Sha256::digest(body.as_deref().unwrap_or(b"")));
+    let mut headers: Vec<(&str, String)> = vec![
+        ("host", host.to_string()),
+        ("x-amz-content-sha256", payload_hash.clone()),
+        ("x-amz-date", datetime.clone()),
+    ];
+    if let Some(token) = &credentials.token {
+        headers.push(("x-amz-security-token", token.clone()));
+    }
+
+    let url = format!("{}://{}{}?{}", scheme, host, path, query);
+    let mut request = match body {
+        Some(body) => network::client().post(&url).body(body),
+        None => network::client().get(&url),
+    };
+    if let (Some(access_key), Some(secret_key)) = (&credentials.access_key, &credentials.secret_key)
+    {
+        let signed_headers: Vec<&str> = headers.iter().map(|(name, _)| *name).collect();
+        let canonical_headers: String = headers
+            .iter()
+            .map(|(name, value)| format!("{}:{}\n", name, value.trim()))
+            .collect();
+        let canonical_request = format!(
+            "{}\n{}\n{}\n{}\n{}\n{}",
+            method,
+            path,
+            query,
+            canonical_headers,
+            signed_headers.join(";"),
+            payload_hash
+        );
+        let scope = format!("{}/{}/{}/aws4_request", date, region, service);
+        let string_to_sign = format!(
+            "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+            datetime,
+            scope,
+            hex::encode(Sha256::digest(canonical_request.as_bytes()))
+        );
+        let mut signing_key = format!("AWS4{}", secret_key).into_bytes();
+        for part in &[date.as_str(), region, service, "aws4_request"] {
+            signing_key = hmac_sha256(&signing_key, part)?;
+        }
+        let signature = hex::encode(hmac_sha256(&signing_key, &string_to_sign)?);
+        request = request.header(
+            "Authorization",
+            format!(
+                "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+                access_key,
+                scope,
+                signed_headers.join(";"),
+                signature
+            ),
+        );
+    }
+    for (name, value) in headers.into_iter().filter(|(name, _)| *name != "host") {
+        request = request.header(name, value);
+    }
+    Ok(request)
+}
+
+/// Sends a signed GET request for `key` in `bucket` (the bucket itself if `key` is empty, the
+/// account's buckets if both are). This covers the calls that rust-s3 doesn't support, like
+/// listing buckets and object versions.
+fn signed_get(
+    region: &Region,
+    credentials: &Credentials,
+    bucket: &str,
+    key: &str,
+    query: &[(&str, &str)],
+) -> Result<Vec<u8>, SimpleError> {
+    let path = if bucket.is_empty() {
+        String::from("/")
+    } else {
+        format!(
+            "/{}/{}",
+            uri_encode(bucket, false),
+            uri_encode(key.trim_start_matches('/'), true)
+        )
+    };
+    let request = signed_request(
+        credentials,
+        "s3",
+        &region.to_string(),
+        (&region.scheme(), &region.host()),
+        &path,
+        query,
+        None,
+    )?;
+    let response = try_with!(retry::send(request), "S3 request failed");
+    if !response.status().is_success() {
+        return Err(SimpleError::new(format!(
+            "S3 returned {} for s3://{}/{}",
+            response.status(),
+            bucket,
+            key.trim_start_matches('/')
+        )));
+    }
+    let body = try_with!(response.bytes(), "Failed to read the S3 response");
+    Ok(body.to_vec())
+}
+
+/// Calls an operation of an AWS API that uses the JSON protocol, like CloudWatch Logs or DynamoDB,
+/// in `region`. `target` names the operation, e.g. `Logs_20140328.FilterLogEvents`, and
+/// `json_version` is the version of the protocol the API speaks (`1.0` or `1.1`). Throttled calls
+/// are retried with an exponential backoff.
+pub(crate) fn aws_json_call(
+    credentials: &Credentials,
+    service: &str,
+    region: &str,
+    (target, json_version): (&str, &str),
+    body: &Value,
+) -> Result<Value, SimpleError> {
+    let host = format!("{}.{}.amazonaws.com", service, region);
+    let mut retries = 0;
+    loop {
+        debug!("POST {} {}", host, target);
+        let request = signed_request(
+            credentials,
+            service,
+            region,
+            ("https", &host),
+            "/",
+            &[],
+            Some(body.to_string().into_bytes()),
+        )?
+        .header(
+            "Content-Type",
+            format!("application/x-amz-json-{}", json_version),
+        )
+        .header("X-Amz-Target", target);
+        let response = try_with!(retry::send(request), "{} request failed", target);
+        let status = response.status();
+        let json: Value = response.json().unwrap_or(Value::Null);
+        if status.is_success() {
+            return Ok(json);
+        }
+        // the error type is given as e.g. "com.amazonaws.dynamodb.v20120810#ThrottlingException"
+        let error = json["__type"].as_str().unwrap_or("");
+        let error = error.rsplit('#').next().unwrap_or(error);
+        let throttled = error.contains("Throttling") || error.contains("ThroughputExceeded");
+        if (throttled || status.is_server_error()) && retries < MAX_RETRIES {
+            debug!("{} returned {}, retrying", target, status);
+            thread::sleep(Duration::from_millis(200 << retries));
+            retries += 1;
+            continue;
+        }
+        return Err(SimpleError::new(format!(
+            "{} returned {} {}: {}",
+            target,
+            status,
+            error,
+            json["message"]
+                .as_str()
+                .or_else(|| json["Message"].as_str())
+                .unwrap_or("")
+        )));
+    }
+}
+
+/// Reads the objects created from the body of an SQS message: an S3 event notification, the same
+/// wrapped in an SNS notification, or an EventBridge "Object Created" event. Other events (such
+/// as deletions and the `s3:TestEvent` sent when notifications are set up) announce nothing.
+pub fn parse_s3_events(body: &str) -> Vec<S3Event> {
+    let json: Value = match serde_json::from_str(body) {
+        Ok(j) => j,
+        Err(_) => return Vec::new(),
+    };
+    // SNS delivers the notification as a string in its own envelope
+    if let (Some("Notification"), Some(message)) = (json["Type"].as_str(), json["Message"].as_str())
+    {
+        return parse_s3_events(message);
+    }
+    if json["detail-type"].as_str() == Some("Object Created") {
+        let detail = &json["detail"];
+        return match (
+            detail["bucket"]["name"].as_str(),
+            detail["object"]["key"].as_str(),
+        ) {
+            (Some(bucket), Some(key)) => vec![S3Event {
+                bucket: bucket.to_string(),
+                key: key.to_string(),
+                region: json["region"].as_str().unwrap_or("").to_string(),
+                version_id: detail["object"]["version-id"].as_str().map(String::from),
+                size: detail["object"]["size"].as_u64(),
+            }],
+            _ => Vec::new(),
+        };
+    }
+    json["Records"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter(|r| {
+            r["eventName"]
+                .as_str()
+                .map_or(false, |e| e.starts_with("ObjectCreated:"))
+        })
+        .filter_map(|r| {
+            let object = &r["s3"]["object"];
+            // keys are URL encoded, with spaces as +
+            let key = form_urlencoded::parse(object["key"].as_str()?.as_bytes())
+                .next()
+                .map(|(k, _)| k.into_owned())?;
+            Some(S3Event {
+                bucket: r["s3"]["bucket"]["name"].as_str()?.to_string(),
+                key,
+                region: r["awsRegion"].as_str().unwrap_or("").to_string(),
+                version_id: object["versionId"].as_str().map(String::from),
+                size: object["size"].as_u64(),
+            })
+        })
+        .collect()
+}
+
+/// Reads the region of an SQS queue from its URL, e.g.
+/// `https://sqs.us-east-1.amazonaws.com/123456789012/uploads`
+fn sqs_region(queue_url: &str) -> Result<String, SimpleError> {
+    let url = try_with!(Url::parse(queue_url), "Invalid queue URL {}", queue_url);
+    let host = require_with!(url.host_str(), "Queue URL {} has no host", queue_url);
+    let parts: Vec<&str> = host.split('.').collect();
+    match parts.as_slice() {
+        ["sqs", region, ..] => Ok(region.to_string()),
+        // the legacy form, https://us-east-1.queue.amazonaws.com/...
+        [region, "queue", ..] => Ok(region.to_string()),
+        _ => Err(SimpleError::new(format!(
+            "Can't tell the region of queue {}",
+            queue_url
+        ))),
+    }
+}
+
+/// Sends a GET to an AWS API in `region`, like the EC2 query API or the Lambda REST API, and
+/// returns the response body
+pub(crate) fn aws_get(
+    credentials: &Credentials,
+    service: &str,
+    region: &str,
+    path: &str,
+    query: &[(&str, &str)],
+) -> Result<Vec<u8>, SimpleError> {
+    let host = format!("{}.{}.amazonaws.com", service, region);
+    debug!("GET {}{}", host, path);
+    let request = signed_request(
+        credentials,
+        service,
+        region,
+        ("https", &host),
+        path,
+        query,
+        None,
+    )?;
+    let response = try_with!(retry::send(request), "{} request failed", service);
+    let status = response.status();
+    let body = try_with!(response.bytes(), "Failed to read the {} response", service);
+    if !status.is_success() {
+        return Err(SimpleError::new(format!(
+            "{} returned {} for {}: {}",
+            service,
+            status,
+            path,
+            String::from_utf8_lossy(&body)
+        )));
+    }
+    Ok(body.to_vec())
+}
+
+/// Writes `body` to the object an `s3://bucket/key` URI names, with the credentials of the default
+/// credential chain (the environment, the credentials file, or the instance, task or Lambda role)
+/// and in the bucket's own region. This is how reports are written to `--outputfile s3://...`, so
+/// that scans in Lambda or Fargate don't need a local disk.
+pub fn put_s3_object(uri: &str, body: &[u8], content_type: &str) -> Result<(), SimpleError> {
+    let url = try_with!(Url::parse(uri), "Invalid S3 URI {}", uri);
+    let bucket_name = require_with!(url.host_str(), "S3 URI {} has no bucket", uri);
+    let key = url.path().trim_start_matches('/');
+    if url.scheme() != "s3" || key.is_empty() {
+        return Err(SimpleError::new(format!(
+            "{} isn't an S3 object URI (s3://bucket/key)",
+            uri
+        )));
+    }
+    let credentials = Credentials::new(None, None, None, None);
+    let region = S3Scanner::default().bucket_region(&credentials, bucket_name)?;
+    let bucket = try_with!(
+        Bucket::new(bucket_name, region, credentials),
+        "Failed to set up bucket {}",
+        bucket_name
+    );
+    let (response, status) = try_with!(
+        bucket.put_object(key, body, content_type),
+        "Failed to write {}",
+        uri
+    );
+    if status != 200 {
+        return Err(SimpleError::new(format!(
+            "S3 returned {} writing {}: {}",
+            status,
+            uri,
+            String::from_utf8_lossy(&response)
+        )));
+    }
+    info!("Wrote {} bytes to {}", body.len(), uri);
+    Ok(())
+}
+
+/// Returns the credentials the AWS hogs scan with: those of the default credential chain (the
+/// environment, `profile` in the credentials file, or the instance profile), exchanged for those
+/// of `role_arn` if given (see `assume_role`)
+pub fn credentials_from_args(
+    profile: Option<&str>,
+    role_arn: Option<&str>,
+    external_id: Option<&str>,
+) -> Result<Credentials, SimpleError> {
+    let credentials = Credentials::new(None, None, None, profile.map(String::from));
+    match role_arn {
+        Some(role_arn) => assume_role(&credentials, role_arn, external_id),
+        None => Ok(credentials),
+    }
+}
+
+/// Exchanges `credentials` for temporary credentials of the role `role_arn` with STS AssumeRole,
+/// e.g. to scan other accounts from a central security account through a read-only audit role.
+/// `external_id` is the external ID the role's trust policy requires, if any. The temporary
+/// credentials last an hour.
+pub fn assume_role(
+    credentials: &Credentials,
+    role_arn: &str,
+    external_id: Option<&str>,
+) -> Result<Credentials, SimpleError> {
+    let mut query = vec![
+        ("Action", "AssumeRole"),
+        ("Version", "2011-06-15"),
+        ("RoleArn", role_arn),
+        ("RoleSessionName", "rusty-hog"),
+    ];
+    if let Some(id) = external_id {
+        query.push(("ExternalId", id));
+    }
+    let request = signed_request(
+        credentials,
+        "sts",
+        "us-east-1",
+        ("https", "sts.amazonaws.com"),
+        "/",
+        &query,
+        None,
+    )?;
+    let response = try_with!(retry::send(request), "STS request failed");
+    let status = response.status();
+    let body = try_with!(response.text(), "Failed to read the STS response");
+    if !status.is_success() {
+        let message_re = Regex::new(r"<Message>(.*?)</Message>").unwrap();
+        return Err(SimpleError::new(format!(
+            "Failed to assume role {}: {} {}",
+            role_arn,
+            status,
+            message_re
+                .captures(&body)
+                .map_or("", |c| c.get(1).unwrap().as_str())
+        )));
+    }
+    let text = |element: &str| -> Result<String, SimpleError> {
+        let re = Regex::new(&format!("<{0}>(.*?)</{0}>", element)).unwrap();
+        match re.captures(&body) {
+            Some(c) => Ok(c[1].to_string()),
+            None => Err(SimpleError::new(format!(
+                "No {} in the AssumeRole response",
+                element
+            ))),
+        }
+    };
+    info!("Assumed role {}", role_arn);
+    Ok(Credentials::new(
+        Some(text("AccessKeyId")?),
+        Some(text("SecretAccessKey")?),
+        Some(text("SessionToken")?),
+        None,
+    ))
+}
+
+/// Splits a line of an S3 Inventory CSV file, whose fields are all quoted
+fn parse_csv_line(line: &str) -> Vec<String> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                chars.next();
+                field.push('"');
+            }
+            '"' => quoted = !quoted,
+            ',' if !quoted => fields.push(std::mem::replace(&mut field, String::new())),
+            c => field.push(c),
+        }
+    }
+    fields.push(field);
+    fields
+}
+
+/// Translates a glob into an anchored regex
+fn glob_regex(glob: &str) -> Result<Regex, SimpleError> {
+    let mut pattern = String::from("^");
+    let mut chars = glob.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '*' if chars.peek() == Some(&'*') => {
+                chars.next();
+                pattern.push_str(".*");
+            }
+            '*' => pattern.push_str("[^/]*"),
+            '?' => pattern.push_str("[^/]"),
+            c => pattern.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+    pattern.push('$');
+    Ok(try_with!(Regex::new(&pattern), "Invalid glob {:?}", glob))
 }
 
 #[derive(Debug, PartialEq, Eq, Hash, Clone)]
@@ -89,6 +691,354 @@ impl S3Scanner {
 
     pub fn new() -> Self { Self { secret_scanner: SecretScanner::default() } }
 
+    /// Lists the names of the buckets these credentials own with ListBuckets
+    pub fn list_buckets(&self, credentials: &Credentials) -> Result<Vec<String>, SimpleError> {
+        let name_re = Regex::new(r"(?s)<Bucket>.*?<Name>(.*?)</Name>.*?</Bucket>").unwrap();
+        let page = signed_get(&Region::UsEast1, credentials, "", "", &[])?;
+        let page = String::from_utf8_lossy(&page);
+        let buckets: Vec<String> = name_re
+            .captures_iter(&page)
+            .map(|c| {
+                String::from_utf8_lossy(&decoding::decode_entities(c[1].as_bytes())).into_owned()
+            })
+            .collect();
+        info!("Listed {} buckets", buckets.len());
+        Ok(buckets)
+    }
+
+    /// Looks up the region of a bucket with GetBucketLocation
+    pub fn bucket_region(
+        &self,
+        credentials: &Credentials,
+        bucket: &str,
+    ) -> Result<Region, SimpleError> {
+        let location_re =
+            Regex::new(r"<LocationConstraint[^>]*>(.*?)</LocationConstraint>").unwrap();
+        let page = signed_get(
+            &Region::UsEast1,
+            credentials,
+            bucket,
+            "",
+            &[("location", "")],
+        )?;
+        let page = String::from_utf8_lossy(&page);
+        // buckets in us-east-1 have no location constraint, and the oldest ones in eu-west-1 "EU"
+        let location = match location_re.captures(&page) {
+            Some(c) if &c[1] == "EU" => String::from("eu-west-1"),
+            Some(c) if !c[1].is_empty() => c[1].to_string(),
+            _ => String::from("us-east-1"),
+        };
+        match location.parse() {
+            Ok(r) => Ok(r),
+            Err(e) => Err(SimpleError::new(format!(
+                "Unknown region {} of bucket {}: {}",
+                location, bucket, e
+            ))),
+        }
+    }
+
+    /// Lists the objects under `prefix` in `bucket` from an S3 Inventory report instead of the
+    /// bucket itself, filtered like `list_objects`. `manifest` is the `s3://` URI of the report's
+    /// manifest.json, and only CSV reports are supported. Objects in subfolders of the prefix are
+    /// skipped unless `recursive`, and noncurrent versions unless `all_versions` (the report
+    /// must include them). Also returns how many objects the report lists before filtering.
+    pub fn list_inventory(
+        &self,
+        credentials: &Credentials,
+        manifest: &str,
+        bucket: &str,
+        prefix: &str,
+        recursive: bool,
+        all_versions: bool,
+        filter: &S3KeyFilter,
+    ) -> Result<(usize, Vec<S3ObjectVersion>), SimpleError> {
+        let url = try_with!(
+            Url::parse(manifest),
+            "Failed to parse the inventory manifest URI"
+        );
+        let manifest_bucket = require_with!(url.host_str(), "No bucket in {}", manifest);
+        let region = self.bucket_region(credentials, manifest_bucket)?;
+        let json = signed_get(&region, credentials, manifest_bucket, url.path(), &[])?;
+        let json: Value = try_with!(
+            serde_json::from_slice(&json),
+            "Failed to parse the inventory manifest {}",
+            manifest
+        );
+
+        let format = json["fileFormat"].as_str().unwrap_or("");
+        if format != "CSV" {
+            return Err(SimpleError::new(format!(
+                "Only CSV inventory reports are supported, {} is {}",
+                manifest, format
+            )));
+        }
+        let source = json["sourceBucket"].as_str().unwrap_or("");
+        if source != bucket {
+            return Err(SimpleError::new(format!(
+                "The inventory report {} is of bucket {}, not {}",
+                manifest, source, bucket
+            )));
+        }
+        // the report's files are in its destination bucket, given as an ARN
+        let destination = json["destinationBucket"]
+            .as_str()
+            .map_or(manifest_bucket, |d| d.trim_start_matches("arn:aws:s3:::"));
+        let columns: Vec<&str> = json["fileSchema"]
+            .as_str()
+            .unwrap_or("")
+            .split(',')
+            .map(|c| c.trim())
+            .collect();
+        let column = |name: &str| columns.iter().position(|c| *c == name);
+        let key_column = require_with!(column("Key"), "No Key in the schema of {}", manifest);
+        let (version_column, latest_column, delete_marker_column, modified_column) = (
+            column("VersionId"),
+            column("IsLatest"),
+            column("IsDeleteMarker"),
+            column("LastModifiedDate"),
+        );
+
+        let time_range = self.secret_scanner.time_range;
+        let prefix = prefix.trim_start_matches('/');
+        let mut versions: Vec<S3ObjectVersion> = Vec::new();
+        let mut listed = 0;
+        let files = json["files"].as_array().cloned().unwrap_or_default();
+        for file in files {
+            if shutdown::interrupted() {
+                break;
+            }
+            let key = require_with!(file["key"].as_str(), "A file of {} has no key", manifest);
+            debug!("Reading inventory file {}", key);
+            let data = signed_get(&region, credentials, destination, key, &[])?;
+            let mut csv = String::new();
+            match decoding::detect_compression(&data) {
+                Some(compression) => try_with!(
+                    decoding::decompressing_reader(&data[..], compression)?
+                        .read_to_string(&mut csv),
+                    "Failed to decompress the inventory file {}",
+                    key
+                ),
+                None => try_with!(
+                    (&data[..]).read_to_string(&mut csv),
+                    "The inventory file {} is not UTF-8",
+                    key
+                ),
+            };
+
+            for line in csv.lines().filter(|l| !l.is_empty()) {
+                listed += 1;
+                let fields = parse_csv_line(line);
+                let field = |i: Option<usize>| i.and_then(|i| fields.get(i)).map(String::as_str);
+                // keys are URL-encoded in the report
+                let raw_key = field(Some(key_column)).unwrap_or("");
+                let key = match form_urlencoded::parse(raw_key.as_bytes()).next() {
+                    Some((k, _)) => k.into_owned(),
+                    None => continue,
+                };
+                let version = S3ObjectVersion {
+                    version_id: field(version_column).unwrap_or("").to_string(),
+                    is_latest: field(latest_column) != Some("false"),
+                    last_modified: field(modified_column).unwrap_or("").to_string(),
+                    key,
+                };
+                let in_prefix = version.key.starts_with(prefix)
+                    && (recursive || !version.key[prefix.len()..].contains('/'));
+                if in_prefix
+                    && !version.key.ends_with('/')
+                    && field(delete_marker_column) != Some("true")
+                    && (all_versions || version.is_latest)
+                    && filter.matches(&version.key)
+                    && time_range.contains_str(&version.last_modified)
+                {
+                    versions.push(version);
+                }
+            }
+        }
+        info!(
+            "Read {} objects from the inventory report, {} selected",
+            listed,
+            versions.len()
+        );
+        Ok((listed, versions))
+    }
+
+    /// Lists the objects under `prefix` (only those directly under it if `delimiter` is `/`),
+    /// one page at a time, keeping the objects that `filter` matches and that were last modified
+    /// within the scanner's time range. Also returns how many objects were listed before
+    /// filtering.
+    pub fn list_objects(
+        &self,
+        bucket: &Bucket,
+        prefix: &str,
+        delimiter: Option<String>,
+        filter: &S3KeyFilter,
+    ) -> Result<(usize, Vec<Object>), SimpleError> {
+        let time_range = self.secret_scanner.time_range;
+        let mut objects: Vec<Object> = Vec::new();
+        let mut listed = 0;
+        let mut continuation_token: Option<String> = None;
+        loop {
+            if shutdown::interrupted() {
+                break;
+            }
+            let result =
+                bucket.list_page(prefix.to_string(), delimiter.clone(), continuation_token);
+            let (page, _) = match result {
+                Ok(r) => r,
+                Err(e) => return Err(SimpleError::new(e.to_string())),
+            };
+            listed += page.contents.len();
+            objects.extend(page.contents.into_iter().filter(|o| {
+                !o.key.ends_with('/')
+                    && filter.matches(&o.key)
+                    && time_range.contains_str(&o.last_modified)
+            }));
+            continuation_token = page.next_continuation_token;
+            if continuation_token.is_none() {
+                break;
+            }
+        }
+        info!("Listed {} objects, {} selected", listed, objects.len());
+        Ok((listed, objects))
+    }
+
+    /// Lists every version of the objects under `prefix` with ListObjectVersions, filtered like
+    /// `list_objects`. Objects that were overwritten or deleted keep their earlier versions, so
+    /// this finds secrets that were "removed" from the current ones. Delete markers have no
+    /// content and are skipped, the versions they hide are listed. Also returns how many
+    /// versions were listed before filtering.
+    pub fn list_object_versions(
+        &self,
+        bucket: &Bucket,
+        prefix: &str,
+        delimiter: Option<String>,
+        filter: &S3KeyFilter,
+    ) -> Result<(usize, Vec<S3ObjectVersion>), SimpleError> {
+        let version_re = Regex::new(r"(?s)<Version>(.*?)</Version>").unwrap();
+        let key_re = Regex::new(r"<Key>(.*?)</Key>").unwrap();
+        let version_id_re = Regex::new(r"<VersionId>(.*?)</VersionId>").unwrap();
+        let is_latest_re = Regex::new(r"<IsLatest>(.*?)</IsLatest>").unwrap();
+        let modified_re = Regex::new(r"<LastModified>(.*?)</LastModified>").unwrap();
+        let truncated_re = Regex::new(r"<IsTruncated>(.*?)</IsTruncated>").unwrap();
+        let key_marker_re = Regex::new(r"<NextKeyMarker>(.*?)</NextKeyMarker>").unwrap();
+        let version_marker_re =
+            Regex::new(r"<NextVersionIdMarker>(.*?)</NextVersionIdMarker>").unwrap();
+        let text = |re: &Regex, xml: &str| -> String {
+            match re.captures(xml) {
+                Some(c) => String::from_utf8_lossy(&decoding::decode_entities(c[1].as_bytes()))
+                    .into_owned(),
+                None => String::new(),
+            }
+        };
+
+        let time_range = self.secret_scanner.time_range;
+        let prefix = prefix.trim_start_matches('/');
+        let mut versions: Vec<S3ObjectVersion> = Vec::new();
+        let mut listed = 0;
+        let mut markers = (String::new(), String::new());
+        loop {
+            if shutdown::interrupted() {
+                break;
+            }
+            let mut query = vec![("versions", ""), ("prefix", prefix)];
+            if let Some(d) = &delimiter {
+                query.push(("delimiter", d));
+            }
+            if !markers.0.is_empty() {
+                query.push(("key-marker", &markers.0));
+                query.push(("version-id-marker", &markers.1));
+            }
+            let page = signed_get(
+                &bucket.region,
+                &bucket.credentials,
+                &bucket.name,
+                "",
+                &query,
+            )?;
+            let page = String::from_utf8_lossy(&page);
+            for version in version_re.captures_iter(&page) {
+                listed += 1;
+                let version = S3ObjectVersion {
+                    key: text(&key_re, &version[1]),
+                    version_id: text(&version_id_re, &version[1]),
+                    is_latest: text(&is_latest_re, &version[1]) == "true",
+                    last_modified: text(&modified_re, &version[1]),
+                };
+                if !version.key.ends_with('/')
+                    && filter.matches(&version.key)
+                    && time_range.contains_str(&version.last_modified)
+                {
+                    versions.push(version);
+                }
+            }
+            if text(&truncated_re, &page) != "true" {
+                break;
+            }
+            markers = (text(&key_marker_re, &page), text(&version_marker_re, &page));
+        }
+        info!("Listed {} versions, {} selected", listed, versions.len());
+        Ok((listed, versions))
+    }
+
+    /// Long-polls the SQS queue at `queue_url` for up to `wait_seconds` (at most
+    /// `SQS_MAX_WAIT_SECONDS`), returning the messages received, up to 10. The messages stay
+    /// invisible to other consumers for the queue's visibility timeout, and are received again
+    /// after it unless deleted.
+    pub fn receive_s3_events(
+        &self,
+        credentials: &Credentials,
+        queue_url: &str,
+        wait_seconds: u64,
+    ) -> Result<Vec<S3EventMessage>, SimpleError> {
+        let region = sqs_region(queue_url)?;
+        let response = aws_json_call(
+            credentials,
+            "sqs",
+            &region,
+            ("AmazonSQS.ReceiveMessage", "1.0"),
+            &json!({
+                "QueueUrl": queue_url,
+                "MaxNumberOfMessages": SQS_MAX_MESSAGES,
+                "WaitTimeSeconds": wait_seconds.min(SQS_MAX_WAIT_SECONDS),
+            }),
+        )?;
+        let messages: Vec<S3EventMessage> = response["Messages"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|m| {
+                Some(S3EventMessage {
+                    message_id: m["MessageId"].as_str().unwrap_or("").to_string(),
+                    receipt_handle: m["ReceiptHandle"].as_str()?.to_string(),
+                    events: parse_s3_events(m["Body"].as_str().unwrap_or("")),
+                })
+            })
+            .collect();
+        debug!("Received {} messages from {}", messages.len(), queue_url);
+        Ok(messages)
+    }
+
+    /// Deletes a message received with `receive_s3_events` from the queue
+    pub fn delete_message(
+        &self,
+        credentials: &Credentials,
+        queue_url: &str,
+        message: &S3EventMessage,
+    ) -> Result<(), SimpleError> {
+        let region = sqs_region(queue_url)?;
+        aws_json_call(
+            credentials,
+            "sqs",
+            &region,
+            ("AmazonSQS.DeleteMessage", "1.0"),
+            &json!({
+                "QueueUrl": queue_url,
+                "ReceiptHandle": message.receipt_handle,
+            }),
+        )?;
+        Ok(())
+    }
+
     /// Takes an initialized [Bucket](https://durch.github.io/rust-s3/s3/bucket/struct.Bucket.html)
     /// object and an S3 object path in the format `s3://<path>` and returns a list of S3Finding
     /// objects.
@@ -97,45 +1047,155 @@ impl S3Scanner {
         bucket: Bucket,
         filepath: &str,
     ) -> Result<Vec<S3Finding>, SimpleError> {
-        // Initialize our S3 variables
-        let mut output: Vec<S3Finding> = Vec::new();
-
         // Get the actual data from S3
         let (data, code) = match bucket.get_object(filepath) {
             Ok(x) => (x.0, x.1),
             Err(e) => return Err(SimpleError::new(e.to_string())),
         };
         trace!("Code: {}\nData: {:?}", code, data);
+        Ok(self.scan_s3_data(&bucket, filepath, None, data))
+    }
+
+    /// Like `scan_s3_file`, but scans the version `version_id` of the object, which needn't be
+    /// the current one. The findings record the version.
+    pub fn scan_s3_file_version(
+        &self,
+        bucket: Bucket,
+        filepath: &str,
+        version_id: &str,
+    ) -> Result<Vec<S3Finding>, SimpleError> {
+        let data = signed_get(
+            &bucket.region,
+            &bucket.credentials,
+            &bucket.name,
+            filepath,
+            &[("versionId", version_id)],
+        )?;
+        trace!("Version: {}\nData: {:?}", version_id, data);
+        Ok(self.scan_s3_data(&bucket, filepath, Some(version_id), data))
+    }
+
+    /// Downloads and scans each object (or object version, if it has a version ID) using
+    /// `threads` worker threads, returning the result for each. A panic while scanning an object
+    /// is returned as an error for that object.
+    pub fn scan_s3_files(
+        &self,
+        bucket: &Bucket,
+        keys: Vec<(String, Option<String>)>,
+        threads: usize,
+    ) -> Vec<(String, Option<String>, Result<Vec<S3Finding>, SimpleError>)> {
+        progress::add_total(keys.len());
+        let queue = Arc::new(Mutex::new(keys.into_iter()));
+        let (tx, rx) = mpsc::channel();
+        let mut handles = Vec::new();
+        for _ in 0..threads.max(1) {
+            let queue = Arc::clone(&queue);
+            let tx = tx.clone();
+            let scanner = self.clone();
+            let bucket = bucket.clone();
+            handles.push(thread::spawn(move || loop {
+                if shutdown::interrupted() {
+                    break;
+                }
+                let (key, version_id) = match queue.lock().unwrap().next() {
+                    Some(k) => k,
+                    None => break,
+                };
+                let result = panic::catch_unwind(AssertUnwindSafe(|| match &version_id {
+                    Some(v) => scanner.scan_s3_file_version(bucket.clone(), &key, v),
+                    None => scanner.scan_s3_file(bucket.clone(), &key),
+                }))
+                .unwrap_or_else(|_| Err(SimpleError::new(format!("Scanning {} panicked", key))));
+                if tx.send((key, version_id, result)).is_err() {
+                    break;
+                }
+            }));
+        }
+        drop(tx);
+        let results = rx.iter().collect();
+        for handle in handles {
+            if handle.join().is_err() {
+                error!("A scanning thread exited unexpectedly");
+            }
+        }
+        results
+    }
+
+    /// Scans the content of an object, or each file inside it when it is an archive that
+    /// `archive_limits` opens
+    fn scan_s3_data(
+        &self,
+        bucket: &Bucket,
+        filepath: &str,
+        version_id: Option<&str>,
+        data: Vec<u8>,
+    ) -> Vec<S3Finding> {
+        let size = data.len();
+        let limits = self.secret_scanner.archive_limits;
+        let output: Vec<S3Finding> = archive_scanning::expand(filepath, data, limits)
+            .into_iter()
+            .flat_map(|e| self.scan_s3_file_data(bucket, &e.path, version_id, e.data))
+            .collect();
+        progress::scanned(size, output.len());
+        output
+    }
+
+    /// Scans one file of an object, `filepath` naming the file inside the archives it came from
+    fn scan_s3_file_data(
+        &self,
+        bucket: &Bucket,
+        filepath: &str,
+        version_id: Option<&str>,
+        data: Vec<u8>,
+    ) -> Vec<S3Finding> {
+        let mut output: Vec<S3Finding> = Vec::new();
 
         // Main loop - split the data based on newlines, then run get_matches() on each line,
         // then make a list of findings in output
-        let lines = data.split(|&x| (x as char) == '\n');
-        for new_line in lines {
-            let results = self.secret_scanner.matches(new_line);
-            for (r, matches) in results {
-                let mut strings_found: Vec<String> = Vec::new();
-                for m in matches {
-                    let result = ASCII
-                        .decode(&new_line[m.start()..m.end()], DecoderTrap::Ignore)
-                        .unwrap_or_else(|_| "<STRING DECODE ERROR>".parse().unwrap());
-                    strings_found.push(result);
-                }
-                if !strings_found.is_empty() {
-                    let new_line_string = ASCII
-                        .decode(&new_line, DecoderTrap::Ignore)
-                        .unwrap_or_else(|_| "<STRING DECODE ERROR>".parse().unwrap());
-                    output.push(S3Finding {
-                        diff: new_line_string,
-                        strings_found,
-                        bucket: bucket.name.clone(),
-                        key: filepath.parse().unwrap(),
-                        region: bucket.region.to_string(),
-                        reason: r.clone(),
-                    });
+        let data = self.secret_scanner.preprocess_owned(data);
+
+        // Config files are parsed and scanned value by value, falling back to lines on failure
+        if self.secret_scanner.structured {
+            if let Some(format) = StructuredFormat::detect(filepath, &data) {
+                match structured_scanning::scan_document(&self.secret_scanner, &data, format, false)
+                {
+                    Ok(matches) => {
+                        return matches
+                            .into_iter()
+                            .map(|m| S3Finding {
+                                diff: m.diff,
+                                strings_found: m.strings_found,
+                                bucket: bucket.name.clone(),
+                                key: filepath.to_string(),
+                                region: bucket.region.to_string(),
+                                reason: m.reason,
+                                key_path: Some(m.key_path),
+                                version_id: version_id.map(String::from),
+                                spans: Vec::new(),
+                            })
+                            .collect();
+                    }
+                    Err(e) => debug!("{}, scanning {} line by line", e, filepath),
                 }
             }
         }
-        Ok(output)
+
+        // Large objects are split into chunks that are scanned in parallel
+        let options = self.secret_scanner.chunk_options;
+        for m in chunked_scanning::scan_lines(&self.secret_scanner, data, options, false) {
+            output.push(S3Finding {
+                diff: m.diff,
+                strings_found: m.strings_found,
+                bucket: bucket.name.clone(),
+                key: filepath.to_string(),
+                region: bucket.region.to_string(),
+                reason: m.reason,
+                key_path: None,
+                version_id: version_id.map(String::from),
+                spans: m.spans,
+            });
+        }
+        output
     }
 }
 
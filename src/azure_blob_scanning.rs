@@ -0,0 +1,586 @@
+//! Collection of tools for scanning Azure Blob Storage containers for secrets.
+//!
+//! `AzureBlobScanner` acts as a wrapper around a `SecretScanner` object and talks to the
+//! [Blob service REST API](https://docs.microsoft.com/en-us/rest/api/storageservices/blob-service-rest-api).
+//! It lists the blobs in a container under a prefix, then downloads and scans them on several
+//! threads, the same way `gcs_scanning` scans Cloud Storage objects: config files are scanned
+//! value by value with `--structured` and large blobs are split into chunks.
+//!
+//! Requests are authorized with one of:
+//!
+//! * a connection string, either with the storage account key (requests are signed with
+//!   [Shared Key](https://docs.microsoft.com/en-us/rest/api/storageservices/authorize-with-shared-key))
+//!   or with a shared access signature
+//! * a shared access signature (SAS) token, with read and list permissions on the container
+//! * a managed identity, from the App Service identity endpoint or the instance metadata service
+//!   of the VM, scale set or container the scan runs on. The identity needs the Storage Blob Data
+//!   Reader role.
+//!
+//! Public containers can be scanned without credentials.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use rusty_hogs::azure_blob_scanning::{AzureBlobConfig, AzureBlobCredentials, AzureBlobScanner};
+//!
+//! let abs = AzureBlobScanner::new();
+//! let credentials = AzureBlobCredentials::from_connection_string(
+//!     "DefaultEndpointsProtocol=https;AccountName=myaccount;AccountKey=bXlrZXk=",
+//! )
+//! .unwrap();
+//! let config = AzureBlobConfig::new("https://myaccount.blob.core.windows.net", credentials);
+//! let blobs = abs.list_blobs(&config, "mycontainer", "config/", true).unwrap();
+//! for (blob, result) in abs.scan_blobs(&config, "mycontainer", blobs, 4, false) {
+//!     println!("{}: {:?}", blob.name, result.map(|f| f.len()));
+//! }
+//! ```
+//!
+//! Connection strings are parsed into the credentials they contain.
+//!
+//! ```
+//! use rusty_hogs::azure_blob_scanning::AzureBlobCredentials;
+//!
+//! let credentials = AzureBlobCredentials::from_connection_string(
+//!     "BlobEndpoint=https://myaccount.blob.core.windows.net/;SharedAccessSignature=sv=2019-12-12&sp=rl&sig=abc",
+//! )
+//! .unwrap();
+//! assert_eq!(
+//!     credentials,
+//!     AzureBlobCredentials::Sas(String::from("sv=2019-12-12&sp=rl&sig=abc"))
+//! );
+//! ```
+
+use crate::archive_scanning;
+use crate::chunked_scanning;
+use crate::decoding;
+use crate::network;
+use crate::progress;
+use crate::retry;
+use crate::shutdown;
+use crate::structured_scanning::{self, StructuredFormat};
+use crate::{SecretScanner, Span};
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use log::{self, debug, error, info};
+use regex::Regex;
+use reqwest::blocking::{RequestBuilder, Response};
+use serde_derive::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::Sha256;
+use simple_error::{require_with, try_with, SimpleError};
+use std::env;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use url::Url;
+
+/// The version of the REST API we send (bearer tokens need 2017-11-09 or later)
+const API_VERSION: &str = "2019-12-12";
+
+/// The resource managed identity tokens are requested for
+const STORAGE_RESOURCE: &str = "https://storage.azure.com/";
+
+/// The token endpoint of the Azure instance metadata service
+const IMDS_TOKEN_URL: &str = "http://169.254.169.254/metadata/identity/oauth2/token";
+
+/// Tokens are refreshed this long before they expire
+const TOKEN_REFRESH_MARGIN: Duration = Duration::from_secs(60);
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Hash, Clone, Default)]
+/// `serde_json` object that represents a single found secret - finding
+pub struct AzureBlobFinding {
+    pub diff: String,
+    #[serde(rename = "stringsFound")]
+    pub strings_found: Vec<String>,
+    pub container: String,
+    /// The name of the blob, followed by the path of the file inside it for archives, e.g.
+    /// `builds/build.zip!config/settings.xml` (see the `archive_scanning` module)
+    pub key: String,
+    /// The URL of the blob, without any SAS token
+    pub url: String,
+    pub reason: String,
+    #[serde(rename = "keyPath", default, skip_serializing_if = "Option::is_none")]
+    pub key_path: Option<String>,
+    /// Where each of `strings_found` is in the blob, see `Span` (none with a `key_path`)
+    #[serde(default)]
+    pub spans: Vec<Span>,
+}
+
+/// How requests are authorized, see the module documentation
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+pub enum AzureBlobCredentials {
+    /// A storage account name and its (base64) access key
+    SharedKey { account: String, key: String },
+    /// A SAS token, without the leading `?`
+    Sas(String),
+    /// A managed identity, with the client ID of a user-assigned identity
+    ManagedIdentity(Option<String>),
+    /// No credentials, for public containers
+    Anonymous,
+}
+
+/// The storage account endpoint and credentials to use, and the access token they last produced
+#[derive(Debug, Clone)]
+pub struct AzureBlobConfig {
+    /// The blob service endpoint, e.g. `https://myaccount.blob.core.windows.net`
+    pub endpoint: String,
+    pub credentials: AzureBlobCredentials,
+    /// Shared by the clones of a config, so that threads reuse the same token
+    token: Arc<Mutex<Option<(String, Instant)>>>,
+}
+
+/// A blob in a container. Only the fields we need are included.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Hash, Clone, Default)]
+pub struct AzureBlob {
+    pub name: String,
+    /// The size in bytes
+    pub size: u64,
+    /// When the blob was last modified (RFC 3339)
+    pub last_modified: String,
+}
+
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+/// Contains helper functions for performing scans of Azure Blob Storage containers
+pub struct AzureBlobScanner {
+    pub secret_scanner: SecretScanner,
+}
+
+impl AzureBlobCredentials {
+    /// Reads the credentials from a storage account connection string, preferring a shared access
+    /// signature to the account key when it has both
+    pub fn from_connection_string(connection_string: &str) -> Result<Self, SimpleError> {
+        let mut account: Option<&str> = None;
+        let mut key: Option<&str> = None;
+        for part in connection_string.split(';') {
+            let mut kv = part.splitn(2, '=');
+            match (kv.next().map(str::trim), kv.next()) {
+                (Some("SharedAccessSignature"), Some(sas)) => {
+                    return Ok(AzureBlobCredentials::Sas(
+                        sas.trim_start_matches('?').to_string(),
+                    ))
+                }
+                (Some("AccountName"), Some(a)) => account = Some(a),
+                (Some("AccountKey"), Some(k)) => key = Some(k),
+                _ => {}
+            }
+        }
+        match (account, key) {
+            (Some(account), Some(key)) => Ok(AzureBlobCredentials::SharedKey {
+                account: account.to_string(),
+                key: key.to_string(),
+            }),
+            _ => Err(SimpleError::new(
+                "The connection string has neither an AccountName and AccountKey nor a SharedAccessSignature",
+            )),
+        }
+    }
+
+    /// Gets a new access token and the number of seconds it is valid for, or None for credentials
+    /// that don't use tokens
+    fn fetch_token(&self) -> Result<Option<(String, u64)>, SimpleError> {
+        let client_id = match self {
+            AzureBlobCredentials::ManagedIdentity(client_id) => client_id,
+            _ => return Ok(None),
+        };
+        // App Service and Functions have their own endpoint, VMs and containers use IMDS
+        let request = match (env::var("IDENTITY_ENDPOINT"), env::var("IDENTITY_HEADER")) {
+            (Ok(endpoint), Ok(header)) => {
+                let mut request = network::client()
+                    .get(&endpoint)
+                    .query(&[
+                        ("resource", STORAGE_RESOURCE),
+                        ("api-version", "2019-08-01"),
+                    ])
+                    .header("X-IDENTITY-HEADER", header);
+                if let Some(id) = client_id {
+                    request = request.query(&[("client_id", id)]);
+                }
+                request
+            }
+            _ => {
+                let mut request = network::client()
+                    .get(IMDS_TOKEN_URL)
+                    .query(&[
+                        ("resource", STORAGE_RESOURCE),
+                        ("api-version", "2018-02-01"),
+                    ])
+                    .header("Metadata", "true");
+                if let Some(id) = client_id {
+                    request = request.query(&[("client_id", id)]);
+                }
+                request
+            }
+        };
+        let response = try_with!(
+            retry::send(request),
+            "Managed identity token request failed"
+        );
+        if !response.status().is_success() {
+            return Err(SimpleError::new(format!(
+                "Managed identity token request returned {}",
+                response.status()
+            )));
+        }
+        let token: Value = try_with!(response.json(), "Invalid managed identity token response");
+        let access_token = require_with!(
+            token["access_token"].as_str(),
+            "No access token in the response"
+        );
+        // expires_in is a string in IMDS responses
+        let expires_in = match &token["expires_in"] {
+            Value::String(s) => s.parse().unwrap_or(3600),
+            v => v.as_u64().unwrap_or(3600),
+        };
+        Ok(Some((access_token.to_string(), expires_in)))
+    }
+}
+
+impl AzureBlobConfig {
+    pub fn new(endpoint: &str, credentials: AzureBlobCredentials) -> Self {
+        Self {
+            endpoint: endpoint.trim_end_matches('/').to_string(),
+            credentials,
+            token: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    /// Returns a valid access token, getting a new one if the last one is about to expire
+    fn access_token(&self) -> Result<Option<String>, SimpleError> {
+        let mut cached = self.token.lock().unwrap();
+        if let Some((token, expiry)) = cached.as_ref() {
+            if Instant::now() + TOKEN_REFRESH_MARGIN < *expiry {
+                return Ok(Some(token.clone()));
+            }
+        }
+        Ok(match self.credentials.fetch_token()? {
+            Some((token, expires_in)) => {
+                let expiry = Instant::now() + Duration::from_secs(expires_in);
+                *cached = Some((token.clone(), expiry));
+                Some(token)
+            }
+            None => None,
+        })
+    }
+
+    /// Builds a URL for a container or a blob in it, escaping the blob name
+    fn url(&self, container: &str, blob: Option<&str>) -> Result<Url, SimpleError> {
+        let mut url = try_with!(
+            Url::parse(&self.endpoint),
+            "Invalid storage endpoint {}",
+            self.endpoint
+        );
+        match url.path_segments_mut() {
+            Ok(mut path) => {
+                path.pop_if_empty().push(container);
+                if let Some(name) = blob {
+                    path.extend(name.split('/'));
+                }
+            }
+            Err(_) => return Err(SimpleError::new("Invalid storage endpoint")),
+        }
+        Ok(url)
+    }
+
+    /// Adds the credentials to a GET request for `url`
+    fn authorize(&self, mut url: Url) -> Result<RequestBuilder, SimpleError> {
+        if let AzureBlobCredentials::Sas(sas) = &self.credentials {
+            let query = match url.query() {
+                Some(q) => format!("{}&{}", q, sas),
+                None => sas.clone(),
+            };
+            url.set_query(Some(&query));
+        }
+        let date = Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string();
+        let request = network::client()
+            .get(url.as_str())
+            .header("x-ms-date", date.as_str())
+            .header("x-ms-version", API_VERSION);
+        Ok(match &self.credentials {
+            AzureBlobCredentials::SharedKey { account, key } => {
+                let signature = sign(account, key, &url, &date)?;
+                request.header(
+                    "Authorization",
+                    format!("SharedKey {}:{}", account, signature),
+                )
+            }
+            AzureBlobCredentials::ManagedIdentity(_) => match self.access_token()? {
+                Some(token) => request.bearer_auth(token),
+                None => request,
+            },
+            _ => request,
+        })
+    }
+}
+
+/// Signs a GET request with an account key, see
+/// <https://docs.microsoft.com/en-us/rest/api/storageservices/authorize-with-shared-key>
+fn sign(account: &str, key: &str, url: &Url, date: &str) -> Result<String, SimpleError> {
+    let key = try_with!(base64::decode(key), "The account key is not valid base64");
+    // the verb, then 11 empty standard headers
+    let mut string_to_sign = format!(
+        "GET{}x-ms-date:{}\nx-ms-version:{}\n/{}{}",
+        "\n".repeat(12),
+        date,
+        API_VERSION,
+        account,
+        url.path()
+    );
+    let mut params: Vec<(String, String)> = url
+        .query_pairs()
+        .map(|(name, value)| (name.to_lowercase(), value.to_string()))
+        .collect();
+    params.sort();
+    for (name, value) in params {
+        string_to_sign.push_str(&format!("\n{}:{}", name, value));
+    }
+    let mut mac =
+        Hmac::<Sha256>::new_varkey(&key).map_err(|_| SimpleError::new("Invalid account key"))?;
+    mac.input(string_to_sign.as_bytes());
+    Ok(base64::encode(&mac.result().code()))
+}
+
+/// Acts as a wrapper around a `SecretScanner` object to provide helper functions for performing
+/// scanning against Azure Blob Storage. Relies on the [reqwest](https://docs.rs/reqwest/) library
+/// for HTTP access.
+impl AzureBlobScanner {
+    /// Initialize the SecretScanner object first using the SecretScannerBuilder, then provide
+    /// it to this constructor method.
+    pub fn new_from_scanner(secret_scanner: SecretScanner) -> Self {
+        Self { secret_scanner }
+    }
+
+    pub fn new() -> Self {
+        Self {
+            secret_scanner: SecretScanner::default(),
+        }
+    }
+
+    /// Lists the blobs in `container` whose names start with `prefix`. Without `recursive`, blobs
+    /// in "subdirectories" of the prefix (names with another `/` after it) are left out.
+    pub fn list_blobs(
+        &self,
+        config: &AzureBlobConfig,
+        container: &str,
+        prefix: &str,
+        recursive: bool,
+    ) -> Result<Vec<AzureBlob>, SimpleError> {
+        let blob_re = Regex::new(r"(?s)<Blob>(.*?)</Blob>").unwrap();
+        let name_re = Regex::new(r"<Name>(.*?)</Name>").unwrap();
+        let size_re = Regex::new(r"<Content-Length>(\d+)</Content-Length>").unwrap();
+        let modified_re = Regex::new(r"<Last-Modified>(.*?)</Last-Modified>").unwrap();
+        let marker_re = Regex::new(r"<NextMarker>(.*?)</NextMarker>").unwrap();
+        let text = |re: &Regex, xml: &str| -> String {
+            match re.captures(xml) {
+                Some(c) => String::from_utf8_lossy(&decoding::decode_entities(c[1].as_bytes()))
+                    .into_owned(),
+                None => String::new(),
+            }
+        };
+
+        let mut blobs: Vec<AzureBlob> = Vec::new();
+        let mut marker = String::new();
+        loop {
+            let mut url = config.url(container, None)?;
+            url.query_pairs_mut()
+                .append_pair("restype", "container")
+                .append_pair("comp", "list")
+                .append_pair("prefix", prefix);
+            if !recursive {
+                url.query_pairs_mut().append_pair("delimiter", "/");
+            }
+            if !marker.is_empty() {
+                url.query_pairs_mut().append_pair("marker", &marker);
+            }
+            let page = try_with!(
+                get(config, url)?.text(),
+                "Failed to read the blob listing of {}",
+                container
+            );
+            for blob in blob_re.captures_iter(&page) {
+                let name = text(&name_re, &blob[1]);
+                if name.is_empty() || name.ends_with('/') {
+                    continue;
+                }
+                // Last-Modified is an RFC 1123 date
+                let last_modified = text(&modified_re, &blob[1]);
+                blobs.push(AzureBlob {
+                    name,
+                    size: text(&size_re, &blob[1]).parse().unwrap_or(0),
+                    last_modified: match DateTime::parse_from_rfc2822(&last_modified) {
+                        Ok(t) => t.with_timezone(&Utc).to_rfc3339(),
+                        Err(_) => last_modified,
+                    },
+                });
+            }
+            marker = text(&marker_re, &page);
+            if marker.is_empty() {
+                info!(
+                    "Found {} blobs in {}/{}/{}",
+                    blobs.len(),
+                    config.endpoint,
+                    container,
+                    prefix
+                );
+                return Ok(blobs);
+            }
+        }
+    }
+
+    /// Downloads a blob and returns a list of findings
+    pub fn scan_blob(
+        &self,
+        config: &AzureBlobConfig,
+        container: &str,
+        name: &str,
+        scan_entropy: bool,
+    ) -> Result<Vec<AzureBlobFinding>, SimpleError> {
+        let url = config.url(container, Some(name))?;
+        let template = AzureBlobFinding {
+            container: container.to_string(),
+            key: name.to_string(),
+            url: url.to_string(),
+            ..Default::default()
+        };
+        let data = try_with!(
+            get(config, url)?.bytes(),
+            "Failed to download {}",
+            template.url
+        );
+        let size = data.len();
+        let limits = self.secret_scanner.archive_limits;
+        let mut findings: Vec<AzureBlobFinding> = Vec::new();
+        for entry in archive_scanning::expand(name, data.to_vec(), limits) {
+            let template = AzureBlobFinding {
+                key: entry.path,
+                ..template.clone()
+            };
+            findings.extend(self.scan_file(template, entry.data, scan_entropy));
+        }
+        progress::scanned(size, findings.len());
+        Ok(findings)
+    }
+
+    /// Scans one file of a blob, the blob itself unless it is an archive, reporting its findings
+    /// as `template` with the matches filled in
+    fn scan_file(
+        &self,
+        template: AzureBlobFinding,
+        data: Vec<u8>,
+        scan_entropy: bool,
+    ) -> Vec<AzureBlobFinding> {
+        let data = self.secret_scanner.preprocess_owned(data);
+        let name = &template.key;
+
+        // Config files are parsed and scanned value by value, falling back to lines on failure
+        if self.secret_scanner.structured {
+            if let Some(format) = StructuredFormat::detect(name, &data) {
+                match structured_scanning::scan_document(
+                    &self.secret_scanner,
+                    &data,
+                    format,
+                    scan_entropy,
+                ) {
+                    Ok(matches) => {
+                        return matches
+                            .into_iter()
+                            .map(|m| AzureBlobFinding {
+                                diff: m.diff,
+                                strings_found: m.strings_found,
+                                reason: m.reason,
+                                key_path: Some(m.key_path),
+                                ..template.clone()
+                            })
+                            .collect();
+                    }
+                    Err(e) => debug!("{}, scanning {} line by line", e, name),
+                }
+            }
+        }
+
+        // Large blobs are split into chunks that are scanned in parallel
+        let options = self.secret_scanner.chunk_options;
+        chunked_scanning::scan_lines(&self.secret_scanner, data, options, scan_entropy)
+            .into_iter()
+            .map(|m| AzureBlobFinding {
+                diff: m.diff,
+                strings_found: m.strings_found,
+                reason: m.reason,
+                spans: m.spans,
+                ..template.clone()
+            })
+            .collect()
+    }
+
+    /// Scans each blob with `scan_blob` using `threads` worker threads, returning the result for
+    /// each blob. A panic while scanning a blob is returned as an error for that blob.
+    pub fn scan_blobs(
+        &self,
+        config: &AzureBlobConfig,
+        container: &str,
+        blobs: Vec<AzureBlob>,
+        threads: usize,
+        scan_entropy: bool,
+    ) -> Vec<(AzureBlob, Result<Vec<AzureBlobFinding>, SimpleError>)> {
+        progress::add_total(blobs.len());
+        let queue = Arc::new(Mutex::new(blobs.into_iter()));
+        let (tx, rx) = mpsc::channel();
+        let mut handles = Vec::new();
+        for _ in 0..threads.max(1) {
+            let queue = Arc::clone(&queue);
+            let tx = tx.clone();
+            let scanner = self.clone();
+            let config = config.clone();
+            let container = container.to_string();
+            handles.push(thread::spawn(move || loop {
+                if shutdown::interrupted() {
+                    break;
+                }
+                let blob = match queue.lock().unwrap().next() {
+                    Some(b) => b,
+                    None => break,
+                };
+                let result = panic::catch_unwind(AssertUnwindSafe(|| {
+                    scanner.scan_blob(&config, &container, &blob.name, scan_entropy)
+                }))
+                .unwrap_or_else(|_| {
+                    Err(SimpleError::new(format!("Scanning {} panicked", blob.name)))
+                });
+                if tx.send((blob, result)).is_err() {
+                    break;
+                }
+            }));
+        }
+        drop(tx);
+        let results = rx.iter().collect();
+        for handle in handles {
+            if handle.join().is_err() {
+                error!("A scanning thread exited unexpectedly");
+            }
+        }
+        results
+    }
+}
+
+impl Default for AzureBlobScanner {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Performs an authorized GET
+fn get(config: &AzureBlobConfig, url: Url) -> Result<Response, SimpleError> {
+    debug!("GET {}", url);
+    let response = try_with!(
+        retry::send(config.authorize(url.clone())?),
+        "Blob Storage request failed"
+    );
+    if !response.status().is_success() {
+        return Err(SimpleError::new(format!(
+            "Blob Storage returned {} for {}",
+            response.status(),
+            url
+        )));
+    }
+    Ok(response)
+}
@@ -0,0 +1,546 @@
+//! Collection of tools for scanning Azure DevOps projects for secrets.
+//!
+//! `AzureDevOpsScanner` acts as a wrapper around a `SecretScanner` object and talks to the
+//! [Azure DevOps REST API](https://docs.microsoft.com/en-us/rest/api/azure/devops/) using a
+//! personal access token (PAT) with read access to Code, Build, Variable Groups and Work Items.
+//! It works against Azure DevOps Services (`https://dev.azure.com/<organization>`) as well as
+//! Azure DevOps Server collections (`https://ado.example.com/tfs/DefaultCollection`). For a
+//! project, it scans:
+//!
+//! * every branch of every Azure Repos repository, cloned over HTTPS and scanned with `GitScanner`
+//! * the YAML file of each YAML pipeline stored in Azure Repos, and the variables defined on
+//!   each pipeline (build definition)
+//! * the variables of each variable group
+//! * the description and comments of every work item
+//!
+//! Secret pipeline and variable group values can't be read through the API, so only plain ones
+//! are scanned. Plain variables whose name looks like a credential (`*Password`, `*_TOKEN`, ...)
+//! are always reported with the reason "Unsecured pipeline variable".
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use rusty_hogs::azure_devops_scanning::{AzureDevOpsConfig, AzureDevOpsScanner};
+//!
+//! let ads = AzureDevOpsScanner::new();
+//! let config = AzureDevOpsConfig {
+//!     organization_url: "https://dev.azure.com/my-org".to_string(),
+//!     project: "my-project".to_string(),
+//!     token: "my-pat".to_string(),
+//!     api_version: "6.0".to_string(),
+//! };
+//! let findings = ads.scan_project(&config, true, false).unwrap();
+//! ads.secret_scanner.output_findings(&findings);
+//! ```
+
+use crate::decoding::{self, MarkupMode};
+use crate::git_scanning::GitScanner;
+use crate::network;
+use crate::retry;
+use crate::shutdown;
+use crate::structured_scanning;
+use crate::{Finding, SecretScanner, Span, TextFinding};
+use log::{self, debug, info};
+use reqwest::blocking::{RequestBuilder, Response};
+use serde_derive::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use simple_error::{try_with, SimpleError};
+use std::collections::HashSet;
+use url::form_urlencoded;
+
+/// Azure DevOps ignores the username when a PAT is used for git over HTTPS
+const TOKEN_GIT_USER: &str = "pat";
+
+/// Work items are fetched in batches of this size, the most the API allows
+const WORK_ITEM_BATCH: usize = 200;
+
+/// Reason reported for plain variables with credential-like names
+const UNSECURED_VARIABLE_REASON: &str = "Unsecured pipeline variable";
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Hash, Clone, Default)]
+/// `serde_json` object that represents a single found secret - finding
+pub struct AzureDevOpsFinding {
+    pub diff: String,
+    #[serde(rename = "stringsFound")]
+    pub strings_found: Vec<String>,
+    pub project: String,
+    /// One of `repository`, `pipeline`, `variable_group` or `work_item`
+    pub source: String,
+    /// The repository and file path, the pipeline or variable group name (and variable name), or
+    /// `#<id>` for work items
+    pub path: String,
+    /// A link to the file, pipeline, variable group or work item
+    pub url: String,
+    #[serde(
+        rename = "commitHash",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub commit_hash: Option<String>,
+    /// The email address of the commit author, for repository findings
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub author: Option<String>,
+    pub date: String,
+    pub reason: String,
+    /// Where each of `strings_found` is in the text, see `Span`
+    #[serde(default)]
+    pub spans: Vec<Span>,
+}
+
+impl TextFinding for AzureDevOpsFinding {
+    fn with_match(&self, found: Finding) -> Self {
+        Self {
+            diff: found.diff,
+            strings_found: found.strings_found,
+            reason: found.reason,
+            spans: found.spans,
+            ..self.clone()
+        }
+    }
+}
+
+/// The organization (or collection) and project to scan, and the PAT to authenticate with
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+pub struct AzureDevOpsConfig {
+    /// e.g. `https://dev.azure.com/my-org` or `https://ado.example.com/tfs/DefaultCollection`
+    pub organization_url: String,
+    pub project: String,
+    pub token: String,
+    /// The REST API version to request, e.g. `6.0`. Azure DevOps Server 2019 needs `5.0`.
+    pub api_version: String,
+}
+
+impl AzureDevOpsConfig {
+    fn project_url(&self) -> String {
+        format!(
+            "{}/{}",
+            self.organization_url.trim_end_matches('/'),
+            self.project
+        )
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+/// Contains helper functions for performing scans of Azure DevOps projects
+pub struct AzureDevOpsScanner {
+    pub secret_scanner: SecretScanner,
+}
+
+/// Acts as a wrapper around a `SecretScanner` object to provide helper functions for performing
+/// scanning against Azure DevOps. Relies on the [reqwest](https://docs.rs/reqwest/) library for
+/// HTTP access.
+impl AzureDevOpsScanner {
+    /// Initialize the SecretScanner object first using the SecretScannerBuilder, then provide
+    /// it to this constructor method.
+    pub fn new_from_scanner(secret_scanner: SecretScanner) -> Self {
+        Self { secret_scanner }
+    }
+
+    pub fn new() -> Self {
+        Self {
+            secret_scanner: SecretScanner::default(),
+        }
+    }
+
+    /// Scans the pipelines, variable groups and work items of the project, and every repository
+    /// unless `scan_repositories` is false, returning a HashSet of findings.
+    pub fn scan_project(
+        &self,
+        config: &AzureDevOpsConfig,
+        scan_repositories: bool,
+        scan_entropy: bool,
+    ) -> Result<HashSet<AzureDevOpsFinding>, SimpleError> {
+        info!("Scanning project {}", config.project);
+        let mut findings: HashSet<AzureDevOpsFinding> = HashSet::new();
+        if scan_repositories {
+            findings.extend(self.scan_repositories(config, scan_entropy)?);
+        }
+        findings.extend(self.scan_pipelines(config, scan_entropy)?);
+        findings.extend(self.scan_variable_groups(config, scan_entropy)?);
+        findings.extend(self.scan_work_items(config, scan_entropy)?);
+        Ok(findings)
+    }
+
+    /// Clones each repository into a temporary directory and scans every branch with `GitScanner`
+    fn scan_repositories(
+        &self,
+        config: &AzureDevOpsConfig,
+        scan_entropy: bool,
+    ) -> Result<HashSet<AzureDevOpsFinding>, SimpleError> {
+        let mut findings: HashSet<AzureDevOpsFinding> = HashSet::new();
+        let repositories = get_values(config, "git/repositories")?;
+        info!("Found {} repositories", repositories.len());
+        for repo in repositories {
+            if shutdown::interrupted() {
+                break;
+            }
+            let (name, remote_url, web_url) = match (
+                repo["name"].as_str(),
+                repo["remoteUrl"].as_str(),
+                repo["webUrl"].as_str(),
+            ) {
+                (Some(n), Some(r), Some(w)) => (n, r, w),
+                _ => continue,
+            };
+            if repo["isDisabled"].as_bool().unwrap_or(false) || repo["size"].as_u64() == Some(0) {
+                debug!("Skipping disabled or empty repository {}", name);
+                continue;
+            }
+            let dest_dir = self.secret_scanner.workspace()?;
+            let git_scanner = GitScanner::new_from_scanner(self.secret_scanner.clone())
+                .init_git_repo(
+                    remote_url,
+                    dest_dir.path(),
+                    None,
+                    None,
+                    Some(TOKEN_GIT_USER),
+                    Some(&config.token),
+                );
+            dest_dir.check_quota()?;
+            let git_findings = git_scanner.perform_scan(None, None, None, scan_entropy)?;
+            debug!(
+                "Found {} secrets in repository {}",
+                git_findings.len(),
+                name
+            );
+            findings.extend(git_findings.into_iter().map(|f| AzureDevOpsFinding {
+                url: format!("{}?path=/{}&version=GC{}", web_url, f.path, f.commit_hash),
+                diff: f.diff,
+                strings_found: f.strings_found,
+                project: config.project.clone(),
+                source: String::from("repository"),
+                path: format!("{}/{}", name, f.path),
+                commit_hash: Some(f.commit_hash),
+                author: Some(f.author),
+                date: f.date,
+                reason: f.reason,
+                spans: f.spans,
+            }));
+        }
+        Ok(findings)
+    }
+
+    /// Scans the variables of each build definition, and the YAML file of YAML pipelines whose
+    /// source is an Azure Repos repository
+    fn scan_pipelines(
+        &self,
+        config: &AzureDevOpsConfig,
+        scan_entropy: bool,
+    ) -> Result<HashSet<AzureDevOpsFinding>, SimpleError> {
+        let mut findings: HashSet<AzureDevOpsFinding> = HashSet::new();
+        let definitions = get_values(config, "build/definitions?includeAllProperties=true")?;
+        debug!("Scanning {} pipelines", definitions.len());
+        for definition in definitions {
+            let name = definition["name"].as_str().unwrap_or("").to_string();
+            let template = AzureDevOpsFinding {
+                project: config.project.clone(),
+                source: String::from("pipeline"),
+                path: name.clone(),
+                url: definition["_links"]["web"]["href"]
+                    .as_str()
+                    .unwrap_or("")
+                    .to_string(),
+                date: definition["createdDate"].as_str().unwrap_or("").to_string(),
+                ..Default::default()
+            };
+            self.scan_variables(
+                &definition["variables"],
+                &template,
+                scan_entropy,
+                &mut findings,
+            );
+
+            // only YAML pipelines (process type 2) have a yamlFilename
+            let yaml_path = definition["process"]["yamlFilename"].as_str();
+            let repository = &definition["repository"];
+            if let (Some(yaml_path), Some("TfsGit"), Some(repo_id)) = (
+                yaml_path,
+                repository["type"].as_str(),
+                repository["id"].as_str(),
+            ) {
+                let item_path = format!(
+                    "git/repositories/{}/items?path={}&includeContent=true",
+                    repo_id,
+                    form_urlencoded::byte_serialize(yaml_path.as_bytes()).collect::<String>()
+                );
+                let item: Result<Value, SimpleError> = get(config, &item_path).and_then(|r| {
+                    r.json()
+                        .map_err(|e| SimpleError::with("Failed to parse pipeline YAML item", e))
+                });
+                match item {
+                    Ok(item) => {
+                        let template = AzureDevOpsFinding {
+                            path: format!("{}/{}", name, yaml_path.trim_start_matches('/')),
+                            commit_hash: item["commitId"].as_str().map(String::from),
+                            ..template.clone()
+                        };
+                        let content = item["content"].as_str().unwrap_or("");
+                        self.secret_scanner.scan_text(
+                            content.as_bytes(),
+                            &template,
+                            scan_entropy,
+                            &mut findings,
+                        );
+                    }
+                    Err(e) => debug!("Skipping the YAML file of pipeline {}: {}", name, e),
+                }
+            }
+        }
+        Ok(findings)
+    }
+
+    /// Scans the plain variables of each variable group
+    fn scan_variable_groups(
+        &self,
+        config: &AzureDevOpsConfig,
+        scan_entropy: bool,
+    ) -> Result<HashSet<AzureDevOpsFinding>, SimpleError> {
+        let mut findings: HashSet<AzureDevOpsFinding> = HashSet::new();
+        let groups = get_values(config, "distributedtask/variablegroups")?;
+        debug!("Scanning {} variable groups", groups.len());
+        for group in groups {
+            let template = AzureDevOpsFinding {
+                project: config.project.clone(),
+                source: String::from("variable_group"),
+                path: group["name"].as_str().unwrap_or("").to_string(),
+                url: format!(
+                    "{}/_library?itemType=VariableGroups&view=VariableGroupView&variableGroupId={}",
+                    config.project_url(),
+                    group["id"]
+                ),
+                date: group["modifiedOn"].as_str().unwrap_or("").to_string(),
+                ..Default::default()
+            };
+            self.scan_variables(&group["variables"], &template, scan_entropy, &mut findings);
+        }
+        Ok(findings)
+    }
+
+    /// Scans a `{"name": {"value": "...", "isSecret": false}}` map of pipeline variables. Secret
+    /// variables come back without a value, so they're skipped.
+    fn scan_variables(
+        &self,
+        variables: &Value,
+        template: &AzureDevOpsFinding,
+        scan_entropy: bool,
+        findings: &mut HashSet<AzureDevOpsFinding>,
+    ) {
+        let variables = match variables.as_object() {
+            Some(v) => v,
+            None => return,
+        };
+        for (key, variable) in variables {
+            if variable["isSecret"].as_bool().unwrap_or(false) {
+                continue;
+            }
+            let value = match variable["value"].as_str() {
+                Some(v) => v,
+                None => continue,
+            };
+            let line = format!("{}={}", key, value);
+            let template = AzureDevOpsFinding {
+                path: format!("{}.{}", template.path, key),
+                ..template.clone()
+            };
+            self.secret_scanner
+                .scan_text(line.as_bytes(), &template, scan_entropy, findings);
+            if structured_scanning::is_sensitive_key(key)
+                && structured_scanning::is_plausible_secret(value)
+            {
+                findings.insert(AzureDevOpsFinding {
+                    diff: line.clone(),
+                    strings_found: vec![value.to_string()],
+                    reason: UNSECURED_VARIABLE_REASON.to_string(),
+                    ..template
+                });
+            }
+        }
+    }
+
+    /// Scans the description and comments of every work item in the project. Both are HTML, so
+    /// tags are stripped before scanning.
+    fn scan_work_items(
+        &self,
+        config: &AzureDevOpsConfig,
+        scan_entropy: bool,
+    ) -> Result<HashSet<AzureDevOpsFinding>, SimpleError> {
+        let mut findings: HashSet<AzureDevOpsFinding> = HashSet::new();
+        let query = json!({
+            "query": "SELECT [System.Id] FROM WorkItems WHERE [System.TeamProject] = @project"
+        });
+        let url = api_url(config, "wit/wiql", &config.api_version);
+        debug!("POST {}", url);
+        let response = try_with!(
+            retry::send(authorize(network::client().post(&url), config).json(&query)),
+            "Azure DevOps request failed"
+        );
+        let result: Value = try_with!(
+            check_status(response, &url)?.json(),
+            "Failed to parse work item query results"
+        );
+        let ids: Vec<u64> = result["workItems"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|w| w["id"].as_u64())
+            .collect();
+        debug!("Scanning {} work items", ids.len());
+
+        for batch in ids.chunks(WORK_ITEM_BATCH) {
+            let ids_param: Vec<String> = batch.iter().map(u64::to_string).collect();
+            let path = format!(
+                "wit/workitems?ids={}&fields=System.Description,System.ChangedDate",
+                ids_param.join(",")
+            );
+            for item in get_values(config, &path)? {
+                let id = item["id"].as_u64().unwrap_or(0);
+                let template = AzureDevOpsFinding {
+                    project: config.project.clone(),
+                    source: String::from("work_item"),
+                    path: format!("#{}", id),
+                    url: format!("{}/_workitems/edit/{}", config.project_url(), id),
+                    date: item["fields"]["System.ChangedDate"]
+                        .as_str()
+                        .unwrap_or("")
+                        .to_string(),
+                    ..Default::default()
+                };
+                if let Some(description) = item["fields"]["System.Description"].as_str() {
+                    self.scan_html(description, &template, scan_entropy, &mut findings);
+                }
+                self.scan_work_item_comments(config, id, &template, scan_entropy, &mut findings)?;
+            }
+        }
+        Ok(findings)
+    }
+
+    /// Pages through the comments on a single work item, following `continuationToken`
+    fn scan_work_item_comments(
+        &self,
+        config: &AzureDevOpsConfig,
+        id: u64,
+        template: &AzureDevOpsFinding,
+        scan_entropy: bool,
+        findings: &mut HashSet<AzureDevOpsFinding>,
+    ) -> Result<(), SimpleError> {
+        // the comments API has only ever been released as a preview
+        let api_version = format!("{}-preview", config.api_version);
+        let mut continuation: Option<String> = None;
+        loop {
+            let mut path = format!("wit/workItems/{}/comments", id);
+            if let Some(token) = &continuation {
+                path = format!("{}?continuationToken={}", path, token);
+            }
+            let url = api_url(config, &path, &api_version);
+            debug!("GET {}", url);
+            let response = try_with!(
+                retry::send(authorize(network::client().get(&url), config)),
+                "Azure DevOps request failed"
+            );
+            let page: Value = try_with!(
+                check_status(response, &url)?.json(),
+                "Failed to parse work item comments"
+            );
+            for comment in page["comments"].as_array().into_iter().flatten() {
+                if let Some(text) = comment["text"].as_str() {
+                    let template = AzureDevOpsFinding {
+                        url: format!("{}#{}", template.url, comment["id"]),
+                        date: comment["modifiedDate"].as_str().unwrap_or("").to_string(),
+                        ..template.clone()
+                    };
+                    self.scan_html(text, &template, scan_entropy, findings);
+                }
+            }
+            continuation = page["continuationToken"].as_str().map(String::from);
+            if continuation.is_none() {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Strips tags from a work item description or comment before scanning it
+    fn scan_html(
+        &self,
+        html: &str,
+        template: &AzureDevOpsFinding,
+        scan_entropy: bool,
+        findings: &mut HashSet<AzureDevOpsFinding>,
+    ) {
+        let text = decoding::decode_markup(html.as_bytes(), MarkupMode::Html);
+        self.secret_scanner
+            .scan_text(&text, template, scan_entropy, findings);
+    }
+}
+
+impl Default for AzureDevOpsScanner {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Builds the URL of a project-level API. `path` may already contain query parameters.
+fn api_url(config: &AzureDevOpsConfig, path: &str, api_version: &str) -> String {
+    let separator = if path.contains('?') { '&' } else { '?' };
+    format!(
+        "{}/_apis/{}{}api-version={}",
+        config.project_url(),
+        path,
+        separator,
+        api_version
+    )
+}
+
+/// PATs are sent as the password of HTTP basic auth, with an empty username
+fn authorize(builder: RequestBuilder, config: &AzureDevOpsConfig) -> RequestBuilder {
+    builder.basic_auth("", Some(&config.token))
+}
+
+fn check_status(response: Response, url: &str) -> Result<Response, SimpleError> {
+    // an expired or invalid PAT gets a 203 sign-in page rather than a 401
+    if !response.status().is_success() || response.status().as_u16() == 203 {
+        return Err(SimpleError::new(format!(
+            "Azure DevOps returned {} for {}",
+            response.status(),
+            url
+        )));
+    }
+    Ok(response)
+}
+
+/// Performs an authenticated GET against a project-level API
+fn get(config: &AzureDevOpsConfig, path: &str) -> Result<Response, SimpleError> {
+    let url = api_url(config, path, &config.api_version);
+    debug!("GET {}", url);
+    let response = try_with!(
+        retry::send(authorize(network::client().get(&url), config)),
+        "Azure DevOps request failed"
+    );
+    check_status(response, &url)
+}
+
+/// Returns the `value` array of every page of a list API, following the
+/// `x-ms-continuationtoken` header
+fn get_values(config: &AzureDevOpsConfig, path: &str) -> Result<Vec<Value>, SimpleError> {
+    let separator = if path.contains('?') { '&' } else { '?' };
+    let mut values: Vec<Value> = Vec::new();
+    let mut continuation: Option<String> = None;
+    loop {
+        let page_path = match &continuation {
+            Some(token) => format!("{}{}continuationToken={}", path, separator, token),
+            None => path.to_string(),
+        };
+        let response = get(config, &page_path)?;
+        continuation = response
+            .headers()
+            .get("x-ms-continuationtoken")
+            .and_then(|h| h.to_str().ok())
+            .map(String::from);
+        let page: Value = try_with!(response.json(), "Failed to parse Azure DevOps response");
+        if let Some(v) = page["value"].as_array() {
+            values.extend(v.iter().cloned());
+        }
+        if continuation.is_none() {
+            return Ok(values);
+        }
+    }
+}
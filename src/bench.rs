@@ -0,0 +1,229 @@
+//! Measuring how fast the rules of a scanner run, for finding the pathological rule that makes
+//! large scans slow.
+//!
+//! `run` scans a corpus twice: once with `SecretScanner::scan_bytes`, for the throughput of a real
+//! scan (decoding, chunks and threads included), then line by line with each rule's regex alone,
+//! timing every rule. Rules are reported slowest first, with their share of the total rule time
+//! and their number of matches. The corpus is the files of a target (`read_target`), or a
+//! generated sample of source code, configuration and logs with a few secrets (`corpus`).
+//!
+//! Allocations are counted when the program installs `CountingAllocator` as its global allocator,
+//! as the `rule_bench` binary does; the counts are None otherwise.
+//!
+//! # Examples
+//!
+//! ```
+//! use rusty_hogs::bench;
+//! use rusty_hogs::SecretScannerBuilder;
+//!
+//! let scanner = SecretScannerBuilder::new().build();
+//! let corpus = bench::corpus(64 * 1024);
+//! let report = bench::run(&scanner, &corpus, false);
+//! assert_eq!(report.bytes, corpus.len());
+//! assert_eq!(report.rules.len(), scanner.regex_map.len());
+//! assert!(report.rules.iter().any(|r| r.matches > 0));
+//! assert!(report.rules.windows(2).all(|w| w[0].seconds >= w[1].seconds));
+//! assert!(report.allocations.is_none());
+//! ```
+
+use crate::SecretScanner;
+use serde_derive::Serialize;
+use simple_error::{try_with, SimpleError};
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::fs;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::Instant;
+
+/// Directories `read_target` doesn't descend into
+const SKIPPED_DIRS: &[&str] = &[".git", "node_modules", "target"];
+
+/// Lines the generated corpus is made of, with `{}` replaced by a pseudo-random token
+const CORPUS_LINES: &[&str] = &[
+    "fn main() { let config = load_config(\"settings.toml\").expect(\"config\"); }",
+    "    if user.is_admin() && request.path().starts_with(\"/admin\") { return Ok(()); }",
+    "import os, sys, json  # standard library imports for the deployment script",
+    "2021-03-01T12:00:00Z INFO request_id={} method=GET path=/api/v1/items status=200",
+    "<dependency><groupId>org.example</groupId><artifactId>client</artifactId></dependency>",
+    "    \"description\": \"Internal service that forwards events to the queue\",",
+    "database:\n  host: db.internal\n  port: 5432\n  user: app",
+    "SELECT id, name, created_at FROM accounts WHERE org_id = ? ORDER BY created_at DESC;",
+    "    checksum: sha256:{}",
+    "// TODO: retry the request with exponential backoff when the server returns 503",
+    "aws_access_key_id = AKIA{}",
+    "Authorization: Bearer {}",
+    "contact the on-call engineer at ops@example.org before restarting the cluster",
+    "export SLACK_WEBHOOK=https://hooks.slack.com/services/T00000000/B00000000/{}",
+];
+
+/// The run time and matches of one rule
+#[derive(Serialize, Debug, PartialEq, Clone)]
+pub struct RuleTiming {
+    pub rule: String,
+    pub seconds: f64,
+    /// The share of the time of all rules, from 0 to 1
+    pub share: f64,
+    pub matches: usize,
+}
+
+/// Allocations made during the scan, see `CountingAllocator`
+#[derive(Serialize, Debug, PartialEq, Eq, Hash, Clone, Copy, Default)]
+pub struct AllocationStats {
+    pub allocations: u64,
+    pub bytes: u64,
+}
+
+/// The results of `run`
+#[derive(Serialize, Debug, PartialEq, Clone)]
+pub struct BenchReport {
+    pub bytes: usize,
+    pub lines: usize,
+    /// The time `scan_bytes` took
+    pub seconds: f64,
+    #[serde(rename = "mbPerSecond")]
+    pub mb_per_second: f64,
+    pub findings: usize,
+    /// The allocations of `scan_bytes`, None if `CountingAllocator` isn't installed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub allocations: Option<AllocationStats>,
+    /// Every rule, slowest first
+    pub rules: Vec<RuleTiming>,
+}
+
+static COUNTING: AtomicBool = AtomicBool::new(false);
+static ALLOCATIONS: AtomicU64 = AtomicU64::new(0);
+static ALLOCATED_BYTES: AtomicU64 = AtomicU64::new(0);
+
+/// A global allocator that counts allocations, for `BenchReport::allocations`:
+/// `#[global_allocator] static ALLOCATOR: CountingAllocator = CountingAllocator;`
+pub struct CountingAllocator;
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        COUNTING.store(true, Ordering::Relaxed);
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        ALLOCATED_BYTES.fetch_add(layout.size() as u64, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+/// The allocations counted so far, None if `CountingAllocator` isn't installed
+fn allocation_counts() -> Option<AllocationStats> {
+    if !COUNTING.load(Ordering::Relaxed) {
+        return None;
+    }
+    Some(AllocationStats {
+        allocations: ALLOCATIONS.load(Ordering::Relaxed),
+        bytes: ALLOCATED_BYTES.load(Ordering::Relaxed),
+    })
+}
+
+/// Benchmarks `scanner` on `data`, see the module documentation
+pub fn run(scanner: &SecretScanner, data: &[u8], scan_entropy: bool) -> BenchReport {
+    let before = allocation_counts();
+    let start = Instant::now();
+    let findings = scanner.scan_bytes(data, scan_entropy).len();
+    let seconds = start.elapsed().as_secs_f64();
+    let allocations = match (before, allocation_counts()) {
+        (Some(b), Some(a)) => Some(AllocationStats {
+            allocations: a.allocations - b.allocations,
+            bytes: a.bytes - b.bytes,
+        }),
+        _ => None,
+    };
+
+    let lines: Vec<&[u8]> = data.split(|b| *b == b'\n').collect();
+    let mut rules: Vec<RuleTiming> = scanner
+        .regex_map
+        .iter()
+        .map(|(rule, regex)| {
+            let start = Instant::now();
+            let matches = lines.iter().map(|l| regex.find_iter(l).count()).sum();
+            RuleTiming {
+                rule: rule.clone(),
+                seconds: start.elapsed().as_secs_f64(),
+                share: 0.0,
+                matches,
+            }
+        })
+        .collect();
+    let total: f64 = rules.iter().map(|r| r.seconds).sum();
+    for rule in &mut rules {
+        rule.share = if total > 0.0 {
+            rule.seconds / total
+        } else {
+            0.0
+        };
+    }
+    rules.sort_by(|a, b| {
+        b.seconds
+            .partial_cmp(&a.seconds)
+            .unwrap_or(std::cmp::Ordering::Equal)
+    });
+
+    BenchReport {
+        bytes: data.len(),
+        lines: lines.len(),
+        seconds,
+        mb_per_second: if seconds > 0.0 {
+            data.len() as f64 / 1_000_000.0 / seconds
+        } else {
+            0.0
+        },
+        findings,
+        allocations,
+        rules,
+    }
+}
+
+/// A generated corpus of about `size` bytes of source code, configuration and log lines, a few of
+/// which contain secrets. The same size always gives the same corpus.
+pub fn corpus(size: usize) -> Vec<u8> {
+    let mut data: Vec<u8> = Vec::with_capacity(size + 256);
+    // a xorshift generator, for tokens that differ from line to line
+    let mut state: u64 = 0x2545_f491_4f6c_dd1d;
+    let mut i = 0;
+    while data.len() < size {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        let token = format!("{:016X}{:016x}", state, state.rotate_left(29));
+        let line = CORPUS_LINES[i % CORPUS_LINES.len()].replace("{}", &token);
+        data.extend_from_slice(line.as_bytes());
+        data.push(b'\n');
+        i += 1;
+    }
+    data
+}
+
+/// Reads the files of a target, a file or a directory read recursively (skipping `.git`,
+/// `node_modules` and `target`), into one corpus
+pub fn read_target(path: &Path) -> Result<Vec<u8>, SimpleError> {
+    let mut data: Vec<u8> = Vec::new();
+    read_into(path, &mut data)?;
+    Ok(data)
+}
+
+fn read_into(path: &Path, data: &mut Vec<u8>) -> Result<(), SimpleError> {
+    if path.is_dir() {
+        let entries = try_with!(fs::read_dir(path), "Failed to read {}", path.display());
+        for entry in entries {
+            let entry = try_with!(entry, "Failed to read {}", path.display());
+            let skipped = SKIPPED_DIRS
+                .iter()
+                .any(|d| entry.file_name().to_str() == Some(d));
+            if !skipped {
+                read_into(&entry.path(), data)?;
+            }
+        }
+    } else {
+        let contents = try_with!(fs::read(path), "Failed to read {}", path.display());
+        data.extend_from_slice(&contents);
+        data.push(b'\n');
+    }
+    Ok(())
+}
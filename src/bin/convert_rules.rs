@@ -0,0 +1,91 @@
+//! Converts a gitleaks configuration (`.toml`) or trufflehog custom detectors (`.yaml`) to a rule
+//! pack JSON file, to review what `--regex` loads from them or to keep maintaining the rules in
+//! this format. See the `rule_formats` module for what is converted.
+//!
+//! # Usage
+//! ```text
+//! convert_rules [FLAGS] [OPTIONS] <RULES>
+//!
+//!FLAGS:
+//!        --prettyprint    Outputs the JSON in human readable format
+//!    -v, --verbose        Sets the level of debugging information
+//!    -h, --help           Prints help information
+//!    -V, --version        Prints version information
+//!
+//!OPTIONS:
+//!    -o, --outputfile <OUTPUT>    Sets the path to write the rule pack to (stdout by default)
+//!
+//!ARGS:
+//!    <RULES>    The gitleaks (.toml) or trufflehog (.yaml) rule file to convert
+//! ```
+
+#[macro_use]
+extern crate clap;
+
+use clap::ArgMatches;
+use log::{self, info};
+use serde_json::Value;
+use simple_error::{try_with, SimpleError};
+use std::fs;
+use std::path::Path;
+
+use rusty_hogs::rule_formats;
+use rusty_hogs::rules::RulePack;
+use rusty_hogs::SecretScanner;
+
+/// Main entry function that uses the [clap crate](https://docs.rs/clap/2.33.0/clap/)
+fn main() {
+    let matches = clap_app!(convert_rules =>
+        (version: "1.0.1")
+        (author: "Scott Cutler <scutler@newrelic.com>")
+        (about: "Converts gitleaks and trufflehog rules to a rule pack.")
+        (@arg RULES: +required "The gitleaks (.toml) or trufflehog (.yaml) rule file to convert")
+        (@arg OUTPUT: -o --outputfile +takes_value "Sets the path to write the rule pack to (stdout by default)")
+        (@arg PRETTYPRINT: --prettyprint "Outputs the JSON in human readable format")
+        (@arg VERBOSE: -v --verbose ... "Sets the level of debugging information")
+    )
+    .get_matches();
+    match run(&matches) {
+        Ok(()) => {}
+        Err(e) => panic!("error: {}", e),
+    }
+}
+
+/// Main logic contained here. Read and convert the rules, check them, then output the rule pack.
+fn run(arg_matches: &ArgMatches) -> Result<(), SimpleError> {
+    // Set logging
+    SecretScanner::set_logging(arg_matches.occurrences_of("VERBOSE"));
+
+    // Read and convert the rules
+    let path = arg_matches.value_of("RULES").unwrap();
+    let data = try_with!(fs::read_to_string(path), "Failed to read {}", path);
+    let pack: Value = match Path::new(path).extension().and_then(|e| e.to_str()) {
+        Some("toml") => rule_formats::from_gitleaks(&data)?,
+        Some("yaml") | Some("yml") => rule_formats::from_trufflehog(&data)?,
+        _ => {
+            return Err(SimpleError::new(format!(
+                "{} is not a gitleaks (.toml) or trufflehog (.yaml) rule file",
+                path
+            )))
+        }
+    };
+
+    // Load the converted pack like --regex would, logging the rules it can't use
+    if let Some(object) = pack.as_object() {
+        let loaded = RulePack::from_object(object, "converted")?;
+        info!("Converted {} rules from {}", loaded.rules.len(), path);
+    }
+
+    // Output the rule pack
+    let json = if arg_matches.is_present("PRETTYPRINT") {
+        serde_json::to_string_pretty(&pack)
+    } else {
+        serde_json::to_string(&pack)
+    };
+    let json = try_with!(json, "Failed to serialize the rule pack");
+    match arg_matches.value_of("OUTPUT") {
+        Some(path) => try_with!(fs::write(path, json), "Failed to write {}", path),
+        None => println!("{}", json),
+    }
+    Ok(())
+}
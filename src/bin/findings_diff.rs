@@ -0,0 +1,85 @@
+//! Compares the JSON reports of two scans, reporting the findings added and resolved between them
+//! so that a scheduled report can list the net new secrets. Findings are matched by their rule,
+//! location, strings found and commit; see `FindingsDiff` in the `stats` module.
+//!
+//! # Usage
+//! ```text
+//! findings_diff [FLAGS] [OPTIONS] <OLD> <NEW>
+//!
+//!FLAGS:
+//!        --added-only     Outputs the added findings as a JSON report instead of the whole comparison
+//!        --prettyprint    Outputs the JSON in human readable format
+//!    -v, --verbose        Sets the level of debugging information
+//!    -h, --help           Prints help information
+//!    -V, --version        Prints version information
+//!
+//!OPTIONS:
+//!    -o, --outputfile <OUTPUT>    Sets the path to write the comparison to (stdout by default)
+//!
+//!ARGS:
+//!    <OLD>    The JSON report of the earlier scan
+//!    <NEW>    The JSON report of the later scan
+//! ```
+
+#[macro_use]
+extern crate clap;
+
+use clap::ArgMatches;
+use log::{self, info};
+use simple_error::{try_with, SimpleError};
+use std::fs;
+
+use rusty_hogs::stats::{FindingsDiff, ScanReport};
+use rusty_hogs::SecretScanner;
+
+/// Main entry function that uses the [clap crate](https://docs.rs/clap/2.33.0/clap/)
+fn main() {
+    let matches = clap_app!(findings_diff =>
+        (version: "1.0.1")
+        (author: "Scott Cutler <scutler@newrelic.com>")
+        (about: "Compares the reports of two scans.")
+        (@arg OLD: +required "The JSON report of the earlier scan")
+        (@arg NEW: +required "The JSON report of the later scan")
+        (@arg ADDEDONLY: --("added-only") "Outputs the added findings as a JSON report instead of the whole comparison")
+        (@arg OUTPUT: -o --outputfile +takes_value "Sets the path to write the comparison to (stdout by default)")
+        (@arg PRETTYPRINT: --prettyprint "Outputs the JSON in human readable format")
+        (@arg VERBOSE: -v --verbose ... "Sets the level of debugging information")
+    )
+    .get_matches();
+    match run(&matches) {
+        Ok(()) => {}
+        Err(e) => panic!("error: {}", e),
+    }
+}
+
+/// Main logic contained here. Read the reports, compare them, then output the comparison.
+fn run(arg_matches: &ArgMatches) -> Result<(), SimpleError> {
+    // Set logging
+    SecretScanner::set_logging(arg_matches.occurrences_of("VERBOSE"));
+
+    // Read and compare the reports
+    let old = ScanReport::from_file(arg_matches.value_of("OLD").unwrap())?;
+    let new = ScanReport::from_file(arg_matches.value_of("NEW").unwrap())?;
+    let diff = FindingsDiff::compute(&old.findings, &new.findings);
+    info!(
+        "{} findings added, {} resolved, {} unchanged",
+        diff.added.len(),
+        diff.resolved.len(),
+        diff.unchanged
+    );
+
+    // Output the comparison
+    let pretty_print = arg_matches.is_present("PRETTYPRINT");
+    let json = match (arg_matches.is_present("ADDEDONLY"), pretty_print) {
+        (true, true) => serde_json::to_string_pretty(&diff.added),
+        (true, false) => serde_json::to_string(&diff.added),
+        (false, true) => serde_json::to_string_pretty(&diff),
+        (false, false) => serde_json::to_string(&diff),
+    };
+    let json = try_with!(json, "Failed to serialize the comparison");
+    match arg_matches.value_of("OUTPUT") {
+        Some(path) => try_with!(fs::write(path, json), "Failed to write {}", path),
+        None => println!("{}", json),
+    }
+    Ok(())
+}
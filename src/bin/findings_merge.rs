@@ -0,0 +1,91 @@
+//! Merges the reports of different scanners and runs into one, listing each finding once with the
+//! reports it was found in and counting the findings of each report, source and rule. Reports can
+//! be in any output format; see `normalize_report` in the `reporting` module and `FindingsMerge` in
+//! the `stats` module.
+//!
+//! # Usage
+//! ```text
+//! findings_merge [FLAGS] [OPTIONS] <REPORT>...
+//!
+//!FLAGS:
+//!        --findings-only    Outputs the merged findings as a JSON report instead of the whole merge
+//!        --prettyprint      Outputs the JSON in human readable format
+//!    -v, --verbose          Sets the level of debugging information
+//!    -h, --help             Prints help information
+//!    -V, --version          Prints version information
+//!
+//!OPTIONS:
+//!    -o, --outputfile <OUTPUT>    Sets the path to write the merged report to (stdout by default)
+//!
+//!ARGS:
+//!    <REPORT>...    Reports of any scanner, in any output format
+//! ```
+
+#[macro_use]
+extern crate clap;
+
+use clap::ArgMatches;
+use log::{self, info};
+use serde_json::Value;
+use simple_error::{try_with, SimpleError};
+use std::fs;
+
+use rusty_hogs::reporting::normalize_report;
+use rusty_hogs::stats::FindingsMerge;
+use rusty_hogs::SecretScanner;
+
+/// Main entry function that uses the [clap crate](https://docs.rs/clap/2.33.0/clap/)
+fn main() {
+    let matches = clap_app!(findings_merge =>
+        (version: "1.0.1")
+        (author: "Scott Cutler <scutler@newrelic.com>")
+        (about: "Merges the reports of different scanners and runs, listing each finding once.")
+        (@arg REPORT: +required +multiple "Reports of any scanner, in any output format")
+        (@arg FINDINGSONLY: --("findings-only") "Outputs the merged findings as a JSON report instead of the whole merge")
+        (@arg OUTPUT: -o --outputfile +takes_value "Sets the path to write the merged report to (stdout by default)")
+        (@arg PRETTYPRINT: --prettyprint "Outputs the JSON in human readable format")
+        (@arg VERBOSE: -v --verbose ... "Sets the level of debugging information")
+    )
+    .get_matches();
+    match run(&matches) {
+        Ok(()) => {}
+        Err(e) => panic!("error: {}", e),
+    }
+}
+
+/// Main logic contained here. Read and normalize the reports, merge them, then output the merge.
+fn run(arg_matches: &ArgMatches) -> Result<(), SimpleError> {
+    // Set logging
+    SecretScanner::set_logging(arg_matches.occurrences_of("VERBOSE"));
+
+    // Read and merge the reports
+    let mut reports: Vec<(String, Vec<Value>)> = Vec::new();
+    for path in arg_matches.values_of("REPORT").unwrap() {
+        let json = try_with!(fs::read_to_string(path), "Failed to read {}", path);
+        let report: Value = try_with!(serde_json::from_str(&json), "Failed to parse {}", path);
+        let findings = try_with!(normalize_report(&report), "Failed to read {}", path);
+        info!("{}: {} findings", path, findings.len());
+        reports.push((path.to_string(), findings));
+    }
+    let merge = FindingsMerge::compute(&reports);
+    info!(
+        "{} distinct findings, {} duplicates",
+        merge.findings.len(),
+        merge.duplicates
+    );
+
+    // Output the merge
+    let pretty_print = arg_matches.is_present("PRETTYPRINT");
+    let json = match (arg_matches.is_present("FINDINGSONLY"), pretty_print) {
+        (true, true) => serde_json::to_string_pretty(&merge.findings),
+        (true, false) => serde_json::to_string(&merge.findings),
+        (false, true) => serde_json::to_string_pretty(&merge),
+        (false, false) => serde_json::to_string(&merge),
+    };
+    let json = try_with!(json, "Failed to serialize the merged report");
+    match arg_matches.value_of("OUTPUT") {
+        Some(path) => try_with!(fs::write(path, json), "Failed to write {}", path),
+        None => println!("{}", json),
+    }
+    Ok(())
+}
@@ -0,0 +1,93 @@
+//! Computes program-level metrics (open and remediated findings, mean time to remediate, findings
+//! per 1,000 commits and the sources with the most findings) from the JSON reports of repeated
+//! scans. Nothing is sent anywhere; see the `stats` module for how the metrics are computed.
+//!
+//! # Usage
+//! ```text
+//! findings_stats [FLAGS] [OPTIONS] <REPORT>...
+//!
+//!FLAGS:
+//!        --prettyprint    Outputs the JSON in human readable format
+//!    -v, --verbose        Sets the level of debugging information
+//!    -h, --help           Prints help information
+//!    -V, --version        Prints version information
+//!
+//!OPTIONS:
+//!        --commits <COMMITS>      The number of commits the scans covered, for findings per 1,000 commits
+//!    -o, --outputfile <OUTPUT>    Sets the path to write the metrics to (stdout by default)
+//!        --top <TOP>              The number of sources to list (10 by default)
+//!
+//!ARGS:
+//!    <REPORT>...    JSON reports of the same scan run at different times, dated by their modification time
+//! ```
+
+#[macro_use]
+extern crate clap;
+
+use clap::ArgMatches;
+use log::{self, info};
+use simple_error::{try_with, SimpleError};
+use std::fs;
+
+use rusty_hogs::stats::{FindingStats, ScanReport};
+use rusty_hogs::SecretScanner;
+
+const DEFAULT_TOP: usize = 10;
+
+/// Main entry function that uses the [clap crate](https://docs.rs/clap/2.33.0/clap/)
+fn main() {
+    let matches = clap_app!(findings_stats =>
+        (version: "1.0.1")
+        (author: "Scott Cutler <scutler@newrelic.com>")
+        (about: "Computes metrics from the reports of repeated scans.")
+        (@arg REPORT: +required +multiple "JSON reports of the same scan run at different times, dated by their modification time")
+        (@arg COMMITS: --commits +takes_value "The number of commits the scans covered, for findings per 1,000 commits")
+        (@arg TOP: --top +takes_value "The number of sources to list (10 by default)")
+        (@arg OUTPUT: -o --outputfile +takes_value "Sets the path to write the metrics to (stdout by default)")
+        (@arg PRETTYPRINT: --prettyprint "Outputs the JSON in human readable format")
+        (@arg VERBOSE: -v --verbose ... "Sets the level of debugging information")
+    )
+    .get_matches();
+    match run(&matches) {
+        Ok(()) => {}
+        Err(e) => panic!("error: {}", e),
+    }
+}
+
+/// Main logic contained here. Read the reports, compute the metrics, then output them.
+fn run(arg_matches: &ArgMatches) -> Result<(), SimpleError> {
+    // Set logging
+    SecretScanner::set_logging(arg_matches.occurrences_of("VERBOSE"));
+
+    // Initialize some variables
+    let commits: Option<u64> = match arg_matches.value_of("COMMITS") {
+        Some(c) => Some(try_with!(c.parse(), "--commits must be a number")),
+        None => None,
+    };
+    let top: usize = match arg_matches.value_of("TOP") {
+        Some(t) => try_with!(t.parse(), "--top must be a number"),
+        None => DEFAULT_TOP,
+    };
+
+    // Read the reports and compute the metrics
+    let mut scans: Vec<ScanReport> = Vec::new();
+    for path in arg_matches.values_of("REPORT").unwrap() {
+        let scan = ScanReport::from_file(path)?;
+        info!("{} ({}): {} findings", path, scan.date, scan.findings.len());
+        scans.push(scan);
+    }
+    let stats = FindingStats::compute(&scans, commits, top);
+
+    // Output the metrics
+    let json = if arg_matches.is_present("PRETTYPRINT") {
+        serde_json::to_string_pretty(&stats)
+    } else {
+        serde_json::to_string(&stats)
+    };
+    let json = try_with!(json, "Failed to serialize the metrics");
+    match arg_matches.value_of("OUTPUT") {
+        Some(path) => try_with!(fs::write(path, json), "Failed to write {}", path),
+        None => println!("{}", json),
+    }
+    Ok(())
+}
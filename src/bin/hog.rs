@@ -0,0 +1,203 @@
+//! Scans every target of a manifest (see the `manifest` module) with the scanner of its source, a
+//! few at a time, and merges their findings into one report.
+//!
+//! # Usage
+//! ```text
+//! hog [FLAGS] [OPTIONS] <MANIFEST>
+//!
+//!FLAGS:
+//!        --prettyprint    Outputs the JSON in human readable format
+//!    -v, --verbose        Sets the level of debugging information
+//!    -h, --help           Prints help information
+//!    -V, --version        Prints version information
+//!
+//!OPTIONS:
+//!        --bindir <DIR>               The directory of the scanner binaries (the directory of hog by default, or the PATH)
+//!    -o, --outputfile <OUTPUT>        Sets the path to write the merged report to (stdout by default)
+//!        --plugins <PLUGINS>          A directory of plugin descriptions (JSON files) to add to the sources
+//!                                     (RUSTY_HOG_PLUGINS by default)
+//!        --workdir <DIR>              Keeps the results of each target in this directory (a temporary directory that is
+//!                                     removed by default)
+//!        --workers <N>                The number of scanners to run at the same time (the manifest's workers, or 4, by
+//!                                     default)
+//!
+//!ARGS:
+//!    <MANIFEST>    The TOML or YAML manifest of the targets to scan
+//! ```
+
+#[macro_use]
+extern crate clap;
+
+use clap::ArgMatches;
+use log::{self, error, info};
+use serde_json::Value;
+use simple_error::{try_with, SimpleError};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
+use tempdir::TempDir;
+
+use rusty_hogs::jobs::{JobQueue, JobState, Runner};
+use rusty_hogs::manifest::ScanManifest;
+use rusty_hogs::sources::SourceRegistry;
+use rusty_hogs::SecretScanner;
+
+const DEFAULT_WORKERS: usize = 4;
+/// The file of the work directory the manifest's settings are written to
+const SETTINGS_FILE: &str = "settings.yaml";
+
+/// Main entry function that uses the [clap crate](https://docs.rs/clap/2.33.0/clap/)
+fn main() {
+    let matches = clap_app!(hog =>
+        (version: "1.0.1")
+        (author: "Scott Cutler <scutler@newrelic.com>")
+        (about: "Scans the targets of a manifest with the scanner of each and merges their findings.")
+        (@arg MANIFEST: +required "The TOML or YAML manifest of the targets to scan")
+        (@arg WORKERS: --workers [N] "The number of scanners to run at the same time (the manifest's workers, or 4, by default)")
+        (@arg WORKDIR: --workdir [DIR] "Keeps the results of each target in this directory (a temporary directory that is removed by default)")
+        (@arg BINDIR: --bindir [DIR] "The directory of the scanner binaries (the directory of hog by default, or the PATH)")
+        (@arg PLUGINS: --plugins +takes_value "A directory of plugin descriptions (JSON files) to add to the sources (RUSTY_HOG_PLUGINS by default)")
+        (@arg OUTPUT: -o --outputfile +takes_value "Sets the path to write the merged report to (stdout by default)")
+        (@arg PRETTYPRINT: --prettyprint "Outputs the JSON in human readable format")
+        (@arg VERBOSE: -v --verbose ... "Sets the level of debugging information")
+    )
+    .get_matches();
+    match run(&matches) {
+        Ok(()) => {}
+        Err(e) => panic!("error: {}", e),
+    }
+}
+
+/// Main logic contained here. Read the manifest, run a job for each target, wait for all of them,
+/// then output the findings of the targets that were scanned and fail if any target failed.
+fn run(arg_matches: &ArgMatches) -> Result<(), SimpleError> {
+    // Set logging
+    SecretScanner::set_logging(arg_matches.occurrences_of("VERBOSE"));
+
+    // Read the manifest and build the sources
+    let manifest = ScanManifest::read(arg_matches.value_of("MANIFEST").unwrap())?;
+    let workers = match arg_matches.value_of("WORKERS") {
+        Some(n) => try_with!(n.parse::<usize>(), "Invalid --workers {}", n),
+        None => manifest.workers.unwrap_or(DEFAULT_WORKERS),
+    };
+    let mut registry = SourceRegistry::new();
+    let plugins = match arg_matches.value_of("PLUGINS") {
+        Some(p) => Some(p.to_string()),
+        None => env::var("RUSTY_HOG_PLUGINS").ok(),
+    };
+    if let Some(dir) = plugins {
+        registry.load_plugins(Path::new(&dir))?;
+    }
+    let bin_dir = match arg_matches.value_of("BINDIR") {
+        Some(d) => Some(PathBuf::from(d)),
+        None => env::current_exe()
+            .ok()
+            .and_then(|p| p.parent().map(PathBuf::from)),
+    };
+
+    // The results go to --workdir, or to a temporary directory removed once they are merged
+    let temp_dir: Option<TempDir>;
+    let work_dir = match arg_matches.value_of("WORKDIR") {
+        Some(d) => {
+            temp_dir = None;
+            PathBuf::from(d)
+        }
+        None => {
+            let t = try_with!(TempDir::new("hog"), "Failed to create a work directory");
+            let path = t.path().to_path_buf();
+            temp_dir = Some(t);
+            path
+        }
+    };
+    try_with!(
+        fs::create_dir_all(&work_dir),
+        "Failed to create the work directory {}",
+        work_dir.display()
+    );
+    let work_dir = try_with!(
+        fs::canonicalize(&work_dir),
+        "Failed to resolve the work directory {}",
+        work_dir.display()
+    );
+    let settings_path = if manifest.has_settings() {
+        let path = work_dir.join(SETTINGS_FILE);
+        let yaml = try_with!(
+            serde_yaml::to_string(&manifest.settings),
+            "Failed to serialize the settings"
+        );
+        try_with!(fs::write(&path, yaml), "Failed to write {}", path.display());
+        Some(path)
+    } else {
+        None
+    };
+    let requests = manifest.job_requests(&registry, settings_path.as_deref())?;
+
+    // Run the scanners from the current directory, so relative paths in the manifest resolve
+    let mut queue = JobQueue::start(registry, &work_dir, Runner::Binary(bin_dir), workers)?;
+    let current_dir = try_with!(env::current_dir(), "Failed to read the current directory");
+    queue.set_current_dir(&current_dir);
+    let mut ids: Vec<String> = Vec::with_capacity(requests.len());
+    for request in requests {
+        ids.push(queue.submit(request)?.id);
+    }
+    info!(
+        "Scanning {} targets with {} workers",
+        ids.len(),
+        workers.max(1)
+    );
+
+    // Wait for every job and merge their findings, tagging each with its target
+    let mut findings: Vec<Value> = Vec::new();
+    let mut failed: Vec<&str> = Vec::new();
+    for (target, id) in manifest.targets.iter().zip(ids.iter()) {
+        let status = loop {
+            match queue.status(id) {
+                Some(s) if s.state.is_finished() => break s,
+                Some(_) => thread::sleep(Duration::from_millis(500)),
+                None => return Err(SimpleError::new(format!("Lost the job of {}", target.name))),
+            }
+        };
+        if status.state == JobState::Failed {
+            error!(
+                "Failed to scan {}: {}",
+                target.name,
+                status.error.unwrap_or_default()
+            );
+            failed.push(&target.name);
+            continue;
+        }
+        let target_findings = queue.findings(id)?;
+        let target_findings = target_findings.as_array().cloned().unwrap_or_default();
+        info!("Found {} secrets in {}", target_findings.len(), target.name);
+        findings.extend(target_findings.into_iter().map(|mut finding| {
+            if let Some(object) = finding.as_object_mut() {
+                object.insert(String::from("target"), Value::String(target.name.clone()));
+            }
+            finding
+        }));
+    }
+
+    // Output the merged report
+    let json = if arg_matches.is_present("PRETTYPRINT") {
+        serde_json::to_string_pretty(&findings)
+    } else {
+        serde_json::to_string(&findings)
+    };
+    let json = try_with!(json, "Failed to serialize the findings");
+    match arg_matches.value_of("OUTPUT") {
+        Some(path) => try_with!(fs::write(path, json), "Failed to write {}", path),
+        None => println!("{}", json),
+    }
+    drop(temp_dir);
+    if !failed.is_empty() {
+        return Err(SimpleError::new(format!(
+            "{} of {} targets failed: {}",
+            failed.len(),
+            manifest.targets.len(),
+            failed.join(", ")
+        )));
+    }
+    Ok(())
+}
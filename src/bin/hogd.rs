@@ -0,0 +1,309 @@
+//! A scan server: runs the Rusty Hog scanners as jobs submitted over a small HTTP API, on a pool
+//! of workers (see the `jobs` module). The scanners run in-process, and a job can only set the
+//! target and the options of its source that choose what to scan, see `GET /sources`.
+//!
+//! Without a token, hogd only listens on a loopback address.
+//!
+//! Every job scans with the rules of `--regex` and the allowlist of `--allowlist`, which hogd
+//! reloads when the files change (see `rules::RuleWatcher`). A job keeps the rules it started
+//! with, and its findings have the `rules_version` they were found with.
+//!
+//! hogd forgets a finished job, and removes its results, a day after it finished (`--job-ttl`) or
+//! 10 minutes after its findings were first fetched, and keeps at most `--max-jobs` finished jobs
+//! and as many waiting for a worker. A job scans on at most `jobs::MAX_THREADS` threads.
+//!
+//! hogd runs as a managed service (see the `service` module): under systemd from a unit with
+//! `Type=notify`, which is told once it listens and pinged on a `WatchdogSec`, and on Windows as
+//! a service created with `sc.exe create hogd binPath= "<path of hogd.exe> --service ..."`.
+//! SIGTERM, or stopping the service, lets the running jobs finish before exiting.
+//!
+//! # Usage
+//! ```text
+//! hogd [FLAGS] [OPTIONS]
+//!
+//!FLAGS:
+//!        --no-default-allowlist    Reports the well-known example secrets and placeholders that are suppressed by
+//!                                  default
+//!        --service                 Runs as a Windows service, under the service control manager
+//!    -v, --verbose                 Sets the level of debugging information
+//!    -h, --help                    Prints help information
+//!    -V, --version                 Prints version information
+//!
+//!OPTIONS:
+//!        --allowlist <FILE>         Sets a JSON file of findings to suppress in every job, reloaded when it
+//!                                   changes
+//!        --job-ttl <MINUTES>        How long a finished job and its findings are kept (1440 by default)
+//!        --listen <ADDRESS>         The address to listen on (127.0.0.1:8080 by default), only a loopback address
+//!                                   without a token
+//!        --max-jobs <N>             The most finished jobs kept, and jobs waiting for a worker (1000 by default)
+//!        --regex <REGEX>...         Sets a custom regex JSON file for every job (repeatable, "builtin" for the
+//!                                   default rules), reloaded when it changes
+//!        --token <TOKEN>            Requires this bearer token in the Authorization header of requests
+//!                                   (HOGD_TOKEN by default)
+//!        --workdir <DIR>            The directory the jobs write their results to (hogd in the temporary
+//!                                   directory by default)
+//!        --workers <N>              The number of scans to run at the same time (4 by default)
+//! ```
+//!
+//! # API
+//! ```text
+//! GET  /health              Whether hogd is up, the number of queued and running jobs and the
+//!                           version of the rules, without the token, for health checks
+//! GET  /sources             The sources jobs can scan, with the options a job can set
+//! POST /jobs                Submits a job, e.g. {"source": "git", "args": ["https://host/repo.git"]}
+//! GET  /jobs                The status of every job
+//! GET  /jobs/<ID>           The status of a job
+//! GET  /jobs/<ID>/findings  The findings of a job that succeeded
+//! ```
+
+#[macro_use]
+extern crate clap;
+
+use clap::ArgMatches;
+use log::{self, debug, info, warn};
+use serde::Serialize;
+use serde_json::json;
+use simple_error::{try_with, SimpleError};
+use std::env;
+use std::net::ToSocketAddrs;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+use tiny_http::{Header, Method, Request, Response, Server};
+
+use rusty_hogs::cli;
+use rusty_hogs::jobs::{JobLimits, JobQueue, JobRequest, JobState, Runner};
+use rusty_hogs::rules::RuleWatcher;
+use rusty_hogs::service;
+use rusty_hogs::shutdown;
+use rusty_hogs::sources::SourceRegistry;
+use rusty_hogs::{SecretScanner, SecretScannerBuilder};
+
+const DEFAULT_LISTEN: &str = "127.0.0.1:8080";
+const DEFAULT_WORKERS: usize = 4;
+const TOKEN_VAR: &str = "HOGD_TOKEN";
+/// How long to wait for a request before checking whether hogd was asked to stop
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Main entry function that uses the [clap crate](https://docs.rs/clap/2.33.0/clap/)
+fn main() {
+    let matches = clap_app!(hogd =>
+        (version: "1.0.1")
+        (author: "Scott Cutler <scutler@newrelic.com>")
+        (about: "Runs Rusty Hog scans submitted over an HTTP API.")
+        (@arg LISTEN: --listen [ADDRESS] "The address to listen on (127.0.0.1:8080 by default), only a loopback address without a token")
+        (@arg WORKERS: --workers [N] "The number of scans to run at the same time (4 by default)")
+        (@arg WORKDIR: --workdir [DIR] "The directory the jobs write their results to (hogd in the temporary directory by default)")
+        (@arg JOBTTL: --("job-ttl") [MINUTES] "How long a finished job and its findings are kept (1440 by default)")
+        (@arg MAXJOBS: --("max-jobs") [N] "The most finished jobs kept, and jobs waiting for a worker (1000 by default)")
+        (@arg TOKEN: --token [TOKEN] "Requires this bearer token in the Authorization header of requests (HOGD_TOKEN by default)")
+        (@arg REGEX: --regex +takes_value +multiple number_of_values(1) "Sets a custom regex JSON file for every job (repeatable, \"builtin\" for the default rules), reloaded when it changes")
+        (@arg ALLOWLIST: --allowlist [FILE] "Sets a JSON file of findings to suppress in every job, reloaded when it changes")
+        (@arg NODEFAULTALLOWLIST: --("no-default-allowlist") "Reports the well-known example secrets and placeholders that are suppressed by default")
+        (@arg SERVICE: --service "Runs as a Windows service, under the service control manager")
+        (@arg VERBOSE: -v --verbose ... "Sets the level of debugging information")
+    )
+    .get_matches();
+    let result = if matches.is_present("SERVICE") {
+        service::run_service("hogd", move || run(&matches))
+    } else {
+        run(&matches)
+    };
+    match result {
+        Ok(()) => {}
+        Err(e) => panic!("error: {}", e),
+    }
+}
+
+/// Main logic contained here. Start the workers, then answer requests until asked to stop, and
+/// wait for the running jobs.
+fn run(arg_matches: &ArgMatches) -> Result<(), SimpleError> {
+    // Set logging
+    SecretScanner::set_logging(arg_matches.occurrences_of("VERBOSE"));
+    shutdown::install();
+
+    // Parse the arguments
+    let listen = arg_matches.value_of("LISTEN").unwrap_or(DEFAULT_LISTEN);
+    let workers = match arg_matches.value_of("WORKERS") {
+        Some(n) => try_with!(n.parse::<usize>(), "Invalid --workers {}", n),
+        None => DEFAULT_WORKERS,
+    };
+    let work_dir = match arg_matches.value_of("WORKDIR") {
+        Some(d) => PathBuf::from(d),
+        None => env::temp_dir().join("hogd"),
+    };
+    let mut limits = JobLimits::default();
+    if let Some(n) = arg_matches.value_of("MAXJOBS") {
+        let n = try_with!(n.parse::<usize>(), "Invalid --max-jobs {}", n);
+        limits.max_queued = n;
+        limits.max_finished = n;
+    }
+    if let Some(m) = arg_matches.value_of("JOBTTL") {
+        let minutes = try_with!(m.parse::<u64>(), "Invalid --job-ttl {}", m);
+        limits.ttl = Duration::from_secs(minutes.saturating_mul(60));
+    }
+    let token = cli::value_or_env(arg_matches, "TOKEN", TOKEN_VAR);
+    if token.is_none() && !is_loopback(listen)? {
+        return Err(SimpleError::new(format!(
+            "Refusing to listen on {} without a --token or {}, anyone who can connect could run scans",
+            listen, TOKEN_VAR
+        )));
+    }
+
+    // Load the rules of the jobs
+    let rules = Arc::new(Mutex::new(RuleWatcher::new(
+        SecretScannerBuilder::new().conf_argm(arg_matches),
+    )));
+
+    // Start the workers
+    let mut queue = JobQueue::start(SourceRegistry::new(), &work_dir, Runner::InProcess, workers)?;
+    queue.set_rules(Arc::clone(&rules));
+    queue.set_limits(limits);
+    let sources = queue.sources();
+
+    // Answer requests
+    let server = match Server::http(listen) {
+        Ok(s) => s,
+        Err(e) => {
+            return Err(SimpleError::new(format!(
+                "Failed to listen on {}: {}",
+                listen, e
+            )))
+        }
+    };
+    info!("Listening on {} with {} workers", listen, workers);
+    service::ready(&format!("Listening on {} with {} workers", listen, workers));
+    while !shutdown::interrupted() {
+        service::alive();
+        rules.lock().unwrap().reload_if_changed();
+        queue.forget_expired();
+        let mut request = match server.recv_timeout(POLL_INTERVAL) {
+            Ok(Some(r)) => r,
+            Ok(None) => continue,
+            Err(e) => {
+                warn!("Failed to receive a request: {}", e);
+                continue;
+            }
+        };
+        debug!("{} {}", request.method(), request.url());
+        let (code, body) = if is_health_check(&request) {
+            (200, health(&queue, &rules))
+        } else if authorized(&request, token.as_deref()) {
+            route(&mut request, &queue, &sources)
+        } else {
+            (401, json!({ "error": "Missing or wrong bearer token" }))
+        };
+        if let Err(e) = request.respond(json_response(code, &body)) {
+            warn!("Failed to answer a request: {}", e);
+        }
+    }
+
+    // Stop taking jobs, and let those already running finish
+    drop(server);
+    service::stopping();
+    info!("Stopping, waiting for the running jobs");
+    while queue.list().iter().any(|j| j.state == JobState::Running) {
+        service::alive();
+        thread::sleep(POLL_INTERVAL);
+    }
+    Ok(())
+}
+
+/// True for `GET /health`, which needs no token
+fn is_health_check(request: &Request) -> bool {
+    request.method() == &Method::Get && request.url().split('?').next() == Some("/health")
+}
+
+/// The body of `GET /health`: hogd is up, with the number of queued and running jobs and the
+/// version of the rules new jobs start with
+fn health(queue: &JobQueue, rules: &Mutex<RuleWatcher>) -> serde_json::Value {
+    let jobs = queue.list();
+    let count = |state: JobState| jobs.iter().filter(|j| j.state == state).count();
+    json!({
+        "status": "ok",
+        "queued": count(JobState::Queued),
+        "running": count(JobState::Running),
+        "rules_version": rules.lock().unwrap().scanner().rules_version.clone(),
+    })
+}
+
+/// True if every address `listen` resolves to is a loopback address
+fn is_loopback(listen: &str) -> Result<bool, SimpleError> {
+    let addresses: Vec<_> = try_with!(
+        listen.to_socket_addrs(),
+        "Invalid --listen address {}",
+        listen
+    )
+    .collect();
+    Ok(!addresses.is_empty() && addresses.iter().all(|a| a.ip().is_loopback()))
+}
+
+/// True if the request has the bearer token, or no token is needed
+fn authorized(request: &Request, token: Option<&str>) -> bool {
+    let token = match token {
+        Some(t) => t,
+        None => return true,
+    };
+    let expected = format!("Bearer {}", token);
+    request
+        .headers()
+        .iter()
+        .any(|h| h.field.equiv("Authorization") && h.value.as_str() == expected.as_str())
+}
+
+/// Answer a request, returning the status code and JSON body of the response
+fn route<T: Serialize>(
+    request: &mut Request,
+    queue: &JobQueue,
+    sources: &T,
+) -> (u16, serde_json::Value) {
+    let url = request.url().split('?').next().unwrap_or("").to_string();
+    let path: Vec<&str> = url.split('/').filter(|s| !s.is_empty()).collect();
+    let method = request.method().clone();
+    match (&method, path.as_slice()) {
+        (&Method::Get, ["sources"]) => (200, json!(sources)),
+        (&Method::Get, ["jobs"]) => (200, json!(queue.list())),
+        (&Method::Post, ["jobs"]) => {
+            let mut body = String::new();
+            if let Err(e) = request.as_reader().read_to_string(&mut body) {
+                return (
+                    400,
+                    json!({ "error": format!("Failed to read the request: {}", e) }),
+                );
+            }
+            let job: JobRequest = match serde_json::from_str(&body) {
+                Ok(j) => j,
+                Err(e) => return (400, json!({ "error": format!("Invalid job: {}", e) })),
+            };
+            match queue.submit(job) {
+                Ok(status) => (202, json!(status)),
+                Err(e) => (400, json!({ "error": e.to_string() })),
+            }
+        }
+        (&Method::Get, ["jobs", id]) => match queue.status(id) {
+            Some(status) => (200, json!(status)),
+            None => (404, json!({ "error": format!("Unknown job {}", id) })),
+        },
+        (&Method::Get, ["jobs", id, "findings"]) => match queue.status(id) {
+            Some(status) if status.state == JobState::Succeeded => match queue.findings(id) {
+                Ok(findings) => (200, findings),
+                Err(e) => (500, json!({ "error": e.to_string() })),
+            },
+            Some(status) => (409, json!(status)),
+            None => (404, json!({ "error": format!("Unknown job {}", id) })),
+        },
+        _ => (
+            404,
+            json!({ "error": format!("No route for {} {}", method, url) }),
+        ),
+    }
+}
+
+/// A JSON response with this status code
+fn json_response(code: u16, body: &serde_json::Value) -> Response<std::io::Cursor<Vec<u8>>> {
+    let content_type = Header::from_bytes(&b"Content-Type"[..], &b"application/json"[..]).unwrap();
+    Response::from_string(body.to_string())
+        .with_status_code(code)
+        .with_header(content_type)
+}
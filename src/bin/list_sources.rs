@@ -0,0 +1,102 @@
+//! Lists the data sources Rusty Hog can scan, with the binary, target, authentication and options
+//! of each one.
+//!
+//! # Usage
+//! ```text
+//! list_sources [FLAGS] [OPTIONS] [SOURCE]
+//!
+//!FLAGS:
+//!        --json           Outputs the registry as JSON
+//!        --prettyprint    Outputs the JSON in human readable format
+//!    -v, --verbose        Sets the level of debugging information
+//!    -h, --help           Prints help information
+//!    -V, --version        Prints version information
+//!
+//!OPTIONS:
+//!        --plugins <PLUGINS>    A directory of plugin descriptions (JSON files) to add to the list (RUSTY_HOG_PLUGINS by
+//!                               default)
+//!
+//!ARGS:
+//!    <SOURCE>    Only lists this source
+//! ```
+
+#[macro_use]
+extern crate clap;
+
+use clap::ArgMatches;
+use simple_error::{try_with, SimpleError};
+use std::env;
+use std::path::Path;
+
+use rusty_hogs::sources::{SourceInfo, SourceRegistry};
+use rusty_hogs::SecretScanner;
+
+/// Main entry function that uses the [clap crate](https://docs.rs/clap/2.33.0/clap/)
+fn main() {
+    let matches = clap_app!(list_sources =>
+        (version: "1.0.1")
+        (author: "Scott Cutler <scutler@newrelic.com>")
+        (about: "Lists the data sources Rusty Hog can scan.")
+        (@arg SOURCE: "Only lists this source")
+        (@arg PLUGINS: --plugins +takes_value "A directory of plugin descriptions (JSON files) to add to the list (RUSTY_HOG_PLUGINS by default)")
+        (@arg JSON: --json "Outputs the registry as JSON")
+        (@arg PRETTYPRINT: --prettyprint "Outputs the JSON in human readable format")
+        (@arg VERBOSE: -v --verbose ... "Sets the level of debugging information")
+    )
+    .get_matches();
+    match run(&matches) {
+        Ok(()) => {}
+        Err(e) => panic!("error: {}", e),
+    }
+}
+
+/// Main logic contained here. Build the registry, load any plugins, then print it.
+fn run(arg_matches: &ArgMatches) -> Result<(), SimpleError> {
+    // Set logging
+    SecretScanner::set_logging(arg_matches.occurrences_of("VERBOSE"));
+
+    // Build the registry
+    let mut registry = SourceRegistry::new();
+    let plugins = match arg_matches.value_of("PLUGINS") {
+        Some(p) => Some(p.to_string()),
+        None => env::var("RUSTY_HOG_PLUGINS").ok(),
+    };
+    if let Some(dir) = plugins {
+        registry.load_plugins(Path::new(&dir))?;
+    }
+    let sources: Vec<&SourceInfo> = match arg_matches.value_of("SOURCE") {
+        Some(name) => vec![registry
+            .get(name)
+            .ok_or_else(|| SimpleError::new(format!("Unknown source {}", name)))?],
+        None => registry.sources().iter().collect(),
+    };
+
+    // Output the list
+    if arg_matches.is_present("JSON") {
+        let json = if arg_matches.is_present("PRETTYPRINT") {
+            serde_json::to_string_pretty(&sources)
+        } else {
+            serde_json::to_string(&sources)
+        };
+        println!("{}", try_with!(json, "Failed to serialize the sources"));
+        return Ok(());
+    }
+    for source in sources {
+        println!("{} ({})", source.name, source.binary);
+        println!("    {}", source.description);
+        println!("    usage:   {} [OPTIONS] {}", source.binary, source.target);
+        if !source.auth.is_empty() {
+            println!("    auth:    {}", source.auth);
+        }
+        if !source.env.is_empty() {
+            println!("    env:     {}", source.env.join(", "));
+        }
+        if !source.options.is_empty() {
+            println!("    options: {}", source.options.join(", "));
+        }
+        if !source.builtin {
+            println!("    (plugin)");
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,136 @@
+//! Benchmarks the rules of a scanner on the files of a target, or on a generated corpus, reporting
+//! the throughput of the scan, its allocations and the time each rule's regex takes, slowest
+//! first. Use it to find the rule that makes large scans slow. See the `bench` module for what is
+//! measured.
+//!
+//! # Usage
+//! ```text
+//! rule_bench [FLAGS] [OPTIONS] [PATH]...
+//!
+//!FLAGS:
+//!        --caseinsensitive    Sets the case insensitive flag for all regexes
+//!        --entropy            Enables entropy scanning
+//!        --prettyprint        Outputs the JSON in human readable format
+//!    -v, --verbose            Sets the level of debugging information
+//!    -h, --help               Prints help information
+//!    -V, --version            Prints version information
+//!
+//!OPTIONS:
+//!        --corpus-size <BYTES>       The size of the generated corpus when no path is given (10000000 by default)
+//!        --disable-rule <RULE>...    Disables a rule for this run
+//!        --enable-rule <RULE>...     Enables a rule for this run, even if its rule pack disables it
+//!        --entropy-mode <MODE>       Sets how entropy findings are found (default, base64, hex, alphanumeric or normalized)
+//!    -o, --outputfile <OUTPUT>       Sets the path to write the report to (stdout by default)
+//!        --regex <REGEX>...          Sets the rule pack JSON file to benchmark (repeatable, "builtin" for the default rules)
+//!        --top <TOP>                 The number of slowest rules to report (all by default)
+//!
+//!ARGS:
+//!    <PATH>...    Files or directories to scan (a generated corpus by default)
+//! ```
+
+#[macro_use]
+extern crate clap;
+
+use clap::ArgMatches;
+use log::{self, info};
+use simple_error::{try_with, SimpleError};
+use std::fs;
+use std::path::Path;
+
+use rusty_hogs::bench::{self, CountingAllocator};
+use rusty_hogs::{SecretScanner, SecretScannerBuilder};
+
+const DEFAULT_CORPUS_SIZE: usize = 10_000_000;
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+/// Main entry function that uses the [clap crate](https://docs.rs/clap/2.33.0/clap/)
+fn main() {
+    let matches = clap_app!(rule_bench =>
+        (version: "1.0.1")
+        (author: "Scott Cutler <scutler@newrelic.com>")
+        (about: "Benchmarks the rules of a scanner.")
+        (@arg PATH: +multiple "Files or directories to scan (a generated corpus by default)")
+        (@arg REGEX: --regex +takes_value +multiple number_of_values(1) "Sets the rule pack JSON file to benchmark (repeatable, \"builtin\" for the default rules)")
+        (@arg ENABLERULE: --("enable-rule") [RULE] +multiple number_of_values(1) "Enables a rule for this run, even if its rule pack disables it")
+        (@arg DISABLERULE: --("disable-rule") [RULE] +multiple number_of_values(1) "Disables a rule for this run")
+        (@arg CASE: --caseinsensitive "Sets the case insensitive flag for all regexes")
+        (@arg ENTROPY: --entropy "Enables entropy scanning")
+        (@arg ENTROPYMODE: --("entropy-mode") [MODE] "Sets how entropy findings are found (default, base64, hex, alphanumeric or normalized)")
+        (@arg CORPUSSIZE: --("corpus-size") [BYTES] "The size of the generated corpus when no path is given (10000000 by default)")
+        (@arg TOP: --top +takes_value "The number of slowest rules to report (all by default)")
+        (@arg OUTPUT: -o --outputfile +takes_value "Sets the path to write the report to (stdout by default)")
+        (@arg PRETTYPRINT: --prettyprint "Outputs the JSON in human readable format")
+        (@arg VERBOSE: -v --verbose ... "Sets the level of debugging information")
+    )
+    .get_matches();
+    match run(&matches) {
+        Ok(()) => {}
+        Err(e) => panic!("error: {}", e),
+    }
+}
+
+/// Main logic contained here. Read or generate the corpus, benchmark the rules on it, then output
+/// the report.
+fn run(arg_matches: &ArgMatches) -> Result<(), SimpleError> {
+    // Set logging
+    SecretScanner::set_logging(arg_matches.occurrences_of("VERBOSE"));
+
+    // Initialize some variables
+    let secret_scanner = SecretScannerBuilder::new().conf_argm(arg_matches).build();
+    let scan_entropy = arg_matches.is_present("ENTROPY");
+    let top: Option<usize> = match arg_matches.value_of("TOP") {
+        Some(t) => Some(try_with!(t.parse(), "--top must be a number")),
+        None => None,
+    };
+
+    // Read the target, or generate the corpus
+    let data = match arg_matches.values_of("PATH") {
+        Some(paths) => {
+            let mut data: Vec<u8> = Vec::new();
+            for path in paths {
+                data.extend(bench::read_target(Path::new(path))?);
+            }
+            data
+        }
+        None => {
+            let size: usize = match arg_matches.value_of("CORPUSSIZE") {
+                Some(s) => try_with!(s.parse(), "--corpus-size must be a number"),
+                None => DEFAULT_CORPUS_SIZE,
+            };
+            bench::corpus(size)
+        }
+    };
+
+    // Benchmark the rules
+    let mut report = bench::run(&secret_scanner, &data, scan_entropy);
+    info!(
+        "Scanned {} bytes in {:.3}s ({:.1} MB/s), {} findings",
+        report.bytes, report.seconds, report.mb_per_second, report.findings
+    );
+    if let Some(slowest) = report.rules.first() {
+        info!(
+            "Slowest rule: {} ({:.3}s, {:.0}% of the rule time)",
+            slowest.rule,
+            slowest.seconds,
+            slowest.share * 100.0
+        );
+    }
+    if let Some(top) = top {
+        report.rules.truncate(top);
+    }
+
+    // Output the report
+    let json = if arg_matches.is_present("PRETTYPRINT") {
+        serde_json::to_string_pretty(&report)
+    } else {
+        serde_json::to_string(&report)
+    };
+    let json = try_with!(json, "Failed to serialize the report");
+    match arg_matches.value_of("OUTPUT") {
+        Some(path) => try_with!(fs::write(path, json), "Failed to write {}", path),
+        None => println!("{}", json),
+    }
+    Ok(())
+}
@@ -0,0 +1,100 @@
+//! Replays a rule pack against the finding contexts captured by a scanner's `--capture-contexts`
+//! option, reporting for each rule the lines it matches before and after without re-running the
+//! scan. See the `replay` module for what is captured and compared.
+//!
+//! # Usage
+//! ```text
+//! rule_replay [FLAGS] [OPTIONS] <CONTEXTS>...
+//!
+//!FLAGS:
+//!        --caseinsensitive    Sets the case insensitive flag for all regexes
+//!        --entropy            Also compares entropy findings
+//!        --prettyprint        Outputs the JSON in human readable format
+//!    -v, --verbose            Sets the level of debugging information
+//!    -h, --help               Prints help information
+//!    -V, --version            Prints version information
+//!
+//!OPTIONS:
+//!        --disable-rule <RULE>...    Disables a rule for this run
+//!        --enable-rule <RULE>...     Enables a rule for this run, even if its rule pack disables it
+//!        --examples <EXAMPLES>       The number of added and removed lines to list for each rule (5 by default)
+//!    -o, --outputfile <OUTPUT>       Sets the path to write the report to (stdout by default)
+//!        --regex <REGEX>...          Sets the rule pack JSON file to replay (repeatable, "builtin" for the default rules)
+//!
+//!ARGS:
+//!    <CONTEXTS>...    Context files written by --capture-contexts
+//! ```
+
+#[macro_use]
+extern crate clap;
+
+use clap::ArgMatches;
+use log::{self, info};
+use simple_error::{try_with, SimpleError};
+use std::fs;
+
+use rusty_hogs::replay::{read_contexts, MatchContext, ReplayReport};
+use rusty_hogs::{SecretScanner, SecretScannerBuilder};
+
+const DEFAULT_EXAMPLES: usize = 5;
+
+/// Main entry function that uses the [clap crate](https://docs.rs/clap/2.33.0/clap/)
+fn main() {
+    let matches = clap_app!(rule_replay =>
+        (version: "1.0.1")
+        (author: "Scott Cutler <scutler@newrelic.com>")
+        (about: "Replays a rule pack against captured finding contexts.")
+        (@arg CONTEXTS: +required +multiple "Context files written by --capture-contexts")
+        (@arg REGEX: --regex +takes_value +multiple number_of_values(1) "Sets the rule pack JSON file to replay (repeatable, \"builtin\" for the default rules)")
+        (@arg ENABLERULE: --("enable-rule") [RULE] +multiple number_of_values(1) "Enables a rule for this run, even if its rule pack disables it")
+        (@arg DISABLERULE: --("disable-rule") [RULE] +multiple number_of_values(1) "Disables a rule for this run")
+        (@arg CASE: --caseinsensitive "Sets the case insensitive flag for all regexes")
+        (@arg ENTROPY: --entropy "Also compares entropy findings")
+        (@arg EXAMPLES: --examples +takes_value "The number of added and removed lines to list for each rule (5 by default)")
+        (@arg OUTPUT: -o --outputfile +takes_value "Sets the path to write the report to (stdout by default)")
+        (@arg PRETTYPRINT: --prettyprint "Outputs the JSON in human readable format")
+        (@arg VERBOSE: -v --verbose ... "Sets the level of debugging information")
+    )
+    .get_matches();
+    match run(&matches) {
+        Ok(()) => {}
+        Err(e) => panic!("error: {}", e),
+    }
+}
+
+/// Main logic contained here. Read the contexts, replay the rules against them, then output the
+/// report.
+fn run(arg_matches: &ArgMatches) -> Result<(), SimpleError> {
+    // Set logging
+    SecretScanner::set_logging(arg_matches.occurrences_of("VERBOSE"));
+
+    // Initialize some variables
+    let secret_scanner = SecretScannerBuilder::new().conf_argm(arg_matches).build();
+    let scan_entropy = arg_matches.is_present("ENTROPY");
+    let examples: usize = match arg_matches.value_of("EXAMPLES") {
+        Some(e) => try_with!(e.parse(), "--examples must be a number"),
+        None => DEFAULT_EXAMPLES,
+    };
+
+    // Read the contexts and replay the rules
+    let mut contexts: Vec<MatchContext> = Vec::new();
+    for path in arg_matches.values_of("CONTEXTS").unwrap() {
+        let c = read_contexts(path)?;
+        info!("{}: {} contexts", path, c.len());
+        contexts.extend(c);
+    }
+    let report = ReplayReport::compute(&contexts, &secret_scanner, scan_entropy, examples);
+
+    // Output the report
+    let json = if arg_matches.is_present("PRETTYPRINT") {
+        serde_json::to_string_pretty(&report)
+    } else {
+        serde_json::to_string(&report)
+    };
+    let json = try_with!(json, "Failed to serialize the report");
+    match arg_matches.value_of("OUTPUT") {
+        Some(path) => try_with!(fs::write(path, json), "Failed to write {}", path),
+        None => println!("{}", json),
+    }
+    Ok(())
+}
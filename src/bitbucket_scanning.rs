@@ -0,0 +1,581 @@
+//! Collection of tools for scanning Bitbucket Cloud and Bitbucket Server / Data Center for
+//! secrets.
+//!
+//! `BitbucketScanner` acts as a wrapper around a `SecretScanner` object and talks to either the
+//! [Bitbucket Cloud REST API](https://developer.atlassian.com/cloud/bitbucket/rest/) (2.0) or the
+//! [Bitbucket Server REST API](https://docs.atlassian.com/bitbucket-server/rest/latest/) (1.0).
+//! It can enumerate every repository in a Cloud workspace or a Server project and, for each
+//! repository, scan:
+//!
+//! * the full history of the repository, cloned over HTTPS and scanned with `GitScanner`
+//! * the description and comments of every pull request, in any state
+//! * the repository's pipeline variables (Cloud only, Bitbucket Server has no pipelines)
+//!
+//! Secured pipeline variables can't be read through the API, so only unsecured ones are scanned.
+//! Unsecured variables whose name looks like a credential (`*_PASSWORD`, `*_TOKEN`, ...) are
+//! always reported with the reason "Unsecured pipeline variable".
+//!
+//! Authenticate with a username and an app password (Cloud) or password / HTTP access token
+//! (Server), or with an OAuth access token.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use rusty_hogs::bitbucket_scanning::{BitbucketAuth, BitbucketConfig, BitbucketFlavor, BitbucketScanner};
+//!
+//! let bs = BitbucketScanner::new();
+//! let config = BitbucketConfig {
+//!     url: "https://api.bitbucket.org".to_string(),
+//!     flavor: BitbucketFlavor::Cloud,
+//!     auth: BitbucketAuth::Basic {
+//!         username: "me".to_string(),
+//!         password: "app-password".to_string(),
+//!     },
+//! };
+//! for repo in bs.list_repositories(&config, "my-workspace").unwrap() {
+//!     let findings = bs.scan_repository(&config, &repo, true, false).unwrap();
+//!     bs.secret_scanner.output_findings(&findings);
+//! }
+//! ```
+
+use crate::git_scanning::GitScanner;
+use crate::network;
+use crate::retry;
+use crate::structured_scanning;
+use crate::{Finding, SecretScanner, Span, TextFinding};
+use log::{self, debug, info};
+use reqwest::blocking::RequestBuilder;
+use serde_derive::{Deserialize, Serialize};
+use serde_json::Value;
+use simple_error::{try_with, SimpleError};
+use std::collections::HashSet;
+use std::fmt;
+use std::str::FromStr;
+
+const PAGE_SIZE: &str = "100";
+
+/// The username Bitbucket Cloud expects when an OAuth access token is used as a git password
+const TOKEN_GIT_USER: &str = "x-token-auth";
+
+/// Reason reported for unsecured pipeline variables with credential-like names
+const UNSECURED_VARIABLE_REASON: &str = "Unsecured pipeline variable";
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Hash, Clone, Default)]
+/// `serde_json` object that represents a single found secret - finding
+pub struct BitbucketFinding {
+    pub diff: String,
+    #[serde(rename = "stringsFound")]
+    pub strings_found: Vec<String>,
+    /// The full name of the repository, e.g. `my-workspace/my-repo` or `PROJ/my-repo`
+    pub repository: String,
+    /// One of `repository`, `pull_request` or `pipeline_variable`
+    pub source: String,
+    /// The file path for repository findings, `#<id>` for pull requests, or the variable name
+    pub path: String,
+    /// A link to the file, pull request or comment
+    pub url: String,
+    #[serde(
+        rename = "commitHash",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub commit_hash: Option<String>,
+    /// The email address of the commit author, for repository findings
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub author: Option<String>,
+    pub date: String,
+    pub reason: String,
+    /// Where each of `strings_found` is in the text, see `Span`
+    #[serde(default)]
+    pub spans: Vec<Span>,
+}
+
+impl TextFinding for BitbucketFinding {
+    fn with_match(&self, found: Finding) -> Self {
+        Self {
+            diff: found.diff,
+            strings_found: found.strings_found,
+            reason: found.reason,
+            spans: found.spans,
+            ..self.clone()
+        }
+    }
+}
+
+/// Which of the two (quite different) Bitbucket APIs to use
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub enum BitbucketFlavor {
+    /// bitbucket.org, API 2.0
+    Cloud,
+    /// Bitbucket Server and Data Center, API 1.0
+    Server,
+}
+
+impl FromStr for BitbucketFlavor {
+    type Err = SimpleError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_ref() {
+            "cloud" => Ok(BitbucketFlavor::Cloud),
+            "server" | "datacenter" => Ok(BitbucketFlavor::Server),
+            other => Err(SimpleError::new(format!(
+                "Unknown Bitbucket flavor {:?}",
+                other
+            ))),
+        }
+    }
+}
+
+impl fmt::Display for BitbucketFlavor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BitbucketFlavor::Cloud => write!(f, "cloud"),
+            BitbucketFlavor::Server => write!(f, "server"),
+        }
+    }
+}
+
+/// Credentials used for both the API and cloning
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+pub enum BitbucketAuth {
+    /// A username and an app password (Cloud) or a password / HTTP access token (Server)
+    Basic { username: String, password: String },
+    /// An OAuth access token, or a Server HTTP access token, sent as a bearer token
+    Bearer(String),
+}
+
+/// The Bitbucket instance to talk to and the credentials to authenticate with
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+pub struct BitbucketConfig {
+    /// The base URL of the API, `https://api.bitbucket.org` for Cloud or the base URL of the
+    /// instance (e.g. `https://bitbucket.example.com`) for Server
+    pub url: String,
+    pub flavor: BitbucketFlavor,
+    pub auth: BitbucketAuth,
+}
+
+/// A repository to scan, normalized from either API
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Hash, Clone)]
+pub struct BitbucketRepository {
+    /// `workspace/slug` for Cloud, `PROJECT/slug` for Server
+    pub full_name: String,
+    pub web_url: String,
+    pub clone_url: Option<String>,
+    /// The API path of the repository, relative to the API root
+    pub api_path: String,
+}
+
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+/// Contains helper functions for performing scans of Bitbucket repositories
+pub struct BitbucketScanner {
+    pub secret_scanner: SecretScanner,
+}
+
+/// Acts as a wrapper around a `SecretScanner` object to provide helper functions for performing
+/// scanning against Bitbucket. Relies on the [reqwest](https://docs.rs/reqwest/) library for
+/// HTTP access.
+impl BitbucketScanner {
+    /// Initialize the SecretScanner object first using the SecretScannerBuilder, then provide
+    /// it to this constructor method.
+    pub fn new_from_scanner(secret_scanner: SecretScanner) -> Self {
+        Self { secret_scanner }
+    }
+
+    pub fn new() -> Self {
+        Self {
+            secret_scanner: SecretScanner::default(),
+        }
+    }
+
+    /// Returns every repository in a Cloud workspace, or a Server project (by project key)
+    pub fn list_repositories(
+        &self,
+        config: &BitbucketConfig,
+        workspace: &str,
+    ) -> Result<Vec<BitbucketRepository>, SimpleError> {
+        let repositories: Vec<BitbucketRepository> = match config.flavor {
+            BitbucketFlavor::Cloud => get_values(config, &format!("repositories/{}", workspace))?
+                .iter()
+                .map(|r| {
+                    let full_name = r["full_name"].as_str().unwrap_or("").to_string();
+                    BitbucketRepository {
+                        web_url: r["links"]["html"]["href"]
+                            .as_str()
+                            .unwrap_or("")
+                            .to_string(),
+                        clone_url: clone_link(&r["links"]["clone"], "https"),
+                        api_path: format!("repositories/{}", full_name),
+                        full_name,
+                    }
+                })
+                .collect(),
+            BitbucketFlavor::Server => {
+                get_values(config, &format!("projects/{}/repos", workspace))?
+                    .iter()
+                    .map(|r| {
+                        let slug = r["slug"].as_str().unwrap_or("");
+                        BitbucketRepository {
+                            full_name: format!("{}/{}", workspace, slug),
+                            web_url: r["links"]["self"][0]["href"]
+                                .as_str()
+                                .unwrap_or("")
+                                .to_string(),
+                            clone_url: clone_link(&r["links"]["clone"], "http"),
+                            api_path: format!("projects/{}/repos/{}", workspace, slug),
+                        }
+                    })
+                    .collect()
+            }
+        };
+        info!("Found {} repositories in {}", repositories.len(), workspace);
+        Ok(repositories)
+    }
+
+    /// Scans the pull requests and pipeline variables of `repo`, and its history unless
+    /// `scan_history` is false, returning a HashSet of findings.
+    pub fn scan_repository(
+        &self,
+        config: &BitbucketConfig,
+        repo: &BitbucketRepository,
+        scan_history: bool,
+        scan_entropy: bool,
+    ) -> Result<HashSet<BitbucketFinding>, SimpleError> {
+        info!("Scanning repository {}", repo.full_name);
+        let mut findings: HashSet<BitbucketFinding> = HashSet::new();
+        if scan_history {
+            findings.extend(self.scan_history(config, repo, scan_entropy)?);
+        }
+        findings.extend(self.scan_pull_requests(config, repo, scan_entropy)?);
+        if config.flavor == BitbucketFlavor::Cloud {
+            findings.extend(self.scan_pipeline_variables(config, repo, scan_entropy)?);
+        }
+        Ok(findings)
+    }
+
+    /// Clones the repository into a temporary directory and scans every commit with `GitScanner`
+    fn scan_history(
+        &self,
+        config: &BitbucketConfig,
+        repo: &BitbucketRepository,
+        scan_entropy: bool,
+    ) -> Result<HashSet<BitbucketFinding>, SimpleError> {
+        let clone_url = match &repo.clone_url {
+            Some(u) => u,
+            None => {
+                debug!(
+                    "No HTTPS clone link for {}, skipping history",
+                    repo.full_name
+                );
+                return Ok(HashSet::new());
+            }
+        };
+        let (username, password) = match &config.auth {
+            BitbucketAuth::Basic { username, password } => (username.as_str(), password.as_str()),
+            BitbucketAuth::Bearer(token) => (TOKEN_GIT_USER, token.as_str()),
+        };
+        let dest_dir = self.secret_scanner.workspace()?;
+        let git_scanner = GitScanner::new_from_scanner(self.secret_scanner.clone()).init_git_repo(
+            clone_url,
+            dest_dir.path(),
+            None,
+            None,
+            Some(username),
+            Some(password),
+        );
+        dest_dir.check_quota()?;
+        let git_findings = git_scanner.perform_scan(None, None, None, scan_entropy)?;
+        Ok(git_findings
+            .into_iter()
+            .map(|f| BitbucketFinding {
+                // Server's web link already ends in /browse
+                url: match config.flavor {
+                    BitbucketFlavor::Cloud => {
+                        format!("{}/src/{}/{}", repo.web_url, f.commit_hash, f.path)
+                    }
+                    BitbucketFlavor::Server => {
+                        format!("{}/{}?at={}", repo.web_url, f.path, f.commit_hash)
+                    }
+                },
+                diff: f.diff,
+                strings_found: f.strings_found,
+                repository: repo.full_name.clone(),
+                source: String::from("repository"),
+                path: f.path,
+                commit_hash: Some(f.commit_hash),
+                author: Some(f.author),
+                date: f.date,
+                reason: f.reason,
+                spans: f.spans,
+            })
+            .collect())
+    }
+
+    /// Scans the description and comments of every pull request
+    fn scan_pull_requests(
+        &self,
+        config: &BitbucketConfig,
+        repo: &BitbucketRepository,
+        scan_entropy: bool,
+    ) -> Result<HashSet<BitbucketFinding>, SimpleError> {
+        let mut findings: HashSet<BitbucketFinding> = HashSet::new();
+        let list_path = match config.flavor {
+            BitbucketFlavor::Cloud => format!(
+                "{}/pullrequests?state=OPEN&state=MERGED&state=DECLINED&state=SUPERSEDED",
+                repo.api_path
+            ),
+            BitbucketFlavor::Server => format!("{}/pull-requests?state=ALL", repo.api_path),
+        };
+        let pull_requests = get_values(config, &list_path)?;
+        debug!(
+            "Scanning {} pull requests in {}",
+            pull_requests.len(),
+            repo.full_name
+        );
+        for pr in pull_requests {
+            let id = match pr["id"].as_u64() {
+                Some(id) => id,
+                None => continue,
+            };
+            let pr_url = match config.flavor {
+                BitbucketFlavor::Cloud => pr["links"]["html"]["href"].as_str(),
+                BitbucketFlavor::Server => pr["links"]["self"][0]["href"].as_str(),
+            }
+            .unwrap_or("")
+            .to_string();
+            let template = BitbucketFinding {
+                repository: repo.full_name.clone(),
+                source: String::from("pull_request"),
+                path: format!("#{}", id),
+                url: pr_url.clone(),
+                ..Default::default()
+            };
+            if let Some(description) = pr["description"].as_str() {
+                let template = BitbucketFinding {
+                    date: date_string(&pr["updated_on"], &pr["updatedDate"]),
+                    ..template.clone()
+                };
+                self.secret_scanner.scan_text(
+                    description.as_bytes(),
+                    &template,
+                    scan_entropy,
+                    &mut findings,
+                );
+            }
+            for (comment_id, text, date) in self.pr_comments(config, repo, id)? {
+                let template = BitbucketFinding {
+                    url: match config.flavor {
+                        BitbucketFlavor::Cloud => format!("{}#comment-{}", pr_url, comment_id),
+                        BitbucketFlavor::Server => {
+                            format!("{}/overview?commentId={}", pr_url, comment_id)
+                        }
+                    },
+                    date,
+                    ..template.clone()
+                };
+                self.secret_scanner.scan_text(
+                    text.as_bytes(),
+                    &template,
+                    scan_entropy,
+                    &mut findings,
+                );
+            }
+        }
+        Ok(findings)
+    }
+
+    /// Returns the ID, text and date of every comment on a pull request. On Server, comments
+    /// (and their replies) are found in the pull request's activity stream.
+    fn pr_comments(
+        &self,
+        config: &BitbucketConfig,
+        repo: &BitbucketRepository,
+        id: u64,
+    ) -> Result<Vec<(u64, String, String)>, SimpleError> {
+        let mut comments: Vec<(u64, String, String)> = Vec::new();
+        match config.flavor {
+            BitbucketFlavor::Cloud => {
+                let path = format!("{}/pullrequests/{}/comments", repo.api_path, id);
+                for c in get_values(config, &path)? {
+                    if let (Some(comment_id), Some(text)) =
+                        (c["id"].as_u64(), c["content"]["raw"].as_str())
+                    {
+                        let date = date_string(&c["updated_on"], &Value::Null);
+                        comments.push((comment_id, text.to_string(), date));
+                    }
+                }
+            }
+            BitbucketFlavor::Server => {
+                let path = format!("{}/pull-requests/{}/activities", repo.api_path, id);
+                for activity in get_values(config, &path)? {
+                    if activity["action"].as_str() == Some("COMMENTED") {
+                        collect_server_comments(&activity["comment"], &mut comments);
+                    }
+                }
+            }
+        }
+        Ok(comments)
+    }
+
+    /// Scans the unsecured repository pipeline variables, and reports any whose name suggests
+    /// they should have been secured
+    fn scan_pipeline_variables(
+        &self,
+        config: &BitbucketConfig,
+        repo: &BitbucketRepository,
+        scan_entropy: bool,
+    ) -> Result<HashSet<BitbucketFinding>, SimpleError> {
+        let mut findings: HashSet<BitbucketFinding> = HashSet::new();
+        let path = format!("{}/pipelines_config/variables/", repo.api_path);
+        // repositories without pipelines enabled return an error here, which isn't interesting
+        let variables = match get_values(config, &path) {
+            Ok(v) => v,
+            Err(e) => {
+                debug!("Skipping pipeline variables of {}: {}", repo.full_name, e);
+                return Ok(findings);
+            }
+        };
+        for variable in variables {
+            if variable["secured"].as_bool().unwrap_or(false) {
+                continue;
+            }
+            let (key, value) = match (variable["key"].as_str(), variable["value"].as_str()) {
+                (Some(k), Some(v)) => (k, v),
+                _ => continue,
+            };
+            let line = format!("{}={}", key, value);
+            let template = BitbucketFinding {
+                repository: repo.full_name.clone(),
+                source: String::from("pipeline_variable"),
+                path: key.to_string(),
+                url: format!("{}/admin/pipelines/repository-variables", repo.web_url),
+                ..Default::default()
+            };
+            self.secret_scanner
+                .scan_text(line.as_bytes(), &template, scan_entropy, &mut findings);
+            if structured_scanning::is_sensitive_key(key)
+                && structured_scanning::is_plausible_secret(value)
+            {
+                findings.insert(BitbucketFinding {
+                    diff: line.clone(),
+                    strings_found: vec![value.to_string()],
+                    reason: UNSECURED_VARIABLE_REASON.to_string(),
+                    ..template
+                });
+            }
+        }
+        Ok(findings)
+    }
+}
+
+impl Default for BitbucketScanner {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Adds a Server comment and, recursively, its replies
+fn collect_server_comments(comment: &Value, comments: &mut Vec<(u64, String, String)>) {
+    if let (Some(id), Some(text)) = (comment["id"].as_u64(), comment["text"].as_str()) {
+        let date = date_string(&Value::Null, &comment["updatedDate"]);
+        comments.push((id, text.to_string(), date));
+    }
+    if let Some(replies) = comment["comments"].as_array() {
+        for reply in replies {
+            collect_server_comments(reply, comments);
+        }
+    }
+}
+
+/// Returns the href of the clone link with the given name (`https` on Cloud, `http` on Server)
+fn clone_link(links: &Value, name: &str) -> Option<String> {
+    links
+        .as_array()?
+        .iter()
+        .find(|l| l["name"].as_str() == Some(name))
+        .and_then(|l| l["href"].as_str())
+        .map(String::from)
+}
+
+/// Cloud dates are ISO 8601 strings, Server dates are milliseconds since the epoch
+fn date_string(cloud_date: &Value, server_date: &Value) -> String {
+    if let Some(d) = cloud_date.as_str() {
+        return d.to_string();
+    }
+    match server_date.as_i64() {
+        Some(ms) => chrono::NaiveDateTime::from_timestamp(ms / 1000, 0).to_string(),
+        None => String::new(),
+    }
+}
+
+fn request(config: &BitbucketConfig, url: &str) -> RequestBuilder {
+    let builder = network::client().get(url);
+    match &config.auth {
+        BitbucketAuth::Basic { username, password } => builder.basic_auth(username, Some(password)),
+        BitbucketAuth::Bearer(token) => builder.bearer_auth(token),
+    }
+}
+
+/// Performs an authenticated GET and parses the JSON response
+fn get_json(config: &BitbucketConfig, url: &str) -> Result<Value, SimpleError> {
+    debug!("GET {}", url);
+    let response = try_with!(
+        retry::send(request(config, url)),
+        "Bitbucket request failed"
+    );
+    if !response.status().is_success() {
+        return Err(SimpleError::new(format!(
+            "Bitbucket returned {} for {}",
+            response.status(),
+            url
+        )));
+    }
+    Ok(try_with!(
+        response.json(),
+        "Failed to parse Bitbucket response"
+    ))
+}
+
+/// Returns the `values` of every page of a list endpoint. `path` is relative to `/2.0/` on Cloud
+/// and `/rest/api/1.0/` on Server. Cloud pages link to the next page with `next`, Server pages
+/// report `isLastPage` and `nextPageStart`.
+fn get_values(config: &BitbucketConfig, path: &str) -> Result<Vec<Value>, SimpleError> {
+    let base = config.url.trim_end_matches('/');
+    let separator = if path.contains('?') { '&' } else { '?' };
+    let mut values: Vec<Value> = Vec::new();
+    match config.flavor {
+        BitbucketFlavor::Cloud => {
+            let mut url = format!("{}/2.0/{}{}pagelen={}", base, path, separator, PAGE_SIZE);
+            loop {
+                let page = get_json(config, &url)?;
+                if let Some(v) = page["values"].as_array() {
+                    values.extend(v.iter().cloned());
+                }
+                match page["next"].as_str() {
+                    Some(next) => url = next.to_string(),
+                    None => break,
+                }
+            }
+        }
+        BitbucketFlavor::Server => {
+            let mut start: u64 = 0;
+            loop {
+                let url = format!(
+                    "{}/rest/api/1.0/{}{}limit={}&start={}",
+                    base, path, separator, PAGE_SIZE, start
+                );
+                let page = get_json(config, &url)?;
+                if let Some(v) = page["values"].as_array() {
+                    values.extend(v.iter().cloned());
+                }
+                if page["isLastPage"].as_bool().unwrap_or(true) {
+                    break;
+                }
+                start = match page["nextPageStart"].as_u64() {
+                    Some(s) => s,
+                    None => break,
+                };
+            }
+        }
+    }
+    Ok(values)
+}
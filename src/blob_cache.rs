@@ -0,0 +1,230 @@
+//! Scanning each line of a git blob once.
+//!
+//! Choctaw Hog scans the lines each commit changes, so the same file content is scanned again
+//! and again: a line is scanned when a commit adds it and again when a later commit removes it,
+//! and every line of a rebased, cherry-picked, reverted or stashed change is scanned once for each
+//! commit that makes it. A line is identified by the object ID of the blob it belongs to and its
+//! line number, so a `BlobCache` keeps the findings of each line scanned and hands them out the
+//! next time the line turns up, in whichever commit and path.
+//!
+//! What a line contains depends on the rules and entropy scanning, so the cache only applies to
+//! one set of them, identified by `SecretScanner::rules_hash`. With `--blob-cache <FILE>` the cache
+//! is kept in a file, so repeated scans of the same repository (nightly full scans, say) skip the
+//! lines earlier scans have seen, until the rules change.
+//!
+//! # Examples
+//!
+//! ```
+//! use git2::Oid;
+//! use rusty_hogs::blob_cache::{BlobCache, CachedFinding};
+//! use tempdir::TempDir;
+//!
+//! let dir = TempDir::new("blob_cache").unwrap();
+//! let path = dir.path().join("blobs.json");
+//! let path = path.to_str().unwrap();
+//! let blob = Oid::from_str("8ab686eafeb1f44702738c8b0f24f2567c36da6d").unwrap();
+//!
+//! let mut cache = BlobCache::open(path).unwrap();
+//! cache.use_rules(42, false);
+//! assert_eq!(cache.get(blob, 7), None);
+//! let found = vec![CachedFinding { reason: String::from("Slack Token"), ..Default::default() }];
+//! cache.insert(blob, 7, found.clone());
+//! cache.insert(blob, 8, Vec::new());
+//! assert_eq!(cache.get(blob, 7), Some(found));
+//! assert_eq!(cache.get(blob, 8), Some(Vec::new()));
+//! assert_eq!(cache.stats(), (2, 2));
+//! cache.save().unwrap();
+//!
+//! // the next run doesn't scan line 7 again, unless the rules changed
+//! let mut cache = BlobCache::open(path).unwrap();
+//! cache.use_rules(42, false);
+//! assert!(cache.contains(blob, 7));
+//! assert!(!cache.contains(blob, 9));
+//! cache.use_rules(43, false);
+//! assert!(!cache.contains(blob, 7));
+//! ```
+
+use crate::Span;
+use git2::Oid;
+use log::{self, debug, info, warn};
+use serde::{Deserialize, Serialize};
+use simple_error::{try_with, SimpleError};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::Path;
+
+/// A finding on a line of a blob, without the fields that come from the commit and path it was
+/// found at
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Hash, Clone, Default)]
+pub struct CachedFinding {
+    pub diff: String,
+    #[serde(rename = "stringsFound")]
+    pub strings_found: Vec<String>,
+    pub reason: String,
+    #[serde(default)]
+    pub spans: Vec<Span>,
+}
+
+/// The lines of one blob that have been scanned
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+struct BlobLines {
+    /// Runs of consecutive lines, the first line of each mapped to its last
+    scanned: BTreeMap<u32, u32>,
+    /// The findings of the scanned lines that have any
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    found: BTreeMap<u32, Vec<CachedFinding>>,
+}
+
+impl BlobLines {
+    fn contains(&self, line: u32) -> bool {
+        match self.scanned.range(..=line).next_back() {
+            Some((_, &last)) => line <= last,
+            None => false,
+        }
+    }
+
+    /// Adds `line` to the runs of scanned lines, joining it to the runs before and after it
+    fn insert(&mut self, line: u32) {
+        if self.contains(line) {
+            return;
+        }
+        let first = match self.scanned.range(..line).next_back() {
+            Some((&first, &last)) if last + 1 == line => first,
+            _ => line,
+        };
+        let last = line
+            .checked_add(1)
+            .and_then(|next| self.scanned.remove(&next))
+            .unwrap_or(line);
+        self.scanned.insert(first, last);
+    }
+}
+
+/// The contents of a blob cache file, where `B` holds the lines of each blob
+#[derive(Serialize, Deserialize)]
+struct State<B> {
+    #[serde(rename = "rulesHash")]
+    rules_hash: u64,
+    entropy: bool,
+    blobs: BTreeMap<String, B>,
+}
+
+/// The findings of the lines of blobs that have been scanned (see the module documentation).
+/// `BlobCache::default()` is only kept in memory.
+#[derive(Debug, Default)]
+pub struct BlobCache {
+    path: Option<String>,
+    rules_hash: u64,
+    entropy: bool,
+    blobs: HashMap<Oid, BlobLines>,
+    hits: usize,
+    misses: usize,
+}
+
+impl BlobCache {
+    /// Reads the blob cache file at `path`, or starts an empty cache that will be saved there if
+    /// it doesn't exist
+    pub fn open(path: &str) -> Result<Self, SimpleError> {
+        let mut cache = Self {
+            path: Some(path.to_string()),
+            ..Default::default()
+        };
+        if !Path::new(path).exists() {
+            return Ok(cache);
+        }
+        let data = try_with!(fs::read(path), "Failed to read blob cache {}", path);
+        let state: State<BlobLines> = try_with!(
+            serde_json::from_slice(&data),
+            "Failed to parse blob cache {}",
+            path
+        );
+        cache.rules_hash = state.rules_hash;
+        cache.entropy = state.entropy;
+        for (id, lines) in state.blobs {
+            match Oid::from_str(&id) {
+                Ok(oid) => {
+                    cache.blobs.insert(oid, lines);
+                }
+                Err(_) => warn!("Skipping the invalid blob ID {:?} in {}", id, path),
+            }
+        }
+        info!(
+            "Read {} blobs from the blob cache {}",
+            cache.blobs.len(),
+            path
+        );
+        Ok(cache)
+    }
+
+    /// Empties the cache if it was filled with other rules than those with `rules_hash` (see
+    /// `SecretScanner::rules_hash`), or with or without entropy scanning, and keeps it for them
+    pub fn use_rules(&mut self, rules_hash: u64, entropy: bool) {
+        if (self.rules_hash, self.entropy) != (rules_hash, entropy) {
+            if !self.blobs.is_empty() {
+                info!("The rules have changed, emptying the blob cache");
+                self.blobs.clear();
+            }
+            self.rules_hash = rules_hash;
+            self.entropy = entropy;
+        }
+    }
+
+    /// True if line `line` of `blob` has been scanned
+    pub fn contains(&self, blob: Oid, line: u32) -> bool {
+        self.blobs.get(&blob).map_or(false, |b| b.contains(line))
+    }
+
+    /// The findings of line `line` of `blob`, None if it hasn't been scanned
+    pub fn get(&mut self, blob: Oid, line: u32) -> Option<Vec<CachedFinding>> {
+        let lines = self.blobs.get(&blob).filter(|b| b.contains(line))?;
+        self.hits += 1;
+        Some(lines.found.get(&line).cloned().unwrap_or_default())
+    }
+
+    /// Adds the findings of line `line` of `blob`, which has just been scanned
+    pub fn insert(&mut self, blob: Oid, line: u32, found: Vec<CachedFinding>) {
+        self.misses += 1;
+        let lines = self.blobs.entry(blob).or_default();
+        lines.insert(line);
+        if !found.is_empty() {
+            lines.found.insert(line, found);
+        }
+    }
+
+    /// The number of lines whose findings came from the cache, and the number added to it
+    pub fn stats(&self) -> (usize, usize) {
+        (self.hits, self.misses)
+    }
+
+    /// Writes the cache file, replacing it only once the new one is complete. Does nothing for a
+    /// cache that isn't kept in a file.
+    pub fn save(&self) -> Result<(), SimpleError> {
+        let path = match &self.path {
+            Some(p) => p,
+            None => return Ok(()),
+        };
+        let state = State {
+            rules_hash: self.rules_hash,
+            entropy: self.entropy,
+            blobs: self
+                .blobs
+                .iter()
+                .map(|(id, lines)| (id.to_string(), lines))
+                .collect(),
+        };
+        let data = try_with!(serde_json::to_vec(&state), "Failed to serialize blob cache");
+        let temp_path = format!("{}.tmp", path);
+        try_with!(
+            fs::write(&temp_path, data),
+            "Failed to write blob cache {}",
+            temp_path
+        );
+        try_with!(
+            fs::rename(&temp_path, path),
+            "Failed to replace blob cache {}",
+            path
+        );
+        debug!("Saved {} blobs to {}", self.blobs.len(), path);
+        Ok(())
+    }
+}
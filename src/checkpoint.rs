@@ -0,0 +1,151 @@
+//! Resuming long scans after an interruption.
+//!
+//! With `--checkpoint <FILE>`, a scan records each object it finishes (an S3 object version, a
+//! Google Drive file, a Git commit) together with the findings it had, and writes them to the file
+//! every `SAVE_INTERVAL` and when the scan stops. Running the same command again after a crash,
+//! a timeout or SIGINT skips the objects already in the file, adds their findings to the report
+//! and scans the rest. The file is removed once a scan completes, so the next run starts over.
+//!
+//! Objects are identified by a string chosen by the scanner, which includes a version or
+//! modification time where the source has one, so that objects changed since the checkpoint are
+//! scanned again.
+//!
+//! # Examples
+//!
+//! ```
+//! use rusty_hogs::checkpoint::Checkpoint;
+//! use tempdir::TempDir;
+//!
+//! let dir = TempDir::new("checkpoint").unwrap();
+//! let path = dir.path().join("state.json");
+//! let path = path.to_str().unwrap();
+//!
+//! let mut checkpoint: Checkpoint<String> = Checkpoint::open(path).unwrap();
+//! checkpoint.record("logs/a.txt", vec![String::from("AKIA...")]).unwrap();
+//! checkpoint.save().unwrap();
+//!
+//! // the next run skips logs/a.txt but still reports its findings
+//! let checkpoint: Checkpoint<String> = Checkpoint::open(path).unwrap();
+//! assert_eq!(checkpoint.len(), 1);
+//! assert_eq!(checkpoint.scanned("logs/a.txt"), Some(&[String::from("AKIA...")][..]));
+//! assert_eq!(checkpoint.scanned("logs/b.txt"), None);
+//! checkpoint.finish().unwrap();
+//! assert!(!dir.path().join("state.json").exists());
+//! ```
+
+use crate::shutdown;
+use log::{self, debug, info};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use simple_error::{try_with, SimpleError};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+/// How often `record` writes the checkpoint file
+pub const SAVE_INTERVAL: Duration = Duration::from_secs(30);
+
+/// The contents of a checkpoint file, where `M` maps the ID of each object scanned to its findings
+#[derive(Serialize, Deserialize)]
+struct State<M> {
+    scanned: M,
+}
+
+/// The objects a scan has finished, saved to a file (see the module documentation)
+pub struct Checkpoint<T> {
+    path: String,
+    scanned: BTreeMap<String, Vec<T>>,
+    saved_at: Instant,
+}
+
+impl<T: Serialize + DeserializeOwned> Checkpoint<T> {
+    /// Reads the checkpoint file at `path`, or starts an empty checkpoint if it doesn't exist
+    pub fn open(path: &str) -> Result<Self, SimpleError> {
+        let scanned = if Path::new(path).exists() {
+            let data = try_with!(fs::read(path), "Failed to read checkpoint {}", path);
+            let state: State<BTreeMap<String, Vec<T>>> = try_with!(
+                serde_json::from_slice(&data),
+                "Failed to parse checkpoint {}",
+                path
+            );
+            info!(
+                "Resuming from {}, skipping {} objects",
+                path,
+                state.scanned.len()
+            );
+            state.scanned
+        } else {
+            BTreeMap::new()
+        };
+        Ok(Self {
+            path: path.to_string(),
+            scanned,
+            saved_at: Instant::now(),
+        })
+    }
+
+    /// The findings of the object `id`, if an earlier run scanned it
+    pub fn scanned(&self, id: &str) -> Option<&[T]> {
+        self.scanned.get(id).map(Vec::as_slice)
+    }
+
+    /// The number of objects scanned
+    pub fn len(&self) -> usize {
+        self.scanned.len()
+    }
+
+    /// True if no objects have been scanned
+    pub fn is_empty(&self) -> bool {
+        self.scanned.is_empty()
+    }
+
+    /// Records that the object `id` has been scanned with `findings`, saving the checkpoint if it
+    /// hasn't been saved for `SAVE_INTERVAL`
+    pub fn record(&mut self, id: &str, findings: Vec<T>) -> Result<(), SimpleError> {
+        self.scanned.insert(id.to_string(), findings);
+        if self.saved_at.elapsed() >= SAVE_INTERVAL {
+            self.save()?;
+        }
+        Ok(())
+    }
+
+    /// Writes the checkpoint file, replacing it only once the new one is complete
+    pub fn save(&mut self) -> Result<(), SimpleError> {
+        let state = State {
+            scanned: &self.scanned,
+        };
+        let data = try_with!(serde_json::to_vec(&state), "Failed to serialize checkpoint");
+        let temp_path = format!("{}.tmp", self.path);
+        try_with!(
+            fs::write(&temp_path, data),
+            "Failed to write checkpoint {}",
+            temp_path
+        );
+        try_with!(
+            fs::rename(&temp_path, &self.path),
+            "Failed to replace checkpoint {}",
+            self.path
+        );
+        debug!("Saved {} objects to {}", self.scanned.len(), self.path);
+        self.saved_at = Instant::now();
+        Ok(())
+    }
+
+    /// Ends the scan: removes the checkpoint file if the scan completed, or saves it for the next
+    /// run if it was interrupted
+    pub fn finish(mut self) -> Result<(), SimpleError> {
+        if shutdown::interrupted() {
+            info!("Saving the interrupted scan to {}", self.path);
+            return self.save();
+        }
+        if Path::new(&self.path).exists() {
+            try_with!(
+                fs::remove_file(&self.path),
+                "Failed to remove checkpoint {}",
+                self.path
+            );
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,463 @@
+//! Parallel line scanning of single large files.
+//!
+//! Most scanners split each file into lines and match them one at a time, so a multi-GB log or
+//! SQL dump keeps a single core busy for the whole scan. `scan_lines` splits data larger than
+//! `ChunkOptions::chunk_size` into chunks that are scanned on `ChunkOptions::threads` threads,
+//! then merges the matches back into file order with their line numbers and byte offsets.
+//!
+//! Chunks end just after a newline, so no line is split. A line longer than `MAX_LINE_SEARCH`
+//! (a minified dump, for example) is cut instead, and the chunk before the cut keeps scanning for
+//! another `CHUNK_OVERLAP` bytes so that a secret spanning the cut is still found. Each chunk only
+//! reports matches that start before its cut, so nothing is reported twice.
+//!
+//! Multiline rules (see the `rules` module) are run separately, on one `MULTILINE_WINDOW` of data
+//! at a time. Each window overlaps the next by `MULTILINE_OVERLAP` bytes and only reports matches
+//! that start before the overlap, so a PEM block up to that long is captured whole, wherever it
+//! falls. Each of their matches is reported on its own, with the lines it covers as `diff`.
+//!
+//! # Examples
+//!
+//! ```
+//! use rusty_hogs::chunked_scanning::{scan_lines, ChunkOptions};
+//! use rusty_hogs::SecretScannerBuilder;
+//!
+//! let ss = SecretScannerBuilder::new().build();
+//! let mut data: Vec<u8> = Vec::new();
+//! for i in 0..1000 {
+//!     data.extend_from_slice(format!("line {} user{}@example.com\n", i, i).as_bytes());
+//! }
+//! let options = ChunkOptions { chunk_size: 1024, threads: 4 };
+//! let matches = scan_lines(&ss, data, options, false);
+//! let emails: Vec<_> = matches.iter().filter(|m| m.reason == "Email address").collect();
+//! assert_eq!(emails.len(), 1000);
+//! assert_eq!(emails[999].line_number, 1000);
+//! assert_eq!(emails[999].strings_found, vec!["user999@example.com"]);
+//! assert_eq!(emails[999].spans[0].line, 1000);
+//! assert_eq!(emails[999].spans[0].column_start, 10);
+//!
+//! let ss = SecretScannerBuilder::new()
+//!     .set_json_str(
+//!         r##"{ "rules": { "Signing key": {
+//!             "pattern": "-----BEGIN SIGNING KEY-----[\\s\\S]*?-----END SIGNING KEY-----",
+//!             "multiline": true
+//!         } } }"##,
+//!     )
+//!     .build();
+//! let data = b"config:\n-----BEGIN SIGNING KEY-----\nMIIEow\nIBAAK\n-----END SIGNING KEY-----\n";
+//! let matches = scan_lines(&ss, data.to_vec(), ChunkOptions::default(), false);
+//! assert_eq!(matches.len(), 1);
+//! assert_eq!(matches[0].line_number, 2);
+//! assert!(matches[0].strings_found[0].ends_with("IBAAK\n-----END SIGNING KEY-----"));
+//! assert_eq!((matches[0].spans[0].line, matches[0].spans[0].line_end), (2, 5));
+//! ```
+
+use crate::{SecretScanner, Span};
+use encoding::all::ASCII;
+use encoding::{DecoderTrap, Encoding};
+use log::{self, debug, error};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+
+/// How far past the nominal end of a chunk to look for a newline before cutting a line
+pub const MAX_LINE_SEARCH: usize = 1 << 20;
+
+/// How far a chunk that ends by cutting a line keeps scanning past the cut
+pub const CHUNK_OVERLAP: usize = 4096;
+
+/// How much data the multiline rules are run against at once
+pub const MULTILINE_WINDOW: usize = 256 << 10;
+
+/// How far each multiline window reaches into the next, the length of the longest match that is
+/// always found whole
+pub const MULTILINE_OVERLAP: usize = 32 << 10;
+
+/// When and how to split data for `scan_lines`
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub struct ChunkOptions {
+    /// Data larger than this many bytes is split into chunks of about this size
+    pub chunk_size: usize,
+    /// The number of chunks scanned at once. With 1 thread data is never split.
+    pub threads: usize,
+}
+
+impl Default for ChunkOptions {
+    /// 64 MiB chunks on 4 threads
+    fn default() -> Self {
+        Self {
+            chunk_size: 64 << 20,
+            threads: 4,
+        }
+    }
+}
+
+/// The matches of one rule (or of entropy scanning) on one line, or one match of a multiline rule
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Default)]
+pub struct LineMatch {
+    /// The line, the part of a cut line scanned by one chunk, or the lines of a multiline match
+    pub diff: String,
+    pub strings_found: Vec<String>,
+    pub reason: String,
+    /// 1-based
+    pub line_number: usize,
+    /// The byte offset of the start of `diff` in the data
+    pub offset: usize,
+    /// Where each of `strings_found` is in the data, see `Span`
+    pub spans: Vec<Span>,
+}
+
+/// A range of the data scanned by one worker. Matches are reported if they start before
+/// `owned_end`; scanning continues to `end` when the chunk ends by cutting a line.
+#[derive(Debug, Clone, Copy)]
+struct Chunk {
+    start: usize,
+    owned_end: usize,
+    end: usize,
+}
+
+/// Splits `data` into lines and scans each one, in parallel chunks if `data` is larger than
+/// `options.chunk_size`, then runs the multiline rules over it. Matches are returned in the order
+/// they appear in `data`. `data` should already have been through `SecretScanner::preprocess`.
+pub fn scan_lines(
+    scanner: &SecretScanner,
+    data: Vec<u8>,
+    options: ChunkOptions,
+    scan_entropy: bool,
+) -> Vec<LineMatch> {
+    if !scanner.has_multiline_rules() {
+        return scan_chunks(scanner, data, options, scan_entropy);
+    }
+    let multiline_matches = scan_multiline(scanner, &data);
+    let mut output = scan_chunks(scanner, data, options, scan_entropy);
+    output.extend(multiline_matches);
+    // stable, so the matches on each line keep their order
+    output.sort_by_key(|m| m.offset);
+    output
+}
+
+/// The line by line part of `scan_lines`
+fn scan_chunks(
+    scanner: &SecretScanner,
+    data: Vec<u8>,
+    options: ChunkOptions,
+    scan_entropy: bool,
+) -> Vec<LineMatch> {
+    let chunks = split_chunks(&data, options.chunk_size.max(1));
+    if chunks.len() < 2 || options.threads < 2 {
+        let whole = Chunk {
+            start: 0,
+            owned_end: data.len(),
+            end: data.len(),
+        };
+        return scan_chunk(scanner, &data, whole, scan_entropy).1;
+    }
+    debug!(
+        "Scanning {} bytes in {} chunks on {} threads",
+        data.len(),
+        chunks.len(),
+        options.threads
+    );
+
+    let data = Arc::new(data);
+    let queue = Arc::new(Mutex::new(chunks.clone().into_iter().enumerate()));
+    let (tx, rx) = mpsc::channel();
+    let mut handles = Vec::new();
+    for _ in 0..options.threads.min(chunks.len()) {
+        let queue = Arc::clone(&queue);
+        let data = Arc::clone(&data);
+        let tx = tx.clone();
+        let scanner = scanner.clone();
+        handles.push(thread::spawn(move || loop {
+            let (index, chunk) = match queue.lock().unwrap().next() {
+                Some(c) => c,
+                None => break,
+            };
+            let result = scan_chunk(&scanner, &data, chunk, scan_entropy);
+            if tx.send((index, result)).is_err() {
+                break;
+            }
+        }));
+    }
+    drop(tx);
+    let mut results: Vec<(usize, (usize, Vec<LineMatch>))> = rx.iter().collect();
+    for handle in handles {
+        if handle.join().is_err() {
+            error!("A chunk scanning thread exited unexpectedly");
+        }
+    }
+
+    // line numbers within each chunk are relative, so offset them by the newlines before it
+    results.sort_by_key(|(index, _)| *index);
+    let mut lines_before = 0;
+    let mut output: Vec<LineMatch> = Vec::new();
+    for (_, (newlines, matches)) in results {
+        output.extend(matches.into_iter().map(|m| {
+            LineMatch {
+                line_number: m.line_number + lines_before,
+                spans: m
+                    .spans
+                    .into_iter()
+                    .map(|span| Span {
+                        line: span.line + lines_before,
+                        ..span
+                    })
+                    .collect(),
+                ..m
+            }
+        }));
+        lines_before += newlines;
+    }
+    output
+}
+
+/// Splits `data` into chunks of about `chunk_size` bytes that end after a newline where possible
+fn split_chunks(data: &[u8], chunk_size: usize) -> Vec<Chunk> {
+    let mut chunks = Vec::new();
+    let mut start = 0;
+    while start < data.len() {
+        let target = start.saturating_add(chunk_size).min(data.len());
+        let search_end = target.saturating_add(MAX_LINE_SEARCH).min(data.len());
+        let chunk = match data[target..search_end].iter().position(|&b| b == b'\n') {
+            Some(i) => Chunk {
+                start,
+                owned_end: target + i + 1,
+                end: target + i + 1,
+            },
+            None if search_end == data.len() => Chunk {
+                start,
+                owned_end: data.len(),
+                end: data.len(),
+            },
+            None => Chunk {
+                start,
+                owned_end: target,
+                end: target.saturating_add(CHUNK_OVERLAP).min(data.len()),
+            },
+        };
+        start = chunk.owned_end;
+        chunks.push(chunk);
+    }
+    chunks
+}
+
+/// Scans the lines of one chunk. Returns the number of newlines in the owned part of the chunk
+/// and the matches, with line numbers relative to the start of the chunk.
+fn scan_chunk(
+    scanner: &SecretScanner,
+    data: &[u8],
+    chunk: Chunk,
+    scan_entropy: bool,
+) -> (usize, Vec<LineMatch>) {
+    let mut matches: Vec<LineMatch> = Vec::new();
+    let mut newlines = 0;
+    let mut line_start = chunk.start;
+    for line in data[chunk.start..chunk.end].split(|&b| b == b'\n') {
+        if line_start >= chunk.owned_end {
+            break;
+        }
+        // the part of the line that belongs to this chunk
+        let owned_len = line.len().min(chunk.owned_end - line_start);
+        matches.extend(match_line(
+            scanner,
+            line,
+            owned_len,
+            newlines + 1,
+            line_start,
+            scan_entropy,
+        ));
+        line_start += line.len() + 1;
+        if line_start <= chunk.owned_end {
+            newlines += 1;
+        }
+    }
+    (newlines, matches)
+}
+
+/// Scans a single line, found at `offset` in the data, for the streaming scans of
+/// `SecretScanner::scan_bytes_with` and `SecretScanner::scan_reader_with`
+pub fn scan_line(
+    scanner: &SecretScanner,
+    line: &[u8],
+    line_number: usize,
+    offset: usize,
+    scan_entropy: bool,
+) -> Vec<LineMatch> {
+    match_line(scanner, line, line.len(), line_number, offset, scan_entropy)
+}
+
+/// The matches on a line that start within its first `owned_len` bytes, except those of
+/// multiline rules
+fn match_line(
+    scanner: &SecretScanner,
+    line: &[u8],
+    owned_len: usize,
+    line_number: usize,
+    offset: usize,
+    scan_entropy: bool,
+) -> Vec<LineMatch> {
+    let mut matches: Vec<LineMatch> = Vec::new();
+    for (reason, match_iterator) in scanner.single_line_matches(line) {
+        let (strings_found, spans): (Vec<String>, Vec<Span>) = match_iterator
+            .filter(|m| m.start() < owned_len)
+            .map(|m| {
+                let span = Span::new(line, line_number, offset, m.start(), m.end());
+                (decode(&line[m.start()..m.end()]), span)
+            })
+            .unzip();
+        if !strings_found.is_empty() {
+            matches.push(LineMatch {
+                diff: decode(line),
+                strings_found,
+                reason: reason.clone(),
+                line_number,
+                offset,
+                spans,
+            });
+        }
+    }
+    if scan_entropy {
+        let ef = scanner.entropy_matches(&line[..owned_len]);
+        if !ef.is_empty() {
+            let spans = ef
+                .iter()
+                .filter_map(|s| Span::find(line, s.as_bytes(), line_number, offset))
+                .collect();
+            matches.push(LineMatch {
+                diff: decode(line),
+                strings_found: ef,
+                reason: "Entropy".to_string(),
+                line_number,
+                offset,
+                spans,
+            });
+        }
+    }
+    matches
+}
+
+/// Runs the multiline rules over data that arrives a line at a time, for the streaming scans of
+/// `SecretScanner::scan_bytes_with` and `SecretScanner::scan_reader_with`. Lines are only buffered
+/// if the scanner has multiline rules, and at most about `MULTILINE_WINDOW` bytes of them.
+pub struct MultilineWindow<'s> {
+    scanner: &'s SecretScanner,
+    buffer: Vec<u8>,
+    line_number: usize,
+    offset: usize,
+}
+
+impl<'s> MultilineWindow<'s> {
+    pub fn new(scanner: &'s SecretScanner) -> Self {
+        Self {
+            scanner,
+            buffer: Vec::new(),
+            line_number: 1,
+            offset: 0,
+        }
+    }
+
+    /// Adds the next line, without its newline. Returns the matches found once a window is full.
+    pub fn push(&mut self, line: &[u8]) -> Vec<LineMatch> {
+        if !self.scanner.has_multiline_rules() {
+            return Vec::new();
+        }
+        self.buffer.extend_from_slice(line);
+        self.buffer.push(b'\n');
+        if self.buffer.len() < MULTILINE_WINDOW {
+            return Vec::new();
+        }
+        self.scan(false)
+    }
+
+    /// The matches in the rest of the lines, after the last one was pushed
+    pub fn finish(mut self) -> Vec<LineMatch> {
+        if self.buffer.is_empty() {
+            return Vec::new();
+        }
+        self.scan(true)
+    }
+
+    fn scan(&mut self, last: bool) -> Vec<LineMatch> {
+        let (consumed, matches) = scan_window(
+            self.scanner,
+            &self.buffer,
+            self.line_number,
+            self.offset,
+            last,
+        );
+        self.line_number += count_newlines(&self.buffer[..consumed]);
+        self.offset += consumed;
+        self.buffer.drain(..consumed);
+        matches
+    }
+}
+
+/// Runs the multiline rules over `data` one window at a time
+fn scan_multiline(scanner: &SecretScanner, data: &[u8]) -> Vec<LineMatch> {
+    let mut matches: Vec<LineMatch> = Vec::new();
+    let mut start = 0;
+    let mut line_number = 1;
+    while start < data.len() {
+        let end = start.saturating_add(MULTILINE_WINDOW).min(data.len());
+        let window = &data[start..end];
+        let (consumed, window_matches) =
+            scan_window(scanner, window, line_number, start, end == data.len());
+        matches.extend(window_matches);
+        line_number += count_newlines(&window[..consumed]);
+        start += consumed;
+    }
+    matches
+}
+
+/// Runs the multiline rules over a window that starts on line `line_number`, at byte `offset` of
+/// the data. Unless it is the `last` window, only the matches that start before the last
+/// `MULTILINE_OVERLAP` bytes are reported, and the next window should start where they end, just
+/// after a newline where possible. Returns the length of the reported part and the matches.
+fn scan_window(
+    scanner: &SecretScanner,
+    window: &[u8],
+    line_number: usize,
+    offset: usize,
+    last: bool,
+) -> (usize, Vec<LineMatch>) {
+    let owned_end = if last {
+        window.len()
+    } else {
+        let cut = window.len().saturating_sub(MULTILINE_OVERLAP).max(1);
+        window[..cut]
+            .iter()
+            .rposition(|&b| b == b'\n')
+            .map_or(cut, |n| n + 1)
+    };
+    let mut matches: Vec<LineMatch> = Vec::new();
+    for (reason, match_iterator) in scanner.multiline_matches(window) {
+        for m in match_iterator.filter(|m| m.start() < owned_end) {
+            let lines_start = window[..m.start()]
+                .iter()
+                .rposition(|&b| b == b'\n')
+                .map_or(0, |n| n + 1);
+            let lines_end = window[m.end()..]
+                .iter()
+                .position(|&b| b == b'\n')
+                .map_or(window.len(), |n| m.end() + n);
+            matches.push(LineMatch {
+                diff: decode(&window[lines_start..lines_end]),
+                strings_found: vec![decode(m.as_bytes())],
+                reason: reason.clone(),
+                line_number: line_number + count_newlines(&window[..lines_start]),
+                offset: offset + lines_start,
+                spans: vec![Span::new(window, line_number, offset, m.start(), m.end())],
+            });
+        }
+    }
+    (owned_end, matches)
+}
+
+fn count_newlines(data: &[u8]) -> usize {
+    data.iter().filter(|&&b| b == b'\n').count()
+}
+
+/// Decodes found text for reporting, dropping any bytes that are not ASCII
+fn decode(bytes: &[u8]) -> String {
+    ASCII
+        .decode(bytes, DecoderTrap::Ignore)
+        .unwrap_or_else(|_| "<STRING DECODE ERROR>".parse().unwrap())
+}
@@ -0,0 +1,227 @@
+//! Collection of tools for scanning CloudWatch Logs for secrets.
+//!
+//! `CloudWatchScanner` acts as a wrapper around a `SecretScanner` object and talks to the
+//! [CloudWatch Logs API](https://docs.aws.amazon.com/AmazonCloudWatchLogs/latest/APIReference/Welcome.html)
+//! of a region. It can list the log groups of the region, and scan the events of a log group with
+//! FilterLogEvents, a page of up to 10,000 events at a time. Only the events within the scanner's
+//! time range are requested, and a
+//! [filter pattern](https://docs.aws.amazon.com/AmazonCloudWatch/latest/logs/FilterAndPatternSyntax.html)
+//! can narrow them down further on the server side.
+//!
+//! Requests are signed with the credentials of `aws_scanning`, which need the
+//! `logs:DescribeLogGroups` and `logs:FilterLogEvents` permissions.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use rusty_hogs::aws_scanning;
+//! use rusty_hogs::cloudwatch_scanning::{CloudWatchConfig, CloudWatchScanner};
+//!
+//! let cs = CloudWatchScanner::new();
+//! let config = CloudWatchConfig {
+//!     credentials: aws_scanning::credentials_from_args(None, None, None).unwrap(),
+//!     region: "us-east-1".to_string(),
+//! };
+//! for log_group in cs.list_log_groups(&config, Some("/aws/lambda/")).unwrap() {
+//!     let findings = cs.scan_log_group(&config, &log_group, None, false).unwrap();
+//!     cs.secret_scanner.output_findings(&findings);
+//! }
+//! ```
+
+use crate::aws_scanning;
+use crate::shutdown;
+use crate::{Finding, SecretScanner, Span, TextFinding};
+use chrono::{TimeZone, Utc};
+use log::{self, debug, info};
+use s3::credentials::Credentials;
+use serde_derive::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use simple_error::SimpleError;
+use std::collections::HashSet;
+
+/// The version of the CloudWatch Logs API, and of the JSON protocol it speaks
+const API_TARGET: &str = "Logs_20140328";
+const JSON_VERSION: &str = "1.1";
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Hash, Clone, Default)]
+/// `serde_json` object that represents a single found secret - finding
+pub struct CloudWatchFinding {
+    pub diff: String,
+    #[serde(rename = "stringsFound")]
+    pub strings_found: Vec<String>,
+    pub region: String,
+    #[serde(rename = "logGroup")]
+    pub log_group: String,
+    #[serde(rename = "logStream")]
+    pub log_stream: String,
+    /// `<region>/<logGroup>/<logStream>`, without the leading `/` of the log group
+    pub path: String,
+    /// The time of the log event, in RFC 3339 format
+    pub timestamp: String,
+    pub reason: String,
+    /// Where each of `strings_found` is in the text, see `Span`
+    #[serde(default)]
+    pub spans: Vec<Span>,
+}
+
+impl TextFinding for CloudWatchFinding {
+    fn with_match(&self, found: Finding) -> Self {
+        Self {
+            diff: found.diff,
+            strings_found: found.strings_found,
+            reason: found.reason,
+            spans: found.spans,
+            ..self.clone()
+        }
+    }
+}
+
+/// The region to scan and the credentials to sign requests with
+#[derive(Debug, Clone)]
+pub struct CloudWatchConfig {
+    pub credentials: Credentials,
+    pub region: String,
+}
+
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+/// Contains helper functions for performing scans of CloudWatch log groups
+pub struct CloudWatchScanner {
+    pub secret_scanner: SecretScanner,
+}
+
+/// Acts as a wrapper around a `SecretScanner` object to provide helper functions for performing
+/// scanning against CloudWatch Logs. Relies on the [reqwest](https://docs.rs/reqwest/) library
+/// for HTTP access.
+impl CloudWatchScanner {
+    /// Initialize the SecretScanner object first using the SecretScannerBuilder, then provide
+    /// it to this constructor method.
+    pub fn new_from_scanner(secret_scanner: SecretScanner) -> Self {
+        Self { secret_scanner }
+    }
+
+    pub fn new() -> Self {
+        Self {
+            secret_scanner: SecretScanner::default(),
+        }
+    }
+
+    /// Returns the names of the log groups of the region, only those starting with `prefix` if
+    /// given
+    pub fn list_log_groups(
+        &self,
+        config: &CloudWatchConfig,
+        prefix: Option<&str>,
+    ) -> Result<Vec<String>, SimpleError> {
+        let mut log_groups: Vec<String> = Vec::new();
+        let mut next_token: Option<String> = None;
+        loop {
+            let mut body = json!({});
+            if let Some(p) = prefix {
+                body["logGroupNamePrefix"] = json!(p);
+            }
+            if let Some(t) = &next_token {
+                body["nextToken"] = json!(t);
+            }
+            let page = call(config, "DescribeLogGroups", &body)?;
+            log_groups.extend(
+                page["logGroups"]
+                    .as_array()
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|g| g["logGroupName"].as_str())
+                    .map(String::from),
+            );
+            next_token = page["nextToken"].as_str().map(String::from);
+            if next_token.is_none() {
+                break;
+            }
+        }
+        info!("Found {} log groups in {}", log_groups.len(), config.region);
+        Ok(log_groups)
+    }
+
+    /// Scans the events of `log_group` within the scanner's time range, only those matching
+    /// `filter_pattern` if given. Returns a HashSet of findings.
+    pub fn scan_log_group(
+        &self,
+        config: &CloudWatchConfig,
+        log_group: &str,
+        filter_pattern: Option<&str>,
+        scan_entropy: bool,
+    ) -> Result<HashSet<CloudWatchFinding>, SimpleError> {
+        info!("Scanning log group {}", log_group);
+        let time_range = self.secret_scanner.time_range;
+        let mut findings: HashSet<CloudWatchFinding> = HashSet::new();
+        let mut next_token: Option<String> = None;
+        loop {
+            if shutdown::interrupted() {
+                break;
+            }
+            let mut body = json!({ "logGroupName": log_group });
+            if let Some(since) = time_range.since {
+                body["startTime"] = json!(since.timestamp_millis());
+            }
+            if let Some(until) = time_range.until {
+                body["endTime"] = json!(until.timestamp_millis());
+            }
+            if let Some(p) = filter_pattern {
+                body["filterPattern"] = json!(p);
+            }
+            if let Some(t) = &next_token {
+                body["nextToken"] = json!(t);
+            }
+            let page = call(config, "FilterLogEvents", &body)?;
+            let events = page["events"].as_array().cloned().unwrap_or_default();
+            debug!("Scanning {} events of {}", events.len(), log_group);
+            for event in &events {
+                let log_stream = event["logStreamName"].as_str().unwrap_or("");
+                let template = CloudWatchFinding {
+                    region: config.region.clone(),
+                    log_group: log_group.to_string(),
+                    log_stream: log_stream.to_string(),
+                    path: format!(
+                        "{}/{}/{}",
+                        config.region,
+                        log_group.trim_start_matches('/'),
+                        log_stream
+                    ),
+                    timestamp: event["timestamp"]
+                        .as_i64()
+                        .map(|t| Utc.timestamp_millis(t).to_rfc3339())
+                        .unwrap_or_default(),
+                    ..Default::default()
+                };
+                let message = event["message"].as_str().unwrap_or("");
+                self.secret_scanner.scan_text(
+                    message.as_bytes(),
+                    &template,
+                    scan_entropy,
+                    &mut findings,
+                );
+            }
+            // pages can be empty while the search goes on, only a missing token ends it
+            next_token = page["nextToken"].as_str().map(String::from);
+            if next_token.is_none() {
+                break;
+            }
+        }
+        Ok(findings)
+    }
+}
+
+impl Default for CloudWatchScanner {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Calls a CloudWatch Logs operation, e.g. `FilterLogEvents`
+fn call(config: &CloudWatchConfig, operation: &str, body: &Value) -> Result<Value, SimpleError> {
+    aws_scanning::aws_json_call(
+        &config.credentials,
+        "logs",
+        &config.region,
+        (&format!("{}.{}", API_TARGET, operation), JSON_VERSION),
+        body,
+    )
+}
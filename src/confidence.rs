@@ -0,0 +1,231 @@
+//! Scoring how likely a finding is to be a real secret rather than a false positive.
+//!
+//! Entropy findings and generic rules (`Generic Secret`, `Generic API Key`, ...) match plenty of
+//! hashes, identifiers and sample values. `score` rates a finding from 0 to 100, starting from how
+//! specific its rule is and adjusting for:
+//! - the characters of the strings found: random secrets mix upper and lower case letters and
+//!   digits, identifiers and hashes tend to use one or two of them
+//! - the share of them made of dictionary-like words, as in `mySecretPasswordHere`
+//! - placeholders such as `example`, `changeme` or `xxxx`, and references to a secret stored
+//!   elsewhere (`${...}`, `os.environ`, ...) on the same line
+//! - locations under test, fixture, example and documentation directories
+//!
+//! With `--min-confidence <SCORE>` the findings scoring less are left out of reports, and every
+//! reported finding gets a `confidence` field. `--confidence` adds the field without filtering.
+//!
+//! # Examples
+//!
+//! ```
+//! use rusty_hogs::confidence;
+//! use serde_json::json;
+//!
+//! let real = json!({
+//!     "reason": "Generic Secret",
+//!     "stringsFound": ["secret = kX9mQ2vL7pR4tZ8wB3nF6hJ1"],
+//!     "diff": "secret = kX9mQ2vL7pR4tZ8wB3nF6hJ1",
+//!     "path": "config/prod.yml"
+//! });
+//! let sample = json!({
+//!     "reason": "Generic Secret",
+//!     "stringsFound": ["secret = example_secret_value_here"],
+//!     "diff": "secret = example_secret_value_here",
+//!     "path": "tests/fixtures/config.yml"
+//! });
+//! assert!(confidence::score(&real) > confidence::score(&sample));
+//!
+//! let reported = confidence::apply(vec![real, sample], Some(50));
+//! assert_eq!(reported.len(), 1);
+//! assert!(reported[0]["confidence"].as_u64().unwrap() >= 50);
+//! ```
+
+use serde_json::Value;
+
+/// The field reports give the score in
+pub const CONFIDENCE_FIELD: &str = "confidence";
+
+/// The reasons of findings that aren't matched by a rule for a specific kind of secret
+const GENERIC_REASONS: &[&str] = &["Entropy", "Generic", "Sensitive key"];
+
+/// Words that mark a sample or placeholder value rather than a real secret
+const PLACEHOLDERS: &[&str] = &[
+    "example",
+    "sample",
+    "dummy",
+    "placeholder",
+    "changeme",
+    "change_me",
+    "your_",
+    "yourkey",
+    "xxxx",
+    "fake",
+    "redacted",
+    "test",
+    "todo",
+    "foobar",
+];
+
+/// Line contents that refer to a secret stored elsewhere instead of containing one
+const REFERENCES: &[&str] = &[
+    "${",
+    "{{",
+    "os.environ",
+    "getenv",
+    "process.env",
+    "env::var",
+    "secretkeyref",
+    "vault:",
+];
+
+/// Path components of test, fixture, example and documentation files
+const TEST_PATHS: &[&str] = &[
+    "test/",
+    "tests/",
+    "spec/",
+    "fixture",
+    "mock",
+    "example",
+    "sample",
+    "docs/",
+    "testdata/",
+    "__tests__/",
+];
+
+/// The fields findings locate themselves with (see the `reporting` module)
+const LOCATION_FIELDS: &[&str] = &["path", "key", "file", "object", "name"];
+
+/// Scores a finding (serialized to a JSON object) from 0, surely a false positive, to 100
+pub fn score(finding: &Value) -> u8 {
+    let reason = finding["reason"].as_str().unwrap_or("");
+    let generic = GENERIC_REASONS.iter().any(|g| reason.starts_with(g));
+    let mut score: i32 = if generic { 50 } else { 80 };
+
+    let strings: Vec<&str> = finding["stringsFound"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|s| s.as_str())
+        .collect();
+    let secret: String = strings.concat();
+    if generic {
+        // specific rules match a known format, for which the charset says little
+        score += match char_classes(&secret) {
+            0 | 1 => -20,
+            2 => -5,
+            3 => 10,
+            _ => 15,
+        };
+        let words = word_ratio(&secret);
+        if words > 0.6 {
+            score -= 25;
+        } else if words > 0.3 {
+            score -= 10;
+        }
+    }
+
+    let secret = secret.to_lowercase();
+    if PLACEHOLDERS.iter().any(|p| secret.contains(p)) {
+        score -= 30;
+    }
+    let diff = finding["diff"].as_str().unwrap_or("").to_lowercase();
+    if REFERENCES.iter().any(|r| diff.contains(r)) {
+        score -= 25;
+    } else if PLACEHOLDERS.iter().any(|p| diff.contains(p)) {
+        score -= 10;
+    }
+
+    let in_tests = LOCATION_FIELDS
+        .iter()
+        .filter_map(|field| finding[*field].as_str())
+        .any(|location| {
+            let location = location.to_lowercase();
+            TEST_PATHS.iter().any(|p| location.contains(p))
+        });
+    if in_tests {
+        score -= 20;
+    }
+    score.max(0).min(100) as u8
+}
+
+/// Adds the `confidence` field to each finding and, if `min_confidence` is set, leaves out those
+/// scoring less. Findings are returned as they are when it is None.
+pub fn apply(findings: Vec<Value>, min_confidence: Option<u8>) -> Vec<Value> {
+    let min_confidence = match min_confidence {
+        Some(m) => m,
+        None => return findings,
+    };
+    findings
+        .into_iter()
+        .filter_map(|mut finding| {
+            let score = score(&finding);
+            if score < min_confidence {
+                return None;
+            }
+            if let Some(object) = finding.as_object_mut() {
+                object.insert(String::from(CONFIDENCE_FIELD), Value::from(score));
+            }
+            Some(finding)
+        })
+        .collect()
+}
+
+/// The number of character classes (lower case, upper case, digits and others) in `s`
+fn char_classes(s: &str) -> usize {
+    // the value after the key, e.g. `secret = <value>`, when the match includes the key
+    let value = s
+        .rsplit(|c: char| c == '=' || c == ':' || c == ' ')
+        .next()
+        .unwrap_or(s);
+    let checks: [fn(&char) -> bool; 3] = [
+        char::is_ascii_lowercase,
+        char::is_ascii_uppercase,
+        char::is_ascii_digit,
+    ];
+    let mut classes = checks
+        .iter()
+        .filter(|f| value.chars().any(|c| f(&c)))
+        .count();
+    if value.chars().any(|c| !c.is_ascii_alphanumeric()) {
+        classes += 1;
+    }
+    classes
+}
+
+/// The share of the letters of `s` in runs that look like words: 4 or more letters, split at
+/// case changes, with a natural share of vowels
+fn word_ratio(s: &str) -> f32 {
+    let mut runs: Vec<String> = Vec::new();
+    let mut current = String::new();
+    for c in s.chars() {
+        let boundary = !c.is_ascii_alphabetic()
+            || (c.is_ascii_uppercase()
+                && current
+                    .chars()
+                    .last()
+                    .map_or(false, |p| p.is_ascii_lowercase()));
+        if boundary && !current.is_empty() {
+            runs.push(current.clone());
+            current.clear();
+        }
+        if c.is_ascii_alphabetic() {
+            current.push(c);
+        }
+    }
+    if !current.is_empty() {
+        runs.push(current);
+    }
+    let letters: usize = runs.iter().map(|r| r.len()).sum();
+    if letters == 0 {
+        return 0.0;
+    }
+    let word_letters: usize = runs
+        .iter()
+        .filter(|r| r.len() >= 4)
+        .filter(|r| {
+            let vowels = r.chars().filter(|c| "aeiouAEIOU".contains(*c)).count();
+            let ratio = vowels as f32 / r.len() as f32;
+            (0.25..=0.6).contains(&ratio)
+        })
+        .map(|r| r.len())
+        .sum();
+    word_letters as f32 / letters as f32
+}
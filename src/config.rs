@@ -0,0 +1,203 @@
+//! Default command line flags for every binary, read from a shared TOML or YAML file.
+//!
+//! Each binary takes `--config <FILE>` (see `WithConfig`). The file sets flags by their long names: keys at the top
+//! level apply to every binary that has the flag, and keys in a table named after a binary apply to
+//! it alone, replacing top level keys of the same name. `true` sets a flag, `false` leaves it
+//! unset, and an array repeats the flag once for each value:
+//!
+//! ```toml
+//! regex = ["builtin", "rules/internal.json"]
+//! allowlist = "allowlist.json"
+//! entropy = true
+//! outputfile = "findings.json"
+//!
+//! [choctaw_hog]
+//! since = "-30d"
+//! branch = ["main", "release/*"]
+//!
+//! [github_hog]
+//! url = "https://github.example.com/api/v3"
+//! threads = 8
+//! ```
+//!
+//! Flags given on the command line override the file: a key is dropped when its flag is on the
+//! command line. Top level keys for flags a binary doesn't have are ignored, while unknown keys in
+//! its own table are reported by `clap` like any other unknown flag.
+//!
+//! # Examples
+//!
+//! ```
+//! use clap::{App, Arg};
+//! use rusty_hogs::config;
+//! use serde_json::json;
+//!
+//! let app = App::new("choctaw_hog")
+//!     .arg(Arg::from_usage("--entropy 'Enables entropy scanning'"))
+//!     .arg(Arg::from_usage("-o, --outputfile [OUTPUT] 'Sets the output file'"))
+//!     .arg(Arg::from_usage("<GITPATH> 'Sets the path of the repository'"));
+//! let settings = json!({
+//!     "entropy": true,
+//!     "outputfile": "shared.json",
+//!     "segments": 4,
+//!     "choctaw_hog": { "outputfile": "choctaw.json" }
+//! });
+//!
+//! let args: Vec<String> = vec!["choctaw_hog".into(), ".".into()];
+//! let merged = config::merge_args(&app, &args, &settings).unwrap();
+//! assert_eq!(merged, vec!["choctaw_hog", "--entropy", "--outputfile=choctaw.json", "."]);
+//!
+//! let args: Vec<String> = vec!["choctaw_hog".into(), "-o".into(), "cli.json".into(), ".".into()];
+//! let merged = config::merge_args(&app, &args, &settings).unwrap();
+//! assert_eq!(merged, vec!["choctaw_hog", "--entropy", "-o", "cli.json", "."]);
+//! ```
+
+use clap::{App, ArgMatches, ErrorKind};
+use log::{self, debug};
+use serde_json::{Map, Value};
+use simple_error::{try_with, SimpleError};
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::str;
+
+/// Adds `--config` support to the `clap` command line of a binary
+pub trait WithConfig<'a> {
+    /// Parses the command line after adding the flags set by its `--config` file, exiting with a
+    /// usage error like `get_matches` when the file can't be read
+    fn get_matches_with_config(self) -> ArgMatches<'a>;
+}
+
+impl<'a, 'b> WithConfig<'a> for App<'a, 'b> {
+    fn get_matches_with_config(self) -> ArgMatches<'a> {
+        let args: Vec<String> = env::args().collect();
+        let merged = match config_path(&args) {
+            Some(path) => match read_config(&path).and_then(|s| merge_args(&self, &args, &s)) {
+                Ok(merged) => merged,
+                Err(e) => {
+                    clap::Error::with_description(&e.to_string(), ErrorKind::InvalidValue).exit()
+                }
+            },
+            None => args,
+        };
+        self.get_matches_from(merged)
+    }
+}
+
+/// Reads a config file as TOML, or as YAML when its extension is .yaml or .yml
+pub fn read_config(path: &str) -> Result<Value, SimpleError> {
+    let data = try_with!(fs::read(path), "Failed to read config file {}", path);
+    let extension = Path::new(path).extension().and_then(|e| e.to_str());
+    let settings: Value = match extension {
+        Some("yaml") | Some("yml") => try_with!(
+            serde_yaml::from_slice(&data),
+            "Failed to parse YAML config file {}",
+            path
+        ),
+        _ => {
+            let text = try_with!(str::from_utf8(&data), "Config file {} is not UTF-8", path);
+            try_with!(
+                toml::from_str(text),
+                "Failed to parse TOML config file {}",
+                path
+            )
+        }
+    };
+    if !settings.is_object() {
+        return Err(SimpleError::new(format!(
+            "Config file {} is not a table of flags",
+            path
+        )));
+    }
+    Ok(settings)
+}
+
+/// Returns the command line `args` (starting with the program name) with the flags set by
+/// `settings` inserted before the command line flags, skipping those given on the command line
+pub fn merge_args(
+    app: &App,
+    args: &[String],
+    settings: &Value,
+) -> Result<Vec<String>, SimpleError> {
+    let empty = Map::new();
+    let shared = settings.as_object().unwrap_or(&empty);
+    let own = match shared.get(app.get_name()) {
+        Some(Value::Object(own)) => own,
+        _ => &empty,
+    };
+    let mut keys: Vec<(&String, &Value, bool)> = shared
+        .iter()
+        .filter(|(k, v)| !v.is_object() && !own.contains_key(*k))
+        .map(|(k, v)| (k, v, true))
+        .collect();
+    keys.extend(own.iter().map(|(k, v)| (k, v, false)));
+    keys.sort_by(|a, b| a.0.cmp(b.0));
+
+    let (program, cli) = args.split_at(1.min(args.len()));
+    let mut merged: Vec<String> = program.to_vec();
+    for (key, value, is_shared) in keys {
+        let flag = format!("--{}", key);
+        if key == "config" || on_command_line(cli, &flag) {
+            continue;
+        }
+        let flag_args = config_flag_args(&flag, value)?;
+        let mut trial = merged.clone();
+        trial.extend(flag_args.iter().cloned());
+        trial.extend(cli.iter().cloned());
+        // short flags on the command line (-o) only show up as a repeated flag
+        match app.clone().get_matches_from_safe(trial) {
+            Err(ref e) if e.kind == ErrorKind::UnexpectedMultipleUsage => {
+                debug!(
+                    "{} is set on the command line, ignoring the config file",
+                    flag
+                )
+            }
+            Err(ref e) if e.kind == ErrorKind::UnknownArgument && is_shared => {
+                debug!("{} doesn't take {}, ignoring it", app.get_name(), flag)
+            }
+            _ => merged.extend(flag_args),
+        }
+    }
+    merged.extend(cli.iter().cloned());
+    Ok(merged)
+}
+
+/// Finds the value of --config in the command line
+fn config_path(args: &[String]) -> Option<String> {
+    let mut iter = args.iter().skip(1);
+    while let Some(arg) = iter.next() {
+        if arg == "--config" {
+            return iter.next().cloned();
+        } else if arg.starts_with("--config=") {
+            return Some(arg["--config=".len()..].to_string());
+        }
+    }
+    None
+}
+
+fn on_command_line(cli: &[String], flag: &str) -> bool {
+    let with_value = format!("{}=", flag);
+    cli.iter()
+        .take_while(|a| *a != "--")
+        .any(|a| a == flag || a.starts_with(&with_value))
+}
+
+/// Turns a config value into command line arguments for `flag`
+fn config_flag_args(flag: &str, value: &Value) -> Result<Vec<String>, SimpleError> {
+    match value {
+        Value::Bool(true) => Ok(vec![flag.to_string()]),
+        Value::Bool(false) | Value::Null => Ok(Vec::new()),
+        Value::String(s) => Ok(vec![format!("{}={}", flag, s)]),
+        Value::Number(n) => Ok(vec![format!("{}={}", flag, n)]),
+        Value::Array(values) => {
+            let mut output: Vec<String> = Vec::new();
+            for value in values {
+                output.extend(config_flag_args(flag, value)?);
+            }
+            Ok(output)
+        }
+        Value::Object(_) => Err(SimpleError::new(format!(
+            "Config value for {} is a table, not a flag value",
+            flag
+        ))),
+    }
+}
@@ -0,0 +1,337 @@
+//! Collection of tools for scanning Discord servers for secrets.
+//!
+//! `DiscordScanner` acts as a wrapper around a `SecretScanner` object and talks to the
+//! [Discord API](https://discord.com/developers/docs/intro) with a bot token. It can list the text
+//! and announcement channels of a server (a guild, in the API) along with its active threads, and
+//! scan the message history of each one: the message text, the text of embeds, and the contents of
+//! attached files.
+//!
+//! The bot has to be a member of the server, with the View Channel and Read Message History
+//! permissions, and needs the privileged Message Content intent to see the text of messages.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use rusty_hogs::discord_scanning::{DiscordConfig, DiscordScanner};
+//!
+//! let ds = DiscordScanner::new();
+//! let config = DiscordConfig {
+//!     url: rusty_hogs::discord_scanning::DISCORD_API_URL.to_string(),
+//!     token: "my-bot-token".to_string(),
+//!     max_attachment_size: 10 * 1024 * 1024,
+//! };
+//! for channel in ds.list_channels(&config, "81384788765712384").unwrap() {
+//!     let findings = ds.scan_channel(&config, &channel, true, false).unwrap();
+//!     ds.secret_scanner.output_findings(&findings);
+//! }
+//! ```
+
+use crate::network;
+use crate::retry;
+use crate::{Finding, SecretScanner, Span, TextFinding};
+use chrono::{DateTime, Utc};
+use log::{self, debug, info};
+use reqwest::blocking::Response;
+use serde_derive::{Deserialize, Serialize};
+use serde_json::Value;
+use simple_error::{try_with, SimpleError};
+use std::collections::HashSet;
+
+/// The Discord API endpoint
+pub const DISCORD_API_URL: &str = "https://discord.com/api/v10";
+
+/// The most messages Discord returns per page
+const PAGE_SIZE: usize = 100;
+
+/// Channel types whose messages are scanned: text, announcement, and announcement, public and
+/// private threads
+const MESSAGE_CHANNEL_TYPES: &[u64] = &[0, 5, 10, 11, 12];
+
+/// Discord rejects API requests without a User-Agent in this format
+const USER_AGENT: &str = "DiscordBot (https://github.com/newrelic/rusty-hog, 1.0.1)";
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Hash, Clone, Default)]
+/// `serde_json` object that represents a single found secret - finding
+pub struct DiscordFinding {
+    pub diff: String,
+    #[serde(rename = "stringsFound")]
+    pub strings_found: Vec<String>,
+    /// The name of the channel or thread
+    pub channel: String,
+    /// The message ID, or `<message ID>/<file name>` for attachments
+    pub path: String,
+    /// A link to the message
+    pub url: String,
+    /// The username of the message author
+    pub user: String,
+    pub date: String,
+    pub reason: String,
+    /// Where each of `strings_found` is in the text, see `Span`
+    #[serde(default)]
+    pub spans: Vec<Span>,
+}
+
+impl TextFinding for DiscordFinding {
+    fn with_match(&self, found: Finding) -> Self {
+        Self {
+            diff: found.diff,
+            strings_found: found.strings_found,
+            reason: found.reason,
+            spans: found.spans,
+            ..self.clone()
+        }
+    }
+}
+
+/// The Discord API to talk to and the bot token to authenticate with
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+pub struct DiscordConfig {
+    /// The base URL of the API, `DISCORD_API_URL` unless testing against a proxy
+    pub url: String,
+    /// A bot token
+    pub token: String,
+    /// Attachments larger than this many bytes are skipped
+    pub max_attachment_size: u64,
+}
+
+/// A channel or thread of a server. Only the fields we need are included.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Hash, Clone)]
+pub struct DiscordChannel {
+    pub id: String,
+    pub guild_id: String,
+    pub name: String,
+}
+
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+/// Contains helper functions for performing scans of Discord channels
+pub struct DiscordScanner {
+    pub secret_scanner: SecretScanner,
+}
+
+/// Acts as a wrapper around a `SecretScanner` object to provide helper functions for performing
+/// scanning against Discord. Relies on the [reqwest](https://docs.rs/reqwest/) library for HTTP
+/// access.
+impl DiscordScanner {
+    /// Initialize the SecretScanner object first using the SecretScannerBuilder, then provide
+    /// it to this constructor method.
+    pub fn new_from_scanner(secret_scanner: SecretScanner) -> Self {
+        Self { secret_scanner }
+    }
+
+    pub fn new() -> Self {
+        Self {
+            secret_scanner: SecretScanner::default(),
+        }
+    }
+
+    /// Returns the text and announcement channels of the server `guild_id`, and its active threads
+    pub fn list_channels(
+        &self,
+        config: &DiscordConfig,
+        guild_id: &str,
+    ) -> Result<Vec<DiscordChannel>, SimpleError> {
+        let channels = get_json(config, &format!("guilds/{}/channels", guild_id))?;
+        let threads = get_json(config, &format!("guilds/{}/threads/active", guild_id))?;
+        Ok(channels
+            .as_array()
+            .into_iter()
+            .chain(threads["threads"].as_array())
+            .flatten()
+            .filter(|c| {
+                c["type"]
+                    .as_u64()
+                    .map_or(false, |t| MESSAGE_CHANNEL_TYPES.contains(&t))
+            })
+            .filter_map(|c| serde_json::from_value(c.clone()).ok())
+            .collect())
+    }
+
+    /// Scans the message history of `channel`, newest first, stopping at the start of the
+    /// scanner's time range. Attached files are downloaded and scanned if `scan_attachments` is
+    /// set, unless they are images, video or audio. Returns a HashSet of findings.
+    pub fn scan_channel(
+        &self,
+        config: &DiscordConfig,
+        channel: &DiscordChannel,
+        scan_attachments: bool,
+        scan_entropy: bool,
+    ) -> Result<HashSet<DiscordFinding>, SimpleError> {
+        info!("Scanning channel {}", channel.name);
+        let time_range = self.secret_scanner.time_range;
+        let mut findings: HashSet<DiscordFinding> = HashSet::new();
+        let mut before: Option<String> = None;
+        loop {
+            let mut path = format!("channels/{}/messages?limit={}", channel.id, PAGE_SIZE);
+            if let Some(id) = &before {
+                path.push_str(&format!("&before={}", id));
+            }
+            let page = get_json(config, &path)?;
+            let messages = match page.as_array() {
+                Some(m) if !m.is_empty() => m.clone(),
+                _ => break,
+            };
+            debug!("Scanning {} messages of {}", messages.len(), channel.name);
+            let mut reached_start = false;
+            for message in &messages {
+                let date = message["timestamp"].as_str().unwrap_or("");
+                if let (Some(since), Ok(t)) = (time_range.since, date.parse::<DateTime<Utc>>()) {
+                    if t < since {
+                        reached_start = true;
+                        break;
+                    }
+                }
+                if time_range.contains_str(date) {
+                    self.scan_message(
+                        config,
+                        channel,
+                        message,
+                        scan_attachments,
+                        scan_entropy,
+                        &mut findings,
+                    );
+                }
+            }
+            before = messages
+                .last()
+                .and_then(|m| m["id"].as_str())
+                .map(String::from);
+            if reached_start || messages.len() < PAGE_SIZE || before.is_none() {
+                break;
+            }
+        }
+        Ok(findings)
+    }
+
+    /// Scans the text, embeds and attachments of a message and adds any findings
+    fn scan_message(
+        &self,
+        config: &DiscordConfig,
+        channel: &DiscordChannel,
+        message: &Value,
+        scan_attachments: bool,
+        scan_entropy: bool,
+        findings: &mut HashSet<DiscordFinding>,
+    ) {
+        let message_id = message["id"].as_str().unwrap_or("");
+        let template = DiscordFinding {
+            channel: channel.name.clone(),
+            path: message_id.to_string(),
+            url: format!(
+                "https://discord.com/channels/{}/{}/{}",
+                channel.guild_id, channel.id, message_id
+            ),
+            user: message["author"]["username"]
+                .as_str()
+                .unwrap_or("")
+                .to_string(),
+            date: message["timestamp"].as_str().unwrap_or("").to_string(),
+            ..Default::default()
+        };
+        let mut text = message["content"].as_str().unwrap_or("").to_string();
+        for embed in message["embeds"].as_array().into_iter().flatten() {
+            for field in &["title", "description", "url"] {
+                if let Some(s) = embed[*field].as_str() {
+                    text.push('\n');
+                    text.push_str(s);
+                }
+            }
+            for field in embed["fields"].as_array().into_iter().flatten() {
+                text.push_str(&format!(
+                    "\n{}: {}",
+                    field["name"].as_str().unwrap_or(""),
+                    field["value"].as_str().unwrap_or("")
+                ));
+            }
+        }
+        self.secret_scanner
+            .scan_text(text.as_bytes(), &template, scan_entropy, findings);
+
+        if !scan_attachments {
+            return;
+        }
+        for attachment in message["attachments"].as_array().into_iter().flatten() {
+            let filename = attachment["filename"].as_str().unwrap_or("");
+            let content_type = attachment["content_type"].as_str().unwrap_or("");
+            let size = attachment["size"].as_u64().unwrap_or(0);
+            if ["image/", "video/", "audio/"]
+                .iter()
+                .any(|t| content_type.starts_with(t))
+            {
+                continue;
+            }
+            if size > config.max_attachment_size {
+                info!("Skipping {} ({} bytes) in {}", filename, size, channel.name);
+                continue;
+            }
+            let url = match attachment["url"].as_str() {
+                Some(u) => u,
+                None => continue,
+            };
+            let content = match download(url) {
+                Ok(c) => c,
+                Err(e) => {
+                    info!("Skipping {} in {}: {}", filename, channel.name, e);
+                    continue;
+                }
+            };
+            let template = DiscordFinding {
+                path: format!("{}/{}", message_id, filename),
+                ..template.clone()
+            };
+            self.secret_scanner
+                .scan_text(&content, &template, scan_entropy, findings);
+        }
+    }
+}
+
+impl Default for DiscordScanner {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Performs a GET against the Discord API, waiting and retrying when rate limited. `path` is
+/// relative to the configured API URL.
+fn get(config: &DiscordConfig, path: &str) -> Result<Response, SimpleError> {
+    let url = format!("{}/{}", config.url.trim_end_matches('/'), path);
+    debug!("GET {}", url);
+    let response = try_with!(
+        retry::send(
+            network::client()
+                .get(&url)
+                .header("Authorization", format!("Bot {}", config.token))
+                .header("User-Agent", USER_AGENT)
+        ),
+        "Discord request failed"
+    );
+    if !response.status().is_success() {
+        return Err(SimpleError::new(format!(
+            "Discord returned {} for {}",
+            response.status(),
+            url
+        )));
+    }
+    Ok(response)
+}
+
+/// Performs a GET and parses the response as JSON
+fn get_json(config: &DiscordConfig, path: &str) -> Result<Value, SimpleError> {
+    let response = get(config, path)?;
+    Ok(try_with!(
+        response.json(),
+        "Failed to parse Discord response"
+    ))
+}
+
+/// Downloads an attachment from the Discord CDN, which needs no authentication
+fn download(url: &str) -> Result<Vec<u8>, SimpleError> {
+    debug!("GET {}", url);
+    let response = try_with!(retry::send(network::client().get(url)), "Download failed");
+    if !response.status().is_success() {
+        return Err(SimpleError::new(format!(
+            "Discord returned {}",
+            response.status()
+        )));
+    }
+    Ok(try_with!(response.bytes(), "Download failed").to_vec())
+}
@@ -0,0 +1,413 @@
+//! Collection of tools for scanning Docker images for secrets.
+//!
+//! `DockerScanner` acts as a wrapper around a `SecretScanner` object to provide helper functions
+//! for performing scanning against the layers of a Docker image exported with `docker save`.
+//! Relies on the [tar-rs](https://github.com/alexcrichton/tar-rs) library to unpack the image and
+//! each of its layers.
+//!
+//! Every file in every layer is scanned, not just the files visible in the final image. When a
+//! later layer deletes a file (with an AUFS/OCI whiteout entry) or replaces it, the finding is
+//! still reported and `removedInLayer` is set to the digest of the layer that hid it, since the
+//! original content can still be recovered by anyone who pulls the image.
+//!
+//! # Examples
+//!
+//! Basic usage requires you to create a `DockerScanner` object...
+//!
+//! ```
+//! use rusty_hogs::docker_scanning::DockerScanner;
+//! let ds = DockerScanner::new();
+//! ```
+//!
+//! Alternatively you can build a custom `SecretScanner` object and supply it to the
+//! `DockerScanner` constructor...
+//!
+//! ```
+//! use rusty_hogs::SecretScannerBuilder;
+//! use rusty_hogs::docker_scanning::DockerScanner;
+//! let ss = SecretScannerBuilder::new().set_pretty_print(true).build();
+//! let ds = DockerScanner::new_from_scanner(ss);
+//! ```
+//!
+//! After that, supply the path of a `docker save` tarball to `scan_image_tarball()`, which returns
+//! a `HashSet` of findings.
+//!
+//! ```no_run
+//! use rusty_hogs::docker_scanning::{DockerFinding, DockerScanner};
+//! use std::collections::HashSet;
+//! use std::path::Path;
+//!
+//! let ds = DockerScanner::new();
+//! let findings: HashSet<DockerFinding> =
+//!     ds.scan_image_tarball(Path::new("alpine.tar"), false).unwrap();
+//! ds.secret_scanner.output_findings(&findings);
+//! ```
+
+use crate::chunked_scanning;
+use crate::progress;
+use crate::structured_scanning::{self, StructuredFormat};
+use crate::{SecretScanner, Span};
+use flate2::read::GzDecoder;
+use log::{self, debug, error, info};
+use serde_derive::{Deserialize, Serialize};
+use simple_error::{try_with, SimpleError};
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{BufReader, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use tar::Archive;
+
+const WHITEOUT_PREFIX: &str = ".wh.";
+const OPAQUE_WHITEOUT: &str = ".wh..wh..opq";
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Hash, Clone, Default)]
+/// `serde_json` object that represents a single found secret - finding
+pub struct DockerFinding {
+    pub diff: String,
+    #[serde(rename = "stringsFound")]
+    pub strings_found: Vec<String>,
+    pub image: String,
+    pub layer: String,
+    pub path: String,
+    pub reason: String,
+    #[serde(rename = "removedInLayer")]
+    pub removed_in_layer: Option<String>,
+    #[serde(rename = "keyPath", default, skip_serializing_if = "Option::is_none")]
+    pub key_path: Option<String>,
+    /// Where each of `strings_found` is in the file, see `Span` (none with a `key_path`)
+    #[serde(default)]
+    pub spans: Vec<Span>,
+}
+
+/// One entry of the `manifest.json` file written by `docker save`
+#[derive(Deserialize, Debug, Clone)]
+struct ManifestEntry {
+    #[serde(rename = "Config")]
+    config: String,
+    #[serde(rename = "RepoTags")]
+    repo_tags: Option<Vec<String>>,
+    #[serde(rename = "Layers")]
+    layers: Vec<String>,
+}
+
+/// The subset of the image configuration JSON needed to map layers to their digests
+#[derive(Deserialize, Debug, Clone)]
+struct ImageConfig {
+    rootfs: RootFs,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+struct RootFs {
+    diff_ids: Vec<String>,
+}
+
+/// An unpacked layer tarball and the paths it adds or removes, used to work out which files are
+/// hidden from the final image by later layers.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct LayerIndex {
+    digest: String,
+    path: PathBuf,
+    files: HashSet<String>,
+    whiteouts: HashSet<String>,
+    opaque_dirs: HashSet<String>,
+}
+
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+/// Contains helper functions for performing scans of Docker image tarballs
+pub struct DockerScanner {
+    pub secret_scanner: SecretScanner,
+}
+
+/// Acts as a wrapper around a `SecretScanner` object to provide helper functions for performing
+/// scanning against Docker images. Relies on the [tar](https://docs.rs/tar/0.4/tar/) library.
+impl DockerScanner {
+    /// Initialize the SecretScanner object first using the SecretScannerBuilder, then provide
+    /// it to this constructor method.
+    pub fn new_from_scanner(secret_scanner: SecretScanner) -> Self {
+        Self { secret_scanner }
+    }
+
+    pub fn new() -> Self {
+        Self {
+            secret_scanner: SecretScanner::default(),
+        }
+    }
+
+    /// Uses the `docker` CLI (and therefore the local Docker daemon socket) to export an image
+    /// reference such as `alpine:3.11` to a tarball inside `dest_dir`. Returns the tarball path.
+    pub fn export_image(image_ref: &str, dest_dir: &Path) -> Result<PathBuf, SimpleError> {
+        let tarball = dest_dir.join("image.tar");
+        info!("Exporting {:?} with docker save...", image_ref);
+        let status = try_with!(
+            Command::new("docker")
+                .arg("save")
+                .arg("-o")
+                .arg(&tarball)
+                .arg(image_ref)
+                .status(),
+            "Failed to run docker save, is the docker CLI installed?"
+        );
+        if !status.success() {
+            return Err(SimpleError::new(format!(
+                "docker save exited with {} for image {:?}",
+                status, image_ref
+            )));
+        }
+        Ok(tarball)
+    }
+
+    /// Unpacks a `docker save` tarball and scans every file in every layer of every image it
+    /// contains, returning a HashSet of findings.
+    pub fn scan_image_tarball(
+        &self,
+        tarball: &Path,
+        scan_entropy: bool,
+    ) -> Result<HashSet<DockerFinding>, SimpleError> {
+        let dest_dir = self.secret_scanner.workspace()?;
+        let f = try_with!(File::open(tarball), "Failed to open the image tarball");
+        try_with!(
+            Archive::new(BufReader::new(f)).unpack(dest_dir.path()),
+            "Failed to unpack the image tarball"
+        );
+        dest_dir.check_quota()?;
+        let manifest_file = try_with!(
+            File::open(dest_dir.path().join("manifest.json")),
+            "No manifest.json found, was the tarball created with docker save?"
+        );
+        let manifest: Vec<ManifestEntry> = try_with!(
+            serde_json::from_reader(BufReader::new(manifest_file)),
+            "Failed to parse manifest.json"
+        );
+
+        let mut findings: HashSet<DockerFinding> = HashSet::new();
+        for image in manifest {
+            let image_name = match image.repo_tags.as_ref().and_then(|t| t.first()) {
+                Some(tag) => tag.clone(),
+                None => image.config.clone(),
+            };
+            info!("Scanning image {}", image_name);
+            let layers = Self::index_layers(dest_dir.path(), &image)?;
+            self.scan_layers(&image_name, &layers, scan_entropy, &mut findings)?;
+        }
+        Ok(findings)
+    }
+
+    /// Scans each layer of an image in order, checking later layers for whiteouts of its files
+    pub(crate) fn scan_layers(
+        &self,
+        image_name: &str,
+        layers: &[LayerIndex],
+        scan_entropy: bool,
+        findings: &mut HashSet<DockerFinding>,
+    ) -> Result<(), SimpleError> {
+        progress::add_total(layers.len());
+        for (i, layer) in layers.iter().enumerate() {
+            self.scan_layer(image_name, layer, &layers[i + 1..], scan_entropy, findings)?;
+        }
+        Ok(())
+    }
+
+    /// Reads the image config to pair each layer tarball with its digest, then lists the files
+    /// and whiteouts of each layer.
+    fn index_layers(root: &Path, image: &ManifestEntry) -> Result<Vec<LayerIndex>, SimpleError> {
+        let config_file = try_with!(
+            File::open(root.join(&image.config)),
+            "Failed to open the image config"
+        );
+        let config: ImageConfig = try_with!(
+            serde_json::from_reader(BufReader::new(config_file)),
+            "Failed to parse the image config"
+        );
+        let mut layers: Vec<LayerIndex> = Vec::new();
+        for (i, layer_path) in image.layers.iter().enumerate() {
+            let digest = match config.rootfs.diff_ids.get(i) {
+                Some(d) => d.clone(),
+                None => layer_path.clone(),
+            };
+            layers.push(Self::index_layer(digest, root.join(layer_path))?);
+        }
+        Ok(layers)
+    }
+
+    /// Lists the files and whiteouts of a single layer tarball (optionally gzipped) on disk
+    pub(crate) fn index_layer(digest: String, path: PathBuf) -> Result<LayerIndex, SimpleError> {
+        let mut index = LayerIndex {
+            digest,
+            path,
+            ..Default::default()
+        };
+        let mut archive = Archive::new(Self::open_layer(&index.path)?);
+        let entries = try_with!(archive.entries(), "Failed to read image layer");
+        for entry in entries {
+            let entry = try_with!(entry, "Failed to read image layer entry");
+            let path = match entry.path() {
+                Ok(p) => normalize_path(&p),
+                Err(_) => continue,
+            };
+            let (parent, file_name) = split_path(&path);
+            if file_name == OPAQUE_WHITEOUT {
+                index.opaque_dirs.insert(parent.to_string());
+            } else if file_name.starts_with(WHITEOUT_PREFIX) {
+                let removed = &file_name[WHITEOUT_PREFIX.len()..];
+                index.whiteouts.insert(join_path(parent, removed));
+            } else if entry.header().entry_type().is_file() {
+                index.files.insert(path);
+            }
+        }
+        debug!(
+            "Layer {} has {} files and {} whiteouts",
+            index.digest,
+            index.files.len(),
+            index.whiteouts.len() + index.opaque_dirs.len()
+        );
+        Ok(index)
+    }
+
+    /// Opens a layer tarball, transparently decompressing it if it is gzipped (OCI layouts).
+    fn open_layer(path: &Path) -> Result<Box<dyn Read>, SimpleError> {
+        let mut f = try_with!(File::open(path), "Failed to open image layer");
+        let mut magic = [0_u8; 2];
+        let n = try_with!(f.read(&mut magic), "Failed to read image layer");
+        try_with!(f.seek(SeekFrom::Start(0)), "Failed to read image layer");
+        if n == 2 && magic == [0x1f, 0x8b] {
+            Ok(Box::new(GzDecoder::new(BufReader::new(f))))
+        } else {
+            Ok(Box::new(BufReader::new(f)))
+        }
+    }
+
+    /// Returns the digest of the first later layer that deletes, hides or replaces `path`
+    fn removed_in_layer(path: &str, later_layers: &[LayerIndex]) -> Option<String> {
+        later_layers
+            .iter()
+            .find(|layer| {
+                if layer.files.contains(path) || layer.whiteouts.contains(path) {
+                    return true;
+                }
+                Path::new(path).ancestors().skip(1).any(|a| {
+                    let a = a.to_string_lossy();
+                    layer.whiteouts.contains(a.as_ref()) || layer.opaque_dirs.contains(a.as_ref())
+                })
+            })
+            .map(|layer| layer.digest.clone())
+    }
+
+    /// Scans each regular file in a single layer, adding any findings to `findings`
+    fn scan_layer(
+        &self,
+        image_name: &str,
+        layer: &LayerIndex,
+        later_layers: &[LayerIndex],
+        scan_entropy: bool,
+        findings: &mut HashSet<DockerFinding>,
+    ) -> Result<(), SimpleError> {
+        info!("Scanning layer {}", layer.digest);
+        let found = findings.len();
+        let mut size = 0;
+        let mut archive = Archive::new(Self::open_layer(&layer.path)?);
+        let entries = try_with!(archive.entries(), "Failed to read image layer");
+        for entry in entries {
+            let mut entry = try_with!(entry, "Failed to read image layer entry");
+            if !entry.header().entry_type().is_file() {
+                continue;
+            }
+            let path = match entry.path() {
+                Ok(p) => normalize_path(&p),
+                Err(_) => continue,
+            };
+            if split_path(&path).1.starts_with(WHITEOUT_PREFIX) {
+                continue;
+            }
+            let mut data: Vec<u8> = Vec::new();
+            if let Err(e) = entry.read_to_end(&mut data) {
+                error!(
+                    "Failed to read {:?} from layer {}: {:?}",
+                    path, layer.digest, e
+                );
+                continue;
+            }
+            let removed_in_layer = Self::removed_in_layer(&path, later_layers);
+
+            let data = self.secret_scanner.preprocess_owned(data);
+            size += data.len();
+
+            // Config files are parsed and scanned value by value, falling back to lines on failure
+            if self.secret_scanner.structured {
+                if let Some(format) = StructuredFormat::detect(&path, &data) {
+                    match structured_scanning::scan_document(
+                        &self.secret_scanner,
+                        &data,
+                        format,
+                        scan_entropy,
+                    ) {
+                        Ok(matches) => {
+                            findings.extend(matches.into_iter().map(|m| DockerFinding {
+                                diff: m.diff,
+                                strings_found: m.strings_found,
+                                image: image_name.to_string(),
+                                layer: layer.digest.clone(),
+                                path: path.clone(),
+                                reason: m.reason,
+                                removed_in_layer: removed_in_layer.clone(),
+                                key_path: Some(m.key_path),
+                                spans: Vec::new(),
+                            }));
+                            continue;
+                        }
+                        Err(e) => debug!("{}, scanning {} line by line", e, path),
+                    }
+                }
+            }
+
+            // Large files are split into chunks that are scanned in parallel
+            let options = self.secret_scanner.chunk_options;
+            let matches =
+                chunked_scanning::scan_lines(&self.secret_scanner, data, options, scan_entropy);
+            findings.extend(matches.into_iter().map(|m| DockerFinding {
+                diff: m.diff,
+                strings_found: m.strings_found,
+                image: image_name.to_string(),
+                layer: layer.digest.clone(),
+                path: path.clone(),
+                reason: m.reason,
+                removed_in_layer: removed_in_layer.clone(),
+                key_path: None,
+                spans: m.spans,
+            }));
+        }
+        progress::scanned(size, findings.len() - found);
+        Ok(())
+    }
+}
+
+impl Default for DockerScanner {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Strips the leading `./` or `/` that layer tarballs may or may not include
+fn normalize_path(path: &Path) -> String {
+    path.to_string_lossy()
+        .trim_start_matches("./")
+        .trim_start_matches('/')
+        .trim_end_matches('/')
+        .to_string()
+}
+
+/// Splits a normalized path into its parent directory and file name
+fn split_path(path: &str) -> (&str, &str) {
+    match path.rfind('/') {
+        Some(i) => (&path[..i], &path[i + 1..]),
+        None => ("", path),
+    }
+}
+
+fn join_path(parent: &str, name: &str) -> String {
+    if parent.is_empty() {
+        name.to_string()
+    } else {
+        format!("{}/{}", parent, name)
+    }
+}
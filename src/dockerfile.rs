@@ -0,0 +1,288 @@
+//! Parsing of the variables set by Dockerfiles and Compose files.
+//!
+//! Credentials in container builds usually end up in `ENV` and `ARG` instructions, or in the
+//! `environment:` and `build.args` of a Compose service. A line-based scan misses many of them:
+//! an instruction continued over several lines with `\`, a value in quotes, or a value pieced
+//! together from other variables, such as `ENV DB_URL=postgres://app:${DB_PASSWORD}@db/app`.
+//!
+//! `variables` joins the continued lines of a Dockerfile, splits each `ENV` and `ARG` into its
+//! names and values the way Docker does (quotes, escapes and the legacy `ENV NAME value` form)
+//! and substitutes the variables set earlier in the file, so every value is scanned whole.
+//! `normalize_compose` turns the `KEY=value` lists of a Compose file into maps and expands the
+//! `${VAR:-default}` defaults in it. Both are used by the `structured_scanning` module.
+//!
+//! # Examples
+//!
+//! ```
+//! use rusty_hogs::dockerfile;
+//!
+//! let text = "FROM alpine\nARG DB_PASSWORD=hunter2\nENV DB_URL=postgres://app:${DB_PASSWORD}@db/app \\\n    DEBUG=1\n";
+//! let variables = dockerfile::variables(text);
+//! assert_eq!(variables.len(), 3);
+//! assert_eq!(variables[1].instruction, "ENV");
+//! assert_eq!(variables[1].name, "DB_URL");
+//! assert_eq!(variables[1].value, "postgres://app:hunter2@db/app");
+//! assert_eq!(variables[1].line, 3);
+//! ```
+
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+
+/// A Dockerfile instruction, with its continued lines joined
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Default)]
+pub struct Instruction {
+    /// The instruction in upper case, e.g. `ENV`
+    pub name: String,
+    pub arguments: String,
+    /// The line the instruction starts on, from 1
+    pub line: usize,
+}
+
+/// A variable set by an `ENV` or `ARG` instruction
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Default)]
+pub struct Variable {
+    /// `ENV` or `ARG`
+    pub instruction: String,
+    pub name: String,
+    /// The value, with the variables set earlier in the file substituted
+    pub value: String,
+    /// The line the instruction starts on, from 1
+    pub line: usize,
+}
+
+/// Splits a Dockerfile into instructions, honouring the `# escape=` parser directive, joining
+/// continued lines and skipping comments
+pub fn parse(text: &str) -> Vec<Instruction> {
+    let escape = escape_character(text);
+    let mut instructions: Vec<Instruction> = Vec::new();
+    let mut current: Option<Instruction> = None;
+    for (i, line) in text.lines().enumerate() {
+        let trimmed = line.trim();
+        if trimmed.starts_with('#') || (trimmed.is_empty() && current.is_none()) {
+            continue;
+        }
+        let (content, continued) = if trimmed.ends_with(escape) {
+            (&trimmed[..trimmed.len() - escape.len_utf8()], true)
+        } else {
+            (trimmed, false)
+        };
+        let mut instruction = match current.take() {
+            Some(mut c) => {
+                c.arguments.push(' ');
+                c.arguments.push_str(content.trim());
+                c
+            }
+            None => {
+                let mut parts = content.splitn(2, char::is_whitespace);
+                Instruction {
+                    name: parts.next().unwrap_or("").to_ascii_uppercase(),
+                    arguments: parts.next().unwrap_or("").trim().to_string(),
+                    line: i + 1,
+                }
+            }
+        };
+        if continued {
+            current = Some(instruction);
+        } else {
+            instruction.arguments = instruction.arguments.trim().to_string();
+            instructions.push(instruction);
+        }
+    }
+    instructions.extend(current);
+    instructions
+}
+
+/// Returns the variables set by the `ENV` and `ARG` instructions of a Dockerfile, in order. `ARG`
+/// instructions without a default value set nothing and are left out.
+pub fn variables(text: &str) -> Vec<Variable> {
+    let escape = escape_character(text);
+    let mut scope: HashMap<String, String> = HashMap::new();
+    let mut output: Vec<Variable> = Vec::new();
+    for instruction in parse(text) {
+        let words = split_words(&instruction.arguments, escape);
+        let pairs: Vec<(String, String)> = match instruction.name.as_str() {
+            // the legacy form, ENV NAME the rest of the line
+            "ENV" if !words.first().map_or(false, |w| w.contains('=')) => {
+                let mut parts = instruction.arguments.splitn(2, char::is_whitespace);
+                let name = parts.next().unwrap_or("").to_string();
+                let value = parts.next().unwrap_or("").trim().to_string();
+                vec![(name, value)]
+            }
+            "ENV" | "ARG" => words
+                .iter()
+                .filter_map(|w| {
+                    let mut parts = w.splitn(2, '=');
+                    Some((parts.next()?.to_string(), parts.next()?.to_string()))
+                })
+                .collect(),
+            _ => continue,
+        };
+        for (name, value) in pairs {
+            if name.is_empty() {
+                continue;
+            }
+            let value = interpolate(&value, &scope);
+            scope.insert(name.clone(), value.clone());
+            output.push(Variable {
+                instruction: instruction.name.clone(),
+                name,
+                value,
+                line: instruction.line,
+            });
+        }
+    }
+    output
+}
+
+/// Turns the `environment` and `build.args` lists of each Compose service (`- KEY=value`) into
+/// maps, and expands the defaults of `${VAR:-default}` and `${VAR-default}` in every string, so
+/// that each variable is scanned under its own name
+pub fn normalize_compose(document: &mut Value) {
+    if let Some(services) = document.get_mut("services").and_then(Value::as_object_mut) {
+        for service in services.values_mut() {
+            if let Some(environment) = service.get_mut("environment") {
+                list_to_map(environment);
+            }
+            if let Some(args) = service.get_mut("build").and_then(|b| b.get_mut("args")) {
+                list_to_map(args);
+            }
+        }
+    }
+    expand_defaults(document);
+}
+
+fn list_to_map(value: &mut Value) {
+    let list = match value.as_array() {
+        Some(l) => l,
+        None => return,
+    };
+    let mut map = Map::new();
+    for entry in list.iter().filter_map(Value::as_str) {
+        let mut parts = entry.splitn(2, '=');
+        let key = parts.next().unwrap_or("").to_string();
+        match parts.next() {
+            Some(v) => map.insert(key, Value::String(v.to_string())),
+            // a name alone passes the variable through from the shell
+            None => map.insert(key, Value::Null),
+        };
+    }
+    *value = Value::Object(map);
+}
+
+fn expand_defaults(value: &mut Value) {
+    match value {
+        Value::String(s) => *s = interpolate(s, &HashMap::new()),
+        Value::Array(array) => array.iter_mut().for_each(expand_defaults),
+        Value::Object(map) => map.values_mut().for_each(expand_defaults),
+        _ => {}
+    }
+}
+
+/// Substitutes `$NAME`, `${NAME}`, `${NAME:-default}` and `${NAME-default}` with the value of
+/// `NAME` in `scope`, or its default. References to unknown variables without a default are kept
+/// as they are, so they still read as placeholders.
+pub fn interpolate(value: &str, scope: &HashMap<String, String>) -> String {
+    let mut output = String::with_capacity(value.len());
+    let mut rest = value;
+    while let Some(i) = rest.find('$') {
+        output.push_str(&rest[..i]);
+        rest = &rest[i..];
+        let (reference, length) = if rest.starts_with("${") {
+            match rest.find('}') {
+                Some(end) => (&rest[2..end], end + 1),
+                None => break,
+            }
+        } else {
+            let end = rest[1..]
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                .map_or(rest.len(), |e| e + 1);
+            (&rest[1..end], end)
+        };
+        let (name, default) = match reference.find(|c| c == ':' || c == '-') {
+            Some(d) => {
+                let operator = reference[d..].trim_start_matches(':');
+                let default = if operator.starts_with('-') {
+                    Some(operator[1..].to_string())
+                } else {
+                    None
+                };
+                (&reference[..d], default)
+            }
+            None => (reference, None),
+        };
+        match (scope.get(name), default) {
+            (Some(v), _) => output.push_str(v),
+            (None, Some(d)) => output.push_str(&d),
+            _ if name.is_empty() => output.push('$'),
+            _ => output.push_str(&rest[..length]),
+        }
+        rest = &rest[length.max(1)..];
+    }
+    output.push_str(rest);
+    output
+}
+
+/// Reads the `# escape=` parser directive, which must come before any instruction or comment,
+/// returning the default `\` without one
+fn escape_character(text: &str) -> char {
+    for line in text.lines() {
+        let line = line.trim();
+        if !line.starts_with('#') {
+            break;
+        }
+        let directive: String = line[1..]
+            .chars()
+            .filter(|c| !c.is_whitespace())
+            .collect::<String>()
+            .to_ascii_lowercase();
+        if directive.starts_with("escape=") {
+            return directive["escape=".len()..].chars().next().unwrap_or('\\');
+        }
+        if !directive.contains('=') {
+            // an ordinary comment ends the directives
+            break;
+        }
+    }
+    '\\'
+}
+
+/// Splits the arguments of an instruction into words on unquoted whitespace, removing quotes and
+/// escape characters
+fn split_words(arguments: &str, escape: char) -> Vec<String> {
+    let mut words: Vec<String> = Vec::new();
+    let mut word = String::new();
+    let mut in_word = false;
+    let mut quote: Option<char> = None;
+    let mut chars = arguments.chars();
+    while let Some(c) = chars.next() {
+        match (quote, c) {
+            (Some('\''), '\'') | (Some('"'), '"') => quote = None,
+            (Some('"'), c) | (None, c) if c == escape => {
+                if let Some(next) = chars.next() {
+                    word.push(next);
+                }
+                in_word = true;
+            }
+            (Some(_), c) => word.push(c),
+            (None, '\'') | (None, '"') => {
+                quote = Some(c);
+                in_word = true;
+            }
+            (None, c) if c.is_whitespace() => {
+                if in_word {
+                    words.push(word.clone());
+                    word.clear();
+                    in_word = false;
+                }
+            }
+            (None, c) => {
+                word.push(c);
+                in_word = true;
+            }
+        }
+    }
+    if in_word {
+        words.push(word);
+    }
+    words
+}
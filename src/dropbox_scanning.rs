@@ -0,0 +1,444 @@
+//! Collection of tools for scanning Dropbox for secrets.
+//!
+//! `DropboxScanner` acts as a wrapper around a `SecretScanner` object and talks to the
+//! [Dropbox API](https://www.dropbox.com/developers/documentation/http/documentation) with an
+//! access token. It lists the files in a folder and all of its subfolders, then downloads and
+//! scans them on several threads, the same way `sharepoint_scanning` scans document libraries:
+//! office documents are split into their parts, config files are scanned value by value with
+//! `--structured` and large files are split into chunks.
+//!
+//! The token is either the token of an app with the `files.content.read` scope, which reads the
+//! Dropbox of the user who authorized it, or the token of a team app with the
+//! `members.read` and `team_data.member` scopes. A team token acts as each member of the team in
+//! turn, by selecting them with `DropboxConfig::as_member`.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use rusty_hogs::dropbox_scanning::{DropboxConfig, DropboxScanner};
+//! use rusty_hogs::file_filter::FileFilter;
+//!
+//! let ds = DropboxScanner::new();
+//! let team_config = DropboxConfig::new("my-team-token");
+//! for member in ds.list_members(&team_config).unwrap() {
+//!     let config = team_config.as_member(&member.id);
+//!     let files = ds.list_folder(&config, "", &FileFilter::default()).unwrap();
+//!     for (file, result) in ds.scan_files(&config, &member.email, files, 4, false) {
+//!         println!("{}: {:?}", file.path, result.map(|f| f.len()));
+//!     }
+//! }
+//! ```
+
+use crate::chunked_scanning;
+use crate::decoding::{self, ZIP_MAGIC};
+use crate::file_filter::FileFilter;
+use crate::network;
+use crate::progress;
+use crate::retry;
+use crate::shutdown;
+use crate::structured_scanning::{self, StructuredFormat};
+use crate::{SecretScanner, Span};
+use log::{self, debug, error, info};
+use reqwest::blocking::Response;
+use serde_derive::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use simple_error::{try_with, SimpleError};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+
+/// The endpoint of the Dropbox API calls that take and return JSON
+pub const DROPBOX_API_URL: &str = "https://api.dropboxapi.com/2";
+
+/// The endpoint of the Dropbox API calls that download content
+pub const DROPBOX_CONTENT_URL: &str = "https://content.dropboxapi.com/2";
+
+/// The most entries Dropbox returns per page of a folder listing
+const PAGE_SIZE: u64 = 2000;
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Hash, Clone, Default)]
+/// `serde_json` object that represents a single found secret - finding
+pub struct DropboxFinding {
+    pub diff: String,
+    #[serde(rename = "stringsFound")]
+    pub strings_found: Vec<String>,
+    /// The path of the file, as the user sees it
+    pub path: String,
+    /// The Dropbox ID of the file
+    pub id: String,
+    /// The email address of the team member whose Dropbox the file is in, empty for a user token
+    #[serde(default)]
+    pub member: String,
+    /// When the file was last uploaded to Dropbox (RFC 3339)
+    pub date: String,
+    pub reason: String,
+    #[serde(rename = "keyPath", default, skip_serializing_if = "Option::is_none")]
+    pub key_path: Option<String>,
+    /// The sheet, slide or other part of an office document that the secret was found in
+    #[serde(
+        rename = "documentPart",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub document_part: Option<String>,
+    /// Where each of `strings_found` is in the file, see `Span` (none with a `key_path`)
+    #[serde(default)]
+    pub spans: Vec<Span>,
+}
+
+/// The Dropbox API to talk to and the token to authenticate with
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+pub struct DropboxConfig {
+    /// The base URL of the JSON calls, `DROPBOX_API_URL` unless testing against a proxy
+    pub url: String,
+    /// The base URL of the download calls, `DROPBOX_CONTENT_URL` unless testing against a proxy
+    pub content_url: String,
+    /// An app or team access token
+    pub token: String,
+    /// The team member a team token acts as
+    pub member_id: Option<String>,
+}
+
+/// A file in Dropbox. Only the fields we need are included.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Hash, Clone, Default)]
+pub struct DropboxFile {
+    pub id: String,
+    /// The path of the file, as the user sees it
+    pub path: String,
+    /// The size in bytes
+    pub size: u64,
+    /// When the file was last uploaded to Dropbox (RFC 3339)
+    pub server_modified: String,
+}
+
+/// An active member of a team. Only the fields we need are included.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Hash, Clone, Default)]
+pub struct TeamMember {
+    /// The team member ID
+    pub id: String,
+    pub email: String,
+}
+
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+/// Contains helper functions for performing scans of Dropbox folders
+pub struct DropboxScanner {
+    pub secret_scanner: SecretScanner,
+}
+
+impl DropboxConfig {
+    /// A config for the Dropbox API with `token`
+    pub fn new(token: &str) -> Self {
+        Self {
+            url: DROPBOX_API_URL.to_string(),
+            content_url: DROPBOX_CONTENT_URL.to_string(),
+            token: token.to_string(),
+            member_id: None,
+        }
+    }
+
+    /// A copy of a team config that acts as the team member `member_id`
+    pub fn as_member(&self, member_id: &str) -> Self {
+        Self {
+            member_id: Some(member_id.to_string()),
+            ..self.clone()
+        }
+    }
+}
+
+/// Acts as a wrapper around a `SecretScanner` object to provide helper functions for performing
+/// scanning against Dropbox. Relies on the [reqwest](https://docs.rs/reqwest/) library for HTTP
+/// access.
+impl DropboxScanner {
+    /// Initialize the SecretScanner object first using the SecretScannerBuilder, then provide
+    /// it to this constructor method.
+    pub fn new_from_scanner(secret_scanner: SecretScanner) -> Self {
+        Self { secret_scanner }
+    }
+
+    pub fn new() -> Self {
+        Self {
+            secret_scanner: SecretScanner::default(),
+        }
+    }
+
+    /// Returns the active members of the team, with a team token
+    pub fn list_members(&self, config: &DropboxConfig) -> Result<Vec<TeamMember>, SimpleError> {
+        let mut members: Vec<TeamMember> = Vec::new();
+        let mut page = post_json(config, "team/members/list_v2", &json!({ "limit": 1000 }))?;
+        loop {
+            for member in page["members"].as_array().into_iter().flatten() {
+                let profile = &member["profile"];
+                if profile["status"][".tag"] != "active" {
+                    continue;
+                }
+                members.push(TeamMember {
+                    id: profile["team_member_id"].as_str().unwrap_or("").to_string(),
+                    email: profile["email"].as_str().unwrap_or("").to_string(),
+                });
+            }
+            if page["has_more"] != true {
+                return Ok(members);
+            }
+            page = post_json(
+                config,
+                "team/members/list/continue_v2",
+                &json!({ "cursor": page["cursor"] }),
+            )?;
+        }
+    }
+
+    /// Lists the files that `filter` selects in the folder at `path` ("" for the root of the
+    /// Dropbox) and all of its subfolders. Files that can only be exported, such as Paper docs,
+    /// are skipped.
+    pub fn list_folder(
+        &self,
+        config: &DropboxConfig,
+        path: &str,
+        filter: &FileFilter,
+    ) -> Result<Vec<DropboxFile>, SimpleError> {
+        let mut files: Vec<DropboxFile> = Vec::new();
+        let mut page = post_json(
+            config,
+            "files/list_folder",
+            &json!({ "path": path, "recursive": true, "limit": PAGE_SIZE }),
+        )?;
+        loop {
+            for entry in page["entries"].as_array().into_iter().flatten() {
+                if entry[".tag"] != "file" {
+                    continue;
+                }
+                let file = DropboxFile {
+                    id: entry["id"].as_str().unwrap_or("").to_string(),
+                    path: entry["path_display"].as_str().unwrap_or("").to_string(),
+                    size: entry["size"].as_u64().unwrap_or(0),
+                    server_modified: entry["server_modified"].as_str().unwrap_or("").to_string(),
+                };
+                if entry["is_downloadable"] == false {
+                    debug!("Skipping {}, which can't be downloaded", file.path);
+                } else if filter.matches(&file.path, file.size) {
+                    files.push(file);
+                }
+            }
+            if page["has_more"] != true {
+                break;
+            }
+            page = post_json(
+                config,
+                "files/list_folder/continue",
+                &json!({ "cursor": page["cursor"] }),
+            )?;
+        }
+        info!(
+            "Found {} files in {}",
+            files.len(),
+            if path.is_empty() { "/" } else { path }
+        );
+        Ok(files)
+    }
+
+    /// Downloads a file and returns a list of findings. `member` is the email address of the team
+    /// member the config acts as, if any.
+    pub fn scan_file(
+        &self,
+        config: &DropboxConfig,
+        member: &str,
+        file: &DropboxFile,
+        scan_entropy: bool,
+    ) -> Result<Vec<DropboxFinding>, SimpleError> {
+        let template = DropboxFinding {
+            path: file.path.clone(),
+            id: file.id.clone(),
+            member: member.to_string(),
+            date: file.server_modified.clone(),
+            ..Default::default()
+        };
+        let data = try_with!(
+            download(config, &file.id)?.bytes(),
+            "Failed to download {}",
+            file.path
+        );
+
+        // Office documents are split into their sheets, slides and other parts
+        let parts: Vec<(Option<String>, Vec<u8>)> = if data.starts_with(ZIP_MAGIC) {
+            match decoding::extract_ooxml(&data) {
+                Ok(p) => p
+                    .into_iter()
+                    .map(|(name, data)| (Some(name), data))
+                    .collect(),
+                Err(e) => {
+                    debug!("{}, scanning {} as-is", e, file.path);
+                    vec![(None, data.to_vec())]
+                }
+            }
+        } else {
+            vec![(None, data.to_vec())]
+        };
+
+        let mut size = 0;
+        let mut findings: Vec<DropboxFinding> = Vec::new();
+        for (document_part, data) in parts {
+            let data = self.secret_scanner.preprocess_owned(data);
+            size += data.len();
+            let template = DropboxFinding {
+                document_part: document_part.clone(),
+                ..template.clone()
+            };
+
+            // Config files are parsed and scanned value by value, falling back to lines on failure
+            if self.secret_scanner.structured && document_part.is_none() {
+                if let Some(format) = StructuredFormat::detect(&file.path, &data) {
+                    match structured_scanning::scan_document(
+                        &self.secret_scanner,
+                        &data,
+                        format,
+                        scan_entropy,
+                    ) {
+                        Ok(matches) => {
+                            findings.extend(matches.into_iter().map(|m| DropboxFinding {
+                                diff: m.diff,
+                                strings_found: m.strings_found,
+                                reason: m.reason,
+                                key_path: Some(m.key_path),
+                                ..template.clone()
+                            }));
+                            continue;
+                        }
+                        Err(e) => debug!("{}, scanning {} line by line", e, file.path),
+                    }
+                }
+            }
+
+            // Large files are split into chunks that are scanned in parallel
+            let options = self.secret_scanner.chunk_options;
+            findings.extend(
+                chunked_scanning::scan_lines(&self.secret_scanner, data, options, scan_entropy)
+                    .into_iter()
+                    .map(|m| DropboxFinding {
+                        diff: m.diff,
+                        strings_found: m.strings_found,
+                        reason: m.reason,
+                        spans: m.spans,
+                        ..template.clone()
+                    }),
+            );
+        }
+        progress::scanned(size, findings.len());
+        Ok(findings)
+    }
+
+    /// Scans each file with `scan_file` using `threads` worker threads, returning the result for
+    /// each file. A panic while scanning a file is returned as an error for that file.
+    pub fn scan_files(
+        &self,
+        config: &DropboxConfig,
+        member: &str,
+        files: Vec<DropboxFile>,
+        threads: usize,
+        scan_entropy: bool,
+    ) -> Vec<(DropboxFile, Result<Vec<DropboxFinding>, SimpleError>)> {
+        progress::add_total(files.len());
+        let queue = Arc::new(Mutex::new(files.into_iter()));
+        let (tx, rx) = mpsc::channel();
+        let mut handles = Vec::new();
+        for _ in 0..threads.max(1) {
+            let queue = Arc::clone(&queue);
+            let tx = tx.clone();
+            let scanner = self.clone();
+            let config = config.clone();
+            let member = member.to_string();
+            handles.push(thread::spawn(move || loop {
+                if shutdown::interrupted() {
+                    break;
+                }
+                let file = match queue.lock().unwrap().next() {
+                    Some(f) => f,
+                    None => break,
+                };
+                let result = panic::catch_unwind(AssertUnwindSafe(|| {
+                    scanner.scan_file(&config, &member, &file, scan_entropy)
+                }))
+                .unwrap_or_else(|_| {
+                    Err(SimpleError::new(format!("Scanning {} panicked", file.path)))
+                });
+                if tx.send((file, result)).is_err() {
+                    break;
+                }
+            }));
+        }
+        drop(tx);
+        let results = rx.iter().collect();
+        for handle in handles {
+            if handle.join().is_err() {
+                error!("A scanning thread exited unexpectedly");
+            }
+        }
+        results
+    }
+}
+
+impl Default for DropboxScanner {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Calls a JSON endpoint of the Dropbox API, waiting and retrying when rate limited, and parses
+/// the response. `endpoint` is relative to the configured API URL.
+fn post_json(config: &DropboxConfig, endpoint: &str, body: &Value) -> Result<Value, SimpleError> {
+    let url = format!("{}/{}", config.url.trim_end_matches('/'), endpoint);
+    debug!("POST {}", url);
+    let mut request = network::client()
+        .post(&url)
+        .bearer_auth(&config.token)
+        .json(body);
+    if let Some(id) = &config.member_id {
+        request = request.header("Dropbox-API-Select-User", id.as_str());
+    }
+    let response = check(
+        try_with!(retry::send(request), "Dropbox request failed"),
+        &url,
+    )?;
+    Ok(try_with!(
+        response.json(),
+        "Failed to parse Dropbox response"
+    ))
+}
+
+/// Downloads the content of the file with the Dropbox ID `id`
+fn download(config: &DropboxConfig, id: &str) -> Result<Response, SimpleError> {
+    let url = format!(
+        "{}/files/download",
+        config.content_url.trim_end_matches('/')
+    );
+    debug!("POST {} ({})", url, id);
+    let mut request = network::client()
+        .post(&url)
+        .bearer_auth(&config.token)
+        .header("Dropbox-API-Arg", json!({ "path": id }).to_string());
+    if let Some(member_id) = &config.member_id {
+        request = request.header("Dropbox-API-Select-User", member_id.as_str());
+    }
+    check(
+        try_with!(retry::send(request), "Dropbox request failed"),
+        &url,
+    )
+}
+
+/// Turns an unsuccessful response into an error with the summary Dropbox gives
+fn check(response: Response, url: &str) -> Result<Response, SimpleError> {
+    let status = response.status();
+    if status.is_success() {
+        return Ok(response);
+    }
+    let body = response.text().unwrap_or_default();
+    let summary = match serde_json::from_str::<Value>(&body) {
+        Ok(error) => error["error_summary"].as_str().unwrap_or("").to_string(),
+        Err(_) => body,
+    };
+    Err(SimpleError::new(format!(
+        "Dropbox returned {} for {}: {}",
+        status,
+        url,
+        summary.trim()
+    )))
+}
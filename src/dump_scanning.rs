@@ -0,0 +1,317 @@
+//! Collection of tools for scanning memory images and core dumps for secrets.
+//!
+//! Core dumps, minidumps and memory images are raw bytes without lines, so `DumpScanner` doesn't
+//! split them into lines. It extracts the printable strings of at least `min_length` characters,
+//! like `strings`, both ASCII and UTF-16LE (the strings of Windows processes), and runs the rules
+//! over each string. Findings report the byte offset of the first match in the file instead of a
+//! line number.
+//!
+//! Files are read a block at a time, so dumps of any size can be scanned. Strings longer than
+//! `MAX_STRING_LENGTH` characters are split.
+//!
+//! # Examples
+//!
+//! ```
+//! use rusty_hogs::dump_scanning::{DumpScanner, StringExtractor};
+//!
+//! let dump = b"\x00\x01arst@example.com\x00\x7fk\x00e\x00y\x00=\x00v\x00a\x00l\x00u\x00e\x00\x00";
+//! let mut strings = Vec::new();
+//! let mut extractor = StringExtractor::new(8);
+//! extractor.push(dump, |offset, encoding, string| {
+//!     strings.push((offset, encoding, String::from_utf8_lossy(string).into_owned()))
+//! });
+//! extractor.finish(|offset, encoding, string| {
+//!     strings.push((offset, encoding, String::from_utf8_lossy(string).into_owned()))
+//! });
+//! assert_eq!(strings[0], (2, "ascii", String::from("arst@example.com")));
+//! assert_eq!(strings[1], (20, "utf16", String::from("key=value")));
+//!
+//! let ds = DumpScanner::new();
+//! let findings = ds.scan_bytes("core.1234", dump, false);
+//! assert_eq!(findings.len(), 1);
+//! let finding = findings.iter().next().unwrap();
+//! assert_eq!(finding.offset, 2);
+//! assert_eq!(finding.reason, "Email address");
+//! ```
+
+use crate::progress;
+use crate::SecretScanner;
+use encoding::all::ASCII;
+use encoding::{DecoderTrap, Encoding};
+use log::{self, info};
+use serde_derive::{Deserialize, Serialize};
+use simple_error::{try_with, SimpleError};
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+/// The shortest string extracted by default, as `--min-length`
+pub const DEFAULT_MIN_LENGTH: usize = 8;
+
+/// Longer strings are split, so that one finding doesn't hold megabytes of memory
+pub const MAX_STRING_LENGTH: usize = 4096;
+
+/// The size of the blocks a dump is read in
+const BLOCK_SIZE: usize = 1024 * 1024;
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Hash, Clone, Default)]
+/// `serde_json` object that represents a single found secret - finding
+pub struct DumpFinding {
+    pub diff: String,
+    #[serde(rename = "stringsFound")]
+    pub strings_found: Vec<String>,
+    pub path: String,
+    /// The byte offset in the file of the first match
+    pub offset: u64,
+    /// `ascii` or `utf16`, the encoding of the string the secret was found in
+    pub encoding: String,
+    pub reason: String,
+}
+
+/// A string being extracted, in one of the encodings
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Default)]
+struct Run {
+    start: u64,
+    string: Vec<u8>,
+}
+
+/// Extracts the printable strings of a stream of bytes, ASCII and UTF-16LE at either alignment.
+/// Bytes are given with `push` as they are read, strings that continue over the end of a block
+/// are carried over to the next one. UTF-16 strings are returned as ASCII, since only ASCII
+/// characters are extracted.
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+pub struct StringExtractor {
+    min_length: usize,
+    offset: u64,
+    ascii: Run,
+    /// UTF-16 strings starting at even and odd offsets
+    utf16: [Run; 2],
+    /// The low byte of the current UTF-16 character at each alignment
+    low_byte: [Option<u8>; 2],
+}
+
+impl StringExtractor {
+    pub fn new(min_length: usize) -> Self {
+        Self {
+            min_length: min_length.max(1),
+            offset: 0,
+            ascii: Run::default(),
+            utf16: [Run::default(), Run::default()],
+            low_byte: [None, None],
+        }
+    }
+
+    /// Extracts the strings of the next bytes of the stream, calling `found` with the offset,
+    /// encoding and bytes of each string that ended
+    pub fn push<F: FnMut(u64, &'static str, &[u8])>(&mut self, data: &[u8], mut found: F) {
+        for byte in data {
+            let offset = self.offset;
+            let printable = is_printable(*byte);
+
+            if printable {
+                if self.ascii.string.is_empty() {
+                    self.ascii.start = offset;
+                }
+                self.ascii.string.push(*byte);
+                if self.ascii.string.len() >= MAX_STRING_LENGTH {
+                    emit(&mut self.ascii, self.min_length, "ascii", &mut found);
+                }
+            } else {
+                emit(&mut self.ascii, self.min_length, "ascii", &mut found);
+            }
+
+            let alignment = (offset % 2) as usize;
+            // the byte starts a character at its alignment and ends one at the other alignment
+            let other = 1 - alignment;
+            match self.low_byte[other].take() {
+                Some(low) if *byte == 0 => {
+                    let run = &mut self.utf16[other];
+                    if run.string.is_empty() {
+                        run.start = offset - 1;
+                    }
+                    run.string.push(low);
+                    if run.string.len() >= MAX_STRING_LENGTH {
+                        emit(run, self.min_length, "utf16", &mut found);
+                    }
+                }
+                _ => emit(&mut self.utf16[other], self.min_length, "utf16", &mut found),
+            }
+            self.low_byte[alignment] = if printable { Some(*byte) } else { None };
+            if !printable {
+                emit(
+                    &mut self.utf16[alignment],
+                    self.min_length,
+                    "utf16",
+                    &mut found,
+                );
+            }
+            self.offset += 1;
+        }
+    }
+
+    /// Returns the strings still being extracted at the end of the stream
+    pub fn finish<F: FnMut(u64, &'static str, &[u8])>(&mut self, mut found: F) {
+        emit(&mut self.ascii, self.min_length, "ascii", &mut found);
+        for run in self.utf16.iter_mut() {
+            emit(run, self.min_length, "utf16", &mut found);
+        }
+        self.low_byte = [None, None];
+    }
+}
+
+/// Passes `run` to `found` if it's long enough, and starts a new one
+fn emit<F: FnMut(u64, &'static str, &[u8])>(
+    run: &mut Run,
+    min_length: usize,
+    encoding: &'static str,
+    found: &mut F,
+) {
+    if run.string.len() >= min_length {
+        found(run.start, encoding, &run.string);
+    }
+    run.string.clear();
+}
+
+/// Printable ASCII and tabs, the characters `strings` extracts
+fn is_printable(byte: u8) -> bool {
+    byte.is_ascii_graphic() || byte == b' ' || byte == b'\t'
+}
+
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+/// Contains helper functions for performing scans of memory images and core dumps
+pub struct DumpScanner {
+    pub secret_scanner: SecretScanner,
+    /// The shortest string scanned
+    pub min_length: usize,
+}
+
+/// Acts as a wrapper around a `SecretScanner` object to provide helper functions for performing
+/// scanning against memory images and core dumps.
+impl DumpScanner {
+    /// Initialize the SecretScanner object first using the SecretScannerBuilder, then provide
+    /// it to this constructor method.
+    pub fn new_from_scanner(secret_scanner: SecretScanner) -> Self {
+        Self {
+            secret_scanner,
+            min_length: DEFAULT_MIN_LENGTH,
+        }
+    }
+
+    pub fn new() -> Self {
+        Self::new_from_scanner(SecretScanner::default())
+    }
+
+    /// Sets the shortest string scanned (`DEFAULT_MIN_LENGTH` by default)
+    pub fn set_min_length(mut self, min_length: usize) -> Self {
+        self.min_length = min_length;
+        self
+    }
+
+    /// Scans the file at `path`, a block at a time. Returns a HashSet of findings.
+    pub fn scan_file(
+        &self,
+        path: &Path,
+        scan_entropy: bool,
+    ) -> Result<HashSet<DumpFinding>, SimpleError> {
+        let name = path.to_string_lossy();
+        let mut file = try_with!(File::open(path), "Failed to open {}", name);
+        let mut findings: HashSet<DumpFinding> = HashSet::new();
+        let mut extractor = StringExtractor::new(self.min_length);
+        let mut block = vec![0u8; BLOCK_SIZE];
+        let mut size: u64 = 0;
+        loop {
+            let read = try_with!(file.read(&mut block), "Failed to read {}", name);
+            if read == 0 {
+                break;
+            }
+            let found = findings.len();
+            extractor.push(&block[..read], |offset, encoding, string| {
+                self.scan_string(&name, offset, encoding, string, scan_entropy, &mut findings)
+            });
+            progress::scanned(read, findings.len() - found);
+            size += read as u64;
+        }
+        extractor.finish(|offset, encoding, string| {
+            self.scan_string(&name, offset, encoding, string, scan_entropy, &mut findings)
+        });
+        info!("Scanned {} bytes of {}", size, name);
+        Ok(findings)
+    }
+
+    /// Scans a dump already in memory, read from `path`. Returns a HashSet of findings.
+    pub fn scan_bytes(&self, path: &str, data: &[u8], scan_entropy: bool) -> HashSet<DumpFinding> {
+        let mut findings: HashSet<DumpFinding> = HashSet::new();
+        let mut extractor = StringExtractor::new(self.min_length);
+        extractor.push(data, |offset, encoding, string| {
+            self.scan_string(path, offset, encoding, string, scan_entropy, &mut findings)
+        });
+        extractor.finish(|offset, encoding, string| {
+            self.scan_string(path, offset, encoding, string, scan_entropy, &mut findings)
+        });
+        findings
+    }
+
+    /// Scans one extracted string, found at `offset`, and adds any findings
+    fn scan_string(
+        &self,
+        path: &str,
+        offset: u64,
+        encoding: &str,
+        string: &[u8],
+        scan_entropy: bool,
+        findings: &mut HashSet<DumpFinding>,
+    ) {
+        // the characters of UTF-16 strings take two bytes
+        let width: u64 = if encoding == "utf16" { 2 } else { 1 };
+        let diff = ASCII
+            .decode(string, DecoderTrap::Ignore)
+            .unwrap_or_else(|_| "<STRING DECODE ERROR>".parse().unwrap());
+        let matches_map = self.secret_scanner.matches(string);
+        for (reason, match_iterator) in matches_map {
+            let mut secrets: Vec<String> = Vec::new();
+            let mut first: Option<usize> = None;
+            for matchobj in match_iterator {
+                first = first.or(Some(matchobj.start()));
+                secrets.push(
+                    ASCII
+                        .decode(
+                            &string[matchobj.start()..matchobj.end()],
+                            DecoderTrap::Ignore,
+                        )
+                        .unwrap_or_else(|_| "<STRING DECODE ERROR>".parse().unwrap()),
+                );
+            }
+            if !secrets.is_empty() {
+                findings.insert(DumpFinding {
+                    diff: diff.clone(),
+                    strings_found: secrets,
+                    path: path.to_string(),
+                    offset: offset + first.unwrap_or(0) as u64 * width,
+                    encoding: encoding.to_string(),
+                    reason: reason.clone(),
+                });
+            }
+        }
+
+        if scan_entropy {
+            let ef = self.secret_scanner.entropy_matches(string);
+            if !ef.is_empty() {
+                findings.insert(DumpFinding {
+                    diff,
+                    strings_found: ef,
+                    path: path.to_string(),
+                    offset,
+                    encoding: encoding.to_string(),
+                    reason: "Entropy".to_string(),
+                });
+            }
+        }
+    }
+}
+
+impl Default for DumpScanner {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,310 @@
+//! Collection of tools for scanning the items of DynamoDB tables for secrets.
+//!
+//! `DynamoScanner` acts as a wrapper around a `SecretScanner` object and talks to the
+//! [DynamoDB API](https://docs.aws.amazon.com/amazondynamodb/latest/APIReference/Welcome.html) of a
+//! region. It reads every item of a table with a
+//! [parallel Scan](https://docs.aws.amazon.com/amazondynamodb/latest/developerguide/Scan.html#Scan.ParallelScan),
+//! splitting the table into segments that are read on a thread each. Items are converted from
+//! DynamoDB's typed JSON into plain JSON (binary attributes are base64-decoded) and scanned like
+//! structured documents (see `structured_scanning`), so each finding names the attribute it was
+//! found in, e.g. `settings.smtp.password` or `tokens[2]`, along with the primary key of its item.
+//!
+//! Scans read the whole table, and consume read capacity accordingly. Requests are signed with
+//! the credentials of `aws_scanning`, which need the `dynamodb:DescribeTable` and
+//! `dynamodb:Scan` permissions.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use rusty_hogs::aws_scanning;
+//! use rusty_hogs::dynamodb_scanning::{DynamoConfig, DynamoScanner};
+//!
+//! let ds = DynamoScanner::new();
+//! let config = DynamoConfig {
+//!     credentials: aws_scanning::credentials_from_args(None, None, None).unwrap(),
+//!     region: "us-east-1".to_string(),
+//! };
+//! let findings = ds.scan_table(&config, "users", 4, false).unwrap();
+//! ds.secret_scanner.output_findings(&findings);
+//! ```
+
+use crate::aws_scanning;
+use crate::progress;
+use crate::shutdown;
+use crate::structured_scanning::{self, StructuredFormat};
+use crate::SecretScanner;
+use log::{self, debug, info};
+use s3::credentials::Credentials;
+use serde_derive::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use simple_error::SimpleError;
+use std::collections::HashSet;
+use std::thread;
+
+/// The version of the DynamoDB API, and of the JSON protocol it speaks
+const API_TARGET: &str = "DynamoDB_20120810";
+const JSON_VERSION: &str = "1.0";
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Hash, Clone, Default)]
+/// `serde_json` object that represents a single found secret - finding
+pub struct DynamoFinding {
+    pub diff: String,
+    #[serde(rename = "stringsFound")]
+    pub strings_found: Vec<String>,
+    pub region: String,
+    pub table: String,
+    /// The primary key of the item, as a JSON object
+    pub key: String,
+    /// The path of the attribute within the item
+    #[serde(rename = "keyPath")]
+    pub key_path: String,
+    pub reason: String,
+}
+
+/// The region to scan and the credentials to sign requests with
+#[derive(Debug, Clone)]
+pub struct DynamoConfig {
+    pub credentials: Credentials,
+    pub region: String,
+}
+
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+/// Contains helper functions for performing scans of DynamoDB tables
+pub struct DynamoScanner {
+    pub secret_scanner: SecretScanner,
+}
+
+/// Acts as a wrapper around a `SecretScanner` object to provide helper functions for performing
+/// scanning against DynamoDB. Relies on the [reqwest](https://docs.rs/reqwest/) library for HTTP
+/// access.
+impl DynamoScanner {
+    /// Initialize the SecretScanner object first using the SecretScannerBuilder, then provide
+    /// it to this constructor method.
+    pub fn new_from_scanner(secret_scanner: SecretScanner) -> Self {
+        Self { secret_scanner }
+    }
+
+    pub fn new() -> Self {
+        Self {
+            secret_scanner: SecretScanner::default(),
+        }
+    }
+
+    /// Returns the names of the attributes of the primary key of `table`
+    pub fn key_attributes(
+        &self,
+        config: &DynamoConfig,
+        table: &str,
+    ) -> Result<Vec<String>, SimpleError> {
+        let description = call(config, "DescribeTable", &json!({ "TableName": table }))?;
+        Ok(description["Table"]["KeySchema"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|k| k["AttributeName"].as_str())
+            .map(String::from)
+            .collect())
+    }
+
+    /// Scans every item of `table`, reading it in `segments` segments in parallel. Returns a
+    /// HashSet of findings, or the first error any segment hit.
+    pub fn scan_table(
+        &self,
+        config: &DynamoConfig,
+        table: &str,
+        segments: usize,
+        scan_entropy: bool,
+    ) -> Result<HashSet<DynamoFinding>, SimpleError> {
+        let key_attributes = self.key_attributes(config, table)?;
+        let segments = segments.max(1);
+        info!("Scanning table {} in {} segments", table, segments);
+        let mut handles = Vec::new();
+        for segment in 0..segments {
+            let scanner = self.clone();
+            let config = config.clone();
+            let table = table.to_string();
+            let key_attributes = key_attributes.clone();
+            handles.push(thread::spawn(move || {
+                scanner.scan_segment(
+                    &config,
+                    &table,
+                    &key_attributes,
+                    (segment, segments),
+                    scan_entropy,
+                )
+            }));
+        }
+        let mut findings: HashSet<DynamoFinding> = HashSet::new();
+        for handle in handles {
+            match handle.join() {
+                Ok(result) => findings.extend(result?),
+                Err(_) => {
+                    return Err(SimpleError::new(format!(
+                        "A thread scanning {} panicked",
+                        table
+                    )))
+                }
+            }
+        }
+        Ok(findings)
+    }
+
+    /// Scans the items of one segment of `table`, a page at a time. `segment` is the number of
+    /// the segment and the total number of segments.
+    pub fn scan_segment(
+        &self,
+        config: &DynamoConfig,
+        table: &str,
+        key_attributes: &[String],
+        segment: (usize, usize),
+        scan_entropy: bool,
+    ) -> Result<HashSet<DynamoFinding>, SimpleError> {
+        let mut findings: HashSet<DynamoFinding> = HashSet::new();
+        let mut start_key: Option<Value> = None;
+        let mut scanned = 0;
+        loop {
+            if shutdown::interrupted() {
+                break;
+            }
+            let mut body = json!({
+                "TableName": table,
+                "Segment": segment.0,
+                "TotalSegments": segment.1,
+            });
+            if let Some(k) = start_key {
+                body["ExclusiveStartKey"] = k;
+            }
+            let page = call(config, "Scan", &body)?;
+            for item in page["Items"].as_array().into_iter().flatten() {
+                let item = unmarshal(&json!({ "M": item }));
+                let template = DynamoFinding {
+                    region: config.region.clone(),
+                    table: table.to_string(),
+                    key: item_key(&item, key_attributes),
+                    ..Default::default()
+                };
+                self.scan_item(&item, &template, scan_entropy, &mut findings)?;
+                scanned += 1;
+            }
+            start_key = match &page["LastEvaluatedKey"] {
+                Value::Null => None,
+                k => Some(k.clone()),
+            };
+            if start_key.is_none() {
+                break;
+            }
+        }
+        debug!(
+            "Scanned {} items of segment {} of {}",
+            scanned, segment.0, table
+        );
+        Ok(findings)
+    }
+
+    /// Scans each attribute of `item`, already converted to plain JSON, and adds any findings,
+    /// based on `template`
+    fn scan_item(
+        &self,
+        item: &Value,
+        template: &DynamoFinding,
+        scan_entropy: bool,
+        findings: &mut HashSet<DynamoFinding>,
+    ) -> Result<(), SimpleError> {
+        let data = item.to_string();
+        let matches = structured_scanning::scan_document(
+            &self.secret_scanner,
+            data.as_bytes(),
+            StructuredFormat::Json,
+            scan_entropy,
+        )?;
+        progress::scanned(data.len(), matches.len());
+        for m in matches {
+            findings.insert(DynamoFinding {
+                diff: m.diff,
+                strings_found: m.strings_found,
+                key_path: m.key_path,
+                reason: m.reason,
+                ..template.clone()
+            });
+        }
+        Ok(())
+    }
+}
+
+impl Default for DynamoScanner {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Converts an attribute value from DynamoDB's typed JSON, e.g. `{"M": {"port": {"N": "25"}}}`,
+/// into plain JSON. Numbers are kept as strings so that none lose precision, and binary values
+/// are base64-decoded into (lossy) text.
+///
+/// # Examples
+///
+/// ```
+/// use rusty_hogs::dynamodb_scanning::unmarshal;
+/// use serde_json::json;
+///
+/// let item = json!({"M": {
+///     "id": {"S": "user#1"},
+///     "port": {"N": "25"},
+///     "smtp": {"M": {"password": {"B": "aHVudGVyMg=="}}},
+///     "tags": {"SS": ["mail", "prod"]}
+/// }});
+/// assert_eq!(
+///     unmarshal(&item),
+///     json!({
+///         "id": "user#1",
+///         "port": "25",
+///         "smtp": {"password": "hunter2"},
+///         "tags": ["mail", "prod"]
+///     })
+/// );
+/// ```
+pub fn unmarshal(value: &Value) -> Value {
+    let binary = |v: &Value| -> Value {
+        let data = base64::decode(v.as_str().unwrap_or("")).unwrap_or_default();
+        Value::String(String::from_utf8_lossy(&data).into_owned())
+    };
+    let (data_type, v) = match value.as_object().and_then(|o| o.iter().next()) {
+        Some(typed) => typed,
+        None => return Value::Null,
+    };
+    match data_type.as_str() {
+        "S" | "N" | "BOOL" => v.clone(),
+        "B" => binary(v),
+        "M" => Value::Object(
+            v.as_object()
+                .into_iter()
+                .flatten()
+                .map(|(name, v)| (name.clone(), unmarshal(v)))
+                .collect::<Map<String, Value>>(),
+        ),
+        "L" => Value::Array(v.as_array().into_iter().flatten().map(unmarshal).collect()),
+        "SS" | "NS" => v.clone(),
+        "BS" => Value::Array(v.as_array().into_iter().flatten().map(binary).collect()),
+        _ => Value::Null,
+    }
+}
+
+/// Returns the primary key attributes of `item` as a JSON object
+fn item_key(item: &Value, key_attributes: &[String]) -> String {
+    let key: Map<String, Value> = key_attributes
+        .iter()
+        .map(|name| (name.clone(), item[name].clone()))
+        .collect();
+    Value::Object(key).to_string()
+}
+
+/// Calls a DynamoDB operation, e.g. `Scan`
+fn call(config: &DynamoConfig, operation: &str, body: &Value) -> Result<Value, SimpleError> {
+    aws_scanning::aws_json_call(
+        &config.credentials,
+        "dynamodb",
+        &config.region,
+        (&format!("{}.{}", API_TARGET, operation), JSON_VERSION),
+        body,
+    )
+}
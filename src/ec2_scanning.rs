@@ -0,0 +1,258 @@
+//! Collection of tools for scanning the user data of EC2 instances and launch templates for
+//! secrets.
+//!
+//! `Ec2Scanner` acts as a wrapper around a `SecretScanner` object and talks to the
+//! [EC2 API](https://docs.aws.amazon.com/AWSEC2/latest/APIReference/Welcome.html) of a region.
+//! User data holds the bootstrap scripts and cloud-init configuration an instance runs at launch,
+//! which often embed passwords and keys. It can be read from each instance with
+//! DescribeInstanceAttribute, and from every version of each launch template with
+//! DescribeLaunchTemplateVersions, since older versions keep the user data they were created with.
+//! User data is base64 encoded by the API, and is decoded (and decompressed, if gzipped) before
+//! it is scanned.
+//!
+//! Requests are signed with the credentials of `aws_scanning`, which need the
+//! `ec2:DescribeInstances`, `ec2:DescribeInstanceAttribute`, `ec2:DescribeLaunchTemplates` and
+//! `ec2:DescribeLaunchTemplateVersions` permissions.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use rusty_hogs::aws_scanning;
+//! use rusty_hogs::ec2_scanning::{Ec2Config, Ec2Scanner};
+//!
+//! let es = Ec2Scanner::new();
+//! let config = Ec2Config {
+//!     credentials: aws_scanning::credentials_from_args(None, None, None).unwrap(),
+//!     region: "us-east-1".to_string(),
+//! };
+//! for instance_id in es.list_instances(&config).unwrap() {
+//!     let findings = es.scan_instance(&config, &instance_id, false).unwrap();
+//!     es.secret_scanner.output_findings(&findings);
+//! }
+//! ```
+
+use crate::aws_scanning;
+use crate::shutdown;
+use crate::{Finding, SecretScanner, Span, TextFinding};
+use log::{self, debug, info};
+use regex::Regex;
+use s3::credentials::Credentials;
+use serde_derive::{Deserialize, Serialize};
+use simple_error::{try_with, SimpleError};
+use std::collections::HashSet;
+
+/// The version of the EC2 API
+const API_VERSION: &str = "2016-11-15";
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Hash, Clone, Default)]
+/// `serde_json` object that represents a single found secret - finding
+pub struct Ec2Finding {
+    pub diff: String,
+    #[serde(rename = "stringsFound")]
+    pub strings_found: Vec<String>,
+    pub region: String,
+    /// `instance` or `launch_template`
+    #[serde(rename = "resourceType")]
+    pub resource_type: String,
+    /// The instance ID or launch template ID
+    #[serde(rename = "resourceId")]
+    pub resource_id: String,
+    /// The version of the launch template
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<u64>,
+    pub reason: String,
+    /// Where each of `strings_found` is in the text, see `Span`
+    #[serde(default)]
+    pub spans: Vec<Span>,
+}
+
+impl TextFinding for Ec2Finding {
+    fn with_match(&self, found: Finding) -> Self {
+        Self {
+            diff: found.diff,
+            strings_found: found.strings_found,
+            reason: found.reason,
+            spans: found.spans,
+            ..self.clone()
+        }
+    }
+}
+
+/// The region to scan and the credentials to sign requests with
+#[derive(Debug, Clone)]
+pub struct Ec2Config {
+    pub credentials: Credentials,
+    pub region: String,
+}
+
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+/// Contains helper functions for performing scans of EC2 user data
+pub struct Ec2Scanner {
+    pub secret_scanner: SecretScanner,
+}
+
+/// Acts as a wrapper around a `SecretScanner` object to provide helper functions for performing
+/// scanning against EC2. Relies on the [reqwest](https://docs.rs/reqwest/) library for HTTP
+/// access.
+impl Ec2Scanner {
+    /// Initialize the SecretScanner object first using the SecretScannerBuilder, then provide
+    /// it to this constructor method.
+    pub fn new_from_scanner(secret_scanner: SecretScanner) -> Self {
+        Self { secret_scanner }
+    }
+
+    pub fn new() -> Self {
+        Self {
+            secret_scanner: SecretScanner::default(),
+        }
+    }
+
+    /// Returns the IDs of the instances of the region
+    pub fn list_instances(&self, config: &Ec2Config) -> Result<Vec<String>, SimpleError> {
+        let id_re = Regex::new(r"<instanceId>(.*?)</instanceId>").unwrap();
+        let mut instances: Vec<String> = Vec::new();
+        for page in pages(config, "DescribeInstances", &[("MaxResults", "1000")])? {
+            instances.extend(id_re.captures_iter(&page).map(|c| c[1].to_string()));
+        }
+        info!("Found {} instances in {}", instances.len(), config.region);
+        Ok(instances)
+    }
+
+    /// Returns the IDs of the launch templates of the region
+    pub fn list_launch_templates(&self, config: &Ec2Config) -> Result<Vec<String>, SimpleError> {
+        let id_re = Regex::new(r"<launchTemplateId>(.*?)</launchTemplateId>").unwrap();
+        let mut templates: Vec<String> = Vec::new();
+        for page in pages(config, "DescribeLaunchTemplates", &[("MaxResults", "200")])? {
+            templates.extend(id_re.captures_iter(&page).map(|c| c[1].to_string()));
+        }
+        info!(
+            "Found {} launch templates in {}",
+            templates.len(),
+            config.region
+        );
+        Ok(templates)
+    }
+
+    /// Scans the user data of the instance `instance_id`. Returns a HashSet of findings.
+    pub fn scan_instance(
+        &self,
+        config: &Ec2Config,
+        instance_id: &str,
+        scan_entropy: bool,
+    ) -> Result<HashSet<Ec2Finding>, SimpleError> {
+        let value_re = Regex::new(r"(?s)<userData>\s*<value>(.*?)</value>").unwrap();
+        let mut findings: HashSet<Ec2Finding> = HashSet::new();
+        let response = call(
+            config,
+            &[
+                ("Action", "DescribeInstanceAttribute"),
+                ("InstanceId", instance_id),
+                ("Attribute", "userData"),
+            ],
+        )?;
+        if let Some(c) = value_re.captures(&response) {
+            let template = Ec2Finding {
+                region: config.region.clone(),
+                resource_type: String::from("instance"),
+                resource_id: instance_id.to_string(),
+                ..Default::default()
+            };
+            let user_data = decode_user_data(&c[1], instance_id)?;
+            self.secret_scanner
+                .scan_text(&user_data, &template, scan_entropy, &mut findings);
+        } else {
+            debug!("No user data for {}", instance_id);
+        }
+        Ok(findings)
+    }
+
+    /// Scans the user data of every version of the launch template `template_id`. Returns a
+    /// HashSet of findings.
+    pub fn scan_launch_template(
+        &self,
+        config: &Ec2Config,
+        template_id: &str,
+        scan_entropy: bool,
+    ) -> Result<HashSet<Ec2Finding>, SimpleError> {
+        let user_data_re = Regex::new(r"(?s)<userData>(.*?)</userData>").unwrap();
+        let mut findings: HashSet<Ec2Finding> = HashSet::new();
+        let query = [("LaunchTemplateId", template_id), ("MaxResults", "200")];
+        for page in pages(config, "DescribeLaunchTemplateVersions", &query)? {
+            // each version ends with its versionNumber, after its launchTemplateData
+            let mut versions: Vec<&str> = page.split("</versionNumber>").collect();
+            versions.pop();
+            for version in versions {
+                if shutdown::interrupted() {
+                    break;
+                }
+                let number = version.rsplit("<versionNumber>").next().unwrap_or("");
+                let user_data = match user_data_re.captures(version) {
+                    Some(c) => decode_user_data(&c[1], template_id)?,
+                    None => continue,
+                };
+                let template = Ec2Finding {
+                    region: config.region.clone(),
+                    resource_type: String::from("launch_template"),
+                    resource_id: template_id.to_string(),
+                    version: number.trim().parse().ok(),
+                    ..Default::default()
+                };
+                self.secret_scanner
+                    .scan_text(&user_data, &template, scan_entropy, &mut findings);
+            }
+        }
+        Ok(findings)
+    }
+}
+
+impl Default for Ec2Scanner {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Decodes the base64 user data of `resource`, ignoring the line breaks the API may add
+fn decode_user_data(data: &str, resource: &str) -> Result<Vec<u8>, SimpleError> {
+    let data: String = data.chars().filter(|c| !c.is_whitespace()).collect();
+    Ok(try_with!(
+        base64::decode(&data),
+        "Invalid user data for {}",
+        resource
+    ))
+}
+
+/// Calls an EC2 API action, returning the XML response
+fn call(config: &Ec2Config, query: &[(&str, &str)]) -> Result<String, SimpleError> {
+    let mut query = query.to_vec();
+    query.push(("Version", API_VERSION));
+    let body = aws_scanning::aws_get(&config.credentials, "ec2", &config.region, "/", &query)?;
+    Ok(String::from_utf8_lossy(&body).into_owned())
+}
+
+/// Calls `action` until there is no nextToken, returning each page of the response
+fn pages(
+    config: &Ec2Config,
+    action: &str,
+    query: &[(&str, &str)],
+) -> Result<Vec<String>, SimpleError> {
+    let token_re = Regex::new(r"<nextToken>(.*?)</nextToken>").unwrap();
+    let mut pages: Vec<String> = Vec::new();
+    let mut next_token: Option<String> = None;
+    loop {
+        if shutdown::interrupted() {
+            break;
+        }
+        let mut page_query = vec![("Action", action)];
+        page_query.extend_from_slice(query);
+        if let Some(t) = &next_token {
+            page_query.push(("NextToken", t.as_str()));
+        }
+        let page = call(config, &page_query)?;
+        next_token = token_re.captures(&page).map(|c| c[1].to_string());
+        pages.push(page);
+        if next_token.is_none() {
+            break;
+        }
+    }
+    Ok(pages)
+}
@@ -0,0 +1,174 @@
+//! Entropy modes: how `--entropy` decides that a string looks random enough to be a secret.
+//!
+//! The `default` mode is the original check, applied to each space separated word of 20 or more
+//! characters: base64 words with an entropy above 4.5 bits, and hex words whose decoded bytes have
+//! an entropy above 3 bits. It misses short tokens and those embedded in other text, so the other
+//! modes look at runs of characters of a given charset instead, with the [Shannon
+//! entropy](https://en.wikipedia.org/wiki/Entropy_(information_theory)) in bits per character:
+//! - `base64`: runs of base64 characters (including `-` and `_`), 20 or more long, above 4.2 bits
+//! - `hex`: runs of hex digits, 20 or more long, above 3 bits (4 bits is the maximum)
+//! - `alphanumeric`: runs of letters and digits, 20 or more long, above 3.8 bits
+//! - `normalized`: runs of base64 characters, 16 or more long, whose entropy is at least 85% of the
+//!   maximum for their length and charset (hex, alphanumeric or base64), so that short tokens are
+//!   held to the same standard as long ones
+//!
+//! `--entropy-mode` selects the mode of `--entropy`. A rule can also require its matches to look
+//! random in a given mode with an `"entropy"` field (see the `rules` module), e.g.
+//! `{ "pattern": "(?i)token[=:]\\s*[0-9a-f]{32}", "entropy": "hex" }`: the longest run of the mode's
+//! charset in each match must pass the check for it to be reported.
+//!
+//! # Examples
+//!
+//! ```
+//! use rusty_hogs::entropy::{entropy_findings, is_random, EntropyMode};
+//!
+//! let line = b"token=9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08;";
+//! assert_eq!(entropy_findings(line, EntropyMode::Default).len(), 0);
+//! assert_eq!(entropy_findings(line, EntropyMode::Hex).len(), 1);
+//!
+//! assert!(is_random(b"kX9mQ2vL7pR4tZ8wB3nF", EntropyMode::Normalized));
+//! assert!(!is_random(b"aaaaaaaaaaaaaaaabbbb", EntropyMode::Normalized));
+//! ```
+
+use crate::SecretScanner;
+use simple_error::SimpleError;
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+
+/// How strings are checked for randomness, see the module documentation
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub enum EntropyMode {
+    Default,
+    Base64,
+    Hex,
+    Alphanumeric,
+    Normalized,
+}
+
+impl Default for EntropyMode {
+    fn default() -> Self {
+        EntropyMode::Default
+    }
+}
+
+impl FromStr for EntropyMode {
+    type Err = SimpleError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "default" => Ok(EntropyMode::Default),
+            "base64" => Ok(EntropyMode::Base64),
+            "hex" => Ok(EntropyMode::Hex),
+            "alphanumeric" => Ok(EntropyMode::Alphanumeric),
+            "normalized" => Ok(EntropyMode::Normalized),
+            _ => Err(SimpleError::new(format!(
+                "Unknown entropy mode {:?}, expected default, base64, hex, alphanumeric or normalized",
+                s
+            ))),
+        }
+    }
+}
+
+impl fmt::Display for EntropyMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            EntropyMode::Default => "default",
+            EntropyMode::Base64 => "base64",
+            EntropyMode::Hex => "hex",
+            EntropyMode::Alphanumeric => "alphanumeric",
+            EntropyMode::Normalized => "normalized",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+impl EntropyMode {
+    /// True for the characters of the runs this mode checks
+    fn in_charset(self, b: u8) -> bool {
+        match self {
+            EntropyMode::Hex => b.is_ascii_hexdigit(),
+            EntropyMode::Alphanumeric => b.is_ascii_alphanumeric(),
+            _ => is_base64_char(b),
+        }
+    }
+
+    /// The minimum length of the runs this mode checks
+    fn min_len(self) -> usize {
+        match self {
+            EntropyMode::Normalized => 16,
+            _ => 20,
+        }
+    }
+}
+
+/// The strings of `line` that look random in `mode`
+pub fn entropy_findings(line: &[u8], mode: EntropyMode) -> Vec<String> {
+    if mode == EntropyMode::Default {
+        return SecretScanner::entropy_findings(line);
+    }
+    runs(line, mode)
+        .filter(|run| passes(run, mode))
+        .map(|run| String::from_utf8_lossy(run).into_owned())
+        .collect()
+}
+
+/// True if the longest run of the charset of `mode` in `token` looks random in `mode`. The default
+/// mode checks the whole token as a single word.
+pub fn is_random(token: &[u8], mode: EntropyMode) -> bool {
+    if mode == EntropyMode::Default {
+        return !SecretScanner::entropy_findings(token).is_empty();
+    }
+    match runs(token, mode).max_by_key(|run| run.len()) {
+        Some(run) => passes(run, mode),
+        None => false,
+    }
+}
+
+/// The Shannon entropy of `bytes`, in bits per byte
+pub fn shannon_entropy(bytes: &[u8]) -> f32 {
+    let mut counts: HashMap<u8, usize> = HashMap::new();
+    for &b in bytes {
+        *counts.entry(b).or_insert(0) += 1;
+    }
+    let len = bytes.len() as f32;
+    counts
+        .values()
+        .map(|&count| {
+            let p = count as f32 / len;
+            -p * p.log2()
+        })
+        .sum()
+}
+
+/// The runs of the charset of `mode` in `line` that are long enough to check
+fn runs(line: &[u8], mode: EntropyMode) -> impl Iterator<Item = &[u8]> {
+    line.split(move |&b| !mode.in_charset(b))
+        .filter(move |run| run.len() >= mode.min_len())
+}
+
+/// True if a run of the charset of `mode` passes its entropy threshold
+fn passes(run: &[u8], mode: EntropyMode) -> bool {
+    let entropy = shannon_entropy(run);
+    match mode {
+        EntropyMode::Base64 => entropy > 4.2,
+        EntropyMode::Hex => entropy > 3.0,
+        EntropyMode::Alphanumeric => entropy > 3.8,
+        _ => {
+            let charset_size = if run.iter().all(u8::is_ascii_hexdigit) {
+                16.0
+            } else if run.iter().all(u8::is_ascii_alphanumeric) {
+                62.0
+            } else {
+                64.0
+            };
+            let max = (run.len() as f32).min(charset_size).log2();
+            entropy >= 0.85 * max
+        }
+    }
+}
+
+/// True for the characters of standard and URL safe base64
+fn is_base64_char(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || b == b'+' || b == b'/' || b == b'=' || b == b'-' || b == b'_'
+}
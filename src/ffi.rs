@@ -0,0 +1,222 @@
+//! A C API, built with the `ffi` feature, for embedding the scanner in programs written in other
+//! languages with the same rule behavior as the binaries.
+//!
+//! `include/rusty_hog.h` declares the functions for C and C++, and Go programs can use it with
+//! cgo. Build the library with `cargo build --release --features ffi` and link against
+//! `librusty_hogs.so` (`.dylib` on macOS, `rusty_hogs.dll` on Windows) in `target/release`.
+//!
+//! A scanner is created from a rule pack JSON string, or NULL for the built-in rules, and scans
+//! buffers into a list of findings. The scanner and each list must be freed with their `free`
+//! function, and the strings of a finding live as long as its list. Functions return NULL on
+//! invalid arguments, an invalid rule pack or a panic, rather than unwinding into the caller.
+//!
+//! ```text
+//! RustyHogScanner *scanner = rusty_hog_scanner_new(NULL);
+//! RustyHogFindings *findings = rusty_hog_scan(scanner, data, len, false);
+//! for (size_t i = 0; i < rusty_hog_findings_len(findings); i++) {
+//!     const RustyHogFinding *finding = rusty_hog_findings_get(findings, i);
+//!     printf("%s on line %zu\n", finding->reason, finding->line);
+//! }
+//! rusty_hog_findings_free(findings);
+//! rusty_hog_scanner_free(scanner);
+//! ```
+
+use crate::rules::RulePack;
+use crate::{Finding, SecretScanner, SecretScannerBuilder, Span};
+use log::{self, error};
+use std::ffi::{CStr, CString};
+use std::os::raw::c_char;
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+use std::slice;
+
+/// A finding, as seen from C. The pointers are owned by the `RustyHogFindings` it came from.
+#[repr(C)]
+pub struct RustyHogFinding {
+    pub reason: *const c_char,
+    pub diff: *const c_char,
+    pub strings_found: *const *const c_char,
+    pub strings_found_len: usize,
+    /// The line number, starting at 1
+    pub line: usize,
+    /// The byte offset of the start of the line in the buffer
+    pub offset: usize,
+    /// Where each of the strings found is in the buffer, `spans_len` of them
+    pub spans: *const Span,
+    pub spans_len: usize,
+}
+
+/// The findings of a scan, and the strings their `RustyHogFinding`s point to
+pub struct RustyHogFindings {
+    findings: Vec<RustyHogFinding>,
+    // kept alive for the pointers above, never read from Rust
+    _strings: Vec<CString>,
+    _string_arrays: Vec<Vec<*const c_char>>,
+    _span_arrays: Vec<Vec<Span>>,
+}
+
+impl RustyHogFindings {
+    fn new(findings: Vec<Finding>) -> Self {
+        let mut strings: Vec<CString> = Vec::new();
+        let mut string_arrays: Vec<Vec<*const c_char>> = Vec::new();
+        let mut span_arrays: Vec<Vec<Span>> = Vec::new();
+        let mut c_findings: Vec<RustyHogFinding> = Vec::new();
+        // a CString's heap buffer doesn't move when the CString is moved into `strings`
+        fn keep(s: String, strings: &mut Vec<CString>) -> *const c_char {
+            let c = CString::new(s.replace('\0', "")).unwrap_or_default();
+            let p = c.as_ptr();
+            strings.push(c);
+            p
+        }
+        for finding in findings {
+            let reason = keep(finding.reason, &mut strings);
+            let diff = keep(finding.diff, &mut strings);
+            let found: Vec<*const c_char> = finding
+                .strings_found
+                .into_iter()
+                .map(|s| keep(s, &mut strings))
+                .collect();
+            c_findings.push(RustyHogFinding {
+                reason,
+                diff,
+                strings_found: found.as_ptr(),
+                strings_found_len: found.len(),
+                line: finding.line,
+                offset: finding.offset,
+                spans: finding.spans.as_ptr(),
+                spans_len: finding.spans.len(),
+            });
+            string_arrays.push(found);
+            span_arrays.push(finding.spans);
+        }
+        Self {
+            findings: c_findings,
+            _strings: strings,
+            _string_arrays: string_arrays,
+            _span_arrays: span_arrays,
+        }
+    }
+}
+
+/// Creates a scanner from a NUL terminated rule pack JSON string, or with the built-in rules if
+/// `rules_json` is NULL. Returns NULL if the rule pack is invalid.
+///
+/// # Safety
+///
+/// `rules_json` must be NULL or point to a NUL terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn rusty_hog_scanner_new(rules_json: *const c_char) -> *mut SecretScanner {
+    let rules_json = if rules_json.is_null() {
+        None
+    } else {
+        match CStr::from_ptr(rules_json).to_str() {
+            Ok(s) => Some(s.to_string()),
+            Err(e) => {
+                error!("The rule pack is not valid UTF-8: {}", e);
+                return ptr::null_mut();
+            }
+        }
+    };
+    let result = panic::catch_unwind(|| {
+        let mut builder = SecretScannerBuilder::new();
+        if let Some(json) = rules_json {
+            // the builder falls back to the built-in rules on an invalid rule pack, so check first
+            if let Err(e) = RulePack::from_str(&json, "custom") {
+                error!("{}", e);
+                return None;
+            }
+            builder = builder.set_json_str(&json);
+        }
+        Some(Box::new(builder.build()))
+    });
+    match result {
+        Ok(Some(scanner)) => Box::into_raw(scanner),
+        _ => ptr::null_mut(),
+    }
+}
+
+/// Frees a scanner created by `rusty_hog_scanner_new`. Does nothing if `scanner` is NULL.
+///
+/// # Safety
+///
+/// `scanner` must be NULL or a scanner returned by `rusty_hog_scanner_new` that was not freed.
+#[no_mangle]
+pub unsafe extern "C" fn rusty_hog_scanner_free(scanner: *mut SecretScanner) {
+    if !scanner.is_null() {
+        drop(Box::from_raw(scanner));
+    }
+}
+
+/// Scans `len` bytes at `data` (see `SecretScanner::scan_bytes`), also reporting high entropy
+/// strings if `entropy` is true. Returns NULL if `scanner` is NULL or the scan panicked.
+///
+/// # Safety
+///
+/// `scanner` must be a live scanner, and `data` must point to `len` readable bytes (or be NULL
+/// with a `len` of 0).
+#[no_mangle]
+pub unsafe extern "C" fn rusty_hog_scan(
+    scanner: *const SecretScanner,
+    data: *const u8,
+    len: usize,
+    entropy: bool,
+) -> *mut RustyHogFindings {
+    if scanner.is_null() || (data.is_null() && len > 0) {
+        return ptr::null_mut();
+    }
+    let data: &[u8] = if len == 0 {
+        &[]
+    } else {
+        slice::from_raw_parts(data, len)
+    };
+    let scanner = &*scanner;
+    match panic::catch_unwind(AssertUnwindSafe(|| scanner.scan_bytes(data, entropy))) {
+        Ok(findings) => Box::into_raw(Box::new(RustyHogFindings::new(findings))),
+        Err(_) => ptr::null_mut(),
+    }
+}
+
+/// The number of findings in a list, 0 if `findings` is NULL
+///
+/// # Safety
+///
+/// `findings` must be NULL or a list returned by `rusty_hog_scan` that was not freed.
+#[no_mangle]
+pub unsafe extern "C" fn rusty_hog_findings_len(findings: *const RustyHogFindings) -> usize {
+    if findings.is_null() {
+        return 0;
+    }
+    (*findings).findings.len()
+}
+
+/// The finding at `index` in a list, NULL if `findings` is NULL or `index` is out of range
+///
+/// # Safety
+///
+/// `findings` must be NULL or a list returned by `rusty_hog_scan` that was not freed.
+#[no_mangle]
+pub unsafe extern "C" fn rusty_hog_findings_get(
+    findings: *const RustyHogFindings,
+    index: usize,
+) -> *const RustyHogFinding {
+    if findings.is_null() {
+        return ptr::null();
+    }
+    let findings = &*findings;
+    match findings.findings.get(index) {
+        Some(finding) => finding,
+        None => ptr::null(),
+    }
+}
+
+/// Frees a list of findings and their strings. Does nothing if `findings` is NULL.
+///
+/// # Safety
+///
+/// `findings` must be NULL or a list returned by `rusty_hog_scan` that was not freed.
+#[no_mangle]
+pub unsafe extern "C" fn rusty_hog_findings_free(findings: *mut RustyHogFindings) {
+    if !findings.is_null() {
+        drop(Box::from_raw(findings));
+    }
+}
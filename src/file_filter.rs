@@ -0,0 +1,45 @@
+//! Selecting the files to scan by their name and size, for the scanners that walk folders of
+//! files (Dropbox Hog and Duroc Hog), before the files are downloaded or read.
+
+/// Selects the files to scan by their type and size. Each type is an extension, with or without
+/// the leading dot, or a whole file name, ignoring case: `env` selects `.env` files, `tar.gz`
+/// selects `backup.tar.gz` and `package-lock.json` only that file. An empty `include` list
+/// includes every type.
+///
+/// ```
+/// # use rusty_hogs::file_filter::FileFilter;
+/// let filter = FileFilter {
+///     include: vec![String::from("json"), String::from(".env")],
+///     exclude: vec![String::from("package-lock.json")],
+///     max_size: Some(1024),
+/// };
+/// assert!(filter.matches("config/Prod.JSON", 100));
+/// assert!(filter.matches("app/.env", 100));
+/// assert!(!filter.matches("app/package-lock.json", 100));
+/// assert!(!filter.matches("config/prod.json", 2048));
+/// assert!(!filter.matches("notes.txt", 100));
+/// assert!(FileFilter::default().matches("notes.txt", 1 << 40));
+/// ```
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Default)]
+pub struct FileFilter {
+    pub include: Vec<String>,
+    pub exclude: Vec<String>,
+    /// Files larger than this many bytes are skipped
+    pub max_size: Option<u64>,
+}
+
+impl FileFilter {
+    /// Returns true if a file with this name (or path) and size should be scanned
+    pub fn matches(&self, name: &str, size: u64) -> bool {
+        let name = name.rsplit('/').next().unwrap_or(name).to_lowercase();
+        let matches_any = |types: &[String]| {
+            types.iter().any(|t| {
+                let extension = t.trim_start_matches('.').to_lowercase();
+                name.ends_with(&format!(".{}", extension)) || name == extension
+            })
+        };
+        (self.include.is_empty() || matches_any(&self.include))
+            && !matches_any(&self.exclude)
+            && self.max_size.map_or(true, |max| size <= max)
+    }
+}
@@ -0,0 +1,546 @@
+//! Collection of tools for scanning the files and directories of a file system for secrets.
+//!
+//! `FileScanner` acts as a wrapper around a `SecretScanner` object to provide helper functions
+//! for scanning files on disk, such as a checkout, the output of a build or a mounted share.
+//! `list_files` walks a directory and its subdirectories, in name order, and `scan_file` reads a
+//! file and scans it the way the object store scanners scan an object: archives are opened with
+//! `--max-archive-depth` (see the `archive_scanning` module), config files are scanned value by
+//! value with `--structured` and large files are split into chunks.
+//!
+//! Symbolic links are skipped unless `follow_symlinks` is set, e.g. for config trees mounted
+//! over NFS and linked into place. Each directory is then listed once, whatever the links it is
+//! reached by, so links back to a parent don't loop, and no deeper than `max_depth` levels.
+//!
+//! The `filter` of the scanner (see the `file_filter` module) leaves files out by their extension
+//! or size before they are read, e.g. multi-GB database dumps or media files. Skipped files are
+//! logged at the info level.
+//!
+//! With `respect_ignore`, the files and directories that the `.gitignore` and `.hogignore` files
+//! of the directories ignore are skipped too, e.g. `node_modules` and build outputs (see the
+//! `ignore_file` module). Only the ignore files of the directory that is listed and of its
+//! subdirectories apply, not those of its parents.
+//!
+//! `FileWatcher` reports the files and directories that are created or modified under the
+//! directories it watches, once they stop changing, and `list_changed` lists the files of each
+//! one that `list_files` would list, for `duroc_hog --watch`.
+//!
+//! Data that isn't in a file, e.g. the output of `curl` or `kubectl logs` piped to the scanner,
+//! is read with `scan_reader` and reported under a label, which takes the place of the path.
+//!
+//! # Examples
+//!
+//! ```
+//! use rusty_hogs::file_filter::FileFilter;
+//! use rusty_hogs::filesystem_scanning::FileScanner;
+//! use std::fs;
+//! use tempdir::TempDir;
+//!
+//! let dir = TempDir::new("duroc").unwrap();
+//! fs::create_dir(dir.path().join("config")).unwrap();
+//! fs::write(dir.path().join("config/app.env"), "my email is arst@example.com\n").unwrap();
+//! fs::write(dir.path().join("README"), "Nothing to see here\n").unwrap();
+//!
+//! let fs_scanner = FileScanner::new();
+//! let files = fs_scanner.list_files(dir.path()).unwrap();
+//! assert_eq!(files, vec![dir.path().join("README"), dir.path().join("config/app.env")]);
+//!
+//! let findings = fs_scanner.scan_file(&files[1], false).unwrap();
+//! assert_eq!(findings.len(), 1);
+//! let finding = findings.iter().next().unwrap();
+//! assert_eq!(finding.reason, "Email address");
+//! assert_eq!(finding.path, files[1].to_string_lossy());
+//!
+//! let logs = &b"starting\nsent the report to qwfp@example.com\n"[..];
+//! let findings = fs_scanner.scan_reader("kubectl logs web", logs, false).unwrap();
+//! let finding = findings.iter().next().unwrap();
+//! assert_eq!(finding.path, "kubectl logs web");
+//! assert_eq!(finding.spans[0].line, 2);
+//!
+//! let filter = FileFilter {
+//!     exclude: vec![String::from("env")],
+//!     ..Default::default()
+//! };
+//! let fs_scanner = FileScanner::new().set_filter(filter);
+//! assert_eq!(fs_scanner.list_files(dir.path()).unwrap(), vec![files[0].clone()]);
+//!
+//! fs::write(dir.path().join(".hogignore"), "/config/\n").unwrap();
+//! let fs_scanner = FileScanner::new().set_respect_ignore(true);
+//! let files = fs_scanner.list_files(dir.path()).unwrap();
+//! assert_eq!(files, vec![dir.path().join(".hogignore"), dir.path().join("README")]);
+//! let changed = |path: &str| fs_scanner.list_changed(dir.path(), &dir.path().join(path));
+//! assert_eq!(changed("README").unwrap(), vec![dir.path().join("README")]);
+//! assert!(changed("config/app.env").unwrap().is_empty());
+//!
+//! # #[cfg(unix)]
+//! # {
+//! std::os::unix::fs::symlink(dir.path(), dir.path().join("config/loop")).unwrap();
+//! let fs_scanner = FileScanner::new().set_follow_symlinks(true);
+//! let files = fs_scanner.list_files(&dir.path().join("config")).unwrap();
+//! assert_eq!(
+//!     files,
+//!     vec![
+//!         dir.path().join("config/app.env"),
+//!         dir.path().join("config/loop/.hogignore"),
+//!         dir.path().join("config/loop/README"),
+//!     ]
+//! );
+//! # }
+//! ```
+
+use crate::archive_scanning;
+use crate::chunked_scanning;
+use crate::file_filter::FileFilter;
+use crate::ignore_file::{self, IgnoreFile};
+use crate::progress;
+use crate::structured_scanning::{self, StructuredFormat};
+use crate::{SecretScanner, Span};
+use log::{self, debug, info, warn};
+use notify::{DebouncedEvent, RecommendedWatcher, RecursiveMode, Watcher};
+use serde_derive::{Deserialize, Serialize};
+use simple_error::{try_with, SimpleError};
+use std::collections::HashSet;
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::time::Duration;
+
+/// How many levels of subdirectories `list_files` lists, unless `set_max_depth` says otherwise
+pub const DEFAULT_MAX_DEPTH: usize = 64;
+
+/// How long `FileWatcher` waits for a file to stop changing before it reports it
+pub const WATCH_DELAY: Duration = Duration::from_secs(2);
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Hash, Clone, Default)]
+/// `serde_json` object that represents a single found secret - finding
+pub struct FileFinding {
+    pub diff: String,
+    #[serde(rename = "stringsFound")]
+    pub strings_found: Vec<String>,
+    /// The path of the file, followed by the path of the file inside it for archives, e.g.
+    /// `dist/app.zip!config/settings.xml` (see the `archive_scanning` module), or the label of
+    /// data read with `scan_reader`
+    pub path: String,
+    pub reason: String,
+    #[serde(rename = "keyPath", default, skip_serializing_if = "Option::is_none")]
+    pub key_path: Option<String>,
+    /// Where each of `strings_found` is in the file, see `Span` (none with a `key_path`)
+    #[serde(default)]
+    pub spans: Vec<Span>,
+}
+
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+/// Contains helper functions for performing scans of files and directories
+pub struct FileScanner {
+    pub secret_scanner: SecretScanner,
+    /// Selects the files to scan by their extension and size
+    pub filter: FileFilter,
+    /// Skips the files that `.gitignore` and `.hogignore` files ignore
+    pub respect_ignore: bool,
+    /// Lists the files and directories symbolic links point to rather than skipping them
+    pub follow_symlinks: bool,
+    /// How many levels of subdirectories `list_files` lists
+    pub max_depth: usize,
+}
+
+/// Acts as a wrapper around a `SecretScanner` object to provide helper functions for performing
+/// scanning against files and directories.
+impl FileScanner {
+    /// Initialize the SecretScanner object first using the SecretScannerBuilder, then provide
+    /// it to this constructor method.
+    pub fn new_from_scanner(secret_scanner: SecretScanner) -> Self {
+        Self {
+            secret_scanner,
+            filter: FileFilter::default(),
+            respect_ignore: false,
+            follow_symlinks: false,
+            max_depth: DEFAULT_MAX_DEPTH,
+        }
+    }
+
+    pub fn new() -> Self {
+        Self::new_from_scanner(SecretScanner::default())
+    }
+
+    /// Sets the files to scan by their extension and size (every file by default)
+    pub fn set_filter(mut self, filter: FileFilter) -> Self {
+        self.filter = filter;
+        self
+    }
+
+    /// Skips the files and directories that the `.gitignore` and `.hogignore` files of the
+    /// directories ignore when listing them (false by default)
+    pub fn set_respect_ignore(mut self, respect_ignore: bool) -> Self {
+        self.respect_ignore = respect_ignore;
+        self
+    }
+
+    /// Lists the files and directories symbolic links point to, each directory once, rather than
+    /// skipping the links (false by default)
+    pub fn set_follow_symlinks(mut self, follow_symlinks: bool) -> Self {
+        self.follow_symlinks = follow_symlinks;
+        self
+    }
+
+    /// Makes `list_files` list subdirectories at most `depth` levels below the directory: 1 lists
+    /// its subdirectories but not theirs, and 0 none
+    pub fn set_max_depth(mut self, depth: usize) -> Self {
+        self.max_depth = depth;
+        self
+    }
+
+    /// Returns the files to scan under `path`: `path` itself if it is a file, or else the files
+    /// of the directory and its subdirectories, in name order. Files the `filter` doesn't match
+    /// are left out.
+    pub fn list_files(&self, path: &Path) -> Result<Vec<PathBuf>, SimpleError> {
+        let metadata = try_with!(fs::metadata(path), "Failed to read {}", path.display());
+        let mut walk = Walk::default();
+        if metadata.is_dir() {
+            self.list_dir(path, &metadata, 0, &mut walk)?;
+        } else {
+            self.add_file(path.to_path_buf(), metadata.len(), &mut walk.files);
+        }
+        Ok(walk.files)
+    }
+
+    /// Adds the files of a directory `depth` levels below the listed one, and of its
+    /// subdirectories, to the files of `walk`, in name order
+    fn list_dir(
+        &self,
+        dir: &Path,
+        metadata: &fs::Metadata,
+        depth: usize,
+        walk: &mut Walk,
+    ) -> Result<(), SimpleError> {
+        if depth > self.max_depth {
+            warn!(
+                "Skipping {}, which is more than {} levels deep",
+                dir.display(),
+                self.max_depth
+            );
+            return Ok(());
+        }
+        if let Some(id) = dir_id(dir, metadata) {
+            if !walk.visited.insert(id) {
+                info!("Skipping {}, which was already listed", dir.display());
+                return Ok(());
+            }
+        }
+        let ignore_file = if self.respect_ignore {
+            IgnoreFile::read(dir)?
+        } else {
+            None
+        };
+        let pushed = ignore_file.is_some();
+        walk.ignore_files.extend(ignore_file);
+        let result = self.list_entries(dir, depth, walk);
+        if pushed {
+            walk.ignore_files.pop();
+        }
+        result
+    }
+
+    /// Adds the files of the entries of a directory to the files of `walk`, see `list_dir`
+    fn list_entries(&self, dir: &Path, depth: usize, walk: &mut Walk) -> Result<(), SimpleError> {
+        let mut entries = Vec::new();
+        for entry in try_with!(fs::read_dir(dir), "Failed to read {}", dir.display()) {
+            entries.push(try_with!(entry, "Failed to read {}", dir.display()));
+        }
+        entries.sort_by_key(|e| e.file_name());
+        for entry in entries {
+            let path = entry.path();
+            let mut metadata = try_with!(entry.metadata(), "Failed to read {}", path.display());
+            if metadata.file_type().is_symlink() {
+                if !self.follow_symlinks {
+                    debug!("Skipping the symbolic link {}", path.display());
+                    continue;
+                }
+                metadata = match fs::metadata(&path) {
+                    Ok(m) => m,
+                    Err(e) => {
+                        info!("Skipping the symbolic link {}: {}", path.display(), e);
+                        continue;
+                    }
+                };
+            }
+            if ignore_file::is_ignored(&walk.ignore_files, &path, metadata.is_dir()) {
+                debug!("Skipping {}, which an ignore file ignores", path.display());
+            } else if metadata.is_dir() {
+                self.list_dir(&path, &metadata, depth + 1, walk)?;
+            } else if metadata.is_file() {
+                self.add_file(path, metadata.len(), &mut walk.files);
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the files to scan of a file or directory under `root` that was created or modified,
+    /// those `list_files(root)` would list: none if it is ignored, below `max_depth` or reached
+    /// by a symbolic link that isn't followed, the file if the `filter` matches it and the files
+    /// of a directory
+    pub fn list_changed(&self, root: &Path, path: &Path) -> Result<Vec<PathBuf>, SimpleError> {
+        let mut walk = Walk::default();
+        let relative = match path.strip_prefix(root) {
+            Ok(r) => r,
+            Err(_) => return Ok(walk.files),
+        };
+        let mut current = root.to_path_buf();
+        let mut metadata = try_with!(fs::metadata(root), "Failed to read {}", root.display());
+        for component in relative.components() {
+            if self.respect_ignore {
+                walk.ignore_files.extend(IgnoreFile::read(&current)?);
+            }
+            current.push(component);
+            metadata = try_with!(
+                fs::symlink_metadata(&current),
+                "Failed to read {}",
+                current.display()
+            );
+            if metadata.file_type().is_symlink() {
+                if !self.follow_symlinks {
+                    debug!("Skipping the symbolic link {}", current.display());
+                    return Ok(walk.files);
+                }
+                metadata = try_with!(
+                    fs::metadata(&current),
+                    "Failed to read {}",
+                    current.display()
+                );
+            }
+            if ignore_file::is_ignored(&walk.ignore_files, &current, metadata.is_dir()) {
+                debug!(
+                    "Skipping {}, which an ignore file ignores",
+                    current.display()
+                );
+                return Ok(walk.files);
+            }
+        }
+        let depth = relative.components().count();
+        if metadata.is_dir() {
+            self.list_dir(&current, &metadata, depth, &mut walk)?;
+        } else if metadata.is_file() && depth <= self.max_depth + 1 {
+            self.add_file(current, metadata.len(), &mut walk.files);
+        }
+        Ok(walk.files)
+    }
+
+    /// Adds the file at `path`, of `size` bytes, to `files` if the `filter` matches it
+    fn add_file(&self, path: PathBuf, size: u64, files: &mut Vec<PathBuf>) {
+        let name = path
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        if self.filter.matches(&name, size) {
+            files.push(path);
+        } else {
+            info!("Skipping {} ({} bytes)", path.display(), size);
+        }
+    }
+
+    /// Reads the file at `path` and scans it. Returns a HashSet of findings.
+    pub fn scan_file(
+        &self,
+        path: &Path,
+        scan_entropy: bool,
+    ) -> Result<HashSet<FileFinding>, SimpleError> {
+        let data = try_with!(fs::read(path), "Failed to read {}", path.display());
+        Ok(self.scan_data(&path.to_string_lossy(), data, scan_entropy))
+    }
+
+    /// Reads `reader` to the end and scans what it read as a file named `label`, e.g. stdin.
+    /// Returns a HashSet of findings.
+    pub fn scan_reader<R: Read>(
+        &self,
+        label: &str,
+        mut reader: R,
+        scan_entropy: bool,
+    ) -> Result<HashSet<FileFinding>, SimpleError> {
+        let mut data: Vec<u8> = Vec::new();
+        try_with!(reader.read_to_end(&mut data), "Failed to read {}", label);
+        Ok(self.scan_data(label, data, scan_entropy))
+    }
+
+    /// Scans the content of the file named `path`, opening it if it is an archive
+    fn scan_data(&self, path: &str, data: Vec<u8>, scan_entropy: bool) -> HashSet<FileFinding> {
+        let size = data.len();
+        let limits = self.secret_scanner.archive_limits;
+        let mut findings: HashSet<FileFinding> = HashSet::new();
+        for entry in archive_scanning::expand(path, data, limits) {
+            let template = FileFinding {
+                path: entry.path,
+                ..Default::default()
+            };
+            findings.extend(self.scan_entry(template, entry.data, scan_entropy));
+        }
+        progress::scanned(size, findings.len());
+        findings
+    }
+
+    /// Scans one file, the file itself unless it is an archive, reporting its findings as
+    /// `template` with the matches filled in
+    fn scan_entry(
+        &self,
+        template: FileFinding,
+        data: Vec<u8>,
+        scan_entropy: bool,
+    ) -> Vec<FileFinding> {
+        let data = self.secret_scanner.preprocess_owned(data);
+        let name = &template.path;
+
+        // Config files are parsed and scanned value by value, falling back to lines on failure
+        if self.secret_scanner.structured {
+            if let Some(format) = StructuredFormat::detect(name, &data) {
+                match structured_scanning::scan_document(
+                    &self.secret_scanner,
+                    &data,
+                    format,
+                    scan_entropy,
+                ) {
+                    Ok(matches) => {
+                        return matches
+                            .into_iter()
+                            .map(|m| FileFinding {
+                                diff: m.diff,
+                                strings_found: m.strings_found,
+                                reason: m.reason,
+                                key_path: Some(m.key_path),
+                                ..template.clone()
+                            })
+                            .collect();
+                    }
+                    Err(e) => debug!("{}, scanning {} line by line", e, name),
+                }
+            }
+        }
+
+        // Large files are split into chunks that are scanned in parallel
+        let options = self.secret_scanner.chunk_options;
+        chunked_scanning::scan_lines(&self.secret_scanner, data, options, scan_entropy)
+            .into_iter()
+            .map(|m| FileFinding {
+                diff: m.diff,
+                strings_found: m.strings_found,
+                reason: m.reason,
+                spans: m.spans,
+                ..template.clone()
+            })
+            .collect()
+    }
+}
+
+impl Default for FileScanner {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Reports the files and directories created or modified under the files and directories it
+/// watches, with the file system events of the OS (see the `notify` crate). Stops watching once
+/// dropped.
+pub struct FileWatcher {
+    /// The watched paths, canonicalized as the OS reports the paths of events
+    pub roots: Vec<PathBuf>,
+    // Kept for as long as the events are received
+    _watcher: RecommendedWatcher,
+    events: Receiver<DebouncedEvent>,
+}
+
+impl FileWatcher {
+    /// Starts watching the files and directories at `paths`, and the subdirectories of the
+    /// directories
+    pub fn new(paths: &[&Path]) -> Result<Self, SimpleError> {
+        let (sender, events) = mpsc::channel();
+        let mut watcher: RecommendedWatcher = try_with!(
+            Watcher::new(sender, WATCH_DELAY),
+            "Failed to watch the files"
+        );
+        let mut roots = Vec::new();
+        for path in paths {
+            let root = try_with!(fs::canonicalize(path), "Failed to read {}", path.display());
+            try_with!(
+                watcher.watch(&root, RecursiveMode::Recursive),
+                "Failed to watch {}",
+                path.display()
+            );
+            roots.push(root);
+        }
+        Ok(Self {
+            roots,
+            _watcher: watcher,
+            events,
+        })
+    }
+
+    /// Waits up to `timeout` for a change, then returns the paths that were created or modified
+    /// since the last call and still exist, each once and with the watched path it is under
+    pub fn changed(&self, timeout: Duration) -> Result<Vec<(PathBuf, PathBuf)>, SimpleError> {
+        let mut paths: Vec<PathBuf> = Vec::new();
+        let mut event = match self.events.recv_timeout(timeout) {
+            Ok(e) => Some(e),
+            Err(RecvTimeoutError::Timeout) => None,
+            Err(RecvTimeoutError::Disconnected) => {
+                return Err(SimpleError::new("Stopped watching the files"))
+            }
+        };
+        while let Some(e) = event {
+            match e {
+                DebouncedEvent::Create(path)
+                | DebouncedEvent::Write(path)
+                | DebouncedEvent::Rename(_, path) => {
+                    if !paths.contains(&path) {
+                        paths.push(path);
+                    }
+                }
+                DebouncedEvent::Rescan => warn!("Missed some changes to the watched files"),
+                DebouncedEvent::Error(e, Some(path)) => {
+                    warn!("Failed to watch {}: {}", path.display(), e)
+                }
+                DebouncedEvent::Error(e, None) => warn!("Failed to watch the files: {}", e),
+                _ => (),
+            }
+            event = self.events.try_recv().ok();
+        }
+        Ok(paths
+            .into_iter()
+            .filter(|p| p.exists())
+            .filter_map(|p| {
+                // the innermost watched path, if they are nested
+                let root = self
+                    .roots
+                    .iter()
+                    .filter(|r| p.starts_with(r))
+                    .max_by_key(|r| r.components().count())?;
+                Some((root.clone(), p))
+            })
+            .collect())
+    }
+}
+
+/// Identifies a directory whatever the path it is reached by: its device and inode numbers
+#[cfg(unix)]
+type DirId = (u64, u64);
+
+/// Identifies a directory whatever the path it is reached by: its canonical path
+#[cfg(not(unix))]
+type DirId = PathBuf;
+
+#[cfg(unix)]
+fn dir_id(_path: &Path, metadata: &fs::Metadata) -> Option<DirId> {
+    use std::os::unix::fs::MetadataExt;
+    Some((metadata.dev(), metadata.ino()))
+}
+
+#[cfg(not(unix))]
+fn dir_id(path: &Path, _metadata: &fs::Metadata) -> Option<DirId> {
+    fs::canonicalize(path).ok()
+}
+
+/// The state of a `list_files` walk
+#[derive(Default)]
+struct Walk {
+    /// The ignore files of the directory being listed and of its parents, see `respect_ignore`
+    ignore_files: Vec<IgnoreFile>,
+    /// The directories listed so far, see `follow_symlinks`
+    visited: HashSet<DirId>,
+    files: Vec<PathBuf>,
+}
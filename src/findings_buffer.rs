@@ -0,0 +1,202 @@
+//! A set of findings that stays within a memory budget by spilling to disk.
+//!
+//! Binaries that scan many repositories, buckets or images collect every finding before writing
+//! the report, so a pathological input (a generated file full of matches, a log with millions of
+//! email addresses) can exhaust the memory of a small container. `FindingsBuffer` keeps findings
+//! in a `HashSet` until their serialized size exceeds the budget, then appends them to a JSON
+//! lines file in a temporary directory and keeps only a 64-bit hash of each one, so that
+//! duplicates are still dropped. The temporary directory is deleted when the buffer is dropped.
+//!
+//! `SecretScanner::output_findings_buffer` writes the report. JSON reports are streamed from the
+//! spill file; the other formats (and finding caps) need every finding at once, so the spilled
+//! findings are read back into memory for them.
+//!
+//! # Examples
+//!
+//! ```
+//! use rusty_hogs::findings_buffer::FindingsBuffer;
+//!
+//! // a budget of 64 bytes forces findings to disk almost immediately
+//! let mut buffer: FindingsBuffer<String> = FindingsBuffer::new(Some(64));
+//! for i in 0..100 {
+//!     buffer.insert(format!("finding number {}", i)).unwrap();
+//! }
+//! buffer.insert(String::from("finding number 1")).unwrap();
+//! assert_eq!(buffer.len(), 100);
+//! assert!(buffer.is_spilled());
+//! assert_eq!(buffer.into_set().unwrap().len(), 100);
+//! ```
+
+use log::{self, debug, info};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use simple_error::{try_with, SimpleError};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashSet;
+use std::fs::File;
+use std::hash::{Hash, Hasher};
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::PathBuf;
+use tempdir::TempDir;
+
+const SPILL_FILE_NAME: &str = "findings.jsonl";
+
+/// The on-disk part of a `FindingsBuffer`
+struct Spill {
+    // kept so the directory is deleted with the buffer
+    _dir: TempDir,
+    path: PathBuf,
+    writer: BufWriter<File>,
+    /// Hashes of the spilled findings, for deduplication. A collision drops a finding, which at
+    /// 64 bits is far less likely than the scan running out of memory.
+    hashes: HashSet<u64>,
+}
+
+/// A set of findings with an optional memory budget in bytes (see the module documentation)
+pub struct FindingsBuffer<T: Serialize + Eq + Hash> {
+    budget: Option<usize>,
+    used: usize,
+    memory: HashSet<T>,
+    spill: Option<Spill>,
+}
+
+impl<T: Serialize + DeserializeOwned + Eq + Hash> FindingsBuffer<T> {
+    /// Create an empty buffer. With a budget of None findings are never spilled.
+    pub fn new(budget: Option<usize>) -> Self {
+        Self {
+            budget,
+            used: 0,
+            memory: HashSet::new(),
+            spill: None,
+        }
+    }
+
+    /// Adds a finding, returning false if it was already in the buffer. Errors if the spill file
+    /// can't be written.
+    pub fn insert(&mut self, finding: T) -> Result<bool, SimpleError> {
+        if self.memory.contains(&finding) {
+            return Ok(false);
+        }
+        if let Some(spill) = &self.spill {
+            if spill.hashes.contains(&hash_of(&finding)) {
+                return Ok(false);
+            }
+        }
+        let budget = match self.budget {
+            Some(b) => b,
+            None => return Ok(self.memory.insert(finding)),
+        };
+        self.used += serde_json::to_vec(&finding).map(|v| v.len()).unwrap_or(0);
+        self.memory.insert(finding);
+        if self.used > budget {
+            self.spill_memory()?;
+        }
+        Ok(true)
+    }
+
+    /// Adds every finding in `findings`
+    pub fn extend<I: IntoIterator<Item = T>>(&mut self, findings: I) -> Result<(), SimpleError> {
+        for finding in findings {
+            self.insert(finding)?;
+        }
+        Ok(())
+    }
+
+    /// The number of findings in the buffer, in memory and on disk
+    pub fn len(&self) -> usize {
+        self.memory.len() + self.spill.as_ref().map_or(0, |s| s.hashes.len())
+    }
+
+    /// True if the buffer holds no findings
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// True if some findings have been written to disk
+    pub fn is_spilled(&self) -> bool {
+        self.spill.is_some()
+    }
+
+    /// Reads every finding back into a single set, ignoring the budget
+    pub fn into_set(mut self) -> Result<HashSet<T>, SimpleError> {
+        let mut findings = std::mem::replace(&mut self.memory, HashSet::new());
+        if let Some(reader) = self.spill_reader()? {
+            for line in reader.lines() {
+                let line = try_with!(line, "Failed to read the spill file");
+                findings.insert(try_with!(
+                    serde_json::from_str(&line),
+                    "Failed to parse a spilled finding"
+                ));
+            }
+        }
+        Ok(findings)
+    }
+
+    /// Calls `f` with the JSON of each finding, first those on disk and then those in memory,
+    /// without loading the spilled findings into memory
+    pub fn for_each_json<F: FnMut(&str) -> Result<(), SimpleError>>(
+        &mut self,
+        mut f: F,
+    ) -> Result<(), SimpleError> {
+        if let Some(reader) = self.spill_reader()? {
+            for line in reader.lines() {
+                f(&try_with!(line, "Failed to read the spill file"))?;
+            }
+        }
+        for finding in &self.memory {
+            f(&try_with!(
+                serde_json::to_string(finding),
+                "Failed to serialize a finding"
+            ))?;
+        }
+        Ok(())
+    }
+
+    /// Moves every in-memory finding to the spill file, creating it if needed
+    fn spill_memory(&mut self) -> Result<(), SimpleError> {
+        if self.spill.is_none() {
+            let dir = try_with!(TempDir::new("rusty_hogs"), "Failed to create a temp dir");
+            let path = dir.path().join(SPILL_FILE_NAME);
+            let file = try_with!(File::create(&path), "Failed to create {:?}", path);
+            info!(
+                "Findings exceeded the memory budget, spilling to {}",
+                path.display()
+            );
+            self.spill = Some(Spill {
+                _dir: dir,
+                path,
+                writer: BufWriter::new(file),
+                hashes: HashSet::new(),
+            });
+        }
+        let spill = self.spill.as_mut().unwrap();
+        debug!("Spilling {} findings to disk", self.memory.len());
+        for finding in self.memory.drain() {
+            try_with!(
+                serde_json::to_writer(&mut spill.writer, &finding),
+                "Failed to spill a finding"
+            );
+            try_with!(spill.writer.write_all(b"\n"), "Failed to spill a finding");
+            spill.hashes.insert(hash_of(&finding));
+        }
+        self.used = 0;
+        Ok(())
+    }
+
+    /// Flushes the spill file and opens it for reading, if there is one
+    fn spill_reader(&mut self) -> Result<Option<BufReader<File>>, SimpleError> {
+        let spill = match self.spill.as_mut() {
+            Some(s) => s,
+            None => return Ok(None),
+        };
+        try_with!(spill.writer.flush(), "Failed to flush the spill file");
+        let file = try_with!(File::open(&spill.path), "Failed to open the spill file");
+        Ok(Some(BufReader::new(file)))
+    }
+}
+
+fn hash_of<T: Hash>(finding: &T) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    finding.hash(&mut hasher);
+    hasher.finish()
+}
@@ -0,0 +1,376 @@
+//! Recording the API responses a scan receives, and replaying them offline.
+//!
+//! With `--record-fixtures <DIR>`, every request the API scanners make (GitHub, GitLab, Bitbucket,
+//! Azure DevOps, Jenkins, Teams, Discord, Cloud Storage, container and package registries and
+//! identity connectors) is written to the directory as a single-entry
+//! [HAR](https://w3c.github.io/web-performance/specs/HAR/Overview.html) file. With
+//! `--replay-fixtures <DIR>`, the same requests are answered from those files without touching the
+//! network, so a rule pack or configuration can be tested against realistic data in CI.
+//!
+//! Fixtures are sanitized as they are written: request headers (which carry the scanner's own
+//! credentials) are not recorded, credential response headers such as `Set-Cookie` are dropped,
+//! query parameters with sensitive names such as `access_token` and URL signatures such as the
+//! `sig` of a SAS token are replaced with `REDACTED`, and so are the tokens returned by OAuth and
+//! registry token endpoints. The content being scanned is kept as is, secrets included, since
+//! that is what the fixtures are for; review them before sharing them.
+//!
+//! Requests are matched by method and (sanitized) URL. A request made several times is answered
+//! with its recordings in order, then with the last one again. Git clones, Google Drive and S3 do
+//! not go through this module and are not recorded.
+//!
+//! # Examples
+//!
+//! ```
+//! use rusty_hogs::fixtures::{self, FixtureMode};
+//! use rusty_hogs::network;
+//! use std::fs;
+//! use tempdir::TempDir;
+//!
+//! let dir = TempDir::new("fixtures").unwrap();
+//! let har = r#"{"log": {"entries": [{
+//!     "request": {"method": "GET", "url": "https://api.example.com/v1/items?access_token=REDACTED"},
+//!     "response": {"status": 200, "headers": [], "content": {"text": "{\"items\": []}"}}
+//! }]}}"#;
+//! fs::write(dir.path().join("00000.har"), har).unwrap();
+//! fixtures::install(FixtureMode::Replay(dir.path().to_path_buf())).unwrap();
+//!
+//! let client = network::client();
+//! let request = client.get("https://api.example.com/v1/items?access_token=abc123abc123");
+//! let response = fixtures::send(request).unwrap();
+//! assert_eq!(response.status(), 200);
+//! assert_eq!(response.text().unwrap(), r#"{"items": []}"#);
+//! ```
+
+use crate::network;
+use crate::structured_scanning;
+use lazy_static::lazy_static;
+use log::{self, debug, info};
+use reqwest::blocking::{RequestBuilder, Response};
+use serde_json::{json, Value};
+use simple_error::{require_with, try_with, SimpleError};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use url::Url;
+
+/// Replaces sanitized values in recorded fixtures
+const REDACTED: &str = "REDACTED";
+
+/// Response headers (lower case) that are not recorded
+const SENSITIVE_HEADERS: &[&str] = &[
+    "set-cookie",
+    "authorization",
+    "proxy-authorization",
+    "x-api-key",
+    "x-auth-token",
+    "x-csrf-token",
+];
+
+/// Query parameters (lower case) that sign a URL, such as the signature of an Azure SAS token
+const SIGNATURE_PARAMS: &[&str] = &["sig", "signature", "x-goog-signature", "x-amz-signature"];
+
+/// Top-level fields of JSON response bodies that are redacted, as returned by token endpoints
+const TOKEN_FIELDS: &[&str] = &["access_token", "refresh_token", "id_token", "token"];
+
+/// Whether requests go to the network, are recorded, or are answered from recordings
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum FixtureMode {
+    Live,
+    /// Sends requests and writes each exchange to this directory
+    Record(PathBuf),
+    /// Answers requests from the fixtures in this directory
+    Replay(PathBuf),
+}
+
+impl FixtureMode {
+    /// The mode selected by the `--record-fixtures` and `--replay-fixtures` options
+    pub fn from_args(record: Option<&str>, replay: Option<&str>) -> Self {
+        match (record, replay) {
+            (Some(dir), _) => FixtureMode::Record(PathBuf::from(dir)),
+            (None, Some(dir)) => FixtureMode::Replay(PathBuf::from(dir)),
+            (None, None) => FixtureMode::Live,
+        }
+    }
+}
+
+/// A recorded response
+#[derive(Debug, Clone)]
+struct Fixture {
+    status: u16,
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+}
+
+struct State {
+    mode: FixtureMode,
+    /// The number of the next fixture file to record
+    next: usize,
+    /// Recorded responses by request key, and how many of each have been replayed
+    fixtures: HashMap<String, (usize, Vec<Fixture>)>,
+}
+
+lazy_static! {
+    static ref STATE: Mutex<State> = Mutex::new(State {
+        mode: FixtureMode::Live,
+        next: 0,
+        fixtures: HashMap::new(),
+    });
+}
+
+/// Selects how requests made with `send` are handled, creating the directory to record into or
+/// loading the fixtures to replay
+pub fn install(mode: FixtureMode) -> Result<(), SimpleError> {
+    let mut next = 0;
+    let mut fixtures: HashMap<String, (usize, Vec<Fixture>)> = HashMap::new();
+    match &mode {
+        FixtureMode::Live => {}
+        FixtureMode::Record(dir) => {
+            try_with!(
+                fs::create_dir_all(dir),
+                "Failed to create {}",
+                dir.display()
+            );
+            next = fixture_files(dir)?.len();
+            info!("Recording fixtures to {}", dir.display());
+        }
+        FixtureMode::Replay(dir) => {
+            for path in fixture_files(dir)? {
+                let har = try_with!(fs::read(&path), "Failed to read {}", path.display());
+                let har: Value = try_with!(
+                    serde_json::from_slice(&har),
+                    "Failed to parse {}",
+                    path.display()
+                );
+                let entries = require_with!(
+                    har["log"]["entries"].as_array(),
+                    "{} has no log.entries array",
+                    path.display()
+                );
+                for entry in entries {
+                    let (key, fixture) = parse_entry(entry)?;
+                    fixtures.entry(key).or_default().1.push(fixture);
+                }
+            }
+            info!(
+                "Replaying {} requests from {}",
+                fixtures.len(),
+                dir.display()
+            );
+        }
+    }
+    let mut state = STATE.lock().unwrap();
+    *state = State {
+        mode,
+        next,
+        fixtures,
+    };
+    Ok(())
+}
+
+/// True if requests are answered from fixtures rather than sent
+pub fn replaying() -> bool {
+    match STATE.lock().unwrap().mode {
+        FixtureMode::Replay(_) => true,
+        _ => false,
+    }
+}
+
+/// Sends a request, recording the exchange or answering it from a fixture if `install` selected
+/// it. Scanners send requests with `retry::send`, which calls this.
+pub fn send(request: RequestBuilder) -> Result<Response, SimpleError> {
+    let mode = STATE.lock().unwrap().mode.clone();
+    match mode {
+        FixtureMode::Live => request.send().map_err(SimpleError::from),
+        FixtureMode::Record(dir) => {
+            let request = try_with!(request.build(), "Invalid request");
+            let key = request_key(request.method().as_str(), request.url().as_str());
+            let response = network::client()
+                .execute(request)
+                .map_err(SimpleError::from)?;
+            let fixture = Fixture {
+                status: response.status().as_u16(),
+                headers: response
+                    .headers()
+                    .iter()
+                    .filter(|(name, _)| !SENSITIVE_HEADERS.contains(&name.as_str()))
+                    .filter_map(|(name, value)| {
+                        Some((name.to_string(), value.to_str().ok()?.to_string()))
+                    })
+                    .collect(),
+                body: try_with!(response.bytes(), "Failed to read the response").to_vec(),
+            };
+            record(&dir, &key, &fixture)?;
+            to_response(fixture)
+        }
+        FixtureMode::Replay(_) => {
+            let request = try_with!(request.build(), "Invalid request");
+            let key = request_key(request.method().as_str(), request.url().as_str());
+            debug!("Replaying {}", key);
+            let mut state = STATE.lock().unwrap();
+            let (replayed, recorded) = require_with!(
+                state.fixtures.get_mut(&key),
+                "No fixture recorded for {}",
+                key
+            );
+            let fixture = recorded[(*replayed).min(recorded.len() - 1)].clone();
+            *replayed += 1;
+            to_response(fixture)
+        }
+    }
+}
+
+/// Writes an exchange to the next fixture file, with the body redacted
+fn record(dir: &Path, key: &str, fixture: &Fixture) -> Result<(), SimpleError> {
+    let mut parts = key.splitn(2, ' ');
+    let method = parts.next().unwrap_or_default();
+    let url = parts.next().unwrap_or_default();
+    let body = redact_tokens(&fixture.body);
+    let mime_type = fixture
+        .headers
+        .iter()
+        .find(|(name, _)| name == "content-type")
+        .map(|(_, value)| value.as_str())
+        .unwrap_or("");
+    let content = match String::from_utf8(body.clone()) {
+        Ok(text) => json!({ "mimeType": mime_type, "size": body.len(), "text": text }),
+        Err(_) => json!({
+            "mimeType": mime_type,
+            "size": body.len(),
+            "text": base64::encode(&body),
+            "encoding": "base64",
+        }),
+    };
+    let headers: Vec<Value> = fixture
+        .headers
+        .iter()
+        .map(|(name, value)| json!({ "name": name, "value": value }))
+        .collect();
+    let har = json!({ "log": {
+        "version": "1.2",
+        "creator": { "name": "rusty_hogs", "version": env!("CARGO_PKG_VERSION") },
+        "entries": [{
+            "startedDateTime": chrono::Utc::now().to_rfc3339(),
+            "request": { "method": method, "url": url, "headers": [] },
+            "response": { "status": fixture.status, "headers": headers, "content": content },
+        }],
+    }});
+
+    let path = {
+        let mut state = STATE.lock().unwrap();
+        state.next += 1;
+        dir.join(format!("{:05}.har", state.next - 1))
+    };
+    let har = try_with!(
+        serde_json::to_vec_pretty(&har),
+        "Failed to serialize a fixture"
+    );
+    try_with!(fs::write(&path, har), "Failed to write {}", path.display());
+    debug!("Recorded {} to {}", key, path.display());
+    Ok(())
+}
+
+/// Reads a HAR entry back into its request key and response
+fn parse_entry(entry: &Value) -> Result<(String, Fixture), SimpleError> {
+    let request = &entry["request"];
+    let response = &entry["response"];
+    let method = require_with!(request["method"].as_str(), "Fixture has no request method");
+    let url = require_with!(request["url"].as_str(), "Fixture has no request URL");
+    let text = response["content"]["text"].as_str().unwrap_or("");
+    let body = if response["content"]["encoding"].as_str() == Some("base64") {
+        try_with!(
+            base64::decode(text),
+            "Invalid base64 body in the fixture for {}",
+            url
+        )
+    } else {
+        text.as_bytes().to_vec()
+    };
+    let headers = match response["headers"].as_array() {
+        Some(h) => h
+            .iter()
+            .filter_map(|nv| {
+                Some((
+                    nv["name"].as_str()?.to_string(),
+                    nv["value"].as_str()?.to_string(),
+                ))
+            })
+            .collect(),
+        None => Vec::new(),
+    };
+    let fixture = Fixture {
+        status: response["status"].as_u64().unwrap_or(200) as u16,
+        headers,
+        body,
+    };
+    Ok((request_key(method, url), fixture))
+}
+
+fn to_response(fixture: Fixture) -> Result<Response, SimpleError> {
+    let mut builder = http::Response::builder().status(fixture.status);
+    for (name, value) in &fixture.headers {
+        builder = builder.header(name.as_str(), value.as_str());
+    }
+    let response = try_with!(builder.body(fixture.body), "Invalid fixture response");
+    Ok(Response::from(response))
+}
+
+/// Identifies a request by its method and URL, without credentials in the URL
+fn request_key(method: &str, url: &str) -> String {
+    let url = match Url::parse(url) {
+        Ok(mut url) => {
+            let pairs: Vec<(String, String)> = url
+                .query_pairs()
+                .map(|(name, value)| {
+                    if structured_scanning::is_sensitive_key(&name)
+                        || SIGNATURE_PARAMS.contains(&name.to_lowercase().as_str())
+                    {
+                        (name.to_string(), REDACTED.to_string())
+                    } else {
+                        (name.to_string(), value.to_string())
+                    }
+                })
+                .collect();
+            if !pairs.is_empty() {
+                url.query_pairs_mut().clear().extend_pairs(pairs);
+            }
+            let _ = url.set_username("");
+            let _ = url.set_password(None);
+            url.to_string()
+        }
+        Err(_) => url.to_string(),
+    };
+    format!("{} {}", method.to_uppercase(), url)
+}
+
+/// Redacts the `TOKEN_FIELDS` of a JSON object body, leaving other bodies unchanged
+fn redact_tokens(body: &[u8]) -> Vec<u8> {
+    let mut json: Value = match serde_json::from_slice(body) {
+        Ok(Value::Object(o)) => Value::Object(o),
+        _ => return body.to_vec(),
+    };
+    let mut redacted = false;
+    for field in TOKEN_FIELDS {
+        if json[*field].is_string() {
+            json[*field] = Value::from(REDACTED);
+            redacted = true;
+        }
+    }
+    if redacted {
+        serde_json::to_vec(&json).unwrap_or_else(|_| body.to_vec())
+    } else {
+        body.to_vec()
+    }
+}
+
+/// The HAR files in a fixture directory, in the order they were recorded
+fn fixture_files(dir: &Path) -> Result<Vec<PathBuf>, SimpleError> {
+    let entries = try_with!(fs::read_dir(dir), "Failed to read {}", dir.display());
+    let mut files: Vec<PathBuf> = entries
+        .filter_map(Result::ok)
+        .map(|e| e.path())
+        .filter(|p| p.extension().map_or(false, |e| e == "har"))
+        .collect();
+    files.sort();
+    Ok(files)
+}
@@ -0,0 +1,516 @@
+//! Collection of tools for scanning Google Cloud Storage buckets for secrets.
+//!
+//! `GcsScanner` acts as a wrapper around a `SecretScanner` object and talks to the
+//! [Cloud Storage JSON API](https://cloud.google.com/storage/docs/json_api). It lists the objects
+//! under a bucket and prefix, then downloads and scans them on several threads, the same way
+//! `aws_scanning` scans S3 objects: config files are scanned value by value with `--structured`
+//! and large objects are split into chunks.
+//!
+//! Requests are authorized with OAuth access tokens for the read-only storage scope, obtained from
+//! the first of these [application default
+//! credentials](https://cloud.google.com/docs/authentication/application-default-credentials):
+//!
+//! * a key file given explicitly, or in the `GOOGLE_APPLICATION_CREDENTIALS` environment variable,
+//!   which can be a service account key or a user's credentials
+//! * the user credentials saved by `gcloud auth application-default login`
+//! * the service account of the Compute Engine instance, GKE node or Cloud Run service the scan
+//!   runs on, from the metadata server
+//!
+//! Public buckets can be scanned without credentials. Tokens are refreshed before they expire, so
+//! scans of large buckets can run for longer than an hour.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use rusty_hogs::gcs_scanning::{GcsConfig, GcsCredentials, GcsScanner};
+//!
+//! let gs = GcsScanner::new();
+//! let config = GcsConfig::new(GcsCredentials::application_default(None).unwrap());
+//! let objects = gs.list_objects(&config, "my-bucket", "config/", true).unwrap();
+//! for (object, result) in gs.scan_objects(&config, "my-bucket", objects, 4, false) {
+//!     println!("{}: {:?}", object.name, result.map(|f| f.len()));
+//! }
+//! ```
+
+use crate::archive_scanning;
+use crate::chunked_scanning;
+use crate::network;
+use crate::progress;
+use crate::retry;
+use crate::shutdown;
+use crate::structured_scanning::{self, StructuredFormat};
+use crate::{SecretScanner, Span};
+use log::{self, debug, error, info};
+use reqwest::blocking::Response;
+use serde_derive::{Deserialize, Serialize};
+use serde_json::Value;
+use simple_error::{require_with, try_with, SimpleError};
+use std::env;
+use std::fs;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use url::Url;
+use yup_oauth2::{GetToken, ServiceAccountAccess};
+
+/// The Cloud Storage JSON API
+const API_URL: &str = "https://storage.googleapis.com/storage/v1";
+
+/// The OAuth scope needed to list and download objects
+const READ_ONLY_SCOPE: &str = "https://www.googleapis.com/auth/devstorage.read_only";
+
+/// Where user credentials are exchanged for access tokens
+const TOKEN_URL: &str = "https://oauth2.googleapis.com/token";
+
+/// Where instances get the access tokens of their service account
+const METADATA_TOKEN_URL: &str =
+    "http://metadata.google.internal/computeMetadata/v1/instance/service-accounts/default/token";
+
+/// Tokens are refreshed this long before they expire
+const TOKEN_REFRESH_MARGIN: Duration = Duration::from_secs(60);
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Hash, Clone, Default)]
+/// `serde_json` object that represents a single found secret - finding
+pub struct GcsFinding {
+    pub diff: String,
+    #[serde(rename = "stringsFound")]
+    pub strings_found: Vec<String>,
+    pub bucket: String,
+    /// The name of the object, followed by the path of the file inside it for archives, e.g.
+    /// `builds/build.zip!config/settings.xml` (see the `archive_scanning` module)
+    pub key: String,
+    /// The `gs://` URI of the object
+    pub url: String,
+    pub reason: String,
+    #[serde(rename = "keyPath", default, skip_serializing_if = "Option::is_none")]
+    pub key_path: Option<String>,
+    /// Where each of `strings_found` is in the object, see `Span` (none with a `key_path`)
+    #[serde(default)]
+    pub spans: Vec<Span>,
+}
+
+/// Where access tokens come from, see the module documentation
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+pub enum GcsCredentials {
+    /// The path of a service account key file
+    ServiceAccount(String),
+    /// A user's refresh token, as saved by `gcloud auth application-default login`
+    AuthorizedUser {
+        client_id: String,
+        client_secret: String,
+        refresh_token: String,
+    },
+    /// The service account of the instance the scan runs on
+    MetadataServer,
+    /// No credentials, for public buckets
+    Anonymous,
+}
+
+/// The credentials to authorize requests with, and the access token they last produced
+#[derive(Debug, Clone)]
+pub struct GcsConfig {
+    pub credentials: GcsCredentials,
+    /// Shared by the clones of a config, so that threads reuse the same token
+    token: Arc<Mutex<Option<(String, Instant)>>>,
+}
+
+/// An object in a bucket. Only the fields we need are included.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Hash, Clone, Default)]
+pub struct GcsObject {
+    pub name: String,
+    /// The size in bytes
+    pub size: u64,
+    /// When the object was last modified (RFC 3339)
+    pub updated: String,
+}
+
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+/// Contains helper functions for performing scans of Cloud Storage objects
+pub struct GcsScanner {
+    pub secret_scanner: SecretScanner,
+}
+
+impl GcsCredentials {
+    /// Finds the application default credentials, starting with `key_file` if given
+    pub fn application_default(key_file: Option<&str>) -> Result<Self, SimpleError> {
+        if let Some(path) = key_file {
+            return Self::from_file(Path::new(path));
+        }
+        if let Ok(path) = env::var("GOOGLE_APPLICATION_CREDENTIALS") {
+            return Self::from_file(Path::new(&path));
+        }
+        match gcloud_credentials_path() {
+            Some(path) if path.is_file() => Self::from_file(&path),
+            _ => {
+                debug!("No credentials file found, using the metadata server");
+                Ok(GcsCredentials::MetadataServer)
+            }
+        }
+    }
+
+    /// Reads a service account key or user credentials file
+    pub fn from_file(path: &Path) -> Result<Self, SimpleError> {
+        let json = try_with!(
+            fs::read_to_string(path),
+            "Failed to read {}",
+            path.display()
+        );
+        let file: Value = try_with!(
+            serde_json::from_str(&json),
+            "Failed to parse {}",
+            path.display()
+        );
+        let field = |name: &str| file[name].as_str().unwrap_or_default().to_string();
+        match file["type"].as_str() {
+            Some("service_account") => Ok(GcsCredentials::ServiceAccount(
+                path.to_string_lossy().to_string(),
+            )),
+            Some("authorized_user") => Ok(GcsCredentials::AuthorizedUser {
+                client_id: field("client_id"),
+                client_secret: field("client_secret"),
+                refresh_token: field("refresh_token"),
+            }),
+            t => Err(SimpleError::new(format!(
+                "Unsupported credentials type {:?} in {}",
+                t,
+                path.display()
+            ))),
+        }
+    }
+
+    /// Gets a new access token and the number of seconds it is valid for, or None for anonymous
+    /// access
+    fn fetch_token(&self) -> Result<Option<(String, u64)>, SimpleError> {
+        let response = match self {
+            GcsCredentials::ServiceAccount(path) => {
+                let key = try_with!(
+                    yup_oauth2::service_account_key_from_file(path),
+                    "Failed to read the service account key {}",
+                    path
+                );
+                let client = network::hyper_client()?;
+                let token = try_with!(
+                    ServiceAccountAccess::new(key, client).token(&[READ_ONLY_SCOPE]),
+                    "Failed to get an access token for the service account"
+                );
+                let expires_in = token.expires_in.unwrap_or(3600).max(0) as u64;
+                return Ok(Some((token.access_token, expires_in)));
+            }
+            GcsCredentials::AuthorizedUser {
+                client_id,
+                client_secret,
+                refresh_token,
+            } => network::client().post(TOKEN_URL).form(&[
+                ("grant_type", "refresh_token"),
+                ("client_id", client_id.as_str()),
+                ("client_secret", client_secret.as_str()),
+                ("refresh_token", refresh_token.as_str()),
+            ]),
+            GcsCredentials::MetadataServer => network::client()
+                .get(METADATA_TOKEN_URL)
+                .header("Metadata-Flavor", "Google"),
+            GcsCredentials::Anonymous => return Ok(None),
+        };
+        let response = try_with!(retry::send(response), "Access token request failed");
+        if !response.status().is_success() {
+            return Err(SimpleError::new(format!(
+                "Access token request returned {}",
+                response.status()
+            )));
+        }
+        let token: Value = try_with!(response.json(), "Invalid access token response");
+        let access_token = require_with!(
+            token["access_token"].as_str(),
+            "No access token in the response"
+        );
+        let expires_in = token["expires_in"].as_u64().unwrap_or(3600);
+        Ok(Some((access_token.to_string(), expires_in)))
+    }
+}
+
+/// The user credentials file written by `gcloud auth application-default login`
+fn gcloud_credentials_path() -> Option<PathBuf> {
+    let config_dir = if cfg!(windows) {
+        PathBuf::from(env::var("APPDATA").ok()?)
+    } else {
+        PathBuf::from(env::var("HOME").ok()?).join(".config")
+    };
+    Some(
+        config_dir
+            .join("gcloud")
+            .join("application_default_credentials.json"),
+    )
+}
+
+impl GcsConfig {
+    pub fn new(credentials: GcsCredentials) -> Self {
+        Self {
+            credentials,
+            token: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    /// Returns a valid access token, getting a new one if the last one is about to expire
+    fn access_token(&self) -> Result<Option<String>, SimpleError> {
+        let mut cached = self.token.lock().unwrap();
+        if let Some((token, expiry)) = cached.as_ref() {
+            if Instant::now() + TOKEN_REFRESH_MARGIN < *expiry {
+                return Ok(Some(token.clone()));
+            }
+        }
+        Ok(match self.credentials.fetch_token()? {
+            Some((token, expires_in)) => {
+                let expiry = Instant::now() + Duration::from_secs(expires_in);
+                *cached = Some((token.clone(), expiry));
+                Some(token)
+            }
+            None => None,
+        })
+    }
+}
+
+/// Acts as a wrapper around a `SecretScanner` object to provide helper functions for performing
+/// scanning against Cloud Storage. Relies on the [reqwest](https://docs.rs/reqwest/) library for
+/// HTTP access.
+impl GcsScanner {
+    /// Initialize the SecretScanner object first using the SecretScannerBuilder, then provide
+    /// it to this constructor method.
+    pub fn new_from_scanner(secret_scanner: SecretScanner) -> Self {
+        Self { secret_scanner }
+    }
+
+    pub fn new() -> Self {
+        Self {
+            secret_scanner: SecretScanner::default(),
+        }
+    }
+
+    /// Lists the objects in `bucket` whose names start with `prefix`. Without `recursive`, objects
+    /// in "subdirectories" of the prefix (names with another `/` after it) are left out.
+    pub fn list_objects(
+        &self,
+        config: &GcsConfig,
+        bucket: &str,
+        prefix: &str,
+        recursive: bool,
+    ) -> Result<Vec<GcsObject>, SimpleError> {
+        let mut objects: Vec<GcsObject> = Vec::new();
+        let mut page_token: Option<String> = None;
+        loop {
+            let mut url = api_url(&["b", bucket, "o"])?;
+            url.query_pairs_mut()
+                .append_pair("prefix", prefix)
+                .append_pair("fields", "nextPageToken,items(name,size,updated)");
+            if !recursive {
+                url.query_pairs_mut().append_pair("delimiter", "/");
+            }
+            if let Some(token) = &page_token {
+                url.query_pairs_mut().append_pair("pageToken", token);
+            }
+            let page: Value = try_with!(
+                get(config, url.as_str())?.json(),
+                "Failed to parse the object listing of {}",
+                bucket
+            );
+            for item in page["items"].as_array().into_iter().flatten() {
+                let name = item["name"].as_str().unwrap_or_default();
+                if name.is_empty() || name.ends_with('/') {
+                    continue;
+                }
+                objects.push(GcsObject {
+                    name: name.to_string(),
+                    // the JSON API returns sizes as strings
+                    size: item["size"]
+                        .as_str()
+                        .and_then(|s| s.parse().ok())
+                        .unwrap_or(0),
+                    updated: item["updated"].as_str().unwrap_or_default().to_string(),
+                });
+            }
+            page_token = page["nextPageToken"].as_str().map(String::from);
+            if page_token.is_none() {
+                info!(
+                    "Found {} objects in gs://{}/{}",
+                    objects.len(),
+                    bucket,
+                    prefix
+                );
+                return Ok(objects);
+            }
+        }
+    }
+
+    /// Downloads an object and returns a list of findings
+    pub fn scan_object(
+        &self,
+        config: &GcsConfig,
+        bucket: &str,
+        name: &str,
+        scan_entropy: bool,
+    ) -> Result<Vec<GcsFinding>, SimpleError> {
+        let mut url = api_url(&["b", bucket, "o", name])?;
+        url.query_pairs_mut().append_pair("alt", "media");
+        let data = try_with!(
+            get(config, url.as_str())?.bytes(),
+            "Failed to download gs://{}/{}",
+            bucket,
+            name
+        );
+        let size = data.len();
+        let limits = self.secret_scanner.archive_limits;
+        let mut findings: Vec<GcsFinding> = Vec::new();
+        for entry in archive_scanning::expand(name, data.to_vec(), limits) {
+            let template = GcsFinding {
+                bucket: bucket.to_string(),
+                key: entry.path,
+                url: format!("gs://{}/{}", bucket, name),
+                ..Default::default()
+            };
+            findings.extend(self.scan_file(template, entry.data, scan_entropy));
+        }
+        progress::scanned(size, findings.len());
+        Ok(findings)
+    }
+
+    /// Scans one file of an object, the object itself unless it is an archive, reporting its
+    /// findings as `template` with the matches filled in
+    fn scan_file(
+        &self,
+        template: GcsFinding,
+        data: Vec<u8>,
+        scan_entropy: bool,
+    ) -> Vec<GcsFinding> {
+        let data = self.secret_scanner.preprocess_owned(data);
+        let name = &template.key;
+
+        // Config files are parsed and scanned value by value, falling back to lines on failure
+        if self.secret_scanner.structured {
+            if let Some(format) = StructuredFormat::detect(name, &data) {
+                match structured_scanning::scan_document(
+                    &self.secret_scanner,
+                    &data,
+                    format,
+                    scan_entropy,
+                ) {
+                    Ok(matches) => {
+                        return matches
+                            .into_iter()
+                            .map(|m| GcsFinding {
+                                diff: m.diff,
+                                strings_found: m.strings_found,
+                                reason: m.reason,
+                                key_path: Some(m.key_path),
+                                ..template.clone()
+                            })
+                            .collect();
+                    }
+                    Err(e) => debug!("{}, scanning {} line by line", e, name),
+                }
+            }
+        }
+
+        // Large objects are split into chunks that are scanned in parallel
+        let options = self.secret_scanner.chunk_options;
+        chunked_scanning::scan_lines(&self.secret_scanner, data, options, scan_entropy)
+            .into_iter()
+            .map(|m| GcsFinding {
+                diff: m.diff,
+                strings_found: m.strings_found,
+                reason: m.reason,
+                spans: m.spans,
+                ..template.clone()
+            })
+            .collect()
+    }
+
+    /// Scans each object with `scan_object` using `threads` worker threads, returning the result
+    /// for each object. A panic while scanning an object is returned as an error for that object.
+    pub fn scan_objects(
+        &self,
+        config: &GcsConfig,
+        bucket: &str,
+        objects: Vec<GcsObject>,
+        threads: usize,
+        scan_entropy: bool,
+    ) -> Vec<(GcsObject, Result<Vec<GcsFinding>, SimpleError>)> {
+        progress::add_total(objects.len());
+        let queue = Arc::new(Mutex::new(objects.into_iter()));
+        let (tx, rx) = mpsc::channel();
+        let mut handles = Vec::new();
+        for _ in 0..threads.max(1) {
+            let queue = Arc::clone(&queue);
+            let tx = tx.clone();
+            let scanner = self.clone();
+            let config = config.clone();
+            let bucket = bucket.to_string();
+            handles.push(thread::spawn(move || loop {
+                if shutdown::interrupted() {
+                    break;
+                }
+                let object = match queue.lock().unwrap().next() {
+                    Some(o) => o,
+                    None => break,
+                };
+                let result = panic::catch_unwind(AssertUnwindSafe(|| {
+                    scanner.scan_object(&config, &bucket, &object.name, scan_entropy)
+                }))
+                .unwrap_or_else(|_| {
+                    Err(SimpleError::new(format!(
+                        "Scanning {} panicked",
+                        object.name
+                    )))
+                });
+                if tx.send((object, result)).is_err() {
+                    break;
+                }
+            }));
+        }
+        drop(tx);
+        let results = rx.iter().collect();
+        for handle in handles {
+            if handle.join().is_err() {
+                error!("A scanning thread exited unexpectedly");
+            }
+        }
+        results
+    }
+}
+
+impl Default for GcsScanner {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Builds an API URL from path segments, escaping each one (object names can contain slashes)
+fn api_url(segments: &[&str]) -> Result<Url, SimpleError> {
+    let mut url = try_with!(Url::parse(API_URL), "Invalid API URL");
+    match url.path_segments_mut() {
+        Ok(mut path) => {
+            path.extend(segments);
+        }
+        Err(_) => return Err(SimpleError::new("Invalid API URL")),
+    }
+    Ok(url)
+}
+
+/// Performs an authorized GET. `url` is a full URL.
+fn get(config: &GcsConfig, url: &str) -> Result<Response, SimpleError> {
+    debug!("GET {}", url);
+    let request = network::client().get(url);
+    let request = match config.access_token()? {
+        Some(token) => request.bearer_auth(token),
+        None => request,
+    };
+    let response = try_with!(retry::send(request), "Cloud Storage request failed");
+    if !response.status().is_success() {
+        return Err(SimpleError::new(format!(
+            "Cloud Storage returned {} for {}",
+            response.status(),
+            url
+        )));
+    }
+    Ok(response)
+}
@@ -0,0 +1,947 @@
+//! Collection of tools for scanning GitHub organizations for secrets.
+//!
+//! `GithubScanner` acts as a wrapper around a `SecretScanner` object and talks to the
+//! [GitHub REST API](https://docs.github.com/en/rest) using a personal access token with the
+//! `repo` scope (or a fine-grained token with read access to contents, issues and pull requests).
+//! It can enumerate every repository of an organization and, for each repository, scan:
+//!
+//! * the full history of the repository, cloned over HTTPS and scanned with `GitScanner`
+//! * the history of the repository's wiki, if it has one
+//! * the body of every issue and pull request
+//! * every issue comment, pull request comment and pull request review comment
+//!
+//! Gists are scanned separately with `scan_gists`, including every revision and the gist's
+//! comments. The API only lists a user's public gists, except for the owner of the token, whose
+//! secret gists are listed too. `list_members` returns the members of an organization so that
+//! each member's gists can be scanned.
+//!
+//! List endpoints are paginated by following the `Link` header. GitHub Enterprise Server is
+//! supported by setting the API URL to `https://<host>/api/v3`.
+//!
+//! Repositories can be scanned in parallel with `scan_repositories`, which runs a fixed number of
+//! worker threads that each take the next repository from a shared queue. Setting
+//! `GithubConfig::limiter` additionally limits the number of concurrent API requests, tuning the
+//! limit to the latency and rate limiting of the server (see the `adaptive_concurrency` module).
+//!
+//! `scan_actions_logs` scans the logs of a repository's GitHub Actions workflow runs, where
+//! misconfigured masking often leaves secrets in plain text. Each run's logs are downloaded as a
+//! zip archive and every step's log is scanned. A `WorkflowRunFilter` limits the runs to a date
+//! range or to failed runs. GitHub deletes logs after a retention period (90 days by default).
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use rusty_hogs::github_scanning::{GithubConfig, GithubScanner};
+//!
+//! let gs = GithubScanner::new();
+//! let config = GithubConfig {
+//!     url: "https://api.github.com".to_string(),
+//!     token: "ghp_...".to_string(),
+//!     limiter: None,
+//! };
+//! let repositories = gs.list_repositories(&config, "my-org").unwrap();
+//! for (_, result) in gs.scan_repositories(&config, repositories, true, true, false, 4) {
+//!     gs.secret_scanner.output_findings(&result.unwrap());
+//! }
+//! ```
+
+use crate::adaptive_concurrency::AdaptiveLimiter;
+use crate::git_scanning::{GitScanner, GitScheme};
+use crate::network;
+use crate::retry;
+use crate::shutdown;
+use crate::{Finding, SecretScanner, Span, TextFinding};
+use log::{self, debug, error, info};
+use regex::Regex;
+use reqwest::blocking::Response;
+use reqwest::header::HeaderMap;
+use reqwest::StatusCode;
+use serde_derive::{Deserialize, Serialize};
+use serde_json::Value;
+use simple_error::{try_with, SimpleError};
+use std::collections::HashSet;
+use std::io::{Cursor, Read};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::Path;
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use zip::ZipArchive;
+
+const PER_PAGE: &str = "100";
+
+/// The username GitHub expects when a token is used as an HTTPS git password
+const TOKEN_GIT_USER: &str = "x-access-token";
+
+/// GitHub rejects API requests without a User-Agent
+const USER_AGENT: &str = "rusty-hog";
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Hash, Clone, Default)]
+/// `serde_json` object that represents a single found secret - finding
+pub struct GithubFinding {
+    pub diff: String,
+    #[serde(rename = "stringsFound")]
+    pub strings_found: Vec<String>,
+    /// The full name of the repository, e.g. `my-org/my-repo`, or `<owner>/<id>` for gists
+    pub repository: String,
+    /// One of `repository`, `wiki`, `issue`, `pull_request`, `gist` or `actions_log`
+    pub source: String,
+    /// The file path for repository, wiki and gist findings, `#<number>` for issues and pull
+    /// requests, `comment` for gist comments, or `<job>/<step>.txt` for workflow run logs
+    pub path: String,
+    /// A link to the file, wiki page, issue, pull request, comment or workflow run
+    pub url: String,
+    #[serde(
+        rename = "commitHash",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub commit_hash: Option<String>,
+    /// The email address of the commit author, for repository, wiki and gist findings
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub author: Option<String>,
+    pub date: String,
+    pub reason: String,
+    /// Where each of `strings_found` is in the text, see `Span`
+    #[serde(default)]
+    pub spans: Vec<Span>,
+}
+
+impl TextFinding for GithubFinding {
+    fn with_match(&self, found: Finding) -> Self {
+        Self {
+            diff: found.diff,
+            strings_found: found.strings_found,
+            reason: found.reason,
+            spans: found.spans,
+            ..self.clone()
+        }
+    }
+}
+
+/// The GitHub API to talk to and the token to authenticate with
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+pub struct GithubConfig {
+    /// The base URL of the API, `https://api.github.com` or `https://<host>/api/v3`
+    pub url: String,
+    /// A personal access token or GitHub App installation token
+    pub token: String,
+    /// Limits the number of concurrent API requests, or None for no limit
+    pub limiter: Option<AdaptiveLimiter>,
+}
+
+/// A repository as returned by the GitHub API. Only the fields we need are included.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Hash, Clone)]
+pub struct GithubRepository {
+    pub name: String,
+    pub full_name: String,
+    pub html_url: String,
+    pub clone_url: String,
+    #[serde(default)]
+    pub has_wiki: bool,
+    #[serde(default)]
+    pub archived: bool,
+    #[serde(default)]
+    pub fork: bool,
+    /// The size of the repository in KB, 0 for an empty repository
+    #[serde(default)]
+    pub size: u64,
+}
+
+/// A repository and the result of scanning it, as returned by `scan_repositories`
+pub type RepositoryResult = (
+    GithubRepository,
+    Result<HashSet<GithubFinding>, SimpleError>,
+);
+
+/// A gist as returned by the GitHub API. Only the fields we need are included.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Hash, Clone)]
+pub struct GithubGist {
+    pub id: String,
+    pub html_url: String,
+    pub git_pull_url: String,
+    #[serde(default)]
+    pub public: bool,
+    /// The number of comments on the gist
+    #[serde(default)]
+    pub comments: u64,
+    #[serde(default)]
+    pub owner: Option<GithubUser>,
+}
+
+/// A GitHub Actions workflow run as returned by the GitHub API. Only the fields we need are
+/// included.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Hash, Clone)]
+pub struct GithubWorkflowRun {
+    pub id: u64,
+    /// The name of the workflow
+    #[serde(default)]
+    pub name: Option<String>,
+    pub html_url: String,
+    pub head_sha: String,
+    pub created_at: String,
+    /// `success`, `failure`, `cancelled`, ... or None while the run is in progress
+    #[serde(default)]
+    pub conclusion: Option<String>,
+}
+
+/// Limits the workflow runs returned by `list_workflow_runs`
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Default)]
+pub struct WorkflowRunFilter {
+    /// Only runs created on or after this date (`YYYY-MM-DD`) or UTC time (`YYYY-MM-DDTHH:MM:SSZ`)
+    pub since: Option<String>,
+    /// Only runs created on or before this date (`YYYY-MM-DD`) or UTC time (`YYYY-MM-DDTHH:MM:SSZ`)
+    pub until: Option<String>,
+    /// Only runs that failed
+    pub only_failed: bool,
+}
+
+impl WorkflowRunFilter {
+    /// Returns the query string for the workflow runs endpoint
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rusty_hogs::github_scanning::WorkflowRunFilter;
+    ///
+    /// let filter = WorkflowRunFilter {
+    ///     since: Some("2020-06-01".to_string()),
+    ///     until: Some("2020-06-30".to_string()),
+    ///     only_failed: true,
+    /// };
+    /// assert_eq!(filter.query(), "created=2020-06-01..2020-06-30&status=failure");
+    /// let filter = WorkflowRunFilter {
+    ///     since: Some("2020-06-01".to_string()),
+    ///     ..Default::default()
+    /// };
+    /// assert_eq!(filter.query(), "created=%3E%3D2020-06-01");
+    /// ```
+    pub fn query(&self) -> String {
+        let mut params: Vec<String> = Vec::new();
+        match (&self.since, &self.until) {
+            (Some(s), Some(u)) => params.push(format!("created={}..{}", s, u)),
+            (Some(s), None) => params.push(format!("created=%3E%3D{}", s)),
+            (None, Some(u)) => params.push(format!("created=%3C%3D{}", u)),
+            (None, None) => {}
+        }
+        if self.only_failed {
+            params.push(String::from("status=failure"));
+        }
+        params.join("&")
+    }
+}
+
+/// The owner of a gist. Only the fields we need are included.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Hash, Clone)]
+pub struct GithubUser {
+    pub login: String,
+}
+
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+/// Contains helper functions for performing scans of GitHub repositories
+pub struct GithubScanner {
+    pub secret_scanner: SecretScanner,
+}
+
+/// Acts as a wrapper around a `SecretScanner` object to provide helper functions for performing
+/// scanning against GitHub. Relies on the [reqwest](https://docs.rs/reqwest/) library for HTTP
+/// access.
+impl GithubScanner {
+    /// Initialize the SecretScanner object first using the SecretScannerBuilder, then provide
+    /// it to this constructor method.
+    pub fn new_from_scanner(secret_scanner: SecretScanner) -> Self {
+        Self { secret_scanner }
+    }
+
+    pub fn new() -> Self {
+        Self {
+            secret_scanner: SecretScanner::default(),
+        }
+    }
+
+    /// Returns every repository of the organization `org` that the token can see, including
+    /// forks and archived repositories
+    pub fn list_repositories(
+        &self,
+        config: &GithubConfig,
+        org: &str,
+    ) -> Result<Vec<GithubRepository>, SimpleError> {
+        let repositories = get_values(config, &format!("orgs/{}/repos?type=all", org))?;
+        let repositories: Vec<GithubRepository> = try_with!(
+            serde_json::from_value(Value::Array(repositories)),
+            "Failed to parse the repository list of {}",
+            org
+        );
+        info!("Found {} repositories in {}", repositories.len(), org);
+        Ok(repositories)
+    }
+
+    /// Returns a single repository by its full name, e.g. `my-org/my-repo`
+    pub fn get_repository(
+        &self,
+        config: &GithubConfig,
+        full_name: &str,
+    ) -> Result<GithubRepository, SimpleError> {
+        let response = get(config, &format!("repos/{}", full_name))?;
+        Ok(try_with!(
+            response.json(),
+            "Failed to parse repository {}",
+            full_name
+        ))
+    }
+
+    /// Returns the login of every member of the organization `org` that the token can see
+    pub fn list_members(
+        &self,
+        config: &GithubConfig,
+        org: &str,
+    ) -> Result<Vec<String>, SimpleError> {
+        let members = get_values(config, &format!("orgs/{}/members", org))?;
+        let members: Vec<String> = members
+            .iter()
+            .filter_map(|m| m["login"].as_str().map(String::from))
+            .collect();
+        info!("Found {} members in {}", members.len(), org);
+        Ok(members)
+    }
+
+    /// Returns the public gists of `user`, or every gist (including secret gists) of the owner of
+    /// the token if `user` is None
+    pub fn list_gists(
+        &self,
+        config: &GithubConfig,
+        user: Option<&str>,
+    ) -> Result<Vec<GithubGist>, SimpleError> {
+        let path = match user {
+            Some(u) => format!("users/{}/gists", u),
+            None => String::from("gists"),
+        };
+        let gists = get_values(config, &path)?;
+        let gists: Vec<GithubGist> = try_with!(
+            serde_json::from_value(Value::Array(gists)),
+            "Failed to parse the gist list of {}",
+            user.unwrap_or("the token owner")
+        );
+        debug!(
+            "Found {} gists for {}",
+            gists.len(),
+            user.unwrap_or("the token owner")
+        );
+        Ok(gists)
+    }
+
+    /// Lists the gists of `user` (see `list_gists`) and scans each one, skipping (but reporting)
+    /// gists that fail
+    pub fn scan_gists(
+        &self,
+        config: &GithubConfig,
+        user: Option<&str>,
+        scan_entropy: bool,
+    ) -> Result<HashSet<GithubFinding>, SimpleError> {
+        let mut findings: HashSet<GithubFinding> = HashSet::new();
+        for gist in self.list_gists(config, user)? {
+            match self.scan_gist(config, &gist, scan_entropy) {
+                Ok(f) => findings.extend(f),
+                Err(e) => error!("Failed to scan gist {}: {}", gist.html_url, e),
+            }
+        }
+        Ok(findings)
+    }
+
+    /// Clones a gist and scans every revision, then scans its comments
+    pub fn scan_gist(
+        &self,
+        config: &GithubConfig,
+        gist: &GithubGist,
+        scan_entropy: bool,
+    ) -> Result<HashSet<GithubFinding>, SimpleError> {
+        let owner = match &gist.owner {
+            Some(o) => o.login.as_str(),
+            None => "anonymous",
+        };
+        let name = format!("{}/{}", owner, gist.id);
+        debug!("Scanning gist {}", name);
+        let dest_dir = self.secret_scanner.workspace()?;
+        let git_scanner = GitScanner {
+            repo: Some(clone_with_token(
+                &gist.git_pull_url,
+                dest_dir.path(),
+                &config.token,
+            )?),
+            scheme: Some(GitScheme::Http),
+            ..GitScanner::new_from_scanner(self.secret_scanner.clone())
+        };
+        dest_dir.check_quota()?;
+        let mut findings: HashSet<GithubFinding> = git_scanner
+            .perform_scan(None, None, None, scan_entropy)?
+            .into_iter()
+            .map(|f| GithubFinding {
+                url: format!("{}/{}", gist.html_url, f.commit_hash),
+                diff: f.diff,
+                strings_found: f.strings_found,
+                repository: name.clone(),
+                source: String::from("gist"),
+                path: f.path,
+                commit_hash: Some(f.commit_hash),
+                author: Some(f.author),
+                date: f.date,
+                reason: f.reason,
+                spans: f.spans,
+            })
+            .collect();
+        if gist.comments > 0 {
+            let comments = get_values(config, &format!("gists/{}/comments", gist.id))?;
+            for comment in comments {
+                let body = match comment["body"].as_str() {
+                    Some(b) => b,
+                    None => continue,
+                };
+                let template = GithubFinding {
+                    repository: name.clone(),
+                    source: String::from("gist"),
+                    path: String::from("comment"),
+                    url: format!("{}#gistcomment-{}", gist.html_url, comment["id"]),
+                    date: comment["updated_at"].as_str().unwrap_or("").to_string(),
+                    ..Default::default()
+                };
+                self.secret_scanner.scan_text(
+                    body.as_bytes(),
+                    &template,
+                    scan_entropy,
+                    &mut findings,
+                );
+            }
+        }
+        Ok(findings)
+    }
+
+    /// Returns the workflow runs of `repo` that match `filter`, newest first
+    pub fn list_workflow_runs(
+        &self,
+        config: &GithubConfig,
+        repo: &GithubRepository,
+        filter: &WorkflowRunFilter,
+    ) -> Result<Vec<GithubWorkflowRun>, SimpleError> {
+        let query = filter.query();
+        let path = if query.is_empty() {
+            format!("repos/{}/actions/runs", repo.full_name)
+        } else {
+            format!("repos/{}/actions/runs?{}", repo.full_name, query)
+        };
+        let runs = get_field_values(config, &path, Some("workflow_runs"))?;
+        let runs: Vec<GithubWorkflowRun> = try_with!(
+            serde_json::from_value(Value::Array(runs)),
+            "Failed to parse the workflow runs of {}",
+            repo.full_name
+        );
+        debug!("Found {} workflow runs in {}", runs.len(), repo.full_name);
+        Ok(runs)
+    }
+
+    /// Lists the workflow runs of `repo` that match `filter` and scans the logs of each one,
+    /// skipping (but reporting) runs whose logs can't be downloaded, e.g. because they expired
+    pub fn scan_actions_logs(
+        &self,
+        config: &GithubConfig,
+        repo: &GithubRepository,
+        filter: &WorkflowRunFilter,
+        scan_entropy: bool,
+    ) -> Result<HashSet<GithubFinding>, SimpleError> {
+        let mut findings: HashSet<GithubFinding> = HashSet::new();
+        for run in self.list_workflow_runs(config, repo, filter)? {
+            match self.scan_workflow_run(config, repo, &run, scan_entropy) {
+                Ok(f) => findings.extend(f),
+                Err(e) => error!("Failed to scan the logs of {}: {}", run.html_url, e),
+            }
+        }
+        Ok(findings)
+    }
+
+    /// Downloads the logs of a workflow run and scans the log of each step. The archive also
+    /// holds a combined log per job, which is only scanned for jobs without step logs.
+    pub fn scan_workflow_run(
+        &self,
+        config: &GithubConfig,
+        repo: &GithubRepository,
+        run: &GithubWorkflowRun,
+        scan_entropy: bool,
+    ) -> Result<HashSet<GithubFinding>, SimpleError> {
+        debug!("Scanning the logs of {}", run.html_url);
+        let response = get(
+            config,
+            &format!("repos/{}/actions/runs/{}/logs", repo.full_name, run.id),
+        )?;
+        let data = try_with!(response.bytes(), "Failed to download {}", run.html_url);
+        let mut archive = try_with!(
+            ZipArchive::new(Cursor::new(data.as_ref())),
+            "Failed to open the logs of {}",
+            run.html_url
+        );
+        let mut logs: Vec<(String, Vec<u8>)> = Vec::new();
+        for i in 0..archive.len() {
+            let mut file = try_with!(archive.by_index(i), "Failed to read a log file");
+            if file.is_dir() {
+                continue;
+            }
+            let name = file.name().to_string();
+            let mut log: Vec<u8> = Vec::new();
+            try_with!(file.read_to_end(&mut log), "Failed to read {}", name);
+            logs.push((name, log));
+        }
+        // combined job logs are named "<n>_<job>.txt", next to a "<job>/" directory of step logs
+        let job_dirs: HashSet<String> = logs
+            .iter()
+            .filter(|(name, _)| name.contains('/'))
+            .filter_map(|(name, _)| name.split('/').next())
+            .map(String::from)
+            .collect();
+        let mut findings: HashSet<GithubFinding> = HashSet::new();
+        for (name, log) in logs {
+            if !name.contains('/') {
+                let job = name.trim_end_matches(".txt");
+                let job = job.splitn(2, '_').nth(1).unwrap_or(job);
+                if job_dirs.contains(job) {
+                    continue;
+                }
+            }
+            let template = GithubFinding {
+                repository: repo.full_name.clone(),
+                source: String::from("actions_log"),
+                path: name,
+                url: run.html_url.clone(),
+                commit_hash: Some(run.head_sha.clone()),
+                date: run.created_at.clone(),
+                ..Default::default()
+            };
+            self.secret_scanner
+                .scan_text(&log, &template, scan_entropy, &mut findings);
+        }
+        Ok(findings)
+    }
+
+    /// Scans the issues and pull requests of `repo`, its history unless `scan_history` is false,
+    /// and its wiki unless `scan_wiki` is false, returning a HashSet of findings.
+    pub fn scan_repository(
+        &self,
+        config: &GithubConfig,
+        repo: &GithubRepository,
+        scan_history: bool,
+        scan_wiki: bool,
+        scan_entropy: bool,
+    ) -> Result<HashSet<GithubFinding>, SimpleError> {
+        info!("Scanning repository {}", repo.full_name);
+        let mut findings: HashSet<GithubFinding> = HashSet::new();
+        if scan_history && repo.size > 0 {
+            findings.extend(self.scan_history(config, repo, scan_entropy)?);
+        }
+        if scan_wiki && repo.has_wiki {
+            findings.extend(self.scan_wiki(config, repo, scan_entropy)?);
+        }
+        findings.extend(self.scan_issues(config, repo, scan_entropy)?);
+        findings.extend(self.scan_comments(config, repo, scan_entropy)?);
+        Ok(findings)
+    }
+
+    /// Scans each repository with `scan_repository` using `threads` worker threads. Returns each
+    /// repository with its result, in the order they finished. A repository whose scan panics
+    /// (e.g. because it couldn't be cloned) is returned with an error.
+    pub fn scan_repositories(
+        &self,
+        config: &GithubConfig,
+        repositories: Vec<GithubRepository>,
+        scan_history: bool,
+        scan_wiki: bool,
+        scan_entropy: bool,
+        threads: usize,
+    ) -> Vec<RepositoryResult> {
+        let queue = Arc::new(Mutex::new(repositories.into_iter()));
+        let (tx, rx) = mpsc::channel();
+        let mut handles = Vec::new();
+        for _ in 0..threads.max(1) {
+            let queue = Arc::clone(&queue);
+            let tx = tx.clone();
+            let scanner = self.clone();
+            let config = config.clone();
+            handles.push(thread::spawn(move || loop {
+                if shutdown::interrupted() {
+                    break;
+                }
+                let repo = match queue.lock().unwrap().next() {
+                    Some(r) => r,
+                    None => break,
+                };
+                let result = panic::catch_unwind(AssertUnwindSafe(|| {
+                    scanner.scan_repository(&config, &repo, scan_history, scan_wiki, scan_entropy)
+                }))
+                .unwrap_or_else(|_| {
+                    Err(SimpleError::new(format!(
+                        "Scanning {} panicked",
+                        repo.full_name
+                    )))
+                });
+                if tx.send((repo, result)).is_err() {
+                    break;
+                }
+            }));
+        }
+        drop(tx);
+        let results = rx.iter().collect();
+        for handle in handles {
+            if handle.join().is_err() {
+                error!("A scanning thread exited unexpectedly");
+            }
+        }
+        results
+    }
+
+    /// Clones the repository into a temporary directory and scans every commit with `GitScanner`
+    fn scan_history(
+        &self,
+        config: &GithubConfig,
+        repo: &GithubRepository,
+        scan_entropy: bool,
+    ) -> Result<HashSet<GithubFinding>, SimpleError> {
+        let dest_dir = self.secret_scanner.workspace()?;
+        let git_scanner = GitScanner::new_from_scanner(self.secret_scanner.clone()).init_git_repo(
+            &repo.clone_url,
+            dest_dir.path(),
+            None,
+            None,
+            Some(TOKEN_GIT_USER),
+            Some(&config.token),
+        );
+        dest_dir.check_quota()?;
+        let git_findings = git_scanner.perform_scan(None, None, None, scan_entropy)?;
+        debug!(
+            "Found {} secrets in the history of {}",
+            git_findings.len(),
+            repo.full_name
+        );
+        Ok(git_findings
+            .into_iter()
+            .map(|f| GithubFinding {
+                url: format!("{}/blob/{}/{}", repo.html_url, f.commit_hash, f.path),
+                diff: f.diff,
+                strings_found: f.strings_found,
+                repository: repo.full_name.clone(),
+                source: String::from("repository"),
+                path: f.path,
+                commit_hash: Some(f.commit_hash),
+                author: Some(f.author),
+                date: f.date,
+                reason: f.reason,
+                spans: f.spans,
+            })
+            .collect())
+    }
+
+    /// Clones the wiki repository and scans every commit. `has_wiki` is set for most repositories
+    /// whether or not a wiki page was ever created, so a failed clone is treated as no wiki.
+    fn scan_wiki(
+        &self,
+        config: &GithubConfig,
+        repo: &GithubRepository,
+        scan_entropy: bool,
+    ) -> Result<HashSet<GithubFinding>, SimpleError> {
+        let dest_dir = self.secret_scanner.workspace()?;
+        let wiki_url = format!("{}.wiki.git", repo.clone_url.trim_end_matches(".git"));
+        let wiki_repo = match clone_with_token(&wiki_url, dest_dir.path(), &config.token) {
+            Ok(r) => r,
+            Err(e) => {
+                debug!("No wiki found for {}: {}", repo.full_name, e);
+                return Ok(HashSet::new());
+            }
+        };
+        let git_scanner = GitScanner {
+            repo: Some(wiki_repo),
+            scheme: Some(GitScheme::Http),
+            ..GitScanner::new_from_scanner(self.secret_scanner.clone())
+        };
+        dest_dir.check_quota()?;
+        let git_findings = git_scanner.perform_scan(None, None, None, scan_entropy)?;
+        debug!(
+            "Found {} secrets in the wiki of {}",
+            git_findings.len(),
+            repo.full_name
+        );
+        Ok(git_findings
+            .into_iter()
+            .map(|f| {
+                let page = Path::new(&f.path)
+                    .file_stem()
+                    .map(|s| s.to_string_lossy().to_string())
+                    .unwrap_or_default();
+                GithubFinding {
+                    url: format!("{}/wiki/{}", repo.html_url, page),
+                    diff: f.diff,
+                    strings_found: f.strings_found,
+                    repository: repo.full_name.clone(),
+                    source: String::from("wiki"),
+                    path: f.path,
+                    commit_hash: Some(f.commit_hash),
+                    author: Some(f.author),
+                    date: f.date,
+                    reason: f.reason,
+                    spans: f.spans,
+                }
+            })
+            .collect())
+    }
+
+    /// Scans the body of every issue and pull request
+    fn scan_issues(
+        &self,
+        config: &GithubConfig,
+        repo: &GithubRepository,
+        scan_entropy: bool,
+    ) -> Result<HashSet<GithubFinding>, SimpleError> {
+        let mut findings: HashSet<GithubFinding> = HashSet::new();
+        let issues = get_values(
+            config,
+            &format!("repos/{}/issues?state=all", repo.full_name),
+        )?;
+        debug!(
+            "Scanning {} issues and pull requests in {}",
+            issues.len(),
+            repo.full_name
+        );
+        for issue in issues {
+            let body = match issue["body"].as_str() {
+                Some(b) => b,
+                None => continue,
+            };
+            // the issues endpoint also returns pull requests, marked with a pull_request object
+            let source = if issue["pull_request"].is_object() {
+                "pull_request"
+            } else {
+                "issue"
+            };
+            let template = GithubFinding {
+                repository: repo.full_name.clone(),
+                source: String::from(source),
+                path: format!("#{}", issue["number"]),
+                url: issue["html_url"].as_str().unwrap_or("").to_string(),
+                date: issue["updated_at"].as_str().unwrap_or("").to_string(),
+                ..Default::default()
+            };
+            self.secret_scanner
+                .scan_text(body.as_bytes(), &template, scan_entropy, &mut findings);
+        }
+        Ok(findings)
+    }
+
+    /// Scans every issue and pull request comment, and every pull request review comment
+    fn scan_comments(
+        &self,
+        config: &GithubConfig,
+        repo: &GithubRepository,
+        scan_entropy: bool,
+    ) -> Result<HashSet<GithubFinding>, SimpleError> {
+        let mut findings: HashSet<GithubFinding> = HashSet::new();
+        let issue_comments =
+            get_values(config, &format!("repos/{}/issues/comments", repo.full_name))?;
+        let review_comments =
+            get_values(config, &format!("repos/{}/pulls/comments", repo.full_name))?;
+        debug!(
+            "Scanning {} comments and {} review comments in {}",
+            issue_comments.len(),
+            review_comments.len(),
+            repo.full_name
+        );
+        let comments = issue_comments
+            .iter()
+            .map(|c| (c, "issue_url"))
+            .chain(review_comments.iter().map(|c| (c, "pull_request_url")));
+        for (comment, parent_field) in comments {
+            let body = match comment["body"].as_str() {
+                Some(b) => b,
+                None => continue,
+            };
+            let url = comment["html_url"].as_str().unwrap_or("").to_string();
+            let source = if parent_field == "pull_request_url" || url.contains("/pull/") {
+                "pull_request"
+            } else {
+                "issue"
+            };
+            let number = comment[parent_field]
+                .as_str()
+                .and_then(|u| u.rsplit('/').next())
+                .unwrap_or("");
+            let template = GithubFinding {
+                repository: repo.full_name.clone(),
+                source: String::from(source),
+                path: format!("#{}", number),
+                url,
+                date: comment["updated_at"].as_str().unwrap_or("").to_string(),
+                ..Default::default()
+            };
+            self.secret_scanner
+                .scan_text(body.as_bytes(), &template, scan_entropy, &mut findings);
+        }
+        Ok(findings)
+    }
+}
+
+impl Default for GithubScanner {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Keeps the repositories whose name matches at least one `include` regex (or all of them if
+/// `include` is empty) and none of the `exclude` regexes
+///
+/// # Examples
+///
+/// ```
+/// use regex::Regex;
+/// use rusty_hogs::github_scanning::{filter_repositories, GithubRepository};
+///
+/// let repo = |name: &str| GithubRepository {
+///     name: name.to_string(),
+///     full_name: format!("my-org/{}", name),
+///     html_url: String::new(),
+///     clone_url: String::new(),
+///     has_wiki: false,
+///     archived: false,
+///     fork: false,
+///     size: 1,
+/// };
+/// let repositories = vec![repo("api-server"), repo("api-docs"), repo("website")];
+/// let include = vec![Regex::new("^api-").unwrap()];
+/// let exclude = vec![Regex::new("-docs$").unwrap()];
+/// let filtered = filter_repositories(repositories, &include, &exclude);
+/// assert_eq!(filtered.len(), 1);
+/// assert_eq!(filtered[0].name, "api-server");
+/// ```
+pub fn filter_repositories(
+    repositories: Vec<GithubRepository>,
+    include: &[Regex],
+    exclude: &[Regex],
+) -> Vec<GithubRepository> {
+    repositories
+        .into_iter()
+        .filter(|r| include.is_empty() || include.iter().any(|re| re.is_match(&r.name)))
+        .filter(|r| !exclude.iter().any(|re| re.is_match(&r.name)))
+        .collect()
+}
+
+/// Clones a wiki or gist, returning an error rather than panicking like `GitScanner::init_git_repo`
+/// if it doesn't exist
+fn clone_with_token(
+    url: &str,
+    dest_dir: &Path,
+    token: &str,
+) -> Result<git2::Repository, SimpleError> {
+    let mut cb = git2::RemoteCallbacks::new();
+    cb.credentials(|_, _, _| git2::Cred::userpass_plaintext(TOKEN_GIT_USER, token));
+    let mut fo = git2::FetchOptions::new();
+    fo.remote_callbacks(cb);
+    fo.proxy_options(network::git_proxy_options());
+    let mut builder = git2::build::RepoBuilder::new();
+    builder.fetch_options(fo);
+    Ok(try_with!(
+        builder.clone(url, dest_dir),
+        "Failed to clone {}",
+        url
+    ))
+}
+
+/// Performs an authenticated GET against the GitHub API. `path` is relative to the API URL, or a
+/// full URL taken from a `Link` header.
+fn get(config: &GithubConfig, path: &str) -> Result<Response, SimpleError> {
+    let url = if path.starts_with("http://") || path.starts_with("https://") {
+        path.to_string()
+    } else {
+        format!("{}/{}", config.url.trim_end_matches('/'), path)
+    };
+    debug!("GET {}", url);
+    let permit = config.limiter.as_ref().map(AdaptiveLimiter::acquire);
+    let response = retry::send(
+        network::client()
+            .get(&url)
+            .header("Authorization", format!("token {}", config.token))
+            .header("Accept", "application/vnd.github.v3+json")
+            .header("User-Agent", USER_AGENT),
+    );
+    if let Some(permit) = permit {
+        match &response {
+            Ok(r) if !is_rate_limited(r) && !r.status().is_server_error() => permit.success(),
+            _ => permit.overloaded(),
+        }
+    }
+    let response = try_with!(response, "GitHub request failed");
+    if !response.status().is_success() {
+        return Err(SimpleError::new(format!(
+            "GitHub returned {} for {}",
+            response.status(),
+            url
+        )));
+    }
+    Ok(response)
+}
+
+/// True if GitHub rejected the request because of a primary or secondary rate limit
+fn is_rate_limited(response: &Response) -> bool {
+    let status = response.status();
+    status == StatusCode::TOO_MANY_REQUESTS
+        || (status == StatusCode::FORBIDDEN
+            && (response.headers().contains_key("Retry-After")
+                || response
+                    .headers()
+                    .get("X-RateLimit-Remaining")
+                    .map_or(false, |v| v == "0")))
+}
+
+/// Follows the `rel="next"` links of a list endpoint and returns the items of every page
+fn get_values(config: &GithubConfig, path: &str) -> Result<Vec<Value>, SimpleError> {
+    get_field_values(config, path, None)
+}
+
+/// Like `get_values`, for endpoints that return the items of each page in the `field` array of an
+/// object rather than as a top-level array
+fn get_field_values(
+    config: &GithubConfig,
+    path: &str,
+    field: Option<&str>,
+) -> Result<Vec<Value>, SimpleError> {
+    let separator = if path.contains('?') { '&' } else { '?' };
+    let mut url = format!("{}{}per_page={}", path, separator, PER_PAGE);
+    let mut values: Vec<Value> = Vec::new();
+    loop {
+        let response = get(config, &url)?;
+        let next = next_link(response.headers());
+        let page: Value = try_with!(response.json(), "Failed to parse GitHub response");
+        let page = match field {
+            Some(f) => page[f].clone(),
+            None => page,
+        };
+        match page {
+            Value::Array(items) => values.extend(items),
+            _ => {
+                return Err(SimpleError::new(
+                    "Unexpected GitHub response, expected a list",
+                ))
+            }
+        }
+        match next {
+            Some(n) => url = n,
+            None => break,
+        }
+    }
+    Ok(values)
+}
+
+/// Returns the `rel="next"` URL of a `Link` header, if there is one
+fn next_link(headers: &HeaderMap) -> Option<String> {
+    let link = headers.get("Link")?.to_str().ok()?;
+    link.split(',')
+        .map(str::trim)
+        .find(|l| l.ends_with("rel=\"next\""))
+        .and_then(|l| l.split(';').next())
+        .map(|u| {
+            u.trim()
+                .trim_start_matches('<')
+                .trim_end_matches('>')
+                .to_string()
+        })
+}
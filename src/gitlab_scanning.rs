@@ -0,0 +1,387 @@
+//! Collection of tools for scanning GitLab projects for secrets.
+//!
+//! `GitlabScanner` acts as a wrapper around a `SecretScanner` object and talks to the
+//! [GitLab REST API](https://docs.gitlab.com/ee/api/) using a personal access token with the
+//! `read_api` and `read_repository` scopes. It can enumerate every project in a group (including
+//! subgroups) and, for each project, scan:
+//!
+//! * the full history of the repository, cloned over HTTPS and scanned with `GitScanner`
+//! * the contents of the project's snippets
+//! * the description and comments of every merge request
+//!
+//! All list endpoints are paginated with the `X-Next-Page` header, so groups with more than one
+//! page of projects, snippets or merge requests are scanned completely.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use rusty_hogs::gitlab_scanning::{GitlabConfig, GitlabScanner};
+//!
+//! let gs = GitlabScanner::new();
+//! let config = GitlabConfig {
+//!     url: "https://gitlab.com".to_string(),
+//!     token: "glpat-...".to_string(),
+//! };
+//! for project in gs.list_projects(&config, "my-group").unwrap() {
+//!     let findings = gs.scan_project(&config, &project, true, false).unwrap();
+//!     gs.secret_scanner.output_findings(&findings);
+//! }
+//! ```
+
+use crate::git_scanning::GitScanner;
+use crate::network;
+use crate::retry;
+use crate::{Finding, SecretScanner, Span, TextFinding};
+use log::{self, debug, info};
+use reqwest::blocking::Response;
+use serde_derive::{Deserialize, Serialize};
+use serde_json::Value;
+use simple_error::{try_with, SimpleError};
+use std::collections::HashSet;
+use url::form_urlencoded;
+
+const PER_PAGE: &str = "100";
+
+/// The username GitLab expects when a personal access token is used as an HTTPS git password
+const TOKEN_GIT_USER: &str = "oauth2";
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Hash, Clone, Default)]
+/// `serde_json` object that represents a single found secret - finding
+pub struct GitlabFinding {
+    pub diff: String,
+    #[serde(rename = "stringsFound")]
+    pub strings_found: Vec<String>,
+    /// The full path of the project, e.g. `my-group/my-project`
+    pub project: String,
+    /// One of `repository`, `snippet` or `merge_request`
+    pub source: String,
+    /// The file path for repository findings, the snippet file name, or `!<iid>` for merge requests
+    pub path: String,
+    /// A link to the file, snippet, merge request description or comment
+    pub url: String,
+    #[serde(
+        rename = "commitHash",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub commit_hash: Option<String>,
+    /// The email address of the commit author, for repository findings
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub author: Option<String>,
+    pub date: String,
+    pub reason: String,
+    /// Where each of `strings_found` is in the text, see `Span`
+    #[serde(default)]
+    pub spans: Vec<Span>,
+}
+
+impl TextFinding for GitlabFinding {
+    fn with_match(&self, found: Finding) -> Self {
+        Self {
+            diff: found.diff,
+            strings_found: found.strings_found,
+            reason: found.reason,
+            spans: found.spans,
+            ..self.clone()
+        }
+    }
+}
+
+/// The GitLab instance to talk to and the token to authenticate with
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+pub struct GitlabConfig {
+    /// The base URL of the instance, e.g. `https://gitlab.com` or `https://gitlab.example.com`
+    pub url: String,
+    /// A personal, group or project access token
+    pub token: String,
+}
+
+/// A project as returned by the GitLab API. Only the fields we need are included.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Hash, Clone)]
+pub struct GitlabProject {
+    pub id: u64,
+    pub path_with_namespace: String,
+    pub web_url: String,
+    pub http_url_to_repo: String,
+    #[serde(default)]
+    pub empty_repo: bool,
+}
+
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+/// Contains helper functions for performing scans of GitLab projects
+pub struct GitlabScanner {
+    pub secret_scanner: SecretScanner,
+}
+
+/// Acts as a wrapper around a `SecretScanner` object to provide helper functions for performing
+/// scanning against GitLab. Relies on the [reqwest](https://docs.rs/reqwest/) library for HTTP
+/// access.
+impl GitlabScanner {
+    /// Initialize the SecretScanner object first using the SecretScannerBuilder, then provide
+    /// it to this constructor method.
+    pub fn new_from_scanner(secret_scanner: SecretScanner) -> Self {
+        Self { secret_scanner }
+    }
+
+    pub fn new() -> Self {
+        Self {
+            secret_scanner: SecretScanner::default(),
+        }
+    }
+
+    /// Returns every project in `group` (a numeric ID or a full path such as
+    /// `my-group/subgroup`), including the projects of its subgroups. Archived projects are
+    /// included.
+    pub fn list_projects(
+        &self,
+        config: &GitlabConfig,
+        group: &str,
+    ) -> Result<Vec<GitlabProject>, SimpleError> {
+        let path = format!(
+            "groups/{}/projects?include_subgroups=true&with_shared=false",
+            encode_id(group)
+        );
+        let projects = get_paginated(config, &path)?;
+        let projects: Vec<GitlabProject> = try_with!(
+            serde_json::from_value(Value::Array(projects)),
+            "Failed to parse the project list of group {}",
+            group
+        );
+        info!("Found {} projects in group {}", projects.len(), group);
+        Ok(projects)
+    }
+
+    /// Returns a single project by numeric ID or full path
+    pub fn get_project(
+        &self,
+        config: &GitlabConfig,
+        project: &str,
+    ) -> Result<GitlabProject, SimpleError> {
+        let response = get(config, &format!("projects/{}", encode_id(project)))?;
+        Ok(try_with!(
+            response.json(),
+            "Failed to parse project {}",
+            project
+        ))
+    }
+
+    /// Scans the snippets and merge requests of `project`, and its repository history unless
+    /// `scan_repository` is false, returning a HashSet of findings.
+    pub fn scan_project(
+        &self,
+        config: &GitlabConfig,
+        project: &GitlabProject,
+        scan_repository: bool,
+        scan_entropy: bool,
+    ) -> Result<HashSet<GitlabFinding>, SimpleError> {
+        info!("Scanning project {}", project.path_with_namespace);
+        let mut findings: HashSet<GitlabFinding> = HashSet::new();
+        if scan_repository && !project.empty_repo {
+            findings.extend(self.scan_repository(config, project, scan_entropy)?);
+        }
+        findings.extend(self.scan_snippets(config, project, scan_entropy)?);
+        findings.extend(self.scan_merge_requests(config, project, scan_entropy)?);
+        Ok(findings)
+    }
+
+    /// Clones the repository into a temporary directory and scans every commit with `GitScanner`
+    fn scan_repository(
+        &self,
+        config: &GitlabConfig,
+        project: &GitlabProject,
+        scan_entropy: bool,
+    ) -> Result<HashSet<GitlabFinding>, SimpleError> {
+        let dest_dir = self.secret_scanner.workspace()?;
+        let git_scanner = GitScanner::new_from_scanner(self.secret_scanner.clone()).init_git_repo(
+            &project.http_url_to_repo,
+            dest_dir.path(),
+            None,
+            None,
+            Some(TOKEN_GIT_USER),
+            Some(&config.token),
+        );
+        dest_dir.check_quota()?;
+        let git_findings = git_scanner.perform_scan(None, None, None, scan_entropy)?;
+        debug!(
+            "Found {} secrets in the repository of {}",
+            git_findings.len(),
+            project.path_with_namespace
+        );
+        Ok(git_findings
+            .into_iter()
+            .map(|f| GitlabFinding {
+                url: format!("{}/-/blob/{}/{}", project.web_url, f.commit_hash, f.path),
+                diff: f.diff,
+                strings_found: f.strings_found,
+                project: project.path_with_namespace.clone(),
+                source: String::from("repository"),
+                path: f.path,
+                commit_hash: Some(f.commit_hash),
+                author: Some(f.author),
+                date: f.date,
+                reason: f.reason,
+                spans: f.spans,
+            })
+            .collect())
+    }
+
+    /// Downloads and scans the raw content of each of the project's snippets
+    fn scan_snippets(
+        &self,
+        config: &GitlabConfig,
+        project: &GitlabProject,
+        scan_entropy: bool,
+    ) -> Result<HashSet<GitlabFinding>, SimpleError> {
+        let mut findings: HashSet<GitlabFinding> = HashSet::new();
+        let snippets = get_paginated(config, &format!("projects/{}/snippets", project.id))?;
+        for snippet in snippets {
+            let id = match snippet["id"].as_u64() {
+                Some(id) => id,
+                None => continue,
+            };
+            let response = get(
+                config,
+                &format!("projects/{}/snippets/{}/raw", project.id, id),
+            )?;
+            let content = try_with!(response.bytes(), "Failed to download snippet {}", id);
+            let template = GitlabFinding {
+                project: project.path_with_namespace.clone(),
+                source: String::from("snippet"),
+                path: snippet["file_name"].as_str().unwrap_or("").to_string(),
+                url: snippet["web_url"].as_str().unwrap_or("").to_string(),
+                date: snippet["updated_at"].as_str().unwrap_or("").to_string(),
+                ..Default::default()
+            };
+            self.secret_scanner
+                .scan_text(&content, &template, scan_entropy, &mut findings);
+        }
+        Ok(findings)
+    }
+
+    /// Scans the description and every non-system comment of each merge request
+    fn scan_merge_requests(
+        &self,
+        config: &GitlabConfig,
+        project: &GitlabProject,
+        scan_entropy: bool,
+    ) -> Result<HashSet<GitlabFinding>, SimpleError> {
+        let mut findings: HashSet<GitlabFinding> = HashSet::new();
+        let merge_requests = get_paginated(
+            config,
+            &format!("projects/{}/merge_requests?state=all", project.id),
+        )?;
+        debug!(
+            "Scanning {} merge requests in {}",
+            merge_requests.len(),
+            project.path_with_namespace
+        );
+        for mr in merge_requests {
+            let iid = match mr["iid"].as_u64() {
+                Some(iid) => iid,
+                None => continue,
+            };
+            let mr_url = mr["web_url"].as_str().unwrap_or("").to_string();
+            let template = GitlabFinding {
+                project: project.path_with_namespace.clone(),
+                source: String::from("merge_request"),
+                path: format!("!{}", iid),
+                ..Default::default()
+            };
+            if let Some(description) = mr["description"].as_str() {
+                let template = GitlabFinding {
+                    url: mr_url.clone(),
+                    date: mr["updated_at"].as_str().unwrap_or("").to_string(),
+                    ..template.clone()
+                };
+                self.secret_scanner.scan_text(
+                    description.as_bytes(),
+                    &template,
+                    scan_entropy,
+                    &mut findings,
+                );
+            }
+            let notes = get_paginated(
+                config,
+                &format!("projects/{}/merge_requests/{}/notes", project.id, iid),
+            )?;
+            for note in notes {
+                if note["system"].as_bool().unwrap_or(false) {
+                    continue;
+                }
+                let body = match note["body"].as_str() {
+                    Some(b) => b,
+                    None => continue,
+                };
+                let template = GitlabFinding {
+                    url: format!("{}#note_{}", mr_url, note["id"]),
+                    date: note["updated_at"].as_str().unwrap_or("").to_string(),
+                    ..template.clone()
+                };
+                self.secret_scanner.scan_text(
+                    body.as_bytes(),
+                    &template,
+                    scan_entropy,
+                    &mut findings,
+                );
+            }
+        }
+        Ok(findings)
+    }
+}
+
+impl Default for GitlabScanner {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// URL-encodes a group or project path so it can be used in place of a numeric ID
+fn encode_id(id: &str) -> String {
+    form_urlencoded::byte_serialize(id.as_bytes()).collect()
+}
+
+/// Performs an authenticated GET against the GitLab API. `path` is relative to `/api/v4/`.
+fn get(config: &GitlabConfig, path: &str) -> Result<Response, SimpleError> {
+    let url = format!("{}/api/v4/{}", config.url.trim_end_matches('/'), path);
+    debug!("GET {}", url);
+    let response = try_with!(
+        retry::send(
+            network::client()
+                .get(&url)
+                .header("PRIVATE-TOKEN", config.token.as_str())
+        ),
+        "GitLab request failed"
+    );
+    if !response.status().is_success() {
+        return Err(SimpleError::new(format!(
+            "GitLab returned {} for {}",
+            response.status(),
+            url
+        )));
+    }
+    Ok(response)
+}
+
+/// Follows the `X-Next-Page` header of a list endpoint and returns the items of every page
+fn get_paginated(config: &GitlabConfig, path: &str) -> Result<Vec<Value>, SimpleError> {
+    let separator = if path.contains('?') { '&' } else { '?' };
+    let mut items: Vec<Value> = Vec::new();
+    let mut page = String::from("1");
+    loop {
+        let response = get(
+            config,
+            &format!("{}{}per_page={}&page={}", path, separator, PER_PAGE, page),
+        )?;
+        let next_page = match response.headers().get("X-Next-Page") {
+            Some(h) => h.to_str().unwrap_or("").to_string(),
+            None => String::new(),
+        };
+        let page_items: Vec<Value> = try_with!(response.json(), "Failed to parse GitLab response");
+        items.extend(page_items);
+        if next_page.is_empty() {
+            break;
+        }
+        page = next_page;
+    }
+    Ok(items)
+}
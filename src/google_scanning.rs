@@ -25,13 +25,14 @@
 //! ```
 //!
 //! The next step is to create an authenticated [`DriveHub`] object and use it to create a
-//! [`GDriveFileInfo`] object.
+//! [`GDriveFileInfo`] object. The hub is authorized with a [`GDriveAuth`], either an OAuth client,
+//! which asks a user to sign in the first time, or a service account for unattended scans.
 //!
 //! Lastly, pass all these objects to the [`perform_scan`] method of [`GDriveScanner`].
 //!
 //! ```no_run
 //! use rusty_hogs::SecretScannerBuilder;
-//! use rusty_hogs::google_scanning::{GDriveScanner, GDriveFileInfo};
+//! use rusty_hogs::google_scanning::{GDriveAuth, GDriveScanner, GDriveFileInfo};
 //! # use yup_oauth2::{ApplicationSecret, DiskTokenStorage, Authenticator, DefaultAuthenticatorDelegate, FlowType};
 //! # use std::path::Path;
 //! use google_drive3::DriveHub;
@@ -58,7 +59,7 @@
 //!     hyper::Client::with_connector(hyper::net::HttpsConnector::new(
 //!         hyper_rustls::TlsClient::new(),
 //!     )),
-//!     auth,
+//!     GDriveAuth::Installed(auth),
 //! );
 //!
 //! // get some initial info about the file
@@ -69,24 +70,99 @@
 //! gdrive_scanner.secret_scanner.output_findings(&findings);
 //! ```
 //!
+//! To scan a folder or a shared drive, list its files with [`list_folder`] or
+//! [`list_shared_drive`] and scan them on several threads with [`scan_files`]. To only scan the
+//! files changed since a previous scan, save a [`start_page_token`] and later pass it to
+//! [`list_changes`].
+//!
 //! [`SecretScanner`]: ../struct.SecretScanner.html
 //! [`google_drive3`]: https://docs.rs/google-drive3/1.0.12+20190620/google_drive3/
 //! [`DriveHub`]: https://docs.rs/google-drive3/1.0.12+20190620/google_drive3/struct.DriveHub.html
 //! [`GDriveScanner`]: struct.GDriveScanner.html
+//! [`GDriveAuth`]: enum.GDriveAuth.html
 //! [`GDriveFileInfo`]: struct.GDriveFileInfo.html
 //! [`perform_scan`]: struct.GDriveScanner.html#method.perform_scan
+//! [`list_folder`]: struct.GDriveScanner.html#method.list_folder
+//! [`list_shared_drive`]: struct.GDriveScanner.html#method.list_shared_drive
+//! [`scan_files`]: struct.GDriveScanner.html#method.scan_files
+//! [`start_page_token`]: struct.GDriveScanner.html#method.start_page_token
+//! [`list_changes`]: struct.GDriveScanner.html#method.list_changes
 
+use crate::decoding::{self, ZIP_MAGIC};
+use crate::network;
+use crate::progress;
+use crate::retry;
+use crate::shutdown;
 use crate::SecretScanner;
 use encoding::all::ASCII;
 use encoding::{DecoderTrap, Encoding};
-use google_drive3::{DriveHub, Scope};
+use google_drive3::{
+    Delegate, DriveHub, File, JsonServerError, MethodInfo, Retry, Scope, ServerError,
+};
 use hyper::Client;
+use log::{self, debug, error, info};
+use reqwest::StatusCode;
 use serde_derive::{Deserialize, Serialize};
-use simple_error::SimpleError;
+use simple_error::{require_with, try_with, SimpleError};
 use std::collections::HashSet;
+use std::error::Error;
 use std::io::Read;
 use std::iter::FromIterator;
-use yup_oauth2::{Authenticator, DefaultAuthenticatorDelegate, DiskTokenStorage};
+use std::panic::{self, AssertUnwindSafe};
+use std::str;
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use yup_oauth2::{
+    Authenticator, DefaultAuthenticatorDelegate, DiskTokenStorage, GetToken, ServiceAccountAccess,
+    Token,
+};
+
+/// An authorized `DriveHub`, see the module documentation for how to create one
+pub type GDriveHub = DriveHub<Client, GDriveAuth>;
+
+/// How a `GDriveHub` is authorized
+pub enum GDriveAuth {
+    /// An OAuth client, which runs the interactive flow if its token storage has no token yet
+    Installed(Authenticator<DefaultAuthenticatorDelegate, DiskTokenStorage, Client>),
+    /// A service account, which needs no user and so can run on a schedule. Files are only
+    /// visible to it if they are shared with its email address, or if it impersonates a user
+    /// with domain-wide delegation (see `service_account`).
+    ServiceAccount(ServiceAccountAccess<Client>),
+}
+
+/// The metadata requested for each file
+const FILE_FIELDS: &str =
+    "kind, id, name, mimeType, webViewLink, modifiedTime, parents, owners(emailAddress)";
+
+/// The MIME type of Google Drive folders
+pub const FOLDER_MIME_TYPE: &str = "application/vnd.google-apps.folder";
+
+/// The formats native Google files are exported to for scanning. Sheets and Slides are exported
+/// as Office documents so that they are scanned one sheet (tab) or slide at a time, as the CSV
+/// export only contains the first sheet.
+const EXPORT_MIME_TYPES: &[(&str, &str)] = &[
+    ("application/vnd.google-apps.document", "text/plain"),
+    (
+        "application/vnd.google-apps.spreadsheet",
+        "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet",
+    ),
+    (
+        "application/vnd.google-apps.presentation",
+        "application/vnd.openxmlformats-officedocument.presentationml.presentation",
+    ),
+    ("application/vnd.google-apps.drawing", "image/svg+xml"),
+    (
+        "application/vnd.google-apps.script",
+        "application/vnd.google-apps.script+json",
+    ),
+];
+
+/// Microsoft Office documents (docx, xlsx and pptx) stored in Google Drive
+const OOXML_MIME_TYPES: &[&str] = &[
+    "application/vnd.openxmlformats-officedocument.wordprocessingml.document",
+    "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet",
+    "application/vnd.openxmlformats-officedocument.presentationml.presentation",
+];
 
 #[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Hash, Clone, Default)]
 /// `serde_json` object that represents a single found secret - finding
@@ -100,7 +176,9 @@ use yup_oauth2::{Authenticator, DefaultAuthenticatorDelegate, DiskTokenStorage};
 ///    strings_found: Vec::new(),
 ///    g_drive_id: String::from("GDrive file ID"),
 ///    reason: String::from("Regex description"),
-///    web_link: String::from("http://drive.google.com/docs/gdriveid")
+///    web_link: String::from("http://drive.google.com/docs/gdriveid"),
+///    document_part: Some(String::from("Sheet: Passwords")),
+///    owner: String::from("owner@example.com")
 /// };
 /// ```
 pub struct GDriveFinding {
@@ -112,6 +190,16 @@ pub struct GDriveFinding {
     pub g_drive_id: String,
     pub reason: String,
     pub web_link: String,
+    /// The sheet, slide or other part of an office document that the secret was found in
+    #[serde(
+        rename = "documentPart",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub document_part: Option<String>,
+    /// The email address of the file's owner, empty for files in shared drives
+    #[serde(default)]
+    pub owner: String,
 }
 
 #[derive(Debug, PartialEq, Eq, Hash, Clone)]
@@ -133,39 +221,129 @@ pub struct GDriveScanner {
 /// let gdfi: GDriveFileInfo = GDriveFileInfo {
 ///   file_id: String::from("GDrive file ID"),
 ///    mime_type: String::from("MIME"),
+///    export: false,
 ///    modified_time: String::from("context around finding"),
 ///    web_link: String::from("context around finding"),
 ///    parents: Vec::new(),
 ///    name: String::from("context around finding"),
-///    path: String::from("context around finding")
+///    path: String::from("context around finding"),
+///    owner: String::from("owner@example.com")
 /// };
 /// ```
 pub struct GDriveFileInfo {
     pub file_id: String,
+    /// The format the file is scanned in
     pub mime_type: String,
+    /// True for native Google files (Docs, Sheets, Slides...), which are exported to `mime_type`
+    /// rather than downloaded
+    pub export: bool,
     pub modified_time: String,
     pub web_link: String,
     pub parents: Vec<String>,
     pub name: String,
     pub path: String,
+    pub owner: String,
 }
 
-impl GDriveFileInfo {
+impl GDriveAuth {
+    /// Reads a service account key file (JSON). With a `subject`, files are accessed as that user,
+    /// which requires domain-wide delegation of the Drive read-only scope to the service account.
+    pub fn service_account(key_file: &str, subject: Option<&str>) -> Result<Self, SimpleError> {
+        let key = try_with!(
+            yup_oauth2::service_account_key_from_file(&String::from(key_file)),
+            "Failed to read the service account key {}",
+            key_file
+        );
+        let client = network::hyper_client()?;
+        Ok(GDriveAuth::ServiceAccount(ServiceAccountAccess::with_sub(
+            key,
+            client,
+            subject.map(String::from),
+        )))
+    }
+}
+
+impl GetToken for GDriveAuth {
+    fn token<'b, I, T>(&mut self, scopes: I) -> Result<Token, Box<dyn Error>>
+    where
+        T: AsRef<str> + Ord + 'b,
+        I: IntoIterator<Item = &'b T>,
+    {
+        match self {
+            GDriveAuth::Installed(auth) => auth.token(scopes),
+            GDriveAuth::ServiceAccount(auth) => auth.token(scopes),
+        }
+    }
 
+    fn api_key(&mut self) -> Option<String> {
+        match self {
+            GDriveAuth::Installed(auth) => auth.api_key(),
+            GDriveAuth::ServiceAccount(auth) => auth.api_key(),
+        }
+    }
+}
+
+/// Retries the Drive API requests that were rate limited or hit an unavailable server, and waits
+/// for the rate limit before each request, following the policy of the `retry` module
+#[derive(Debug, Default)]
+struct RetryDelegate {
+    attempts: u32,
+}
+
+impl Delegate for RetryDelegate {
+    fn begin(&mut self, _: MethodInfo) {
+        retry::wait_for_rate_limit();
+    }
+
+    fn http_failure(
+        &mut self,
+        response: &hyper::client::Response,
+        _: Option<JsonServerError>,
+        error: Option<ServerError>,
+    ) -> Retry {
+        let status = StatusCode::from_u16(response.status.to_u16())
+            .unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+        let retry_after = response
+            .headers
+            .get_raw("Retry-After")
+            .and_then(|v| v.first())
+            .and_then(|v| str::from_utf8(v).ok());
+        // Drive reports most rate limits as 403 userRateLimitExceeded rather than 429
+        let rate_limited = error.map_or(false, |e| {
+            e.errors
+                .iter()
+                .any(|m| m.reason.to_lowercase().ends_with("ratelimitexceeded"))
+        });
+        let status = if rate_limited {
+            StatusCode::TOO_MANY_REQUESTS
+        } else {
+            status
+        };
+        match retry::retry_delay(status, retry_after, self.attempts) {
+            Some(delay) => {
+                debug!(
+                    "Google Drive returned {}, retrying in {:.1}s",
+                    status,
+                    delay.as_secs_f64()
+                );
+                self.attempts += 1;
+                Retry::After(delay)
+            }
+            None => Retry::Abort,
+        }
+    }
+}
+
+impl GDriveFileInfo {
     /// Construct a `GDriveFileInfo` object from a Google Drive File ID and an authorized `DriveHub` object
-    pub fn new(
-        file_id: &str,
-        hub: &DriveHub<
-            Client,
-            Authenticator<DefaultAuthenticatorDelegate, DiskTokenStorage, Client>,
-        >,
-    ) -> Result<Self, SimpleError> {
-        let fields = "kind, id, name, mimeType, webViewLink, modifiedTime, parents";
+    pub fn new(file_id: &str, hub: &GDriveHub) -> Result<Self, SimpleError> {
         let hub_result = hub
             .files()
             .get(file_id)
+            .supports_all_drives(true)
             .add_scope(Scope::Readonly)
-            .param("fields", fields)
+            .param("fields", FILE_FIELDS)
+            .delegate(&mut RetryDelegate::default())
             .doit();
         let (_, file_object) = match hub_result {
             Ok(x) => x,
@@ -176,30 +354,85 @@ impl GDriveFileInfo {
                 )))
             }
         };
+        Self::from_file(file_object, None)
+    }
 
+    /// Construct a `GDriveFileInfo` object from the metadata returned by the Drive API. `folder`
+    /// is the path of the folder the file was listed in, if known, otherwise the path is made of
+    /// the IDs of the file's parents.
+    fn from_file(file_object: File, folder: Option<&str>) -> Result<Self, SimpleError> {
         // initialize some variables from the response
-        let modified_time = file_object.modified_time.unwrap().clone();
-        let web_link = file_object.web_view_link.unwrap();
+        let file_id = file_object.id.unwrap_or_default();
+        let modified_time = file_object.modified_time.unwrap_or_default();
+        let web_link = file_object.web_view_link.unwrap_or_default();
         let parents = file_object.parents.unwrap_or_else(Vec::new); //TODO: add code to map from id -> name
-        let name = file_object.name.unwrap();
-        let path = format!("{}/{}", parents.join("/"), name);
-        let mime_type = match file_object.mime_type.unwrap().as_ref() {
-            "application/vnd.google-apps.spreadsheet" => "text/csv", //TODO: Support application/x-vnd.oasis.opendocument.spreadsheet https://github.com/tafia/calamine
-            "application/vnd.google-apps.document" => "text/plain",
-            u => return Err(SimpleError::new(format!("unknown doc type {}", u))),
+        let name = file_object.name.unwrap_or_default();
+        let path = match folder {
+            Some(f) => format!("{}/{}", f, name),
+            None => format!("{}/{}", parents.join("/"), name),
+        };
+        let owner = file_object
+            .owners
+            .unwrap_or_else(Vec::new)
+            .into_iter()
+            .find_map(|o| o.email_address)
+            .unwrap_or_default();
+        let file_mime_type = file_object.mime_type.unwrap_or_default();
+        let export = EXPORT_MIME_TYPES
+            .iter()
+            .find(|(native, _)| *native == file_mime_type)
+            .map(|(_, format)| *format);
+        let mime_type = match (export, file_mime_type.as_ref()) {
+            (Some(format), _) => format, // exported, then split into parts if it's an Office document
+            #[cfg(feature = "pdf")]
+            (None, "application/pdf") => "application/pdf", // downloaded as-is, text extracted before scanning
+            (None, m) if OOXML_MIME_TYPES.contains(&m) => m, // downloaded as-is, split into parts
+            (None, u) => return Err(SimpleError::new(format!("unknown doc type {}", u))),
         };
         Ok(Self {
-            file_id: file_id.to_owned(),
+            file_id,
             mime_type: mime_type.to_owned(),
+            export: export.is_some(),
             modified_time,
             web_link,
             parents,
             name,
             path,
+            owner,
         })
     }
 }
 
+/// Selects the files of a folder to scan by their Google Drive MIME type (e.g.
+/// `application/vnd.google-apps.document` rather than the format it is exported to). Each filter
+/// is a MIME type or a prefix of one, such as `application/vnd.google-apps.`, and an empty
+/// `include` list includes every type.
+///
+/// ```
+/// # use rusty_hogs::google_scanning::MimeFilter;
+/// let filter = MimeFilter {
+///     include: vec![String::from("application/vnd.google-apps.")],
+///     exclude: vec![String::from("application/vnd.google-apps.spreadsheet")],
+/// };
+/// assert!(filter.matches("application/vnd.google-apps.document"));
+/// assert!(!filter.matches("application/vnd.google-apps.spreadsheet"));
+/// assert!(!filter.matches("application/pdf"));
+/// assert!(MimeFilter::default().matches("application/pdf"));
+/// ```
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Default)]
+pub struct MimeFilter {
+    pub include: Vec<String>,
+    pub exclude: Vec<String>,
+}
+
+impl MimeFilter {
+    /// Returns true if files of this MIME type should be scanned
+    pub fn matches(&self, mime_type: &str) -> bool {
+        let matches_any = |prefixes: &[String]| prefixes.iter().any(|m| mime_type.starts_with(m));
+        (self.include.is_empty() || matches_any(&self.include)) && !matches_any(&self.exclude)
+    }
+}
+
 /// Acts as a wrapper around a `SecretScanner` object to provide helper functions for performing
 /// scanning against Google Drive files. Relies on the [`google_drive3`](https://docs.rs/google-drive3/1.0.10+20190620/google_drive3/)
 /// library which provides a wrapper around the Google Drive v3 API.
@@ -212,19 +445,323 @@ impl GDriveScanner {
 
     pub fn new() -> Self { Self { secret_scanner: SecretScanner::default() } }
 
+    /// Lists the files in a Google Drive folder that `filter` selects, and with `recursive` the
+    /// files in its subfolders too. Files whose type can't be scanned are skipped. Paths start
+    /// with the name of the folder.
+    pub fn list_folder(
+        &self,
+        folder_id: &str,
+        hub: &GDriveHub,
+        recursive: bool,
+        filter: &MimeFilter,
+    ) -> Result<Vec<GDriveFileInfo>, SimpleError> {
+        let folder = match hub
+            .files()
+            .get(folder_id)
+            .supports_all_drives(true)
+            .add_scope(Scope::Readonly)
+            .param("fields", "name, driveId")
+            .delegate(&mut RetryDelegate::default())
+            .doit()
+        {
+            Ok((_, f)) => f,
+            Err(e) => {
+                return Err(SimpleError::new(format!(
+                    "failed accessing Google Metadata API {:?}",
+                    e
+                )))
+            }
+        };
+        let folder_name = folder.name.unwrap_or_else(|| folder_id.to_string());
+        // folders in a shared drive are listed from that drive
+        let drive_id = folder.drive_id.as_ref().map(String::as_str);
+        Self::list_tree(folder_id, &folder_name, drive_id, hub, recursive, filter)
+    }
+
+    /// Lists every file in a shared drive (formerly Team Drive) that `filter` selects. Files whose
+    /// type can't be scanned are skipped. Paths start with the name of the drive.
+    pub fn list_shared_drive(
+        &self,
+        drive_id: &str,
+        hub: &GDriveHub,
+        filter: &MimeFilter,
+    ) -> Result<Vec<GDriveFileInfo>, SimpleError> {
+        let drive_name = match hub
+            .drives()
+            .get(drive_id)
+            .add_scope(Scope::Readonly)
+            .delegate(&mut RetryDelegate::default())
+            .doit()
+        {
+            Ok((_, d)) => d.name.unwrap_or_else(|| drive_id.to_string()),
+            Err(e) => {
+                return Err(SimpleError::new(format!(
+                    "failed accessing shared drive {}: {:?}",
+                    drive_id, e
+                )))
+            }
+        };
+        // the root folder of a shared drive has the ID of the drive
+        Self::list_tree(drive_id, &drive_name, Some(drive_id), hub, true, filter)
+    }
+
+    /// Returns the page token marking the end of the change log of the user's drives, or of a
+    /// shared drive, for `list_changes` to start from on a later scan
+    pub fn start_page_token(
+        &self,
+        drive_id: Option<&str>,
+        hub: &GDriveHub,
+    ) -> Result<String, SimpleError> {
+        let mut call = hub
+            .changes()
+            .get_start_page_token()
+            .supports_all_drives(true)
+            .add_scope(Scope::Readonly);
+        if let Some(d) = drive_id {
+            call = call.drive_id(d);
+        }
+        match call.delegate(&mut RetryDelegate::default()).doit() {
+            Ok((_, t)) => Ok(require_with!(
+                t.start_page_token,
+                "Google Drive returned no start page token"
+            )),
+            Err(e) => Err(SimpleError::new(format!(
+                "failed getting a Google Drive start page token {:?}",
+                e
+            ))),
+        }
+    }
+
+    /// Lists the files that `filter` selects and that were created or modified after
+    /// `page_token` (from `start_page_token` or an earlier `list_changes`), in the user's drives
+    /// or in a shared drive. Removed and trashed files, folders and files whose type can't be
+    /// scanned are skipped. Also returns the page token to list the next changes from.
+    pub fn list_changes(
+        &self,
+        page_token: &str,
+        drive_id: Option<&str>,
+        hub: &GDriveHub,
+        filter: &MimeFilter,
+    ) -> Result<(Vec<GDriveFileInfo>, String), SimpleError> {
+        let fields = format!(
+            "nextPageToken, newStartPageToken, changes(removed, file(trashed, {}))",
+            FILE_FIELDS
+        );
+        let mut files: Vec<GDriveFileInfo> = Vec::new();
+        let mut seen: HashSet<String> = HashSet::new();
+        let mut page_token = page_token.to_string();
+        loop {
+            let mut call = hub
+                .changes()
+                .list(&page_token)
+                .page_size(1000)
+                .include_removed(false)
+                .supports_all_drives(true)
+                .include_items_from_all_drives(true)
+                .add_scope(Scope::Readonly)
+                .param("fields", &fields);
+            if let Some(d) = drive_id {
+                call = call.drive_id(d);
+            }
+            let (_, change_list) = match call.delegate(&mut RetryDelegate::default()).doit() {
+                Ok(x) => x,
+                Err(e) => {
+                    return Err(SimpleError::new(format!(
+                        "failed listing Google Drive changes {:?}",
+                        e
+                    )))
+                }
+            };
+            for change in change_list.changes.unwrap_or_else(Vec::new) {
+                let file_object = match change.file {
+                    Some(f) if !change.removed.unwrap_or(false) && !f.trashed.unwrap_or(false) => f,
+                    _ => continue,
+                };
+                let mime_type = file_object.mime_type.clone().unwrap_or_default();
+                let name = file_object.name.clone().unwrap_or_default();
+                // a file changed several times can appear more than once
+                if mime_type == FOLDER_MIME_TYPE
+                    || !seen.insert(file_object.id.clone().unwrap_or_default())
+                {
+                    continue;
+                }
+                if !filter.matches(&mime_type) {
+                    debug!("Skipping {} ({})", name, mime_type);
+                    continue;
+                }
+                match GDriveFileInfo::from_file(file_object, None) {
+                    Ok(f) => files.push(f),
+                    Err(e) => debug!("Skipping {}: {}", name, e),
+                }
+            }
+            // the last page has the token to start from next time instead of a next page
+            if let Some(token) = change_list.new_start_page_token {
+                info!("Found {} changed files to scan", files.len());
+                return Ok((files, token));
+            }
+            page_token = require_with!(
+                change_list.next_page_token,
+                "Google Drive returned neither a next page nor a new start page token"
+            );
+        }
+    }
+
+    /// Lists the files selected by `filter` in a folder, descending into subfolders with
+    /// `recursive`. `drive_id` limits the listing to a shared drive.
+    fn list_tree(
+        folder_id: &str,
+        folder_name: &str,
+        drive_id: Option<&str>,
+        hub: &GDriveHub,
+        recursive: bool,
+        filter: &MimeFilter,
+    ) -> Result<Vec<GDriveFileInfo>, SimpleError> {
+        let mut files: Vec<GDriveFileInfo> = Vec::new();
+        let mut folders: Vec<(String, String)> =
+            vec![(folder_id.to_string(), folder_name.to_string())];
+        while let Some((id, path)) = folders.pop() {
+            for file_object in Self::list_children(&id, drive_id, hub)? {
+                let mime_type = file_object.mime_type.clone().unwrap_or_default();
+                let name = file_object.name.clone().unwrap_or_default();
+                if mime_type == FOLDER_MIME_TYPE {
+                    if recursive {
+                        let sub_id = file_object.id.clone().unwrap_or_default();
+                        folders.push((sub_id, format!("{}/{}", path, name)));
+                    }
+                } else if !filter.matches(&mime_type) {
+                    debug!("Skipping {}/{} ({})", path, name, mime_type);
+                } else {
+                    match GDriveFileInfo::from_file(file_object, Some(&path)) {
+                        Ok(f) => files.push(f),
+                        Err(e) => debug!("Skipping {}/{}: {}", path, name, e),
+                    }
+                }
+            }
+        }
+        info!("Found {} files to scan in {}", files.len(), folder_name);
+        Ok(files)
+    }
+
+    /// Lists every file and folder directly in a folder, following pagination. `drive_id` is the
+    /// shared drive the folder is in, if any.
+    fn list_children(
+        folder_id: &str,
+        drive_id: Option<&str>,
+        hub: &GDriveHub,
+    ) -> Result<Vec<File>, SimpleError> {
+        let query = format!("'{}' in parents and trashed = false", folder_id);
+        let fields = format!("nextPageToken, files({})", FILE_FIELDS);
+        let mut children: Vec<File> = Vec::new();
+        let mut page_token: Option<String> = None;
+        loop {
+            let mut call = hub
+                .files()
+                .list()
+                .q(&query)
+                .page_size(1000)
+                .supports_all_drives(true)
+                .include_items_from_all_drives(true)
+                .add_scope(Scope::Readonly)
+                .param("fields", &fields);
+            if let Some(d) = drive_id {
+                call = call.corpora("drive").drive_id(d);
+            }
+            if let Some(token) = &page_token {
+                call = call.page_token(token);
+            }
+            let (_, file_list) = match call.delegate(&mut RetryDelegate::default()).doit() {
+                Ok(x) => x,
+                Err(e) => {
+                    return Err(SimpleError::new(format!(
+                        "failed listing Google Drive folder {}: {:?}",
+                        folder_id, e
+                    )))
+                }
+            };
+            children.extend(file_list.files.unwrap_or_else(Vec::new));
+            page_token = file_list.next_page_token;
+            if page_token.is_none() {
+                return Ok(children);
+            }
+        }
+    }
+
+    /// Scans each file with `perform_scan` using `threads` worker threads. `DriveHub` can't be
+    /// shared between threads, so each one creates its own with `new_hub`. Returns each file with
+    /// its findings, in the order they finished. A file whose scan panics (e.g. because it
+    /// couldn't be downloaded) is returned with an error.
+    pub fn scan_files<F>(
+        &self,
+        files: Vec<GDriveFileInfo>,
+        new_hub: F,
+        threads: usize,
+        scan_entropy: bool,
+    ) -> Vec<(GDriveFileInfo, Result<HashSet<GDriveFinding>, SimpleError>)>
+    where
+        F: Fn() -> GDriveHub + Send + Sync + 'static,
+    {
+        progress::add_total(files.len());
+        let queue = Arc::new(Mutex::new(files.into_iter()));
+        let new_hub = Arc::new(new_hub);
+        let (tx, rx) = mpsc::channel();
+        let mut handles = Vec::new();
+        for _ in 0..threads.max(1) {
+            let queue = Arc::clone(&queue);
+            let new_hub = Arc::clone(&new_hub);
+            let tx = tx.clone();
+            let scanner = self.clone();
+            handles.push(thread::spawn(move || {
+                let hub = new_hub();
+                loop {
+                    if shutdown::interrupted() {
+                        break;
+                    }
+                    let file = match queue.lock().unwrap().next() {
+                        Some(f) => f,
+                        None => break,
+                    };
+                    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+                        scanner.perform_scan(&file, &hub, scan_entropy)
+                    }))
+                    .map_err(|_| SimpleError::new(format!("Scanning {} panicked", file.path)));
+                    if tx.send((file, result)).is_err() {
+                        break;
+                    }
+                }
+            }));
+        }
+        drop(tx);
+        let results = rx.iter().collect();
+        for handle in handles {
+            if handle.join().is_err() {
+                error!("A scanning thread exited unexpectedly");
+            }
+        }
+        results
+    }
+
     /// Takes information about the file, and the DriveHub object, and retrieves the content from
     /// Google Drive. Expect authorization issues here if you don't have access to the file.
     fn gdrive_file_contents(
         gdrivefile: &GDriveFileInfo,
-        hub: &DriveHub<
-            Client,
-            Authenticator<DefaultAuthenticatorDelegate, DiskTokenStorage, Client>,
-        >,
+        hub: &GDriveHub,
     ) -> Result<Vec<u8>, SimpleError> {
-        let resp_obj = hub
-            .files()
-            .export(&gdrivefile.file_id, &gdrivefile.mime_type)
-            .doit();
+        // Google Docs formats have to be exported, anything else is downloaded directly
+        let resp_obj = if !gdrivefile.export {
+            hub.files()
+                .get(&gdrivefile.file_id)
+                .supports_all_drives(true)
+                .add_scope(Scope::Readonly)
+                .param("alt", "media")
+                .delegate(&mut RetryDelegate::default())
+                .doit()
+                .map(|(r, _)| r)
+        } else {
+            hub.files()
+                .export(&gdrivefile.file_id, &gdrivefile.mime_type)
+                .delegate(&mut RetryDelegate::default())
+                .doit()
+        };
         let mut resp_obj = match resp_obj {
             Ok(r) => r,
             Err(e) => return Err(SimpleError::new(e.to_string())),
@@ -242,65 +779,85 @@ impl GDriveScanner {
     pub fn perform_scan(
         &self,
         gdrivefile: &GDriveFileInfo,
-        hub: &DriveHub<
-            Client,
-            Authenticator<DefaultAuthenticatorDelegate, DiskTokenStorage, Client>,
-        >,
+        hub: &GDriveHub,
         scan_entropy: bool,
     ) -> HashSet<GDriveFinding> {
-        // download an export of the file, split on new lines, store in lines
+        // download an export of the file, and split office documents into their sheets/slides
         let buffer = Self::gdrive_file_contents(gdrivefile, hub).unwrap();
-        let lines = buffer.split(|x| (*x as char) == '\n');
+        let parts: Vec<(Option<String>, Vec<u8>)> = if buffer.starts_with(ZIP_MAGIC) {
+            match decoding::extract_ooxml(&buffer) {
+                Ok(p) => p
+                    .into_iter()
+                    .map(|(name, data)| (Some(name), data))
+                    .collect(),
+                Err(e) => {
+                    error!("{}, scanning {} as-is", e, gdrivefile.name);
+                    vec![(None, buffer)]
+                }
+            }
+        } else {
+            vec![(None, buffer)]
+        };
 
         // main loop - search each line for secrets, output a list of GDriveFinding objects
+        let size = parts.iter().map(|(_, data)| data.len()).sum();
         let mut findings: HashSet<GDriveFinding> = HashSet::new();
-        for new_line in lines {
-            let matches_map = self.secret_scanner.matches(&new_line);
-            for (reason, match_iterator) in matches_map {
-                let mut secrets: Vec<String> = Vec::new();
-                for matchobj in match_iterator {
-                    secrets.push(
-                        ASCII
-                            .decode(
-                                &new_line[matchobj.start()..matchobj.end()],
-                                DecoderTrap::Ignore,
-                            )
-                            .unwrap_or_else(|_| "<STRING DECODE ERROR>".parse().unwrap()),
-                    );
-                }
-                if !secrets.is_empty() {
-                    findings.insert(GDriveFinding {
-                        diff: ASCII
-                            .decode(&new_line, DecoderTrap::Ignore)
-                            .unwrap_or_else(|_| "<STRING DECODE ERROR>".parse().unwrap()),
-                        date: gdrivefile.modified_time.clone(),
-                        strings_found: secrets.clone(),
-                        reason: reason.clone(),
-                        g_drive_id: gdrivefile.file_id.to_string(),
-                        path: gdrivefile.path.clone(),
-                        web_link: gdrivefile.web_link.clone(),
-                    });
+        for (document_part, buffer) in parts {
+            let buffer = self.secret_scanner.preprocess(&buffer);
+            let lines = buffer.split(|x| (*x as char) == '\n');
+            for new_line in lines {
+                let matches_map = self.secret_scanner.matches(&new_line);
+                for (reason, match_iterator) in matches_map {
+                    let mut secrets: Vec<String> = Vec::new();
+                    for matchobj in match_iterator {
+                        secrets.push(
+                            ASCII
+                                .decode(
+                                    &new_line[matchobj.start()..matchobj.end()],
+                                    DecoderTrap::Ignore,
+                                )
+                                .unwrap_or_else(|_| "<STRING DECODE ERROR>".parse().unwrap()),
+                        );
+                    }
+                    if !secrets.is_empty() {
+                        findings.insert(GDriveFinding {
+                            diff: ASCII
+                                .decode(&new_line, DecoderTrap::Ignore)
+                                .unwrap_or_else(|_| "<STRING DECODE ERROR>".parse().unwrap()),
+                            date: gdrivefile.modified_time.clone(),
+                            strings_found: secrets.clone(),
+                            reason: reason.clone(),
+                            g_drive_id: gdrivefile.file_id.to_string(),
+                            path: gdrivefile.path.clone(),
+                            web_link: gdrivefile.web_link.clone(),
+                            document_part: document_part.clone(),
+                            owner: gdrivefile.owner.clone(),
+                        });
+                    }
                 }
-            }
 
-            if scan_entropy {
-                let ef = SecretScanner::entropy_findings(new_line);
-                if !ef.is_empty() {
-                    findings.insert(GDriveFinding {
-                        diff: ASCII
-                            .decode(&new_line, DecoderTrap::Ignore)
-                            .unwrap_or_else(|_| "<STRING DECODE ERROR>".parse().unwrap()),
-                        date: gdrivefile.modified_time.clone(),
-                        strings_found: ef,
-                        reason: "Entropy".parse().unwrap(),
-                        g_drive_id: gdrivefile.file_id.to_string(),
-                        path: gdrivefile.path.clone(),
-                        web_link: gdrivefile.web_link.clone(),
-                    });
+                if scan_entropy {
+                    let ef = self.secret_scanner.entropy_matches(new_line);
+                    if !ef.is_empty() {
+                        findings.insert(GDriveFinding {
+                            diff: ASCII
+                                .decode(&new_line, DecoderTrap::Ignore)
+                                .unwrap_or_else(|_| "<STRING DECODE ERROR>".parse().unwrap()),
+                            date: gdrivefile.modified_time.clone(),
+                            strings_found: ef,
+                            reason: "Entropy".parse().unwrap(),
+                            g_drive_id: gdrivefile.file_id.to_string(),
+                            path: gdrivefile.path.clone(),
+                            web_link: gdrivefile.web_link.clone(),
+                            document_part: document_part.clone(),
+                            owner: gdrivefile.owner.clone(),
+                        });
+                    }
                 }
             }
         }
 
+        progress::scanned(size, findings.len());
         HashSet::from_iter(findings.into_iter())
     }
 }
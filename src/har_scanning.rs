@@ -0,0 +1,313 @@
+//! Collection of tools for scanning HTTP traffic captures for secrets.
+//!
+//! `HarScanner` acts as a wrapper around a `SecretScanner` object to provide helper functions for
+//! performing scanning against [HAR](https://w3c.github.io/web-performance/specs/HAR/Overview.html)
+//! files, the HTTP Archive format exported by browser developer tools, Charles, Fiddler and
+//! mitmproxy (`mitmdump --set hardump=capture.har`).
+//!
+//! Every request and response in the capture is scanned: headers, cookies, query string
+//! parameters and bodies (base64-encoded bodies are decoded first). Findings record the request
+//! URL and the part of the exchange the secret was found in, e.g. `request.header[Authorization]`
+//! or `response.body`. Credential-bearing headers such as `Authorization` and `Cookie`, and query
+//! parameters with sensitive names such as `access_token`, are always reported even when no regex
+//! matches their value.
+//!
+//! # Examples
+//!
+//! Basic usage requires you to create a `HarScanner` object...
+//!
+//! ```
+//! use rusty_hogs::har_scanning::HarScanner;
+//! let hs = HarScanner::new();
+//! ```
+//!
+//! ...then supply the contents of a HAR file to `scan_har()`.
+//!
+//! ```
+//! use rusty_hogs::har_scanning::HarScanner;
+//!
+//! let hs = HarScanner::new();
+//! let har = br#"{"log": {"entries": [{"request": {
+//!     "method": "GET",
+//!     "url": "https://api.example.com/v1/me",
+//!     "headers": [{"name": "Authorization", "value": "Bearer eyJhbGciOiJIUzI1NiJ9.e30.abc"}]
+//! }}]}}"#;
+//! let findings = hs.scan_har(har, "capture.har", false).unwrap();
+//! let finding = findings.iter().next().unwrap();
+//! assert_eq!(finding.url, "https://api.example.com/v1/me");
+//! assert_eq!(finding.field, "request.header[Authorization]");
+//! ```
+
+use crate::progress;
+use crate::structured_scanning::{self, StructuredFormat};
+use crate::SecretScanner;
+use encoding::all::ASCII;
+use encoding::{DecoderTrap, Encoding};
+use log::{self, debug, info};
+use serde_derive::{Deserialize, Serialize};
+use serde_json::Value;
+use simple_error::{try_with, SimpleError};
+use std::collections::HashSet;
+
+/// Headers (lower case) whose values are credentials by definition
+const SENSITIVE_HEADERS: &[&str] = &[
+    "authorization",
+    "proxy-authorization",
+    "cookie",
+    "set-cookie",
+    "x-api-key",
+    "x-auth-token",
+    "x-csrf-token",
+];
+
+/// Reason reported for header and parameter values found by name rather than by regex
+const SENSITIVE_FIELD_REASON: &str = "Sensitive HTTP field";
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Hash, Clone, Default)]
+/// `serde_json` object that represents a single found secret - finding
+pub struct HarFinding {
+    pub diff: String,
+    #[serde(rename = "stringsFound")]
+    pub strings_found: Vec<String>,
+    /// The HAR file the exchange was captured in
+    pub path: String,
+    pub url: String,
+    pub method: String,
+    /// Where in the exchange the secret was found, e.g. `request.header[Authorization]`,
+    /// `request.query[token]` or `response.body`
+    pub field: String,
+    pub reason: String,
+    #[serde(rename = "keyPath", default, skip_serializing_if = "Option::is_none")]
+    pub key_path: Option<String>,
+}
+
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+/// Contains helper functions for performing scans of HTTP traffic captures
+pub struct HarScanner {
+    pub secret_scanner: SecretScanner,
+}
+
+/// Acts as a wrapper around a `SecretScanner` object to provide helper functions for performing
+/// scanning against HAR files.
+impl HarScanner {
+    /// Initialize the SecretScanner object first using the SecretScannerBuilder, then provide
+    /// it to this constructor method.
+    pub fn new_from_scanner(secret_scanner: SecretScanner) -> Self {
+        Self { secret_scanner }
+    }
+
+    pub fn new() -> Self {
+        Self {
+            secret_scanner: SecretScanner::default(),
+        }
+    }
+
+    /// Parses the contents of a HAR file and scans every entry in it. `path` is only used to
+    /// label the findings.
+    pub fn scan_har(
+        &self,
+        har: &[u8],
+        path: &str,
+        scan_entropy: bool,
+    ) -> Result<HashSet<HarFinding>, SimpleError> {
+        let size = har.len();
+        let har: Value = try_with!(serde_json::from_slice(har), "Failed to parse HAR file");
+        let entries = match har["log"]["entries"].as_array() {
+            Some(e) => e,
+            None => return Err(SimpleError::new("HAR file has no log.entries array")),
+        };
+        info!("Scanning {} entries from {}", entries.len(), path);
+        let mut findings: HashSet<HarFinding> = HashSet::new();
+        for entry in entries {
+            let request = &entry["request"];
+            let template = HarFinding {
+                path: path.to_string(),
+                url: request["url"].as_str().unwrap_or("").to_string(),
+                method: request["method"].as_str().unwrap_or("").to_string(),
+                ..Default::default()
+            };
+            self.scan_message(request, "request", &template, scan_entropy, &mut findings);
+            self.scan_message(
+                &entry["response"],
+                "response",
+                &template,
+                scan_entropy,
+                &mut findings,
+            );
+        }
+        progress::scanned(size, findings.len());
+        Ok(findings)
+    }
+
+    /// Scans the headers, cookies, query string and body of a single request or response
+    fn scan_message(
+        &self,
+        message: &Value,
+        prefix: &str,
+        template: &HarFinding,
+        scan_entropy: bool,
+        findings: &mut HashSet<HarFinding>,
+    ) {
+        let name_values = |key: &str| -> Vec<(String, String)> {
+            match message[key].as_array() {
+                Some(a) => a
+                    .iter()
+                    .filter_map(|nv| {
+                        Some((
+                            nv["name"].as_str()?.to_string(),
+                            nv["value"].as_str()?.to_string(),
+                        ))
+                    })
+                    .collect(),
+                None => Vec::new(),
+            }
+        };
+
+        for (name, value) in name_values("headers") {
+            let field = format!("{}.header[{}]", prefix, name);
+            let line = format!("{}: {}", name, value);
+            self.scan_text(line.as_bytes(), &field, template, scan_entropy, findings);
+            if SENSITIVE_HEADERS.contains(&name.to_ascii_lowercase().as_str()) {
+                Self::report_sensitive(&field, &line, &value, template, findings);
+            }
+        }
+        for (name, value) in name_values("cookies") {
+            let field = format!("{}.cookie[{}]", prefix, name);
+            let line = format!("{}={}", name, value);
+            self.scan_text(line.as_bytes(), &field, template, scan_entropy, findings);
+        }
+        for (name, value) in name_values("queryString") {
+            let field = format!("{}.query[{}]", prefix, name);
+            let line = format!("{}={}", name, value);
+            self.scan_text(line.as_bytes(), &field, template, scan_entropy, findings);
+            if structured_scanning::is_sensitive_key(&name)
+                && structured_scanning::is_plausible_secret(&value)
+            {
+                Self::report_sensitive(&field, &line, &value, template, findings);
+            }
+        }
+
+        // request bodies are in postData, response bodies in content (sometimes base64 encoded)
+        let (body, mime_type) = if prefix == "request" {
+            (
+                &message["postData"]["text"],
+                &message["postData"]["mimeType"],
+            )
+        } else {
+            (&message["content"]["text"], &message["content"]["mimeType"])
+        };
+        let body = match body.as_str() {
+            Some(b) if !b.is_empty() => b,
+            _ => return,
+        };
+        let body: Vec<u8> = if message["content"]["encoding"].as_str() == Some("base64") {
+            base64::decode(body).unwrap_or_else(|_| body.as_bytes().to_vec())
+        } else {
+            body.as_bytes().to_vec()
+        };
+        let field = format!("{}.body", prefix);
+        let is_json = mime_type
+            .as_str()
+            .map(|m| m.contains("json"))
+            .unwrap_or(false);
+        if self.secret_scanner.structured && is_json {
+            match structured_scanning::scan_document(
+                &self.secret_scanner,
+                &body,
+                StructuredFormat::Json,
+                scan_entropy,
+            ) {
+                Ok(matches) => {
+                    findings.extend(matches.into_iter().map(|m| HarFinding {
+                        diff: m.diff,
+                        strings_found: m.strings_found,
+                        field: field.clone(),
+                        reason: m.reason,
+                        key_path: Some(m.key_path),
+                        ..template.clone()
+                    }));
+                    return;
+                }
+                Err(e) => debug!("{}, scanning {} line by line", e, field),
+            }
+        }
+        let body = self.secret_scanner.preprocess(&body);
+        self.scan_text(&body, &field, template, scan_entropy, findings);
+    }
+
+    /// Reports the value of a header or parameter that is a credential by name
+    fn report_sensitive(
+        field: &str,
+        line: &str,
+        value: &str,
+        template: &HarFinding,
+        findings: &mut HashSet<HarFinding>,
+    ) {
+        findings.insert(HarFinding {
+            diff: line.to_string(),
+            strings_found: vec![value.to_string()],
+            field: field.to_string(),
+            reason: SENSITIVE_FIELD_REASON.to_string(),
+            ..template.clone()
+        });
+    }
+
+    /// Scans each line of `data` and adds any findings, labelled with `field`
+    fn scan_text(
+        &self,
+        data: &[u8],
+        field: &str,
+        template: &HarFinding,
+        scan_entropy: bool,
+        findings: &mut HashSet<HarFinding>,
+    ) {
+        for new_line in data.split(|x| (*x as char) == '\n') {
+            let matches_map = self.secret_scanner.matches(new_line);
+            for (reason, match_iterator) in matches_map {
+                let mut secrets: Vec<String> = Vec::new();
+                for matchobj in match_iterator {
+                    secrets.push(
+                        ASCII
+                            .decode(
+                                &new_line[matchobj.start()..matchobj.end()],
+                                DecoderTrap::Ignore,
+                            )
+                            .unwrap_or_else(|_| "<STRING DECODE ERROR>".parse().unwrap()),
+                    );
+                }
+                if !secrets.is_empty() {
+                    findings.insert(HarFinding {
+                        diff: ASCII
+                            .decode(new_line, DecoderTrap::Ignore)
+                            .unwrap_or_else(|_| "<STRING DECODE ERROR>".parse().unwrap()),
+                        strings_found: secrets,
+                        field: field.to_string(),
+                        reason: reason.clone(),
+                        ..template.clone()
+                    });
+                }
+            }
+
+            if scan_entropy {
+                let ef = self.secret_scanner.entropy_matches(new_line);
+                if !ef.is_empty() {
+                    findings.insert(HarFinding {
+                        diff: ASCII
+                            .decode(new_line, DecoderTrap::Ignore)
+                            .unwrap_or_else(|_| "<STRING DECODE ERROR>".parse().unwrap()),
+                        strings_found: ef,
+                        field: field.to_string(),
+                        reason: "Entropy".to_string(),
+                        ..template.clone()
+                    });
+                }
+            }
+        }
+    }
+}
+
+impl Default for HarScanner {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,470 @@
+//! Collection of tools for scanning offline Windows registry hives for secrets.
+//!
+//! Windows services, autologon (`Winlogon\DefaultPassword`), RDP and VNC tools and many
+//! applications store credentials in the registry, so hive files copied from a host or a disk
+//! image (`SYSTEM`, `SOFTWARE`, `SAM`, `SECURITY`, `NTUSER.DAT`, `UsrClass.dat`) are worth
+//! scanning. As binaries their strings are UTF-16 and split across cells, so `HiveScanner` parses
+//! them instead: `Hive` walks the keys of a hive (the `regf` format) and returns the data of each
+//! value, and each key is scanned as a document of its values, like the structured documents of
+//! `--structured`. Values stored under sensitive-looking names are reported even when no regex
+//! matches them.
+//!
+//! String values (`REG_SZ`, `REG_EXPAND_SZ`, `REG_MULTI_SZ`, `REG_LINK`) are decoded from UTF-16.
+//! Binary values are decoded from UTF-16 when they hold text, kept as they are when they are
+//! mostly printable, and otherwise scanned as hex, e.g. for obfuscated VNC passwords. Numbers
+//! aren't scanned. Transaction logs (`.LOG1`, `.LOG2`) aren't replayed, so changes that were
+//! not yet written to the hive are missed.
+//!
+//! # Examples
+//!
+//! ```
+//! use rusty_hogs::hive_scanning::{value_text, Hive, REG_BINARY, REG_DWORD, REG_SZ};
+//!
+//! let utf16: Vec<u8> = "hunter2\0".encode_utf16().flat_map(|c| c.to_le_bytes().to_vec()).collect();
+//! assert_eq!(value_text(REG_SZ, &utf16), Some(vec![String::from("hunter2")]));
+//! assert_eq!(value_text(REG_BINARY, &utf16), Some(vec![String::from("hunter2")]));
+//! assert_eq!(value_text(REG_BINARY, &[0xde, 0xad, 0xbe, 0xef]), Some(vec![String::from("deadbeef")]));
+//! assert_eq!(value_text(REG_DWORD, &[1, 0, 0, 0]), None);
+//!
+//! assert!(Hive::parse(b"not a registry hive").is_err());
+//! ```
+
+use crate::progress;
+use crate::structured_scanning::{self, StructuredFormat};
+use crate::SecretScanner;
+use log::{self, debug, error, info};
+use serde_derive::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use simple_error::{try_with, SimpleError};
+use std::collections::{BTreeMap, HashSet};
+use std::convert::TryInto;
+use std::fs;
+use std::io::Read;
+use std::path::Path;
+
+pub const REG_SZ: u32 = 1;
+pub const REG_EXPAND_SZ: u32 = 2;
+pub const REG_BINARY: u32 = 3;
+pub const REG_DWORD: u32 = 4;
+pub const REG_DWORD_BIG_ENDIAN: u32 = 5;
+pub const REG_LINK: u32 = 6;
+pub const REG_MULTI_SZ: u32 = 7;
+pub const REG_QWORD: u32 = 11;
+
+/// The signature of the base block, at the start of a hive file
+const HIVE_SIGNATURE: &[u8] = b"regf";
+
+/// Cell offsets are relative to the first hive bin, after the 4 KB base block
+const HBIN_START: usize = 0x1000;
+
+/// The offset of the root key cell in the base block
+const ROOT_CELL_OFFSET: usize = 0x24;
+
+/// The most data a cell of a big data ("db") value holds
+const BIG_DATA_SEGMENT: usize = 16344;
+
+/// Key names are stored in ASCII (Latin-1) rather than UTF-16 when this key node flag is set
+const KEY_COMP_NAME: u16 = 0x20;
+
+/// Value names are stored in ASCII (Latin-1) rather than UTF-16 when this value flag is set
+const VALUE_COMP_NAME: u16 = 0x01;
+
+/// The name shown for the unnamed, default value of a key
+const DEFAULT_VALUE_NAME: &str = "(Default)";
+
+/// Offsets meaning "none" in key nodes
+const NO_CELL: u32 = 0xFFFF_FFFF;
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Hash, Clone, Default)]
+/// `serde_json` object that represents a single found secret - finding
+pub struct HiveFinding {
+    pub diff: String,
+    #[serde(rename = "stringsFound")]
+    pub strings_found: Vec<String>,
+    /// The hive file
+    pub path: String,
+    /// The path of the key from the root of the hive, e.g.
+    /// `Microsoft\Windows NT\CurrentVersion\Winlogon`
+    pub key: String,
+    /// The name of the value, with the index of the string for `REG_MULTI_SZ` values, e.g.
+    /// `DefaultPassword` or `Servers[1]`
+    pub value: String,
+    pub reason: String,
+}
+
+/// A value read from a hive
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+pub struct HiveValue {
+    /// The path of the key the value belongs to, empty for the root key
+    pub key: String,
+    /// The name of the value, `(Default)` for the unnamed value
+    pub name: String,
+    /// The type of the value, e.g. `REG_SZ`
+    pub data_type: u32,
+    pub data: Vec<u8>,
+}
+
+/// A registry hive file, parsed as it is walked. Corrupt cells are skipped rather than failing
+/// the whole hive.
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub struct Hive<'a> {
+    data: &'a [u8],
+    root: u32,
+}
+
+impl<'a> Hive<'a> {
+    /// Checks that `data` is a registry hive
+    pub fn parse(data: &'a [u8]) -> Result<Self, SimpleError> {
+        if data.len() < HBIN_START || !data.starts_with(HIVE_SIGNATURE) {
+            return Err(SimpleError::new("Not a registry hive (no regf signature)"));
+        }
+        Ok(Self {
+            data,
+            root: u32_at(data, ROOT_CELL_OFFSET).unwrap_or(NO_CELL),
+        })
+    }
+
+    /// Returns every value of every key of the hive, keys in depth first order
+    pub fn values(&self) -> Vec<HiveValue> {
+        let mut values: Vec<HiveValue> = Vec::new();
+        // cells already walked, so that a corrupt hive can't make the walk loop
+        let mut visited: HashSet<u32> = HashSet::new();
+        let mut stack: Vec<(u32, Option<String>)> = vec![(self.root, None)];
+        while let Some((offset, parent)) = stack.pop() {
+            if !visited.insert(offset) {
+                continue;
+            }
+            let cell = match self.cell(offset) {
+                Some(c) if c.starts_with(b"nk") && c.len() >= 0x4C => c,
+                _ => {
+                    debug!("Skipping key cell {:#x}, which is not a key node", offset);
+                    continue;
+                }
+            };
+            let flags = u16_at(cell, 0x02).unwrap_or(0);
+            let name_length = u16_at(cell, 0x48).unwrap_or(0) as usize;
+            let name = cell
+                .get(0x4C..0x4C + name_length)
+                .map(|n| decode_name(n, flags & KEY_COMP_NAME != 0))
+                .unwrap_or_default();
+            // paths start below the root key, whose name varies from hive to hive
+            let path = match parent {
+                None => String::new(),
+                Some(p) if p.is_empty() => name,
+                Some(p) => format!("{}\\{}", p, name),
+            };
+
+            let value_count = u32_at(cell, 0x24).unwrap_or(0);
+            let value_list = u32_at(cell, 0x28).unwrap_or(NO_CELL);
+            if value_count > 0 && value_list != NO_CELL {
+                if let Some(list) = self.cell(value_list) {
+                    // the count of a corrupt key can be larger than its list
+                    for i in 0..(value_count as usize).min(list.len() / 4) {
+                        if let Some(value) = u32_at(list, i * 4).and_then(|o| self.value(o, &path))
+                        {
+                            values.push(value);
+                        }
+                    }
+                }
+            }
+
+            let subkey_count = u32_at(cell, 0x14).unwrap_or(0);
+            let subkey_list = u32_at(cell, 0x1C).unwrap_or(NO_CELL);
+            if subkey_count > 0 && subkey_list != NO_CELL {
+                let mut subkeys: Vec<u32> = Vec::new();
+                self.subkeys(subkey_list, &mut subkeys, &mut visited);
+                // reversed, for the stack to walk them in order
+                for subkey in subkeys.into_iter().rev() {
+                    stack.push((subkey, Some(path.clone())));
+                }
+            }
+        }
+        values
+    }
+
+    /// Collects the key node offsets of a subkey list (`lf`, `lh`, `li`, or `ri`, a list of lists)
+    fn subkeys(&self, offset: u32, subkeys: &mut Vec<u32>, visited: &mut HashSet<u32>) {
+        let cell = match self.cell(offset) {
+            Some(c) if c.len() >= 4 => c,
+            _ => return,
+        };
+        let count = u16_at(cell, 0x02).unwrap_or(0) as usize;
+        match &cell[0..2] {
+            b"lf" | b"lh" => subkeys.extend((0..count).filter_map(|i| u32_at(cell, 4 + i * 8))),
+            b"li" => subkeys.extend((0..count).filter_map(|i| u32_at(cell, 4 + i * 4))),
+            b"ri" => {
+                for list in (0..count).filter_map(|i| u32_at(cell, 4 + i * 4)) {
+                    if visited.insert(list) {
+                        self.subkeys(list, subkeys, visited);
+                    }
+                }
+            }
+            _ => debug!(
+                "Skipping subkey list {:#x} with an unknown signature",
+                offset
+            ),
+        }
+    }
+
+    /// Reads the value cell (`vk`) at `offset`
+    fn value(&self, offset: u32, key: &str) -> Option<HiveValue> {
+        let cell = self.cell(offset)?;
+        if !cell.starts_with(b"vk") || cell.len() < 0x14 {
+            return None;
+        }
+        let name_length = u16_at(cell, 0x02)? as usize;
+        let size = u32_at(cell, 0x04)?;
+        let data_offset = u32_at(cell, 0x08)?;
+        let data_type = u32_at(cell, 0x0C)?;
+        let flags = u16_at(cell, 0x10)?;
+        let name = match cell.get(0x14..0x14 + name_length) {
+            Some(n) if !n.is_empty() => decode_name(n, flags & VALUE_COMP_NAME != 0),
+            _ => String::from(DEFAULT_VALUE_NAME),
+        };
+
+        let data = if size & 0x8000_0000 != 0 {
+            // data of up to 4 bytes is stored in the offset field itself
+            let length = (size & 0x7FFF_FFFF).min(4) as usize;
+            cell[0x08..0x08 + length].to_vec()
+        } else {
+            let length = size as usize;
+            let data_cell = self.cell(data_offset)?;
+            if length > BIG_DATA_SEGMENT && data_cell.starts_with(b"db") {
+                self.big_data(data_cell, length)
+            } else {
+                data_cell[..length.min(data_cell.len())].to_vec()
+            }
+        };
+        Some(HiveValue {
+            key: key.to_string(),
+            name,
+            data_type,
+            data,
+        })
+    }
+
+    /// Joins the segments of a big data (`db`) value
+    fn big_data(&self, cell: &[u8], length: usize) -> Vec<u8> {
+        let count = u16_at(cell, 0x02).unwrap_or(0) as usize;
+        let segments = match u32_at(cell, 0x04).and_then(|o| self.cell(o)) {
+            Some(s) => s,
+            None => return Vec::new(),
+        };
+        let mut data: Vec<u8> = Vec::with_capacity(length);
+        for segment in (0..count).filter_map(|i| u32_at(segments, i * 4)) {
+            if let Some(segment) = self.cell(segment) {
+                data.extend_from_slice(&segment[..segment.len().min(BIG_DATA_SEGMENT)]);
+            }
+        }
+        data.truncate(length);
+        data
+    }
+
+    /// The data of the cell at `offset`, without its size
+    fn cell(&self, offset: u32) -> Option<&'a [u8]> {
+        let start = HBIN_START.checked_add(offset as usize)?;
+        // allocated cells have a negative size
+        let size = u32_at(self.data, start)? as i32;
+        let size = size.checked_abs()? as usize;
+        if size < 4 {
+            return None;
+        }
+        self.data.get(start + 4..start.checked_add(size)?)
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+/// Contains helper functions for performing scans of registry hives
+pub struct HiveScanner {
+    pub secret_scanner: SecretScanner,
+}
+
+/// Acts as a wrapper around a `SecretScanner` object to provide helper functions for performing
+/// scanning against registry hives.
+impl HiveScanner {
+    /// Initialize the SecretScanner object first using the SecretScannerBuilder, then provide
+    /// it to this constructor method.
+    pub fn new_from_scanner(secret_scanner: SecretScanner) -> Self {
+        Self { secret_scanner }
+    }
+
+    pub fn new() -> Self {
+        Self {
+            secret_scanner: SecretScanner::default(),
+        }
+    }
+
+    /// Scans a hive file, or the hive files of a directory and its subdirectories. Files in a
+    /// directory that aren't hives are skipped. Returns a HashSet of findings.
+    pub fn scan_path(
+        &self,
+        path: &Path,
+        scan_entropy: bool,
+    ) -> Result<HashSet<HiveFinding>, SimpleError> {
+        let mut findings: HashSet<HiveFinding> = HashSet::new();
+        if path.is_dir() {
+            let entries = try_with!(fs::read_dir(path), "Failed to read {}", path.display());
+            for entry in entries {
+                let entry = try_with!(entry, "Failed to read {}", path.display());
+                let entry_path = entry.path();
+                if entry_path.is_dir() {
+                    findings.extend(self.scan_path(&entry_path, scan_entropy)?);
+                    continue;
+                }
+                if !is_hive_file(&entry_path) {
+                    debug!("Skipping {}, which is not a hive", entry_path.display());
+                    continue;
+                }
+                let name = entry_path.to_string_lossy();
+                let scanned = fs::read(&entry_path)
+                    .map_err(|e| SimpleError::new(e.to_string()))
+                    .and_then(|data| self.scan_hive(&name, &data, scan_entropy));
+                match scanned {
+                    Ok(f) => findings.extend(f),
+                    Err(e) => error!("Failed to scan {}: {}", name, e),
+                }
+            }
+        } else {
+            let data = try_with!(fs::read(path), "Failed to read {}", path.display());
+            findings.extend(self.scan_hive(&path.to_string_lossy(), &data, scan_entropy)?);
+        }
+        Ok(findings)
+    }
+
+    /// Scans the values of the hive `data`, read from the file `path`. Returns a HashSet of
+    /// findings.
+    pub fn scan_hive(
+        &self,
+        path: &str,
+        data: &[u8],
+        scan_entropy: bool,
+    ) -> Result<HashSet<HiveFinding>, SimpleError> {
+        let hive = try_with!(Hive::parse(data), "Failed to parse {}", path);
+        let values = hive.values();
+        info!("Found {} values in {}", values.len(), path);
+
+        // each key is scanned as one document of its values
+        let mut keys: BTreeMap<&str, Map<String, Value>> = BTreeMap::new();
+        for value in &values {
+            if let Some(text) = value_text(value.data_type, &value.data) {
+                let text = match text.len() {
+                    1 => Value::from(text[0].clone()),
+                    _ => Value::from(text),
+                };
+                keys.entry(value.key.as_str())
+                    .or_insert_with(Map::new)
+                    .insert(value.name.clone(), text);
+            }
+        }
+        let mut findings: HashSet<HiveFinding> = HashSet::new();
+        for (key, key_values) in keys {
+            let document = Value::Object(key_values).to_string();
+            let matches = structured_scanning::scan_document(
+                &self.secret_scanner,
+                document.as_bytes(),
+                StructuredFormat::Json,
+                scan_entropy,
+            )?;
+            progress::scanned(document.len(), matches.len());
+            for m in matches {
+                findings.insert(HiveFinding {
+                    diff: m.diff,
+                    strings_found: m.strings_found,
+                    path: path.to_string(),
+                    key: key.to_string(),
+                    value: m.key_path,
+                    reason: m.reason,
+                });
+            }
+        }
+        Ok(findings)
+    }
+}
+
+impl Default for HiveScanner {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The text to scan for a value of type `data_type`, one string per string of a `REG_MULTI_SZ`,
+/// or None for numbers and empty values. See the module documentation.
+pub fn value_text(data_type: u32, data: &[u8]) -> Option<Vec<String>> {
+    let strings: Vec<String> = match data_type {
+        REG_DWORD | REG_DWORD_BIG_ENDIAN | REG_QWORD => return None,
+        REG_SZ | REG_EXPAND_SZ | REG_LINK => vec![first_string(&decode_utf16(data))],
+        REG_MULTI_SZ => decode_utf16(data).split('\0').map(String::from).collect(),
+        _ if is_utf16_text(data) => vec![first_string(&decode_utf16(data))],
+        _ if is_printable(data) => vec![String::from_utf8_lossy(data).into_owned()],
+        _ => vec![data.iter().map(|b| format!("{:02x}", b)).collect()],
+    };
+    let strings: Vec<String> = strings.into_iter().filter(|s| !s.is_empty()).collect();
+    if strings.is_empty() {
+        None
+    } else {
+        Some(strings)
+    }
+}
+
+/// Whether the file at `path` starts with the signature of a hive
+fn is_hive_file(path: &Path) -> bool {
+    let mut signature = [0u8; 4];
+    fs::File::open(path)
+        .and_then(|mut f| f.read_exact(&mut signature))
+        .map(|_| signature[..] == *HIVE_SIGNATURE)
+        .unwrap_or(false)
+}
+
+/// Decodes a key or value name, Latin-1 if `compressed`, otherwise UTF-16
+fn decode_name(name: &[u8], compressed: bool) -> String {
+    if compressed {
+        name.iter().map(|b| *b as char).collect()
+    } else {
+        decode_utf16(name)
+    }
+}
+
+/// Decodes UTF-16LE text, ignoring an odd trailing byte
+fn decode_utf16(data: &[u8]) -> String {
+    let units: Vec<u16> = data
+        .chunks_exact(2)
+        .map(|c| u16::from_le_bytes([c[0], c[1]]))
+        .collect();
+    String::from_utf16_lossy(&units)
+}
+
+/// The text up to the first NUL, which ends registry strings
+fn first_string(text: &str) -> String {
+    text.split('\0').next().unwrap_or("").to_string()
+}
+
+/// Binary data holding UTF-16LE text: printable ASCII low bytes with zero high bytes, up to a NUL
+fn is_utf16_text(data: &[u8]) -> bool {
+    let units: Vec<&[u8]> = data
+        .chunks_exact(2)
+        .take_while(|c| c[0] != 0 || c[1] != 0)
+        .collect();
+    units.len() >= 4
+        && units
+            .iter()
+            .all(|c| c[1] == 0 && (c[0].is_ascii_graphic() || c[0] == b' '))
+}
+
+/// Binary data that is mostly printable ASCII
+fn is_printable(data: &[u8]) -> bool {
+    let printable = data
+        .iter()
+        .filter(|b| b.is_ascii_graphic() || b.is_ascii_whitespace())
+        .count();
+    !data.is_empty() && printable * 10 >= data.len() * 9
+}
+
+fn u16_at(data: &[u8], offset: usize) -> Option<u16> {
+    let bytes = data.get(offset..offset.checked_add(2)?)?;
+    Some(u16::from_le_bytes(bytes.try_into().ok()?))
+}
+
+fn u32_at(data: &[u8], offset: usize) -> Option<u32> {
+    let bytes = data.get(offset..offset.checked_add(4)?)?;
+    Some(u32::from_le_bytes(bytes.try_into().ok()?))
+}
@@ -0,0 +1,862 @@
+//! Collection of tools for finding credentials in SSH, cloud CLI and database client config files,
+//! and correlating them with the hosts they grant access to.
+//!
+//! Unlike the other scanners, `HostCredentialScanner` doesn't run the regex rules over each line.
+//! It parses well-known config files and reports every piece of authentication material it
+//! finds, together with the host (or service) and user it's for, so that the output can be used
+//! to assess lateral movement risk from a compromised machine or home directory:
+//!
+//! * `~/.ssh`: identity files named in `config` are matched to their `Host` block, and other
+//!   private keys in the directory are matched to the hosts in `known_hosts`. Private keys are
+//!   checked for a passphrase.
+//! * `~/.aws/credentials` and `~/.aws/config`: access keys and session tokens per profile
+//! * gcloud `application_default_credentials.json` and `legacy_credentials/*/adc.json`: refresh
+//!   tokens and service account keys
+//! * `~/.azure/accessTokens.json`, `msal_token_cache.json` and `service_principal_entries.json`:
+//!   refresh tokens and service principal secrets
+//! * `~/.kube/config`: tokens, passwords and client keys, matched to cluster servers by context
+//! * `~/.docker/config.json`: registry logins
+//! * `~/.pgpass` and `~/.my.cnf`: database passwords
+//!
+//! # Examples
+//!
+//! Directories are walked with `scan_path()`, which handles `.ssh` directories as a unit. Single
+//! files can be parsed with `scan_file()`, which identifies the file by its path.
+//!
+//! ```
+//! use rusty_hogs::host_credential_scanning::HostCredentialScanner;
+//!
+//! let hs = HostCredentialScanner::new();
+//! let findings = hs.scan_file("/home/me/.pgpass", b"db.internal:5432:*:app:hunter2\n");
+//! assert_eq!(findings[0].host, "db.internal:5432/*");
+//! assert_eq!(findings[0].user, "app");
+//! assert_eq!(findings[0].auth_type, "password");
+//! assert_eq!(findings[0].strings_found, vec!["hunter2"]);
+//! ```
+
+use crate::progress;
+use crate::structured_scanning::{self, StructuredFormat};
+use crate::SecretScanner;
+use log::{self, debug, info};
+use serde_derive::{Deserialize, Serialize};
+use serde_json::Value;
+use simple_error::{try_with, SimpleError};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str;
+
+/// Files in a `.ssh` directory that are never private keys
+const SSH_NON_KEY_FILES: &[&str] = &[
+    "config",
+    "known_hosts",
+    "known_hosts.old",
+    "authorized_keys",
+    "authorized_keys2",
+    "environment",
+    "rc",
+];
+
+/// Private keys larger than this are assumed to be something else
+const MAX_KEY_SIZE: u64 = 64 * 1024;
+
+const OPENSSH_KEY_MAGIC: &[u8] = b"openssh-key-v1\0";
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Hash, Clone, Default)]
+/// `serde_json` object that represents a single found credential - finding
+pub struct HostCredentialFinding {
+    pub diff: String,
+    #[serde(rename = "stringsFound")]
+    pub strings_found: Vec<String>,
+    /// The config file the credential was found in
+    pub path: String,
+    /// The host, host pattern or service the credential grants access to, e.g. `db.internal:5432`,
+    /// `https://k8s.example.com:6443` or `aws`
+    pub host: String,
+    /// The user, account or profile the credential belongs to, if known
+    pub user: String,
+    /// The kind of authentication material, e.g. `private_key`, `password`, `access_key`,
+    /// `session_token`, `refresh_token`, `token`, `client_key` or `client_secret`
+    #[serde(rename = "authType")]
+    pub auth_type: String,
+    pub reason: String,
+}
+
+/// The config files understood by `scan_file`
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub enum CredentialFile {
+    AwsCredentials,
+    GcloudCredentials,
+    AzureAccessTokens,
+    AzureMsalCache,
+    AzureServicePrincipals,
+    Kubeconfig,
+    DockerConfig,
+    Pgpass,
+    MyCnf,
+}
+
+impl CredentialFile {
+    /// Identifies a config file from its path, returning `None` for anything else
+    pub fn from_path(path: &str) -> Option<Self> {
+        let path = path.replace('\\', "/");
+        let file_name = path.rsplit('/').next()?;
+        let parent = path.rsplit('/').nth(1).unwrap_or("");
+        match (parent, file_name) {
+            (".aws", "credentials") | (".aws", "config") => Some(CredentialFile::AwsCredentials),
+            (_, "application_default_credentials.json") | (_, "adc.json") => {
+                Some(CredentialFile::GcloudCredentials)
+            }
+            (".azure", "accessTokens.json") => Some(CredentialFile::AzureAccessTokens),
+            (".azure", "msal_token_cache.json") => Some(CredentialFile::AzureMsalCache),
+            (".azure", "service_principal_entries.json") => {
+                Some(CredentialFile::AzureServicePrincipals)
+            }
+            (".kube", "config") => Some(CredentialFile::Kubeconfig),
+            (".docker", "config.json") => Some(CredentialFile::DockerConfig),
+            (_, ".pgpass") => Some(CredentialFile::Pgpass),
+            (_, ".my.cnf") | (_, "my.cnf") => Some(CredentialFile::MyCnf),
+            _ => None,
+        }
+    }
+}
+
+/// A `Host` (or `Match`) block from an OpenSSH client config
+#[derive(Debug, Default)]
+struct SshHostBlock {
+    patterns: String,
+    hostname: Option<String>,
+    user: Option<String>,
+    port: Option<String>,
+    /// The IdentityFile values and the lines they were set on
+    identity_files: Vec<(String, String)>,
+}
+
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+/// Contains helper functions for finding credentials in config files
+pub struct HostCredentialScanner {
+    pub secret_scanner: SecretScanner,
+}
+
+/// Acts as a wrapper around a `SecretScanner` object to provide helper functions for finding
+/// credentials in SSH, cloud CLI and database client config files.
+impl HostCredentialScanner {
+    /// Initialize the SecretScanner object first using the SecretScannerBuilder, then provide
+    /// it to this constructor method.
+    pub fn new_from_scanner(secret_scanner: SecretScanner) -> Self {
+        Self { secret_scanner }
+    }
+
+    pub fn new() -> Self {
+        Self {
+            secret_scanner: SecretScanner::default(),
+        }
+    }
+
+    /// Walks `root` (typically a home directory, or `/home`), scanning each `.ssh` directory and
+    /// each recognised config file. Symlinks are not followed, and unreadable directories are
+    /// skipped.
+    pub fn scan_path(&self, root: &Path) -> Result<HashSet<HostCredentialFinding>, SimpleError> {
+        let metadata = try_with!(fs::symlink_metadata(root), "Failed to read {:?}", root);
+        let mut findings: HashSet<HostCredentialFinding> = HashSet::new();
+        if metadata.is_file() {
+            findings.extend(self.scan_file_path(root));
+            return Ok(findings);
+        }
+        let mut dirs: Vec<PathBuf> = vec![root.to_path_buf()];
+        while let Some(dir) = dirs.pop() {
+            if dir.file_name().map(|n| n == ".ssh").unwrap_or(false) {
+                findings.extend(self.scan_ssh_dir(&dir));
+                continue;
+            }
+            let entries = match fs::read_dir(&dir) {
+                Ok(e) => e,
+                Err(e) => {
+                    debug!("Skipping {:?}: {}", dir, e);
+                    continue;
+                }
+            };
+            for entry in entries.filter_map(Result::ok) {
+                let file_type = match entry.file_type() {
+                    Ok(t) => t,
+                    Err(_) => continue,
+                };
+                if file_type.is_dir() {
+                    dirs.push(entry.path());
+                } else if file_type.is_file() {
+                    findings.extend(self.scan_file_path(&entry.path()));
+                }
+            }
+        }
+        info!("Found {} credentials under {:?}", findings.len(), root);
+        Ok(findings)
+    }
+
+    /// Reads and scans a single file if it's one we recognise
+    fn scan_file_path(&self, path: &Path) -> Vec<HostCredentialFinding> {
+        let path_str = path.to_string_lossy();
+        if CredentialFile::from_path(&path_str).is_none() {
+            return Vec::new();
+        }
+        match fs::read(path) {
+            Ok(data) => {
+                let findings = self.scan_file(&path_str, &data);
+                progress::scanned(data.len(), findings.len());
+                findings
+            }
+            Err(e) => {
+                debug!("Failed to read {:?}: {}", path, e);
+                Vec::new()
+            }
+        }
+    }
+
+    /// Parses the contents of a config file, identified by its path (see
+    /// `CredentialFile::from_path`), and returns the credentials in it. Files that aren't
+    /// recognised or don't parse return no findings.
+    pub fn scan_file(&self, path: &str, data: &[u8]) -> Vec<HostCredentialFinding> {
+        let kind = match CredentialFile::from_path(path) {
+            Some(k) => k,
+            None => return Vec::new(),
+        };
+        let text = String::from_utf8_lossy(data);
+        let result = match kind {
+            CredentialFile::AwsCredentials => Ok(aws_credentials(&text)),
+            CredentialFile::Pgpass => Ok(pgpass(&text)),
+            CredentialFile::MyCnf => Ok(my_cnf(&text)),
+            CredentialFile::Kubeconfig => {
+                structured_scanning::parse_document(data, StructuredFormat::Yaml)
+                    .map(|v| kubeconfig(&v))
+            }
+            json_kind => {
+                structured_scanning::parse_document(data, StructuredFormat::Json).map(|v| {
+                    match json_kind {
+                        CredentialFile::GcloudCredentials => gcloud_credentials(path, &v),
+                        CredentialFile::AzureAccessTokens => azure_access_tokens(&v),
+                        CredentialFile::AzureMsalCache => azure_msal_cache(&v),
+                        CredentialFile::AzureServicePrincipals => azure_service_principals(&v),
+                        _ => docker_config(&v),
+                    }
+                })
+            }
+        };
+        match result {
+            Ok(findings) => findings
+                .into_iter()
+                .map(|f| HostCredentialFinding {
+                    path: path.to_string(),
+                    ..f
+                })
+                .collect(),
+            Err(e) => {
+                debug!("Failed to parse {}: {}", path, e);
+                Vec::new()
+            }
+        }
+    }
+
+    /// Scans an OpenSSH user directory. Identity files named in `config` are reported against
+    /// the host they're configured for; any other private keys in the directory are reported
+    /// against each (unhashed) host in `known_hosts`, as ssh would offer them to all of them.
+    pub fn scan_ssh_dir(&self, dir: &Path) -> Vec<HostCredentialFinding> {
+        let home = dir.parent().unwrap_or(dir);
+        let mut findings: Vec<HostCredentialFinding> = Vec::new();
+        let mut configured_keys: HashSet<PathBuf> = HashSet::new();
+
+        let config_path = dir.join("config");
+        if let Ok(config) = fs::read_to_string(&config_path) {
+            for block in parse_ssh_config(&config) {
+                let host = match (&block.hostname, &block.port) {
+                    (Some(h), Some(p)) => format!("{}:{}", h, p),
+                    (Some(h), None) => h.clone(),
+                    (None, Some(p)) => format!("{}:{}", block.patterns, p),
+                    (None, None) => block.patterns.clone(),
+                };
+                for (identity_file, line) in &block.identity_files {
+                    let key_path = match resolve_identity_file(identity_file, home, dir) {
+                        Some(p) => p,
+                        None => continue,
+                    };
+                    if let Some(reason) = private_key_reason(&key_path) {
+                        configured_keys.insert(key_path.clone());
+                        findings.push(HostCredentialFinding {
+                            diff: line.clone(),
+                            strings_found: vec![key_path.to_string_lossy().to_string()],
+                            path: config_path.to_string_lossy().to_string(),
+                            host: host.clone(),
+                            user: block.user.clone().unwrap_or_default(),
+                            auth_type: String::from("private_key"),
+                            reason,
+                        });
+                    }
+                }
+            }
+        }
+
+        let known_hosts: Vec<String> = match fs::read_to_string(dir.join("known_hosts")) {
+            Ok(k) => parse_known_hosts(&k),
+            Err(_) => Vec::new(),
+        };
+        let entries = match fs::read_dir(dir) {
+            Ok(e) => e,
+            Err(e) => {
+                debug!("Failed to list {:?}: {}", dir, e);
+                return findings;
+            }
+        };
+        for entry in entries.filter_map(Result::ok) {
+            let key_path = entry.path();
+            let file_name = entry.file_name().to_string_lossy().to_string();
+            if SSH_NON_KEY_FILES.contains(&file_name.as_str())
+                || file_name.ends_with(".pub")
+                || configured_keys.contains(&key_path)
+            {
+                continue;
+            }
+            let reason = match private_key_reason(&key_path) {
+                Some(r) => r,
+                None => continue,
+            };
+            let hosts: Vec<String> = if known_hosts.is_empty() {
+                vec![String::from("*")]
+            } else {
+                known_hosts.clone()
+            };
+            for host in hosts {
+                findings.push(HostCredentialFinding {
+                    diff: file_name.clone(),
+                    strings_found: vec![key_path.to_string_lossy().to_string()],
+                    path: key_path.to_string_lossy().to_string(),
+                    host,
+                    user: String::new(),
+                    auth_type: String::from("private_key"),
+                    reason: reason.clone(),
+                });
+            }
+        }
+        findings
+    }
+}
+
+impl Default for HostCredentialScanner {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Builds a finding for a secret value; `path` is filled in by `scan_file`
+fn finding(
+    host: &str,
+    user: &str,
+    auth_type: &str,
+    reason: &str,
+    diff: String,
+    secret: &str,
+) -> HostCredentialFinding {
+    HostCredentialFinding {
+        diff,
+        strings_found: vec![secret.to_string()],
+        path: String::new(),
+        host: host.to_string(),
+        user: user.to_string(),
+        auth_type: auth_type.to_string(),
+        reason: reason.to_string(),
+    }
+}
+
+/// Splits an OpenSSH client config into `Host` / `Match` blocks. Settings before the first block
+/// apply to every host, so they're returned as a block for `*`.
+fn parse_ssh_config(config: &str) -> Vec<SshHostBlock> {
+    let mut blocks: Vec<SshHostBlock> = vec![SshHostBlock {
+        patterns: String::from("*"),
+        ..Default::default()
+    }];
+    for line in config.lines() {
+        let trimmed = line.trim();
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            continue;
+        }
+        let mut parts = trimmed.splitn(2, |c: char| c.is_whitespace() || c == '=');
+        let key = parts.next().unwrap_or("").to_ascii_lowercase();
+        let value = parts
+            .next()
+            .unwrap_or("")
+            .trim_start_matches(|c: char| c.is_whitespace() || c == '=')
+            .trim_matches('"')
+            .to_string();
+        let block = blocks.last_mut().unwrap();
+        match key.as_ref() {
+            "host" | "match" => blocks.push(SshHostBlock {
+                patterns: value,
+                ..Default::default()
+            }),
+            "hostname" => block.hostname = Some(value),
+            "user" => block.user = Some(value),
+            "port" => block.port = Some(value),
+            "identityfile" => block.identity_files.push((value, trimmed.to_string())),
+            _ => {}
+        }
+    }
+    blocks
+}
+
+/// Resolves an IdentityFile value against the home directory the config was found in. If an
+/// absolute path doesn't exist (e.g. when scanning a copy of a home directory), the file name is
+/// looked up in the `.ssh` directory instead.
+fn resolve_identity_file(value: &str, home: &Path, ssh_dir: &Path) -> Option<PathBuf> {
+    let value = value.replace("%d", &home.to_string_lossy());
+    let path = if value.starts_with("~/") {
+        home.join(&value[2..])
+    } else if Path::new(&value).is_absolute() {
+        PathBuf::from(&value)
+    } else {
+        home.join(&value)
+    };
+    if path.is_file() {
+        return Some(path);
+    }
+    let fallback = ssh_dir.join(path.file_name()?);
+    if fallback.is_file() {
+        Some(fallback)
+    } else {
+        None
+    }
+}
+
+/// Returns the hosts named in a known_hosts file, skipping hashed entries and revoked keys
+fn parse_known_hosts(known_hosts: &str) -> Vec<String> {
+    let mut hosts: Vec<String> = Vec::new();
+    for line in known_hosts.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') || line.starts_with("@revoked") {
+            continue;
+        }
+        let line = line.trim_start_matches("@cert-authority").trim_start();
+        let host_field = line.split_whitespace().next().unwrap_or("");
+        if host_field.starts_with('|') {
+            continue;
+        }
+        for host in host_field.split(',') {
+            // [host]:port form is used for non-standard ports
+            let host = host.replace('[', "").replace(']', "");
+            if !host.is_empty() && !host.starts_with('!') && !hosts.contains(&host) {
+                hosts.push(host);
+            }
+        }
+    }
+    hosts
+}
+
+/// Returns the reason to report a private key with, or `None` if the file isn't a private key
+fn private_key_reason(path: &Path) -> Option<String> {
+    if fs::metadata(path).ok()?.len() > MAX_KEY_SIZE {
+        return None;
+    }
+    let data = fs::read(path).ok()?;
+    if private_key_encrypted(&data)? {
+        Some(String::from("Encrypted SSH private key"))
+    } else {
+        Some(String::from("Unencrypted SSH private key"))
+    }
+}
+
+/// Checks whether a PEM or OpenSSH format private key is protected by a passphrase. Returns
+/// `None` if `data` isn't a private key at all.
+fn private_key_encrypted(data: &[u8]) -> Option<bool> {
+    let text = str::from_utf8(data).ok()?;
+    if !text.contains("PRIVATE KEY-----") {
+        return None;
+    }
+    if text.contains("BEGIN ENCRYPTED PRIVATE KEY") || text.contains("Proc-Type: 4,ENCRYPTED") {
+        return Some(true);
+    }
+    if text.contains("BEGIN OPENSSH PRIVATE KEY") {
+        // the cipher name follows the magic string, as a length-prefixed string
+        let body: String = text.lines().filter(|l| !l.starts_with("-----")).collect();
+        let decoded = base64::decode(body.trim()).ok()?;
+        let rest = decoded.get(OPENSSH_KEY_MAGIC.len()..)?;
+        if !decoded.starts_with(OPENSSH_KEY_MAGIC) || rest.len() < 4 {
+            return None;
+        }
+        let len = u32::from_be_bytes([rest[0], rest[1], rest[2], rest[3]]) as usize;
+        return Some(rest.get(4..4 + len)? != b"none");
+    }
+    Some(false)
+}
+
+/// Parses an INI style file into (section, lower case key, unquoted value, line) tuples
+fn parse_ini(text: &str) -> Vec<(String, String, String, String)> {
+    let mut entries: Vec<(String, String, String, String)> = Vec::new();
+    let mut section = String::new();
+    for line in text.lines() {
+        let trimmed = line.trim();
+        if trimmed.is_empty() || trimmed.starts_with('#') || trimmed.starts_with(';') {
+            continue;
+        }
+        if trimmed.starts_with('[') && trimmed.ends_with(']') {
+            section = trimmed[1..trimmed.len() - 1].trim().to_string();
+            continue;
+        }
+        let mut parts = trimmed.splitn(2, '=');
+        let key = parts.next().unwrap_or("").trim().to_ascii_lowercase();
+        let value = match parts.next() {
+            Some(v) => v.trim().trim_matches(|c| c == '"' || c == '\'').to_string(),
+            None => continue,
+        };
+        entries.push((section.clone(), key, value, trimmed.to_string()));
+    }
+    entries
+}
+
+/// `~/.aws/credentials` and `~/.aws/config`, where sections are profiles
+fn aws_credentials(text: &str) -> Vec<HostCredentialFinding> {
+    let mut findings: Vec<HostCredentialFinding> = Vec::new();
+    for (section, key, value, line) in parse_ini(text) {
+        let profile = section.trim_start_matches("profile ").trim();
+        let (auth_type, reason) = match key.as_ref() {
+            "aws_secret_access_key" => ("access_key", "AWS secret access key"),
+            "aws_session_token" | "aws_security_token" => ("session_token", "AWS session token"),
+            _ => continue,
+        };
+        if !value.is_empty() {
+            findings.push(finding("aws", profile, auth_type, reason, line, &value));
+        }
+    }
+    findings
+}
+
+/// `.pgpass` lines are `hostname:port:database:username:password`, with `\:` and `\\` escapes
+fn pgpass(text: &str) -> Vec<HostCredentialFinding> {
+    let mut findings: Vec<HostCredentialFinding> = Vec::new();
+    for line in text.lines() {
+        if line.trim().is_empty() || line.trim_start().starts_with('#') {
+            continue;
+        }
+        let mut fields: Vec<String> = vec![String::new()];
+        let mut chars = line.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '\\' => {
+                    if let Some(escaped) = chars.next() {
+                        fields.last_mut().unwrap().push(escaped);
+                    }
+                }
+                ':' if fields.len() < 5 => fields.push(String::new()),
+                c => fields.last_mut().unwrap().push(c),
+            }
+        }
+        if fields.len() != 5 || fields[4].is_empty() {
+            continue;
+        }
+        let host = format!("{}:{}/{}", fields[0], fields[1], fields[2]);
+        findings.push(finding(
+            &host,
+            &fields[3],
+            "password",
+            "PostgreSQL password",
+            line.to_string(),
+            &fields[4],
+        ));
+    }
+    findings
+}
+
+/// `.my.cnf` option groups, e.g. `[client]` or `[mysqldump]`, with host, port, user and password
+fn my_cnf(text: &str) -> Vec<HostCredentialFinding> {
+    let mut groups: HashMap<String, HashMap<String, (String, String)>> = HashMap::new();
+    for (section, key, value, line) in parse_ini(text) {
+        groups
+            .entry(section)
+            .or_insert_with(HashMap::new)
+            .insert(key.replace('-', "_"), (value, line));
+    }
+    let mut findings: Vec<HostCredentialFinding> = Vec::new();
+    for options in groups.values() {
+        let (password, line) = match options.get("password") {
+            Some((p, l)) if !p.is_empty() => (p, l),
+            _ => continue,
+        };
+        let get = |k: &str| options.get(k).map(|(v, _)| v.as_str());
+        let host = match (get("host").unwrap_or("localhost"), get("port")) {
+            (h, Some(p)) => format!("{}:{}", h, p),
+            (h, None) => h.to_string(),
+        };
+        findings.push(finding(
+            &host,
+            get("user").unwrap_or(""),
+            "password",
+            "MySQL password",
+            line.clone(),
+            password,
+        ));
+    }
+    findings
+}
+
+/// gcloud application default credentials, either a user's refresh token or a service account
+/// key. Legacy per-account credentials are stored under `legacy_credentials/<account>/adc.json`.
+fn gcloud_credentials(path: &str, credentials: &Value) -> Vec<HostCredentialFinding> {
+    let path = path.replace('\\', "/");
+    let account = path.rsplit('/').nth(1).unwrap_or("");
+    let account = if account.contains('@') { account } else { "" };
+    let mut findings: Vec<HostCredentialFinding> = Vec::new();
+    match credentials["type"].as_str() {
+        Some("authorized_user") => {
+            if let Some(token) = credentials["refresh_token"].as_str() {
+                findings.push(finding(
+                    "googleapis.com",
+                    account,
+                    "refresh_token",
+                    "GCP user refresh token",
+                    String::from("refresh_token"),
+                    token,
+                ));
+            }
+        }
+        Some("service_account") => {
+            if let Some(key) = credentials["private_key"].as_str() {
+                findings.push(finding(
+                    "googleapis.com",
+                    credentials["client_email"].as_str().unwrap_or(account),
+                    "private_key",
+                    "GCP service account key",
+                    format!(
+                        "private_key_id: {}",
+                        credentials["private_key_id"].as_str().unwrap_or("")
+                    ),
+                    key,
+                ));
+            }
+        }
+        _ => {}
+    }
+    findings
+}
+
+/// The host part of an authority URL such as `https://login.microsoftonline.com/<tenant>`
+fn authority_host(authority: &str) -> String {
+    authority
+        .trim_start_matches("https://")
+        .split('/')
+        .next()
+        .unwrap_or("login.microsoftonline.com")
+        .to_string()
+}
+
+/// `accessTokens.json` from Azure CLI versions before 2.30
+fn azure_access_tokens(tokens: &Value) -> Vec<HostCredentialFinding> {
+    let mut findings: Vec<HostCredentialFinding> = Vec::new();
+    for token in tokens.as_array().into_iter().flatten() {
+        let host = authority_host(token["_authority"].as_str().unwrap_or(""));
+        let user = token["userId"]
+            .as_str()
+            .or_else(|| token["servicePrincipalId"].as_str())
+            .unwrap_or("");
+        if let Some(t) = token["refreshToken"].as_str() {
+            findings.push(finding(
+                &host,
+                user,
+                "refresh_token",
+                "Azure refresh token",
+                String::from("refreshToken"),
+                t,
+            ));
+        }
+        // service principal entries store the client secret in accessToken
+        let is_service_principal = token["servicePrincipalId"].is_string();
+        if let Some(s) = token["accessToken"]
+            .as_str()
+            .filter(|_| is_service_principal)
+        {
+            findings.push(finding(
+                &host,
+                user,
+                "client_secret",
+                "Azure service principal secret",
+                String::from("accessToken"),
+                s,
+            ));
+        }
+    }
+    findings
+}
+
+/// `msal_token_cache.json` from Azure CLI 2.30 and later. Refresh tokens are matched to the
+/// username of their account by `home_account_id`.
+fn azure_msal_cache(cache: &Value) -> Vec<HostCredentialFinding> {
+    let mut usernames: HashMap<String, String> = HashMap::new();
+    if let Some(accounts) = cache["Account"].as_object() {
+        for account in accounts.values() {
+            if let (Some(id), Some(name)) = (
+                account["home_account_id"].as_str(),
+                account["username"].as_str(),
+            ) {
+                usernames.insert(id.to_string(), name.to_string());
+            }
+        }
+    }
+    let mut findings: Vec<HostCredentialFinding> = Vec::new();
+    if let Some(tokens) = cache["RefreshToken"].as_object() {
+        for (key, token) in tokens {
+            let secret = match token["secret"].as_str() {
+                Some(s) => s,
+                None => continue,
+            };
+            let account_id = token["home_account_id"].as_str().unwrap_or("");
+            findings.push(finding(
+                token["environment"]
+                    .as_str()
+                    .unwrap_or("login.microsoftonline.com"),
+                usernames
+                    .get(account_id)
+                    .map(String::as_str)
+                    .unwrap_or(account_id),
+                "refresh_token",
+                "Azure refresh token",
+                key.clone(),
+                secret,
+            ));
+        }
+    }
+    findings
+}
+
+/// `service_principal_entries.json` from Azure CLI 2.30 and later
+fn azure_service_principals(entries: &Value) -> Vec<HostCredentialFinding> {
+    let mut findings: Vec<HostCredentialFinding> = Vec::new();
+    for entry in entries.as_array().into_iter().flatten() {
+        let secret = match entry["client_secret"].as_str() {
+            Some(s) => s,
+            None => continue,
+        };
+        let tenant = entry["tenant"].as_str().unwrap_or("");
+        findings.push(finding(
+            &format!("login.microsoftonline.com/{}", tenant),
+            entry["client_id"].as_str().unwrap_or(""),
+            "client_secret",
+            "Azure service principal secret",
+            String::from("client_secret"),
+            secret,
+        ));
+    }
+    findings
+}
+
+/// A kubeconfig's users, matched to the server of each cluster they're paired with in a context
+fn kubeconfig(config: &Value) -> Vec<HostCredentialFinding> {
+    let named = |list: &str| -> HashMap<String, Value> {
+        config[list]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|item| Some((item["name"].as_str()?.to_string(), item.clone())))
+            .collect()
+    };
+    let clusters = named("clusters");
+    let users = named("users");
+    let mut servers_by_user: HashMap<String, Vec<String>> = HashMap::new();
+    for context in named("contexts").values() {
+        let user = context["context"]["user"]
+            .as_str()
+            .unwrap_or("")
+            .to_string();
+        let cluster = context["context"]["cluster"].as_str().unwrap_or("");
+        if let Some(server) = clusters
+            .get(cluster)
+            .and_then(|c| c["cluster"]["server"].as_str())
+        {
+            servers_by_user
+                .entry(user)
+                .or_insert_with(Vec::new)
+                .push(server.to_string());
+        }
+    }
+    let mut findings: Vec<HostCredentialFinding> = Vec::new();
+    for (name, user) in &users {
+        let user = &user["user"];
+        let auth_provider = &user["auth-provider"]["config"];
+        let material: Vec<(&str, &str, &str, &Value)> = vec![
+            ("token", "token", "Kubernetes bearer token", &user["token"]),
+            (
+                "password",
+                "password",
+                "Kubernetes password",
+                &user["password"],
+            ),
+            (
+                "client-key-data",
+                "client_key",
+                "Kubernetes client key",
+                &user["client-key-data"],
+            ),
+            (
+                "refresh-token",
+                "refresh_token",
+                "Kubernetes auth provider refresh token",
+                &auth_provider["refresh-token"],
+            ),
+            (
+                "client-secret",
+                "client_secret",
+                "Kubernetes auth provider client secret",
+                &auth_provider["client-secret"],
+            ),
+        ];
+        let username = user["username"].as_str().unwrap_or(name);
+        let default_servers = vec![String::new()];
+        let servers = servers_by_user.get(name).unwrap_or(&default_servers);
+        for (key, auth_type, reason, value) in material {
+            let secret = match value.as_str() {
+                Some(s) if !s.is_empty() => s,
+                _ => continue,
+            };
+            for server in servers {
+                findings.push(finding(
+                    server,
+                    username,
+                    auth_type,
+                    reason,
+                    format!("users[{}].user.{}", name, key),
+                    secret,
+                ));
+            }
+        }
+    }
+    findings
+}
+
+/// `~/.docker/config.json` logins, stored as base64 `user:password` per registry
+fn docker_config(config: &Value) -> Vec<HostCredentialFinding> {
+    let mut findings: Vec<HostCredentialFinding> = Vec::new();
+    if let Some(auths) = config["auths"].as_object() {
+        for (registry, auth) in auths {
+            if let Some(token) = auth["identitytoken"].as_str() {
+                findings.push(finding(
+                    registry,
+                    "",
+                    "token",
+                    "Docker registry identity token",
+                    format!("auths.{}.identitytoken", registry),
+                    token,
+                ));
+            }
+            let decoded = match auth["auth"].as_str().and_then(|a| base64::decode(a).ok()) {
+                Some(d) => String::from_utf8_lossy(&d).to_string(),
+                None => continue,
+            };
+            let mut parts = decoded.splitn(2, ':');
+            let user = parts.next().unwrap_or("");
+            if let Some(password) = parts.next().filter(|p| !p.is_empty()) {
+                findings.push(finding(
+                    registry,
+                    user,
+                    "password",
+                    "Docker registry password",
+                    format!("auths.{}.auth", registry),
+                    password,
+                ));
+            }
+        }
+    }
+    findings
+}
@@ -0,0 +1,454 @@
+//! Resolution of the people behind findings, for routing findings to their owners.
+//!
+//! Findings from Git repositories carry the email address of the commit `author`, and findings
+//! from Google Drive the email address of the file `owner`. An `IdentityResolver` looks these up
+//! with a list of connectors, in order, and adds the first match to the finding as an `identity`
+//! object:
+//!
+//! ```json
+//! "identity": {
+//!     "id": "jdoe",
+//!     "name": "Jane Doe",
+//!     "email": "jdoe@example.com",
+//!     "team": "Platform",
+//!     "source": "ldap"
+//! }
+//! ```
+//!
+//! The connectors are described in a JSON file, each with a `type`:
+//!
+//! * `ldap`: searches `base_dn` for an entry whose `mail` attribute is the email address, binding
+//!   as `bind_dn` with `bind_password` (or the `LDAP_PASSWORD` environment variable) if set. The
+//!   team is read from `team_attribute`, `department` by default. Needs the `ldap` feature.
+//! * `google_directory`: reads the user from the Google Workspace directory (Admin SDK Directory
+//!   API) with an OAuth access token, `token` or the `GOOGLE_DIRECTORY_TOKEN` environment
+//!   variable. The team is the user's department, or their organizational unit.
+//! * `github_org`: matches GitHub noreply addresses, or the public email of a GitHub profile, to a
+//!   member of `org`, with `token` or the `GITHUB_TOKEN` environment variable. Not available in
+//!   WebAssembly, like `google_directory`, as both need an HTTP client.
+//!
+//! ```json
+//! [
+//!     { "type": "ldap", "url": "ldaps://ldap.example.com", "base_dn": "ou=people,dc=example,dc=com" },
+//!     { "type": "google_directory" },
+//!     { "type": "github_org", "org": "example" }
+//! ]
+//! ```
+//!
+//! Each address is looked up once per report. Lookups that fail are logged and the next connector
+//! is tried, so a directory outage never fails a scan.
+//!
+//! # Examples
+//!
+//! ```
+//! use rusty_hogs::identity::{IdentityConnector, IdentityResolver};
+//!
+//! let resolver: IdentityResolver = r#"[{ "type": "github_org", "org": "example" }]"#
+//!     .parse()
+//!     .unwrap();
+//! assert_eq!(
+//!     resolver.connectors[0],
+//!     IdentityConnector::GithubOrg {
+//!         org: String::from("example"),
+//!         url: String::from("https://api.github.com"),
+//!         token: None,
+//!     }
+//! );
+//! assert!(IdentityResolver::default().is_empty());
+//! ```
+
+#[cfg(not(target_arch = "wasm32"))]
+use crate::network;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::retry;
+use log::{self, debug, warn};
+#[cfg(not(target_arch = "wasm32"))]
+use regex::Regex;
+#[cfg(not(target_arch = "wasm32"))]
+use reqwest::blocking::RequestBuilder;
+#[cfg(not(target_arch = "wasm32"))]
+use reqwest::StatusCode;
+use serde_derive::{Deserialize, Serialize};
+use serde_json::Value;
+use simple_error::{require_with, try_with, SimpleError};
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::str::FromStr;
+#[cfg(not(target_arch = "wasm32"))]
+use url::form_urlencoded;
+
+/// The finding fields holding the email address of the person behind a finding
+const IDENTITY_FIELDS: &[&str] = &["author", "owner"];
+
+/// GitHub rejects API requests without a User-Agent
+#[cfg(not(target_arch = "wasm32"))]
+const USER_AGENT: &str = "rusty-hog";
+
+/// A person resolved by a connector
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Hash, Clone, Default)]
+pub struct Identity {
+    /// The username or directory ID
+    pub id: String,
+    pub name: String,
+    pub email: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub team: Option<String>,
+    /// The type of connector that resolved the identity
+    pub source: String,
+}
+
+/// A directory to look people up in, see the module documentation
+#[derive(Deserialize, Debug, PartialEq, Eq, Hash, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum IdentityConnector {
+    Ldap {
+        url: String,
+        base_dn: String,
+        #[serde(default)]
+        bind_dn: Option<String>,
+        #[serde(default)]
+        bind_password: Option<String>,
+        #[serde(default = "default_team_attribute")]
+        team_attribute: String,
+    },
+    GoogleDirectory {
+        #[serde(default)]
+        token: Option<String>,
+    },
+    GithubOrg {
+        org: String,
+        #[serde(default = "default_github_url")]
+        url: String,
+        #[serde(default)]
+        token: Option<String>,
+    },
+}
+
+fn default_team_attribute() -> String {
+    String::from("department")
+}
+
+fn default_github_url() -> String {
+    String::from("https://api.github.com")
+}
+
+/// Connectors tried in order to resolve the people behind findings
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Default)]
+pub struct IdentityResolver {
+    pub connectors: Vec<IdentityConnector>,
+}
+
+impl IdentityResolver {
+    /// Reads and parses a connector file
+    pub fn from_file(path: &str) -> Result<Self, SimpleError> {
+        let json = try_with!(fs::read_to_string(path), "Failed to read {}", path);
+        json.parse()
+    }
+
+    /// True if there are no connectors
+    pub fn is_empty(&self) -> bool {
+        self.connectors.is_empty()
+    }
+
+    /// Looks up `email` with each connector in turn, returning the first match
+    pub fn resolve(&self, email: &str) -> Option<Identity> {
+        for connector in &self.connectors {
+            match connector.resolve(email) {
+                Ok(Some(identity)) => return Some(identity),
+                Ok(None) => debug!("{} has no match for {}", connector.name(), email),
+                Err(e) => warn!("{} lookup of {} failed: {}", connector.name(), email, e),
+            }
+        }
+        None
+    }
+
+    /// Adds an `identity` object to each serialized finding whose author or owner resolves
+    pub fn annotate(&self, findings: Vec<Value>) -> Vec<Value> {
+        if self.is_empty() {
+            return findings;
+        }
+        let mut cache: HashMap<String, Option<Identity>> = HashMap::new();
+        findings
+            .into_iter()
+            .map(|mut finding| {
+                let email = IDENTITY_FIELDS
+                    .iter()
+                    .filter_map(|f| finding[*f].as_str())
+                    .find(|e| !e.is_empty())
+                    .map(str::to_lowercase);
+                let identity = match email {
+                    Some(e) => cache
+                        .entry(e.clone())
+                        .or_insert_with(|| self.resolve(&e))
+                        .clone(),
+                    None => None,
+                };
+                if let (Some(identity), Some(object)) = (identity, finding.as_object_mut()) {
+                    object.insert(
+                        String::from("identity"),
+                        serde_json::to_value(identity).unwrap(),
+                    );
+                }
+                finding
+            })
+            .collect()
+    }
+}
+
+impl FromStr for IdentityResolver {
+    type Err = SimpleError;
+
+    /// Parses a JSON array of connectors
+    fn from_str(json: &str) -> Result<Self, Self::Err> {
+        let connectors: Vec<IdentityConnector> = try_with!(
+            serde_json::from_str(json),
+            "Failed to parse the identity connectors"
+        );
+        Ok(Self { connectors })
+    }
+}
+
+impl IdentityConnector {
+    /// The connector type, as written in the connector file
+    pub fn name(&self) -> &'static str {
+        match self {
+            IdentityConnector::Ldap { .. } => "ldap",
+            IdentityConnector::GoogleDirectory { .. } => "google_directory",
+            IdentityConnector::GithubOrg { .. } => "github_org",
+        }
+    }
+
+    /// Looks up `email`, returning None if the directory has no match
+    pub fn resolve(&self, email: &str) -> Result<Option<Identity>, SimpleError> {
+        match self {
+            IdentityConnector::Ldap {
+                url,
+                base_dn,
+                bind_dn,
+                bind_password,
+                team_attribute,
+            } => {
+                let password = bind_password
+                    .clone()
+                    .or_else(|| env::var("LDAP_PASSWORD").ok());
+                ldap_lookup(
+                    url,
+                    base_dn,
+                    bind_dn.as_ref().map(String::as_str),
+                    password.as_ref().map(String::as_str),
+                    team_attribute,
+                    email,
+                )
+            }
+            IdentityConnector::GoogleDirectory { token } => {
+                let token = require_with!(
+                    token
+                        .clone()
+                        .or_else(|| env::var("GOOGLE_DIRECTORY_TOKEN").ok()),
+                    "No Google Directory token, set token or GOOGLE_DIRECTORY_TOKEN"
+                );
+                google_directory_lookup(&token, email)
+            }
+            IdentityConnector::GithubOrg { org, url, token } => {
+                let token = token.clone().or_else(|| env::var("GITHUB_TOKEN").ok());
+                github_org_lookup(url, org, token.as_ref().map(String::as_str), email)
+            }
+        }
+    }
+}
+
+#[cfg(feature = "ldap")]
+fn ldap_lookup(
+    url: &str,
+    base_dn: &str,
+    bind_dn: Option<&str>,
+    bind_password: Option<&str>,
+    team_attribute: &str,
+    email: &str,
+) -> Result<Option<Identity>, SimpleError> {
+    use ldap3::{ldap_escape, LdapConn, Scope, SearchEntry};
+
+    let mut ldap = try_with!(LdapConn::new(url), "Failed to connect to {}", url);
+    if let Some(dn) = bind_dn {
+        let result = try_with!(
+            ldap.simple_bind(dn, bind_password.unwrap_or_default()),
+            "Failed to bind as {}",
+            dn
+        );
+        try_with!(result.success(), "Failed to bind as {}", dn);
+    }
+    let filter = format!("(mail={})", ldap_escape(email));
+    let search = try_with!(
+        ldap.search(
+            base_dn,
+            Scope::Subtree,
+            &filter,
+            vec!["uid", "cn", "mail", team_attribute],
+        ),
+        "Failed to search {}",
+        base_dn
+    );
+    let (entries, _) = try_with!(search.success(), "Failed to search {}", base_dn);
+    let _ = ldap.unbind();
+    Ok(entries.into_iter().next().map(|entry| {
+        let entry = SearchEntry::construct(entry);
+        let first = |attribute: &str| entry.attrs.get(attribute).and_then(|v| v.first()).cloned();
+        Identity {
+            id: first("uid").unwrap_or_else(|| entry.dn.clone()),
+            name: first("cn").unwrap_or_default(),
+            email: first("mail").unwrap_or_else(|| email.to_string()),
+            team: first(team_attribute),
+            source: String::from("ldap"),
+        }
+    }))
+}
+
+#[cfg(not(feature = "ldap"))]
+fn ldap_lookup(
+    _url: &str,
+    _base_dn: &str,
+    _bind_dn: Option<&str>,
+    _bind_password: Option<&str>,
+    _team_attribute: &str,
+    _email: &str,
+) -> Result<Option<Identity>, SimpleError> {
+    Err(SimpleError::new(
+        "LDAP lookups need Rusty Hog to be built with the ldap feature",
+    ))
+}
+
+/// Reads a user from the Admin SDK Directory API, None if there is no such user
+#[cfg(not(target_arch = "wasm32"))]
+fn google_directory_lookup(token: &str, email: &str) -> Result<Option<Identity>, SimpleError> {
+    let url = format!(
+        "https://admin.googleapis.com/admin/directory/v1/users/{}?viewType=domain_public",
+        encode(email)
+    );
+    debug!("GET {}", url);
+    let response = try_with!(
+        retry::send(network::client().get(&url).bearer_auth(token)),
+        "Google Directory request failed"
+    );
+    if response.status() == StatusCode::NOT_FOUND {
+        return Ok(None);
+    }
+    if !response.status().is_success() {
+        return Err(SimpleError::new(format!(
+            "Google Directory returned {} for {}",
+            response.status(),
+            email
+        )));
+    }
+    let user: Value = try_with!(response.json(), "Invalid Google Directory response");
+    let department = user["organizations"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .find_map(|o| o["department"].as_str());
+    Ok(Some(Identity {
+        id: user["id"].as_str().unwrap_or_default().to_string(),
+        name: user["name"]["fullName"]
+            .as_str()
+            .unwrap_or_default()
+            .to_string(),
+        email: user["primaryEmail"].as_str().unwrap_or(email).to_string(),
+        team: department
+            .or_else(|| user["orgUnitPath"].as_str())
+            .map(String::from),
+        source: String::from("google_directory"),
+    }))
+}
+
+#[cfg(target_arch = "wasm32")]
+fn google_directory_lookup(_token: &str, _email: &str) -> Result<Option<Identity>, SimpleError> {
+    Err(SimpleError::new(
+        "Google Directory lookups aren't available in WebAssembly",
+    ))
+}
+
+/// Finds the GitHub user behind `email` and checks that they are a member of `org`
+#[cfg(not(target_arch = "wasm32"))]
+fn github_org_lookup(
+    url: &str,
+    org: &str,
+    token: Option<&str>,
+    email: &str,
+) -> Result<Option<Identity>, SimpleError> {
+    let noreply = Regex::new(r"^(?:\d+\+)?([^@]+)@users\.noreply\.github\.com$").unwrap();
+    let login = match noreply.captures(email) {
+        Some(c) => c[1].to_string(),
+        None => {
+            let path = format!("search/users?q={}+in:email", encode(email));
+            let found: Value = try_with!(
+                github_get(url, &path, token)?.json(),
+                "Invalid GitHub search response"
+            );
+            match found["items"][0]["login"].as_str() {
+                Some(l) => l.to_string(),
+                None => return Ok(None),
+            }
+        }
+    };
+    let membership = github_get(url, &format!("orgs/{}/members/{}", org, login), token)?;
+    if membership.status() != StatusCode::NO_CONTENT {
+        return Ok(None);
+    }
+    let user: Value = try_with!(
+        github_get(url, &format!("users/{}", login), token)?.json(),
+        "Invalid GitHub user response"
+    );
+    Ok(Some(Identity {
+        id: login.clone(),
+        name: user["name"].as_str().unwrap_or(&login).to_string(),
+        email: email.to_string(),
+        team: None,
+        source: String::from("github_org"),
+    }))
+}
+
+#[cfg(target_arch = "wasm32")]
+fn github_org_lookup(
+    _url: &str,
+    _org: &str,
+    _token: Option<&str>,
+    _email: &str,
+) -> Result<Option<Identity>, SimpleError> {
+    Err(SimpleError::new(
+        "GitHub organization lookups aren't available in WebAssembly",
+    ))
+}
+
+/// Percent-encodes `value` for use in a URL
+#[cfg(not(target_arch = "wasm32"))]
+fn encode(value: &str) -> String {
+    form_urlencoded::byte_serialize(value.as_bytes()).collect()
+}
+
+/// Sends a GET request to the GitHub API, failing on any status other than success or 404
+#[cfg(not(target_arch = "wasm32"))]
+fn github_get(
+    url: &str,
+    path: &str,
+    token: Option<&str>,
+) -> Result<reqwest::blocking::Response, SimpleError> {
+    let url = format!("{}/{}", url.trim_end_matches('/'), path);
+    debug!("GET {}", url);
+    let request: RequestBuilder = network::client()
+        .get(&url)
+        .header("Accept", "application/vnd.github.v3+json")
+        .header("User-Agent", USER_AGENT);
+    let request = match token {
+        Some(t) => request.header("Authorization", format!("token {}", t)),
+        None => request,
+    };
+    let response = try_with!(retry::send(request), "GitHub request failed");
+    let status = response.status();
+    if !status.is_success() && status != StatusCode::NOT_FOUND {
+        return Err(SimpleError::new(format!(
+            "GitHub returned {} for {}",
+            status, url
+        )));
+    }
+    Ok(response)
+}
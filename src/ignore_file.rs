@@ -0,0 +1,195 @@
+//! Reading `.gitignore` and `.hogignore` files, to leave out the files they ignore when walking a
+//! directory (Duroc Hog's `--respect-ignore`).
+//!
+//! A `.hogignore` file has the syntax of a `.gitignore` file, for the files that are committed but
+//! shouldn't be scanned, e.g. test fixtures. The patterns of a directory's `.gitignore` come first
+//! and those of its `.hogignore` after them, so a `.hogignore` can re-include a file with `!`.
+//! As in git, the last pattern that matches a path decides whether it is ignored, the ignore
+//! files of a subdirectory take precedence over those of its parents, and the files of an
+//! ignored directory can't be re-included.
+//!
+//! # Examples
+//!
+//! ```
+//! use rusty_hogs::ignore_file::IgnoreFile;
+//! use std::path::Path;
+//!
+//! let text = "# dependencies\nnode_modules/\n/build\n*.log\n!keep.log\ndocs/**/*.pdf\n";
+//! let ignore_file = IgnoreFile::parse(Path::new("/src/app"), text).unwrap();
+//! let matched = |path: &str, is_dir| ignore_file.matched(Path::new(path), is_dir);
+//! assert_eq!(matched("/src/app/web/node_modules", true), Some(true));
+//! assert_eq!(matched("/src/app/web/node_modules", false), None);
+//! assert_eq!(matched("/src/app/build", true), Some(true));
+//! assert_eq!(matched("/src/app/web/build", true), None);
+//! assert_eq!(matched("/src/app/logs/server.log", false), Some(true));
+//! assert_eq!(matched("/src/app/logs/keep.log", false), Some(false));
+//! assert_eq!(matched("/src/app/docs/api/v1/guide.pdf", false), Some(true));
+//! assert_eq!(matched("/src/app/src/main.rs", false), None);
+//! assert_eq!(matched("/src/other/server.log", false), None);
+//! ```
+
+use regex::Regex;
+use simple_error::{try_with, SimpleError};
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+/// The ignore files of a directory, in the order their patterns are read
+pub const IGNORE_FILE_NAMES: &[&str] = &[".gitignore", ".hogignore"];
+
+/// One line of an ignore file
+#[derive(Debug, Clone)]
+struct IgnorePattern {
+    regex: Regex,
+    /// Re-includes the paths it matches, the pattern started with `!`
+    negated: bool,
+    /// Only matches directories, the pattern ended with `/`
+    dir_only: bool,
+}
+
+/// The patterns of the ignore files of a directory, which apply to the paths under it
+#[derive(Debug, Clone)]
+pub struct IgnoreFile {
+    pub dir: PathBuf,
+    patterns: Vec<IgnorePattern>,
+}
+
+impl IgnoreFile {
+    /// Reads the `.gitignore` and `.hogignore` files of `dir`. Returns None if it has neither.
+    pub fn read(dir: &Path) -> Result<Option<Self>, SimpleError> {
+        let mut text = String::new();
+        let mut found = false;
+        for name in IGNORE_FILE_NAMES {
+            let path = dir.join(name);
+            match fs::read_to_string(&path) {
+                Ok(t) => {
+                    text.push_str(&t);
+                    text.push('\n');
+                    found = true;
+                }
+                Err(e) if e.kind() == ErrorKind::NotFound => (),
+                Err(e) => {
+                    return Err(SimpleError::new(format!(
+                        "Failed to read {}: {}",
+                        path.display(),
+                        e
+                    )))
+                }
+            }
+        }
+        if found {
+            Ok(Some(Self::parse(dir, &text)?))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Parses the text of the ignore files of `dir`
+    pub fn parse(dir: &Path, text: &str) -> Result<Self, SimpleError> {
+        let mut patterns = Vec::new();
+        for line in text.lines() {
+            let mut pattern = line.trim_end();
+            if pattern.is_empty() || pattern.starts_with('#') {
+                continue;
+            }
+            let negated = pattern.starts_with('!');
+            if negated {
+                pattern = &pattern[1..];
+            }
+            let dir_only = pattern.ends_with('/');
+            let pattern = pattern.trim_end_matches('/');
+            // A pattern with a slash other than at its end is relative to the directory, one
+            // without matches a file name at any depth
+            let anchored = pattern.contains('/');
+            let pattern = pattern.trim_start_matches('/');
+            if pattern.is_empty() {
+                continue;
+            }
+            let mut regex = String::from(if anchored { "^" } else { "^(?:.*/)?" });
+            regex.push_str(&glob_to_regex(pattern));
+            regex.push('$');
+            patterns.push(IgnorePattern {
+                regex: try_with!(Regex::new(&regex), "Invalid ignore pattern {:?}", line),
+                negated,
+                dir_only,
+            });
+        }
+        Ok(Self {
+            dir: dir.to_path_buf(),
+            patterns,
+        })
+    }
+
+    /// Returns Some(true) if the file or directory at `path` is ignored, Some(false) if it is
+    /// re-included with a `!` pattern and None if no pattern matches it or it isn't under `dir`
+    pub fn matched(&self, path: &Path, is_dir: bool) -> Option<bool> {
+        let relative = path.strip_prefix(&self.dir).ok()?;
+        let relative: Vec<String> = relative
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy().into_owned())
+            .collect();
+        let relative = relative.join("/");
+        self.patterns
+            .iter()
+            .rev()
+            .find(|p| (is_dir || !p.dir_only) && p.regex.is_match(&relative))
+            .map(|p| !p.negated)
+    }
+}
+
+/// Returns true if `path` is ignored by the ignore files in `ignore_files`, which are those of its
+/// parent directories from the outermost to the innermost
+pub fn is_ignored(ignore_files: &[IgnoreFile], path: &Path, is_dir: bool) -> bool {
+    ignore_files
+        .iter()
+        .rev()
+        .find_map(|f| f.matched(path, is_dir))
+        .unwrap_or(false)
+}
+
+/// Translates the glob syntax of ignore files to a regex: `*` and `?` match within a path segment,
+/// `**` matches across segments and `[...]` is a character class
+fn glob_to_regex(glob: &str) -> String {
+    let mut regex = String::new();
+    let mut chars = glob.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '*' if chars.peek() == Some(&'*') => {
+                chars.next();
+                if chars.peek() == Some(&'/') {
+                    chars.next();
+                    regex.push_str("(?:.*/)?");
+                } else {
+                    regex.push_str(".*");
+                }
+            }
+            '*' => regex.push_str("[^/]*"),
+            '?' => regex.push_str("[^/]"),
+            '[' => {
+                let class: String = chars.clone().take_while(|&c| c != ']').collect();
+                if chars.clone().nth(class.chars().count()) == Some(']') && !class.is_empty() {
+                    for _ in 0..=class.chars().count() {
+                        chars.next();
+                    }
+                    let class = if class.starts_with('!') {
+                        format!("^{}", &class[1..])
+                    } else {
+                        class
+                    };
+                    regex.push('[');
+                    regex.push_str(&class.replace('\\', "\\\\").replace('[', "\\["));
+                    regex.push(']');
+                } else {
+                    regex.push_str("\\[");
+                }
+            }
+            '\\' => {
+                if let Some(escaped) = chars.next() {
+                    regex.push_str(&regex::escape(&escaped.to_string()));
+                }
+            }
+            c => regex.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+    regex
+}
@@ -0,0 +1,273 @@
+//! Collection of tools for scanning Jenkins controllers for secrets.
+//!
+//! `JenkinsScanner` acts as a wrapper around a `SecretScanner` object and talks to the
+//! [Jenkins remote access API](https://www.jenkins.io/doc/book/using/remote-access-api/) using a
+//! username and API token. It can enumerate every job on a controller (descending into folders,
+//! multibranch projects and organization folders) and, for each job, scan:
+//!
+//! * the job's `config.xml`, where parameters, environment variables and inline pipeline scripts
+//!   often hold credentials that should have been in the credentials store. Reading it needs the
+//!   Job/ExtendedRead permission; jobs whose configuration can't be read are skipped.
+//! * the console log of its most recent builds, where credentials that Jenkins didn't mask are
+//!   echoed by build scripts
+//!
+//! Listing can start from the root of the controller or from any folder or job URL.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use rusty_hogs::jenkins_scanning::{JenkinsConfig, JenkinsScanner};
+//!
+//! let js = JenkinsScanner::new();
+//! let config = JenkinsConfig {
+//!     url: "https://jenkins.example.com".to_string(),
+//!     username: Some("admin".to_string()),
+//!     token: Some("11aa...".to_string()),
+//! };
+//! for job in js.list_jobs(&config, &config.url).unwrap() {
+//!     let findings = js.scan_job(&config, &job, true, 10, false).unwrap();
+//!     js.secret_scanner.output_findings(&findings);
+//! }
+//! ```
+
+use crate::network;
+use crate::retry;
+use crate::{Finding, SecretScanner, Span, TextFinding};
+use chrono::NaiveDateTime;
+use log::{self, debug, info};
+use reqwest::blocking::Response;
+use serde_derive::{Deserialize, Serialize};
+use serde_json::Value;
+use simple_error::{try_with, SimpleError};
+use std::collections::HashSet;
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Hash, Clone, Default)]
+/// `serde_json` object that represents a single found secret - finding
+pub struct JenkinsFinding {
+    pub diff: String,
+    #[serde(rename = "stringsFound")]
+    pub strings_found: Vec<String>,
+    /// The full name of the job, including its folders, e.g. `team/deploy`
+    pub job: String,
+    /// Either `config` or `console_log`
+    pub source: String,
+    /// `config.xml` for configuration findings, or `#<build number>` for console logs
+    pub path: String,
+    /// A link to the job configuration or build console
+    pub url: String,
+    pub date: String,
+    pub reason: String,
+    /// Where each of `strings_found` is in the text, see `Span`
+    #[serde(default)]
+    pub spans: Vec<Span>,
+}
+
+impl TextFinding for JenkinsFinding {
+    fn with_match(&self, found: Finding) -> Self {
+        Self {
+            diff: found.diff,
+            strings_found: found.strings_found,
+            reason: found.reason,
+            spans: found.spans,
+            ..self.clone()
+        }
+    }
+}
+
+/// The Jenkins controller to talk to and the credentials to authenticate with
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+pub struct JenkinsConfig {
+    /// The base URL of the controller, e.g. `https://jenkins.example.com`
+    pub url: String,
+    /// The user that owns `token`. Requests are anonymous unless both are set.
+    pub username: Option<String>,
+    /// An API token of `username`
+    pub token: Option<String>,
+}
+
+/// A job on a Jenkins controller. Only the fields we need are included.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Hash, Clone)]
+pub struct JenkinsJob {
+    /// The name of the job, including its folders, e.g. `team/deploy`
+    pub full_name: String,
+    /// The URL of the job, ending with a slash
+    pub url: String,
+}
+
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+/// Contains helper functions for performing scans of Jenkins jobs
+pub struct JenkinsScanner {
+    pub secret_scanner: SecretScanner,
+}
+
+/// Acts as a wrapper around a `SecretScanner` object to provide helper functions for performing
+/// scanning against Jenkins. Relies on the [reqwest](https://docs.rs/reqwest/) library for HTTP
+/// access.
+impl JenkinsScanner {
+    /// Initialize the SecretScanner object first using the SecretScannerBuilder, then provide
+    /// it to this constructor method.
+    pub fn new_from_scanner(secret_scanner: SecretScanner) -> Self {
+        Self { secret_scanner }
+    }
+
+    pub fn new() -> Self {
+        Self {
+            secret_scanner: SecretScanner::default(),
+        }
+    }
+
+    /// Returns every job under `url`, which is the controller's URL or the URL of a folder. Items
+    /// that contain jobs themselves (folders, multibranch projects, organization folders) are
+    /// descended into rather than returned. If `url` is a job, just that job is returned.
+    pub fn list_jobs(
+        &self,
+        config: &JenkinsConfig,
+        url: &str,
+    ) -> Result<Vec<JenkinsJob>, SimpleError> {
+        let url = format!("{}/", url.trim_end_matches('/'));
+        let item = get_json(
+            config,
+            &format!("{}api/json?tree=fullName,url,jobs[url]", url),
+        )?;
+        let mut jobs: Vec<JenkinsJob> = Vec::new();
+        match item["jobs"].as_array() {
+            Some(children) => {
+                for child in children {
+                    if let Some(child_url) = child["url"].as_str() {
+                        jobs.extend(self.list_jobs(config, child_url)?);
+                    }
+                }
+            }
+            None => jobs.push(JenkinsJob {
+                full_name: item["fullName"].as_str().unwrap_or("").to_string(),
+                url,
+            }),
+        }
+        Ok(jobs)
+    }
+
+    /// Scans the configuration of `job` unless `scan_config` is false, and the console logs of its
+    /// `max_builds` most recent builds, returning a HashSet of findings.
+    pub fn scan_job(
+        &self,
+        config: &JenkinsConfig,
+        job: &JenkinsJob,
+        scan_config: bool,
+        max_builds: usize,
+        scan_entropy: bool,
+    ) -> Result<HashSet<JenkinsFinding>, SimpleError> {
+        info!("Scanning job {}", job.full_name);
+        let mut findings: HashSet<JenkinsFinding> = HashSet::new();
+        if scan_config {
+            match get(config, &format!("{}config.xml", job.url)) {
+                Ok(response) => {
+                    let content = try_with!(
+                        response.bytes(),
+                        "Failed to download the configuration of {}",
+                        job.full_name
+                    );
+                    let template = JenkinsFinding {
+                        job: job.full_name.clone(),
+                        source: String::from("config"),
+                        path: String::from("config.xml"),
+                        url: format!("{}configure", job.url),
+                        ..Default::default()
+                    };
+                    self.secret_scanner
+                        .scan_text(&content, &template, scan_entropy, &mut findings);
+                }
+                // usually a missing Job/ExtendedRead permission, which shouldn't stop the logs
+                Err(e) => debug!("Skipping the configuration of {}: {}", job.full_name, e),
+            }
+        }
+        if max_builds > 0 {
+            findings.extend(self.scan_builds(config, job, max_builds, scan_entropy)?);
+        }
+        Ok(findings)
+    }
+
+    /// Scans the console logs of the `max_builds` most recent builds of `job`, skipping builds that
+    /// started outside the scanner's time range
+    fn scan_builds(
+        &self,
+        config: &JenkinsConfig,
+        job: &JenkinsJob,
+        max_builds: usize,
+        scan_entropy: bool,
+    ) -> Result<HashSet<JenkinsFinding>, SimpleError> {
+        let mut findings: HashSet<JenkinsFinding> = HashSet::new();
+        let item = get_json(
+            config,
+            &format!(
+                "{}api/json?tree=builds[number,url,timestamp]{{0,{}}}",
+                job.url, max_builds
+            ),
+        )?;
+        let builds = match item["builds"].as_array() {
+            Some(b) => b.clone(),
+            None => Vec::new(),
+        };
+        debug!("Scanning {} builds of {}", builds.len(), job.full_name);
+        for build in builds {
+            let build_url = match build["url"].as_str() {
+                Some(u) => format!("{}/", u.trim_end_matches('/')),
+                None => continue,
+            };
+            if let Some(ms) = build["timestamp"].as_i64() {
+                if !self.secret_scanner.time_range.contains_timestamp(ms / 1000) {
+                    continue;
+                }
+            }
+            let response = get(config, &format!("{}consoleText", build_url))?;
+            let log = try_with!(response.bytes(), "Failed to download {}", build_url);
+            let template = JenkinsFinding {
+                job: job.full_name.clone(),
+                source: String::from("console_log"),
+                path: format!("#{}", build["number"]),
+                url: format!("{}console", build_url),
+                date: match build["timestamp"].as_i64() {
+                    Some(ms) => NaiveDateTime::from_timestamp(ms / 1000, 0).to_string(),
+                    None => String::new(),
+                },
+                ..Default::default()
+            };
+            self.secret_scanner
+                .scan_text(&log, &template, scan_entropy, &mut findings);
+        }
+        Ok(findings)
+    }
+}
+
+impl Default for JenkinsScanner {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Performs a GET against the controller, with basic authentication if a username and token are
+/// configured. `url` is a full URL.
+fn get(config: &JenkinsConfig, url: &str) -> Result<Response, SimpleError> {
+    debug!("GET {}", url);
+    let mut request = network::client().get(url);
+    if let (Some(username), Some(token)) = (&config.username, &config.token) {
+        request = request.basic_auth(username, Some(token));
+    }
+    let response = try_with!(retry::send(request), "Jenkins request failed");
+    if !response.status().is_success() {
+        return Err(SimpleError::new(format!(
+            "Jenkins returned {} for {}",
+            response.status(),
+            url
+        )));
+    }
+    Ok(response)
+}
+
+/// Performs a GET and parses the response as JSON
+fn get_json(config: &JenkinsConfig, url: &str) -> Result<Value, SimpleError> {
+    let response = get(config, url)?;
+    Ok(try_with!(
+        response.json(),
+        "Failed to parse Jenkins response"
+    ))
+}
@@ -0,0 +1,985 @@
+//! Running scans as jobs on a pool of workers, for the `hogd` scan server and the `hog` manifest
+//! runner.
+//!
+//! A job names a source of the `sources` registry and the arguments of its scanner. `JobQueue`
+//! runs it on one of its worker threads, writing the findings as JSON to a directory of the job
+//! under the work directory, and keeps the status of every job it was given: queued, running,
+//! then succeeded or failed. How the scanner runs depends on the `Runner` of the queue:
+//!
+//! * `Runner::InProcess` scans with the library on the worker thread, for the sources of
+//!   `in_process_scanners` only. A job's arguments are parsed against an allowlist: the target
+//!   and options of its source that only choose what to scan, and the `cli::tuning_args`. Any
+//!   other argument is rejected when the job is submitted, so a job can't choose where results
+//!   are written, read or write files of the server or send findings anywhere. This is what
+//!   `hogd` runs, as whoever can submit jobs is not trusted with the server. The rule files and
+//!   allowlist come from the server instead (see `JobQueue::set_rules`), as they are when the job
+//!   starts.
+//!   A job can't take more than `MAX_THREADS` threads, and with `JobQueue::set_limits` the queue
+//!   refuses jobs past a number waiting for a worker and forgets the jobs that finished long ago
+//!   or whose findings were fetched, removing their directories, so that clients can't exhaust
+//!   the memory, disk or threads of the server.
+//! * `Runner::Binary` runs the scanner binary of the source, plugins included, for `hog`. The
+//!   options of a job must be options of its source (see `SourceInfo::options`) or one of
+//!   `COMMON_OPTIONS`. `--outputfile` and `--format` are set by the queue.
+//!
+//! # Examples
+//!
+//! ```
+//! use rusty_hogs::jobs::{JobQueue, JobRequest, JobState, Runner};
+//! use rusty_hogs::sources::{SourceInfo, SourceRegistry};
+//! use std::{thread, time::Duration};
+//!
+//! let mut registry = SourceRegistry::new();
+//! let broken = SourceInfo {
+//!     name: String::from("broken"),
+//!     binary: String::from("false"),
+//!     options: vec![String::from("--fast")],
+//!     ..Default::default()
+//! };
+//! registry.register(broken).unwrap();
+//! let work_dir = tempdir::TempDir::new("hogd").unwrap();
+//! let queue = JobQueue::start(registry, work_dir.path(), Runner::Binary(None), 2).unwrap();
+//!
+//! let job = |source: &str, args: &[&str]| JobRequest {
+//!     source: String::from(source),
+//!     args: args.iter().map(|a| a.to_string()).collect(),
+//! };
+//! assert!(queue.submit(job("nope", &[])).is_err());
+//! assert!(queue.submit(job("broken", &["--outputfile", "/etc/passwd"])).is_err());
+//! let id = queue.submit(job("broken", &["--fast", "--entropy"])).unwrap().id;
+//! while !queue.status(&id).unwrap().state.is_finished() {
+//!     thread::sleep(Duration::from_millis(10));
+//! }
+//! assert_eq!(queue.status(&id).unwrap().state, JobState::Failed);
+//! assert!(queue.findings(&id).is_err());
+//!
+//! // a job run in-process can only set the options of its source and the tuning flags
+//! let work_dir = tempdir::TempDir::new("hogd").unwrap();
+//! let queue = JobQueue::start(SourceRegistry::new(), work_dir.path(), Runner::InProcess, 2).unwrap();
+//! assert!(queue.submit(job("broken", &[])).is_err());
+//! # #[cfg(feature = "git")]
+//! # {
+//! let repo = "https://github.com/newrelic/rusty-hog.git";
+//! assert!(queue.submit(job("git", &[repo, "--regex", "/etc/shadow"])).is_err());
+//! assert!(queue.submit(job("git", &[repo, "--checkpoint", "/tmp/x"])).is_err());
+//! assert!(queue.submit(job("git", &["/var/lib/secrets"])).is_err());
+//! assert!(queue.submit(job("git", &[repo, "--recurse-submodules"])).is_err());
+//! assert!(queue.sources().iter().any(|s| s.name == "git"));
+//! # }
+//! ```
+
+use crate::cli;
+#[cfg(feature = "gcs")]
+use crate::gcs_scanning::{GcsConfig, GcsCredentials, GcsScanner};
+#[cfg(feature = "git")]
+use crate::git_scanning::GitScanner;
+#[cfg(feature = "gitlab")]
+use crate::gitlab_scanning::{GitlabConfig, GitlabProject, GitlabScanner};
+use crate::rules::RuleWatcher;
+use crate::sources::{SourceInfo, SourceRegistry};
+#[cfg(any(feature = "git", feature = "gcs"))]
+use crate::time_range::validate_time;
+use crate::SecretScannerBuilder;
+use chrono::Utc;
+use clap::{App, AppSettings, Arg, ArgMatches};
+use log::{self, debug, error, info};
+use serde_derive::{Deserialize, Serialize};
+use serde_json::Value;
+use simple_error::{require_with, try_with, SimpleError};
+use std::collections::BTreeMap;
+#[cfg(any(feature = "gitlab", feature = "gcs"))]
+use std::collections::HashSet;
+use std::fs;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// The file of a job directory the scanner writes its findings to
+const FINDINGS_FILE: &str = "findings.json";
+
+/// The options every scanner binary takes that a job run by `Runner::Binary` can set besides
+/// the options of its source: the settings of the rules and of which findings are reported.
+/// Values that start with a dash are given as `--option=value`.
+pub const COMMON_OPTIONS: &[&str] = &[
+    "--config",
+    "--regex",
+    "--enable-rule",
+    "--disable-rule",
+    "--rule-timeout",
+    "--disable-slow-rules",
+    "--rule-severity",
+    "--entropy",
+    "--entropy-mode",
+    "--caseinsensitive",
+    "--markup",
+    "--url-decode",
+    "--allowlist",
+    "--no-default-allowlist",
+    "--confidence",
+    "--min-confidence",
+    "--max-findings-per-rule",
+    "--max-findings-per-file",
+];
+
+/// Bounds on the jobs a queue keeps, see `JobQueue::set_limits`
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub struct JobLimits {
+    /// The most jobs waiting for a worker, more are refused until some start
+    pub max_queued: usize,
+    /// The most finished jobs kept, the oldest are forgotten first
+    pub max_finished: usize,
+    /// How long a finished job is kept
+    pub ttl: Duration,
+    /// How long a job that succeeded is kept once its findings have been fetched
+    pub collected_ttl: Duration,
+}
+
+impl Default for JobLimits {
+    /// 1000 jobs waiting and 1000 finished, kept for a day or 10 minutes after their findings
+    /// were fetched
+    fn default() -> Self {
+        Self {
+            max_queued: 1000,
+            max_finished: 1000,
+            ttl: Duration::from_secs(24 * 60 * 60),
+            collected_ttl: Duration::from_secs(10 * 60),
+        }
+    }
+}
+
+/// How a queue runs the scanners of its jobs, see the module documentation
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+pub enum Runner {
+    /// With the library on the worker threads, for the sources of `in_process_scanners`
+    InProcess,
+    /// By running the scanner binary of the source, looked up in this directory, or in the PATH
+    /// when it is None
+    Binary(Option<PathBuf>),
+}
+
+/// A scanner of the library that jobs run in-process
+#[derive(Debug, Clone, Copy)]
+pub struct InProcessScanner {
+    /// The source it scans, as named in the `sources` registry
+    pub source: &'static str,
+    /// The options a job can set, besides the `cli::tuning_args`
+    pub options: &'static [&'static str],
+    /// The target and the options of the source, the allowlist the arguments of a job are
+    /// parsed with
+    args: fn() -> Vec<Arg<'static, 'static>>,
+    /// Scans with the parsed arguments and the rules and allowlist of the given builder, returning
+    /// the findings as reported
+    scan: fn(&ArgMatches, &SecretScannerBuilder) -> Result<Vec<Value>, SimpleError>,
+}
+
+impl InProcessScanner {
+    /// Parse the arguments of a job, failing for any argument outside the allowlist
+    pub fn parse(&self, args: &[String]) -> Result<ArgMatches<'static>, SimpleError> {
+        let app = App::new(self.source)
+            .setting(AppSettings::NoBinaryName)
+            .args(&cli::tuning_args())
+            .args(&(self.args)());
+        Ok(try_with!(
+            app.get_matches_from_safe(args),
+            "Invalid arguments for a {} job",
+            self.source
+        ))
+    }
+}
+
+/// A scan to run: the name of a source (see `SourceRegistry`) and the arguments of its scanner,
+/// e.g. `{"source": "git", "args": ["https://github.com/newrelic/rusty-hog.git"]}`
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Hash, Clone)]
+pub struct JobRequest {
+    pub source: String,
+    #[serde(default)]
+    pub args: Vec<String>,
+}
+
+/// Where a job is in its life
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Hash, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum JobState {
+    Queued,
+    Running,
+    Succeeded,
+    Failed,
+}
+
+impl JobState {
+    /// True once the scanner of the job has exited
+    pub fn is_finished(self) -> bool {
+        self == JobState::Succeeded || self == JobState::Failed
+    }
+}
+
+/// The status of a job, as returned by the API of `hogd`
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Hash, Clone)]
+pub struct JobStatus {
+    pub id: String,
+    pub source: String,
+    pub state: JobState,
+    /// RFC 3339 timestamps of the job being submitted, started and finished
+    pub submitted: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub started: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub finished: Option<String>,
+    /// The number of findings of a job that succeeded
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub findings: Option<usize>,
+    /// Why a job failed: the scanner's error, or the end of the error output of its binary
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// How to run the scanner of a job
+#[derive(Debug, Clone)]
+enum Scan {
+    InProcess(InProcessScanner),
+    Binary(PathBuf),
+}
+
+/// A job and how to run it
+#[derive(Debug, Clone)]
+struct Job {
+    status: JobStatus,
+    scan: Scan,
+    /// The rules of an in-process scan, the default rules if None
+    rules: Option<Arc<Mutex<RuleWatcher>>>,
+    args: Vec<String>,
+    dir: PathBuf,
+    /// The directory the scanner binary runs in
+    current_dir: PathBuf,
+    /// When the job finished, and when its findings were first fetched
+    finished_at: Option<Instant>,
+    collected_at: Option<Instant>,
+}
+
+/// Scan jobs run by a pool of worker threads, see the module documentation
+#[derive(Debug)]
+pub struct JobQueue {
+    registry: SourceRegistry,
+    runner: Runner,
+    scanners: Vec<InProcessScanner>,
+    work_dir: PathBuf,
+    current_dir: Option<PathBuf>,
+    rules: Option<Arc<Mutex<RuleWatcher>>>,
+    limits: Option<JobLimits>,
+    jobs: Arc<Mutex<BTreeMap<String, Job>>>,
+    sender: Mutex<mpsc::Sender<String>>,
+    next_id: AtomicU64,
+}
+
+impl JobQueue {
+    /// Start `workers` threads running the jobs of the sources in `registry` with `runner`. Each
+    /// job writes its results in a directory named after it in `work_dir`.
+    pub fn start(
+        registry: SourceRegistry,
+        work_dir: &Path,
+        runner: Runner,
+        workers: usize,
+    ) -> Result<Self, SimpleError> {
+        try_with!(
+            fs::create_dir_all(work_dir),
+            "Failed to create the work directory {}",
+            work_dir.display()
+        );
+        // the scanners don't run in the work directory, so they need its absolute path
+        let work_dir = try_with!(
+            fs::canonicalize(work_dir),
+            "Failed to resolve the work directory {}",
+            work_dir.display()
+        );
+        let jobs: Arc<Mutex<BTreeMap<String, Job>>> = Arc::new(Mutex::new(BTreeMap::new()));
+        let (sender, receiver) = mpsc::channel::<String>();
+        let receiver = Arc::new(Mutex::new(receiver));
+        for worker in 0..workers.max(1) {
+            let jobs = Arc::clone(&jobs);
+            let receiver = Arc::clone(&receiver);
+            try_with!(
+                thread::Builder::new()
+                    .name(format!("hogd-worker-{}", worker))
+                    .spawn(move || loop {
+                        let id = match receiver.lock().unwrap().recv() {
+                            Ok(id) => id,
+                            Err(_) => break,
+                        };
+                        run_job(&jobs, &id);
+                    }),
+                "Failed to start a worker thread"
+            );
+        }
+        Ok(Self {
+            registry,
+            runner,
+            scanners: in_process_scanners(),
+            work_dir,
+            current_dir: None,
+            rules: None,
+            limits: None,
+            jobs,
+            sender: Mutex::new(sender),
+            next_id: AtomicU64::new(1),
+        })
+    }
+
+    /// Run the scanner binaries of the jobs submitted from now on in `dir`, so that the relative
+    /// paths in their arguments are resolved against it, instead of in the directory of each job
+    pub fn set_current_dir(&mut self, dir: &Path) {
+        self.current_dir = Some(PathBuf::from(dir));
+    }
+
+    /// Scan the in-process jobs that start from now on with the rules and allowlist `rules` has
+    /// loaded when they start, instead of the default rules. Jobs already running keep theirs.
+    pub fn set_rules(&mut self, rules: Arc<Mutex<RuleWatcher>>) {
+        self.rules = Some(rules);
+    }
+
+    /// Refuse jobs past `limits.max_queued` waiting for a worker, and forget the finished jobs
+    /// past the other `limits`, removing their directories (see `forget_expired`). Without
+    /// limits, every job is kept.
+    pub fn set_limits(&mut self, limits: JobLimits) {
+        self.limits = Some(limits);
+    }
+
+    /// Forget the finished jobs past the limits of the queue and remove their directories: the
+    /// oldest past `max_finished`, those finished more than `ttl` ago, and those whose findings
+    /// were fetched more than `collected_ttl` ago. `submit` calls it, and a server should also
+    /// call it now and then to free the disk while no jobs come in.
+    pub fn forget_expired(&self) {
+        let limits = match self.limits {
+            Some(l) => l,
+            None => return,
+        };
+        let mut jobs = self.jobs.lock().unwrap();
+        let mut finished: Vec<(Instant, String)> = jobs
+            .iter()
+            .filter_map(|(id, job)| Some((job.finished_at?, id.clone())))
+            .collect();
+        finished.sort();
+        let excess = finished.len().saturating_sub(limits.max_finished);
+        let expired: Vec<String> = finished
+            .into_iter()
+            .enumerate()
+            .filter(|(i, (finished_at, id))| {
+                *i < excess
+                    || finished_at.elapsed() > limits.ttl
+                    || jobs[id]
+                        .collected_at
+                        .map_or(false, |t| t.elapsed() > limits.collected_ttl)
+            })
+            .map(|(_, (_, id))| id)
+            .collect();
+        for id in expired {
+            if let Some(job) = jobs.remove(&id) {
+                debug!("Forgetting job {}", id);
+                if let Err(e) = fs::remove_dir_all(&job.dir) {
+                    error!("Failed to remove {}: {}", job.dir.display(), e);
+                }
+            }
+        }
+    }
+
+    /// The sources jobs can scan. With `Runner::InProcess`, those of the in-process scanners,
+    /// with the options a job can set.
+    pub fn sources(&self) -> Vec<SourceInfo> {
+        match self.runner {
+            Runner::InProcess => self
+                .scanners
+                .iter()
+                .filter_map(|scanner| {
+                    let mut source = self.registry.get(scanner.source)?.clone();
+                    source.options = scanner.options.iter().map(|o| o.to_string()).collect();
+                    Some(source)
+                })
+                .collect(),
+            Runner::Binary(_) => self.registry.sources().to_vec(),
+        }
+    }
+
+    /// Queue a job, returning its status. Fails for an unknown source, or arguments outside the
+    /// allowlist of the source.
+    pub fn submit(&self, request: JobRequest) -> Result<JobStatus, SimpleError> {
+        self.forget_expired();
+        if let Some(limits) = self.limits {
+            let queued = self
+                .jobs
+                .lock()
+                .unwrap()
+                .values()
+                .filter(|job| job.status.state == JobState::Queued)
+                .count();
+            if queued >= limits.max_queued {
+                return Err(SimpleError::new(format!(
+                    "{} jobs are already waiting, try again later",
+                    queued
+                )));
+            }
+        }
+        let source = require_with!(
+            self.registry.get(&request.source),
+            "Unknown source {}",
+            request.source
+        );
+        let scan = match &self.runner {
+            Runner::InProcess => {
+                let scanner = require_with!(
+                    self.scanners.iter().find(|s| s.source == source.name),
+                    "{} can't be scanned by this server, see its sources",
+                    source.name
+                );
+                scanner.parse(&request.args)?;
+                Scan::InProcess(*scanner)
+            }
+            Runner::Binary(bin_dir) => {
+                check_options(&request.args, &source.options)?;
+                Scan::Binary(match bin_dir {
+                    Some(bin_dir) => bin_dir.join(&source.binary),
+                    None => PathBuf::from(&source.binary),
+                })
+            }
+        };
+        let now = Utc::now();
+        let id = format!(
+            "{}-{}",
+            now.format("%Y%m%d%H%M%S"),
+            self.next_id.fetch_add(1, Ordering::SeqCst)
+        );
+        let dir = self.work_dir.join(&id);
+        try_with!(
+            fs::create_dir_all(&dir),
+            "Failed to create the job directory {}",
+            dir.display()
+        );
+        let status = JobStatus {
+            id: id.clone(),
+            source: request.source,
+            state: JobState::Queued,
+            submitted: now.to_rfc3339(),
+            started: None,
+            finished: None,
+            findings: None,
+            error: None,
+        };
+        let job = Job {
+            status: status.clone(),
+            scan,
+            rules: self.rules.clone(),
+            args: request.args,
+            current_dir: self.current_dir.clone().unwrap_or_else(|| dir.clone()),
+            dir,
+            finished_at: None,
+            collected_at: None,
+        };
+        self.jobs.lock().unwrap().insert(id.clone(), job);
+        try_with!(
+            self.sender.lock().unwrap().send(id.clone()),
+            "The workers of the scan server stopped"
+        );
+        info!("Queued job {} scanning {}", id, status.source);
+        Ok(status)
+    }
+
+    /// The status of a job, None if there is no such job
+    pub fn status(&self, id: &str) -> Option<JobStatus> {
+        self.jobs
+            .lock()
+            .unwrap()
+            .get(id)
+            .map(|job| job.status.clone())
+    }
+
+    /// The status of every job, oldest first
+    pub fn list(&self) -> Vec<JobStatus> {
+        let mut statuses: Vec<JobStatus> = self
+            .jobs
+            .lock()
+            .unwrap()
+            .values()
+            .map(|job| job.status.clone())
+            .collect();
+        statuses.sort_by(|a, b| a.submitted.cmp(&b.submitted));
+        statuses
+    }
+
+    /// The findings of a job that succeeded, as the JSON array its scanner wrote. Fails for an
+    /// unknown job or one that has not succeeded.
+    pub fn findings(&self, id: &str) -> Result<Value, SimpleError> {
+        let path = {
+            let mut jobs = self.jobs.lock().unwrap();
+            let job = require_with!(jobs.get_mut(id), "Unknown job {}", id);
+            if job.status.state != JobState::Succeeded {
+                return Err(SimpleError::new(format!(
+                    "Job {} is {}, it has no findings",
+                    id,
+                    serde_json::to_string(&job.status.state).unwrap_or_default()
+                )));
+            }
+            job.collected_at.get_or_insert_with(Instant::now);
+            job.dir.join(FINDINGS_FILE)
+        };
+        read_findings(&path)
+    }
+}
+
+/// Run the scanner of a job and record how it went
+fn run_job(jobs: &Mutex<BTreeMap<String, Job>>, id: &str) {
+    let job = {
+        let mut jobs = jobs.lock().unwrap();
+        let job = match jobs.get_mut(id) {
+            Some(j) => j,
+            None => return,
+        };
+        job.status.state = JobState::Running;
+        job.status.started = Some(Utc::now().to_rfc3339());
+        job.clone()
+    };
+    let path = job.dir.join(FINDINGS_FILE);
+    let result = match &job.scan {
+        Scan::InProcess(scanner) => {
+            debug!("Scanning {} {:?} for job {}", scanner.source, job.args, id);
+            let rules = match &job.rules {
+                Some(rules) => rules.lock().unwrap().builder().clone(),
+                None => SecretScannerBuilder::new(),
+            };
+            scan_in_process(scanner, &rules, &job.args, &path)
+        }
+        Scan::Binary(binary) => {
+            debug!("Running {} {:?} for job {}", binary.display(), job.args, id);
+            scan_with_binary(binary, &job, &path)
+        }
+    };
+    let mut jobs = jobs.lock().unwrap();
+    let job = match jobs.get_mut(id) {
+        Some(j) => j,
+        None => return,
+    };
+    job.finished_at = Some(Instant::now());
+    let status = &mut job.status;
+    status.finished = Some(Utc::now().to_rfc3339());
+    match result {
+        Ok(findings) => {
+            status.state = JobState::Succeeded;
+            status.findings = Some(findings.as_array().map_or(0, |a| a.len()));
+            info!("Job {} found {} secrets", id, status.findings.unwrap_or(0));
+        }
+        Err(e) => {
+            error!("Job {} failed: {}", id, e);
+            status.state = JobState::Failed;
+            status.error = Some(e.to_string());
+        }
+    }
+}
+
+/// Scan with a library scanner on this thread and the rules of `rules`, writing the findings
+/// where a binary would. A panic of the scanner fails the job rather than the worker.
+fn scan_in_process(
+    scanner: &InProcessScanner,
+    rules: &SecretScannerBuilder,
+    args: &[String],
+    path: &Path,
+) -> Result<Value, SimpleError> {
+    let arg_matches = scanner.parse(args)?;
+    let findings = panic::catch_unwind(AssertUnwindSafe(|| (scanner.scan)(&arg_matches, rules)))
+        .unwrap_or_else(|_| {
+            Err(SimpleError::new(format!(
+                "Scanning {} panicked",
+                scanner.source
+            )))
+        })?;
+    let findings = Value::Array(findings);
+    try_with!(
+        fs::write(path, findings.to_string()),
+        "Failed to write the results to {}",
+        path.display()
+    );
+    Ok(findings)
+}
+
+/// Run the scanner binary of a job, then read the findings it wrote
+fn scan_with_binary(binary: &Path, job: &Job, path: &Path) -> Result<Value, SimpleError> {
+    let output = Command::new(binary)
+        .args(&job.args)
+        .arg("--outputfile")
+        .arg(path)
+        .arg("--format")
+        .arg("json")
+        .current_dir(&job.current_dir)
+        .output();
+    match output {
+        Ok(o) if o.status.success() => read_findings(path),
+        Ok(o) => {
+            let stderr = String::from_utf8_lossy(&o.stderr);
+            let last = stderr.lines().rev().find(|l| !l.trim().is_empty());
+            Err(SimpleError::new(match last {
+                Some(line) => format!("{} ({})", line.trim(), o.status),
+                None => format!("The scanner exited with {}", o.status),
+            }))
+        }
+        Err(e) => Err(SimpleError::new(format!(
+            "Failed to run {}: {}",
+            binary.display(),
+            e
+        ))),
+    }
+}
+
+/// Fail if an option of `args` is neither one of `options` nor one of `COMMON_OPTIONS`
+fn check_options(args: &[String], options: &[String]) -> Result<(), SimpleError> {
+    for arg in args.iter().filter(|a| a.starts_with('-')) {
+        let name = arg.split('=').next().unwrap_or_default();
+        if !options.iter().any(|o| o == name) && !COMMON_OPTIONS.contains(&name) {
+            return Err(SimpleError::new(format!(
+                "{} is not an option a job of this source can set",
+                name
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Read the JSON array of findings a scanner wrote
+fn read_findings(path: &Path) -> Result<Value, SimpleError> {
+    let contents = try_with!(
+        fs::read_to_string(path),
+        "The scanner wrote no results to {}",
+        path.display()
+    );
+    let findings: Value = try_with!(
+        serde_json::from_str(&contents),
+        "Failed to parse the results in {}",
+        path.display()
+    );
+    Ok(findings)
+}
+
+/// The scanners jobs run in-process, those of the sources that were built. A job can't set a
+/// credential that would be sent to a host it chooses from the environment of the server, so
+/// Git passwords and GitLab tokens for another instance are given in the job.
+#[allow(unused_mut)]
+pub fn in_process_scanners() -> Vec<InProcessScanner> {
+    let mut scanners: Vec<InProcessScanner> = Vec::new();
+    #[cfg(feature = "git")]
+    scanners.push(InProcessScanner {
+        source: "git",
+        options: &[
+            "--branch",
+            "--author",
+            "--path",
+            "--since",
+            "--until",
+            "--since-commit",
+            "--notes",
+            "--threads",
+            "--repo-url",
+            "--httpsuser",
+            "--httpspass",
+        ],
+        args: git_args,
+        scan: scan_git,
+    });
+    #[cfg(feature = "gitlab")]
+    scanners.push(InProcessScanner {
+        source: "gitlab",
+        options: &["--url", "--token", "--group", "--project", "--norepo"],
+        args: gitlab_args,
+        scan: scan_gitlab,
+    });
+    #[cfg(feature = "gcs")]
+    scanners.push(InProcessScanner {
+        source: "gcs",
+        options: &[
+            "--recursive",
+            "--anonymous",
+            "--threads",
+            "--max-size",
+            "--since",
+            "--until",
+            "--max-archive-depth",
+            "--max-archive-size",
+        ],
+        args: gcs_args,
+        scan: scan_gcs,
+    });
+    scanners
+}
+
+/// The threads of a job scanning Git commits or Cloud Storage objects, unless it sets `--threads`
+#[cfg(any(feature = "git", feature = "gcs"))]
+const DEFAULT_THREADS: usize = 4;
+
+/// The most threads a job run in-process can ask for with `--threads`
+pub const MAX_THREADS: usize = 16;
+
+/// Parses `--threads`, up to `MAX_THREADS`
+#[cfg(any(feature = "git", feature = "gcs"))]
+fn threads_of(arg_matches: &ArgMatches) -> Result<usize, SimpleError> {
+    let threads: usize = match arg_matches.value_of("THREADS") {
+        Some(t) => try_with!(t.parse(), "--threads must be a number"),
+        None => DEFAULT_THREADS,
+    };
+    if threads > MAX_THREADS {
+        info!("Scanning on {} threads instead of {}", MAX_THREADS, threads);
+    }
+    Ok(threads.min(MAX_THREADS))
+}
+
+/// The arguments of `--since` and `--until`
+#[cfg(any(feature = "git", feature = "gcs"))]
+fn time_args() -> Vec<Arg<'static, 'static>> {
+    vec![
+        Arg::with_name("SINCE")
+            .long("since")
+            .takes_value(true)
+            .allow_hyphen_values(true)
+            .validator(validate_time),
+        Arg::with_name("UNTIL")
+            .long("until")
+            .takes_value(true)
+            .allow_hyphen_values(true)
+            .validator(validate_time),
+    ]
+}
+
+#[cfg(feature = "git")]
+fn git_args() -> Vec<Arg<'static, 'static>> {
+    let mut args = vec![
+        Arg::with_name("GITPATH")
+            .required(true)
+            .validator(remote_git_url),
+        Arg::with_name("BRANCH")
+            .long("branch")
+            .value_name("GLOB")
+            .multiple(true)
+            .number_of_values(1),
+        Arg::with_name("AUTHOR").long("author").value_name("REGEX"),
+        Arg::with_name("PATH")
+            .long("path")
+            .value_name("GLOB")
+            .multiple(true)
+            .number_of_values(1),
+        Arg::with_name("SINCESHA")
+            .long("since-commit")
+            .value_name("SHA"),
+        Arg::with_name("NOTES").long("notes"),
+        Arg::with_name("THREADS").long("threads").takes_value(true),
+        Arg::with_name("REPOURL").long("repo-url").value_name("URL"),
+        Arg::with_name("HTTPSUSER")
+            .long("httpsuser")
+            .takes_value(true),
+        Arg::with_name("HTTPSPASS")
+            .long("httpspass")
+            .takes_value(true),
+    ];
+    args.extend(time_args());
+    args
+}
+
+/// Accepts only the URL of a remote repository, so that a job can't scan the repositories of the
+/// server
+#[cfg(feature = "git")]
+fn remote_git_url(path: String) -> Result<(), String> {
+    match url::Url::parse(&path) {
+        Ok(url) if ["http", "https", "ssh", "git"].contains(&url.scheme()) => Ok(()),
+        _ => Err(format!(
+            "{} is not the http(s), ssh or git URL of a remote repository",
+            path
+        )),
+    }
+}
+
+/// Clones a repository and scans its history, with the HTTPS credentials of the job, none for a
+/// public repository, or the SSH agent of the server. Submodules aren't scanned, as the
+/// `.gitmodules` of the repository can point them at local paths of the server.
+#[cfg(feature = "git")]
+fn scan_git(
+    arg_matches: &ArgMatches,
+    rules: &SecretScannerBuilder,
+) -> Result<Vec<Value>, SimpleError> {
+    let secret_scanner = SecretScannerBuilder::new()
+        .conf_argm(arg_matches)
+        .set_rules_from(rules)
+        .build();
+    let scan_entropy = arg_matches.is_present("ENTROPY");
+    let dest_dir = secret_scanner.workspace()?;
+    let mut git_scanner = GitScanner::new_from_scanner(secret_scanner).init_git_repo(
+        arg_matches.value_of("GITPATH").unwrap(),
+        dest_dir.path(),
+        None,
+        None,
+        Some(arg_matches.value_of("HTTPSUSER").unwrap_or("")),
+        Some(arg_matches.value_of("HTTPSPASS").unwrap_or("")),
+    );
+    dest_dir.check_quota()?;
+    if let Some(branches) = arg_matches.values_of("BRANCH") {
+        git_scanner = git_scanner.only_branches(&branches.collect::<Vec<&str>>());
+        if let Some(branch) = git_scanner.missing_branches().first() {
+            return Err(SimpleError::new(format!(
+                "No branch matches --branch {}",
+                branch
+            )));
+        }
+    }
+    if let Some(authors) = arg_matches.value_of("AUTHOR") {
+        git_scanner = git_scanner.only_authors(authors)?;
+    }
+    if let Some(paths) = arg_matches.values_of("PATH") {
+        git_scanner = git_scanner.only_paths(&paths.collect::<Vec<&str>>());
+    }
+    if arg_matches.is_present("NOTES") {
+        git_scanner = git_scanner.include_notes()?;
+    }
+    if let Some(sha) = arg_matches.value_of("SINCESHA") {
+        git_scanner = git_scanner.skip_history_of(sha)?;
+    }
+    git_scanner = git_scanner.with_threads(threads_of(arg_matches)?);
+    if let Some(url) = arg_matches.value_of("REPOURL") {
+        git_scanner = git_scanner.link_to(url)?;
+    }
+    let findings = git_scanner.perform_scan(None, None, None, scan_entropy)?;
+    info!("Found {} secrets", findings.len());
+    Ok(git_scanner.secret_scanner.reported_findings(&findings))
+}
+
+#[cfg(feature = "gitlab")]
+fn gitlab_args() -> Vec<Arg<'static, 'static>> {
+    vec![
+        Arg::with_name("GROUP").long("group").takes_value(true),
+        Arg::with_name("PROJECT")
+            .long("project")
+            .takes_value(true)
+            .multiple(true)
+            .number_of_values(1),
+        Arg::with_name("URL")
+            .long("url")
+            .takes_value(true)
+            .requires("TOKEN"),
+        Arg::with_name("TOKEN").long("token").takes_value(true),
+        Arg::with_name("NOREPO").long("norepo"),
+    ]
+}
+
+/// Scans the projects of a group or the projects given, on gitlab.com with the token of the job
+/// or else `GITLAB_TOKEN`, or on the `--url` of the job with its token only
+#[cfg(feature = "gitlab")]
+fn scan_gitlab(
+    arg_matches: &ArgMatches,
+    rules: &SecretScannerBuilder,
+) -> Result<Vec<Value>, SimpleError> {
+    let secret_scanner = SecretScannerBuilder::new()
+        .conf_argm(arg_matches)
+        .set_rules_from(rules)
+        .build();
+    let gitlab_scanner = GitlabScanner::new_from_scanner(secret_scanner);
+    let scan_entropy = arg_matches.is_present("ENTROPY");
+    let token = require_with!(
+        cli::value_or_env(arg_matches, "TOKEN", "GITLAB_TOKEN"),
+        "No token supplied, use --token or set GITLAB_TOKEN on the server"
+    );
+    let config = GitlabConfig {
+        url: arg_matches
+            .value_of("URL")
+            .unwrap_or("https://gitlab.com")
+            .to_string(),
+        token,
+    };
+    let mut projects: Vec<GitlabProject> = Vec::new();
+    if let Some(group) = arg_matches.value_of("GROUP") {
+        projects.extend(gitlab_scanner.list_projects(&config, group)?);
+    }
+    if let Some(values) = arg_matches.values_of("PROJECT") {
+        for project in values {
+            projects.push(gitlab_scanner.get_project(&config, project)?);
+        }
+    }
+    if projects.is_empty() {
+        return Err(SimpleError::new(
+            "Nothing to scan, supply a --group or --project with at least one project",
+        ));
+    }
+    let scan_repository = !arg_matches.is_present("NOREPO");
+    let mut findings = HashSet::new();
+    for project in projects {
+        match gitlab_scanner.scan_project(&config, &project, scan_repository, scan_entropy) {
+            Ok(f) => findings.extend(f),
+            Err(e) => error!("Failed to scan {}: {}", project.path_with_namespace, e),
+        }
+    }
+    info!("Found {} secrets", findings.len());
+    Ok(gitlab_scanner.secret_scanner.reported_findings(&findings))
+}
+
+#[cfg(feature = "gcs")]
+fn gcs_args() -> Vec<Arg<'static, 'static>> {
+    let mut args = vec![
+        Arg::with_name("GSURI").required(true),
+        Arg::with_name("RECURSIVE").long("recursive"),
+        Arg::with_name("ANONYMOUS").long("anonymous"),
+        Arg::with_name("THREADS").long("threads").takes_value(true),
+        Arg::with_name("MAXSIZE").long("max-size").value_name("MB"),
+    ];
+    args.extend(time_args());
+    args.extend(cli::archive_args());
+    args
+}
+
+/// Scans the objects of a bucket under a prefix, with the application default credentials of the
+/// server unless the job is `--anonymous`
+#[cfg(feature = "gcs")]
+fn scan_gcs(
+    arg_matches: &ArgMatches,
+    rules: &SecretScannerBuilder,
+) -> Result<Vec<Value>, SimpleError> {
+    let secret_scanner = SecretScannerBuilder::new()
+        .conf_argm(arg_matches)
+        .set_rules_from(rules)
+        .build();
+    let gcs_scanner = GcsScanner::new_from_scanner(secret_scanner);
+    let scan_entropy = arg_matches.is_present("ENTROPY");
+    let max_size: Option<u64> = match arg_matches.value_of("MAXSIZE") {
+        Some(m) => {
+            let mb = try_with!(m.parse::<u64>(), "--max-size must be a number");
+            Some(mb.saturating_mul(1 << 20))
+        }
+        None => None,
+    };
+    let credentials = if arg_matches.is_present("ANONYMOUS") {
+        GcsCredentials::Anonymous
+    } else {
+        GcsCredentials::application_default(None)?
+    };
+    let config = GcsConfig::new(credentials);
+    let url = try_with!(
+        url::Url::parse(arg_matches.value_of("GSURI").unwrap()),
+        "Failed to parse GSURI"
+    );
+    let bucket = require_with!(url.host_str(), "Bucket name not detected in GS URI");
+    let prefix = url.path().trim_start_matches('/');
+    let time_range = gcs_scanner.secret_scanner.time_range;
+    let objects: Vec<_> = gcs_scanner
+        .list_objects(&config, bucket, prefix, arg_matches.is_present("RECURSIVE"))?
+        .into_iter()
+        .filter(|o| time_range.contains_str(&o.updated))
+        .filter(|o| max_size.map_or(true, |max| o.size <= max))
+        .collect();
+    let mut findings = HashSet::new();
+    for (object, result) in gcs_scanner.scan_objects(
+        &config,
+        bucket,
+        objects,
+        threads_of(arg_matches)?,
+        scan_entropy,
+    ) {
+        match result {
+            Ok(f) => findings.extend(f),
+            Err(e) => error!("Failed to scan {}: {}", object.name, e),
+        }
+    }
+    info!("Found {} secrets", findings.len());
+    Ok(gcs_scanner.secret_scanner.reported_findings(&findings))
+}
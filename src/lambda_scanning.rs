@@ -0,0 +1,278 @@
+//! Collection of tools for scanning AWS Lambda functions for secrets.
+//!
+//! `LambdaScanner` acts as a wrapper around a `SecretScanner` object and talks to the
+//! [Lambda API](https://docs.aws.amazon.com/lambda/latest/dg/API_Reference.html) of a region. It
+//! can list the functions of the region and scan their environment variables, which is where
+//! hard-coded credentials usually end up, and download and scan the files of their deployment
+//! packages (zip archives). Functions deployed as container images have no package to download,
+//! use Jinhua Hog to scan their image instead.
+//!
+//! Requests are signed with the credentials of `aws_scanning`, which need the
+//! `lambda:ListFunctions` permission, and `lambda:GetFunction` to download deployment packages.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use rusty_hogs::aws_scanning;
+//! use rusty_hogs::lambda_scanning::{LambdaConfig, LambdaScanner};
+//!
+//! let ls = LambdaScanner::new();
+//! let config = LambdaConfig {
+//!     credentials: aws_scanning::credentials_from_args(None, None, None).unwrap(),
+//!     region: "us-east-1".to_string(),
+//! };
+//! for function in ls.list_functions(&config).unwrap() {
+//!     let findings = ls.scan_environment(&config, &function, false);
+//!     ls.secret_scanner.output_findings(&findings);
+//! }
+//! ```
+
+use crate::aws_scanning;
+use crate::network;
+use crate::retry;
+use crate::{Finding, SecretScanner, Span, TextFinding};
+use log::{self, debug, info};
+use s3::credentials::Credentials;
+use serde_derive::{Deserialize, Serialize};
+use serde_json::Value;
+use simple_error::{try_with, SimpleError};
+use std::collections::HashSet;
+use std::io::{Cursor, Read};
+use zip::ZipArchive;
+
+/// The version of the Lambda API, which prefixes every path
+const API_VERSION: &str = "2015-03-31";
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Hash, Clone, Default)]
+/// `serde_json` object that represents a single found secret - finding
+pub struct LambdaFinding {
+    pub diff: String,
+    #[serde(rename = "stringsFound")]
+    pub strings_found: Vec<String>,
+    pub region: String,
+    #[serde(rename = "functionName")]
+    pub function_name: String,
+    #[serde(rename = "functionArn")]
+    pub function_arn: String,
+    /// Where the secret was found: `environment` or `code`
+    pub source: String,
+    /// The name of the environment variable, or the path of the file in the deployment package
+    pub path: String,
+    pub reason: String,
+    /// Where each of `strings_found` is in the text, see `Span`
+    #[serde(default)]
+    pub spans: Vec<Span>,
+}
+
+impl TextFinding for LambdaFinding {
+    fn with_match(&self, found: Finding) -> Self {
+        Self {
+            diff: found.diff,
+            strings_found: found.strings_found,
+            reason: found.reason,
+            spans: found.spans,
+            ..self.clone()
+        }
+    }
+}
+
+/// The region to scan and the credentials to sign requests with
+#[derive(Debug, Clone)]
+pub struct LambdaConfig {
+    pub credentials: Credentials,
+    pub region: String,
+}
+
+/// A function as returned by ListFunctions
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+pub struct LambdaFunction {
+    pub name: String,
+    pub arn: String,
+    /// `Zip` or `Image`
+    pub package_type: String,
+    /// The size of the deployment package in bytes
+    pub code_size: u64,
+    /// The environment variables, sorted by name
+    pub environment: Vec<(String, String)>,
+}
+
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+/// Contains helper functions for performing scans of Lambda functions
+pub struct LambdaScanner {
+    pub secret_scanner: SecretScanner,
+}
+
+/// Acts as a wrapper around a `SecretScanner` object to provide helper functions for performing
+/// scanning against AWS Lambda. Relies on the [reqwest](https://docs.rs/reqwest/) library for
+/// HTTP access.
+impl LambdaScanner {
+    /// Initialize the SecretScanner object first using the SecretScannerBuilder, then provide
+    /// it to this constructor method.
+    pub fn new_from_scanner(secret_scanner: SecretScanner) -> Self {
+        Self { secret_scanner }
+    }
+
+    pub fn new() -> Self {
+        Self {
+            secret_scanner: SecretScanner::default(),
+        }
+    }
+
+    /// Returns the functions of the region, with their environment variables
+    pub fn list_functions(
+        &self,
+        config: &LambdaConfig,
+    ) -> Result<Vec<LambdaFunction>, SimpleError> {
+        let path = format!("/{}/functions/", API_VERSION);
+        let mut functions: Vec<LambdaFunction> = Vec::new();
+        let mut marker: Option<String> = None;
+        loop {
+            let mut query = vec![("MaxItems", "50")];
+            if let Some(m) = &marker {
+                query.push(("Marker", m.as_str()));
+            }
+            let page = call(config, &path, &query)?;
+            functions.extend(
+                page["Functions"]
+                    .as_array()
+                    .into_iter()
+                    .flatten()
+                    .map(parse_function),
+            );
+            marker = page["NextMarker"].as_str().map(String::from);
+            if marker.is_none() {
+                break;
+            }
+        }
+        info!("Found {} functions in {}", functions.len(), config.region);
+        Ok(functions)
+    }
+
+    /// Scans the environment variables of `function`, each as a `NAME=value` line. Returns a
+    /// HashSet of findings.
+    pub fn scan_environment(
+        &self,
+        config: &LambdaConfig,
+        function: &LambdaFunction,
+        scan_entropy: bool,
+    ) -> HashSet<LambdaFinding> {
+        let mut findings: HashSet<LambdaFinding> = HashSet::new();
+        for (name, value) in &function.environment {
+            let template = LambdaFinding {
+                region: config.region.clone(),
+                function_name: function.name.clone(),
+                function_arn: function.arn.clone(),
+                source: String::from("environment"),
+                path: name.clone(),
+                ..Default::default()
+            };
+            let line = format!("{}={}", name, value);
+            self.secret_scanner
+                .scan_text(line.as_bytes(), &template, scan_entropy, &mut findings);
+        }
+        findings
+    }
+
+    /// Downloads the deployment package of `function` and scans each file in it. Returns a
+    /// HashSet of findings, which is empty for functions deployed as container images.
+    pub fn scan_code(
+        &self,
+        config: &LambdaConfig,
+        function: &LambdaFunction,
+        scan_entropy: bool,
+    ) -> Result<HashSet<LambdaFinding>, SimpleError> {
+        let mut findings: HashSet<LambdaFinding> = HashSet::new();
+        if function.package_type == "Image" {
+            debug!("Skipping the code of {}, a container image", function.name);
+            return Ok(findings);
+        }
+        let path = format!("/{}/functions/{}", API_VERSION, function.name);
+        let details = call(config, &path, &[])?;
+        let location = match details["Code"]["Location"].as_str() {
+            Some(l) => l,
+            None => {
+                debug!("No deployment package for {}", function.name);
+                return Ok(findings);
+            }
+        };
+
+        // the location is a presigned S3 URL, valid for 10 minutes
+        info!("Downloading the code of {}", function.name);
+        let response = try_with!(
+            retry::send(network::client().get(location)),
+            "Failed to download the code of {}",
+            function.name
+        );
+        if !response.status().is_success() {
+            return Err(SimpleError::new(format!(
+                "Downloading the code of {} returned {}",
+                function.name,
+                response.status()
+            )));
+        }
+        let data = try_with!(
+            response.bytes(),
+            "Failed to download the code of {}",
+            function.name
+        );
+        let mut archive = try_with!(
+            ZipArchive::new(Cursor::new(data.as_ref())),
+            "Failed to open the code of {}",
+            function.name
+        );
+        for i in 0..archive.len() {
+            let mut file = try_with!(archive.by_index(i), "Failed to read a file of the package");
+            if file.is_dir() {
+                continue;
+            }
+            let name = file.name().to_string();
+            let mut content: Vec<u8> = Vec::new();
+            try_with!(file.read_to_end(&mut content), "Failed to read {}", name);
+            let template = LambdaFinding {
+                region: config.region.clone(),
+                function_name: function.name.clone(),
+                function_arn: function.arn.clone(),
+                source: String::from("code"),
+                path: name,
+                ..Default::default()
+            };
+            self.secret_scanner
+                .scan_text(&content, &template, scan_entropy, &mut findings);
+        }
+        Ok(findings)
+    }
+}
+
+impl Default for LambdaScanner {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Reads a function configuration returned by the Lambda API
+fn parse_function(json: &Value) -> LambdaFunction {
+    let mut environment: Vec<(String, String)> = json["Environment"]["Variables"]
+        .as_object()
+        .into_iter()
+        .flatten()
+        .map(|(name, value)| (name.clone(), value.as_str().unwrap_or("").to_string()))
+        .collect();
+    environment.sort();
+    LambdaFunction {
+        name: json["FunctionName"].as_str().unwrap_or("").to_string(),
+        arn: json["FunctionArn"].as_str().unwrap_or("").to_string(),
+        package_type: json["PackageType"].as_str().unwrap_or("Zip").to_string(),
+        code_size: json["CodeSize"].as_u64().unwrap_or(0),
+        environment,
+    }
+}
+
+/// Calls a Lambda API path, e.g. `/2015-03-31/functions/`
+fn call(config: &LambdaConfig, path: &str, query: &[(&str, &str)]) -> Result<Value, SimpleError> {
+    let body = aws_scanning::aws_get(&config.credentials, "lambda", &config.region, path, query)?;
+    Ok(try_with!(
+        serde_json::from_slice(&body),
+        "Invalid Lambda API response for {}",
+        path
+    ))
+}
@@ -0,0 +1,169 @@
+//! Scan manifests: the targets of many scanners, for the `hog` binary to scan in one run.
+//!
+//! A manifest is a TOML or YAML file (read like a `--config` file, see the `config` module) that
+//! lists targets of any of the sources of the `sources` registry, each with the arguments of its
+//! scanner, and the flags shared by all of them:
+//!
+//! ```yaml
+//! workers: 4
+//! settings:
+//!   regex: [builtin, rules/internal.json]
+//!   allowlist: allowlist.json
+//!   berkshire_hog:
+//!     recursive: true
+//! targets:
+//!   - name: rusty-hog
+//!     source: git
+//!     args: [https://github.com/newrelic/rusty-hog.git]
+//!   - name: logs
+//!     source: s3
+//!     args: [s3://example-logs/app/, us-east-1]
+//!   - source: gdrive
+//!     args: [--service-account, sa.json, --folder, 1a2b3c]
+//! ```
+//!
+//! `settings` takes the same keys as a `--config` file: flags for every scanner at the top level,
+//! and flags for one scanner in a table named after its binary. It is written to a file passed to
+//! the scanner of each builtin source with `--config`, so the rules and allowlists are shared
+//! without repeating them in every target. Plugin scanners only get their own `args`. A target
+//! without a `name` is named after its source and position, e.g. `gdrive-3`.
+//!
+//! # Examples
+//!
+//! ```
+//! use rusty_hogs::manifest::ScanManifest;
+//! use rusty_hogs::sources::SourceRegistry;
+//! use serde_json::json;
+//! use std::path::Path;
+//!
+//! let manifest = ScanManifest::from_value(json!({
+//!     "settings": { "entropy": true },
+//!     "targets": [
+//!         { "source": "broken", "args": ["--fast"] },
+//!         { "name": "mine", "source": "broken" }
+//!     ]
+//! }))
+//! .unwrap();
+//! assert_eq!(manifest.targets[0].name, "broken-1");
+//!
+//! let mut registry = SourceRegistry::new();
+//! assert!(manifest.job_requests(&registry, None).is_err());
+//! let broken = rusty_hogs::sources::SourceInfo {
+//!     name: String::from("broken"),
+//!     binary: String::from("false"),
+//!     builtin: true,
+//!     ..Default::default()
+//! };
+//! registry.register(broken).unwrap();
+//! let requests = manifest.job_requests(&registry, Some(Path::new("/tmp/settings.yaml"))).unwrap();
+//! assert_eq!(requests[0].args, vec!["--config", "/tmp/settings.yaml", "--fast"]);
+//!
+//! let duplicate = json!({
+//!     "targets": [{ "name": "a", "source": "git" }, { "name": "a", "source": "s3" }]
+//! });
+//! assert!(ScanManifest::from_value(duplicate).is_err());
+//! ```
+
+use crate::config;
+use crate::jobs::JobRequest;
+use crate::sources::SourceRegistry;
+use serde_derive::{Deserialize, Serialize};
+use serde_json::Value;
+use simple_error::{require_with, try_with, SimpleError};
+use std::collections::HashSet;
+use std::path::Path;
+
+/// The targets of a multi-target scan and the flags they share, see the module documentation
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone, Default)]
+pub struct ScanManifest {
+    /// The number of scanners to run at the same time, None for the default of the `hog` binary
+    #[serde(default)]
+    pub workers: Option<usize>,
+    /// The flags of every scanner, in the format of a `--config` file (Null for none)
+    #[serde(default)]
+    pub settings: Value,
+    pub targets: Vec<ManifestTarget>,
+}
+
+/// A target of a manifest: a source of the `sources` registry and the arguments of its scanner
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Hash, Clone, Default)]
+pub struct ManifestTarget {
+    /// A unique name, reported as the `target` of each of its findings
+    #[serde(default)]
+    pub name: String,
+    pub source: String,
+    #[serde(default)]
+    pub args: Vec<String>,
+}
+
+impl ScanManifest {
+    /// Reads a manifest from a TOML file, or a YAML file when its extension is .yaml or .yml
+    pub fn read(path: &str) -> Result<Self, SimpleError> {
+        let value = config::read_config(path)?;
+        match Self::from_value(value) {
+            Ok(m) => Ok(m),
+            Err(e) => Err(SimpleError::new(format!(
+                "Invalid manifest {}: {}",
+                path, e
+            ))),
+        }
+    }
+
+    /// Parses a manifest, naming the targets without a name and checking the names are unique
+    pub fn from_value(value: Value) -> Result<Self, SimpleError> {
+        let mut manifest: ScanManifest = try_with!(serde_json::from_value(value), "Bad manifest");
+        if manifest.targets.is_empty() {
+            return Err(SimpleError::new("The manifest has no targets"));
+        }
+        if !(manifest.settings.is_null() || manifest.settings.is_object()) {
+            return Err(SimpleError::new("The settings are not a table of flags"));
+        }
+        let mut names: HashSet<String> = HashSet::new();
+        for (i, target) in manifest.targets.iter_mut().enumerate() {
+            if target.name.is_empty() {
+                target.name = format!("{}-{}", target.source, i + 1);
+            }
+            if !names.insert(target.name.clone()) {
+                return Err(SimpleError::new(format!(
+                    "More than one target is named {}",
+                    target.name
+                )));
+            }
+        }
+        Ok(manifest)
+    }
+
+    /// True if the manifest sets flags for the scanners
+    pub fn has_settings(&self) -> bool {
+        self.settings.as_object().map_or(false, |s| !s.is_empty())
+    }
+
+    /// The job of each target, in order, failing if a target names an unknown source. The scanners
+    /// of builtin sources read their flags from `settings_file`, the settings written to a file.
+    pub fn job_requests(
+        &self,
+        registry: &SourceRegistry,
+        settings_file: Option<&Path>,
+    ) -> Result<Vec<JobRequest>, SimpleError> {
+        let mut requests: Vec<JobRequest> = Vec::with_capacity(self.targets.len());
+        for target in &self.targets {
+            let source = require_with!(
+                registry.get(&target.source),
+                "Target {} has an unknown source {} (see list_sources)",
+                target.name,
+                target.source
+            );
+            let mut args: Vec<String> = Vec::new();
+            if let (Some(path), true) = (settings_file, source.builtin) {
+                args.push(String::from("--config"));
+                args.push(path.display().to_string());
+            }
+            args.extend(target.args.iter().cloned());
+            requests.push(JobRequest {
+                source: target.source.clone(),
+                args,
+            });
+        }
+        Ok(requests)
+    }
+}
@@ -0,0 +1,183 @@
+//! Proxy and TLS settings for the requests the scanners make.
+//!
+//! Binaries call `install` with the `--proxy` and `--ca-bundle` options, and the API scanners
+//! create their requests with `client`, which is set up with them. Without `--proxy`, requests go
+//! through the proxy in the `HTTPS_PROXY` or `HTTP_PROXY` environment variable, if one is set. The
+//! certificates of `--ca-bundle` (PEM, e.g. the root of a TLS intercepting proxy) are trusted in
+//! addition to the usual roots.
+//!
+//! Google Drive and service account tokens use an older HTTP client, set up with `hyper_client`,
+//! and Git clones over HTTPS take `git_proxy_options`. Libraries and commands that don't take these settings directly (the S3 downloads of
+//! `rust-s3`, and the git CLI used for shallow clones) are given them through `HTTPS_PROXY`,
+//! `SSL_CERT_FILE` and `GIT_SSL_CAINFO`. Connections that don't use HTTP (syslog over TLS and
+//! IMAP) are secured with `tls_config`.
+//!
+//! # Examples
+//!
+//! ```
+//! use rusty_hogs::network::{self, NetworkOptions};
+//!
+//! let options = NetworkOptions::from_args(Some("http://proxy.example.com:3128"), None);
+//! network::install(options).unwrap();
+//! assert_eq!(
+//!     network::proxy().unwrap().as_str(),
+//!     "http://proxy.example.com:3128/"
+//! );
+//! let _request = network::client().get("https://api.example.com/v1/items");
+//! ```
+
+use lazy_static::lazy_static;
+use log::{self, debug};
+use reqwest::blocking::Client;
+use reqwest::{Certificate, Proxy};
+use simple_error::{try_with, SimpleError};
+use std::env;
+use std::fs;
+use std::sync::{Arc, Mutex};
+use url::Url;
+
+/// The environment variables a proxy is read from when `--proxy` isn't given, in order
+pub const PROXY_VARS: &[&str] = &["HTTPS_PROXY", "https_proxy", "HTTP_PROXY", "http_proxy"];
+
+/// How the scanners connect to the APIs they scan
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+pub struct NetworkOptions {
+    /// The URL of the proxy to send requests through, overriding the environment
+    pub proxy: Option<String>,
+    /// A PEM file of extra certificate authorities to trust
+    pub ca_bundle: Option<String>,
+}
+
+impl NetworkOptions {
+    /// The options selected by `--proxy` and `--ca-bundle`
+    pub fn from_args(proxy: Option<&str>, ca_bundle: Option<&str>) -> Self {
+        Self {
+            proxy: proxy.map(String::from),
+            ca_bundle: ca_bundle.map(String::from),
+        }
+    }
+}
+
+struct State {
+    options: NetworkOptions,
+    client: Client,
+}
+
+lazy_static! {
+    static ref STATE: Mutex<State> = Mutex::new(State {
+        options: NetworkOptions::default(),
+        client: Client::new(),
+    });
+}
+
+/// Sets up the client `client` returns, failing if the proxy URL or CA bundle is invalid
+pub fn install(options: NetworkOptions) -> Result<(), SimpleError> {
+    let mut builder = Client::builder();
+    if let Some(proxy) = &options.proxy {
+        try_with!(Url::parse(proxy), "Invalid proxy URL {}", proxy);
+        builder = builder.proxy(try_with!(Proxy::all(proxy), "Invalid proxy URL {}", proxy));
+        env::set_var("HTTPS_PROXY", proxy);
+        debug!("Sending requests through {}", proxy);
+    }
+    if let Some(path) = &options.ca_bundle {
+        let pem = try_with!(fs::read(path), "Failed to read the CA bundle {}", path);
+        let certificate = try_with!(
+            Certificate::from_pem(&pem),
+            "Failed to parse the CA bundle {}",
+            path
+        );
+        builder = builder.add_root_certificate(certificate);
+        env::set_var("SSL_CERT_FILE", path);
+        env::set_var("GIT_SSL_CAINFO", path);
+    }
+    let client = try_with!(builder.build(), "Failed to set up the HTTP client");
+    let mut state = STATE.lock().unwrap();
+    *state = State { options, client };
+    Ok(())
+}
+
+/// The client to create requests with. Clones share their connection pool.
+pub fn client() -> Client {
+    STATE.lock().unwrap().client.clone()
+}
+
+/// The proxy requests are sent through: `--proxy`, or else the first of `PROXY_VARS` that is set
+pub fn proxy() -> Option<Url> {
+    let proxy = STATE.lock().unwrap().options.proxy.clone();
+    let proxy = proxy.or_else(|| PROXY_VARS.iter().find_map(|v| env::var(v).ok()))?;
+    Url::parse(&proxy).ok()
+}
+
+/// The path of the extra certificate authorities given with `--ca-bundle`
+pub fn ca_bundle() -> Option<String> {
+    STATE.lock().unwrap().options.ca_bundle.clone()
+}
+
+/// Creates the `hyper` client that Google Drive and service account token requests are sent with,
+/// set up with the same proxy and CA bundle as `client`
+#[cfg(any(feature = "gdrive", feature = "gcs"))]
+pub fn hyper_client() -> Result<hyper::Client, SimpleError> {
+    let mut tls = hyper_rustls::TlsClient::new();
+    if let Some(path) = ca_bundle() {
+        let pem = try_with!(fs::read(&path), "Failed to read the CA bundle {}", path);
+        let config = std::sync::Arc::make_mut(&mut tls.cfg);
+        if config.root_store.add_pem_file(&mut &pem[..]).is_err() {
+            return Err(SimpleError::new(format!(
+                "Failed to parse the CA bundle {}",
+                path
+            )));
+        }
+    }
+    match proxy() {
+        Some(proxy) => {
+            let host = match proxy.host_str() {
+                Some(h) => h.to_string(),
+                None => return Err(SimpleError::new(format!("Proxy {} has no host", proxy))),
+            };
+            let port = proxy.port_or_known_default().unwrap_or(80);
+            Ok(hyper::Client::with_proxy_config(
+                hyper::client::ProxyConfig::new(
+                    proxy.scheme(),
+                    host,
+                    port,
+                    hyper::net::HttpConnector,
+                    tls,
+                ),
+            ))
+        }
+        None => Ok(hyper::Client::with_connector(
+            hyper::net::HttpsConnector::new(tls),
+        )),
+    }
+}
+
+/// The TLS settings of the connections made without `client`, for syslog over TLS and IMAP: the
+/// usual roots and those of `--ca-bundle`
+pub fn tls_config() -> Result<Arc<rustls::ClientConfig>, SimpleError> {
+    let mut config = rustls::ClientConfig::new();
+    config
+        .root_store
+        .add_server_trust_anchors(&webpki_roots::TLS_SERVER_ROOTS);
+    if let Some(path) = ca_bundle() {
+        let pem = try_with!(fs::read(&path), "Failed to read the CA bundle {}", path);
+        if config.root_store.add_pem_file(&mut &pem[..]).is_err() {
+            return Err(SimpleError::new(format!(
+                "Failed to parse the CA bundle {}",
+                path
+            )));
+        }
+    }
+    Ok(Arc::new(config))
+}
+
+/// The proxy settings for `git2` clones over HTTPS: the proxy of `proxy` if there is one, otherwise
+/// the `http.proxy` of the git config
+#[cfg(feature = "git")]
+pub fn git_proxy_options() -> git2::ProxyOptions<'static> {
+    let mut options = git2::ProxyOptions::new();
+    match proxy() {
+        Some(proxy) => options.url(proxy.as_str()),
+        None => options.auto(),
+    };
+    options
+}
@@ -0,0 +1,387 @@
+//! Collection of tools for scanning Notion workspaces for secrets.
+//!
+//! `NotionScanner` acts as a wrapper around a `SecretScanner` object and talks to the
+//! [Notion API](https://developers.notion.com/reference/intro) with the token of an internal
+//! integration. It can list the pages and databases shared with the integration, and scan the
+//! properties of each one along with the text of every block of a page: paragraphs, headings,
+//! lists, code blocks, callouts, table cells, captions and the URLs of bookmarks, embeds and files.
+//!
+//! An integration only sees the pages and databases that have been shared with it (from the
+//! page's connections menu), and the pages below them. It needs the Read content capability.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use rusty_hogs::notion_scanning::{NotionConfig, NotionScanner};
+//!
+//! let ns = NotionScanner::new();
+//! let config = NotionConfig {
+//!     url: rusty_hogs::notion_scanning::NOTION_API_URL.to_string(),
+//!     token: "secret_my-integration-token".to_string(),
+//! };
+//! for page in ns.list_pages(&config).unwrap() {
+//!     let findings = ns.scan_page(&config, &page, false).unwrap();
+//!     ns.secret_scanner.output_findings(&findings);
+//! }
+//! ```
+
+use crate::network;
+use crate::retry;
+use crate::{Finding, SecretScanner, Span, TextFinding};
+use log::{self, debug, info};
+use reqwest::blocking::Response;
+use serde_derive::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use simple_error::{try_with, SimpleError};
+use std::collections::HashSet;
+
+/// The Notion API endpoint
+pub const NOTION_API_URL: &str = "https://api.notion.com/v1";
+
+/// The version of the API the requests are written against
+const NOTION_VERSION: &str = "2022-06-28";
+
+/// The most results Notion returns per page
+const PAGE_SIZE: u64 = 100;
+
+/// Block types whose children are separate pages, listed and scanned on their own
+const PAGE_BLOCK_TYPES: &[&str] = &["child_page", "child_database"];
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Hash, Clone, Default)]
+/// `serde_json` object that represents a single found secret - finding
+pub struct NotionFinding {
+    pub diff: String,
+    #[serde(rename = "stringsFound")]
+    pub strings_found: Vec<String>,
+    /// The title of the page or database
+    pub page: String,
+    /// The ID of the block, or `properties/<name>` for a property
+    pub path: String,
+    /// A link to the page or database
+    pub url: String,
+    /// When the page or database was last edited
+    pub date: String,
+    pub reason: String,
+    /// Where each of `strings_found` is in the text, see `Span`
+    #[serde(default)]
+    pub spans: Vec<Span>,
+}
+
+impl TextFinding for NotionFinding {
+    fn with_match(&self, found: Finding) -> Self {
+        Self {
+            diff: found.diff,
+            strings_found: found.strings_found,
+            reason: found.reason,
+            spans: found.spans,
+            ..self.clone()
+        }
+    }
+}
+
+/// The Notion API to talk to and the integration token to authenticate with
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+pub struct NotionConfig {
+    /// The base URL of the API, `NOTION_API_URL` unless testing against a proxy
+    pub url: String,
+    /// An internal integration token
+    pub token: String,
+}
+
+/// A page or database shared with the integration. Only the fields we need are included.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Hash, Clone, Default)]
+pub struct NotionPage {
+    pub id: String,
+    /// `page` or `database`
+    pub object: String,
+    pub title: String,
+    pub url: String,
+    /// When the page was last edited (RFC 3339)
+    pub last_edited_time: String,
+    /// The text of each property that has any, by property name. The title and description of
+    /// a database are included as `title` and `description`.
+    pub properties: Vec<(String, String)>,
+}
+
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+/// Contains helper functions for performing scans of Notion pages
+pub struct NotionScanner {
+    pub secret_scanner: SecretScanner,
+}
+
+/// Acts as a wrapper around a `SecretScanner` object to provide helper functions for performing
+/// scanning against Notion. Relies on the [reqwest](https://docs.rs/reqwest/) library for HTTP
+/// access.
+impl NotionScanner {
+    /// Initialize the SecretScanner object first using the SecretScannerBuilder, then provide
+    /// it to this constructor method.
+    pub fn new_from_scanner(secret_scanner: SecretScanner) -> Self {
+        Self { secret_scanner }
+    }
+
+    pub fn new() -> Self {
+        Self {
+            secret_scanner: SecretScanner::default(),
+        }
+    }
+
+    /// Returns the pages and databases shared with the integration, including the pages below
+    /// them and the rows of their databases, most recently edited first
+    pub fn list_pages(&self, config: &NotionConfig) -> Result<Vec<NotionPage>, SimpleError> {
+        let results = get_all(
+            config,
+            "search",
+            Some(json!({
+                "sort": { "direction": "descending", "timestamp": "last_edited_time" }
+            })),
+        )?;
+        Ok(results
+            .iter()
+            .filter(|r| r["archived"] != true)
+            .map(parse_page)
+            .collect())
+    }
+
+    /// Scans the properties of `page` and, for pages, the text of all of its blocks, skipping
+    /// the subpages and databases inside it. Returns a HashSet of findings.
+    pub fn scan_page(
+        &self,
+        config: &NotionConfig,
+        page: &NotionPage,
+        scan_entropy: bool,
+    ) -> Result<HashSet<NotionFinding>, SimpleError> {
+        info!("Scanning {} {}", page.object, page.title);
+        let template = NotionFinding {
+            page: page.title.clone(),
+            url: page.url.clone(),
+            date: page.last_edited_time.clone(),
+            ..Default::default()
+        };
+        let mut findings: HashSet<NotionFinding> = HashSet::new();
+        for (name, text) in &page.properties {
+            let template = NotionFinding {
+                path: format!("properties/{}", name),
+                ..template.clone()
+            };
+            self.secret_scanner
+                .scan_text(text.as_bytes(), &template, scan_entropy, &mut findings);
+        }
+        if page.object != "page" {
+            return Ok(findings);
+        }
+
+        // Walk the block tree with a stack of the blocks whose children are still to be listed
+        let mut parents = vec![page.id.clone()];
+        while let Some(parent) = parents.pop() {
+            let blocks = get_all(
+                config,
+                &format!("blocks/{}/children?page_size={}", parent, PAGE_SIZE),
+                None,
+            )?;
+            debug!("Scanning {} blocks of {}", blocks.len(), page.title);
+            for block in &blocks {
+                let id = block["id"].as_str().unwrap_or("");
+                let block_type = block["type"].as_str().unwrap_or("");
+                let template = NotionFinding {
+                    path: id.to_string(),
+                    ..template.clone()
+                };
+                self.secret_scanner.scan_text(
+                    block_text(block).as_bytes(),
+                    &template,
+                    scan_entropy,
+                    &mut findings,
+                );
+                if block["has_children"] == true && !PAGE_BLOCK_TYPES.contains(&block_type) {
+                    parents.push(id.to_string());
+                }
+            }
+        }
+        Ok(findings)
+    }
+}
+
+impl Default for NotionScanner {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Builds a `NotionPage` from a page or database object of the API
+fn parse_page(object: &Value) -> NotionPage {
+    let mut properties: Vec<(String, String)> = Vec::new();
+    let mut title = String::new();
+    if object["object"] == "database" {
+        title = rich_text(&object["title"]);
+        properties.push((String::from("title"), title.clone()));
+        properties.push((
+            String::from("description"),
+            rich_text(&object["description"]),
+        ));
+    }
+    let empty = Map::new();
+    for (name, property) in object["properties"].as_object().unwrap_or(&empty) {
+        if property["type"] == "title" && object["object"] == "page" {
+            title = rich_text(&property["title"]);
+        }
+        properties.push((name.clone(), property_text(property)));
+    }
+    properties.retain(|(_, text)| !text.is_empty());
+    NotionPage {
+        id: object["id"].as_str().unwrap_or("").to_string(),
+        object: object["object"].as_str().unwrap_or("").to_string(),
+        title,
+        url: object["url"].as_str().unwrap_or("").to_string(),
+        last_edited_time: object["last_edited_time"]
+            .as_str()
+            .unwrap_or("")
+            .to_string(),
+        properties,
+    }
+}
+
+/// The plain text of an array of rich text objects
+fn rich_text(value: &Value) -> String {
+    value
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|t| t["plain_text"].as_str())
+        .collect()
+}
+
+/// The text of a page property, empty for the types that hold no text (e.g. dates, people and
+/// relations) or whose value is itself schema (the properties of a database)
+fn property_text(property: &Value) -> String {
+    let property_type = property["type"].as_str().unwrap_or("");
+    let value = &property[property_type];
+    match property_type {
+        "title" | "rich_text" => rich_text(value),
+        "url" | "email" | "phone_number" => value.as_str().unwrap_or("").to_string(),
+        "select" | "status" => value["name"].as_str().unwrap_or("").to_string(),
+        "multi_select" => value
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|o| o["name"].as_str())
+            .collect::<Vec<&str>>()
+            .join(", "),
+        "formula" => value["string"].as_str().unwrap_or("").to_string(),
+        _ => String::new(),
+    }
+}
+
+/// The text of a block: its rich text, caption and cells, the expression of an equation and the
+/// URL of a bookmark, embed, link or file, each on its own line
+fn block_text(block: &Value) -> String {
+    let block_type = block["type"].as_str().unwrap_or("");
+    let content = &block[block_type];
+    let mut lines = vec![
+        rich_text(&content["rich_text"]),
+        rich_text(&content["caption"]),
+    ];
+    for cell in content["cells"].as_array().into_iter().flatten() {
+        lines.push(rich_text(cell));
+    }
+    for value in &[
+        &content["expression"],
+        &content["url"],
+        &content["external"]["url"],
+    ] {
+        if let Some(s) = value.as_str() {
+            lines.push(s.to_string());
+        }
+    }
+    lines.retain(|l| !l.is_empty());
+    lines.join("\n")
+}
+
+/// Sends a GET, or a POST of `body` if there is one, to the Notion API, waiting and retrying when
+/// rate limited. `path` is relative to the configured API URL.
+fn request(config: &NotionConfig, path: &str, body: Option<&Value>) -> Result<Value, SimpleError> {
+    let url = format!("{}/{}", config.url.trim_end_matches('/'), path);
+    let builder = match body {
+        Some(body) => {
+            debug!("POST {}", url);
+            network::client().post(&url).json(body)
+        }
+        None => {
+            debug!("GET {}", url);
+            network::client().get(&url)
+        }
+    };
+    let response = try_with!(
+        retry::send(
+            builder
+                .bearer_auth(&config.token)
+                .header("Notion-Version", NOTION_VERSION)
+        ),
+        "Notion request failed"
+    );
+    let response = check(response, &url)?;
+    Ok(try_with!(
+        response.json(),
+        "Failed to parse Notion response"
+    ))
+}
+
+/// Follows the cursors of a paginated list and returns all of its results. A POST list such as
+/// a search takes its cursor in the body, a GET list in the query string.
+fn get_all(
+    config: &NotionConfig,
+    path: &str,
+    body: Option<Value>,
+) -> Result<Vec<Value>, SimpleError> {
+    let mut results: Vec<Value> = Vec::new();
+    let mut cursor: Option<String> = None;
+    loop {
+        let page = match &body {
+            Some(body) => {
+                let mut body = body.clone();
+                body["page_size"] = json!(PAGE_SIZE);
+                if let Some(c) = &cursor {
+                    body["start_cursor"] = json!(c);
+                }
+                request(config, path, Some(&body))?
+            }
+            None => match &cursor {
+                Some(c) => {
+                    let separator = if path.contains('?') { '&' } else { '?' };
+                    request(
+                        config,
+                        &format!("{}{}start_cursor={}", path, separator, c),
+                        None,
+                    )?
+                }
+                None => request(config, path, None)?,
+            },
+        };
+        if let Some(r) = page["results"].as_array() {
+            results.extend(r.iter().cloned());
+        }
+        cursor = page["next_cursor"].as_str().map(String::from);
+        if page["has_more"] != true || cursor.is_none() {
+            break;
+        }
+    }
+    Ok(results)
+}
+
+/// Turns an unsuccessful response into an error with the message Notion gives
+fn check(response: Response, url: &str) -> Result<Response, SimpleError> {
+    let status = response.status();
+    if status.is_success() {
+        return Ok(response);
+    }
+    let body = response.text().unwrap_or_default();
+    let message = match serde_json::from_str::<Value>(&body) {
+        Ok(error) => error["message"].as_str().unwrap_or("").to_string(),
+        Err(_) => body,
+    };
+    Err(SimpleError::new(format!(
+        "Notion returned {} for {}: {}",
+        status,
+        url,
+        message.trim()
+    )))
+}
@@ -0,0 +1,933 @@
+//! Collection of tools for scanning published npm, PyPI and Cargo packages for secrets.
+//!
+//! `PackageScanner` acts as a wrapper around a `SecretScanner` object to provide helper functions
+//! for checking that the artifacts a package registry serves don't ship credentials, e.g. an
+//! `.npmrc` with a publish token or a `.env` file swept into a wheel. For each package it asks the
+//! registry for the artifacts of the version (the tarball of an npm package, the sdist and every
+//! wheel of a PyPI release, or the `.crate` file of a crate), downloads them, unpacks them in
+//! memory and scans every file.
+//!
+//! Packages are given as `npm:NAME[@VERSION]`, `pypi:NAME[==VERSION]` or
+//! `crates:NAME[@VERSION]`, the latest version being scanned when none is given, or are read
+//! from a lockfile: `package-lock.json` and `npm-shrinkwrap.json` for npm, `requirements.txt`,
+//! `Pipfile.lock` and `poetry.lock` for PyPI, and `Cargo.lock` for crates.io.
+//!
+//! Crates that are already on disk, as `.crate` files or the directory `cargo vendor` writes, are
+//! scanned with `scan_crate_path()`. Each finding is attributed to the crate it was found in, by
+//! the name and version in the crate's `Cargo.toml`.
+//!
+//! # Examples
+//!
+//! ```
+//! use rusty_hogs::package_scanning::{Ecosystem, PackageSpec};
+//!
+//! let spec: PackageSpec = "npm:@babel/core@7.12.3".parse().unwrap();
+//! assert_eq!(spec.ecosystem, Ecosystem::Npm);
+//! assert_eq!(spec.name, "@babel/core");
+//! assert_eq!(spec.version, Some(String::from("7.12.3")));
+//!
+//! let spec: PackageSpec = "pypi:requests".parse().unwrap();
+//! assert_eq!(spec.ecosystem, Ecosystem::Pypi);
+//! assert_eq!(spec.version, None);
+//!
+//! let spec: PackageSpec = "crates:serde@1.0.117".parse().unwrap();
+//! assert_eq!(spec.ecosystem, Ecosystem::Crates);
+//! assert_eq!(spec.to_string(), "crates:serde@1.0.117");
+//! ```
+//!
+//! Then resolve each package to its artifacts with `resolve()` and supply them to
+//! `scan_artifact()`.
+//!
+//! ```no_run
+//! use rusty_hogs::package_scanning::{PackageScanner, RegistryConfig};
+//!
+//! let ps = PackageScanner::new();
+//! let config = RegistryConfig::default();
+//! let spec = "pypi:requests==2.25.1".parse().unwrap();
+//! let package = ps.resolve(&spec, &config).unwrap();
+//! for artifact in &package.artifacts {
+//!     let findings = ps.scan_artifact(&package, artifact, &config, false).unwrap();
+//!     ps.secret_scanner.output_findings(&findings);
+//! }
+//! ```
+//!
+//! ```no_run
+//! use rusty_hogs::package_scanning::PackageScanner;
+//! use std::path::Path;
+//!
+//! let ps = PackageScanner::new();
+//! let findings = ps.scan_crate_path(Path::new("vendor"), false).unwrap();
+//! ps.secret_scanner.output_findings(&findings);
+//! ```
+
+use crate::chunked_scanning;
+use crate::network;
+use crate::progress;
+use crate::retry;
+use crate::structured_scanning::{self, StructuredFormat};
+use crate::{SecretScanner, Span};
+use bzip2::read::BzDecoder;
+use flate2::read::GzDecoder;
+use log::{self, debug, error, info};
+use serde_derive::{Deserialize, Serialize};
+use serde_json::Value;
+use simple_error::{require_with, try_with, SimpleError};
+use std::collections::{BTreeSet, HashSet};
+use std::fmt;
+use std::fs;
+use std::io::{Cursor, Read};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use tar::Archive;
+use zip::ZipArchive;
+
+const NPM_REGISTRY: &str = "https://registry.npmjs.org";
+const PYPI_INDEX: &str = "https://pypi.org";
+const CRATES_REGISTRY: &str = "https://crates.io";
+
+/// crates.io refuses requests without a user agent
+const USER_AGENT: &str = "rusty-hog (https://github.com/newrelic/rusty-hog)";
+
+/// The `source` of the crates.io packages in a Cargo.lock, with the git and sparse indexes
+const CRATES_IO_SOURCES: &[&str] = &[
+    "registry+https://github.com/rust-lang/crates.io-index",
+    "sparse+https://index.crates.io/",
+];
+
+/// The file `cargo vendor` writes next to each crate, which only holds checksums
+const CARGO_CHECKSUM: &str = ".cargo-checksum.json";
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Hash, Clone, Default)]
+/// `serde_json` object that represents a single found secret - finding
+pub struct PackageFinding {
+    pub diff: String,
+    #[serde(rename = "stringsFound")]
+    pub strings_found: Vec<String>,
+    /// `npm`, `pypi` or `crates`
+    pub ecosystem: String,
+    pub package: String,
+    pub version: String,
+    /// The file name of the tarball, sdist, wheel or `.crate` file the secret was found in, or the
+    /// directory of a vendored crate
+    pub artifact: String,
+    /// The path of the file in the artifact
+    pub path: String,
+    pub reason: String,
+    #[serde(rename = "keyPath", default, skip_serializing_if = "Option::is_none")]
+    pub key_path: Option<String>,
+    /// Where each of `strings_found` is in the file, see `Span` (none with a `key_path`)
+    #[serde(default)]
+    pub spans: Vec<Span>,
+}
+
+/// The package registries `PackageScanner` can download from
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy, PartialOrd, Ord)]
+pub enum Ecosystem {
+    Npm,
+    Pypi,
+    /// crates.io
+    Crates,
+}
+
+impl FromStr for Ecosystem {
+    type Err = SimpleError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "npm" => Ok(Ecosystem::Npm),
+            "pypi" => Ok(Ecosystem::Pypi),
+            "crates" => Ok(Ecosystem::Crates),
+            _ => Err(SimpleError::new(format!(
+                "Unknown package ecosystem {:?}, expected npm, pypi or crates",
+                s
+            ))),
+        }
+    }
+}
+
+impl fmt::Display for Ecosystem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Ecosystem::Npm => write!(f, "npm"),
+            Ecosystem::Pypi => write!(f, "pypi"),
+            Ecosystem::Crates => write!(f, "crates"),
+        }
+    }
+}
+
+/// A package to scan, such as `npm:left-pad@1.3.0` or `pypi:requests==2.25.1`
+#[derive(Debug, PartialEq, Eq, Hash, Clone, PartialOrd, Ord)]
+pub struct PackageSpec {
+    pub ecosystem: Ecosystem,
+    pub name: String,
+    /// The exact version to scan, or None for the latest one
+    pub version: Option<String>,
+}
+
+impl FromStr for PackageSpec {
+    type Err = SimpleError;
+
+    /// Parses `npm:NAME[@VERSION]` (the name may be scoped, `@scope/name`),
+    /// `pypi:NAME[==VERSION]` or `crates:NAME[@VERSION]`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.splitn(2, ':');
+        let ecosystem: Ecosystem = parts.next().unwrap_or("").parse()?;
+        let package = parts.next().unwrap_or("").trim();
+        let (name, version) = match ecosystem {
+            Ecosystem::Npm => match package[1.min(package.len())..].find('@') {
+                Some(i) => (&package[..i + 1], Some(&package[i + 2..])),
+                None => (package, None),
+            },
+            Ecosystem::Crates => match package.find('@') {
+                Some(i) => (&package[..i], Some(&package[i + 1..])),
+                None => (package, None),
+            },
+            Ecosystem::Pypi => match package.find("==") {
+                Some(i) => (&package[..i], Some(&package[i + 2..])),
+                None => (package, None),
+            },
+        };
+        if name.is_empty() || version == Some("") {
+            return Err(SimpleError::new(format!(
+                "Invalid package {:?}, expected npm:NAME[@VERSION], pypi:NAME[==VERSION] or \
+                 crates:NAME[@VERSION]",
+                s
+            )));
+        }
+        Ok(Self {
+            ecosystem,
+            name: name.trim().to_string(),
+            version: version.map(|v| v.trim().to_string()),
+        })
+    }
+}
+
+impl fmt::Display for PackageSpec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let separator = match self.ecosystem {
+            Ecosystem::Npm | Ecosystem::Crates => "@",
+            Ecosystem::Pypi => "==",
+        };
+        match &self.version {
+            Some(v) => write!(f, "{}:{}{}{}", self.ecosystem, self.name, separator, v),
+            None => write!(f, "{}:{}", self.ecosystem, self.name),
+        }
+    }
+}
+
+/// Where to download packages from, for scanning internal mirrors and private registries
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+pub struct RegistryConfig {
+    /// The npm registry, https://registry.npmjs.org by default
+    pub npm_registry: String,
+    /// The PyPI index serving the JSON API, https://pypi.org by default
+    pub pypi_index: String,
+    /// A bearer token for the npm registry, for private packages
+    pub npm_token: Option<String>,
+    /// The crates.io compatible registry serving the web API, https://crates.io by default
+    pub crates_registry: String,
+}
+
+impl Default for RegistryConfig {
+    fn default() -> Self {
+        Self {
+            npm_registry: String::from(NPM_REGISTRY),
+            pypi_index: String::from(PYPI_INDEX),
+            npm_token: None,
+            crates_registry: String::from(CRATES_REGISTRY),
+        }
+    }
+}
+
+/// A downloadable file of a package version: an npm tarball, a PyPI sdist or wheel, or a crate
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Default)]
+pub struct Artifact {
+    pub file_name: String,
+    pub url: String,
+    /// The size in bytes if the registry reports it, unpacked for npm tarballs
+    pub size: Option<u64>,
+}
+
+/// A package version resolved by the registry, with its artifacts
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+pub struct ResolvedPackage {
+    pub ecosystem: Ecosystem,
+    pub name: String,
+    pub version: String,
+    pub artifacts: Vec<Artifact>,
+}
+
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+/// Contains helper functions for performing scans of npm and PyPI packages
+pub struct PackageScanner {
+    pub secret_scanner: SecretScanner,
+}
+
+/// Acts as a wrapper around a `SecretScanner` object to provide helper functions for performing
+/// scanning against package registries. Relies on the [reqwest](https://docs.rs/reqwest/) library
+/// for HTTP access and the [tar](https://docs.rs/tar/0.4/tar/) and
+/// [zip](https://docs.rs/zip/0.5/zip/) libraries to unpack the artifacts.
+impl PackageScanner {
+    /// Initialize the SecretScanner object first using the SecretScannerBuilder, then provide
+    /// it to this constructor method.
+    pub fn new_from_scanner(secret_scanner: SecretScanner) -> Self {
+        Self { secret_scanner }
+    }
+
+    pub fn new() -> Self {
+        Self {
+            secret_scanner: SecretScanner::default(),
+        }
+    }
+
+    /// Asks the registry for the version of `spec` (the latest one if it has none) and the
+    /// artifacts it can be downloaded as
+    pub fn resolve(
+        &self,
+        spec: &PackageSpec,
+        config: &RegistryConfig,
+    ) -> Result<ResolvedPackage, SimpleError> {
+        match spec.ecosystem {
+            Ecosystem::Npm => resolve_npm(spec, config),
+            Ecosystem::Pypi => resolve_pypi(spec, config),
+            Ecosystem::Crates => resolve_crate(spec, config),
+        }
+    }
+
+    /// Downloads an artifact of `package`, unpacks it and scans each file in it, returning a
+    /// HashSet of findings
+    pub fn scan_artifact(
+        &self,
+        package: &ResolvedPackage,
+        artifact: &Artifact,
+        config: &RegistryConfig,
+        scan_entropy: bool,
+    ) -> Result<HashSet<PackageFinding>, SimpleError> {
+        info!(
+            "Downloading {} ({} bytes)",
+            artifact.file_name,
+            artifact.size.unwrap_or(0)
+        );
+        let data = get(&artifact.url, package.ecosystem, config)?;
+        let template = PackageFinding {
+            ecosystem: package.ecosystem.to_string(),
+            package: package.name.clone(),
+            version: package.version.clone(),
+            artifact: artifact.file_name.clone(),
+            ..Default::default()
+        };
+        let mut findings: HashSet<PackageFinding> = HashSet::new();
+        self.scan_archive(data, &template, scan_entropy, &mut findings)?;
+        Ok(findings)
+    }
+
+    /// Scans a `.crate` file, the directory of a single crate, or a directory of crates such as
+    /// the one `cargo vendor` writes. Each finding is attributed to the name and version in the
+    /// `Cargo.toml` of its crate. Returns a HashSet of findings.
+    pub fn scan_crate_path(
+        &self,
+        path: &Path,
+        scan_entropy: bool,
+    ) -> Result<HashSet<PackageFinding>, SimpleError> {
+        let mut findings: HashSet<PackageFinding> = HashSet::new();
+        if path.is_file() {
+            let file_name = path
+                .file_name()
+                .map(|n| n.to_string_lossy().to_string())
+                .unwrap_or_default();
+            let (name, version) = split_crate_file_name(&file_name);
+            let template = PackageFinding {
+                ecosystem: Ecosystem::Crates.to_string(),
+                package: name,
+                version,
+                artifact: file_name,
+                ..Default::default()
+            };
+            let data = try_with!(fs::read(path), "Failed to read {}", path.display());
+            self.scan_archive(data, &template, scan_entropy, &mut findings)?;
+        } else if path.join("Cargo.toml").is_file() {
+            self.scan_crate_dir(path, scan_entropy, &mut findings)?;
+        } else {
+            let entries = try_with!(fs::read_dir(path), "Failed to read {}", path.display());
+            let mut crate_dirs: Vec<PathBuf> = Vec::new();
+            for entry in entries {
+                let entry = try_with!(entry, "Failed to read {}", path.display());
+                if entry.path().join("Cargo.toml").is_file() {
+                    crate_dirs.push(entry.path());
+                } else {
+                    debug!("Skipping {}, which is not a crate", entry.path().display());
+                }
+            }
+            crate_dirs.sort();
+            info!("Found {} crates in {}", crate_dirs.len(), path.display());
+            for crate_dir in crate_dirs {
+                if let Err(e) = self.scan_crate_dir(&crate_dir, scan_entropy, &mut findings) {
+                    error!("Failed to scan {}: {}", crate_dir.display(), e);
+                }
+            }
+        }
+        Ok(findings)
+    }
+
+    /// Scans every file of an unpacked crate, reading its name and version from its `Cargo.toml`
+    fn scan_crate_dir(
+        &self,
+        dir: &Path,
+        scan_entropy: bool,
+        findings: &mut HashSet<PackageFinding>,
+    ) -> Result<(), SimpleError> {
+        let manifest = try_with!(
+            fs::read_to_string(dir.join("Cargo.toml")),
+            "Failed to read the Cargo.toml of {}",
+            dir.display()
+        );
+        let manifest: toml::Value = try_with!(
+            toml::from_str(&manifest),
+            "Invalid Cargo.toml in {}",
+            dir.display()
+        );
+        let package = require_with!(
+            manifest.get("package"),
+            "No [package] in the Cargo.toml of {}",
+            dir.display()
+        );
+        let template = PackageFinding {
+            ecosystem: Ecosystem::Crates.to_string(),
+            package: package
+                .get("name")
+                .and_then(|n| n.as_str())
+                .unwrap_or("")
+                .to_string(),
+            version: package
+                .get("version")
+                .and_then(|v| v.as_str())
+                .unwrap_or("")
+                .to_string(),
+            artifact: dir.display().to_string(),
+            ..Default::default()
+        };
+        debug!(
+            "Scanning {} {} in {}",
+            template.package,
+            template.version,
+            dir.display()
+        );
+        let mut files: Vec<PathBuf> = Vec::new();
+        list_files(dir, &mut files)?;
+        files.sort();
+        for file in files {
+            let data = match fs::read(&file) {
+                Ok(d) => d,
+                Err(e) => {
+                    error!("Failed to read {}: {}", file.display(), e);
+                    continue;
+                }
+            };
+            let relative = file.strip_prefix(dir).unwrap_or(&file);
+            let path = relative.to_string_lossy().replace('\\', "/");
+            self.scan_file(path, data, &template, scan_entropy, findings);
+        }
+        Ok(())
+    }
+
+    /// Unpacks an artifact, recognised by the file name in `template`, and scans each file in it
+    fn scan_archive(
+        &self,
+        data: Vec<u8>,
+        template: &PackageFinding,
+        scan_entropy: bool,
+        findings: &mut HashSet<PackageFinding>,
+    ) -> Result<(), SimpleError> {
+        let name = template.artifact.to_ascii_lowercase();
+        if name.ends_with(".tgz") || name.ends_with(".tar.gz") || name.ends_with(".crate") {
+            let archive = Archive::new(GzDecoder::new(Cursor::new(data)));
+            self.scan_tarball(archive, template, scan_entropy, findings)?;
+        } else if name.ends_with(".tar.bz2") {
+            let archive = Archive::new(BzDecoder::new(Cursor::new(data)));
+            self.scan_tarball(archive, template, scan_entropy, findings)?;
+        } else if name.ends_with(".whl") || name.ends_with(".zip") || name.ends_with(".egg") {
+            self.scan_zip(&data, template, scan_entropy, findings)?;
+        } else {
+            debug!(
+                "Skipping {}, not a tarball or zip archive",
+                template.artifact
+            );
+        }
+        Ok(())
+    }
+
+    /// Scans each regular file of a tar archive
+    fn scan_tarball<R: Read>(
+        &self,
+        mut archive: Archive<R>,
+        template: &PackageFinding,
+        scan_entropy: bool,
+        findings: &mut HashSet<PackageFinding>,
+    ) -> Result<(), SimpleError> {
+        let entries = try_with!(archive.entries(), "Failed to read {}", template.artifact);
+        for entry in entries {
+            let mut entry = try_with!(entry, "Failed to read {}", template.artifact);
+            if !entry.header().entry_type().is_file() {
+                continue;
+            }
+            let path = match entry.path() {
+                Ok(p) => p.to_string_lossy().to_string(),
+                Err(_) => continue,
+            };
+            let mut data: Vec<u8> = Vec::new();
+            if let Err(e) = entry.read_to_end(&mut data) {
+                error!(
+                    "Failed to read {:?} from {}: {:?}",
+                    path, template.artifact, e
+                );
+                continue;
+            }
+            self.scan_file(path, data, template, scan_entropy, findings);
+        }
+        Ok(())
+    }
+
+    /// Scans each file of a zip archive (wheels and eggs are zip archives)
+    fn scan_zip(
+        &self,
+        data: &[u8],
+        template: &PackageFinding,
+        scan_entropy: bool,
+        findings: &mut HashSet<PackageFinding>,
+    ) -> Result<(), SimpleError> {
+        let mut archive = try_with!(
+            ZipArchive::new(Cursor::new(data)),
+            "Failed to open {}",
+            template.artifact
+        );
+        for i in 0..archive.len() {
+            let mut file = try_with!(archive.by_index(i), "Failed to read {}", template.artifact);
+            if file.is_dir() {
+                continue;
+            }
+            let path = file.name().to_string();
+            let mut content: Vec<u8> = Vec::new();
+            if let Err(e) = file.read_to_end(&mut content) {
+                error!(
+                    "Failed to read {:?} from {}: {:?}",
+                    path, template.artifact, e
+                );
+                continue;
+            }
+            self.scan_file(path, content, template, scan_entropy, findings);
+        }
+        Ok(())
+    }
+
+    /// Scans a single file of an artifact, adding any findings to `findings`
+    fn scan_file(
+        &self,
+        path: String,
+        data: Vec<u8>,
+        template: &PackageFinding,
+        scan_entropy: bool,
+        findings: &mut HashSet<PackageFinding>,
+    ) {
+        let found = findings.len();
+        let data = self.secret_scanner.preprocess_owned(data);
+        let size = data.len();
+
+        // Config files are parsed and scanned value by value, falling back to lines on failure
+        if self.secret_scanner.structured {
+            if let Some(format) = StructuredFormat::detect(&path, &data) {
+                match structured_scanning::scan_document(
+                    &self.secret_scanner,
+                    &data,
+                    format,
+                    scan_entropy,
+                ) {
+                    Ok(matches) => {
+                        findings.extend(matches.into_iter().map(|m| PackageFinding {
+                            diff: m.diff,
+                            strings_found: m.strings_found,
+                            path: path.clone(),
+                            reason: m.reason,
+                            key_path: Some(m.key_path),
+                            ..template.clone()
+                        }));
+                        progress::scanned(size, findings.len() - found);
+                        return;
+                    }
+                    Err(e) => debug!("{}, scanning {} line by line", e, path),
+                }
+            }
+        }
+
+        let options = self.secret_scanner.chunk_options;
+        let matches =
+            chunked_scanning::scan_lines(&self.secret_scanner, data, options, scan_entropy);
+        findings.extend(matches.into_iter().map(|m| PackageFinding {
+            diff: m.diff,
+            strings_found: m.strings_found,
+            path: path.clone(),
+            reason: m.reason,
+            spans: m.spans,
+            ..template.clone()
+        }));
+        progress::scanned(size, findings.len() - found);
+    }
+}
+
+impl Default for PackageScanner {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Reads the packages pinned by a lockfile, recognised by its name: `package-lock.json` or
+/// `npm-shrinkwrap.json` (npm), `Pipfile.lock`, `poetry.lock` or any `*.txt` requirements file
+/// (PyPI), or `Cargo.lock` (crates.io). Requirements that aren't pinned with `==` resolve to the
+/// latest version, and crates from git, paths or other registries are left out.
+pub fn read_lockfile(path: &Path) -> Result<Vec<PackageSpec>, SimpleError> {
+    let content = try_with!(fs::read_to_string(path), "Failed to read {:?}", path);
+    let file_name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let specs = match file_name.as_str() {
+        "package-lock.json" | "npm-shrinkwrap.json" => {
+            let lock: Value = try_with!(serde_json::from_str(&content), "Invalid {:?}", path);
+            parse_npm_lock(&lock)
+        }
+        "Pipfile.lock" => {
+            let lock: Value = try_with!(serde_json::from_str(&content), "Invalid {:?}", path);
+            parse_pipfile_lock(&lock)
+        }
+        "poetry.lock" => {
+            let lock: toml::Value = try_with!(toml::from_str(&content), "Invalid {:?}", path);
+            parse_poetry_lock(&lock)
+        }
+        "Cargo.lock" => {
+            let lock: toml::Value = try_with!(toml::from_str(&content), "Invalid {:?}", path);
+            parse_cargo_lock(&lock)
+        }
+        name if name.ends_with(".txt") => parse_requirements(&content),
+        _ => {
+            return Err(SimpleError::new(format!(
+                "Unknown lockfile {:?}, expected package-lock.json, npm-shrinkwrap.json, \
+                 Pipfile.lock, poetry.lock, a requirements file or Cargo.lock",
+                path
+            )))
+        }
+    };
+    // the same version is often locked several times (npm nests copies of dependencies)
+    let specs: BTreeSet<PackageSpec> = specs.into_iter().collect();
+    info!("Found {} packages in {:?}", specs.len(), path);
+    Ok(specs.into_iter().collect())
+}
+
+/// Reads `packages` (lockfile version 2 and 3) or the nested `dependencies` (version 1) of an
+/// npm lockfile, skipping the root project and linked workspace packages
+fn parse_npm_lock(lock: &Value) -> Vec<PackageSpec> {
+    let mut specs: Vec<PackageSpec> = Vec::new();
+    if let Some(packages) = lock["packages"].as_object() {
+        for (path, package) in packages {
+            let name = match path.rfind("node_modules/") {
+                Some(i) => &path[i + "node_modules/".len()..],
+                None => continue,
+            };
+            if package["link"].as_bool().unwrap_or(false) {
+                continue;
+            }
+            if let Some(version) = package["version"].as_str() {
+                specs.push(PackageSpec {
+                    ecosystem: Ecosystem::Npm,
+                    name: name.to_string(),
+                    version: Some(version.to_string()),
+                });
+            }
+        }
+    } else {
+        parse_npm_dependencies(&lock["dependencies"], &mut specs);
+    }
+    specs
+}
+
+fn parse_npm_dependencies(dependencies: &Value, specs: &mut Vec<PackageSpec>) {
+    for (name, dependency) in dependencies.as_object().into_iter().flatten() {
+        // versions such as file:../lib or github:user/repo aren't published to the registry
+        if let Some(version) = dependency["version"].as_str().filter(|v| !v.contains(':')) {
+            specs.push(PackageSpec {
+                ecosystem: Ecosystem::Npm,
+                name: name.clone(),
+                version: Some(version.to_string()),
+            });
+        }
+        parse_npm_dependencies(&dependency["dependencies"], specs);
+    }
+}
+
+/// Reads the `default` and `develop` packages of a Pipfile.lock, whose versions are `==1.2.3`
+fn parse_pipfile_lock(lock: &Value) -> Vec<PackageSpec> {
+    ["default", "develop"]
+        .iter()
+        .flat_map(|section| lock[*section].as_object().into_iter().flatten())
+        .map(|(name, package)| PackageSpec {
+            ecosystem: Ecosystem::Pypi,
+            name: name.clone(),
+            version: package["version"]
+                .as_str()
+                .map(|v| v.trim_start_matches("==").to_string()),
+        })
+        .collect()
+}
+
+/// Reads the `[[package]]` tables of a poetry.lock
+fn parse_poetry_lock(lock: &toml::Value) -> Vec<PackageSpec> {
+    lock.get("package")
+        .and_then(|p| p.as_array())
+        .into_iter()
+        .flatten()
+        .filter_map(|package| {
+            Some(PackageSpec {
+                ecosystem: Ecosystem::Pypi,
+                name: package.get("name")?.as_str()?.to_string(),
+                version: package
+                    .get("version")
+                    .and_then(|v| v.as_str())
+                    .map(String::from),
+            })
+        })
+        .collect()
+}
+
+/// Reads the `[[package]]` tables of a Cargo.lock that come from crates.io
+fn parse_cargo_lock(lock: &toml::Value) -> Vec<PackageSpec> {
+    lock.get("package")
+        .and_then(|p| p.as_array())
+        .into_iter()
+        .flatten()
+        .filter(|package| {
+            let source = package.get("source").and_then(|s| s.as_str());
+            source.map_or(false, |s| CRATES_IO_SOURCES.contains(&s))
+        })
+        .filter_map(|package| {
+            Some(PackageSpec {
+                ecosystem: Ecosystem::Crates,
+                name: package.get("name")?.as_str()?.to_string(),
+                version: package
+                    .get("version")
+                    .and_then(|v| v.as_str())
+                    .map(String::from),
+            })
+        })
+        .collect()
+}
+
+/// Reads a pip requirements file, ignoring comments, options such as `-r` and `--hash`, extras
+/// and environment markers
+fn parse_requirements(content: &str) -> Vec<PackageSpec> {
+    content
+        .lines()
+        .filter_map(|line| {
+            let line = line.split(" #").next()?.split(';').next()?.trim();
+            if line.is_empty() || line.starts_with('#') || line.starts_with('-') {
+                return None;
+            }
+            let line = line.split_whitespace().next()?;
+            let end = line
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.'))
+                .unwrap_or(line.len());
+            let name = &line[..end];
+            if name.is_empty() || line.contains("://") {
+                return None;
+            }
+            let version = line
+                .find("==")
+                .map(|i| {
+                    line[i + 2..]
+                        .trim_matches(|c| c == '=' || c == ' ')
+                        .to_string()
+                })
+                .filter(|v| !v.is_empty() && !v.contains('*'));
+            Some(PackageSpec {
+                ecosystem: Ecosystem::Pypi,
+                name: name.to_string(),
+                version,
+            })
+        })
+        .collect()
+}
+
+/// Looks up an npm package version (or the `latest` dist-tag) and its tarball
+fn resolve_npm(
+    spec: &PackageSpec,
+    config: &RegistryConfig,
+) -> Result<ResolvedPackage, SimpleError> {
+    // scoped names keep their @ but escape the slash, e.g. @babel%2fcore
+    let url = format!(
+        "{}/{}/{}",
+        config.npm_registry.trim_end_matches('/'),
+        spec.name.replace('/', "%2f"),
+        spec.version.as_deref().unwrap_or("latest")
+    );
+    let metadata: Value = try_with!(
+        serde_json::from_slice(&get(&url, Ecosystem::Npm, config)?),
+        "Invalid npm registry response for {}",
+        spec
+    );
+    let tarball = match metadata["dist"]["tarball"].as_str() {
+        Some(t) => t.to_string(),
+        None => {
+            return Err(SimpleError::new(format!(
+                "The npm registry returned no tarball for {}",
+                spec
+            )))
+        }
+    };
+    let file_name = tarball.rsplit('/').next().unwrap_or("").to_string();
+    Ok(ResolvedPackage {
+        ecosystem: Ecosystem::Npm,
+        name: spec.name.clone(),
+        version: metadata["version"].as_str().unwrap_or("").to_string(),
+        artifacts: vec![Artifact {
+            file_name,
+            url: tarball,
+            size: metadata["dist"]["unpackedSize"].as_u64(),
+        }],
+    })
+}
+
+/// Looks up a PyPI release (or the latest one) and its sdist and wheels
+fn resolve_pypi(
+    spec: &PackageSpec,
+    config: &RegistryConfig,
+) -> Result<ResolvedPackage, SimpleError> {
+    let index = config.pypi_index.trim_end_matches('/');
+    let url = match &spec.version {
+        Some(v) => format!("{}/pypi/{}/{}/json", index, spec.name, v),
+        None => format!("{}/pypi/{}/json", index, spec.name),
+    };
+    let metadata: Value = try_with!(
+        serde_json::from_slice(&get(&url, Ecosystem::Pypi, config)?),
+        "Invalid PyPI response for {}",
+        spec
+    );
+    let artifacts: Vec<Artifact> = metadata["urls"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|file| {
+            Some(Artifact {
+                file_name: file["filename"].as_str()?.to_string(),
+                url: file["url"].as_str()?.to_string(),
+                size: file["size"].as_u64(),
+            })
+        })
+        .collect();
+    if artifacts.is_empty() {
+        return Err(SimpleError::new(format!(
+            "PyPI returned no files for {}",
+            spec
+        )));
+    }
+    Ok(ResolvedPackage {
+        ecosystem: Ecosystem::Pypi,
+        name: spec.name.clone(),
+        version: metadata["info"]["version"]
+            .as_str()
+            .unwrap_or("")
+            .to_string(),
+        artifacts,
+    })
+}
+
+/// Looks up a crate version (or the newest stable one) and its `.crate` file
+fn resolve_crate(
+    spec: &PackageSpec,
+    config: &RegistryConfig,
+) -> Result<ResolvedPackage, SimpleError> {
+    let registry = config.crates_registry.trim_end_matches('/');
+    let version = match &spec.version {
+        Some(v) => v.clone(),
+        None => {
+            let url = format!("{}/api/v1/crates/{}", registry, spec.name);
+            let metadata: Value = try_with!(
+                serde_json::from_slice(&get(&url, Ecosystem::Crates, config)?),
+                "Invalid crates.io response for {}",
+                spec
+            );
+            let krate = &metadata["crate"];
+            let version = krate["max_stable_version"]
+                .as_str()
+                .or_else(|| krate["max_version"].as_str());
+            require_with!(version, "crates.io returned no version for {}", spec).to_string()
+        }
+    };
+    Ok(ResolvedPackage {
+        ecosystem: Ecosystem::Crates,
+        name: spec.name.clone(),
+        version: version.clone(),
+        artifacts: vec![Artifact {
+            file_name: format!("{}-{}.crate", spec.name, version),
+            url: format!(
+                "{}/api/v1/crates/{}/{}/download",
+                registry, spec.name, version
+            ),
+            size: None,
+        }],
+    })
+}
+
+/// Splits the file name of a `.crate` file, `NAME-VERSION.crate`, at the first dash followed by
+/// a version number. Crate names may contain dashes, but never one followed by `1.2.`.
+fn split_crate_file_name(file_name: &str) -> (String, String) {
+    let stem = file_name.trim_end_matches(".crate");
+    let is_version = |v: &str| {
+        let mut parts = v.splitn(3, '.');
+        let major = parts.next().unwrap_or("");
+        let minor = parts.next().unwrap_or("");
+        !major.is_empty()
+            && major.bytes().all(|b| b.is_ascii_digit())
+            && !minor.is_empty()
+            && minor.bytes().all(|b| b.is_ascii_digit())
+            && parts.next().is_some()
+    };
+    for (i, _) in stem.match_indices('-') {
+        if is_version(&stem[i + 1..]) {
+            return (stem[..i].to_string(), stem[i + 1..].to_string());
+        }
+    }
+    (stem.to_string(), String::new())
+}
+
+/// Lists the files of a directory and its subdirectories, skipping the checksums `cargo vendor`
+/// writes
+fn list_files(dir: &Path, files: &mut Vec<PathBuf>) -> Result<(), SimpleError> {
+    let entries = try_with!(fs::read_dir(dir), "Failed to read {}", dir.display());
+    for entry in entries {
+        let entry = try_with!(entry, "Failed to read {}", dir.display());
+        let path = entry.path();
+        if path.is_dir() {
+            list_files(&path, files)?;
+        } else if entry.file_name() != CARGO_CHECKSUM {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// GETs a registry URL, sending the npm token to the npm registry only
+fn get(url: &str, ecosystem: Ecosystem, config: &RegistryConfig) -> Result<Vec<u8>, SimpleError> {
+    let mut builder = network::client().get(url).header("User-Agent", USER_AGENT);
+    if let (Ecosystem::Npm, Some(token)) = (ecosystem, &config.npm_token) {
+        if url.starts_with(config.npm_registry.trim_end_matches('/')) {
+            builder = builder.bearer_auth(token);
+        }
+    }
+    let response = try_with!(retry::send(builder), "Registry request failed for {}", url);
+    if !response.status().is_success() {
+        return Err(SimpleError::new(format!(
+            "Registry returned {} for {}",
+            response.status(),
+            url
+        )));
+    }
+    let data = try_with!(response.bytes(), "Failed to download {}", url);
+    Ok(data.to_vec())
+}
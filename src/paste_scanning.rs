@@ -0,0 +1,357 @@
+//! Collection of tools for monitoring paste sites for secrets.
+//!
+//! `PasteScanner` acts as a wrapper around a `SecretScanner` object and polls the scraping APIs
+//! of paste sites for new pastes. An endpoint is a URL that lists the most recent pastes as JSON,
+//! such as the [Pastebin scraping API](https://pastebin.com/doc_scraping_api) (which only answers
+//! allowlisted IP addresses of PRO accounts), or a mirror or archive that lists pastes in the
+//! same shape. The listing is a JSON array, or an object with the array under `pastes`, `items`
+//! or `data`, of objects with:
+//!
+//! * the paste's `key` or `id`
+//! * the URL of its raw content as `scrape_url`, `raw_url` or `raw`, or else made from the
+//!   `--raw-url` template, in which `{key}` is replaced by the paste's key
+//! * optionally a `full_url` or `url` to link to, a `title`, a `user` or `author`, a `size` in
+//!   bytes and a `date`, as Unix seconds or RFC 3339
+//!
+//! Each new paste is downloaded and scanned with the rules, and also for the keywords of the
+//! organization being monitored (its domains, product names or internal host names), which are
+//! matched case-insensitively and reported with the reason "Keyword". `SeenPastes` remembers the
+//! pastes already scanned, so that a monitor polling every minute only scans each paste once.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use rusty_hogs::paste_scanning::{PasteConfig, PasteScanner, SeenPastes, PASTEBIN_SCRAPING_URL};
+//!
+//! let ps = PasteScanner::new();
+//! let config = PasteConfig {
+//!     endpoints: vec![PASTEBIN_SCRAPING_URL.to_string()],
+//!     keywords: vec!["example.com".to_string()],
+//!     ..Default::default()
+//! };
+//! let mut seen = SeenPastes::new();
+//! for paste in ps.list_pastes(&config, &config.endpoints[0]).unwrap() {
+//!     if seen.insert(&paste) {
+//!         let findings = ps.scan_paste(&config, &paste, false).unwrap();
+//!         ps.secret_scanner.output_findings(&findings);
+//!     }
+//! }
+//! ```
+
+use crate::network;
+use crate::progress;
+use crate::retry;
+use crate::SecretScanner;
+use chrono::{DateTime, TimeZone, Utc};
+use encoding::all::ASCII;
+use encoding::{DecoderTrap, Encoding};
+use log::{self, debug, info};
+use reqwest::blocking::Response;
+use serde_derive::{Deserialize, Serialize};
+use serde_json::Value;
+use simple_error::{try_with, SimpleError};
+use std::collections::{HashSet, VecDeque};
+use url::Url;
+
+/// The Pastebin scraping API, listing the 250 most recent public pastes
+pub const PASTEBIN_SCRAPING_URL: &str = "https://scrape.pastebin.com/api_scraping.php?limit=250";
+
+/// The most pastes `SeenPastes` remembers, after which the oldest are forgotten
+const MAX_SEEN: usize = 100_000;
+
+/// Reason reported for lines that mention one of the organization's keywords
+const KEYWORD_REASON: &str = "Keyword";
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Hash, Clone, Default)]
+/// `serde_json` object that represents a single found secret - finding
+pub struct PasteFinding {
+    pub diff: String,
+    #[serde(rename = "stringsFound")]
+    pub strings_found: Vec<String>,
+    /// The host name of the endpoint the paste was listed by, e.g. `scrape.pastebin.com`
+    pub site: String,
+    /// The key of the paste
+    pub path: String,
+    /// A link to the paste
+    pub url: String,
+    pub title: String,
+    pub user: String,
+    pub date: String,
+    pub reason: String,
+}
+
+/// The endpoints to poll, and what to look for in the pastes they list
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Default)]
+pub struct PasteConfig {
+    /// The URLs listing recent pastes
+    pub endpoints: Vec<String>,
+    /// A URL template for the raw content of pastes listed without one, with `{key}` in place of
+    /// the paste's key
+    pub raw_url: Option<String>,
+    /// Strings reported wherever they appear, whatever their case
+    pub keywords: Vec<String>,
+    /// Pastes larger than this many bytes are skipped, if set
+    pub max_size: Option<u64>,
+}
+
+/// A paste listed by an endpoint
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Hash, Clone, Default)]
+pub struct Paste {
+    pub site: String,
+    pub key: String,
+    /// The URL of the raw content
+    pub content_url: String,
+    /// The URL of the paste's page, or of its raw content if the listing has none
+    pub url: String,
+    pub title: String,
+    pub user: String,
+    /// The date the paste was created (RFC 3339), empty if unknown
+    pub date: String,
+    /// The size in bytes, if the listing has it
+    pub size: Option<u64>,
+}
+
+/// The pastes scanned so far, so that each one is scanned once however often it is listed
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+pub struct SeenPastes {
+    keys: HashSet<String>,
+    order: VecDeque<String>,
+}
+
+impl SeenPastes {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records `paste` as seen, returning false if it was already. The oldest pastes are forgotten
+    /// after `MAX_SEEN`, long after they have left the listings.
+    pub fn insert(&mut self, paste: &Paste) -> bool {
+        let id = format!("{}/{}", paste.site, paste.key);
+        if !self.keys.insert(id.clone()) {
+            return false;
+        }
+        self.order.push_back(id);
+        if self.order.len() > MAX_SEEN {
+            if let Some(oldest) = self.order.pop_front() {
+                self.keys.remove(&oldest);
+            }
+        }
+        true
+    }
+
+    pub fn len(&self) -> usize {
+        self.order.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.order.is_empty()
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+/// Contains helper functions for monitoring paste sites
+pub struct PasteScanner {
+    pub secret_scanner: SecretScanner,
+}
+
+/// Acts as a wrapper around a `SecretScanner` object to provide helper functions for scanning
+/// pastes. Relies on the [reqwest](https://docs.rs/reqwest/) library for HTTP access.
+impl PasteScanner {
+    /// Initialize the SecretScanner object first using the SecretScannerBuilder, then provide
+    /// it to this constructor method.
+    pub fn new_from_scanner(secret_scanner: SecretScanner) -> Self {
+        Self { secret_scanner }
+    }
+
+    pub fn new() -> Self {
+        Self {
+            secret_scanner: SecretScanner::default(),
+        }
+    }
+
+    /// Returns the pastes currently listed by `endpoint`. Pastes without a key or a way to get
+    /// their content are left out.
+    pub fn list_pastes(
+        &self,
+        config: &PasteConfig,
+        endpoint: &str,
+    ) -> Result<Vec<Paste>, SimpleError> {
+        let url = try_with!(Url::parse(endpoint), "Invalid endpoint {}", endpoint);
+        let site = url.host_str().unwrap_or("").to_string();
+        let response = get(endpoint)?;
+        let listing: Value = try_with!(response.json(), "Failed to parse the pastes of {}", site);
+        let items = ["pastes", "items", "data"]
+            .iter()
+            .find_map(|field| listing[*field].as_array())
+            .or_else(|| listing.as_array());
+        let items = match items {
+            Some(i) => i,
+            None => {
+                return Err(SimpleError::new(format!(
+                    "{} didn't return a list of pastes",
+                    site
+                )))
+            }
+        };
+        let pastes: Vec<Paste> = items
+            .iter()
+            .filter_map(|item| parse_paste(item, &site, config.raw_url.as_deref()))
+            .collect();
+        debug!("{} lists {} pastes", site, pastes.len());
+        Ok(pastes)
+    }
+
+    /// Downloads the content of `paste` and scans it with the rules and for the keywords of
+    /// `config`. Pastes outside the scanner's time range or larger than `max_size` are skipped.
+    /// Returns a HashSet of findings.
+    pub fn scan_paste(
+        &self,
+        config: &PasteConfig,
+        paste: &Paste,
+        scan_entropy: bool,
+    ) -> Result<HashSet<PasteFinding>, SimpleError> {
+        let mut findings: HashSet<PasteFinding> = HashSet::new();
+        if !paste.date.is_empty() && !self.secret_scanner.time_range.contains_str(&paste.date) {
+            return Ok(findings);
+        }
+        if let (Some(size), Some(max)) = (paste.size, config.max_size) {
+            if size > max {
+                info!("Skipping {}/{} ({} bytes)", paste.site, paste.key, size);
+                return Ok(findings);
+            }
+        }
+        let content = get(&paste.content_url)?;
+        let content = try_with!(content.bytes(), "Failed to download {}", paste.key).to_vec();
+        let template = PasteFinding {
+            site: paste.site.clone(),
+            path: paste.key.clone(),
+            url: paste.url.clone(),
+            title: paste.title.clone(),
+            user: paste.user.clone(),
+            date: paste.date.clone(),
+            ..Default::default()
+        };
+        let keywords: Vec<String> = config
+            .keywords
+            .iter()
+            .filter(|k| !k.is_empty())
+            .map(|k| k.to_lowercase())
+            .collect();
+        let data = self.secret_scanner.preprocess(&content);
+        for new_line in data.split(|x| (*x as char) == '\n') {
+            let diff = ASCII
+                .decode(new_line, DecoderTrap::Ignore)
+                .unwrap_or_else(|_| "<STRING DECODE ERROR>".parse().unwrap());
+            let matches_map = self.secret_scanner.matches(new_line);
+            for (reason, match_iterator) in matches_map {
+                let mut secrets: Vec<String> = Vec::new();
+                for matchobj in match_iterator {
+                    secrets.push(
+                        ASCII
+                            .decode(
+                                &new_line[matchobj.start()..matchobj.end()],
+                                DecoderTrap::Ignore,
+                            )
+                            .unwrap_or_else(|_| "<STRING DECODE ERROR>".parse().unwrap()),
+                    );
+                }
+                if !secrets.is_empty() {
+                    findings.insert(PasteFinding {
+                        diff: diff.clone(),
+                        strings_found: secrets,
+                        reason: reason.clone(),
+                        ..template.clone()
+                    });
+                }
+            }
+
+            let lower = String::from_utf8_lossy(new_line).to_lowercase();
+            let mentioned: Vec<String> = keywords
+                .iter()
+                .filter(|k| lower.contains(k.as_str()))
+                .cloned()
+                .collect();
+            if !mentioned.is_empty() {
+                findings.insert(PasteFinding {
+                    diff: diff.clone(),
+                    strings_found: mentioned,
+                    reason: KEYWORD_REASON.to_string(),
+                    ..template.clone()
+                });
+            }
+
+            if scan_entropy {
+                let ef = self.secret_scanner.entropy_matches(new_line);
+                if !ef.is_empty() {
+                    findings.insert(PasteFinding {
+                        diff,
+                        strings_found: ef,
+                        reason: "Entropy".to_string(),
+                        ..template.clone()
+                    });
+                }
+            }
+        }
+        progress::scanned(data.len(), findings.len());
+        Ok(findings)
+    }
+}
+
+impl Default for PasteScanner {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Reads a paste from an item of a listing
+fn parse_paste(item: &Value, site: &str, raw_url: Option<&str>) -> Option<Paste> {
+    let text = |fields: &[&str]| -> Option<String> {
+        fields.iter().find_map(|f| match &item[*f] {
+            Value::String(s) if !s.is_empty() => Some(s.clone()),
+            Value::Number(n) => Some(n.to_string()),
+            _ => None,
+        })
+    };
+    let key = text(&["key", "id"])?;
+    let content_url = match text(&["scrape_url", "raw_url", "raw"]) {
+        Some(u) => u,
+        None => raw_url?.replace("{key}", &key),
+    };
+    Some(Paste {
+        site: site.to_string(),
+        url: text(&["full_url", "url"]).unwrap_or_else(|| content_url.clone()),
+        title: text(&["title"]).unwrap_or_default(),
+        user: text(&["user", "author"]).unwrap_or_default(),
+        date: text(&["date", "created_at"])
+            .map(|d| normalize_date(&d))
+            .unwrap_or_default(),
+        size: text(&["size"]).and_then(|s| s.parse().ok()),
+        key,
+        content_url,
+    })
+}
+
+/// Turns the Unix seconds of the Pastebin API into RFC 3339, keeping other dates as they are
+fn normalize_date(date: &str) -> String {
+    match date.parse::<i64>() {
+        Ok(seconds) => Utc.timestamp(seconds, 0).to_rfc3339(),
+        Err(_) => match date.parse::<DateTime<Utc>>() {
+            Ok(d) => d.to_rfc3339(),
+            Err(_) => date.to_string(),
+        },
+    }
+}
+
+/// Performs a GET, waiting and retrying when rate limited
+fn get(url: &str) -> Result<Response, SimpleError> {
+    debug!("GET {}", url);
+    let response = try_with!(retry::send(network::client().get(url)), "Request failed");
+    if !response.status().is_success() {
+        return Err(SimpleError::new(format!(
+            "{} returned {}",
+            url,
+            response.status()
+        )));
+    }
+    Ok(response)
+}
@@ -0,0 +1,229 @@
+//! Collection of tools for scanning the environment variables and command lines of running
+//! processes for secrets, through the `/proc` file system of Linux.
+//!
+//! `ProcScanner` acts as a wrapper around a `SecretScanner` object. Credentials passed to a
+//! program in its environment (`/proc/<pid>/environ`) or its arguments (`/proc/<pid>/cmdline`)
+//! can be read by anyone with access to the process, which makes them the first thing to look at
+//! on a compromised host. Each finding is attributed to the PID and executable of the process,
+//! and environment findings to their variable.
+//!
+//! Only root can read the environment of the processes of other users. Processes that can't be
+//! read, or that exit during the scan, are skipped with a debug message.
+//!
+//! # Examples
+//!
+//! `scan_process()` reads a process from a `/proc` directory. `scan_process_data()` scans the
+//! contents of its files, e.g. copied from another host.
+//!
+//! ```
+//! use rusty_hogs::proc_scanning::ProcScanner;
+//!
+//! let ps = ProcScanner::new();
+//! let environ = b"HOME=/root\0DB_PASSWORD=hunter2\0MAIL=arst@example.com\0";
+//! let cmdline = b"/usr/bin/app\0--verbose\0";
+//! let findings = ps.scan_process_data(4242, "/usr/bin/app", environ, cmdline, false);
+//! assert_eq!(findings.len(), 1);
+//! let finding = findings.iter().next().unwrap();
+//! assert_eq!(finding.pid, 4242);
+//! assert_eq!(finding.executable, "/usr/bin/app");
+//! assert_eq!(finding.source, "environ");
+//! assert_eq!(finding.variable, Some(String::from("MAIL")));
+//! assert_eq!(finding.reason, "Email address");
+//! ```
+
+use crate::{Finding, SecretScanner, Span, TextFinding};
+use log::{self, debug, info};
+use serde_derive::{Deserialize, Serialize};
+use simple_error::{try_with, SimpleError};
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
+
+/// The usual mount point of the proc file system
+pub const DEFAULT_PROC_ROOT: &str = "/proc";
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Hash, Clone, Default)]
+/// `serde_json` object that represents a single found secret - finding
+pub struct ProcFinding {
+    pub diff: String,
+    #[serde(rename = "stringsFound")]
+    pub strings_found: Vec<String>,
+    pub pid: u32,
+    /// The path of the executable of the process, or its name if the path can't be read
+    pub executable: String,
+    /// `environ` or `cmdline`
+    pub source: String,
+    /// The environment variable the secret was found in
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub variable: Option<String>,
+    pub reason: String,
+    /// Where each of `strings_found` is in the text, see `Span`
+    #[serde(default)]
+    pub spans: Vec<Span>,
+}
+
+impl TextFinding for ProcFinding {
+    fn with_match(&self, found: Finding) -> Self {
+        Self {
+            diff: found.diff,
+            strings_found: found.strings_found,
+            reason: found.reason,
+            spans: found.spans,
+            ..self.clone()
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+/// Contains helper functions for performing scans of running processes
+pub struct ProcScanner {
+    pub secret_scanner: SecretScanner,
+    /// Scan the environment of each process
+    pub scan_environ: bool,
+    /// Scan the command line of each process
+    pub scan_cmdline: bool,
+}
+
+/// Acts as a wrapper around a `SecretScanner` object to provide helper functions for performing
+/// scanning against the processes of a Linux host.
+impl ProcScanner {
+    /// Initialize the SecretScanner object first using the SecretScannerBuilder, then provide
+    /// it to this constructor method.
+    pub fn new_from_scanner(secret_scanner: SecretScanner) -> Self {
+        Self {
+            secret_scanner,
+            scan_environ: true,
+            scan_cmdline: true,
+        }
+    }
+
+    pub fn new() -> Self {
+        Self::new_from_scanner(SecretScanner::default())
+    }
+
+    /// Sets whether the environment of each process is scanned (true by default)
+    pub fn set_scan_environ(mut self, scan_environ: bool) -> Self {
+        self.scan_environ = scan_environ;
+        self
+    }
+
+    /// Sets whether the command line of each process is scanned (true by default)
+    pub fn set_scan_cmdline(mut self, scan_cmdline: bool) -> Self {
+        self.scan_cmdline = scan_cmdline;
+        self
+    }
+
+    /// Returns the PIDs of the processes in `proc_root`, in ascending order
+    pub fn list_processes(&self, proc_root: &Path) -> Result<Vec<u32>, SimpleError> {
+        let entries = try_with!(
+            fs::read_dir(proc_root),
+            "Failed to read {}",
+            proc_root.display()
+        );
+        let mut pids: Vec<u32> = entries
+            .filter_map(|e| e.ok())
+            .filter_map(|e| e.file_name().to_str().and_then(|n| n.parse().ok()))
+            .collect();
+        pids.sort_unstable();
+        info!("Found {} processes in {}", pids.len(), proc_root.display());
+        Ok(pids)
+    }
+
+    /// Scans the process `pid` of `proc_root`. Returns a HashSet of findings, empty if the process
+    /// can't be read or has exited.
+    pub fn scan_process(
+        &self,
+        proc_root: &Path,
+        pid: u32,
+        scan_entropy: bool,
+    ) -> HashSet<ProcFinding> {
+        let dir = proc_root.join(pid.to_string());
+        let read = |name: &str, wanted: bool| -> Vec<u8> {
+            if !wanted {
+                return Vec::new();
+            }
+            match fs::read(dir.join(name)) {
+                Ok(data) => data,
+                Err(e) => {
+                    debug!("Skipping the {} of process {}: {}", name, pid, e);
+                    Vec::new()
+                }
+            }
+        };
+        let environ = read("environ", self.scan_environ);
+        let cmdline = read("cmdline", true);
+        let executable = executable(&dir, &cmdline);
+        let cmdline = if self.scan_cmdline {
+            cmdline
+        } else {
+            Vec::new()
+        };
+        self.scan_process_data(pid, &executable, &environ, &cmdline, scan_entropy)
+    }
+
+    /// Scans the contents of the `environ` and `cmdline` files of a process, both lists of
+    /// NUL-terminated strings. Returns a HashSet of findings.
+    pub fn scan_process_data(
+        &self,
+        pid: u32,
+        executable: &str,
+        environ: &[u8],
+        cmdline: &[u8],
+        scan_entropy: bool,
+    ) -> HashSet<ProcFinding> {
+        let mut findings: HashSet<ProcFinding> = HashSet::new();
+        let template = ProcFinding {
+            pid,
+            executable: executable.to_string(),
+            ..Default::default()
+        };
+        for variable in environ.split(|x| *x == 0).filter(|v| !v.is_empty()) {
+            let name = variable.split(|x| *x == b'=').next().unwrap_or(variable);
+            let template = ProcFinding {
+                source: String::from("environ"),
+                variable: Some(String::from_utf8_lossy(name).into_owned()),
+                ..template.clone()
+            };
+            self.secret_scanner
+                .scan_text(variable, &template, scan_entropy, &mut findings);
+        }
+        if !cmdline.is_empty() {
+            // the arguments are scanned as one line, for options and their values to match together
+            let arguments: Vec<&[u8]> = cmdline
+                .split(|x| *x == 0)
+                .filter(|a| !a.is_empty())
+                .collect();
+            let template = ProcFinding {
+                source: String::from("cmdline"),
+                ..template
+            };
+            self.secret_scanner.scan_text(
+                &arguments.join(&b' '),
+                &template,
+                scan_entropy,
+                &mut findings,
+            );
+        }
+        findings
+    }
+}
+
+impl Default for ProcScanner {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The executable of the process in `dir`: the target of its `exe` link, else the first argument
+/// of its command line, else its `comm` name
+fn executable(dir: &Path, cmdline: &[u8]) -> String {
+    if let Ok(path) = fs::read_link(dir.join("exe")) {
+        return path.to_string_lossy().into_owned();
+    }
+    match cmdline.split(|x| *x == 0).next() {
+        Some(first) if !first.is_empty() => String::from_utf8_lossy(first).into_owned(),
+        _ => fs::read_to_string(dir.join("comm"))
+            .map(|c| c.trim_end().to_string())
+            .unwrap_or_default(),
+    }
+}
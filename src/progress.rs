@@ -0,0 +1,144 @@
+//! Periodic status lines on stderr for long scans.
+//!
+//! Binaries call `install` when given `--progress`. Scanners call `scanned` each time they finish
+//! an object (a file, blob, commit, message, build log...), with its size and the number of
+//! findings it added, and `add_total` when they know how many objects they are about to scan.
+//! Every `INTERVAL`, a line with the objects scanned so far, the findings, the throughput and, when
+//! the total is known, an estimate of the time left is written to stderr, leaving the report on
+//! stdout untouched. Without `install` the counts are kept but never written.
+//!
+//! # Examples
+//!
+//! ```
+//! use rusty_hogs::progress::{self, Progress};
+//! use std::time::Duration;
+//!
+//! progress::add_total(2);
+//! progress::scanned(1024, 1);
+//! assert_eq!(Progress::current().objects, 1);
+//!
+//! let progress = Progress {
+//!     objects: 120,
+//!     total: 400,
+//!     bytes: 36 * 1024 * 1024,
+//!     findings: 3,
+//! };
+//! assert_eq!(
+//!     progress.status_line(Duration::from_secs(60)),
+//!     "Scanned 120 of 400 objects (36.0 MB, 614.4 KB/s), 3 findings, ETA 2m 20s"
+//! );
+//! ```
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Once;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How often the status line is written
+pub const INTERVAL: Duration = Duration::from_secs(5);
+
+static REPORTER: Once = Once::new();
+static OBJECTS: AtomicU64 = AtomicU64::new(0);
+static TOTAL: AtomicU64 = AtomicU64::new(0);
+static BYTES: AtomicU64 = AtomicU64::new(0);
+static FINDINGS: AtomicU64 = AtomicU64::new(0);
+
+/// The counts of a scan so far
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Progress {
+    /// The number of objects scanned
+    pub objects: u64,
+    /// The number of objects to scan, or 0 if it isn't known
+    pub total: u64,
+    /// The size of the objects scanned
+    pub bytes: u64,
+    /// The number of findings in the objects scanned
+    pub findings: u64,
+}
+
+impl Progress {
+    /// The counts of the scan running in this process
+    pub fn current() -> Self {
+        Progress {
+            objects: OBJECTS.load(Ordering::SeqCst),
+            total: TOTAL.load(Ordering::SeqCst),
+            bytes: BYTES.load(Ordering::SeqCst),
+            findings: FINDINGS.load(Ordering::SeqCst),
+        }
+    }
+
+    /// The status line written after `elapsed`. The time left assumes the remaining objects are
+    /// scanned at the same rate, and is left out while it can't be estimated.
+    pub fn status_line(&self, elapsed: Duration) -> String {
+        let seconds = elapsed.as_secs_f64();
+        let throughput = if seconds > 0.0 {
+            self.bytes as f64 / seconds
+        } else {
+            0.0
+        };
+        let objects = if self.total > 0 {
+            format!("{} of {}", self.objects, self.total)
+        } else {
+            self.objects.to_string()
+        };
+        let mut line = format!(
+            "Scanned {} objects ({}, {}/s), {} findings",
+            objects,
+            format_bytes(self.bytes as f64),
+            format_bytes(throughput),
+            self.findings
+        );
+        if self.total >= self.objects && self.objects > 0 && seconds > 0.0 {
+            let left = (self.total - self.objects) as f64 * seconds / self.objects as f64;
+            line.push_str(&format!(", ETA {}", format_duration(left as u64)));
+        }
+        line
+    }
+}
+
+/// Writes a status line to stderr every `INTERVAL` until the process exits
+pub fn install() {
+    REPORTER.call_once(|| {
+        let start = Instant::now();
+        thread::spawn(move || loop {
+            thread::sleep(INTERVAL);
+            eprintln!("{}", Progress::current().status_line(start.elapsed()));
+        });
+    });
+}
+
+/// Counts an object of `bytes` bytes as scanned, with the number of findings it added
+pub fn scanned(bytes: usize, findings: usize) {
+    OBJECTS.fetch_add(1, Ordering::SeqCst);
+    BYTES.fetch_add(bytes as u64, Ordering::SeqCst);
+    FINDINGS.fetch_add(findings as u64, Ordering::SeqCst);
+}
+
+/// Adds `objects` to the number of objects the scan will go through, for the estimate of the time
+/// left
+pub fn add_total(objects: usize) {
+    TOTAL.fetch_add(objects as u64, Ordering::SeqCst);
+}
+
+fn format_bytes(bytes: f64) -> String {
+    let units = ["B", "KB", "MB", "GB", "TB"];
+    let mut value = bytes;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < units.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} {}", value as u64, units[unit])
+    } else {
+        format!("{:.1} {}", value, units[unit])
+    }
+}
+
+fn format_duration(seconds: u64) -> String {
+    match seconds {
+        0..=59 => format!("{}s", seconds),
+        60..=3599 => format!("{}m {}s", seconds / 60, seconds % 60),
+        _ => format!("{}h {}m", seconds / 3600, seconds % 3600 / 60),
+    }
+}
@@ -0,0 +1,138 @@
+//! Python bindings, built with the `python` feature.
+//!
+//! The `rusty_hog` Python module wraps a `SecretScanner` so that Python jobs scan data with the
+//! same rule packs as the binaries, without running them. Build and install it with
+//! [maturin](https://github.com/PyO3/maturin) from the root of the repository:
+//! `maturin build --release --cargo-extra-args="--features python"`, then `pip install` the wheel
+//! it writes to `target/wheels`.
+//!
+//! `Scanner` takes the rule pack files to load (`rules`, the built-in rules by default) or a rule
+//! pack as a JSON string (`rules_json`), `case_insensitive`, rules to enable or disable, and
+//! `entropy` to also report high entropy strings. Its `scan_bytes` and `scan_file` methods return
+//! a list of dicts with the fields of a `Finding`. Invalid rule packs raise `ValueError`, and files
+//! that can't be read `IOError`.
+//!
+//! ```text
+//! import rusty_hog
+//!
+//! scanner = rusty_hog.Scanner(rules=["rules.json"], entropy=True)
+//! for finding in scanner.scan_bytes(b"my email is arst@example.com"):
+//!     print(finding["reason"], finding["strings_found"], finding["line"])
+//! ```
+
+use crate::rules::RulePack;
+use crate::{Finding, SecretScanner, SecretScannerBuilder, Span};
+use pyo3::exceptions::{PyIOError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
+use std::fs;
+
+/// A secret scanner and whether it reports high entropy strings
+#[pyclass(module = "rusty_hog")]
+pub struct Scanner {
+    scanner: SecretScanner,
+    entropy: bool,
+}
+
+#[pymethods]
+impl Scanner {
+    #[new]
+    #[args(
+        rules = "None",
+        rules_json = "None",
+        case_insensitive = "false",
+        entropy = "false",
+        enable = "None",
+        disable = "None"
+    )]
+    fn new(
+        rules: Option<Vec<String>>,
+        rules_json: Option<String>,
+        case_insensitive: bool,
+        entropy: bool,
+        enable: Option<Vec<String>>,
+        disable: Option<Vec<String>>,
+    ) -> PyResult<Self> {
+        // the builder falls back to the built-in rules on an invalid rule pack, so check first
+        let mut builder = SecretScannerBuilder::new().global_case_insensitive(case_insensitive);
+        for path in rules.unwrap_or_default() {
+            RulePack::from_file(&path).map_err(|e| PyValueError::new_err(e.to_string()))?;
+            builder = builder.add_json_path(&path);
+        }
+        if let Some(json) = rules_json {
+            RulePack::from_str(&json, "custom")
+                .map_err(|e| PyValueError::new_err(e.to_string()))?;
+            builder = builder.set_json_str(&json);
+        }
+        for rule in enable.unwrap_or_default() {
+            builder = builder.enable_rule(&rule);
+        }
+        for rule in disable.unwrap_or_default() {
+            builder = builder.disable_rule(&rule);
+        }
+        Ok(Self {
+            scanner: builder.build(),
+            entropy,
+        })
+    }
+
+    /// The names of the rules this scanner matches
+    #[getter]
+    fn rules(&self) -> Vec<String> {
+        self.scanner.regex_map.keys().cloned().collect()
+    }
+
+    /// The name and version of the rule packs, as in the JSON reports
+    #[getter]
+    fn rules_version(&self) -> String {
+        self.scanner.rules_version.clone()
+    }
+
+    /// Scans a bytes object, returning a list of findings
+    fn scan_bytes(&self, py: Python, data: &[u8]) -> PyResult<Vec<PyObject>> {
+        self.scanner
+            .scan_bytes(data, self.entropy)
+            .into_iter()
+            .map(|f| finding_dict(py, f))
+            .collect()
+    }
+
+    /// Scans the contents of a file, returning a list of findings
+    fn scan_file(&self, py: Python, path: &str) -> PyResult<Vec<PyObject>> {
+        let data = fs::read(path)
+            .map_err(|e| PyIOError::new_err(format!("Failed to read {}: {}", path, e)))?;
+        self.scan_bytes(py, &data)
+    }
+}
+
+/// A finding as a Python dict
+fn finding_dict(py: Python, finding: Finding) -> PyResult<PyObject> {
+    let dict = PyDict::new(py);
+    dict.set_item("reason", finding.reason)?;
+    dict.set_item("strings_found", finding.strings_found)?;
+    dict.set_item("diff", finding.diff)?;
+    dict.set_item("line", finding.line)?;
+    dict.set_item("offset", finding.offset)?;
+    let spans: PyResult<Vec<PyObject>> = finding.spans.iter().map(|s| span_dict(py, s)).collect();
+    dict.set_item("spans", spans?)?;
+    Ok(dict.to_object(py))
+}
+
+/// A span as a Python dict, see `Span`
+fn span_dict(py: Python, span: &Span) -> PyResult<PyObject> {
+    let dict = PyDict::new(py);
+    dict.set_item("line", span.line)?;
+    dict.set_item("line_end", span.line_end)?;
+    dict.set_item("column_start", span.column_start)?;
+    dict.set_item("column_end", span.column_end)?;
+    dict.set_item("byte_start", span.byte_start)?;
+    dict.set_item("byte_end", span.byte_end)?;
+    Ok(dict.to_object(py))
+}
+
+/// The `rusty_hog` Python module
+#[pymodule]
+fn rusty_hog(_py: Python, m: &PyModule) -> PyResult<()> {
+    m.add_class::<Scanner>()?;
+    Ok(())
+}
@@ -0,0 +1,385 @@
+//! Collection of tools for scanning images stored in a remote OCI / Docker registry for secrets.
+//!
+//! `RegistryScanner` acts as a wrapper around a `SecretScanner` object and talks to the
+//! [Docker Registry HTTP API V2](https://docs.docker.com/registry/spec/api/) directly, so it works
+//! against Docker Hub, ECR, GCR and any other OCI distribution compatible registry without Docker
+//! installed. For each image reference it pulls the manifest, downloads every layer blob and then
+//! scans the layers with the same whiteout-aware logic used by `DockerScanner`, so the findings
+//! are `DockerFinding` objects.
+//!
+//! # Examples
+//!
+//! Image references are parsed the same way the Docker CLI parses them...
+//!
+//! ```
+//! use rusty_hogs::registry_scanning::ImageReference;
+//! let image: ImageReference = "alpine:3.11".parse().unwrap();
+//! assert_eq!(image.registry, "registry-1.docker.io");
+//! assert_eq!(image.repository, "library/alpine");
+//! assert_eq!(image.reference, "3.11");
+//!
+//! let image: ImageReference = "gcr.io/my-project/app@sha256:abcd".parse().unwrap();
+//! assert_eq!(image.registry, "gcr.io");
+//! assert_eq!(image.repository, "my-project/app");
+//! assert_eq!(image.reference, "sha256:abcd");
+//! ```
+//!
+//! Then supply each reference, along with any credentials, to `scan_image()`.
+//!
+//! ```no_run
+//! use rusty_hogs::registry_scanning::{RegistryAuth, RegistryScanner};
+//!
+//! let rs = RegistryScanner::new();
+//! let image = "alpine:3.11".parse().unwrap();
+//! let findings = rs.scan_image(&image, &RegistryAuth::Anonymous, false).unwrap();
+//! rs.secret_scanner.output_findings(&findings);
+//! ```
+
+use crate::docker_scanning::{DockerFinding, DockerScanner, LayerIndex};
+use crate::network;
+use crate::retry;
+use crate::SecretScanner;
+use log::{self, debug, info};
+use reqwest::blocking::{Client, Response};
+use reqwest::header::{ACCEPT, WWW_AUTHENTICATE};
+use reqwest::StatusCode;
+use serde_derive::Deserialize;
+use simple_error::{require_with, try_with, SimpleError};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::fs::File;
+use std::str::FromStr;
+
+const DOCKER_HUB_REGISTRY: &str = "registry-1.docker.io";
+const MANIFEST_ACCEPT: &str = "application/vnd.docker.distribution.manifest.v2+json, \
+                               application/vnd.docker.distribution.manifest.list.v2+json, \
+                               application/vnd.oci.image.manifest.v1+json, \
+                               application/vnd.oci.image.index.v1+json";
+
+/// A parsed image reference such as `gcr.io/project/app:1.0` or `alpine@sha256:...`
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+pub struct ImageReference {
+    pub registry: String,
+    pub repository: String,
+    /// Either a tag or a digest
+    pub reference: String,
+}
+
+impl FromStr for ImageReference {
+    type Err = SimpleError;
+
+    /// Parses a reference the way the Docker CLI does: the first path component is a registry
+    /// host only if it contains a `.` or `:` (or is `localhost`), single-component Docker Hub
+    /// names live under `library/`, and the tag defaults to `latest`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.is_empty() {
+            return Err(SimpleError::new("Empty image reference"));
+        }
+        let (registry, remainder) = match s.find('/') {
+            Some(i) => {
+                let host = &s[..i];
+                if host.contains('.') || host.contains(':') || host == "localhost" {
+                    (host.to_string(), &s[i + 1..])
+                } else {
+                    (DOCKER_HUB_REGISTRY.to_string(), s)
+                }
+            }
+            None => (DOCKER_HUB_REGISTRY.to_string(), s),
+        };
+        let (repository, reference) = match remainder.find('@') {
+            Some(i) => (&remainder[..i], &remainder[i + 1..]),
+            None => {
+                let last_slash = remainder.rfind('/').map(|i| i + 1).unwrap_or(0);
+                match remainder[last_slash..].rfind(':') {
+                    Some(i) => (
+                        &remainder[..last_slash + i],
+                        &remainder[last_slash + i + 1..],
+                    ),
+                    None => (remainder, "latest"),
+                }
+            }
+        };
+        let repository = if registry == DOCKER_HUB_REGISTRY && !repository.contains('/') {
+            format!("library/{}", repository)
+        } else {
+            repository.to_string()
+        };
+        Ok(Self {
+            registry,
+            repository,
+            reference: reference.to_string(),
+        })
+    }
+}
+
+impl fmt::Display for ImageReference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let separator = if self.reference.contains(':') { "@" } else { ":" };
+        write!(
+            f,
+            "{}/{}{}{}",
+            self.registry, self.repository, separator, self.reference
+        )
+    }
+}
+
+/// Credentials used to authenticate against a registry
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+pub enum RegistryAuth {
+    Anonymous,
+    /// Username and password (or access token) exchanged for a bearer token when the registry
+    /// asks for one, e.g. `AWS` and the output of `aws ecr get-login-password` for ECR
+    Basic { username: String, password: String },
+    /// A pre-issued bearer token, sent as-is
+    Bearer(String),
+}
+
+/// An image manifest or manifest list / OCI index. Only the fields we need are included.
+#[derive(Deserialize, Debug, Clone)]
+struct Manifest {
+    layers: Option<Vec<Descriptor>>,
+    manifests: Option<Vec<PlatformDescriptor>>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+struct Descriptor {
+    digest: String,
+    size: Option<u64>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+struct PlatformDescriptor {
+    digest: String,
+    platform: Option<Platform>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+struct Platform {
+    architecture: String,
+    os: String,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+struct TokenResponse {
+    token: Option<String>,
+    access_token: Option<String>,
+}
+
+/// Holds the authentication negotiated for a single repository
+struct RegistrySession<'a> {
+    client: &'a Client,
+    image: &'a ImageReference,
+    auth: &'a RegistryAuth,
+    token: Option<String>,
+    use_basic: bool,
+}
+
+impl<'a> RegistrySession<'a> {
+    /// Performs a GET against the registry API, negotiating a bearer token on the first 401
+    fn get(&mut self, path: &str, accept: Option<&str>) -> Result<Response, SimpleError> {
+        let url = format!(
+            "https://{}/v2/{}/{}",
+            self.image.registry, self.image.repository, path
+        );
+        let mut response = try_with!(
+            retry::send(self.request(&url, accept)),
+            "Registry request failed"
+        );
+        if response.status() == StatusCode::UNAUTHORIZED && self.token.is_none() && !self.use_basic
+        {
+            let challenge = match response.headers().get(WWW_AUTHENTICATE) {
+                Some(h) => h.to_str().unwrap_or("").to_string(),
+                None => String::new(),
+            };
+            if challenge.to_ascii_lowercase().starts_with("bearer") {
+                self.token = Some(self.fetch_token(&challenge[6..])?);
+            } else if let RegistryAuth::Basic { .. } = self.auth {
+                self.use_basic = true;
+            } else {
+                return Err(SimpleError::new(
+                    "Registry requires authentication but no credentials were supplied",
+                ));
+            }
+            response = try_with!(
+                retry::send(self.request(&url, accept)),
+                "Registry request failed"
+            );
+        }
+        if !response.status().is_success() {
+            return Err(SimpleError::new(format!(
+                "Registry returned {} for {}",
+                response.status(),
+                url
+            )));
+        }
+        Ok(response)
+    }
+
+    fn request(&self, url: &str, accept: Option<&str>) -> reqwest::blocking::RequestBuilder {
+        let mut builder = self.client.get(url);
+        if let Some(a) = accept {
+            builder = builder.header(ACCEPT, a);
+        }
+        match (&self.token, self.auth) {
+            (Some(t), _) => builder.bearer_auth(t),
+            (None, RegistryAuth::Bearer(t)) => builder.bearer_auth(t),
+            (None, RegistryAuth::Basic { username, password }) if self.use_basic => {
+                builder.basic_auth(username, Some(password))
+            }
+            _ => builder,
+        }
+    }
+
+    /// Exchanges our credentials for a bearer token at the realm named in the challenge header,
+    /// e.g. `realm="https://auth.docker.io/token",service="registry.docker.io"`
+    fn fetch_token(&self, challenge_params: &str) -> Result<String, SimpleError> {
+        if let RegistryAuth::Bearer(t) = self.auth {
+            return Ok(t.clone());
+        }
+        let params = parse_challenge(challenge_params);
+        let realm = require_with!(params.get("realm"), "No realm in the registry auth challenge");
+        let default_scope = format!("repository:{}:pull", self.image.repository);
+        let scope = params.get("scope").unwrap_or(&default_scope);
+        let mut builder = self.client.get(realm.as_str()).query(&[("scope", scope)]);
+        if let Some(service) = params.get("service") {
+            builder = builder.query(&[("service", service)]);
+        }
+        if let RegistryAuth::Basic { username, password } = self.auth {
+            builder = builder.basic_auth(username, Some(password));
+        }
+        debug!("Requesting a registry token from {}", realm);
+        let response = try_with!(retry::send(builder), "Registry token request failed");
+        if !response.status().is_success() {
+            return Err(SimpleError::new(format!(
+                "Registry token request returned {}",
+                response.status()
+            )));
+        }
+        let token: TokenResponse = try_with!(response.json(), "Failed to parse registry token");
+        match token.token.or(token.access_token) {
+            Some(t) => Ok(t),
+            None => Err(SimpleError::new("Registry token response contained no token")),
+        }
+    }
+}
+
+/// Splits `key="value",key2="value2"` auth challenge parameters into a map
+fn parse_challenge(params: &str) -> HashMap<String, String> {
+    params
+        .split(',')
+        .filter_map(|kv| {
+            let mut parts = kv.trim().splitn(2, '=');
+            let key = parts.next()?.trim().to_ascii_lowercase();
+            let value = parts.next()?.trim().trim_matches('"').to_string();
+            Some((key, value))
+        })
+        .collect()
+}
+
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+/// Contains helper functions for performing scans of images in a remote registry
+pub struct RegistryScanner {
+    pub secret_scanner: SecretScanner,
+}
+
+/// Acts as a wrapper around a `SecretScanner` object to provide helper functions for performing
+/// scanning against images in a registry. Relies on the [reqwest](https://docs.rs/reqwest/)
+/// library for HTTP access.
+impl RegistryScanner {
+    /// Initialize the SecretScanner object first using the SecretScannerBuilder, then provide
+    /// it to this constructor method.
+    pub fn new_from_scanner(secret_scanner: SecretScanner) -> Self {
+        Self { secret_scanner }
+    }
+
+    pub fn new() -> Self { Self { secret_scanner: SecretScanner::default() } }
+
+    /// Downloads the manifest and every layer of `image` (for multi-platform images, the one
+    /// matching `platform` in `os/architecture` form, e.g. `linux/amd64`) and scans them,
+    /// returning a HashSet of findings.
+    pub fn scan_image(
+        &self,
+        image: &ImageReference,
+        auth: &RegistryAuth,
+        scan_entropy: bool,
+    ) -> Result<HashSet<DockerFinding>, SimpleError> {
+        self.scan_image_for_platform(image, auth, "linux/amd64", scan_entropy)
+    }
+
+    /// The same as `scan_image()`, with a specific platform for multi-platform images
+    pub fn scan_image_for_platform(
+        &self,
+        image: &ImageReference,
+        auth: &RegistryAuth,
+        platform: &str,
+        scan_entropy: bool,
+    ) -> Result<HashSet<DockerFinding>, SimpleError> {
+        let client = network::client();
+        let mut session = RegistrySession {
+            client: &client,
+            image,
+            auth,
+            token: None,
+            use_basic: false,
+        };
+        info!("Fetching the manifest for {}", image);
+        let manifest = Self::get_manifest(&mut session, &image.reference, platform)?;
+        let layers = require_with!(manifest.layers, "Image manifest contains no layers");
+
+        let dest_dir = self.secret_scanner.workspace()?;
+        let mut indexed_layers: Vec<LayerIndex> = Vec::new();
+        for layer in layers {
+            info!(
+                "Downloading layer {} ({} bytes)",
+                layer.digest,
+                layer.size.unwrap_or(0)
+            );
+            let mut response = session.get(&format!("blobs/{}", layer.digest), None)?;
+            let path = dest_dir.path().join(layer.digest.replace(':', "_"));
+            let mut f = try_with!(File::create(&path), "Failed to create a layer file");
+            try_with!(response.copy_to(&mut f), "Failed to download a layer");
+            dest_dir.check_quota()?;
+            indexed_layers.push(DockerScanner::index_layer(layer.digest, path)?);
+        }
+
+        let docker_scanner = DockerScanner::new_from_scanner(self.secret_scanner.clone());
+        let mut findings: HashSet<DockerFinding> = HashSet::new();
+        docker_scanner.scan_layers(
+            &image.to_string(),
+            &indexed_layers,
+            scan_entropy,
+            &mut findings,
+        )?;
+        Ok(findings)
+    }
+
+    /// Retrieves an image manifest, resolving manifest lists / OCI indexes to a single platform
+    fn get_manifest(
+        session: &mut RegistrySession,
+        reference: &str,
+        platform: &str,
+    ) -> Result<Manifest, SimpleError> {
+        let response = session.get(&format!("manifests/{}", reference), Some(MANIFEST_ACCEPT))?;
+        let manifest: Manifest = try_with!(response.json(), "Failed to parse the image manifest");
+        if manifest.layers.is_some() {
+            return Ok(manifest);
+        }
+        let manifests = require_with!(manifest.manifests, "Image manifest contains no layers");
+        let chosen = manifests
+            .iter()
+            .find(|m| match &m.platform {
+                Some(p) => format!("{}/{}", p.os, p.architecture) == platform,
+                None => false,
+            })
+            .or_else(|| manifests.first());
+        let chosen = require_with!(chosen, "Image index contains no manifests");
+        debug!("Resolved {} to {} for {}", reference, chosen.digest, platform);
+        Self::get_manifest(session, &chosen.digest, platform)
+    }
+}
+
+impl Default for RegistryScanner {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,208 @@
+//! Replaying rule changes against captured match contexts.
+//!
+//! Measuring the impact of a new or changed rule normally means re-running every scan, which for
+//! large organizations can take hours and hit API rate limits. Instead, scanners can capture the
+//! line each finding was found on with `--capture-contexts <FILE>`, before caps, the allowlist or
+//! the output format are applied. `ReplayReport::compute` re-evaluates those lines against
+//! another rule pack and reports, for each rule, how many of them it matches before and after,
+//! with examples of the lines it gains and loses.
+//!
+//! Only lines that some rule matched during the scan are captured, so a replay shows how a change
+//! affects the existing findings, but a new rule is only evaluated against lines that other rules
+//! already flagged. Capturing with a broader rule pack (or `--entropy`) widens what is kept.
+//!
+//! A context file is a JSON array of contexts:
+//!
+//! ```json
+//! [
+//!     { "location": "src/config.py", "context": "token = 'xoxp-...'", "reasons": ["Slack Token"] }
+//! ]
+//! ```
+//!
+//! # Examples
+//!
+//! ```
+//! use rusty_hogs::replay::{MatchContext, ReplayReport};
+//! use rusty_hogs::SecretScannerBuilder;
+//!
+//! let contexts = vec![
+//!     MatchContext {
+//!         location: String::from("a.py"),
+//!         context: String::from("password = hunter2"),
+//!         reasons: vec![String::from("Password")],
+//!     },
+//!     MatchContext {
+//!         location: String::from("b.py"),
+//!         context: String::from("password = None"),
+//!         reasons: vec![String::from("Password")],
+//!     },
+//! ];
+//! // the new rule pack no longer reports passwords that aren't set
+//! let scanner = SecretScannerBuilder::new()
+//!     .set_json_str(r#"{ "Password": "password = (?:[^N]|N[^o])" }"#)
+//!     .build();
+//! let report = ReplayReport::compute(&contexts, &scanner, false, 5);
+//! let impact = &report.rules["Password"];
+//! assert_eq!((impact.before, impact.after), (2, 1));
+//! assert_eq!((impact.added, impact.removed), (0, 1));
+//! assert_eq!(impact.removed_examples[0].location, "b.py");
+//! ```
+
+use crate::reporting::location_of;
+use crate::SecretScanner;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use simple_error::{try_with, SimpleError};
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::fs;
+use std::hash::Hash;
+
+/// The reason given to entropy findings
+const ENTROPY_REASON: &str = "Entropy";
+
+/// A line that one or more rules matched during a scan
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Hash, Clone, Default)]
+pub struct MatchContext {
+    /// The path, key or link of the finding
+    pub location: String,
+    /// The line the finding was found on (its `diff`)
+    pub context: String,
+    /// The rules that matched the line
+    pub reasons: Vec<String>,
+}
+
+/// How a rule's matches change when replayed
+#[derive(Serialize, Debug, PartialEq, Eq, Clone, Default)]
+pub struct RuleImpact {
+    /// The contexts the rule matched when captured
+    pub before: usize,
+    /// The contexts the rule matches now
+    pub after: usize,
+    pub added: usize,
+    pub removed: usize,
+    /// Some of the contexts the rule matches now but didn't before
+    #[serde(rename = "addedExamples")]
+    pub added_examples: Vec<MatchContext>,
+    /// Some of the contexts the rule matched before but doesn't now
+    #[serde(rename = "removedExamples")]
+    pub removed_examples: Vec<MatchContext>,
+}
+
+/// The impact of a rule pack on captured contexts, see the module documentation
+#[derive(Serialize, Debug, PartialEq, Eq, Clone, Default)]
+pub struct ReplayReport {
+    pub contexts: usize,
+    /// The version of the rule pack replayed (see `rules::rules_version`)
+    #[serde(rename = "rulesVersion")]
+    pub rules_version: String,
+    /// Every rule that matched a context before or after
+    pub rules: BTreeMap<String, RuleImpact>,
+}
+
+/// Collects the contexts of serialized findings, merging findings of different rules on the same
+/// line. Findings without a `diff` are left out.
+pub fn collect_contexts<T: Serialize + Eq + Hash>(findings: &HashSet<T>) -> Vec<MatchContext> {
+    let mut contexts: BTreeMap<(String, String), BTreeSet<String>> = BTreeMap::new();
+    for finding in findings {
+        let finding: Value = match serde_json::to_value(finding) {
+            Ok(v) => v,
+            Err(_) => continue,
+        };
+        let context = match finding["diff"].as_str() {
+            Some(d) => d.to_string(),
+            None => continue,
+        };
+        let reason = finding["reason"].as_str().unwrap_or_default().to_string();
+        contexts
+            .entry((location_of(&finding), context))
+            .or_insert_with(BTreeSet::new)
+            .insert(reason);
+    }
+    contexts
+        .into_iter()
+        .map(|((location, context), reasons)| MatchContext {
+            location,
+            context,
+            reasons: reasons.into_iter().collect(),
+        })
+        .collect()
+}
+
+/// Writes contexts to a JSON file, replacing it
+pub fn write_contexts(path: &str, contexts: &[MatchContext]) -> Result<(), SimpleError> {
+    let json = try_with!(
+        serde_json::to_vec(contexts),
+        "Failed to serialize the contexts"
+    );
+    try_with!(fs::write(path, json), "Failed to write {}", path);
+    Ok(())
+}
+
+/// Reads contexts written by `write_contexts`
+pub fn read_contexts(path: &str) -> Result<Vec<MatchContext>, SimpleError> {
+    let json = try_with!(fs::read_to_string(path), "Failed to read {}", path);
+    Ok(try_with!(
+        serde_json::from_str(&json),
+        "Failed to parse the contexts in {}",
+        path
+    ))
+}
+
+impl ReplayReport {
+    /// Replays `contexts` against the rules of `scanner`, listing at most `examples` added and
+    /// removed contexts for each rule. Entropy findings are only compared with `scan_entropy`.
+    pub fn compute(
+        contexts: &[MatchContext],
+        scanner: &SecretScanner,
+        scan_entropy: bool,
+        examples: usize,
+    ) -> Self {
+        let mut rules: BTreeMap<String, RuleImpact> = BTreeMap::new();
+        for context in contexts {
+            let before: BTreeSet<&str> = context
+                .reasons
+                .iter()
+                .map(String::as_str)
+                .filter(|r| scan_entropy || *r != ENTROPY_REASON)
+                .collect();
+            let line = context.context.as_bytes();
+            let mut after: BTreeSet<&str> = scanner
+                .matches(line)
+                .into_iter()
+                .filter_map(|(reason, mut matches)| matches.next().map(|_| reason.as_str()))
+                .collect();
+            if scan_entropy && !scanner.entropy_matches(line).is_empty() {
+                after.insert(ENTROPY_REASON);
+            }
+
+            for reason in before.union(&after) {
+                let impact = rules.entry(reason.to_string()).or_default();
+                match (before.contains(reason), after.contains(reason)) {
+                    (true, true) => {
+                        impact.before += 1;
+                        impact.after += 1;
+                    }
+                    (true, false) => {
+                        impact.before += 1;
+                        impact.removed += 1;
+                        if impact.removed_examples.len() < examples {
+                            impact.removed_examples.push(context.clone());
+                        }
+                    }
+                    _ => {
+                        impact.after += 1;
+                        impact.added += 1;
+                        if impact.added_examples.len() < examples {
+                            impact.added_examples.push(context.clone());
+                        }
+                    }
+                }
+            }
+        }
+        Self {
+            contexts: contexts.len(),
+            rules_version: scanner.rules_version.clone(),
+            rules,
+        }
+    }
+}
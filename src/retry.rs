@@ -0,0 +1,251 @@
+//! Retrying throttled API requests.
+//!
+//! The API scanners send their requests with `send`, which retries a request that was rate limited
+//! (429, or 403 with a `Retry-After` header) or hit an unavailable server (502, 503 or 504) up to
+//! `RetryPolicy::max_retries` times. Before each retry it waits as long as the `Retry-After` header
+//! asks, in seconds or as an HTTP date, up to `MAX_RETRY_AFTER`, or else backs off exponentially
+//! from `BASE_DELAY` up to `MAX_DELAY` with random jitter, so that threads throttled together don't
+//! retry in lockstep.
+//!
+//! Binaries select the policy with `install`, from `--max-retries` and `--rate-limit`. The rate
+//! limit spaces out the requests of every thread so that at most that many are sent each second,
+//! and can't be less than `MIN_RATE_LIMIT`.
+//! Requests answered from fixtures (see the `fixtures` module) are retried without waiting.
+//!
+//! # Examples
+//!
+//! ```
+//! use rusty_hogs::fixtures::{self, FixtureMode};
+//! use rusty_hogs::retry;
+//! use std::fs;
+//! use tempdir::TempDir;
+//!
+//! let dir = TempDir::new("fixtures").unwrap();
+//! let throttled = r#"{"log": {"entries": [{
+//!     "request": {"method": "GET", "url": "https://api.example.com/v1/items"},
+//!     "response": {"status": 429, "headers": [{"name": "retry-after", "value": "30"}], "content": {"text": ""}}
+//! }]}}"#;
+//! let ok = r#"{"log": {"entries": [{
+//!     "request": {"method": "GET", "url": "https://api.example.com/v1/items"},
+//!     "response": {"status": 200, "headers": [], "content": {"text": "[]"}}
+//! }]}}"#;
+//! fs::write(dir.path().join("00000.har"), throttled).unwrap();
+//! fs::write(dir.path().join("00001.har"), ok).unwrap();
+//! fixtures::install(FixtureMode::Replay(dir.path().to_path_buf())).unwrap();
+//!
+//! let client = reqwest::blocking::Client::new();
+//! let response = retry::send(client.get("https://api.example.com/v1/items")).unwrap();
+//! assert_eq!(response.status(), 200);
+//!
+//! let delay = retry::backoff(2);
+//! assert!(delay >= retry::BASE_DELAY * 2 && delay <= retry::BASE_DELAY * 4);
+//!
+//! let throttled = reqwest::StatusCode::TOO_MANY_REQUESTS;
+//! let delay = retry::retry_delay(throttled, Some("1e30"), 0);
+//! assert_eq!(delay, Some(retry::MAX_RETRY_AFTER));
+//! let delay = retry::retry_delay(throttled, Some("inf"), 0).unwrap();
+//! assert!(delay <= retry::BASE_DELAY);
+//! assert!(retry::RetryPolicy::from_args(None, Some("1e-300")).is_err());
+//! ```
+
+use crate::fixtures;
+use crate::shutdown;
+use chrono::{DateTime, Utc};
+use lazy_static::lazy_static;
+use log::{self, debug};
+use reqwest::blocking::{RequestBuilder, Response};
+use reqwest::StatusCode;
+use simple_error::{try_with, SimpleError};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// How many times a request is retried unless `--max-retries` says otherwise
+pub const DEFAULT_MAX_RETRIES: u32 = 5;
+
+/// The backoff before the first retry of a response without `Retry-After`, doubled for each retry
+pub const BASE_DELAY: Duration = Duration::from_secs(1);
+
+/// The longest backoff between retries of a response without `Retry-After`
+pub const MAX_DELAY: Duration = Duration::from_secs(60);
+
+/// The longest wait between retries a `Retry-After` header can ask for
+pub const MAX_RETRY_AFTER: Duration = Duration::from_secs(60 * 60);
+
+/// The lowest `--rate-limit`, one request every 1000 seconds
+pub const MIN_RATE_LIMIT: f64 = 0.001;
+
+/// How throttled requests are retried, and how fast requests are sent
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct RetryPolicy {
+    /// The number of times a throttled request is retried before its response is returned
+    pub max_retries: u32,
+    /// The most requests sent each second, across threads, or None for no limit
+    pub rate_limit: Option<f64>,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: DEFAULT_MAX_RETRIES,
+            rate_limit: None,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// The policy selected by the `--max-retries` and `--rate-limit` options
+    pub fn from_args(
+        max_retries: Option<&str>,
+        rate_limit: Option<&str>,
+    ) -> Result<Self, SimpleError> {
+        let max_retries = match max_retries {
+            Some(n) => try_with!(n.parse(), "--max-retries must be a number"),
+            None => DEFAULT_MAX_RETRIES,
+        };
+        let rate_limit = match rate_limit {
+            Some(n) => {
+                let rate: f64 = try_with!(n.parse(), "--rate-limit must be a number");
+                if !rate.is_finite() || rate < MIN_RATE_LIMIT {
+                    return Err(SimpleError::new(format!(
+                        "--rate-limit must be a number of at least {}",
+                        MIN_RATE_LIMIT
+                    )));
+                }
+                Some(rate)
+            }
+            None => None,
+        };
+        Ok(Self {
+            max_retries,
+            rate_limit,
+        })
+    }
+}
+
+lazy_static! {
+    static ref POLICY: Mutex<RetryPolicy> = Mutex::new(RetryPolicy::default());
+    /// When the next request may be sent under the rate limit
+    static ref NEXT_SLOT: Mutex<Option<Instant>> = Mutex::new(None);
+}
+
+/// Selects the policy `send` follows
+pub fn install(policy: RetryPolicy) {
+    *POLICY.lock().unwrap() = policy;
+}
+
+/// Sends a request with `fixtures::send`, waiting for the rate limit and retrying it while it is
+/// throttled. Returns the last response once the retries run out, for the caller to report. Use it
+/// in place of `RequestBuilder::send`.
+pub fn send(request: RequestBuilder) -> Result<Response, SimpleError> {
+    let mut request = request;
+    let mut attempt = 0;
+    loop {
+        // a request with a streaming body can't be copied, so it is only sent once
+        let next = request.try_clone();
+        wait_for_rate_limit();
+        let response = fixtures::send(request)?;
+        let retry_after = response
+            .headers()
+            .get("Retry-After")
+            .and_then(|v| v.to_str().ok());
+        let delay = match (next, retry_delay(response.status(), retry_after, attempt)) {
+            (Some(next), Some(delay)) => {
+                request = next;
+                delay
+            }
+            _ => return Ok(response),
+        };
+        debug!(
+            "{} returned {}, retrying in {:.1}s",
+            response.url(),
+            response.status(),
+            delay.as_secs_f64()
+        );
+        if !fixtures::replaying() {
+            thread::sleep(delay);
+        }
+        attempt += 1;
+    }
+}
+
+/// The wait before retry number `attempt` (from 0) of a request that got a response with `status`
+/// and `retry_after` (its `Retry-After` header), or None if the request isn't retried: because it
+/// wasn't throttled, the policy's retries are used up, or the scan is stopping
+pub fn retry_delay(
+    status: StatusCode,
+    retry_after: Option<&str>,
+    attempt: u32,
+) -> Option<Duration> {
+    let throttled = match status {
+        StatusCode::TOO_MANY_REQUESTS
+        | StatusCode::BAD_GATEWAY
+        | StatusCode::SERVICE_UNAVAILABLE
+        | StatusCode::GATEWAY_TIMEOUT => true,
+        StatusCode::FORBIDDEN => retry_after.is_some(),
+        _ => false,
+    };
+    if !throttled || attempt >= POLICY.lock().unwrap().max_retries || shutdown::interrupted() {
+        return None;
+    }
+    Some(
+        retry_after
+            .and_then(parse_retry_after)
+            .unwrap_or_else(|| backoff(attempt)),
+    )
+}
+
+/// The backoff before retry number `attempt` (from 0) of a response without `Retry-After`: between
+/// half and all of `BASE_DELAY * 2^attempt`, capped at `MAX_DELAY`
+pub fn backoff(attempt: u32) -> Duration {
+    let ceiling = BASE_DELAY
+        .checked_mul(1 << attempt.min(16))
+        .map_or(MAX_DELAY, |d| d.min(MAX_DELAY));
+    ceiling.mul_f64(0.5 + jitter() / 2.0)
+}
+
+/// Waits for the next free slot under the rate limit of the policy, if it has one, and takes it
+pub fn wait_for_rate_limit() {
+    let rate = match POLICY.lock().unwrap().rate_limit {
+        Some(r) => r,
+        None => return,
+    };
+    let slot = {
+        let mut next_slot = NEXT_SLOT.lock().unwrap();
+        let now = Instant::now();
+        let slot = match *next_slot {
+            Some(s) if s > now => s,
+            _ => now,
+        };
+        *next_slot = Some(slot + Duration::from_secs_f64(1.0 / rate.max(MIN_RATE_LIMIT)));
+        slot
+    };
+    let now = Instant::now();
+    if slot > now {
+        thread::sleep(slot - now);
+    }
+}
+
+/// The wait a `Retry-After` header asks for, given in seconds or as an HTTP date, up to
+/// `MAX_RETRY_AFTER`. None for a value that is neither, or not a finite number of seconds.
+fn parse_retry_after(value: &str) -> Option<Duration> {
+    if let Ok(seconds) = value.trim().parse::<f64>() {
+        if !seconds.is_finite() {
+            return None;
+        }
+        let seconds = seconds.max(0.0).min(MAX_RETRY_AFTER.as_secs_f64());
+        return Some(Duration::from_secs_f64(seconds));
+    }
+    let date = DateTime::parse_from_rfc2822(value.trim()).ok()?;
+    let wait = date.with_timezone(&Utc) - Utc::now();
+    Some(wait.to_std().unwrap_or_default().min(MAX_RETRY_AFTER))
+}
+
+/// A number between 0 and 1 that varies from call to call
+fn jitter() -> f64 {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.subsec_nanos())
+        .unwrap_or_default();
+    f64::from(nanos % 1_000_000) / 1_000_000.0
+}
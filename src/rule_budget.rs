@@ -0,0 +1,161 @@
+//! Time budgets for rules.
+//!
+//! The regex crate matches in time linear in the input, so a single match attempt always ends, but
+//! a badly written rule can still take minutes on the wrong input: a pattern such as `\w+@\w+`
+//! tried at every position of a multi-megabyte minified file or a window of base64 rescans the
+//! same bytes for each match it looks for. One such rule holds up the whole scan.
+//!
+//! Each rule therefore has a budget of time it may spend matching one input (a line, a message or
+//! a window of lines), `--rule-timeout` milliseconds, 1000 by default. A rule pack can give a rule
+//! its own budget with `"timeout_ms"` (see the `rules` module). A rule that runs over its budget
+//! stops looking for further matches in that input, keeping those it already found, and the rule
+//! and the start of the input are logged as a warning. With `--disable-slow-rules` the rule is also
+//! disabled for the rest of the run, in every thread scanning with the same `SecretScanner`.
+//!
+//! # Examples
+//!
+//! ```
+//! use rusty_hogs::SecretScannerBuilder;
+//! use std::time::Duration;
+//!
+//! let ss = SecretScannerBuilder::new()
+//!     .set_json_str(r##"{ "Word pair": "\\w+@\\w+", "Slack Token": "xox[pboa]-[0-9]{12}" }"##)
+//!     .set_rule_timeout(Some(Duration::from_millis(0)))
+//!     .set_disable_slow_rules(true)
+//!     .build();
+//! let input = "word ".repeat(10_000);
+//! for (_, matches) in ss.matches(input.as_bytes()) {
+//!     assert_eq!(matches.count(), 0);
+//! }
+//! let slow_rules = ss.rule_budget.slow_rules();
+//! assert_eq!(slow_rules.len(), 2);
+//! assert_eq!(slow_rules[1].rule, "Word pair");
+//! assert_eq!(slow_rules[1].input_length, 50_000);
+//! assert!(slow_rules[1].disabled);
+//! assert!(ss.rule_budget.is_disabled("Word pair"));
+//! assert_eq!(ss.matches(b"a@b").len(), 0);
+//! ```
+
+use log::{self, debug, warn};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// The default budget of a rule on one input, in milliseconds (`--rule-timeout`)
+pub const DEFAULT_RULE_TIMEOUT_MS: u64 = 1000;
+
+/// How much of an input is reported with a rule that ran over its budget on it
+const PREVIEW_LENGTH: usize = 80;
+
+/// A rule that ran over its budget, on the first input it did
+#[derive(Serialize, Debug, PartialEq, Eq, Hash, Clone)]
+pub struct SlowRule {
+    pub rule: String,
+    #[serde(rename = "budgetMs")]
+    pub budget_ms: u64,
+    /// The time the rule took on the input before it was stopped
+    #[serde(rename = "elapsedMs")]
+    pub elapsed_ms: u64,
+    /// The start of the input, lossily decoded as UTF-8
+    pub input: String,
+    #[serde(rename = "inputLength")]
+    pub input_length: usize,
+    /// The number of inputs the rule ran over its budget on
+    pub overruns: usize,
+    /// The rule was disabled for the rest of the run
+    pub disabled: bool,
+}
+
+/// The time budget of the rules of a `SecretScanner`, and the rules that ran over it (see the
+/// module documentation). Clones share the rules that ran over their budget, so that a rule
+/// disabled in one thread is disabled in all of them.
+#[derive(Debug, Clone)]
+pub struct RuleBudget {
+    /// The time a rule may spend matching one input, None for no limit
+    pub timeout: Option<Duration>,
+    /// Disable a rule for the rest of the run once it runs over its budget
+    pub disable_slow: bool,
+    /// Set once a rule is disabled, so that scans don't lock `slow` to check each rule
+    any_disabled: Arc<AtomicBool>,
+    slow: Arc<Mutex<BTreeMap<String, SlowRule>>>,
+}
+
+impl Default for RuleBudget {
+    fn default() -> Self {
+        Self::new(Some(Duration::from_millis(DEFAULT_RULE_TIMEOUT_MS)), false)
+    }
+}
+
+impl RuleBudget {
+    /// A budget no rule has run over yet
+    pub fn new(timeout: Option<Duration>, disable_slow: bool) -> Self {
+        Self {
+            timeout,
+            disable_slow,
+            any_disabled: Arc::new(AtomicBool::new(false)),
+            slow: Arc::new(Mutex::new(BTreeMap::new())),
+        }
+    }
+
+    /// True if `rule` has been disabled for running over its budget
+    pub fn is_disabled(&self, rule: &str) -> bool {
+        if !self.any_disabled.load(Ordering::Relaxed) {
+            return false;
+        }
+        match self.slow.lock().unwrap().get(rule) {
+            Some(slow_rule) => slow_rule.disabled,
+            None => false,
+        }
+    }
+
+    /// Records that `rule` took `elapsed` matching `input`, more than its budget of `timeout`, and
+    /// disables it if `disable_slow` is set. Only the first overrun of each rule is logged as a
+    /// warning.
+    pub fn overrun(&self, rule: &str, elapsed: Duration, timeout: Duration, input: &[u8]) {
+        let mut slow = self.slow.lock().unwrap();
+        if let Some(slow_rule) = slow.get_mut(rule) {
+            slow_rule.overruns += 1;
+            debug!(
+                "Rule {:?} ran over its budget of {} ms again, on {} bytes",
+                rule,
+                timeout.as_millis(),
+                input.len()
+            );
+            return;
+        }
+        let preview = &input[..input.len().min(PREVIEW_LENGTH)];
+        let slow_rule = SlowRule {
+            rule: String::from(rule),
+            budget_ms: timeout.as_millis() as u64,
+            elapsed_ms: elapsed.as_millis() as u64,
+            input: String::from_utf8_lossy(preview).into_owned(),
+            input_length: input.len(),
+            overruns: 1,
+            disabled: self.disable_slow,
+        };
+        warn!(
+            "Rule {:?} took {} ms, more than its budget of {} ms, on {} bytes starting {:?}; {}",
+            rule,
+            slow_rule.elapsed_ms,
+            slow_rule.budget_ms,
+            slow_rule.input_length,
+            slow_rule.input,
+            if self.disable_slow {
+                "disabling it for the rest of the run"
+            } else {
+                "skipping its remaining matches in this input"
+            }
+        );
+        slow.insert(String::from(rule), slow_rule);
+        if self.disable_slow {
+            self.any_disabled.store(true, Ordering::Relaxed);
+        }
+    }
+
+    /// The rules that ran over their budget so far, by name
+    pub fn slow_rules(&self) -> Vec<SlowRule> {
+        self.slow.lock().unwrap().values().cloned().collect()
+    }
+}
@@ -0,0 +1,171 @@
+//! Converting the rule files of other secret scanners to rule packs.
+//!
+//! `--regex` (see `RulePack::from_file`) reads these formats by their file extension, so one rule
+//! set maintained for another scanner can be used as is:
+//! - `.toml`: a [gitleaks](https://github.com/gitleaks/gitleaks) configuration. Each of its
+//!   `[[rules]]` becomes a rule named after its `id`, with its `regex`, `keywords` and
+//!   `description`, and an `entropy` threshold becomes the `normalized` entropy mode (see the
+//!   `entropy` module). Rules without a `regex` (path only rules), `secretGroup` and the allowlists
+//!   of the configuration have no equivalent and are left out.
+//! - `.yaml` and `.yml`: [trufflehog](https://github.com/trufflesecurity/trufflehog) custom
+//!   detectors. Each detector becomes a rule named after it, with its `keywords`, or one rule per
+//!   regex (`Name (regex name)`) when it has several. Verification endpoints are left out.
+//! - `.json`: rule packs, including the flat `{ "Rule name": "regex" }` files of trufflehog 2.
+//!
+//! The `convert_rules` binary writes the rule pack JSON of a converted file, to review it or keep
+//! maintaining it in this format.
+//!
+//! # Examples
+//!
+//! ```
+//! use rusty_hogs::rule_formats::from_gitleaks;
+//! use rusty_hogs::rules::RulePack;
+//!
+//! let config = r#"
+//! title = "org rules"
+//!
+//! [[rules]]
+//! id = "internal-token"
+//! description = "Internal API token"
+//! regex = '''itk_[0-9a-f]{32}'''
+//! keywords = ["itk_"]
+//! entropy = 3.5
+//! "#;
+//! let pack = from_gitleaks(config).unwrap();
+//! assert_eq!(pack["rules"]["internal-token"]["pattern"], "itk_[0-9a-f]{32}");
+//! assert_eq!(pack["rules"]["internal-token"]["keywords"][0], "itk_");
+//!
+//! let pack = RulePack::from_object(pack.as_object().unwrap(), "gitleaks").unwrap();
+//! assert_eq!(pack.name, "org rules");
+//! assert_eq!(pack.keywords["internal-token"], vec!["itk_"]);
+//! ```
+
+use log::{self, info, warn};
+use serde_json::{json, Map, Value};
+use simple_error::{require_with, try_with, SimpleError};
+
+/// The entropy mode given to rules with an entropy threshold
+const ENTROPY_HINT_MODE: &str = "normalized";
+
+/// Converts a gitleaks TOML configuration to rule pack JSON, see the module documentation
+pub fn from_gitleaks(toml_str: &str) -> Result<Value, SimpleError> {
+    let config: Value = try_with!(
+        toml::from_str(toml_str),
+        "Failed to parse the gitleaks configuration"
+    );
+    let mut rules: Map<String, Value> = Map::new();
+    let config_rules = config["rules"].as_array().cloned().unwrap_or_default();
+    for rule in &config_rules {
+        let id = require_with!(
+            rule["id"].as_str(),
+            "A rule of the gitleaks configuration has no id"
+        );
+        let regex = match rule["regex"].as_str() {
+            Some(r) => r,
+            None => {
+                warn!("Gitleaks rule {:?} has no regex, leaving it out", id);
+                continue;
+            }
+        };
+        if rule.get("allowlist").is_some() || rule.get("allowlists").is_some() {
+            info!("Leaving out the allowlist of gitleaks rule {:?}", id);
+        }
+        let mut converted = json!({ "pattern": unescape_slashes(regex) });
+        copy_keywords(rule, &mut converted);
+        if let Some(description) = rule["description"].as_str() {
+            converted["description"] = Value::from(description);
+        }
+        if rule["entropy"].is_number() {
+            converted["entropy"] = Value::from(ENTROPY_HINT_MODE);
+        }
+        rules.insert(String::from(id), converted);
+    }
+    if config.get("allowlist").is_some() {
+        info!("Leaving out the allowlist of the gitleaks configuration");
+    }
+    let mut pack = json!({ "rules": rules });
+    if let Some(title) = config["title"].as_str() {
+        pack["pack"] = Value::from(title);
+    }
+    Ok(pack)
+}
+
+/// Converts trufflehog custom detectors (YAML) to rule pack JSON, see the module documentation
+pub fn from_trufflehog(yaml_str: &str) -> Result<Value, SimpleError> {
+    let config: Value = try_with!(
+        serde_yaml::from_str(yaml_str),
+        "Failed to parse the trufflehog detectors"
+    );
+    let detectors = require_with!(
+        config["detectors"].as_array(),
+        "The trufflehog configuration has no detectors"
+    );
+    let mut rules: Map<String, Value> = Map::new();
+    for detector in detectors {
+        let name = require_with!(
+            detector["name"].as_str(),
+            "A trufflehog detector has no name"
+        );
+        let regexes = match detector["regex"].as_object() {
+            Some(r) if !r.is_empty() => r,
+            _ => {
+                warn!(
+                    "Trufflehog detector {:?} has no regex, leaving it out",
+                    name
+                );
+                continue;
+            }
+        };
+        for (regex_name, regex) in regexes {
+            let regex = require_with!(
+                regex.as_str(),
+                "Regex {} of trufflehog detector {} is not a string",
+                regex_name,
+                name
+            );
+            let rule_name = if regexes.len() == 1 {
+                String::from(name)
+            } else {
+                format!("{} ({})", name, regex_name)
+            };
+            let mut converted = json!({ "pattern": unescape_slashes(regex) });
+            copy_keywords(detector, &mut converted);
+            if detector["entropy"].is_number() {
+                converted["entropy"] = Value::from(ENTROPY_HINT_MODE);
+            }
+            rules.insert(rule_name, converted);
+        }
+    }
+    Ok(json!({ "rules": rules }))
+}
+
+/// Copies the `keywords` of a converted rule
+fn copy_keywords(rule: &Value, converted: &mut Value) {
+    if let Some(keywords) = rule["keywords"].as_array() {
+        let keywords: Vec<&str> = keywords.iter().filter_map(Value::as_str).collect();
+        if !keywords.is_empty() {
+            converted["keywords"] = Value::from(keywords);
+        }
+    }
+}
+
+/// Go regexes escape `/`, which not every version of the regex crate accepts
+fn unescape_slashes(regex: &str) -> String {
+    let mut unescaped = String::with_capacity(regex.len());
+    let mut chars = regex.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('/') => unescaped.push('/'),
+            Some(next) => {
+                unescaped.push(c);
+                unescaped.push(next);
+            }
+            None => unescaped.push(c),
+        }
+    }
+    unescaped
+}
@@ -0,0 +1,559 @@
+//! Checking rule files and allowlists without scanning.
+//!
+//! Loading rules is lenient so that one mistake doesn't stop a scan: a rule pack that can't be
+//! parsed is replaced by the built-in rules, and a rule without a pattern or with a pattern that
+//! doesn't compile is skipped, each with an error in the log of the scan. `validate_rules`
+//! (Choctaw Hog's `--validate-rules`) checks rule files up front instead, reporting
+//!
+//! * as errors: files that can't be read or parsed, rules that don't follow the format of the
+//!   `rules` module, fields of the wrong type or with an unknown severity or entropy mode, and
+//!   patterns that don't compile, with the line and column of the mistake in the pattern
+//! * as warnings: unknown fields, which are ignored; patterns that match the empty string, and so
+//!   every line; rules with the same pattern as another rule; rules defined in more than one file;
+//!   examples that another rule also matches; and `disable` entries that match no rule
+//!
+//! `validate_allowlist` checks an allowlist file, and warns about the entries for rules that
+//! aren't loaded, which never apply.
+//!
+//! # Examples
+//!
+//! ```
+//! use rusty_hogs::rule_validation::{validate_rules, Level};
+//! use std::io::Write;
+//! use tempdir::TempDir;
+//!
+//! let dir = TempDir::new("rules").unwrap();
+//! let path = dir.path().join("team.json");
+//! let mut file = std::fs::File::create(&path).unwrap();
+//! file.write_all(
+//!     br##"{ "pack": "team", "rules": {
+//!         "Internal token": { "pattern": "itk_([0-9a-f]{32}", "severity": "urgent" },
+//!         "Legacy token": "tkt-[0-9]*",
+//!         "Ticket token": { "keywords": "tkt-", "pattern": "tkt-[0-9]*" }
+//!     } }"##,
+//! )
+//! .unwrap();
+//! let paths = vec![path.to_str().unwrap().to_string()];
+//!
+//! let diagnostics = validate_rules(&paths, false);
+//! let messages: Vec<String> = diagnostics.iter().map(|d| d.message.clone()).collect();
+//! assert_eq!(
+//!     messages,
+//!     vec![
+//!         "Unknown severity \"urgent\", expected info, low, medium, high or critical",
+//!         "unclosed group",
+//!         "keywords must be a list of strings",
+//!         "same pattern as rule \"Legacy token\"",
+//!     ]
+//! );
+//! assert_eq!(diagnostics[1].rule.as_deref(), Some("Internal token"));
+//! assert_eq!((diagnostics[1].line, diagnostics[1].column), (Some(1), Some(5)));
+//! assert_eq!(diagnostics[3].level, Level::Warning);
+//! assert!(validate_rules(&[String::from("builtin")], false)
+//!     .iter()
+//!     .all(|d| d.level == Level::Warning));
+//! ```
+
+use crate::allowlist::{self, Allowlist};
+use crate::entropy::EntropyMode;
+use crate::rule_formats;
+use crate::rules::{RuleFlags, Severity, BUILTIN_PACK_NAME};
+use crate::SecretScanner;
+use regex::bytes::{Regex, RegexBuilder};
+use regex_syntax::ParserBuilder;
+use serde_json::{Map, Value};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+/// The reason of entropy findings, which an allowlist can have entries for without a rule
+const ENTROPY_REASON: &str = "Entropy";
+
+/// The fields of a rule pack besides its rules, with the type of each
+const PACK_FIELDS: &[(&str, FieldType)] = &[
+    ("pack", FieldType::String),
+    ("name", FieldType::String),
+    ("version", FieldType::String),
+    ("priority", FieldType::Integer),
+    ("enabled", FieldType::Bool),
+    ("disable", FieldType::Strings),
+];
+
+/// The fields of a rule object, with the type of each
+const RULE_FIELDS: &[(&str, FieldType)] = &[
+    ("pattern", FieldType::String),
+    ("enabled", FieldType::Bool),
+    ("severity", FieldType::String),
+    ("entropy", FieldType::String),
+    ("keywords", FieldType::Strings),
+    ("multiline", FieldType::Bool),
+    ("timeout_ms", FieldType::Milliseconds),
+    ("case_insensitive", FieldType::Bool),
+    ("dot_all", FieldType::Bool),
+    ("unicode", FieldType::Bool),
+    ("examples", FieldType::Strings),
+    ("counter_examples", FieldType::Strings),
+    ("description", FieldType::String),
+    ("remediation", FieldType::String),
+    ("reference_url", FieldType::String),
+];
+
+/// The JSON type of a field of a rule pack or rule
+#[derive(Debug, Clone, Copy)]
+enum FieldType {
+    String,
+    Bool,
+    Integer,
+    Milliseconds,
+    Strings,
+}
+
+impl FieldType {
+    fn accepts(self, value: &Value) -> bool {
+        match self {
+            FieldType::String => value.is_string(),
+            FieldType::Bool => value.is_boolean(),
+            FieldType::Integer => value.is_i64(),
+            FieldType::Milliseconds => value.is_u64(),
+            FieldType::Strings => value
+                .as_array()
+                .map_or(false, |a| a.iter().all(Value::is_string)),
+        }
+    }
+}
+
+impl fmt::Display for FieldType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            FieldType::String => "a string",
+            FieldType::Bool => "true or false",
+            FieldType::Integer => "an integer",
+            FieldType::Milliseconds => "a number of milliseconds",
+            FieldType::Strings => "a list of strings",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+/// Whether a `Diagnostic` keeps the rules from loading as written
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy, PartialOrd, Ord)]
+pub enum Level {
+    Warning,
+    Error,
+}
+
+impl fmt::Display for Level {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Level::Warning => write!(f, "warning"),
+            Level::Error => write!(f, "error"),
+        }
+    }
+}
+
+/// A problem found in a rule file or allowlist
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+pub struct Diagnostic {
+    pub level: Level,
+    pub file: String,
+    /// The rule the problem is in, None for the file as a whole
+    pub rule: Option<String>,
+    /// The pattern the problem is in
+    pub pattern: Option<String>,
+    /// The line and column, from 1, of the mistake in `pattern`, for patterns that don't compile
+    pub line: Option<usize>,
+    pub column: Option<usize>,
+    pub message: String,
+}
+
+impl Diagnostic {
+    fn new(level: Level, file: &str, rule: Option<&str>, message: String) -> Self {
+        Self {
+            level,
+            file: String::from(file),
+            rule: rule.map(String::from),
+            pattern: None,
+            line: None,
+            column: None,
+            message,
+        }
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.level, self.file)?;
+        if let Some(rule) = &self.rule {
+            write!(f, ": rule {:?}", rule)?;
+        }
+        if let Some(pattern) = &self.pattern {
+            write!(f, ": pattern {:?}", pattern)?;
+            match (self.line, self.column) {
+                (Some(1), Some(column)) => write!(f, " at column {}", column)?,
+                (Some(line), Some(column)) => write!(f, " at line {}, column {}", line, column)?,
+                _ => (),
+            }
+        }
+        write!(f, ": {}", self.message)
+    }
+}
+
+/// A rule read from a rule file, for the checks across rules
+struct LoadedRule {
+    file: String,
+    name: String,
+    pattern: String,
+    regex: Option<Regex>,
+    examples: Vec<String>,
+}
+
+/// The state of `validate_rules`
+struct Validator {
+    case_insensitive: bool,
+    diagnostics: Vec<Diagnostic>,
+    rules: Vec<LoadedRule>,
+    pack_names: BTreeSet<String>,
+    /// The `disable` entries of each file
+    disables: Vec<(String, String)>,
+}
+
+/// Checks the rule files at `paths` the way `SecretScannerBuilder` loads them, "builtin" for the
+/// built-in rules (the default when `paths` is empty), returning the problems found in the order
+/// of the files. Patterns are compiled ignoring case if `case_insensitive` is set
+/// (`--caseinsensitive`).
+pub fn validate_rules(paths: &[String], case_insensitive: bool) -> Vec<Diagnostic> {
+    let mut validator = Validator {
+        case_insensitive,
+        diagnostics: Vec::new(),
+        rules: Vec::new(),
+        pack_names: BTreeSet::new(),
+        disables: Vec::new(),
+    };
+    if paths.is_empty() {
+        validator.check_file(BUILTIN_PACK_NAME);
+    }
+    for path in paths {
+        validator.check_file(path);
+    }
+    validator.check_rules();
+    validator.diagnostics
+}
+
+/// Checks the allowlist file at `path`, and warns about its entries for rules `scanner` doesn't
+/// have
+pub fn validate_allowlist(path: &str, scanner: &SecretScanner) -> Vec<Diagnostic> {
+    match Allowlist::from_file(path) {
+        Ok(a) => a
+            .rules
+            .keys()
+            .filter(|rule| {
+                rule.as_str() != allowlist::GLOBAL
+                    && rule.as_str() != ENTROPY_REASON
+                    && !scanner.regex_map.contains_key(*rule)
+            })
+            .map(|rule| {
+                Diagnostic::new(
+                    Level::Warning,
+                    path,
+                    Some(rule.as_str()),
+                    String::from("not a loaded rule, so its allowlist entries never apply"),
+                )
+            })
+            .collect(),
+        Err(e) => vec![Diagnostic::new(Level::Error, path, None, e.to_string())],
+    }
+}
+
+impl Validator {
+    fn error(&mut self, file: &str, rule: Option<&str>, message: String) {
+        self.diagnostics
+            .push(Diagnostic::new(Level::Error, file, rule, message));
+    }
+
+    fn warning(&mut self, file: &str, rule: Option<&str>, message: String) {
+        self.diagnostics
+            .push(Diagnostic::new(Level::Warning, file, rule, message));
+    }
+
+    /// Reads, parses and checks a rule file, converting gitleaks and trufflehog files like
+    /// `RulePack::from_file`
+    fn check_file(&mut self, path: &str) {
+        let data = if path == BUILTIN_PACK_NAME {
+            String::from(crate::DEFAULT_REGEX_JSON)
+        } else {
+            match fs::read_to_string(path) {
+                Ok(d) => d,
+                Err(e) => return self.error(path, None, format!("failed to read the file: {}", e)),
+            }
+        };
+        let parsed = match Path::new(path).extension().and_then(|e| e.to_str()) {
+            Some("toml") => rule_formats::from_gitleaks(&data).map_err(|e| e.to_string()),
+            Some("yaml") | Some("yml") => {
+                rule_formats::from_trufflehog(&data).map_err(|e| e.to_string())
+            }
+            _ => serde_json::from_str::<Value>(&data)
+                .map_err(|e| format!("failed to parse the JSON: {}", e)),
+        };
+        let value = match parsed {
+            Ok(v) => v,
+            Err(e) => return self.error(path, None, e),
+        };
+        match value.as_object() {
+            Some(obj) => self.check_pack(path, obj),
+            None => self.error(
+                path,
+                None,
+                String::from("expected a JSON object of rules or a rule pack"),
+            ),
+        }
+    }
+
+    /// Checks a rule pack, or an object of rules in the flat format
+    fn check_pack(&mut self, file: &str, obj: &Map<String, Value>) {
+        let default_name = Path::new(file)
+            .file_stem()
+            .map_or_else(|| String::from(file), |s| s.to_string_lossy().to_string());
+        let rules = match obj.get("rules") {
+            Some(Value::Object(rules)) => rules,
+            Some(_) => {
+                return self.error(
+                    file,
+                    None,
+                    String::from("rules must be an object of rule names to rules"),
+                )
+            }
+            None => {
+                self.pack_names.insert(default_name);
+                self.check_rules_of(file, obj);
+                return;
+            }
+        };
+        for (field, value) in obj {
+            if field == "rules" {
+                continue;
+            }
+            match PACK_FIELDS.iter().find(|(name, _)| *name == field.as_str()) {
+                Some((_, field_type)) if !field_type.accepts(value) => {
+                    self.error(file, None, format!("{} must be {}", field, field_type))
+                }
+                Some(_) => (),
+                None => self.warning(
+                    file,
+                    None,
+                    format!("unknown pack field {:?} is ignored", field),
+                ),
+            }
+        }
+        let name = obj
+            .get("pack")
+            .or_else(|| obj.get("name"))
+            .and_then(Value::as_str)
+            .map_or(default_name, String::from);
+        self.pack_names.insert(name);
+        if let Some(Value::Array(disables)) = obj.get("disable") {
+            for entry in disables.iter().filter_map(Value::as_str) {
+                self.disables
+                    .push((String::from(file), String::from(entry)));
+            }
+        }
+        self.check_rules_of(file, rules);
+    }
+
+    fn check_rules_of(&mut self, file: &str, rules: &Map<String, Value>) {
+        if rules.is_empty() {
+            self.warning(file, None, String::from("no rules"));
+        }
+        for (name, value) in rules {
+            self.check_rule(file, name, value);
+        }
+    }
+
+    /// Checks the fields of a rule and compiles its pattern
+    fn check_rule(&mut self, file: &str, name: &str, value: &Value) {
+        let rule = Some(name);
+        let (pattern, examples, flags) = match value {
+            Value::String(pattern) => (pattern, Vec::new(), RuleFlags::default()),
+            Value::Object(o) => {
+                for (field, value) in o {
+                    let field_type = match RULE_FIELDS.iter().find(|(f, _)| *f == field.as_str()) {
+                        Some((_, field_type)) => field_type,
+                        None => {
+                            self.warning(
+                                file,
+                                rule,
+                                format!("unknown field {:?} is ignored", field),
+                            );
+                            continue;
+                        }
+                    };
+                    if !field_type.accepts(value) {
+                        self.error(file, rule, format!("{} must be {}", field, field_type));
+                        continue;
+                    }
+                    let parsed = match field.as_str() {
+                        "severity" => value.as_str().unwrap().parse::<Severity>().map(|_| ()),
+                        "entropy" => value.as_str().unwrap().parse::<EntropyMode>().map(|_| ()),
+                        _ => Ok(()),
+                    };
+                    if let Err(e) = parsed {
+                        self.error(file, rule, e.to_string());
+                    }
+                }
+                let pattern = match o.get("pattern") {
+                    Some(Value::String(pattern)) => pattern,
+                    Some(_) => return,
+                    None => return self.error(file, rule, String::from("no pattern")),
+                };
+                let examples = match o.get("examples").and_then(Value::as_array) {
+                    Some(a) => a
+                        .iter()
+                        .filter_map(Value::as_str)
+                        .map(String::from)
+                        .collect(),
+                    None => Vec::new(),
+                };
+                // flags that aren't booleans were reported with the other fields
+                let flags = RuleFlags::from_rule(o).unwrap_or_default();
+                (pattern, examples, flags)
+            }
+            _ => {
+                return self.error(
+                    file,
+                    rule,
+                    String::from("a rule must be a pattern or an object with a pattern"),
+                )
+            }
+        };
+        let regex = self.compile(file, name, pattern, flags);
+        self.rules.push(LoadedRule {
+            file: String::from(file),
+            name: String::from(name),
+            pattern: pattern.clone(),
+            regex,
+            examples,
+        });
+    }
+
+    /// Compiles a pattern the way `SecretScannerBuilder` does, reporting where a pattern that
+    /// doesn't compile goes wrong, and warning about patterns that match the empty string
+    fn compile(
+        &mut self,
+        file: &str,
+        name: &str,
+        pattern: &str,
+        flags: RuleFlags,
+    ) -> Option<Regex> {
+        if pattern.is_empty() {
+            self.error(
+                file,
+                Some(name),
+                String::from("empty pattern, which matches every line"),
+            );
+            return None;
+        }
+        let mut regex_builder = RegexBuilder::new(pattern);
+        regex_builder.size_limit(10_000_000);
+        flags.configure(&mut regex_builder, self.case_insensitive);
+        let error = match regex_builder.build() {
+            Ok(regex) => {
+                if regex.is_match(b"") {
+                    let mut diagnostic = Diagnostic::new(
+                        Level::Warning,
+                        file,
+                        Some(name),
+                        String::from("matches the empty string, so it is found on every line"),
+                    );
+                    diagnostic.pattern = Some(String::from(pattern));
+                    self.diagnostics.push(diagnostic);
+                }
+                return Some(regex);
+            }
+            Err(e) => e,
+        };
+        let mut diagnostic = Diagnostic::new(Level::Error, file, Some(name), error.to_string());
+        diagnostic.pattern = Some(String::from(pattern));
+        // the regex crate's errors only have the position drawn under the pattern
+        let parsed = ParserBuilder::new()
+            .allow_invalid_utf8(true)
+            .case_insensitive(flags.case_insensitive.unwrap_or(self.case_insensitive))
+            .dot_matches_new_line(flags.dot_all.unwrap_or(false))
+            .unicode(flags.unicode.unwrap_or(true))
+            .build()
+            .parse(pattern);
+        let located = match &parsed {
+            Err(regex_syntax::Error::Parse(e)) => Some((e.kind().to_string(), e.span().start)),
+            Err(regex_syntax::Error::Translate(e)) => Some((e.kind().to_string(), e.span().start)),
+            _ => None,
+        };
+        if let Some((message, position)) = located {
+            diagnostic.message = message;
+            diagnostic.line = Some(position.line);
+            diagnostic.column = Some(position.column);
+        }
+        self.diagnostics.push(diagnostic);
+        None
+    }
+
+    /// The checks across the rules of all the files
+    fn check_rules(&mut self) {
+        let mut diagnostics: Vec<Diagnostic> = Vec::new();
+        let mut first_files: BTreeMap<&str, &str> = BTreeMap::new();
+        let mut first_names: BTreeMap<&str, &str> = BTreeMap::new();
+        for rule in &self.rules {
+            let warning = |message: String| {
+                Diagnostic::new(Level::Warning, &rule.file, Some(&rule.name[..]), message)
+            };
+            match first_files.get(rule.name.as_str()) {
+                Some(first_file) if *first_file != rule.file => diagnostics.push(warning(format!(
+                    "also defined in {}, and the pack with the higher priority, or else the \
+                     later one, overrides the other",
+                    first_file
+                ))),
+                Some(_) => (),
+                None => {
+                    first_files.insert(&rule.name, &rule.file);
+                }
+            }
+            match first_names.get(rule.pattern.as_str()) {
+                Some(first_name) if *first_name != rule.name => {
+                    diagnostics.push(warning(format!("same pattern as rule {:?}", first_name)))
+                }
+                Some(_) => (),
+                None => {
+                    first_names.insert(&rule.pattern, &rule.name);
+                }
+            }
+            for example in &rule.examples {
+                let others = self.rules.iter().filter(|other| other.name != rule.name);
+                for other in others {
+                    let matched = match &other.regex {
+                        Some(regex) => regex.is_match(example.as_bytes()),
+                        None => false,
+                    };
+                    if matched {
+                        diagnostics.push(warning(format!(
+                            "example {:?} is also matched by rule {:?}",
+                            example, other.name
+                        )));
+                    }
+                }
+            }
+        }
+        for (file, entry) in &self.disables {
+            let rule_name = match entry.find(':') {
+                Some(i) if self.pack_names.contains(&entry[..i]) => &entry[i + 1..],
+                _ => entry.as_str(),
+            };
+            if !self.rules.iter().any(|r| r.name == rule_name) {
+                diagnostics.push(Diagnostic::new(
+                    Level::Warning,
+                    file,
+                    None,
+                    format!("disable entry {:?} matches no rule", entry),
+                ));
+            }
+        }
+        self.diagnostics.extend(diagnostics);
+    }
+}
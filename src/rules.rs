@@ -0,0 +1,973 @@
+//! Rule packs: loading several regex JSON files at once.
+//!
+//! Each `--regex` file is loaded as a rule pack. A pack is either the original flat format
+//! `{ "Rule name" : "Regular expression", ... }`, or an object with pack metadata:
+//!
+//! ```json
+//! {
+//!   "pack": "team",
+//!   "version": "1.4.0",
+//!   "priority": 10,
+//!   "enabled": true,
+//!   "rules": {
+//!     "Internal token": "itk_[0-9a-f]{32}",
+//!     "Legacy key": { "pattern": "lk-[0-9]{12}", "enabled": false, "severity": "low" },
+//!     "Session secret": { "pattern": "session_secret=[0-9a-f]{32}", "entropy": "hex" },
+//!     "Deploy key": { "pattern": "dk_[A-Za-z0-9]{40}", "keywords": ["dk_"] },
+//!     "Connection string": { "pattern": "[a-z]+://[^:\\s]+:[^@\\s]+@", "timeout_ms": 250 },
+//!     "Partner key": { "pattern": "PK[0-9A-Z]{30}", "case_insensitive": false },
+//!     "Signing key": {
+//!       "pattern": "-----BEGIN SIGNING KEY-----[\\s\\S]*?-----END SIGNING KEY-----",
+//!       "multiline": true,
+//!       "entropy": "base64"
+//!     },
+//!     "Billing API key": {
+//!       "pattern": "bk_live_[0-9a-z]{24}",
+//!       "description": "A live key of the billing API, which can issue refunds",
+//!       "remediation": "Roll the key in the billing console, then update the vault entry",
+//!       "reference_url": "https://wiki.example.com/billing/keys"
+//!     },
+//!     "Ticket token": {
+//!       "pattern": "tkt-[0-9]{8}",
+//!       "examples": ["token: tkt-20210301"],
+//!       "counter_examples": ["tkt-2021"]
+//!     }
+//!   },
+//!   "disable": ["builtin:Email address"]
+//! }
+//! ```
+//!
+//! The pack name defaults to the file stem (the built-in rules are named `builtin`). Packs are
+//! merged in order of priority (0 by default), and packs with the same priority in the order they
+//! were given, so a later pack overrides a rule with the same name from an earlier one. Disabled
+//! packs are skipped entirely. The `disable` list of an enabled pack removes rules from any pack,
+//! either by plain name or qualified as `pack:Rule name`.
+//!
+//! A rule with an `entropy` mode only reports matches that look random in that mode (see the
+//! `entropy` module), to keep rules for short or generic tokens from matching placeholders. A rule
+//! with `keywords` is only tried on lines that contain one of them, ignoring case, which saves
+//! running expensive patterns on every line. `timeout_ms` sets the time budget of a rule on one
+//! input instead of `--rule-timeout` (see the `rule_budget` module).
+//!
+//! `case_insensitive`, `dot_all` and `unicode` set the regex flags of a single rule (see
+//! `RuleFlags`). `"case_insensitive": false` keeps a rule that depends on the case of its prefix,
+//! such as `AKIA` for AWS access keys, matching exactly when `--caseinsensitive` turns on ignoring
+//! case for the other rules, and `true` ignores case in a rule whatever the option says.
+//! `dot_all` lets `.` match line breaks, for multiline rules, and `"unicode": false` makes `.` and
+//! classes such as `\w` match single bytes rather than UTF-8 characters. Inline flags such as
+//! `(?i)` in the pattern still take precedence.
+//!
+//! Rules are matched against one line at a time, so a pattern for a PEM private key or certificate
+//! only ever sees its BEGIN line. A `multiline` rule is run against a window of many lines instead
+//! (see `chunked_scanning::MultilineWindow`), so that it can capture the whole block, and its
+//! entropy mode is checked against the body of the block, without the BEGIN and END lines,
+//! headers and line breaks. Scanners that match a line or message at a time still try multiline
+//! rules on each line alone.
+//!
+//! `description`, `remediation` and `reference_url` tell whoever gets a finding what the credential
+//! is and how to rotate it. They are added to the rule's findings as `guidance` in the JSON format,
+//! and to the descriptions of the SonarQube, GitLab, OCSF and DefectDojo formats.
+//!
+//! `examples` and `counter_examples` are lines a rule must and must not match. `test_rules` (Choctaw
+//! Hog's `--test-rules`) checks every rule that has them against the scanner built from the packs,
+//! so a change to a pattern, its keywords or its entropy mode that breaks a rule fails the check.
+//!
+//! Gitleaks (`.toml`) and trufflehog (`.yaml`) rule files are converted when loaded, see the
+//! `rule_formats` module.
+//!
+//! `RuleOverrides` (`--enable-rule`, `--disable-rule` and `--rule-severity`) are applied last, so
+//! a single run can turn a disabled rule back on, turn a rule off, or change the severity that
+//! the SonarQube, GitLab and OCSF report formats give its findings.
+//!
+//! Each `SecretScanner` records the packs it was built from in `rules_version`, e.g.
+//! `builtin@1.0.1+team@1.4.0`. Packs without a `version` are identified by a hash of their rules.
+//! Long-running callers (`hogd`, `paste_hog` and `berkshire_hog --sqs-queue`) use a `RuleWatcher`
+//! to rebuild the scanner when a rule file or the `--allowlist` file changes; scans already
+//! holding the previous scanner are not affected. Their findings have the `rules_version` of the
+//! scanner that found them.
+//!
+//! # Examples
+//!
+//! ```
+//! use rusty_hogs::rules::{
+//!     merge_rule_packs, merge_with_overrides, test_rules, RuleOverrides, RulePack, Severity,
+//! };
+//! use rusty_hogs::SecretScannerBuilder;
+//!
+//! let builtin = RulePack::builtin();
+//! let team = RulePack::from_str(
+//!     r##"{ "pack": "team", "rules": { "Internal token": "itk_[0-9a-f]{32}" },
+//!           "disable": ["builtin:Email address"] }"##,
+//!     "custom",
+//! )
+//! .unwrap();
+//! let rules = merge_rule_packs(vec![builtin, team]);
+//! assert!(rules.contains_key("Internal token"));
+//! assert!(rules.contains_key("Slack Token"));
+//! assert!(!rules.contains_key("Email address"));
+//!
+//! let mut overrides = RuleOverrides {
+//!     enable: vec![String::from("Email address")],
+//!     disable: vec![String::from("Slack Token")],
+//!     ..Default::default()
+//! };
+//! overrides.add_severity("Email address=low").unwrap();
+//! let merged = merge_with_overrides(vec![RulePack::builtin()], &overrides);
+//! assert!(merged.patterns.contains_key("Email address"));
+//! assert!(!merged.patterns.contains_key("Slack Token"));
+//! assert_eq!(merged.severities["Email address"], Severity::Low);
+//!
+//! let scanner = SecretScannerBuilder::new()
+//!     .set_json_str(
+//!         r##"{ "rules": { "Ticket token": {
+//!             "pattern": "tkt-[0-9]{8}",
+//!             "examples": ["token: tkt-20210301", "tkt-2021"],
+//!             "counter_examples": ["tkt-2021"]
+//!         } } }"##,
+//!     )
+//!     .build();
+//! let failures = test_rules(&scanner);
+//! assert_eq!(failures.len(), 1);
+//! assert_eq!(failures[0].sample, "tkt-2021");
+//! assert!(failures[0].expected_match);
+//!
+//! let scanner = SecretScannerBuilder::new()
+//!     .set_json_str(
+//!         r##"{ "rules": { "Billing API key": {
+//!             "pattern": "bk_live_[0-9a-z]{24}",
+//!             "remediation": "Roll the key in the billing console"
+//!         } } }"##,
+//!     )
+//!     .build();
+//! let guidance = &scanner.rule_guidance["Billing API key"];
+//! assert_eq!(guidance.remediation.as_deref(), Some("Roll the key in the billing console"));
+//! assert_eq!(guidance.reference_url, None);
+//!
+//! let scanner = SecretScannerBuilder::new()
+//!     .set_json_str(
+//!         r##"{ "rules": {
+//!             "AWS key": { "pattern": "AKIA[0-9A-Z]{16}", "case_insensitive": false },
+//!             "Hex token": "tok_[0-9a-f]{8}"
+//!         } }"##,
+//!     )
+//!     .global_case_insensitive(true)
+//!     .build();
+//! let counts: Vec<usize> = scanner
+//!     .matches(b"akiaabcdefghijklmnop TOK_0123abcd")
+//!     .into_iter()
+//!     .map(|(_, m)| m.count())
+//!     .collect();
+//! assert_eq!(counts, vec![0, 1]); // "AWS key", then "Hex token"
+//! ```
+
+use crate::allowlist::Allowlist;
+use crate::entropy::EntropyMode;
+use crate::rule_formats;
+use crate::{SecretScanner, SecretScannerBuilder};
+use log::{self, error, info, warn};
+use regex::bytes::RegexBuilder;
+use serde_derive::Serialize;
+use serde_json::{Map, Value};
+use simple_error::{require_with, try_with, SimpleError};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::fmt;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+/// The name of the rule pack compiled into the library
+pub const BUILTIN_PACK_NAME: &str = "builtin";
+
+/// A named set of rules (rule name to regular expression) with a priority
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Default)]
+pub struct RulePack {
+    pub name: String,
+    pub version: Option<String>,
+    pub priority: i64,
+    pub enabled: bool,
+    pub rules: BTreeMap<String, String>,
+    /// Rules defined with `"enabled": false`, which can be enabled for a single run
+    pub inactive_rules: BTreeMap<String, String>,
+    pub severities: BTreeMap<String, Severity>,
+    /// The entropy mode matches of a rule must look random in, for the rules that have one
+    pub entropy: BTreeMap<String, EntropyMode>,
+    /// The keywords, in lower case, one of which a line must contain for a rule to be tried on it
+    pub keywords: BTreeMap<String, Vec<String>>,
+    /// The rules run against a window of lines rather than each line
+    pub multiline: BTreeSet<String>,
+    /// The time budgets of the rules that have their own
+    pub timeouts: BTreeMap<String, Duration>,
+    /// The regex flags of the rules that set any
+    pub flags: BTreeMap<String, RuleFlags>,
+    /// The samples of the rules that have them, see `test_rules`
+    pub examples: BTreeMap<String, RuleExamples>,
+    /// The remediation guidance of the rules that have any
+    pub guidance: BTreeMap<String, RuleGuidance>,
+    pub disabled_rules: Vec<String>,
+}
+
+/// The regex flags a rule sets for itself, each overriding the default when set (see the module
+/// documentation)
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy, Default)]
+pub struct RuleFlags {
+    /// Ignore case, instead of following `--caseinsensitive`
+    pub case_insensitive: Option<bool>,
+    /// Let `.` match `\n` (off by default)
+    pub dot_all: Option<bool>,
+    /// Match UTF-8 characters rather than bytes (on by default)
+    pub unicode: Option<bool>,
+}
+
+impl RuleFlags {
+    /// The rule fields that set the flags
+    pub const FIELDS: [&'static str; 3] = ["case_insensitive", "dot_all", "unicode"];
+
+    /// Reads the flags of a rule object. Fails on a flag that isn't true or false.
+    pub fn from_rule(rule: &Map<String, Value>) -> Result<Self, SimpleError> {
+        let flag = |field: &str| match rule.get(field) {
+            None => Ok(None),
+            Some(Value::Bool(b)) => Ok(Some(*b)),
+            Some(_) => Err(SimpleError::new(format!("{} must be true or false", field))),
+        };
+        Ok(Self {
+            case_insensitive: flag("case_insensitive")?,
+            dot_all: flag("dot_all")?,
+            unicode: flag("unicode")?,
+        })
+    }
+
+    /// Sets the flags on `regex_builder`, ignoring case if the rule doesn't say otherwise and
+    /// `case_insensitive` (the global setting) is true
+    pub fn configure(&self, regex_builder: &mut RegexBuilder, case_insensitive: bool) {
+        regex_builder.case_insensitive(self.case_insensitive.unwrap_or(case_insensitive));
+        if let Some(dot_all) = self.dot_all {
+            regex_builder.dot_matches_new_line(dot_all);
+        }
+        if let Some(unicode) = self.unicode {
+            regex_builder.unicode(unicode);
+        }
+    }
+}
+
+/// Lines a rule must match (`examples`) and must not match (`counter_examples`)
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Default)]
+pub struct RuleExamples {
+    pub examples: Vec<String>,
+    pub counter_examples: Vec<String>,
+}
+
+/// What a rule's findings are and how to fix them, reported with each finding of the rule
+#[derive(Serialize, Debug, PartialEq, Eq, Hash, Clone, Default)]
+pub struct RuleGuidance {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// How to rotate or revoke the credential
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub remediation: Option<String>,
+    #[serde(rename = "referenceUrl", skip_serializing_if = "Option::is_none")]
+    pub reference_url: Option<String>,
+}
+
+/// A sample of a rule that `test_rules` found the rule matching when it shouldn't, or the other
+/// way around
+#[derive(Serialize, Debug, PartialEq, Eq, Hash, Clone)]
+pub struct RuleTestFailure {
+    pub rule: String,
+    pub sample: String,
+    /// True for an example the rule doesn't match, false for a counter example it matches
+    #[serde(rename = "expectedMatch")]
+    pub expected_match: bool,
+}
+
+impl fmt::Display for RuleTestFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.expected_match {
+            write!(
+                f,
+                "{} doesn't match its example {:?}",
+                self.rule, self.sample
+            )
+        } else {
+            write!(
+                f,
+                "{} matches its counter example {:?}",
+                self.rule, self.sample
+            )
+        }
+    }
+}
+
+/// The severity of a rule, used by the report formats that have one
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy, PartialOrd, Ord)]
+pub enum Severity {
+    Info,
+    Low,
+    Medium,
+    High,
+    Critical,
+}
+
+impl FromStr for Severity {
+    type Err = SimpleError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "info" => Ok(Severity::Info),
+            "low" => Ok(Severity::Low),
+            "medium" => Ok(Severity::Medium),
+            "high" => Ok(Severity::High),
+            "critical" => Ok(Severity::Critical),
+            _ => Err(SimpleError::new(format!(
+                "Unknown severity {:?}, expected info, low, medium, high or critical",
+                s
+            ))),
+        }
+    }
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            Severity::Info => "info",
+            Severity::Low => "low",
+            Severity::Medium => "medium",
+            Severity::High => "high",
+            Severity::Critical => "critical",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+/// Changes to the loaded rules for a single run, e.g. from `--enable-rule`, `--disable-rule` and
+/// `--rule-severity`. Rule names can be qualified as `pack:Rule name`.
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Default)]
+pub struct RuleOverrides {
+    pub enable: Vec<String>,
+    pub disable: Vec<String>,
+    pub severities: Vec<(String, Severity)>,
+}
+
+impl RuleOverrides {
+    /// Parses a `Rule name=severity` expression and adds it to `severities`
+    pub fn add_severity(&mut self, expression: &str) -> Result<(), SimpleError> {
+        let i = match expression.rfind('=') {
+            Some(i) => i,
+            None => {
+                return Err(SimpleError::new(format!(
+                    "Expected RULE=SEVERITY, got {:?}",
+                    expression
+                )))
+            }
+        };
+        let severity = expression[i + 1..].trim().parse()?;
+        self.severities
+            .push((String::from(expression[..i].trim()), severity));
+        Ok(())
+    }
+}
+
+/// The result of `merge_with_overrides`: the pattern of each enabled rule, the severity, entropy
+/// mode, keywords, time budget, regex flags, examples and guidance of the rules that have them,
+/// and the multiline rules
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+pub struct MergedRules {
+    pub patterns: BTreeMap<String, String>,
+    pub severities: BTreeMap<String, Severity>,
+    pub entropy: BTreeMap<String, EntropyMode>,
+    pub keywords: BTreeMap<String, Vec<String>>,
+    pub multiline: BTreeSet<String>,
+    pub timeouts: BTreeMap<String, Duration>,
+    pub flags: BTreeMap<String, RuleFlags>,
+    pub examples: BTreeMap<String, RuleExamples>,
+    pub guidance: BTreeMap<String, RuleGuidance>,
+}
+
+impl RulePack {
+    /// Returns the rule pack compiled into the library
+    pub fn builtin() -> Self {
+        let mut pack = Self::from_str(crate::DEFAULT_REGEX_JSON, BUILTIN_PACK_NAME).unwrap();
+        pack.version = Some(String::from(env!("CARGO_PKG_VERSION")));
+        pack
+    }
+
+    /// The pack name and version, or a hash of its rules if it has no version
+    pub fn version_id(&self) -> String {
+        match &self.version {
+            Some(v) => format!("{}@{}", self.name, v),
+            None => {
+                let mut hasher = DefaultHasher::new();
+                self.rules.hash(&mut hasher);
+                format!("{}@{:08x}", self.name, hasher.finish() as u32)
+            }
+        }
+    }
+
+    /// Load a rule pack from a JSON file, named after the file stem unless the file names itself.
+    /// The path "builtin" loads the rules compiled into the library. Gitleaks (`.toml`) and
+    /// trufflehog (`.yaml` or `.yml`) files are converted, see the `rule_formats` module.
+    pub fn from_file(path: &str) -> Result<Self, SimpleError> {
+        if path == BUILTIN_PACK_NAME {
+            return Ok(Self::builtin());
+        }
+        info!("Attempting to read JSON regex file from {:?}", path);
+        let data = try_with!(
+            fs::read_to_string(path),
+            "Failed to open the JSON regex file"
+        );
+        let default_name = Path::new(path)
+            .file_stem()
+            .map(|s| s.to_string_lossy().to_string())
+            .unwrap_or_else(|| String::from(path));
+        let converted = match Path::new(path).extension().and_then(|e| e.to_str()) {
+            Some("toml") => rule_formats::from_gitleaks(&data)?,
+            Some("yaml") | Some("yml") => rule_formats::from_trufflehog(&data)?,
+            _ => return Self::from_str(&data, &default_name),
+        };
+        let json_obj = require_with!(converted.as_object(), "Failed to convert {}", path);
+        Self::from_object(json_obj, &default_name)
+    }
+
+    /// Parse a rule pack from a JSON string, using `default_name` if the pack doesn't name itself
+    pub fn from_str(json_str: &str, default_name: &str) -> Result<Self, SimpleError> {
+        let json_obj: Map<String, Value> =
+            try_with!(serde_json::from_str(json_str), "Failed to parse regex JSON");
+        Self::from_object(&json_obj, default_name)
+    }
+
+    /// Like `from_str`, for a parsed JSON object
+    pub fn from_object(
+        json_obj: &Map<String, Value>,
+        default_name: &str,
+    ) -> Result<Self, SimpleError> {
+        // Packs are recognised by their "rules" object, anything else is the flat format
+        let rules_obj = match json_obj.get("rules") {
+            Some(Value::Object(rules)) => rules,
+            _ => {
+                let mut pack = RulePack {
+                    name: String::from(default_name),
+                    enabled: true,
+                    ..Default::default()
+                };
+                parse_rules(&mut pack, json_obj);
+                return Ok(pack);
+            }
+        };
+        let name = json_obj
+            .get("pack")
+            .or_else(|| json_obj.get("name"))
+            .and_then(Value::as_str)
+            .unwrap_or(default_name);
+        let disabled_rules = match json_obj.get("disable") {
+            Some(Value::Array(a)) => a
+                .iter()
+                .filter_map(Value::as_str)
+                .map(String::from)
+                .collect(),
+            _ => Vec::new(),
+        };
+        let mut pack = RulePack {
+            name: String::from(name),
+            version: json_obj
+                .get("version")
+                .and_then(Value::as_str)
+                .map(String::from),
+            priority: json_obj
+                .get("priority")
+                .and_then(Value::as_i64)
+                .unwrap_or(0),
+            enabled: json_obj
+                .get("enabled")
+                .and_then(Value::as_bool)
+                .unwrap_or(true),
+            disabled_rules,
+            ..Default::default()
+        };
+        parse_rules(&mut pack, rules_obj);
+        Ok(pack)
+    }
+}
+
+/// Adds a JSON object of rules to `pack`. A rule is either a pattern string or an object with a
+/// "pattern" and optional "enabled", "severity", "entropy", "keywords", "multiline", "timeout_ms",
+/// "case_insensitive", "dot_all", "unicode", "examples", "counter_examples", "description",
+/// "remediation" and "reference_url" fields.
+/// Invalid rules are logged and skipped.
+fn parse_rules(pack: &mut RulePack, rules_obj: &Map<String, Value>) {
+    for (rule_name, value) in rules_obj {
+        let (pattern, enabled) = match value {
+            Value::String(s) => (s.as_str(), true),
+            Value::Object(o) => {
+                let pattern = match o.get("pattern").and_then(Value::as_str) {
+                    Some(p) => p,
+                    None => {
+                        error!(
+                            "Rule {:?} in pack {:?} has no pattern",
+                            rule_name, pack.name
+                        );
+                        continue;
+                    }
+                };
+                if let Some(severity) = o.get("severity").and_then(Value::as_str) {
+                    match severity.parse() {
+                        Ok(s) => {
+                            pack.severities.insert(rule_name.clone(), s);
+                        }
+                        Err(e) => error!("Rule {:?} in pack {:?}: {}", rule_name, pack.name, e),
+                    }
+                }
+                if let Some(mode) = o.get("entropy").and_then(Value::as_str) {
+                    match mode.parse() {
+                        Ok(m) => {
+                            pack.entropy.insert(rule_name.clone(), m);
+                        }
+                        Err(e) => error!("Rule {:?} in pack {:?}: {}", rule_name, pack.name, e),
+                    }
+                }
+                if let Some(keywords) = o.get("keywords").and_then(Value::as_array) {
+                    let keywords: Vec<String> = keywords
+                        .iter()
+                        .filter_map(Value::as_str)
+                        .map(str::to_lowercase)
+                        .collect();
+                    if !keywords.is_empty() {
+                        pack.keywords.insert(rule_name.clone(), keywords);
+                    }
+                }
+                if o.get("multiline").and_then(Value::as_bool) == Some(true) {
+                    pack.multiline.insert(rule_name.clone());
+                }
+                if let Some(timeout) = o.get("timeout_ms") {
+                    match timeout.as_u64() {
+                        Some(ms) => {
+                            pack.timeouts
+                                .insert(rule_name.clone(), Duration::from_millis(ms));
+                        }
+                        None => error!(
+                            "Rule {:?} in pack {:?}: timeout_ms must be a number of milliseconds",
+                            rule_name, pack.name
+                        ),
+                    }
+                }
+                match RuleFlags::from_rule(o) {
+                    Ok(flags) if flags == RuleFlags::default() => (),
+                    Ok(flags) => {
+                        pack.flags.insert(rule_name.clone(), flags);
+                    }
+                    Err(e) => error!("Rule {:?} in pack {:?}: {}", rule_name, pack.name, e),
+                }
+                let samples = |field: &str| -> Vec<String> {
+                    match o.get(field).and_then(Value::as_array) {
+                        Some(a) => a
+                            .iter()
+                            .filter_map(Value::as_str)
+                            .map(String::from)
+                            .collect(),
+                        None => Vec::new(),
+                    }
+                };
+                let examples = RuleExamples {
+                    examples: samples("examples"),
+                    counter_examples: samples("counter_examples"),
+                };
+                if examples != RuleExamples::default() {
+                    pack.examples.insert(rule_name.clone(), examples);
+                }
+                let text = |field: &str| o.get(field).and_then(Value::as_str).map(String::from);
+                let guidance = RuleGuidance {
+                    description: text("description"),
+                    remediation: text("remediation"),
+                    reference_url: text("reference_url"),
+                };
+                if guidance != RuleGuidance::default() {
+                    pack.guidance.insert(rule_name.clone(), guidance);
+                }
+                (
+                    pattern,
+                    o.get("enabled").and_then(Value::as_bool) != Some(false),
+                )
+            }
+            _ => {
+                error!(
+                    "Rule {:?} in pack {:?} is not a string or object",
+                    rule_name, pack.name
+                );
+                continue;
+            }
+        };
+        if enabled {
+            pack.rules.insert(rule_name.clone(), String::from(pattern));
+        } else {
+            info!("Rule {:?} is disabled in pack {:?}", rule_name, pack.name);
+            pack.inactive_rules
+                .insert(rule_name.clone(), String::from(pattern));
+        }
+    }
+}
+
+/// Merge rule packs into a single map of rule name to pattern. Packs are applied in ascending
+/// priority, keeping the given order for equal priorities, so later packs win conflicts. The
+/// `disable` lists of all enabled packs are applied after merging.
+pub fn merge_rule_packs(packs: Vec<RulePack>) -> BTreeMap<String, String> {
+    merge_with_overrides(packs, &RuleOverrides::default()).patterns
+}
+
+/// A rule after merging, with the pack it came from
+struct MergedRule {
+    pack: String,
+    pattern: String,
+    enabled: bool,
+}
+
+/// Like `merge_rule_packs`, then applies the per-run `overrides` and collects the severity of each
+/// rule. Rules that are disabled in their pack (`"enabled": false` or a `disable` list) can be
+/// turned back on with `RuleOverrides::enable`.
+pub fn merge_with_overrides(packs: Vec<RulePack>, overrides: &RuleOverrides) -> MergedRules {
+    let packs = ordered_packs(packs);
+    let pack_names: HashSet<&str> = packs.iter().map(|p| p.name.as_str()).collect();
+
+    // Remember which pack each rule came from so qualified names can be resolved
+    let mut merged: BTreeMap<String, MergedRule> = BTreeMap::new();
+    let mut severities: BTreeMap<String, Severity> = BTreeMap::new();
+    let mut entropy: BTreeMap<String, EntropyMode> = BTreeMap::new();
+    let mut keywords: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    let mut multiline: BTreeSet<String> = BTreeSet::new();
+    let mut timeouts: BTreeMap<String, Duration> = BTreeMap::new();
+    let mut flags: BTreeMap<String, RuleFlags> = BTreeMap::new();
+    let mut examples: BTreeMap<String, RuleExamples> = BTreeMap::new();
+    let mut guidance: BTreeMap<String, RuleGuidance> = BTreeMap::new();
+    for pack in &packs {
+        let rules = pack
+            .rules
+            .iter()
+            .map(|r| (r, true))
+            .chain(pack.inactive_rules.iter().map(|r| (r, false)));
+        for ((rule_name, pattern), enabled) in rules {
+            if let Some(previous) = merged.get(rule_name) {
+                if previous.pack != pack.name && &previous.pattern != pattern {
+                    warn!(
+                        "Rule {:?} from pack {:?} overrides the rule from pack {:?}",
+                        rule_name, pack.name, previous.pack
+                    );
+                }
+            }
+            merged.insert(
+                rule_name.clone(),
+                MergedRule {
+                    pack: pack.name.clone(),
+                    pattern: pattern.clone(),
+                    enabled,
+                },
+            );
+            match pack.severities.get(rule_name) {
+                Some(s) => severities.insert(rule_name.clone(), *s),
+                None => severities.remove(rule_name),
+            };
+            match pack.entropy.get(rule_name) {
+                Some(m) => entropy.insert(rule_name.clone(), *m),
+                None => entropy.remove(rule_name),
+            };
+            match pack.keywords.get(rule_name) {
+                Some(k) => keywords.insert(rule_name.clone(), k.clone()),
+                None => keywords.remove(rule_name),
+            };
+            if pack.multiline.contains(rule_name) {
+                multiline.insert(rule_name.clone());
+            } else {
+                multiline.remove(rule_name);
+            }
+            match pack.timeouts.get(rule_name) {
+                Some(t) => timeouts.insert(rule_name.clone(), *t),
+                None => timeouts.remove(rule_name),
+            };
+            match pack.flags.get(rule_name) {
+                Some(f) => flags.insert(rule_name.clone(), *f),
+                None => flags.remove(rule_name),
+            };
+            match pack.examples.get(rule_name) {
+                Some(e) => examples.insert(rule_name.clone(), e.clone()),
+                None => examples.remove(rule_name),
+            };
+            match pack.guidance.get(rule_name) {
+                Some(g) => guidance.insert(rule_name.clone(), g.clone()),
+                None => guidance.remove(rule_name),
+            };
+        }
+    }
+
+    let pack_disables = packs.iter().flat_map(|p| p.disabled_rules.iter());
+    let changes = pack_disables
+        .map(|r| (r, false))
+        .chain(overrides.disable.iter().map(|r| (r, false)))
+        .chain(overrides.enable.iter().map(|r| (r, true)));
+    for (qualified_name, enabled) in changes {
+        match find_rule(&merged, &pack_names, qualified_name) {
+            Some(rule_name) => {
+                info!(
+                    "{} rule {:?}",
+                    if enabled { "Enabling" } else { "Disabling" },
+                    qualified_name
+                );
+                merged.get_mut(&rule_name).unwrap().enabled = enabled;
+            }
+            None => warn!(
+                "Rule {:?} does not match a loaded rule, ignoring",
+                qualified_name
+            ),
+        }
+    }
+
+    for (qualified_name, severity) in &overrides.severities {
+        match find_rule(&merged, &pack_names, qualified_name) {
+            Some(rule_name) => {
+                severities.insert(rule_name, *severity);
+            }
+            None => warn!(
+                "Rule {:?} does not match a loaded rule, ignoring its severity",
+                qualified_name
+            ),
+        }
+    }
+
+    let patterns: BTreeMap<String, String> = merged
+        .into_iter()
+        .filter(|(_, rule)| rule.enabled)
+        .map(|(rule_name, rule)| (rule_name, rule.pattern))
+        .collect();
+    severities.retain(|rule_name, _| patterns.contains_key(rule_name));
+    entropy.retain(|rule_name, _| patterns.contains_key(rule_name));
+    keywords.retain(|rule_name, _| patterns.contains_key(rule_name));
+    multiline.retain(|rule_name| patterns.contains_key(rule_name));
+    timeouts.retain(|rule_name, _| patterns.contains_key(rule_name));
+    flags.retain(|rule_name, _| patterns.contains_key(rule_name));
+    examples.retain(|rule_name, _| patterns.contains_key(rule_name));
+    guidance.retain(|rule_name, _| patterns.contains_key(rule_name));
+    MergedRules {
+        patterns,
+        severities,
+        entropy,
+        keywords,
+        multiline,
+        timeouts,
+        flags,
+        examples,
+        guidance,
+    }
+}
+
+/// Resolves a rule name, optionally qualified as `pack:Rule name`, to a merged rule. The prefix is
+/// only treated as a pack name if such a pack was loaded, since rule names can contain ':'.
+fn find_rule(
+    merged: &BTreeMap<String, MergedRule>,
+    pack_names: &HashSet<&str>,
+    qualified_name: &str,
+) -> Option<String> {
+    let (pack_name, rule_name) = match qualified_name.find(':') {
+        Some(i) if pack_names.contains(&qualified_name[..i]) => {
+            (Some(&qualified_name[..i]), &qualified_name[i + 1..])
+        }
+        _ => (None, qualified_name),
+    };
+    let rule = merged.get(rule_name)?;
+    match pack_name {
+        Some(p) if rule.pack != p => None,
+        _ => Some(String::from(rule_name)),
+    }
+}
+
+/// Describes the packs that `merge_rule_packs` would use, e.g. `builtin@1.0.1+team@1.4.0`
+pub fn rules_version(packs: &[RulePack]) -> String {
+    ordered_packs(packs.to_vec())
+        .iter()
+        .map(RulePack::version_id)
+        .collect::<Vec<String>>()
+        .join("+")
+}
+
+/// Checks each rule of `scanner` that has examples (see `SecretScanner::rule_examples`) against
+/// them, returning the samples it matches when it shouldn't or doesn't match when it should
+pub fn test_rules(scanner: &SecretScanner) -> Vec<RuleTestFailure> {
+    let mut failures: Vec<RuleTestFailure> = Vec::new();
+    for (rule, examples) in &scanner.rule_examples {
+        let matches = |sample: &str| -> bool {
+            scanner
+                .matches(sample.as_bytes())
+                .remove(rule)
+                .map_or(false, |mut m| m.next().is_some())
+        };
+        let samples = examples
+            .examples
+            .iter()
+            .map(|s| (s, true))
+            .chain(examples.counter_examples.iter().map(|s| (s, false)));
+        for (sample, expected_match) in samples {
+            if matches(sample) != expected_match {
+                failures.push(RuleTestFailure {
+                    rule: rule.clone(),
+                    sample: sample.clone(),
+                    expected_match,
+                });
+            }
+        }
+    }
+    failures
+}
+
+/// Drops disabled packs and sorts the rest by priority, keeping the given order for ties
+fn ordered_packs(mut packs: Vec<RulePack>) -> Vec<RulePack> {
+    packs.retain(|p| {
+        if !p.enabled {
+            info!("Skipping disabled rule pack {:?}", p.name);
+        }
+        p.enabled
+    });
+    packs.sort_by_key(|p| p.priority);
+    packs
+}
+
+/// Keeps a `SecretScanner` up to date with the rule files and the allowlist file it was built
+/// from, for long-running modes that shouldn't be restarted to pick up new rules.
+///
+/// # Examples
+///
+/// ```
+/// use rusty_hogs::rules::RuleWatcher;
+/// use rusty_hogs::SecretScannerBuilder;
+/// use std::fs;
+///
+/// let dir = tempdir::TempDir::new("rules").unwrap();
+/// let pack = dir.path().join("team.json");
+/// let allowlist = dir.path().join("allowlist.json");
+/// fs::write(&pack, r#"{ "version": "1.0.0", "rules": { "Token": "tok_[0-9]{8}" } }"#).unwrap();
+/// fs::write(&allowlist, r#"{ "Token": ["tok_00000000"] }"#).unwrap();
+/// let mut builder = SecretScannerBuilder::new()
+///     .set_json_path(pack.to_str().unwrap())
+///     .set_builtin_allowlist(false);
+/// builder.allowlist_path = Some(allowlist.to_str().unwrap().to_string());
+///
+/// let mut watcher = RuleWatcher::new(builder);
+/// let scanner = watcher.scanner();
+/// assert!(!watcher.reload_if_changed());
+/// assert_eq!(scanner.rules_version, "team@1.0.0");
+/// assert!(!scanner.allowlist.is_empty());
+///
+/// // a file that fails to load keeps the current rules (modification times may only count
+/// // seconds, hence the waits)
+/// # let wait = || std::thread::sleep(std::time::Duration::from_millis(1100));
+/// # wait();
+/// fs::write(&pack, "{ not json").unwrap();
+/// assert!(!watcher.reload_if_changed());
+///
+/// # wait();
+/// fs::write(&pack, r#"{ "version": "1.1.0", "rules": { "Token": "tok_[0-9]{9}" } }"#).unwrap();
+/// fs::write(&allowlist, "{}").unwrap();
+/// assert!(watcher.reload_if_changed());
+/// assert_eq!(watcher.scanner().rules_version, "team@1.1.0");
+/// assert!(watcher.scanner().allowlist.is_empty());
+/// // the scanner of a scan in progress keeps its rules
+/// assert_eq!(scanner.rules_version, "team@1.0.0");
+/// ```
+#[derive(Debug)]
+pub struct RuleWatcher {
+    builder: SecretScannerBuilder,
+    modified: Vec<Option<SystemTime>>,
+    scanner: Arc<SecretScanner>,
+}
+
+impl RuleWatcher {
+    /// Builds the initial scanner and records the modification time of each rule file and of the
+    /// allowlist file
+    pub fn new(mut builder: SecretScannerBuilder) -> Self {
+        let modified = modified_times(&watched_paths(&builder));
+        // if a file fails to load, `build` reports it and falls back as it does for any scan
+        if let Ok(loaded) = load_rules(&builder) {
+            builder = loaded;
+        }
+        let scanner = Arc::new(builder.build());
+        Self {
+            builder,
+            modified,
+            scanner,
+        }
+    }
+
+    /// The builder of the current scanner, with its rules and allowlist already loaded, for
+    /// building scanners with other settings but the same rules (see
+    /// `SecretScannerBuilder::set_rules_from`)
+    pub fn builder(&self) -> &SecretScannerBuilder {
+        &self.builder
+    }
+
+    /// Returns the current scanner. Callers keep using the scanner they were given for the
+    /// duration of a scan, even if the rules are reloaded in the meantime.
+    pub fn scanner(&self) -> Arc<SecretScanner> {
+        Arc::clone(&self.scanner)
+    }
+
+    /// Rebuilds the scanner if any rule file or the allowlist file changed since the last check,
+    /// returning whether it was replaced. If a changed file fails to load the current scanner is
+    /// kept, rather than falling back to the default rules or no allowlist as
+    /// `SecretScannerBuilder` does.
+    pub fn reload_if_changed(&mut self) -> bool {
+        let modified = modified_times(&watched_paths(&self.builder));
+        if modified == self.modified {
+            return false;
+        }
+        self.modified = modified;
+        let builder = match load_rules(&self.builder) {
+            Ok(b) => b,
+            Err(e) => {
+                error!("Not reloading rules, {}", e);
+                return false;
+            }
+        };
+        let scanner = builder.build();
+        info!(
+            "Reloaded rules and allowlist, {} -> {}",
+            self.scanner.rules_version, scanner.rules_version
+        );
+        self.builder = builder;
+        self.scanner = Arc::new(scanner);
+        true
+    }
+}
+
+/// The files a `RuleWatcher` watches: the rule files, then the allowlist file if there is one
+fn watched_paths(builder: &SecretScannerBuilder) -> Vec<String> {
+    let mut paths = builder.regex_json_paths.clone();
+    paths.extend(builder.allowlist_path.clone());
+    paths
+}
+
+/// A copy of `builder` with its rule files and allowlist file loaded, failing if any of them fails
+/// to load
+fn load_rules(builder: &SecretScannerBuilder) -> Result<SecretScannerBuilder, SimpleError> {
+    let mut loaded = builder.clone();
+    if !builder.regex_json_paths.is_empty() {
+        let packs: Result<Vec<RulePack>, SimpleError> = builder
+            .regex_json_paths
+            .iter()
+            .map(|p| {
+                RulePack::from_file(p)
+                    .map_err(|e| SimpleError::new(format!("{} failed to load: {}", p, e)))
+            })
+            .collect();
+        loaded.rule_packs = Some(packs?);
+    }
+    if let Some(path) = &builder.allowlist_path {
+        loaded.allowlist = Allowlist::from_file(path)
+            .map_err(|e| SimpleError::new(format!("{} failed to load: {}", path, e)))?;
+    }
+    Ok(loaded)
+}
+
+/// The last modification time of each file, `None` for the built-in pack or missing files
+fn modified_times(paths: &[String]) -> Vec<Option<SystemTime>> {
+    paths
+        .iter()
+        .map(|p| fs::metadata(p).and_then(|m| m.modified()).ok())
+        .collect()
+}
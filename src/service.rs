@@ -0,0 +1,256 @@
+//! Running the long-lived modes (`hogd`, `paste_hog`, `berkshire_hog --sqs-queue` and
+//! `duroc_hog --watch`) as managed services.
+//!
+//! Under systemd, a unit with `Type=notify` learns that the service started from `ready`, which
+//! also sets its status line, and that it is stopping from `stopping`. With `WatchdogSec`, the
+//! main loop of the service calls `alive` on each turn, so systemd restarts a service that hangs:
+//! the watchdog interval must be longer than a turn, e.g. one poll of `paste_hog` or one batch of
+//! SQS messages. Messages are sent to the socket in `NOTIFY_SOCKET` (see sd_notify(3)), so these
+//! do nothing outside systemd. SIGTERM stops the service as it stops a scan (see the `shutdown`
+//! module).
+//!
+//! On Windows, a binary given `--service` calls `run_service`, which runs it under the service
+//! control manager, as installed with e.g.
+//! `sc.exe create hogd binPath= "C:\rusty-hog\hogd.exe --service --token <TOKEN>"`. Stopping the
+//! service, or shutting Windows down, stops it like SIGTERM.
+//!
+//! # Examples
+//!
+//! ```
+//! use rusty_hogs::service;
+//!
+//! // outside systemd nothing is sent
+//! std::env::remove_var("NOTIFY_SOCKET");
+//! assert!(!service::ready("Listening on 127.0.0.1:8080"));
+//!
+//! # #[cfg(unix)]
+//! # {
+//! use std::os::unix::net::UnixDatagram;
+//!
+//! let dir = tempdir::TempDir::new("notify").unwrap();
+//! let path = dir.path().join("notify.sock");
+//! let systemd = UnixDatagram::bind(&path).unwrap();
+//! std::env::set_var("NOTIFY_SOCKET", &path);
+//! assert!(service::ready("Listening on 127.0.0.1:8080"));
+//! let mut message = [0; 64];
+//! let n = systemd.recv(&mut message).unwrap();
+//! assert_eq!(&message[..n], &b"READY=1\nSTATUS=Listening on 127.0.0.1:8080"[..]);
+//! # }
+//! ```
+
+use lazy_static::lazy_static;
+use log::{self, debug};
+use simple_error::SimpleError;
+use std::env;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+lazy_static! {
+    /// When `alive` last pinged the watchdog
+    static ref LAST_PING: Mutex<Option<Instant>> = Mutex::new(None);
+}
+
+/// Tells systemd the service started and what it is doing. Returns true if it was told.
+pub fn ready(status: &str) -> bool {
+    notify(&format!("READY=1\nSTATUS={}", status))
+}
+
+/// Tells systemd the service is stopping. Returns true if it was told.
+pub fn stopping() -> bool {
+    notify("STOPPING=1")
+}
+
+/// Pings the watchdog of systemd, if the unit has one, at most twice per watchdog interval
+pub fn alive() {
+    let interval = match watchdog_interval() {
+        Some(i) => i,
+        None => return,
+    };
+    let mut last_ping = LAST_PING.lock().unwrap();
+    if last_ping.map_or(true, |t| t.elapsed() >= interval / 2) && notify("WATCHDOG=1") {
+        *last_ping = Some(Instant::now());
+    }
+}
+
+/// The watchdog interval of the unit, if it has one and it watches this process
+fn watchdog_interval() -> Option<Duration> {
+    let usec: u64 = env::var("WATCHDOG_USEC").ok()?.parse().ok()?;
+    match env::var("WATCHDOG_PID").ok().map(|p| p.parse::<u32>()) {
+        Some(Ok(pid)) if pid != std::process::id() => None,
+        _ => Some(Duration::from_micros(usec)),
+    }
+}
+
+/// Sends a message to the notification socket of systemd, returning true if it was sent
+#[cfg(unix)]
+pub fn notify(message: &str) -> bool {
+    use std::os::unix::net::UnixDatagram;
+
+    let path = match env::var("NOTIFY_SOCKET") {
+        Ok(p) if !p.is_empty() => p,
+        _ => return false,
+    };
+    let socket = match UnixDatagram::unbound() {
+        Ok(s) => s,
+        Err(e) => {
+            debug!("Failed to notify systemd: {}", e);
+            return false;
+        }
+    };
+    let result = if let Some(name) = path.strip_prefix('@') {
+        send_abstract(&socket, name, message)
+    } else {
+        socket.send_to(message.as_bytes(), &path).map(|_| ())
+    };
+    match result {
+        Ok(()) => true,
+        Err(e) => {
+            debug!("Failed to notify systemd at {}: {}", path, e);
+            false
+        }
+    }
+}
+
+/// Sends a message to a socket in the abstract namespace, as `NOTIFY_SOCKET` names with an `@`
+#[cfg(target_os = "linux")]
+fn send_abstract(
+    socket: &std::os::unix::net::UnixDatagram,
+    name: &str,
+    message: &str,
+) -> std::io::Result<()> {
+    use std::os::linux::net::SocketAddrExt;
+    use std::os::unix::net::SocketAddr;
+
+    let address = SocketAddr::from_abstract_name(name.as_bytes())?;
+    socket
+        .send_to_addr(message.as_bytes(), &address)
+        .map(|_| ())
+}
+
+#[cfg(all(unix, not(target_os = "linux")))]
+fn send_abstract(
+    _socket: &std::os::unix::net::UnixDatagram,
+    _name: &str,
+    _message: &str,
+) -> std::io::Result<()> {
+    Err(std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        "abstract sockets are only supported on Linux",
+    ))
+}
+
+/// Sends a message to the notification socket of systemd, which doesn't exist on this platform
+#[cfg(not(unix))]
+pub fn notify(_message: &str) -> bool {
+    false
+}
+
+/// Runs `run` as the Windows service `name`, under the service control manager, until it returns.
+/// A stop or shutdown request is handed to `run` as an interruption (see `shutdown::interrupted`).
+/// An error of `run` stops the service with a service-specific exit code of 1.
+#[cfg(windows)]
+pub fn run_service<F>(name: &str, run: F) -> Result<(), SimpleError>
+where
+    F: FnOnce() -> Result<(), SimpleError> + Send + 'static,
+{
+    windows::run(name, Box::new(run))
+}
+
+/// Fails, as only Windows has a service control manager: under systemd, run the binary from a
+/// unit with `Type=notify` instead
+#[cfg(not(windows))]
+pub fn run_service<F>(_name: &str, _run: F) -> Result<(), SimpleError>
+where
+    F: FnOnce() -> Result<(), SimpleError> + Send + 'static,
+{
+    Err(SimpleError::new(
+        "--service runs a Windows service, run it from a systemd unit with Type=notify instead",
+    ))
+}
+
+#[cfg(windows)]
+mod windows {
+    use crate::shutdown;
+    use lazy_static::lazy_static;
+    use log::{self, error};
+    use simple_error::{try_with, SimpleError};
+    use std::ffi::OsString;
+    use std::sync::Mutex;
+    use std::time::Duration;
+    use windows_service::service::{
+        ServiceControl, ServiceControlAccept, ServiceExitCode, ServiceState, ServiceStatus,
+        ServiceType,
+    };
+    use windows_service::service_control_handler::{self, ServiceControlHandlerResult};
+    use windows_service::{define_windows_service, service_dispatcher};
+
+    type Run = Box<dyn FnOnce() -> Result<(), SimpleError> + Send>;
+
+    lazy_static! {
+        /// The name and the main function of the service, for `service_main` to take
+        static ref SERVICE: Mutex<Option<(String, Run)>> = Mutex::new(None);
+    }
+
+    define_windows_service!(ffi_service_main, service_main);
+
+    pub fn run(name: &str, run: Run) -> Result<(), SimpleError> {
+        *SERVICE.lock().unwrap() = Some((name.to_string(), run));
+        try_with!(
+            service_dispatcher::start(name, ffi_service_main),
+            "Failed to start the service {}, was it started by the service control manager?",
+            name
+        );
+        Ok(())
+    }
+
+    /// Called by the service control manager on a thread of its own
+    fn service_main(_arguments: Vec<OsString>) {
+        let (name, run) = match SERVICE.lock().unwrap().take() {
+            Some(service) => service,
+            None => return,
+        };
+        let handler = |control| match control {
+            ServiceControl::Stop | ServiceControl::Shutdown => {
+                shutdown::stop();
+                ServiceControlHandlerResult::NoError
+            }
+            ServiceControl::Interrogate => ServiceControlHandlerResult::NoError,
+            _ => ServiceControlHandlerResult::NotImplemented,
+        };
+        let status_handle = match service_control_handler::register(&name, handler) {
+            Ok(h) => h,
+            Err(e) => {
+                error!("Failed to register the service {}: {}", name, e);
+                return;
+            }
+        };
+        let status = |state, exit_code| ServiceStatus {
+            service_type: ServiceType::OWN_PROCESS,
+            current_state: state,
+            controls_accepted: if state == ServiceState::Running {
+                ServiceControlAccept::STOP | ServiceControlAccept::SHUTDOWN
+            } else {
+                ServiceControlAccept::empty()
+            },
+            exit_code,
+            checkpoint: 0,
+            wait_hint: Duration::default(),
+            process_id: None,
+        };
+        if let Err(e) = status_handle
+            .set_service_status(status(ServiceState::Running, ServiceExitCode::Win32(0)))
+        {
+            error!("Failed to report the service {} running: {}", name, e);
+        }
+        let exit_code = match run() {
+            Ok(()) => ServiceExitCode::Win32(0),
+            Err(e) => {
+                error!("The service {} failed: {}", name, e);
+                ServiceExitCode::ServiceSpecific(1)
+            }
+        };
+        if let Err(e) = status_handle.set_service_status(status(ServiceState::Stopped, exit_code)) {
+            error!("Failed to report the service {} stopped: {}", name, e);
+        }
+    }
+}
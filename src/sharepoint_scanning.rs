@@ -0,0 +1,564 @@
+//! Collection of tools for scanning SharePoint document libraries and OneDrive for Business for
+//! secrets.
+//!
+//! `SharePointScanner` acts as a wrapper around a `SecretScanner` object and talks to
+//! [Microsoft Graph](https://docs.microsoft.com/en-us/graph/api/resources/onedrive) with the same
+//! application (client credentials) token as `teams_scanning`, so every site and OneDrive in the
+//! tenant can be read without a user signing in. It can find the document libraries (drives) of
+//! SharePoint sites and the OneDrive of users, list the files in a drive and download and scan
+//! them on several threads. Office documents are split into their sheets, slides and other parts
+//! like in `google_scanning`, config files are scanned value by value with `--structured` and large
+//! files are split into chunks.
+//!
+//! Files are listed with a [delta query](https://docs.microsoft.com/en-us/graph/api/driveitem-delta),
+//! which returns every file in the drive the first time and ends with a delta link. Passing that
+//! link to `list_changes` on a later scan lists only the files created or modified since, which is
+//! how the `sharepoint_hog` binary scans incrementally. A delta link that has expired is replaced
+//! by listing the whole drive again.
+//!
+//! The Azure AD application needs the `Sites.Read.All` and `Files.Read.All` application
+//! permissions, and `User.Read.All` to list the users whose OneDrive to scan, granted by an
+//! administrator.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use rusty_hogs::sharepoint_scanning::SharePointScanner;
+//! use rusty_hogs::teams_scanning::TeamsConfig;
+//!
+//! let sps = SharePointScanner::new();
+//! let config = TeamsConfig::from_client_credentials("tenant-id", "client-id", "secret").unwrap();
+//! let site = sps
+//!     .get_site(&config, "https://contoso.sharepoint.com/sites/Finance")
+//!     .unwrap();
+//! for drive in sps.list_site_drives(&config, &site).unwrap() {
+//!     let (items, delta_link) = sps.list_changes(&config, &drive, None).unwrap();
+//!     for (item, result) in sps.scan_items(&config, &drive, items, 4, false) {
+//!         println!("{}: {:?}", item.path, result.map(|f| f.len()));
+//!     }
+//!     println!("next time, list the changes since {}", delta_link);
+//! }
+//! ```
+
+use crate::chunked_scanning;
+use crate::decoding::{self, ZIP_MAGIC};
+use crate::network;
+use crate::progress;
+use crate::retry;
+use crate::shutdown;
+use crate::structured_scanning::{self, StructuredFormat};
+use crate::teams_scanning::{get, get_all, TeamsConfig};
+use crate::{SecretScanner, Span};
+use log::{self, debug, error, info, warn};
+use reqwest::StatusCode;
+use serde_derive::{Deserialize, Serialize};
+use serde_json::Value;
+use simple_error::{require_with, try_with, SimpleError};
+use std::collections::HashMap;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use url::Url;
+
+/// The fields of the items in a delta query that we need
+const ITEM_FIELDS: &str =
+    "id,name,size,webUrl,lastModifiedDateTime,lastModifiedBy,parentReference,file,folder,root,deleted";
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Hash, Clone, Default)]
+/// `serde_json` object that represents a single found secret - finding
+pub struct SharePointFinding {
+    pub diff: String,
+    #[serde(rename = "stringsFound")]
+    pub strings_found: Vec<String>,
+    /// The display name of the site, or of the user whose OneDrive the file is in
+    pub owner: String,
+    /// The name of the document library
+    pub drive: String,
+    /// The path of the file from the root of the drive
+    pub path: String,
+    /// A link to the file in SharePoint or OneDrive
+    pub url: String,
+    /// The display name of the user who last modified the file
+    pub user: String,
+    pub date: String,
+    pub reason: String,
+    #[serde(rename = "keyPath", default, skip_serializing_if = "Option::is_none")]
+    pub key_path: Option<String>,
+    /// The sheet, slide or other part of an office document that the secret was found in
+    #[serde(
+        rename = "documentPart",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub document_part: Option<String>,
+    /// Where each of `strings_found` is in the file, see `Span` (none with a `key_path`)
+    #[serde(default)]
+    pub spans: Vec<Span>,
+}
+
+/// A SharePoint site. Only the fields we need are included.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Hash, Clone, Default)]
+pub struct Site {
+    pub id: String,
+    #[serde(rename = "displayName", default)]
+    pub display_name: String,
+    #[serde(rename = "webUrl", default)]
+    pub web_url: String,
+}
+
+/// A document library of a site, or the OneDrive of a user
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Hash, Clone, Default)]
+pub struct Drive {
+    pub id: String,
+    pub name: String,
+    /// The display name of the site, or of the user whose OneDrive it is
+    pub owner: String,
+    pub web_url: String,
+}
+
+/// A file in a drive. Only the fields we need are included.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Hash, Clone, Default)]
+pub struct DriveItem {
+    pub id: String,
+    /// The path from the root of the drive, e.g. `/Reports/2020.xlsx`
+    pub path: String,
+    /// The size in bytes
+    pub size: u64,
+    pub web_url: String,
+    /// When the file was last modified (RFC 3339)
+    pub last_modified: String,
+    /// The display name of the user who last modified the file
+    pub modified_by: String,
+}
+
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+/// Contains helper functions for performing scans of SharePoint and OneDrive drives
+pub struct SharePointScanner {
+    pub secret_scanner: SecretScanner,
+}
+
+/// Acts as a wrapper around a `SecretScanner` object to provide helper functions for performing
+/// scanning against SharePoint and OneDrive. Relies on the [reqwest](https://docs.rs/reqwest/)
+/// library for HTTP access.
+impl SharePointScanner {
+    /// Initialize the SecretScanner object first using the SecretScannerBuilder, then provide
+    /// it to this constructor method.
+    pub fn new_from_scanner(secret_scanner: SecretScanner) -> Self {
+        Self { secret_scanner }
+    }
+
+    pub fn new() -> Self {
+        Self {
+            secret_scanner: SecretScanner::default(),
+        }
+    }
+
+    /// Returns every site in the tenant
+    pub fn list_sites(&self, config: &TeamsConfig) -> Result<Vec<Site>, SimpleError> {
+        let sites = get_all(config, "sites?search=*&$select=id,displayName,webUrl")?;
+        Ok(sites
+            .into_iter()
+            .filter_map(|s| serde_json::from_value(s).ok())
+            .collect())
+    }
+
+    /// Returns the site at `url`, e.g. `https://contoso.sharepoint.com/sites/Finance`
+    pub fn get_site(&self, config: &TeamsConfig, url: &str) -> Result<Site, SimpleError> {
+        let site_url = try_with!(Url::parse(url), "Invalid site URL {}", url);
+        let host = require_with!(site_url.host_str(), "No host in the site URL {}", url);
+        let path = site_url.path().trim_end_matches('/');
+        let site_path = if path.is_empty() {
+            format!("sites/{}", host)
+        } else {
+            format!("sites/{}:{}", host, path)
+        };
+        let site: Site = try_with!(
+            get(
+                config,
+                &format!("{}?$select=id,displayName,webUrl", site_path)
+            )?
+            .json(),
+            "Failed to parse the site {}",
+            url
+        );
+        Ok(site)
+    }
+
+    /// Returns the document libraries of `site`
+    pub fn list_site_drives(
+        &self,
+        config: &TeamsConfig,
+        site: &Site,
+    ) -> Result<Vec<Drive>, SimpleError> {
+        let drives = get_all(
+            config,
+            &format!("sites/{}/drives?$select=id,name,webUrl", site.id),
+        )?;
+        Ok(drives
+            .iter()
+            .map(|d| Self::drive(d, &site.display_name))
+            .filter(|d| !d.id.is_empty())
+            .collect())
+    }
+
+    /// Returns the user principal names of every user in the tenant
+    pub fn list_users(&self, config: &TeamsConfig) -> Result<Vec<String>, SimpleError> {
+        let users = get_all(config, "users?$select=userPrincipalName")?;
+        Ok(users
+            .iter()
+            .filter_map(|u| u["userPrincipalName"].as_str().map(String::from))
+            .collect())
+    }
+
+    /// Returns the OneDrive of `user`, a user principal name or ID. Fails for users without one.
+    pub fn get_user_drive(&self, config: &TeamsConfig, user: &str) -> Result<Drive, SimpleError> {
+        let drive: Value = try_with!(
+            get(
+                config,
+                &format!("users/{}/drive?$select=id,name,webUrl,owner", user)
+            )?
+            .json(),
+            "Failed to parse the OneDrive of {}",
+            user
+        );
+        let owner = drive["owner"]["user"]["displayName"]
+            .as_str()
+            .unwrap_or(user);
+        Ok(Self::drive(&drive, owner))
+    }
+
+    fn drive(drive: &Value, owner: &str) -> Drive {
+        Drive {
+            id: drive["id"].as_str().unwrap_or("").to_string(),
+            name: drive["name"].as_str().unwrap_or("").to_string(),
+            owner: owner.to_string(),
+            web_url: drive["webUrl"].as_str().unwrap_or("").to_string(),
+        }
+    }
+
+    /// Lists the files in `drive` that were created or modified since `delta_link` (from an
+    /// earlier `list_changes`), or every file in it without one. Deleted files and folders are
+    /// skipped. Also returns the delta link to list the next changes from.
+    pub fn list_changes(
+        &self,
+        config: &TeamsConfig,
+        drive: &Drive,
+        delta_link: Option<&str>,
+    ) -> Result<(Vec<DriveItem>, String), SimpleError> {
+        let first = format!(
+            "{}/drives/{}/root/delta?$select={}",
+            config.url.trim_end_matches('/'),
+            drive.id,
+            ITEM_FIELDS
+        );
+        let mut next = delta_link.unwrap_or(&first).to_string();
+        // the same item can show up on several pages, the last one is its current state
+        let mut items: Vec<Value> = Vec::new();
+        let mut positions: HashMap<String, usize> = HashMap::new();
+        let delta_link = loop {
+            let page = match delta_page(config, &next)? {
+                Some(p) => p,
+                None if next != first => {
+                    warn!(
+                        "The delta link of {} has expired, listing all of it again",
+                        drive.name
+                    );
+                    items.clear();
+                    positions.clear();
+                    next = first.clone();
+                    continue;
+                }
+                None => {
+                    return Err(SimpleError::new(
+                        "Microsoft Graph rejected a new delta query",
+                    ))
+                }
+            };
+            for item in page["value"].as_array().into_iter().flatten() {
+                let id = item["id"].as_str().unwrap_or("").to_string();
+                match positions.get(&id) {
+                    Some(&i) => items[i] = item.clone(),
+                    None => {
+                        positions.insert(id, items.len());
+                        items.push(item.clone());
+                    }
+                }
+            }
+            match (
+                page["@odata.nextLink"].as_str(),
+                page["@odata.deltaLink"].as_str(),
+            ) {
+                (Some(n), _) => next = n.to_string(),
+                (None, Some(d)) => break d.to_string(),
+                (None, None) => {
+                    return Err(SimpleError::new(
+                        "Microsoft Graph returned neither a next link nor a delta link",
+                    ))
+                }
+            }
+        };
+
+        // Delta queries don't return the paths of items, so they are built from their folders.
+        // Folders that haven't changed since the delta link are looked up.
+        let mut folders: HashMap<String, (String, Option<String>)> = HashMap::new();
+        for item in items.iter().filter(|i| i["deleted"].is_null()) {
+            if item["folder"].is_object() || item["root"].is_object() {
+                let parent = if item["root"].is_object() {
+                    None
+                } else {
+                    item["parentReference"]["id"].as_str().map(String::from)
+                };
+                folders.insert(
+                    item["id"].as_str().unwrap_or("").to_string(),
+                    (item["name"].as_str().unwrap_or("").to_string(), parent),
+                );
+            }
+        }
+        let mut paths: HashMap<String, String> = HashMap::new();
+        let mut files: Vec<DriveItem> = Vec::new();
+        for item in items {
+            if !item["deleted"].is_null() || !item["file"].is_object() {
+                continue;
+            }
+            let parent = item["parentReference"]["id"].as_str().unwrap_or("");
+            let folder = self.folder_path(config, drive, parent, &folders, &mut paths)?;
+            files.push(DriveItem {
+                id: item["id"].as_str().unwrap_or("").to_string(),
+                path: format!("{}/{}", folder, item["name"].as_str().unwrap_or("")),
+                size: item["size"].as_u64().unwrap_or(0),
+                web_url: item["webUrl"].as_str().unwrap_or("").to_string(),
+                last_modified: item["lastModifiedDateTime"]
+                    .as_str()
+                    .unwrap_or("")
+                    .to_string(),
+                modified_by: item["lastModifiedBy"]["user"]["displayName"]
+                    .as_str()
+                    .unwrap_or("")
+                    .to_string(),
+            });
+        }
+        info!("Found {} changed files in {}", files.len(), drive.name);
+        Ok((files, delta_link))
+    }
+
+    /// Returns the path of the folder `id` from the root of `drive` ("" for the root), from the
+    /// folders of a delta query or else from Microsoft Graph
+    fn folder_path(
+        &self,
+        config: &TeamsConfig,
+        drive: &Drive,
+        id: &str,
+        folders: &HashMap<String, (String, Option<String>)>,
+        paths: &mut HashMap<String, String>,
+    ) -> Result<String, SimpleError> {
+        if let Some(path) = paths.get(id) {
+            return Ok(path.clone());
+        }
+        let path = match folders.get(id) {
+            Some((_, None)) => String::new(),
+            Some((name, Some(parent))) => format!(
+                "{}/{}",
+                self.folder_path(config, drive, parent, folders, paths)?,
+                name
+            ),
+            None => {
+                let folder: Value = try_with!(
+                    get(
+                        config,
+                        &format!(
+                            "drives/{}/items/{}?$select=name,root,parentReference",
+                            drive.id, id
+                        )
+                    )?
+                    .json(),
+                    "Failed to parse folder {} of {}",
+                    id,
+                    drive.name
+                );
+                if folder["root"].is_object() {
+                    String::new()
+                } else {
+                    // e.g. /drives/b!abc/root:/Reports
+                    let parent = folder["parentReference"]["path"].as_str().unwrap_or("");
+                    let parent = parent.splitn(2, "root:").nth(1).unwrap_or("");
+                    format!("{}/{}", parent, folder["name"].as_str().unwrap_or(""))
+                }
+            }
+        };
+        paths.insert(id.to_string(), path.clone());
+        Ok(path)
+    }
+
+    /// Downloads a file and returns a list of findings
+    pub fn scan_item(
+        &self,
+        config: &TeamsConfig,
+        drive: &Drive,
+        item: &DriveItem,
+        scan_entropy: bool,
+    ) -> Result<Vec<SharePointFinding>, SimpleError> {
+        let template = SharePointFinding {
+            owner: drive.owner.clone(),
+            drive: drive.name.clone(),
+            path: item.path.clone(),
+            url: item.web_url.clone(),
+            user: item.modified_by.clone(),
+            date: item.last_modified.clone(),
+            ..Default::default()
+        };
+        let data = try_with!(
+            get(
+                config,
+                &format!("drives/{}/items/{}/content", drive.id, item.id)
+            )?
+            .bytes(),
+            "Failed to download {}",
+            item.path
+        );
+
+        // Office documents are split into their sheets, slides and other parts
+        let parts: Vec<(Option<String>, Vec<u8>)> = if data.starts_with(ZIP_MAGIC) {
+            match decoding::extract_ooxml(&data) {
+                Ok(p) => p
+                    .into_iter()
+                    .map(|(name, data)| (Some(name), data))
+                    .collect(),
+                Err(e) => {
+                    debug!("{}, scanning {} as-is", e, item.path);
+                    vec![(None, data.to_vec())]
+                }
+            }
+        } else {
+            vec![(None, data.to_vec())]
+        };
+
+        let mut size = 0;
+        let mut findings: Vec<SharePointFinding> = Vec::new();
+        for (document_part, data) in parts {
+            let data = self.secret_scanner.preprocess_owned(data);
+            size += data.len();
+            let template = SharePointFinding {
+                document_part: document_part.clone(),
+                ..template.clone()
+            };
+
+            // Config files are parsed and scanned value by value, falling back to lines on failure
+            if self.secret_scanner.structured && document_part.is_none() {
+                if let Some(format) = StructuredFormat::detect(&item.path, &data) {
+                    match structured_scanning::scan_document(
+                        &self.secret_scanner,
+                        &data,
+                        format,
+                        scan_entropy,
+                    ) {
+                        Ok(matches) => {
+                            findings.extend(matches.into_iter().map(|m| SharePointFinding {
+                                diff: m.diff,
+                                strings_found: m.strings_found,
+                                reason: m.reason,
+                                key_path: Some(m.key_path),
+                                ..template.clone()
+                            }));
+                            continue;
+                        }
+                        Err(e) => debug!("{}, scanning {} line by line", e, item.path),
+                    }
+                }
+            }
+
+            // Large files are split into chunks that are scanned in parallel
+            let options = self.secret_scanner.chunk_options;
+            findings.extend(
+                chunked_scanning::scan_lines(&self.secret_scanner, data, options, scan_entropy)
+                    .into_iter()
+                    .map(|m| SharePointFinding {
+                        diff: m.diff,
+                        strings_found: m.strings_found,
+                        reason: m.reason,
+                        spans: m.spans,
+                        ..template.clone()
+                    }),
+            );
+        }
+        progress::scanned(size, findings.len());
+        Ok(findings)
+    }
+
+    /// Scans each file of `drive` with `scan_item` using `threads` worker threads, returning the
+    /// result for each file. A panic while scanning a file is returned as an error for that file.
+    pub fn scan_items(
+        &self,
+        config: &TeamsConfig,
+        drive: &Drive,
+        items: Vec<DriveItem>,
+        threads: usize,
+        scan_entropy: bool,
+    ) -> Vec<(DriveItem, Result<Vec<SharePointFinding>, SimpleError>)> {
+        progress::add_total(items.len());
+        let queue = Arc::new(Mutex::new(items.into_iter()));
+        let (tx, rx) = mpsc::channel();
+        let mut handles = Vec::new();
+        for _ in 0..threads.max(1) {
+            let queue = Arc::clone(&queue);
+            let tx = tx.clone();
+            let scanner = self.clone();
+            let config = config.clone();
+            let drive = drive.clone();
+            handles.push(thread::spawn(move || loop {
+                if shutdown::interrupted() {
+                    break;
+                }
+                let item = match queue.lock().unwrap().next() {
+                    Some(i) => i,
+                    None => break,
+                };
+                let result = panic::catch_unwind(AssertUnwindSafe(|| {
+                    scanner.scan_item(&config, &drive, &item, scan_entropy)
+                }))
+                .unwrap_or_else(|_| {
+                    Err(SimpleError::new(format!("Scanning {} panicked", item.path)))
+                });
+                if tx.send((item, result)).is_err() {
+                    break;
+                }
+            }));
+        }
+        drop(tx);
+        let results = rx.iter().collect();
+        for handle in handles {
+            if handle.join().is_err() {
+                error!("A scanning thread exited unexpectedly");
+            }
+        }
+        results
+    }
+}
+
+impl Default for SharePointScanner {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Gets a page of a delta query, or None if Microsoft Graph no longer accepts its delta link
+fn delta_page(config: &TeamsConfig, url: &str) -> Result<Option<Value>, SimpleError> {
+    debug!("GET {}", url);
+    let response = try_with!(
+        retry::send(network::client().get(url).bearer_auth(&config.token)),
+        "Microsoft Graph request failed"
+    );
+    if response.status() == StatusCode::GONE {
+        return Ok(None);
+    }
+    if !response.status().is_success() {
+        return Err(SimpleError::new(format!(
+            "Microsoft Graph returned {} for {}",
+            response.status(),
+            url
+        )));
+    }
+    Ok(Some(try_with!(
+        response.json(),
+        "Failed to parse Microsoft Graph response"
+    )))
+}
@@ -0,0 +1,95 @@
+//! Stopping a scan early on SIGINT or SIGTERM without losing what it found.
+//!
+//! Binaries call `install` when they start. The first signal sets a flag instead of killing the
+//! process: scanners stop taking new repositories, objects, files or commits when `interrupted`
+//! returns true, the findings collected so far are written as usual, marked as incomplete (see
+//! `reporting::mark_interrupted`), and the binary exits with `INTERRUPTED_EXIT_CODE` after
+//! `exit_if_interrupted`. A second signal exits immediately, removing any temporary workspaces.
+//!
+//! Without `install`, a signal only removes the temporary workspaces of the process before
+//! exiting (see the `workspace` module).
+//!
+//! There are no signals in WebAssembly, so there `install` does nothing and `interrupted` is only
+//! true after `stop`.
+//!
+//! # Examples
+//!
+//! ```
+//! use rusty_hogs::shutdown;
+//!
+//! shutdown::install();
+//! let mut scanned = Vec::new();
+//! for repository in vec!["a", "b", "c"] {
+//!     if shutdown::interrupted() {
+//!         break;
+//!     }
+//!     scanned.push(repository);
+//! }
+//! assert_eq!(scanned.len(), 3);
+//! shutdown::exit_if_interrupted();
+//! ```
+
+use crate::workspace;
+#[cfg(not(target_arch = "wasm32"))]
+use log::{self, debug, warn};
+use std::process;
+use std::sync::atomic::{AtomicBool, Ordering};
+#[cfg(not(target_arch = "wasm32"))]
+use std::sync::Once;
+
+/// The exit code of a scan stopped by SIGINT or SIGTERM (128 + SIGINT, as shells report it)
+pub const INTERRUPTED_EXIT_CODE: i32 = 130;
+
+#[cfg(not(target_arch = "wasm32"))]
+static HANDLER: Once = Once::new();
+static GRACEFUL: AtomicBool = AtomicBool::new(false);
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+/// Handles SIGINT and SIGTERM by stopping the scan and writing the findings so far, see the
+/// module documentation
+pub fn install() {
+    GRACEFUL.store(true, Ordering::SeqCst);
+    install_handler();
+}
+
+/// Handles SIGINT and SIGTERM by removing the temporary workspaces and exiting, unless `install`
+/// has been called
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) fn install_handler() {
+    HANDLER.call_once(|| {
+        let result = ctrlc::set_handler(|| {
+            if GRACEFUL.load(Ordering::SeqCst) && !INTERRUPTED.swap(true, Ordering::SeqCst) {
+                warn!("Interrupted, writing the findings so far (interrupt again to exit now)");
+                return;
+            }
+            workspace::remove_all();
+            process::exit(INTERRUPTED_EXIT_CODE);
+        });
+        if let Err(e) = result {
+            debug!("Not handling SIGINT and SIGTERM: {}", e);
+        }
+    });
+}
+
+#[cfg(target_arch = "wasm32")]
+pub(crate) fn install_handler() {}
+
+/// Asks the scan to stop as the first signal would, e.g. when the service control manager stops
+/// a Windows service (see the `service` module)
+pub fn stop() {
+    INTERRUPTED.store(true, Ordering::SeqCst);
+}
+
+/// True once the scan has been asked to stop
+pub fn interrupted() -> bool {
+    INTERRUPTED.load(Ordering::SeqCst)
+}
+
+/// Exits with `INTERRUPTED_EXIT_CODE` if the scan was stopped, for binaries to call once their
+/// findings are written
+pub fn exit_if_interrupted() {
+    if interrupted() {
+        workspace::remove_all();
+        process::exit(INTERRUPTED_EXIT_CODE);
+    }
+}
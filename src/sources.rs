@@ -0,0 +1,820 @@
+//! A registry of the data sources Rusty Hog can scan.
+//!
+//! Each binary scans one kind of data source. `SourceRegistry` describes them (the binary to run,
+//! what it takes as a target, how it authenticates and the options specific to it), so that tools
+//! driving several scanners can discover what is available rather than hard coding it. The
+//! `list_sources` binary prints the registry.
+//!
+//! Scanners that live outside this repository are added with `register`, or described in a JSON
+//! file (a serialized `SourceInfo`) in a plugin directory loaded with `load_plugins`.
+//!
+//! # Examples
+//!
+//! ```
+//! use rusty_hogs::sources::{SourceInfo, SourceRegistry};
+//!
+//! let mut registry = SourceRegistry::new();
+//! assert_eq!(registry.get("github").unwrap().binary, "github_hog");
+//!
+//! let confluence = SourceInfo {
+//!     name: String::from("confluence"),
+//!     binary: String::from("essex_hog"),
+//!     description: String::from("Scans for secrets in Confluence pages."),
+//!     target: String::from("<PAGEID>"),
+//!     ..Default::default()
+//! };
+//! registry.register(confluence.clone()).unwrap();
+//! assert!(registry.register(confluence).is_err());
+//! ```
+
+use log::{self, debug};
+use serde_derive::{Deserialize, Serialize};
+use simple_error::{try_with, SimpleError};
+use std::fs;
+use std::path::Path;
+
+/// A data source and the scanner binary for it
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Hash, Clone, Default)]
+pub struct SourceInfo {
+    /// A short unique name, e.g. `s3`
+    pub name: String,
+    /// The binary that scans this source, e.g. `berkshire_hog`
+    pub binary: String,
+    pub description: String,
+    /// The positional arguments the binary takes, e.g. `<S3URI> <S3REGION>`
+    pub target: String,
+    /// How the scanner authenticates, empty if it needs no credentials
+    #[serde(default)]
+    pub auth: String,
+    /// The environment variables the scanner reads credentials or settings from
+    #[serde(default)]
+    pub env: Vec<String>,
+    /// The options specific to this source, in addition to the options every scanner takes
+    #[serde(default)]
+    pub options: Vec<String>,
+    /// True for the scanners in this repository, false for plugins
+    #[serde(default)]
+    pub builtin: bool,
+}
+
+/// The known data sources, in the order they were added
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+pub struct SourceRegistry {
+    sources: Vec<SourceInfo>,
+}
+
+impl SourceRegistry {
+    /// Create a registry of the scanners in this repository that were built, i.e. whose cargo
+    /// feature is enabled
+    pub fn new() -> Self {
+        let sources: Vec<SourceInfo> = vec![
+            builtin(
+                "gdrive",
+                "ankamali_hog",
+                "Scans for secrets in a Google doc, or in the files of a Google Drive folder or shared drive.",
+                "[GDRIVEID]",
+                "An OAuth client secret file and a token storage file that is created on first use, or a service account key",
+                &[],
+                &[
+                    "--oauthsecret",
+                    "--oauthtoken",
+                    "--service-account",
+                    "--subject",
+                    "--folder",
+                    "--recursive",
+                    "--shared-drive",
+                    "--since-token",
+                    "--include-mime",
+                    "--exclude-mime",
+                    "--threads",
+                    "--checkpoint",
+                    "--identity",
+                ],
+            ),
+            builtin(
+                "s3",
+                "berkshire_hog",
+                "Scans for secrets in an S3 bucket.",
+                "<S3URI> <S3REGION>",
+                "The AWS credential chain (environment, credentials file or instance profile)",
+                &["AWS_ACCESS_KEY_ID", "AWS_SECRET_ACCESS_KEY", "AWS_SESSION_TOKEN"],
+                &[
+                    "--recursive",
+                    "--concurrency",
+                    "--all-versions",
+                    "--inventory-manifest",
+                    "--all-buckets",
+                    "--bucket-regex",
+                    "--bucket-region",
+                    "--outputdir",
+                    "--prefix",
+                    "--include",
+                    "--exclude",
+                    "--profile",
+                    "--role-arn",
+                    "--external-id",
+                    "--sqs-queue",
+                    "--since",
+                    "--until",
+                    "--chunk-threads",
+                    "--max-archive-depth",
+                    "--max-archive-size",
+                    "--checkpoint",
+                ],
+            ),
+            builtin(
+                "gcs",
+                "gcs_hog",
+                "Scans for secrets in a Google Cloud Storage bucket.",
+                "<GSURI>",
+                "Application default credentials (a key file, gcloud user credentials or the instance's service account), or none for public buckets",
+                &["GOOGLE_APPLICATION_CREDENTIALS"],
+                &[
+                    "--recursive",
+                    "--service-account",
+                    "--anonymous",
+                    "--threads",
+                    "--max-size",
+                    "--since",
+                    "--until",
+                    "--chunk-threads",
+                    "--max-archive-depth",
+                    "--max-archive-size",
+                    "--record-fixtures",
+                    "--replay-fixtures",
+                ],
+            ),
+            builtin(
+                "azure_blob",
+                "azure_blob_hog",
+                "Scans for secrets in an Azure Blob Storage container.",
+                "<URL>",
+                "A connection string, a SAS token or a managed identity, or none for public containers",
+                &["AZURE_STORAGE_CONNECTION_STRING", "AZURE_STORAGE_SAS_TOKEN"],
+                &[
+                    "--recursive",
+                    "--connection-string",
+                    "--sas",
+                    "--managed-identity",
+                    "--client-id",
+                    "--threads",
+                    "--max-size",
+                    "--since",
+                    "--until",
+                    "--chunk-threads",
+                    "--max-archive-depth",
+                    "--max-archive-size",
+                    "--record-fixtures",
+                    "--replay-fixtures",
+                ],
+            ),
+            builtin(
+                "git",
+                "choctaw_hog",
+                "Scans for secrets in a Git repository.",
+                "<GITPATH>",
+                "An SSH key (ssh-agent by default) or an HTTPS username and password, for remote repositories",
+                &["GIT_SSH_PASSPHRASE", "GIT_HTTPS_USER", "GIT_HTTPS_PASSWORD"],
+                &[
+                    "--all-branches",
+                    "--branch",
+                    "--author",
+                    "--path",
+                    "--since",
+                    "--until",
+                    "--since-commit",
+                    "--state-file",
+                    "--checkpoint",
+                    "--since_commit",
+                    "--until_commit",
+                    "--stash",
+                    "--reflog",
+                    "--notes",
+                    "--recurse-submodules",
+                    "--max-submodule-depth",
+                    "--staged",
+                    "--working-tree",
+                    "--diff",
+                    "--hook",
+                    "--depth",
+                    "--blobless",
+                    "--sshkeypath",
+                    "--sshkeyphrase",
+                    "--httpsuser",
+                    "--httpspass",
+                    "--identity",
+                    "--tmpdir",
+                    "--tmp-quota",
+                    "--record-fixtures",
+                    "--replay-fixtures",
+                ],
+            ),
+            builtin(
+                "docker",
+                "iberian_hog",
+                "Scans for secrets in the layers of a Docker image.",
+                "<IMAGE>",
+                "Access to the Docker daemon, when exporting a local image",
+                &[],
+                &["--chunk-threads", "--tmpdir", "--tmp-quota"],
+            ),
+            builtin(
+                "registry",
+                "jinhua_hog",
+                "Scans for secrets in container images stored in a Docker / OCI registry.",
+                "<IMAGE>...",
+                "Docker credential helpers, a username and password, or a bearer token",
+                &["REGISTRY_USERNAME", "REGISTRY_PASSWORD", "REGISTRY_TOKEN"],
+                &[
+                    "--username",
+                    "--password",
+                    "--token",
+                    "--platform",
+                    "--chunk-threads",
+                    "--tmpdir",
+                    "--tmp-quota",
+                    "--record-fixtures",
+                    "--replay-fixtures",
+                ],
+            ),
+            builtin(
+                "kubernetes",
+                "kunekune_hog",
+                "Scans for secrets in a Kubernetes cluster's Secrets, ConfigMaps, pod environment variables and annotations.",
+                "",
+                "A kubeconfig",
+                &["KUBECONFIG"],
+                &["--kubeconfig", "--context", "--namespace"],
+            ),
+            builtin(
+                "har",
+                "lacombe_hog",
+                "Scans for secrets in HTTP traffic captures (HAR files).",
+                "<HARFILE>...",
+                "",
+                &[],
+                &[],
+            ),
+            builtin(
+                "host",
+                "mangalitsa_hog",
+                "Finds SSH keys, cloud CLI credentials and database passwords in home directories, and the hosts they grant access to.",
+                "<PATH>...",
+                "",
+                &[],
+                &[],
+            ),
+            builtin(
+                "gitlab",
+                "gitlab_hog",
+                "Scans for secrets in the repositories, snippets and merge request comments of GitLab projects.",
+                "",
+                "A personal access token with the read_api and read_repository scopes",
+                &["GITLAB_TOKEN"],
+                &[
+                    "--url",
+                    "--token",
+                    "--group",
+                    "--project",
+                    "--norepo",
+                    "--identity",
+                    "--outputdir",
+                    "--tmpdir",
+                    "--tmp-quota",
+                    "--record-fixtures",
+                    "--replay-fixtures",
+                ],
+            ),
+            builtin(
+                "bitbucket",
+                "bitbucket_hog",
+                "Scans for secrets in the repositories, pull request comments and pipeline variables of Bitbucket Cloud or Server.",
+                "<WORKSPACE>",
+                "A username and app password (Cloud) or password / HTTP access token (Server), or an OAuth access token",
+                &["BITBUCKET_USERNAME", "BITBUCKET_PASSWORD", "BITBUCKET_TOKEN"],
+                &[
+                    "--url",
+                    "--flavor",
+                    "--username",
+                    "--password",
+                    "--token",
+                    "--norepo",
+                    "--identity",
+                    "--outputdir",
+                    "--tmpdir",
+                    "--tmp-quota",
+                    "--record-fixtures",
+                    "--replay-fixtures",
+                ],
+            ),
+            builtin(
+                "azure_devops",
+                "azure_hog",
+                "Scans for secrets in the repositories, pipelines, variable groups and work items of an Azure DevOps project.",
+                "<ORGANIZATION> <PROJECT>",
+                "A personal access token with read access to Code, Build, Variable Groups and Work Items",
+                &["AZURE_DEVOPS_EXT_PAT"],
+                &[
+                    "--token",
+                    "--apiversion",
+                    "--norepo",
+                    "--identity",
+                    "--tmpdir",
+                    "--tmp-quota",
+                    "--record-fixtures",
+                    "--replay-fixtures",
+                ],
+            ),
+            builtin(
+                "github",
+                "github_hog",
+                "Scans for secrets in the repositories, wikis, issues and pull request comments of a GitHub organization.",
+                "[ORGANIZATION]",
+                "A personal access token with the repo scope",
+                &["GITHUB_TOKEN"],
+                &[
+                    "--url",
+                    "--token",
+                    "--include",
+                    "--exclude",
+                    "--threads",
+                    "--target-latency",
+                    "--min-threads",
+                    "--norepo",
+                    "--nowiki",
+                    "--gists",
+                    "--gistuser",
+                    "--mygists",
+                    "--identity",
+                    "--outputdir",
+                    "--tmpdir",
+                    "--tmp-quota",
+                    "--record-fixtures",
+                    "--replay-fixtures",
+                ],
+            ),
+            builtin(
+                "github_actions",
+                "ningxiang_hog",
+                "Scans for secrets in the GitHub Actions logs of a repository or organization.",
+                "<TARGET>",
+                "A personal access token with the repo scope",
+                &["GITHUB_TOKEN"],
+                &[
+                    "--url",
+                    "--token",
+                    "--include",
+                    "--exclude",
+                    "--since",
+                    "--until",
+                    "--failed",
+                    "--outputdir",
+                    "--record-fixtures",
+                    "--replay-fixtures",
+                ],
+            ),
+            builtin(
+                "jenkins",
+                "jenkins_hog",
+                "Scans for secrets in the job configurations and build logs of a Jenkins controller.",
+                "<URL>",
+                "A username and API token, or anonymous access",
+                &["JENKINS_USER", "JENKINS_TOKEN"],
+                &[
+                    "--username",
+                    "--token",
+                    "--include",
+                    "--exclude",
+                    "--builds",
+                    "--noconfig",
+                    "--since",
+                    "--until",
+                    "--outputdir",
+                    "--record-fixtures",
+                    "--replay-fixtures",
+                ],
+            ),
+            builtin(
+                "teams",
+                "teams_hog",
+                "Scans for secrets in the channel messages and replies of Microsoft Teams.",
+                "",
+                "An Azure AD application with a client secret and the Team.ReadBasic.All, Channel.ReadBasic.All and ChannelMessage.Read.All application permissions",
+                &["AZURE_TENANT_ID", "AZURE_CLIENT_ID", "AZURE_CLIENT_SECRET"],
+                &[
+                    "--tenant",
+                    "--clientid",
+                    "--clientsecret",
+                    "--include",
+                    "--exclude",
+                    "--noreplies",
+                    "--since",
+                    "--until",
+                    "--outputdir",
+                    "--record-fixtures",
+                    "--replay-fixtures",
+                ],
+            ),
+            builtin(
+                "discord",
+                "discord_hog",
+                "Scans for secrets in the messages and attachments of a Discord server.",
+                "<GUILD>",
+                "A bot token, for a bot in the server with the Message Content intent",
+                &["DISCORD_TOKEN"],
+                &[
+                    "--token",
+                    "--include",
+                    "--exclude",
+                    "--noattachments",
+                    "--maxattachment",
+                    "--since",
+                    "--until",
+                    "--record-fixtures",
+                    "--replay-fixtures",
+                ],
+            ),
+            builtin(
+                "cloudwatch",
+                "cloudwatch_hog",
+                "Scans for secrets in the log events of CloudWatch Logs groups.",
+                "<REGION>",
+                "The AWS credential chain (environment, credentials file or instance profile)",
+                &["AWS_ACCESS_KEY_ID", "AWS_SECRET_ACCESS_KEY", "AWS_SESSION_TOKEN"],
+                &[
+                    "--log-group",
+                    "--log-group-prefix",
+                    "--filter-pattern",
+                    "--profile",
+                    "--role-arn",
+                    "--external-id",
+                    "--since",
+                    "--until",
+                    "--record-fixtures",
+                    "--replay-fixtures",
+                ],
+            ),
+            builtin(
+                "aws_lambda",
+                "lambda_hog",
+                "Scans for secrets in the environment variables and deployment packages of AWS Lambda functions.",
+                "<REGION>",
+                "The AWS credential chain (environment, credentials file or instance profile)",
+                &["AWS_ACCESS_KEY_ID", "AWS_SECRET_ACCESS_KEY", "AWS_SESSION_TOKEN"],
+                &[
+                    "--function",
+                    "--code",
+                    "--max-size",
+                    "--profile",
+                    "--role-arn",
+                    "--external-id",
+                    "--record-fixtures",
+                    "--replay-fixtures",
+                ],
+            ),
+            builtin(
+                "ec2",
+                "ec2_hog",
+                "Scans for secrets in the user data of EC2 instances and launch templates.",
+                "<REGION>",
+                "The AWS credential chain (environment, credentials file or instance profile)",
+                &["AWS_ACCESS_KEY_ID", "AWS_SECRET_ACCESS_KEY", "AWS_SESSION_TOKEN"],
+                &[
+                    "--noinstances",
+                    "--notemplates",
+                    "--profile",
+                    "--role-arn",
+                    "--external-id",
+                    "--record-fixtures",
+                    "--replay-fixtures",
+                ],
+            ),
+            builtin(
+                "dynamodb",
+                "dynamo_hog",
+                "Scans for secrets in the items of DynamoDB tables.",
+                "<REGION> <TABLE>...",
+                "The AWS credential chain (environment, credentials file or instance profile)",
+                &["AWS_ACCESS_KEY_ID", "AWS_SECRET_ACCESS_KEY", "AWS_SESSION_TOKEN"],
+                &[
+                    "--segments",
+                    "--profile",
+                    "--role-arn",
+                    "--external-id",
+                    "--record-fixtures",
+                    "--replay-fixtures",
+                ],
+            ),
+            builtin(
+                "proc",
+                "proc_hog",
+                "Scans for secrets in the environment variables and command lines of the running processes of a Linux host.",
+                "",
+                "Read access to /proc/<pid>/environ (root, to scan the processes of other users)",
+                &[],
+                &["--pid", "--proc-root", "--noenv", "--nocmdline"],
+            ),
+            builtin(
+                "hive",
+                "hive_hog",
+                "Scans for secrets in the values of offline Windows registry hives.",
+                "<PATH>...",
+                "",
+                &[],
+                &[],
+            ),
+            builtin(
+                "dump",
+                "dump_hog",
+                "Scans for secrets in the strings of core dumps, minidumps and memory images.",
+                "<PATH>...",
+                "",
+                &[],
+                &["--min-length"],
+            ),
+            builtin(
+                "pcap",
+                "pcap_hog",
+                "Scans for plaintext credentials in the TCP streams and UDP flows of packet captures.",
+                "<PATH>...",
+                "",
+                &[],
+                &[],
+            ),
+            builtin(
+                "mail",
+                "mail_hog",
+                "Scans for secrets in the bodies and attachments of mbox files, Maildir folders and IMAP mailboxes.",
+                "[PATH]...",
+                "The password of the IMAP user, for --imap",
+                &["IMAP_PASSWORD"],
+                &["--imap", "--imap-password", "--ca-bundle"],
+            ),
+            builtin(
+                "package",
+                "pkg_hog",
+                "Scans for secrets in the published artifacts of npm, PyPI and Cargo packages, and in vendored crates.",
+                "[PACKAGE]...",
+                "A bearer token for private npm registries, or none for public packages",
+                &["NPM_TOKEN"],
+                &[
+                    "--lockfile",
+                    "--npm-registry",
+                    "--npm-token",
+                    "--pypi-index",
+                    "--crates-registry",
+                    "--crate-path",
+                    "--max-size",
+                    "--proxy",
+                    "--ca-bundle",
+                ],
+            ),
+            builtin(
+                "paste",
+                "paste_hog",
+                "Monitors paste sites for new pastes with secrets or an organization's keywords.",
+                "",
+                "None for public scraping APIs, which may only answer allowlisted IP addresses",
+                &[],
+                &[
+                    "--endpoint",
+                    "--raw-url",
+                    "--keyword",
+                    "--keywords-file",
+                    "--interval",
+                    "--once",
+                    "--max-size",
+                    "--since",
+                    "--until",
+                    "--record-fixtures",
+                    "--replay-fixtures",
+                    "--proxy",
+                    "--ca-bundle",
+                ],
+            ),
+            builtin(
+                "sharepoint",
+                "sharepoint_hog",
+                "Scans for secrets in the files of SharePoint document libraries and OneDrive for Business.",
+                "",
+                "An Azure AD application with a client secret and the Sites.Read.All and Files.Read.All application permissions (and User.Read.All for --all-onedrives)",
+                &["AZURE_TENANT_ID", "AZURE_CLIENT_ID", "AZURE_CLIENT_SECRET"],
+                &[
+                    "--tenant",
+                    "--clientid",
+                    "--clientsecret",
+                    "--site",
+                    "--all-sites",
+                    "--user",
+                    "--all-onedrives",
+                    "--include",
+                    "--exclude",
+                    "--delta-state",
+                    "--threads",
+                    "--max-size",
+                    "--since",
+                    "--until",
+                    "--chunk-threads",
+                    "--record-fixtures",
+                    "--replay-fixtures",
+                    "--proxy",
+                    "--ca-bundle",
+                ],
+            ),
+            builtin(
+                "dropbox",
+                "dropbox_hog",
+                "Scans for secrets in the files of a Dropbox folder, or of every member of a Dropbox team.",
+                "[PATH]",
+                "An app token with the files.content.read scope, or a team token with the members.read and team_data.member scopes for --team",
+                &["DROPBOX_TOKEN"],
+                &[
+                    "--token",
+                    "--team",
+                    "--member",
+                    "--include-ext",
+                    "--exclude-ext",
+                    "--threads",
+                    "--max-size",
+                    "--since",
+                    "--until",
+                    "--chunk-threads",
+                    "--record-fixtures",
+                    "--replay-fixtures",
+                    "--proxy",
+                    "--ca-bundle",
+                ],
+            ),
+            builtin(
+                "notion",
+                "notion_hog",
+                "Scans for secrets in the pages and databases shared with a Notion integration.",
+                "",
+                "An internal integration token with the Read content capability, with the pages to scan shared with the integration",
+                &["NOTION_TOKEN"],
+                &[
+                    "--token",
+                    "--include",
+                    "--exclude",
+                    "--since",
+                    "--until",
+                    "--record-fixtures",
+                    "--replay-fixtures",
+                    "--proxy",
+                    "--ca-bundle",
+                ],
+            ),
+            builtin(
+                "zendesk",
+                "zendesk_hog",
+                "Scans for secrets in the subject, custom fields and comments of Zendesk tickets.",
+                "<INSTANCE>",
+                "An API token of a Zendesk admin, for the incremental export API",
+                &["ZENDESK_EMAIL", "ZENDESK_API_TOKEN"],
+                &[
+                    "--email",
+                    "--token",
+                    "--brand",
+                    "--since",
+                    "--until",
+                    "--record-fixtures",
+                    "--replay-fixtures",
+                    "--proxy",
+                    "--ca-bundle",
+                ],
+            ),
+            builtin(
+                "filesystem",
+                "duroc_hog",
+                "Scans for secrets in files and directories, or in the data piped to it on stdin.",
+                "<PATH>...",
+                "",
+                &[],
+                &[
+                    "--label",
+                    "--max-file-size",
+                    "--include-ext",
+                    "--exclude-ext",
+                    "--respect-ignore",
+                    "--follow-symlinks",
+                    "--max-depth",
+                    "--watch",
+                    "--chunk-threads",
+                    "--max-archive-depth",
+                    "--max-archive-size",
+                ],
+            ),
+        ];
+        Self {
+            sources: sources.into_iter().filter(|s| is_built(&s.name)).collect(),
+        }
+    }
+
+    /// Adds a source, failing if one with the same name is already registered
+    pub fn register(&mut self, source: SourceInfo) -> Result<(), SimpleError> {
+        if self.get(&source.name).is_some() {
+            return Err(SimpleError::new(format!(
+                "A source named {} is already registered",
+                source.name
+            )));
+        }
+        self.sources.push(source);
+        Ok(())
+    }
+
+    /// Registers every `*.json` file in `dir` as a plugin, returning how many were added
+    pub fn load_plugins(&mut self, dir: &Path) -> Result<usize, SimpleError> {
+        let mut count = 0;
+        for entry in try_with!(fs::read_dir(dir), "Failed to read {}", dir.display()) {
+            let path = try_with!(entry, "Failed to read {}", dir.display()).path();
+            if path.extension().map_or(true, |e| e != "json") {
+                continue;
+            }
+            let json = try_with!(fs::read_to_string(&path), "Failed to read {:?}", path);
+            let mut source: SourceInfo =
+                try_with!(serde_json::from_str(&json), "Invalid plugin {:?}", path);
+            source.builtin = false;
+            debug!("Loaded plugin {} from {}", source.name, path.display());
+            self.register(source)?;
+            count += 1;
+        }
+        Ok(count)
+    }
+
+    /// Returns the source named `name`
+    pub fn get(&self, name: &str) -> Option<&SourceInfo> {
+        self.sources.iter().find(|s| s.name == name)
+    }
+
+    /// Returns every registered source
+    pub fn sources(&self) -> &[SourceInfo] {
+        &self.sources
+    }
+}
+
+impl Default for SourceRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn builtin(
+    name: &str,
+    binary: &str,
+    description: &str,
+    target: &str,
+    auth: &str,
+    env: &[&str],
+    options: &[&str],
+) -> SourceInfo {
+    SourceInfo {
+        name: name.to_string(),
+        binary: binary.to_string(),
+        description: description.to_string(),
+        target: target.to_string(),
+        auth: auth.to_string(),
+        env: env.iter().map(|s| s.to_string()).collect(),
+        options: options.iter().map(|s| s.to_string()).collect(),
+        builtin: true,
+    }
+}
+
+/// Whether the scanner for a builtin source was built
+fn is_built(name: &str) -> bool {
+    match name {
+        "gdrive" => cfg!(feature = "gdrive"),
+        "s3" => cfg!(feature = "s3"),
+        "git" => cfg!(feature = "git"),
+        "docker" => cfg!(feature = "docker"),
+        "registry" => cfg!(feature = "registry"),
+        "kubernetes" => cfg!(feature = "kubernetes"),
+        "har" => cfg!(feature = "har"),
+        "host" => cfg!(feature = "host"),
+        "gitlab" => cfg!(feature = "gitlab"),
+        "bitbucket" => cfg!(feature = "bitbucket"),
+        "azure_devops" => cfg!(feature = "azure_devops"),
+        "github" | "github_actions" => cfg!(feature = "github"),
+        "jenkins" => cfg!(feature = "jenkins"),
+        "teams" => cfg!(feature = "teams"),
+        "discord" => cfg!(feature = "discord"),
+        "gcs" => cfg!(feature = "gcs"),
+        "azure_blob" => cfg!(feature = "azure_blob"),
+        "cloudwatch" => cfg!(feature = "cloudwatch"),
+        "aws_lambda" => cfg!(feature = "aws_lambda"),
+        "ec2" => cfg!(feature = "ec2"),
+        "dynamodb" => cfg!(feature = "dynamodb"),
+        "proc" => cfg!(feature = "proc"),
+        "hive" => cfg!(feature = "hive"),
+        "dump" => cfg!(feature = "dump"),
+        "pcap" => cfg!(feature = "pcap"),
+        "mail" => cfg!(feature = "mail"),
+        "package" => cfg!(feature = "package"),
+        "paste" => cfg!(feature = "paste"),
+        "sharepoint" => cfg!(feature = "sharepoint"),
+        "dropbox" => cfg!(feature = "dropbox"),
+        "notion" => cfg!(feature = "notion"),
+        "zendesk" => cfg!(feature = "zendesk"),
+        "filesystem" => cfg!(feature = "filesystem"),
+        _ => true,
+    }
+}
@@ -0,0 +1,523 @@
+//! A reader for SQLite database files, enough to list their tables and read their rows.
+//!
+//! Browser profiles, mobile app data directories and desktop applications keep tokens, cookies and
+//! saved credentials in SQLite databases. `Database` reads the file format directly (the schema
+//! table, the b-tree of each table and its overflow pages), so no SQLite library is needed and the
+//! file is never opened for writing. Only the main database file is read: changes still in its
+//! `-wal` journal and deleted rows aren't seen. Virtual tables and `WITHOUT ROWID` tables are
+//! skipped.
+//!
+//! `structured_scanning` uses it to scan databases cell by cell with `--structured`.
+//!
+//! # Examples
+//!
+//! ```
+//! use rusty_hogs::sqlite::{Database, SqlValue};
+//! use rusty_hogs::structured_scanning::{scan_document, StructuredFormat};
+//! use rusty_hogs::SecretScannerBuilder;
+//!
+//! // A record of (serial type, bytes) columns, and a table b-tree leaf page of (rowid, record)
+//! // cells, small enough for each size to fit in one byte
+//! fn record(columns: &[(u8, &[u8])]) -> Vec<u8> {
+//!     let mut record = vec![columns.len() as u8 + 1];
+//!     record.extend(columns.iter().map(|(serial_type, _)| *serial_type));
+//!     record.extend(columns.iter().flat_map(|(_, value)| value.to_vec()));
+//!     record
+//! }
+//! fn leaf_page(page: &mut [u8], header: usize, cells: &[(u8, Vec<u8>)]) {
+//!     page[header] = 0x0D;
+//!     page[header + 4] = cells.len() as u8;
+//!     let mut end = page.len();
+//!     for (i, (rowid, record)) in cells.iter().enumerate() {
+//!         let cell = [&[record.len() as u8, *rowid][..], record].concat();
+//!         end -= cell.len();
+//!         page[end..end + cell.len()].copy_from_slice(&cell);
+//!         let pointer = header + 8 + i * 2;
+//!         page[pointer..pointer + 2].copy_from_slice(&(end as u16).to_be_bytes());
+//!     }
+//!     page[header + 5..header + 7].copy_from_slice(&(end as u16).to_be_bytes());
+//! }
+//! let text = |s: &'static str| ((s.len() * 2 + 13) as u8, s.as_bytes());
+//!
+//! let mut file = vec![0u8; 1024];
+//! file[..16].copy_from_slice(b"SQLite format 3\0");
+//! file[16..18].copy_from_slice(&512u16.to_be_bytes());
+//! file[59] = 1; // UTF-8
+//! let sql = "CREATE TABLE users(id INTEGER PRIMARY KEY, token TEXT)";
+//! let schema = record(&[text("table"), text("users"), text("users"), (1, &[2]), text(sql)]);
+//! leaf_page(&mut file[..512], 100, &[(1, schema)]);
+//! let row = record(&[(0, &[]), text("hunter2hunter2")]);
+//! leaf_page(&mut file[512..], 0, &[(7, row)]);
+//!
+//! let db = Database::parse(&file).unwrap();
+//! let tables = db.tables().unwrap();
+//! assert_eq!(tables[0].name, "users");
+//! assert_eq!(tables[0].columns, vec!["id", "token"]);
+//! let rows = db.rows(&tables[0]).unwrap();
+//! assert_eq!(rows[0].rowid, 7);
+//! assert_eq!(rows[0].values[0], SqlValue::Integer(7));
+//! assert_eq!(rows[0].values[1], SqlValue::Text(String::from("hunter2hunter2")));
+//!
+//! // databases are recognised by their header, whatever their name
+//! let format = StructuredFormat::detect("Login Data", &file).unwrap();
+//! let ss = SecretScannerBuilder::new().build();
+//! let matches = scan_document(&ss, &file, format, false).unwrap();
+//! assert_eq!(matches[0].key_path, "users.token@7");
+//! assert_eq!(matches[0].reason, "Sensitive key name");
+//! ```
+
+use log::{self, debug};
+use simple_error::{require_with, SimpleError};
+use std::collections::HashSet;
+use std::convert::TryInto;
+
+/// Every SQLite database file starts with this header string
+pub const SQLITE_MAGIC: &[u8] = b"SQLite format 3\0";
+
+/// The b-tree page types of tables
+const INTERIOR_TABLE_PAGE: u8 = 0x05;
+const LEAF_TABLE_PAGE: u8 = 0x0D;
+
+/// The text encodings of the database header
+const ENCODING_UTF16LE: u32 = 2;
+const ENCODING_UTF16BE: u32 = 3;
+
+/// A value of a cell
+#[derive(Debug, PartialEq, Clone)]
+pub enum SqlValue {
+    Null,
+    Integer(i64),
+    Real(f64),
+    Text(String),
+    Blob(Vec<u8>),
+}
+
+impl SqlValue {
+    /// The value as text, for scanning: numbers in decimal and blobs as UTF-8 (invalid sequences
+    /// replaced). NULL has no text.
+    pub fn to_text(&self) -> Option<String> {
+        match self {
+            SqlValue::Null => None,
+            SqlValue::Integer(i) => Some(i.to_string()),
+            SqlValue::Real(r) => Some(r.to_string()),
+            SqlValue::Text(t) => Some(t.clone()),
+            SqlValue::Blob(b) => Some(String::from_utf8_lossy(b).into_owned()),
+        }
+    }
+}
+
+/// A table of the schema
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Default)]
+pub struct Table {
+    pub name: String,
+    /// The names of the columns, from the `CREATE TABLE` statement
+    pub columns: Vec<String>,
+    /// The page of the root of the table's b-tree
+    pub root_page: u32,
+    /// The `INTEGER PRIMARY KEY` column, which holds the rowid rather than a value of its own
+    pub rowid_column: Option<usize>,
+}
+
+/// A row of a table, with a value for each column
+#[derive(Debug, PartialEq, Clone)]
+pub struct Row {
+    pub rowid: i64,
+    pub values: Vec<SqlValue>,
+}
+
+/// A SQLite database file in memory
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+pub struct Database<'a> {
+    data: &'a [u8],
+    page_size: usize,
+    /// The size of each page less the bytes reserved for extensions
+    usable_size: usize,
+    encoding: u32,
+}
+
+impl<'a> Database<'a> {
+    /// Checks the header of a database file
+    pub fn parse(data: &'a [u8]) -> Result<Self, SimpleError> {
+        if data.len() < 100 || !data.starts_with(SQLITE_MAGIC) {
+            return Err(SimpleError::new("Not a SQLite database"));
+        }
+        let page_size = match u16::from_be_bytes([data[16], data[17]]) {
+            1 => 65536,
+            s => s as usize,
+        };
+        if page_size < 512 || !page_size.is_power_of_two() {
+            return Err(SimpleError::new(format!(
+                "Invalid SQLite page size {}",
+                page_size
+            )));
+        }
+        let usable_size = page_size - data[20] as usize;
+        if usable_size < 480 {
+            return Err(SimpleError::new("Invalid SQLite reserved space"));
+        }
+        Ok(Self {
+            data,
+            page_size,
+            usable_size,
+            encoding: be32(&data[56..60]),
+        })
+    }
+
+    /// Lists the tables of the schema, other than SQLite's own, virtual and `WITHOUT ROWID` tables
+    pub fn tables(&self) -> Result<Vec<Table>, SimpleError> {
+        let mut tables: Vec<Table> = Vec::new();
+        for row in self.read_tree(1)? {
+            let text = |i: usize| match row.values.get(i) {
+                Some(SqlValue::Text(t)) => t.clone(),
+                _ => String::new(),
+            };
+            let name = text(1);
+            let sql = text(4);
+            if text(0) != "table" || name.starts_with("sqlite_") {
+                continue;
+            }
+            let root_page = match row.values.get(3) {
+                Some(SqlValue::Integer(p)) if *p > 0 => *p as u32,
+                _ => {
+                    debug!("Skipping the virtual table {}", name);
+                    continue;
+                }
+            };
+            if sql.to_ascii_uppercase().contains("WITHOUT ROWID") {
+                debug!("Skipping the WITHOUT ROWID table {}", name);
+                continue;
+            }
+            let (columns, rowid_column) = parse_columns(&sql);
+            tables.push(Table {
+                name,
+                columns,
+                root_page,
+                rowid_column,
+            });
+        }
+        Ok(tables)
+    }
+
+    /// Reads the rows of a table, in rowid order. Rows written before columns were added have
+    /// NULL values for them.
+    pub fn rows(&self, table: &Table) -> Result<Vec<Row>, SimpleError> {
+        let mut rows = self.read_tree(table.root_page)?;
+        for row in rows.iter_mut() {
+            if row.values.len() < table.columns.len() {
+                row.values.resize(table.columns.len(), SqlValue::Null);
+            }
+            if let Some(column) = table.rowid_column {
+                if let Some(value) = row.values.get_mut(column) {
+                    if *value == SqlValue::Null {
+                        *value = SqlValue::Integer(row.rowid);
+                    }
+                }
+            }
+        }
+        Ok(rows)
+    }
+
+    /// Reads every row of the table b-tree rooted at `root`
+    fn read_tree(&self, root: u32) -> Result<Vec<Row>, SimpleError> {
+        let mut rows: Vec<Row> = Vec::new();
+        let mut visited: HashSet<u32> = HashSet::new();
+        let mut pages: Vec<u32> = vec![root];
+        while let Some(number) = pages.pop() {
+            if !visited.insert(number) {
+                return Err(SimpleError::new(format!(
+                    "SQLite page {} is linked twice",
+                    number
+                )));
+            }
+            let page = self.page(number)?;
+            // the first page starts with the database header
+            let header = if number == 1 { 100 } else { 0 };
+            let kind = page[header];
+            let cells = u16::from_be_bytes([page[header + 3], page[header + 4]]) as usize;
+            let pointers = header + if kind == INTERIOR_TABLE_PAGE { 12 } else { 8 };
+            let pointer = |i: usize| -> Result<usize, SimpleError> {
+                let p = require_with!(
+                    page.get(pointers + i * 2..pointers + i * 2 + 2),
+                    "SQLite page {} has too many cells",
+                    number
+                );
+                Ok(u16::from_be_bytes([p[0], p[1]]) as usize)
+            };
+            match kind {
+                LEAF_TABLE_PAGE => {
+                    for i in 0..cells {
+                        rows.push(self.leaf_cell(page, pointer(i)?, number)?);
+                    }
+                }
+                INTERIOR_TABLE_PAGE => {
+                    // pushed in reverse, so that the rows come out in rowid order
+                    pages.push(be32(&page[header + 8..header + 12]));
+                    for i in (0..cells).rev() {
+                        let cell = pointer(i)?;
+                        let child = require_with!(
+                            page.get(cell..cell + 4),
+                            "SQLite page {} has a truncated cell",
+                            number
+                        );
+                        pages.push(be32(child));
+                    }
+                }
+                _ => {
+                    return Err(SimpleError::new(format!(
+                        "SQLite page {} isn't a table page",
+                        number
+                    )))
+                }
+            }
+        }
+        Ok(rows)
+    }
+
+    /// Reads the row in the cell at `offset` of a leaf page
+    fn leaf_cell(&self, page: &[u8], offset: usize, number: u32) -> Result<Row, SimpleError> {
+        let cell = require_with!(page.get(offset..), "SQLite page {} is truncated", number);
+        let (size, n1) = require_with!(varint(cell), "SQLite page {} is truncated", number);
+        let (rowid, n2) = require_with!(varint(&cell[n1..]), "SQLite page {} is truncated", number);
+        let payload = self.payload(&cell[n1 + n2..], size as usize, number)?;
+        Ok(Row {
+            rowid,
+            values: decode_record(&payload, self.encoding)?,
+        })
+    }
+
+    /// Reads a payload of `size` bytes that starts at the beginning of `cell`, following its
+    /// overflow pages
+    fn payload(&self, cell: &[u8], size: usize, number: u32) -> Result<Vec<u8>, SimpleError> {
+        // the amount stored on the page itself, as set by the file format
+        let usable = self.usable_size;
+        let max_local = usable - 35;
+        let local = if size <= max_local {
+            size
+        } else {
+            let min_local = (usable - 12) * 32 / 255 - 23;
+            let k = min_local + (size - min_local) % (usable - 4);
+            if k <= max_local {
+                k
+            } else {
+                min_local
+            }
+        };
+        let mut payload = require_with!(
+            cell.get(..local),
+            "SQLite page {} has a truncated cell",
+            number
+        )
+        .to_vec();
+        if local == size {
+            return Ok(payload);
+        }
+        let mut next = be32(require_with!(
+            cell.get(local..local + 4),
+            "SQLite page {} has a truncated cell",
+            number
+        ));
+        let mut remaining = self.data.len() / self.page_size;
+        while payload.len() < size {
+            if remaining == 0 {
+                return Err(SimpleError::new("SQLite overflow pages loop"));
+            }
+            remaining -= 1;
+            let page = self.page(next)?;
+            let take = (size - payload.len()).min(usable - 4);
+            payload.extend_from_slice(&page[4..4 + take]);
+            next = be32(&page[..4]);
+        }
+        Ok(payload)
+    }
+
+    /// The page `number`, counting from 1
+    fn page(&self, number: u32) -> Result<&'a [u8], SimpleError> {
+        let start = (number.max(1) as usize - 1) * self.page_size;
+        Ok(require_with!(
+            self.data
+                .get(start..start + self.page_size)
+                .filter(|_| number > 0),
+            "SQLite page {} is past the end of the file",
+            number
+        ))
+    }
+}
+
+/// Reads a big-endian u32
+fn be32(data: &[u8]) -> u32 {
+    u32::from_be_bytes(data[..4].try_into().unwrap())
+}
+
+/// Reads a SQLite varint (1 to 9 bytes, big-endian), returning it and its length
+fn varint(data: &[u8]) -> Option<(i64, usize)> {
+    let mut value: u64 = 0;
+    for (i, byte) in data.iter().enumerate().take(9) {
+        if i == 8 {
+            return Some((((value << 8) | *byte as u64) as i64, 9));
+        }
+        value = (value << 7) | (*byte & 0x7F) as u64;
+        if byte & 0x80 == 0 {
+            return Some((value as i64, i + 1));
+        }
+    }
+    None
+}
+
+/// Decodes a record: a header of serial types, followed by the values
+fn decode_record(payload: &[u8], encoding: u32) -> Result<Vec<SqlValue>, SimpleError> {
+    let (header_size, mut offset) =
+        require_with!(varint(payload), "Truncated SQLite record header");
+    let header_size = header_size as usize;
+    let mut body = header_size;
+    let mut values: Vec<SqlValue> = Vec::new();
+    while offset < header_size {
+        let (serial_type, n) = require_with!(
+            payload.get(offset..header_size).and_then(varint),
+            "Truncated SQLite record header"
+        );
+        offset += n;
+        let size = match serial_type {
+            0 | 8 | 9 => 0,
+            1..=4 => serial_type as usize,
+            5 => 6,
+            6 | 7 => 8,
+            t if t >= 12 => (t as usize - 12) / 2,
+            t => {
+                return Err(SimpleError::new(format!(
+                    "Invalid SQLite serial type {}",
+                    t
+                )))
+            }
+        };
+        let bytes = require_with!(payload.get(body..body + size), "Truncated SQLite record");
+        body += size;
+        values.push(match serial_type {
+            0 => SqlValue::Null,
+            8 => SqlValue::Integer(0),
+            9 => SqlValue::Integer(1),
+            1..=6 => {
+                // big-endian two's complement, sign extended
+                let mut value: i64 = if bytes[0] & 0x80 != 0 { -1 } else { 0 };
+                for byte in bytes {
+                    value = (value << 8) | *byte as i64;
+                }
+                SqlValue::Integer(value)
+            }
+            7 => SqlValue::Real(f64::from_bits(u64::from_be_bytes(
+                bytes.try_into().unwrap(),
+            ))),
+            t if t % 2 == 0 => SqlValue::Blob(bytes.to_vec()),
+            _ => SqlValue::Text(decode_text(bytes, encoding)),
+        });
+    }
+    Ok(values)
+}
+
+/// Decodes a text value in the encoding of the database
+fn decode_text(bytes: &[u8], encoding: u32) -> String {
+    if encoding != ENCODING_UTF16LE && encoding != ENCODING_UTF16BE {
+        return String::from_utf8_lossy(bytes).into_owned();
+    }
+    let units = bytes.chunks_exact(2).map(|c| {
+        if encoding == ENCODING_UTF16LE {
+            u16::from_le_bytes([c[0], c[1]])
+        } else {
+            u16::from_be_bytes([c[0], c[1]])
+        }
+    });
+    std::char::decode_utf16(units)
+        .map(|c| c.unwrap_or(std::char::REPLACEMENT_CHARACTER))
+        .collect()
+}
+
+/// Reads the column names of a `CREATE TABLE` statement, and which of them is an alias of the
+/// rowid (an `INTEGER PRIMARY KEY`)
+fn parse_columns(sql: &str) -> (Vec<String>, Option<usize>) {
+    let start = sql.find('(').map_or(sql.len(), |s| s + 1);
+    let end = sql.rfind(')').filter(|e| *e >= start).unwrap_or(start);
+    let mut definitions: Vec<String> = Vec::new();
+    let mut definition = String::new();
+    let mut depth = 0;
+    let mut quote: Option<char> = None;
+    for c in sql[start..end].chars() {
+        match quote {
+            Some(q) if c == q => quote = None,
+            Some(_) => (),
+            None => match c {
+                '"' | '\'' | '`' => quote = Some(c),
+                '[' => quote = Some(']'),
+                '(' => depth += 1,
+                ')' => depth -= 1,
+                ',' if depth == 0 => {
+                    definitions.push(std::mem::take(&mut definition));
+                    continue;
+                }
+                _ => (),
+            },
+        }
+        definition.push(c);
+    }
+    definitions.push(definition);
+
+    let mut columns: Vec<String> = Vec::new();
+    let mut types: Vec<String> = Vec::new();
+    let mut rowid_column: Option<usize> = None;
+    let mut primary_key: Option<String> = None;
+    for definition in definitions.iter().map(|d| d.trim()) {
+        let upper = definition.to_ascii_uppercase();
+        let constraint = ["CONSTRAINT", "PRIMARY", "UNIQUE", "CHECK", "FOREIGN"]
+            .iter()
+            .any(|k| upper.starts_with(k) && !upper[k.len()..].starts_with(char::is_alphanumeric));
+        if constraint {
+            // a table constraint such as PRIMARY KEY (id)
+            if let (Some(key), Some(open)) = (upper.find("PRIMARY KEY"), definition.find('(')) {
+                let close = definition.rfind(')').unwrap_or(definition.len());
+                if key < open && open < close && !definition[open..close].contains(',') {
+                    primary_key = Some(unquote(definition[open + 1..close].trim()).0);
+                }
+            }
+            continue;
+        }
+        if definition.is_empty() {
+            continue;
+        }
+        let (name, rest) = unquote(definition);
+        let column_type = rest
+            .split_whitespace()
+            .next()
+            .unwrap_or("")
+            .to_ascii_uppercase();
+        if column_type == "INTEGER" && rest.to_ascii_uppercase().contains("PRIMARY KEY") {
+            rowid_column = Some(columns.len());
+        }
+        columns.push(name);
+        types.push(column_type);
+    }
+    if let Some(key) = primary_key {
+        let column = columns.iter().position(|c| c.eq_ignore_ascii_case(&key));
+        if let Some(column) = column.filter(|c| types[*c] == "INTEGER") {
+            rowid_column = Some(column);
+        }
+    }
+    (columns, rowid_column)
+}
+
+/// Splits the identifier at the start of `text`, which may be quoted, from the rest of the text
+fn unquote(text: &str) -> (String, &str) {
+    let close = match text.chars().next() {
+        Some('"') => '"',
+        Some('`') => '`',
+        Some('\'') => '\'',
+        Some('[') => ']',
+        _ => {
+            let end = text
+                .find(|c: char| c.is_whitespace() || c == '(')
+                .unwrap_or(text.len());
+            return (text[..end].to_string(), &text[end..]);
+        }
+    };
+    match text[1..].find(close) {
+        Some(end) => (text[1..end + 1].to_string(), &text[end + 2..]),
+        None => (text[1..].to_string(), ""),
+    }
+}
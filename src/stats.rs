@@ -0,0 +1,350 @@
+//! Program-level metrics computed from the reports of repeated scans.
+//!
+//! Rusty Hog keeps no database of findings: each run writes a report. Keeping the JSON reports of
+//! a scheduled scan gives the history needed to measure a secret scanning program, without sending
+//! anything anywhere. `FindingStats::compute` takes those reports in any order and treats each one
+//! as a snapshot of the open findings at the time of the scan:
+//!
+//! * a finding is identified across scans by its rule, location, strings found and commit (see
+//!   `reporting::finding_id`)
+//! * a finding is open if it is in the latest scan, and remediated otherwise. It was remediated at
+//!   the time of the first scan after the last one it appeared in, and the time to remediate is
+//!   measured from the first scan it appeared in.
+//! * the source of a finding is its repository, project, bucket, image, job, team or namespace,
+//!   or its path for scanners that don't have one (such as Choctaw Hog)
+//!
+//! Reports don't record how many commits were scanned, so findings per 1,000 commits are only
+//! computed when the commit count is given.
+//!
+//! `FindingsDiff::compute` compares two scans, for reports that list the net new secrets of a
+//! scheduled scan rather than every open finding each time.
+//!
+//! `FindingsMerge::compute` consolidates the reports of different scanners and runs, normalized
+//! with `reporting::normalize_report`, into one list where each finding appears once, counting
+//! the findings of each report, source and rule.
+//!
+//! # Examples
+//!
+//! ```
+//! use chrono::{TimeZone, Utc};
+//! use rusty_hogs::stats::{FindingStats, FindingsDiff, FindingsMerge, ScanReport};
+//! use serde_json::json;
+//!
+//! let token = json!({
+//!     "reason": "Slack Token",
+//!     "stringsFound": ["xoxp-1"],
+//!     "path": "a.py",
+//!     "repository": "org/app"
+//! });
+//! let email = json!({
+//!     "reason": "Email address",
+//!     "stringsFound": ["a@b.co"],
+//!     "path": "b.py",
+//!     "repository": "org/web"
+//! });
+//! let scans = vec![
+//!     ScanReport {
+//!         date: Utc.ymd(2020, 6, 1).and_hms(0, 0, 0),
+//!         findings: vec![token, email.clone()],
+//!     },
+//!     ScanReport {
+//!         date: Utc.ymd(2020, 6, 3).and_hms(0, 0, 0),
+//!         findings: vec![email],
+//!     },
+//! ];
+//! let stats = FindingStats::compute(&scans, Some(4000), 10);
+//! assert_eq!(stats.total_findings, 2);
+//! assert_eq!(stats.open_findings, 1);
+//! assert_eq!(stats.remediated_findings, 1);
+//! assert_eq!(stats.mean_days_to_remediate, Some(2.0));
+//! assert_eq!(stats.findings_per_1k_commits, Some(0.25));
+//! assert_eq!(stats.top_sources[0].source, "org/web");
+//!
+//! let diff = FindingsDiff::compute(&scans[0].findings, &scans[1].findings);
+//! assert!(diff.added.is_empty());
+//! assert_eq!(diff.resolved[0]["reason"], "Slack Token");
+//! assert_eq!(diff.unchanged, 1);
+//!
+//! let reports = vec![
+//!     (String::from("june-1.json"), scans[0].findings.clone()),
+//!     (String::from("june-3.json"), scans[1].findings.clone()),
+//! ];
+//! let merge = FindingsMerge::compute(&reports);
+//! assert_eq!(merge.findings.len(), 2);
+//! assert_eq!(merge.duplicates, 1);
+//! assert_eq!(merge.by_report["june-3.json"].duplicates, 1);
+//! assert_eq!(merge.findings[1]["reports"], json!(["june-1.json", "june-3.json"]));
+//! ```
+
+use crate::reporting::{finding_id, location_of};
+use chrono::{DateTime, Utc};
+use serde_derive::Serialize;
+use serde_json::{json, Value};
+use simple_error::{require_with, try_with, SimpleError};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs;
+
+/// The finding fields naming the container a finding was found in, in order of preference
+const SOURCE_FIELDS: &[&str] = &[
+    "repository",
+    "project",
+    "bucket",
+    "image",
+    "job",
+    "team",
+    "namespace",
+];
+
+/// The findings of one scan
+#[derive(Debug, PartialEq, Clone)]
+pub struct ScanReport {
+    /// When the scan ran
+    pub date: DateTime<Utc>,
+    /// The serialized findings, as in a JSON report
+    pub findings: Vec<Value>,
+}
+
+/// The number of open findings in a source
+#[derive(Serialize, Debug, PartialEq, Eq, Hash, Clone)]
+pub struct SourceCount {
+    pub source: String,
+    pub findings: usize,
+}
+
+/// Metrics over a series of scans, see the module documentation
+#[derive(Serialize, Debug, PartialEq, Clone, Default)]
+pub struct FindingStats {
+    pub scans: usize,
+    /// The time of the first scan, in RFC 3339 format
+    #[serde(rename = "firstScan")]
+    pub first_scan: Option<String>,
+    /// The time of the latest scan, in RFC 3339 format
+    #[serde(rename = "lastScan")]
+    pub last_scan: Option<String>,
+    /// Distinct findings across every scan
+    #[serde(rename = "totalFindings")]
+    pub total_findings: usize,
+    /// Findings in the latest scan
+    #[serde(rename = "openFindings")]
+    pub open_findings: usize,
+    #[serde(rename = "remediatedFindings")]
+    pub remediated_findings: usize,
+    /// The mean time from a finding's first scan to its remediation, None if none was remediated
+    #[serde(rename = "meanDaysToRemediate")]
+    pub mean_days_to_remediate: Option<f64>,
+    /// Open findings per 1,000 commits scanned, if the commit count is known
+    #[serde(rename = "findingsPer1kCommits")]
+    pub findings_per_1k_commits: Option<f64>,
+    /// Open findings for each rule
+    #[serde(rename = "openByRule")]
+    pub open_by_rule: BTreeMap<String, usize>,
+    /// The sources with the most open findings, most first
+    #[serde(rename = "topSources")]
+    pub top_sources: Vec<SourceCount>,
+}
+
+/// The findings added and resolved between two scans
+#[derive(Serialize, Debug, PartialEq, Clone, Default)]
+pub struct FindingsDiff {
+    /// Findings of the new scan that are not in the old one
+    pub added: Vec<Value>,
+    /// Findings of the old scan that are not in the new one
+    pub resolved: Vec<Value>,
+    /// The number of findings in both
+    pub unchanged: usize,
+}
+
+/// The findings read from one of the reports of a `FindingsMerge`
+#[derive(Serialize, Debug, PartialEq, Eq, Hash, Clone, Default)]
+pub struct ReportCount {
+    pub findings: usize,
+    /// The findings already read from an earlier report, or earlier in this one
+    pub duplicates: usize,
+}
+
+/// The findings of several reports, each listed once, see `FindingsMerge::compute`
+#[derive(Serialize, Debug, PartialEq, Clone, Default)]
+pub struct FindingsMerge {
+    /// The findings read from every report, duplicates included
+    #[serde(rename = "totalFindings")]
+    pub total_findings: usize,
+    pub duplicates: usize,
+    /// The findings read from each report
+    #[serde(rename = "byReport")]
+    pub by_report: BTreeMap<String, ReportCount>,
+    /// Distinct findings for each source (see the module documentation)
+    #[serde(rename = "bySource")]
+    pub by_source: BTreeMap<String, usize>,
+    /// Distinct findings for each rule
+    #[serde(rename = "byRule")]
+    pub by_rule: BTreeMap<String, usize>,
+    /// The distinct findings, with the names of the reports each was found in as `reports`
+    pub findings: Vec<Value>,
+}
+
+impl ScanReport {
+    /// Reads a JSON report (the default `json` format), dated by the file's modification time
+    pub fn from_file(path: &str) -> Result<Self, SimpleError> {
+        let json = try_with!(fs::read_to_string(path), "Failed to read {}", path);
+        let report: Value = try_with!(serde_json::from_str(&json), "Failed to parse {}", path);
+        let findings = require_with!(report.as_array(), "{} is not a JSON report", path)
+            .iter()
+            // leave out the markers added for capped rules and files
+            .filter(|f| f.get("truncated").is_none())
+            .cloned()
+            .collect();
+        let metadata = try_with!(fs::metadata(path), "Failed to read {}", path);
+        let modified = try_with!(metadata.modified(), "Failed to read the date of {}", path);
+        Ok(Self {
+            date: DateTime::from(modified),
+            findings,
+        })
+    }
+}
+
+impl FindingStats {
+    /// Computes the metrics for `scans`, in any order. `commits` is the number of commits scanned,
+    /// if known, and `top` the number of sources to list.
+    pub fn compute(scans: &[ScanReport], commits: Option<u64>, top: usize) -> Self {
+        let mut scans: Vec<&ScanReport> = scans.iter().collect();
+        scans.sort_by_key(|s| s.date);
+        let latest = match scans.last() {
+            Some(s) => *s,
+            None => return Self::default(),
+        };
+
+        // when each finding was first and last seen, by index into scans
+        let mut first_seen: HashMap<String, usize> = HashMap::new();
+        let mut last_seen: HashMap<String, usize> = HashMap::new();
+        for (i, scan) in scans.iter().enumerate() {
+            for finding in &scan.findings {
+                let id = finding_id(finding);
+                first_seen.entry(id.clone()).or_insert(i);
+                last_seen.insert(id, i);
+            }
+        }
+
+        let last = scans.len() - 1;
+        let days_to_remediate: Vec<f64> = last_seen
+            .iter()
+            .filter(|(_, seen)| **seen < last)
+            .map(|(id, seen)| {
+                let fixed = scans[seen + 1].date;
+                let found = scans[first_seen[id]].date;
+                (fixed - found).num_seconds() as f64 / 86400.0
+            })
+            .collect();
+
+        let mut open_ids: HashSet<String> = HashSet::new();
+        let mut open_by_rule: BTreeMap<String, usize> = BTreeMap::new();
+        let mut by_source: HashMap<String, usize> = HashMap::new();
+        for finding in &latest.findings {
+            if !open_ids.insert(finding_id(finding)) {
+                continue;
+            }
+            let reason = finding["reason"].as_str().unwrap_or_default().to_string();
+            *open_by_rule.entry(reason).or_insert(0) += 1;
+            *by_source.entry(source_of(finding)).or_insert(0) += 1;
+        }
+        let mut top_sources: Vec<SourceCount> = by_source
+            .into_iter()
+            .map(|(source, findings)| SourceCount { source, findings })
+            .collect();
+        top_sources.sort_by(|a, b| b.findings.cmp(&a.findings).then(a.source.cmp(&b.source)));
+        top_sources.truncate(top);
+
+        let open_findings = open_ids.len();
+        Self {
+            scans: scans.len(),
+            first_scan: scans.first().map(|s| s.date.to_rfc3339()),
+            last_scan: Some(latest.date.to_rfc3339()),
+            total_findings: first_seen.len(),
+            open_findings,
+            remediated_findings: days_to_remediate.len(),
+            mean_days_to_remediate: if days_to_remediate.is_empty() {
+                None
+            } else {
+                Some(days_to_remediate.iter().sum::<f64>() / days_to_remediate.len() as f64)
+            },
+            findings_per_1k_commits: match commits {
+                Some(c) if c > 0 => Some(open_findings as f64 * 1000.0 / c as f64),
+                _ => None,
+            },
+            open_by_rule,
+            top_sources,
+        }
+    }
+}
+
+impl FindingsDiff {
+    /// Compares the findings of an old and a new scan, identified by `reporting::finding_id`.
+    /// Findings are listed once, in the order of their report.
+    pub fn compute(old: &[Value], new: &[Value]) -> Self {
+        let old_ids: HashSet<String> = old.iter().map(finding_id).collect();
+        let new_ids: HashSet<String> = new.iter().map(finding_id).collect();
+        let mut seen: HashSet<String> = HashSet::new();
+        let mut diff = Self::default();
+        for finding in new {
+            let id = finding_id(finding);
+            if !seen.insert(id.clone()) {
+                continue;
+            }
+            if old_ids.contains(&id) {
+                diff.unchanged += 1;
+            } else {
+                diff.added.push(finding.clone());
+            }
+        }
+        for finding in old {
+            let id = finding_id(finding);
+            if !new_ids.contains(&id) && seen.insert(id) {
+                diff.resolved.push(finding.clone());
+            }
+        }
+        diff
+    }
+}
+
+impl FindingsMerge {
+    /// Merges named lists of findings, identified by `reporting::finding_id`. Each finding is
+    /// listed once, as it was first read, in the order of the reports.
+    pub fn compute(reports: &[(String, Vec<Value>)]) -> Self {
+        let mut merge = Self::default();
+        let mut index: HashMap<String, usize> = HashMap::new();
+        for (name, findings) in reports {
+            let count = merge.by_report.entry(name.clone()).or_default();
+            for finding in findings {
+                merge.total_findings += 1;
+                count.findings += 1;
+                let id = finding_id(finding);
+                if let Some(i) = index.get(&id) {
+                    merge.duplicates += 1;
+                    count.duplicates += 1;
+                    let seen_in = merge.findings[*i]["reports"].as_array_mut();
+                    if let Some(seen_in) = seen_in.filter(|r| !r.contains(&json!(name))) {
+                        seen_in.push(json!(name));
+                    }
+                    continue;
+                }
+                let reason = finding["reason"].as_str().unwrap_or_default().to_string();
+                *merge.by_rule.entry(reason).or_insert(0) += 1;
+                *merge.by_source.entry(source_of(finding)).or_insert(0) += 1;
+                let mut finding = finding.clone();
+                finding["reports"] = json!([name]);
+                index.insert(id, merge.findings.len());
+                merge.findings.push(finding);
+            }
+        }
+        merge
+    }
+}
+
+/// The container a serialized finding was found in, or its location if it has none
+fn source_of(finding: &Value) -> String {
+    SOURCE_FIELDS
+        .iter()
+        .filter_map(|name| finding[*name].as_str())
+        .find(|s| !s.is_empty())
+        .map(String::from)
+        .unwrap_or_else(|| location_of(finding))
+}
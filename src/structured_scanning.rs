@@ -0,0 +1,568 @@
+//! Structured scanning of JSON, YAML, TOML and `.env` configuration files, Dockerfiles, Compose
+//! files, SQLite databases and Terraform state files and plans.
+//!
+//! Line-based matching loses context in configuration files: a YAML folded scalar spreads one
+//! value over several lines, and a regex such as "Generic Secret" can't see the key when the value
+//! is on the next line. When structured scanning is enabled (`--structured`), files whose name
+//! ends in `.json`, `.yaml`, `.yml` or `.toml` are parsed and each scalar value is scanned
+//! together with the name of its key. Findings carry the full key path, e.g.
+//! `spring.datasource.password` or `users[0].token`. Dotenv files (`.env`, `.env.production`,
+//! `app.env`) are parsed the same way: each `KEY=value` line, quoted values continued over
+//! several lines included, is scanned under the key path `KEY`.
+//!
+//! String values stored under sensitive-looking key names (`password`, `secret`, `api_key`, ...)
+//! are also reported with the reason "Sensitive key name", even when no regex matches them, so
+//! real passwords too short or too plain for regex or entropy scanning are still found. Empty
+//! values and obvious placeholders such as `${DB_PASSWORD}`, `{{ token }}`, `changeme` or
+//! `xxxxxxxx` are ignored.
+//!
+//! SQLite databases (browser profiles, mobile app data) are recognised by their header whatever
+//! their name, and their text, number and blob cells are scanned one at a time with the name of
+//! their column (see the `sqlite` module). The key path of a cell is `table.column@rowid`, so a
+//! finding in a file reads as `db.sqlite!users.api_token@42`.
+//!
+//! Dockerfiles (`Dockerfile`, `Containerfile`, `Dockerfile.prod`, `app.dockerfile`) are read
+//! instruction by instruction, and the value of each variable set by `ENV` and `ARG` is scanned
+//! with its name, after joining continued lines and substituting the variables it refers to (see
+//! the `dockerfile` module). The key path names the instruction, variable and line, e.g.
+//! `ENV.DB_PASSWORD@12`. Compose files (`docker-compose.yml`, `compose.yaml`) are scanned like
+//! other YAML files, except that `environment` and `build.args` lists are read as maps, so a
+//! `- DB_PASSWORD=...` entry has the key path `services.db.environment.DB_PASSWORD`, and the
+//! defaults of `${VAR:-default}` are expanded.
+//!
+//! Terraform state files (`terraform.tfstate`, `.tfstate.backup`) and JSON files written by
+//! Terraform, such as plans saved with `terraform show -json`, are read resource by resource (see
+//! the `terraform` module). The key path of an attribute is the address of its resource followed
+//! by the attribute, e.g. `aws_db_instance.main.password` or `output.db_password`, and values
+//! that Terraform marks as sensitive are reported with the reason "Terraform sensitive value"
+//! whatever their name.
+//!
+//! If a file fails to parse, callers fall back to line-based scanning.
+//!
+//! # Examples
+//!
+//! ```
+//! use rusty_hogs::SecretScannerBuilder;
+//! use rusty_hogs::structured_scanning::{scan_document, StructuredFormat};
+//!
+//! let ss = SecretScannerBuilder::new().build();
+//! let yaml = b"services:\n  db:\n    password: hunter2hunter2\n    user: app\n";
+//! let format = StructuredFormat::from_path("docker-compose.yml").unwrap();
+//! let matches = scan_document(&ss, yaml, format, false).unwrap();
+//! assert_eq!(matches.len(), 1);
+//! assert_eq!(matches[0].key_path, "services.db.password");
+//! assert_eq!(matches[0].reason, "Sensitive key name");
+//!
+//! let env = b"# local settings\nexport DB_PASSWORD=\"Tr0ub4dor&3\"\nSMTP_PASSWORD=changeme\n";
+//! let format = StructuredFormat::from_path(".env.local").unwrap();
+//! let matches = scan_document(&ss, env, format, false).unwrap();
+//! assert_eq!(matches.len(), 1);
+//! assert_eq!(matches[0].key_path, "DB_PASSWORD");
+//! assert_eq!(matches[0].strings_found, vec!["Tr0ub4dor&3"]);
+//! ```
+
+use crate::dockerfile;
+use crate::sqlite::{self, Database, SQLITE_MAGIC};
+use crate::terraform;
+use crate::SecretScanner;
+use encoding::all::ASCII;
+use encoding::{DecoderTrap, Encoding};
+use log::{self, debug};
+use serde_json::{Map, Value};
+use simple_error::{try_with, SimpleError};
+use std::str;
+
+/// Key names (lower case, with punctuation removed) that usually hold a credential
+const SENSITIVE_KEY_NAMES: &[&str] = &[
+    "password",
+    "passwd",
+    "secret",
+    "token",
+    "apikey",
+    "privatekey",
+    "accesskey",
+    "credential",
+];
+
+/// Reason reported for values found by key name rather than by regex
+const SENSITIVE_KEY_REASON: &str = "Sensitive key name";
+
+/// Reason reported for values that Terraform marks as sensitive
+const TERRAFORM_SENSITIVE_REASON: &str = "Terraform sensitive value";
+
+/// How far into a JSON file to look for the `terraform_version` that Terraform writes first
+const TERRAFORM_SNIFF_LEN: usize = 1024;
+
+/// Values (lower case) left in sample configuration in place of a real credential
+const PLACEHOLDER_VALUES: &[&str] = &[
+    "changeme",
+    "change_me",
+    "changeit",
+    "password",
+    "secret",
+    "example",
+    "placeholder",
+    "redacted",
+    "notasecret",
+];
+
+/// The document formats understood by `scan_document`
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub enum StructuredFormat {
+    Json,
+    Yaml,
+    Toml,
+    /// A dotenv file of `KEY=value` lines
+    Dotenv,
+    Sqlite,
+    Dockerfile,
+    /// A Docker Compose file, parsed as YAML
+    Compose,
+    /// A Terraform state file or a JSON plan, parsed as JSON
+    Terraform,
+}
+
+impl StructuredFormat {
+    /// Guesses the format from a file name or path, returning `None` for anything unstructured
+    pub fn from_path(path: &str) -> Option<Self> {
+        let file_name = path.rsplit('/').next()?.to_ascii_lowercase();
+        if file_name == "dockerfile"
+            || file_name == "containerfile"
+            || file_name.starts_with("dockerfile.")
+            || file_name.ends_with(".dockerfile")
+        {
+            return Some(StructuredFormat::Dockerfile);
+        }
+        if (file_name.starts_with("docker-compose") || file_name.starts_with("compose"))
+            && (file_name.ends_with(".yml") || file_name.ends_with(".yaml"))
+        {
+            return Some(StructuredFormat::Compose);
+        }
+        if file_name.ends_with(".tfstate") || file_name.ends_with(".tfstate.backup") {
+            return Some(StructuredFormat::Terraform);
+        }
+        if file_name == ".env" || file_name.starts_with(".env.") || file_name.ends_with(".env") {
+            return Some(StructuredFormat::Dotenv);
+        }
+        let extension = file_name.rsplit('.').next()?;
+        match extension {
+            "json" => Some(StructuredFormat::Json),
+            "yaml" | "yml" => Some(StructuredFormat::Yaml),
+            "toml" => Some(StructuredFormat::Toml),
+            "sqlite" | "sqlite3" | "db" => Some(StructuredFormat::Sqlite),
+            _ => None,
+        }
+    }
+
+    /// Like `from_path`, but also recognises SQLite databases by their header, since browsers and
+    /// apps name them without an extension (`Cookies`, `Login Data`), and Terraform plans and
+    /// state files among JSON files by the `terraform_version` near their start
+    ///
+    /// ```
+    /// use rusty_hogs::structured_scanning::StructuredFormat;
+    ///
+    /// let plan = br#"{"format_version":"1.2","terraform_version":"1.5.7","variables":{}}"#;
+    /// assert_eq!(StructuredFormat::detect("plan.json", plan), Some(StructuredFormat::Terraform));
+    /// let package = br#"{"name":"app","version":"1.0.0"}"#;
+    /// assert_eq!(StructuredFormat::detect("package.json", package), Some(StructuredFormat::Json));
+    /// ```
+    pub fn detect(path: &str, data: &[u8]) -> Option<Self> {
+        if data.starts_with(SQLITE_MAGIC) {
+            return Some(StructuredFormat::Sqlite);
+        }
+        match Self::from_path(path) {
+            Some(StructuredFormat::Json) if is_terraform_json(data) => {
+                Some(StructuredFormat::Terraform)
+            }
+            format => format,
+        }
+    }
+}
+
+/// Checks whether the start of a JSON document names the version of Terraform that wrote it
+fn is_terraform_json(data: &[u8]) -> bool {
+    let start = &data[..data.len().min(TERRAFORM_SNIFF_LEN)];
+    let needle = b"\"terraform_version\"";
+    start.windows(needle.len()).any(|w| w == needle)
+}
+
+/// A single secret found in a structured document. Scanners copy these fields into their own
+/// finding type alongside the location of the document.
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Default)]
+pub struct StructuredMatch {
+    pub key_path: String,
+    pub diff: String,
+    pub strings_found: Vec<String>,
+    pub reason: String,
+}
+
+/// Parses a document in any of the supported formats into a `serde_json` Value
+pub fn parse_document(data: &[u8], format: StructuredFormat) -> Result<Value, SimpleError> {
+    match format {
+        StructuredFormat::Json | StructuredFormat::Terraform => Ok(try_with!(
+            serde_json::from_slice(data),
+            "Failed to parse JSON document"
+        )),
+        StructuredFormat::Yaml => Ok(try_with!(
+            serde_yaml::from_slice(data),
+            "Failed to parse YAML document"
+        )),
+        StructuredFormat::Dotenv => {
+            let text = try_with!(str::from_utf8(data), ".env file is not valid UTF-8");
+            Ok(Value::Object(parse_dotenv(text)?))
+        }
+        StructuredFormat::Compose => {
+            let mut document: Value =
+                try_with!(serde_yaml::from_slice(data), "Failed to parse Compose file");
+            dockerfile::normalize_compose(&mut document);
+            Ok(document)
+        }
+        StructuredFormat::Dockerfile => {
+            // an array of the variables set by the ENV and ARG instructions
+            let text = try_with!(str::from_utf8(data), "Dockerfile is not valid UTF-8");
+            let variables = dockerfile::variables(text).into_iter().map(|v| {
+                let mut variable = Map::new();
+                variable.insert(String::from("instruction"), Value::from(v.instruction));
+                variable.insert(String::from("name"), Value::from(v.name));
+                variable.insert(String::from("value"), Value::from(v.value));
+                variable.insert(String::from("line"), Value::from(v.line));
+                Value::Object(variable)
+            });
+            Ok(Value::Array(variables.collect()))
+        }
+        StructuredFormat::Toml => {
+            let text = try_with!(str::from_utf8(data), "TOML document is not valid UTF-8");
+            Ok(try_with!(
+                toml::from_str(text),
+                "Failed to parse TOML document"
+            ))
+        }
+        StructuredFormat::Sqlite => {
+            // an object of tables, each an array of rows
+            let database = Database::parse(data)?;
+            let mut tables = Map::new();
+            for table in database.tables()? {
+                let rows = database.rows(&table)?;
+                let rows = rows.iter().map(|row| {
+                    let cells = table
+                        .columns
+                        .iter()
+                        .zip(row.values.iter())
+                        .map(|(column, value)| (column.clone(), sqlite_json(value)));
+                    Value::Object(cells.collect())
+                });
+                tables.insert(table.name.clone(), Value::Array(rows.collect()));
+            }
+            Ok(Value::Object(tables))
+        }
+    }
+}
+
+/// Parses the `[export ]KEY=value` lines of a dotenv file. Values may be unquoted (up to a ` #`
+/// comment), single quoted, or double quoted with escapes and continued over several lines.
+fn parse_dotenv(text: &str) -> Result<Map<String, Value>, SimpleError> {
+    let mut variables = Map::new();
+    let mut lines = text.lines();
+    while let Some(line) = lines.next() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let line = if line.starts_with("export ") {
+            line["export ".len()..].trim_start()
+        } else {
+            line
+        };
+        let equals = match line.find('=') {
+            Some(i) => i,
+            None => return Err(SimpleError::new(format!("Invalid .env line {:?}", line))),
+        };
+        let key = line[..equals].trim();
+        let valid_key = key
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.' || c == '-');
+        if key.is_empty() || !valid_key {
+            return Err(SimpleError::new(format!("Invalid .env key {:?}", key)));
+        }
+        let raw = line[equals + 1..].trim();
+        let value = if raw.starts_with('"') {
+            let mut quoted = raw[1..].to_string();
+            while !ends_quote(&quoted) {
+                match lines.next() {
+                    Some(next) => {
+                        quoted.push('\n');
+                        quoted.push_str(next);
+                    }
+                    None => return Err(SimpleError::new(format!("Unterminated value of {}", key))),
+                }
+            }
+            unescape(&quoted)
+        } else if raw.starts_with('\'') && raw[1..].contains('\'') {
+            raw[1..].split('\'').next().unwrap_or("").to_string()
+        } else {
+            raw.split(" #").next().unwrap_or("").trim().to_string()
+        };
+        variables.insert(key.to_string(), Value::String(value));
+    }
+    Ok(variables)
+}
+
+/// Checks whether the text of a double quoted value contains its closing quote, one that isn't
+/// escaped with a backslash
+fn ends_quote(quoted: &str) -> bool {
+    let mut escaped = false;
+    for c in quoted.chars() {
+        match c {
+            '\\' if !escaped => escaped = true,
+            '"' if !escaped => return true,
+            _ => escaped = false,
+        }
+    }
+    false
+}
+
+/// Reads a double quoted value up to its closing quote, resolving `\n`, `\"` and `\\`
+fn unescape(quoted: &str) -> String {
+    let mut output = String::with_capacity(quoted.len());
+    let mut chars = quoted.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => break,
+            '\\' => match chars.next() {
+                Some('n') => output.push('\n'),
+                Some(next) => output.push(next),
+                None => break,
+            },
+            c => output.push(c),
+        }
+    }
+    output
+}
+
+/// A cell of a SQLite database as JSON
+fn sqlite_json(value: &sqlite::SqlValue) -> Value {
+    match value {
+        sqlite::SqlValue::Null => Value::Null,
+        sqlite::SqlValue::Integer(i) => Value::from(*i),
+        sqlite::SqlValue::Real(r) => Value::from(*r),
+        _ => Value::String(value.to_text().unwrap_or_default()),
+    }
+}
+
+/// A scalar value of a document: its key path, the name of its key, its text, and whether the
+/// document marks it as sensitive
+struct Scalar {
+    key_path: String,
+    key: String,
+    value: String,
+    sensitive: bool,
+}
+
+impl Scalar {
+    fn new(key_path: String, key: String, value: String) -> Self {
+        Self {
+            key_path,
+            key,
+            value,
+            sensitive: false,
+        }
+    }
+}
+
+/// The key path, key name and text of every scalar value of a document. The variables of a
+/// Dockerfile have the key path `INSTRUCTION.NAME@line`. The cells of a SQLite database are read
+/// directly, with the key path `table.column@rowid`, and tables that can't be read are skipped.
+/// The attributes of a Terraform document have the address of their resource as key path (see
+/// the `terraform` module).
+fn scalars(data: &[u8], format: StructuredFormat) -> Result<Vec<Scalar>, SimpleError> {
+    if format == StructuredFormat::Dockerfile {
+        let text = try_with!(str::from_utf8(data), "Dockerfile is not valid UTF-8");
+        return Ok(dockerfile::variables(text)
+            .into_iter()
+            .map(|v| {
+                let key_path = format!("{}.{}@{}", v.instruction, v.name, v.line);
+                Scalar::new(key_path, v.name, v.value)
+            })
+            .collect());
+    }
+    if format != StructuredFormat::Sqlite {
+        let document = parse_document(data, format)?;
+        if format == StructuredFormat::Terraform && terraform::is_terraform(&document) {
+            return Ok(terraform::attributes(&document)
+                .into_iter()
+                .map(|a| Scalar {
+                    key_path: a.path,
+                    key: a.key,
+                    value: a.value,
+                    sensitive: a.sensitive,
+                })
+                .collect());
+        }
+        return Ok(flatten(&document)
+            .into_iter()
+            .map(|(key_path, key, value)| Scalar::new(key_path, key, value))
+            .collect());
+    }
+    let database = Database::parse(data)?;
+    let mut output: Vec<Scalar> = Vec::new();
+    for table in database.tables()? {
+        let rows = match database.rows(&table) {
+            Ok(r) => r,
+            Err(e) => {
+                debug!("Skipping the table {}: {}", table.name, e);
+                continue;
+            }
+        };
+        for row in rows {
+            for (column, value) in table.columns.iter().zip(row.values.iter()) {
+                if let Some(text) = value.to_text() {
+                    let key_path = format!("{}.{}@{}", table.name, column, row.rowid);
+                    output.push(Scalar::new(key_path, column.clone(), text));
+                }
+            }
+        }
+    }
+    Ok(output)
+}
+
+/// Walks a document and returns the key path, key name and text of every scalar value in it
+pub fn flatten(document: &Value) -> Vec<(String, String, String)> {
+    let mut output: Vec<(String, String, String)> = Vec::new();
+    flatten_into(document, String::new(), String::new(), &mut output);
+    output
+}
+
+fn flatten_into(
+    value: &Value,
+    path: String,
+    key: String,
+    output: &mut Vec<(String, String, String)>,
+) {
+    match value {
+        Value::Object(map) => {
+            for (k, v) in map {
+                let child_path = if path.is_empty() {
+                    k.clone()
+                } else {
+                    format!("{}.{}", path, k)
+                };
+                flatten_into(v, child_path, k.clone(), output);
+            }
+        }
+        Value::Array(array) => {
+            for (i, v) in array.iter().enumerate() {
+                flatten_into(v, format!("{}[{}]", path, i), key.clone(), output);
+            }
+        }
+        Value::Null => {}
+        Value::String(s) => output.push((path, key, s.clone())),
+        scalar => output.push((path, key, scalar.to_string())),
+    }
+}
+
+/// Parses `data` and scans each scalar value, returning one `StructuredMatch` per regex (or key
+/// name heuristic) that matched. Multi-line values are scanned a line at a time, each prefixed
+/// with the key name so that rules which expect a key still match.
+pub fn scan_document(
+    secret_scanner: &SecretScanner,
+    data: &[u8],
+    format: StructuredFormat,
+    scan_entropy: bool,
+) -> Result<Vec<StructuredMatch>, SimpleError> {
+    let mut findings: Vec<StructuredMatch> = Vec::new();
+    for Scalar {
+        key_path,
+        key,
+        value,
+        sensitive,
+    } in scalars(data, format)?
+    {
+        for value_line in value.split('\n') {
+            let new_line = format!("{}: {}", key, value_line);
+            let new_line = new_line.as_bytes();
+            let matches_map = secret_scanner.matches(new_line);
+            for (reason, match_iterator) in matches_map {
+                let mut secrets: Vec<String> = Vec::new();
+                for matchobj in match_iterator {
+                    secrets.push(
+                        ASCII
+                            .decode(
+                                &new_line[matchobj.start()..matchobj.end()],
+                                DecoderTrap::Ignore,
+                            )
+                            .unwrap_or_else(|_| "<STRING DECODE ERROR>".parse().unwrap()),
+                    );
+                }
+                if !secrets.is_empty() {
+                    findings.push(StructuredMatch {
+                        key_path: key_path.clone(),
+                        diff: ASCII
+                            .decode(new_line, DecoderTrap::Ignore)
+                            .unwrap_or_else(|_| "<STRING DECODE ERROR>".parse().unwrap()),
+                        strings_found: secrets,
+                        reason: reason.clone(),
+                    });
+                }
+            }
+
+            if scan_entropy {
+                let ef = secret_scanner.entropy_matches(value_line.as_bytes());
+                if !ef.is_empty() {
+                    findings.push(StructuredMatch {
+                        key_path: key_path.clone(),
+                        diff: ASCII
+                            .decode(new_line, DecoderTrap::Ignore)
+                            .unwrap_or_else(|_| "<STRING DECODE ERROR>".parse().unwrap()),
+                        strings_found: ef,
+                        reason: "Entropy".to_string(),
+                    });
+                }
+            }
+        }
+
+        let reason = if sensitive {
+            TERRAFORM_SENSITIVE_REASON
+        } else {
+            SENSITIVE_KEY_REASON
+        };
+        if (sensitive || is_sensitive_key(&key)) && is_plausible_secret(&value) {
+            findings.push(StructuredMatch {
+                key_path: key_path.clone(),
+                diff: format!("{}: {}", key, value),
+                strings_found: vec![value.clone()],
+                reason: reason.to_string(),
+            });
+        }
+    }
+    Ok(findings)
+}
+
+/// Checks a key name against SENSITIVE_KEY_NAMES, ignoring case and punctuation so that
+/// `api_key`, `apiKey` and `API-KEY` are all treated the same
+pub(crate) fn is_sensitive_key(key: &str) -> bool {
+    let key: String = key
+        .chars()
+        .filter(char::is_ascii_alphanumeric)
+        .map(|c| c.to_ascii_lowercase())
+        .collect();
+    SENSITIVE_KEY_NAMES.iter().any(|name| key.contains(name))
+}
+
+/// Filters out empty values, booleans, whitespace-separated prose, template placeholders and
+/// sample values such as `changeme` or `xxxxxx`
+pub(crate) fn is_plausible_secret(value: &str) -> bool {
+    let value = value.trim();
+    let lower = value.to_ascii_lowercase();
+    let first = value.chars().next();
+    value.len() >= 6
+        && !value.contains(char::is_whitespace)
+        && !["true", "false", "null", "none"].contains(&lower.as_str())
+        && !PLACEHOLDER_VALUES.contains(&lower.trim_matches(|c| c == '-' || c == '_'))
+        && !lower.starts_with("your_")
+        && !lower.starts_with("your-")
+        && !value.chars().all(|c| Some(c) == first)
+        && !value.starts_with("${")
+        && !value.starts_with("{{")
+        && !value.starts_with("%(")
+        && !value.starts_with('<')
+}
@@ -0,0 +1,331 @@
+//! Collection of tools for scanning Microsoft Teams for secrets.
+//!
+//! `TeamsScanner` acts as a wrapper around a `SecretScanner` object and talks to
+//! [Microsoft Graph](https://docs.microsoft.com/en-us/graph/teams-concept-overview) with an
+//! application (client credentials) token, so that every team in the tenant can be read without a
+//! user signing in. It can list the teams and their channels, and scan the messages of a channel
+//! along with their replies.
+//!
+//! The Azure AD application needs the `Team.ReadBasic.All`, `Channel.ReadBasic.All` and
+//! `ChannelMessage.Read.All` application permissions, granted by an administrator.
+//! `ChannelMessage.Read.All` is a [protected API](https://docs.microsoft.com/en-us/graph/teams-protected-apis)
+//! that Microsoft has to enable for the application.
+//!
+//! Message bodies are HTML, so the `teams_hog` binary strips tags before scanning unless told
+//! otherwise with `--markup`.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use rusty_hogs::teams_scanning::{TeamsConfig, TeamsScanner};
+//!
+//! let ts = TeamsScanner::new();
+//! let config = TeamsConfig::from_client_credentials("tenant-id", "client-id", "secret").unwrap();
+//! for team in ts.list_teams(&config).unwrap() {
+//!     let findings = ts.scan_team(&config, &team, true, false).unwrap();
+//!     ts.secret_scanner.output_findings(&findings);
+//! }
+//! ```
+
+use crate::network;
+use crate::retry;
+use crate::{Finding, SecretScanner, Span, TextFinding};
+use log::{self, debug, info, warn};
+use reqwest::blocking::Response;
+use serde_derive::{Deserialize, Serialize};
+use serde_json::Value;
+use simple_error::{require_with, try_with, SimpleError};
+use std::collections::HashSet;
+
+/// The Microsoft Graph endpoint used unless another one is configured
+pub const GRAPH_URL: &str = "https://graph.microsoft.com/v1.0";
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Hash, Clone, Default)]
+/// `serde_json` object that represents a single found secret - finding
+pub struct TeamsFinding {
+    pub diff: String,
+    #[serde(rename = "stringsFound")]
+    pub strings_found: Vec<String>,
+    /// The display name of the team
+    pub team: String,
+    /// The display name of the channel
+    pub channel: String,
+    /// The message ID, or `<message ID>/<reply ID>` for replies
+    pub path: String,
+    /// A link to the message in Teams
+    pub url: String,
+    /// The display name of the user or application that posted the message
+    pub user: String,
+    pub date: String,
+    pub reason: String,
+    /// Where each of `strings_found` is in the text, see `Span`
+    #[serde(default)]
+    pub spans: Vec<Span>,
+}
+
+impl TextFinding for TeamsFinding {
+    fn with_match(&self, found: Finding) -> Self {
+        Self {
+            diff: found.diff,
+            strings_found: found.strings_found,
+            reason: found.reason,
+            spans: found.spans,
+            ..self.clone()
+        }
+    }
+}
+
+/// The Microsoft Graph endpoint to talk to and the token to authenticate with
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+pub struct TeamsConfig {
+    /// The base URL of the API, `GRAPH_URL` for the global cloud
+    pub url: String,
+    /// An application access token for Microsoft Graph
+    pub token: String,
+}
+
+/// A team. Only the fields we need are included.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Hash, Clone)]
+pub struct Team {
+    pub id: String,
+    #[serde(rename = "displayName")]
+    pub display_name: String,
+}
+
+/// A channel of a team. Only the fields we need are included.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Hash, Clone)]
+pub struct Channel {
+    pub id: String,
+    #[serde(rename = "displayName")]
+    pub display_name: String,
+}
+
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+/// Contains helper functions for performing scans of Microsoft Teams channels
+pub struct TeamsScanner {
+    pub secret_scanner: SecretScanner,
+}
+
+impl TeamsConfig {
+    /// Requests an application token for Microsoft Graph from Azure AD, using the client ID and
+    /// secret of an application registered in `tenant`
+    pub fn from_client_credentials(
+        tenant: &str,
+        client_id: &str,
+        client_secret: &str,
+    ) -> Result<Self, SimpleError> {
+        let url = format!(
+            "https://login.microsoftonline.com/{}/oauth2/v2.0/token",
+            tenant
+        );
+        debug!("POST {}", url);
+        let response = try_with!(
+            retry::send(network::client().post(&url).form(&[
+                ("grant_type", "client_credentials"),
+                ("client_id", client_id),
+                ("client_secret", client_secret),
+                ("scope", "https://graph.microsoft.com/.default"),
+            ])),
+            "Azure AD token request failed"
+        );
+        let status = response.status();
+        let body: Value = try_with!(response.json(), "Failed to parse Azure AD response");
+        if !status.is_success() {
+            return Err(SimpleError::new(format!(
+                "Azure AD returned {}: {}",
+                status,
+                body["error_description"].as_str().unwrap_or("")
+            )));
+        }
+        let token = require_with!(
+            body["access_token"].as_str(),
+            "Azure AD returned no access token"
+        );
+        Ok(Self {
+            url: GRAPH_URL.to_string(),
+            token: token.to_string(),
+        })
+    }
+}
+
+/// Acts as a wrapper around a `SecretScanner` object to provide helper functions for performing
+/// scanning against Microsoft Teams. Relies on the [reqwest](https://docs.rs/reqwest/) library for
+/// HTTP access.
+impl TeamsScanner {
+    /// Initialize the SecretScanner object first using the SecretScannerBuilder, then provide
+    /// it to this constructor method.
+    pub fn new_from_scanner(secret_scanner: SecretScanner) -> Self {
+        Self { secret_scanner }
+    }
+
+    pub fn new() -> Self {
+        Self {
+            secret_scanner: SecretScanner::default(),
+        }
+    }
+
+    /// Returns every team in the tenant
+    pub fn list_teams(&self, config: &TeamsConfig) -> Result<Vec<Team>, SimpleError> {
+        let teams = get_all(config, "teams?$select=id,displayName")?;
+        Ok(teams
+            .into_iter()
+            .filter_map(|t| serde_json::from_value(t).ok())
+            .collect())
+    }
+
+    /// Returns the channels of `team`
+    pub fn list_channels(
+        &self,
+        config: &TeamsConfig,
+        team: &Team,
+    ) -> Result<Vec<Channel>, SimpleError> {
+        let channels = get_all(
+            config,
+            &format!("teams/{}/channels?$select=id,displayName", team.id),
+        )?;
+        Ok(channels
+            .into_iter()
+            .filter_map(|c| serde_json::from_value(c).ok())
+            .collect())
+    }
+
+    /// Scans the messages of every channel of `team`, and their replies unless `scan_replies` is
+    /// false, returning a HashSet of findings. Channels that can't be read are skipped.
+    pub fn scan_team(
+        &self,
+        config: &TeamsConfig,
+        team: &Team,
+        scan_replies: bool,
+        scan_entropy: bool,
+    ) -> Result<HashSet<TeamsFinding>, SimpleError> {
+        info!("Scanning team {}", team.display_name);
+        let mut findings: HashSet<TeamsFinding> = HashSet::new();
+        for channel in self.list_channels(config, team)? {
+            match self.scan_channel(config, team, &channel, scan_replies, scan_entropy) {
+                Ok(f) => findings.extend(f),
+                // private and shared channels the application isn't allowed to read
+                Err(e) => warn!(
+                    "Skipping channel {} of {}: {}",
+                    channel.display_name, team.display_name, e
+                ),
+            }
+        }
+        Ok(findings)
+    }
+
+    /// Scans the messages of `channel`, and their replies unless `scan_replies` is false, skipping
+    /// messages posted outside the scanner's time range
+    pub fn scan_channel(
+        &self,
+        config: &TeamsConfig,
+        team: &Team,
+        channel: &Channel,
+        scan_replies: bool,
+        scan_entropy: bool,
+    ) -> Result<HashSet<TeamsFinding>, SimpleError> {
+        debug!("Scanning channel {}", channel.display_name);
+        let mut findings: HashSet<TeamsFinding> = HashSet::new();
+        let messages_path = format!("teams/{}/channels/{}/messages", team.id, channel.id);
+        let template = TeamsFinding {
+            team: team.display_name.clone(),
+            channel: channel.display_name.clone(),
+            ..Default::default()
+        };
+        for message in get_all(config, &format!("{}?$top=50", messages_path))? {
+            let message_id = message["id"].as_str().unwrap_or("").to_string();
+            self.scan_message(
+                &message,
+                &message_id,
+                &template,
+                scan_entropy,
+                &mut findings,
+            );
+            if scan_replies {
+                let replies_path = format!("{}/{}/replies?$top=50", messages_path, message_id);
+                for reply in get_all(config, &replies_path)? {
+                    let path = format!("{}/{}", message_id, reply["id"].as_str().unwrap_or(""));
+                    self.scan_message(&reply, &path, &template, scan_entropy, &mut findings);
+                }
+            }
+        }
+        Ok(findings)
+    }
+
+    /// Scans the subject and body of a message (or reply) and adds any findings
+    fn scan_message(
+        &self,
+        message: &Value,
+        path: &str,
+        template: &TeamsFinding,
+        scan_entropy: bool,
+        findings: &mut HashSet<TeamsFinding>,
+    ) {
+        let date = message["createdDateTime"].as_str().unwrap_or("");
+        if !self.secret_scanner.time_range.contains_str(date) {
+            return;
+        }
+        let user = if message["from"]["user"].is_object() {
+            &message["from"]["user"]["displayName"]
+        } else {
+            &message["from"]["application"]["displayName"]
+        };
+        let template = TeamsFinding {
+            path: path.to_string(),
+            url: message["webUrl"].as_str().unwrap_or("").to_string(),
+            user: user.as_str().unwrap_or("").to_string(),
+            date: date.to_string(),
+            ..template.clone()
+        };
+        let subject = message["subject"].as_str().unwrap_or("");
+        let body = message["body"]["content"].as_str().unwrap_or("");
+        let text = format!("{}\n{}", subject, body);
+        self.secret_scanner
+            .scan_text(text.as_bytes(), &template, scan_entropy, findings);
+    }
+}
+
+impl Default for TeamsScanner {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Performs a GET against Microsoft Graph, waiting and retrying when throttled. `path` is relative
+/// to the configured API URL, or a full URL such as an `@odata.nextLink`.
+pub(crate) fn get(config: &TeamsConfig, path: &str) -> Result<Response, SimpleError> {
+    let url = if path.starts_with("https://") || path.starts_with("http://") {
+        path.to_string()
+    } else {
+        format!("{}/{}", config.url.trim_end_matches('/'), path)
+    };
+    debug!("GET {}", url);
+    let response = try_with!(
+        retry::send(network::client().get(&url).bearer_auth(&config.token)),
+        "Microsoft Graph request failed"
+    );
+    if !response.status().is_success() {
+        return Err(SimpleError::new(format!(
+            "Microsoft Graph returned {} for {}",
+            response.status(),
+            url
+        )));
+    }
+    Ok(response)
+}
+
+/// Performs a GET and follows `@odata.nextLink` to collect every item of a collection
+pub(crate) fn get_all(config: &TeamsConfig, path: &str) -> Result<Vec<Value>, SimpleError> {
+    let mut items: Vec<Value> = Vec::new();
+    let mut next = Some(path.to_string());
+    while let Some(path) = next {
+        let page: Value = try_with!(
+            get(config, &path)?.json(),
+            "Failed to parse Microsoft Graph response"
+        );
+        if let Some(values) = page["value"].as_array() {
+            items.extend(values.iter().cloned());
+        }
+        next = page["@odata.nextLink"].as_str().map(String::from);
+    }
+    Ok(items)
+}
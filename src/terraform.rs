@@ -0,0 +1,309 @@
+//! Reading the resource attributes of Terraform state files and plans.
+//!
+//! Terraform keeps every attribute of the resources it manages in its state, secrets included:
+//! the `password` of a database, the `secret` of an IAM access key, the `result` of a
+//! `random_password`. Plans rendered as JSON (`terraform show -json tfplan`) hold the same values
+//! before and after each change, along with the values of the input variables. Scanned line by
+//! line, such a document shows a regex `"password": "..."` at best, far from the resource it
+//! belongs to, and a generated password with no recognisable pattern isn't found at all.
+//!
+//! `attributes` walks the resources of a state file (format version 3 or 4), of a plan, or of the
+//! output of `terraform show -json`, and returns each attribute value under the address of its
+//! resource, e.g. `aws_db_instance.main.password` or `module.db.aws_db_instance.this[0].password`.
+//! Outputs are named `output.<name>` and the variables of a plan `var.<name>`. A value found in
+//! several places of a plan (its prior state, planned values and changes) is returned once.
+//! Values Terraform itself marks as sensitive (the `sensitive_attributes` of a state, sensitive
+//! outputs and variables, and the `sensitive_values` of a plan) are flagged, so the
+//! `structured_scanning` module reports them whatever their name.
+//!
+//! # Examples
+//!
+//! ```
+//! use rusty_hogs::terraform;
+//!
+//! let state = serde_json::json!({
+//!     "version": 4,
+//!     "terraform_version": "1.5.7",
+//!     "outputs": {"db_endpoint": {"value": "main.rds.amazonaws.com", "type": "string"}},
+//!     "resources": [{
+//!         "mode": "managed",
+//!         "type": "random_password",
+//!         "name": "db",
+//!         "instances": [{
+//!             "attributes": {"length": 24, "result": "qR7-vX2mKp9Lw4Zs8Nc1Tb6Y"},
+//!             "sensitive_attributes": [[{"type": "get_attr", "value": "result"}]]
+//!         }]
+//!     }, {
+//!         "module": "module.db",
+//!         "mode": "managed",
+//!         "type": "aws_db_instance",
+//!         "name": "main",
+//!         "instances": [{"index_key": 0, "attributes": {"password": "hunter2hunter2"}}]
+//!     }]
+//! });
+//! assert!(terraform::is_terraform(&state));
+//! let attributes = terraform::attributes(&state);
+//! let paths: Vec<&str> = attributes.iter().map(|a| a.path.as_str()).collect();
+//! assert_eq!(
+//!     paths,
+//!     vec![
+//!         "random_password.db.length",
+//!         "random_password.db.result",
+//!         "module.db.aws_db_instance.main[0].password",
+//!         "output.db_endpoint",
+//!     ]
+//! );
+//! assert_eq!(attributes[1].key, "result");
+//! assert!(attributes[1].sensitive);
+//! assert!(!attributes[2].sensitive);
+//! assert_eq!(attributes[3].key, "db_endpoint");
+//! ```
+
+use crate::structured_scanning;
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+
+/// An attribute value of a Terraform document
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Default)]
+pub struct Attribute {
+    /// The address of the resource followed by the path of the attribute in it, e.g.
+    /// `aws_db_instance.main.password`
+    pub path: String,
+    /// The name of the attribute, the last key of `path`
+    pub key: String,
+    pub value: String,
+    /// Terraform marks the value as sensitive
+    pub sensitive: bool,
+}
+
+/// Returns true if `document` is a Terraform state file, plan or `terraform show -json` output,
+/// all of which name the version of Terraform that wrote them
+pub fn is_terraform(document: &Value) -> bool {
+    document["terraform_version"].is_string()
+}
+
+/// Returns every attribute value of the resources, outputs and variables of a Terraform document
+pub fn attributes(document: &Value) -> Vec<Attribute> {
+    let mut walk = Walk::default();
+    // state files
+    for resource in array(&document["resources"]) {
+        walk.state_resource(resource);
+    }
+    walk.outputs("", &document["outputs"]);
+    for module in array(&document["modules"]) {
+        walk.legacy_module(module);
+    }
+    // plans and the output of terraform show -json
+    let configured = &document["configuration"]["root_module"]["variables"];
+    if let Some(variables) = document["variables"].as_object() {
+        for (name, variable) in variables {
+            let sensitive = configured[name]["sensitive"] == true;
+            walk.add(
+                &format!("var.{}", name),
+                name,
+                &variable["value"],
+                sensitive_all(sensitive),
+            );
+        }
+    }
+    for values in &[
+        &document["prior_state"]["values"],
+        &document["values"],
+        &document["planned_values"],
+    ] {
+        walk.outputs("", &values["outputs"]);
+        walk.module(&values["root_module"]);
+    }
+    for change in array(&document["resource_changes"]) {
+        let address = match change["address"].as_str() {
+            Some(a) => a,
+            None => continue,
+        };
+        let change = &change["change"];
+        for (values, sensitive) in &[("before", "before_sensitive"), ("after", "after_sensitive")] {
+            let sensitive = sensitive_paths(&change[*sensitive]);
+            walk.add(address, "", &change[*values], sensitive);
+        }
+    }
+    walk.attributes
+}
+
+/// The items of a JSON array, none if it isn't one
+fn array(value: &Value) -> impl Iterator<Item = &Value> {
+    value.as_array().into_iter().flatten()
+}
+
+/// The paths, relative to a value, of its sensitive parts: the empty path stands for all of it
+type SensitivePaths = HashSet<String>;
+
+/// All of a value if `sensitive`, otherwise none of it
+fn sensitive_all(sensitive: bool) -> SensitivePaths {
+    let mut paths = SensitivePaths::new();
+    if sensitive {
+        paths.insert(String::new());
+    }
+    paths
+}
+
+/// The sensitive paths of a plan's `sensitive_values`, which mirrors a value with `true` in place
+/// of its sensitive parts (or is `true` when all of it is)
+fn sensitive_paths(mirror: &Value) -> SensitivePaths {
+    if *mirror == true {
+        return sensitive_all(true);
+    }
+    structured_scanning::flatten(mirror)
+        .into_iter()
+        .filter(|(_, _, value)| value == "true")
+        .map(|(path, _, _)| path)
+        .collect()
+}
+
+/// The sensitive paths of the `sensitive_attributes` of a state, each a list of steps such as
+/// `{"type": "get_attr", "value": "password"}` or `{"type": "index", "value": {"value": 0}}`
+fn state_sensitive_paths(sensitive_attributes: &Value) -> SensitivePaths {
+    let mut paths = SensitivePaths::new();
+    for steps in array(sensitive_attributes) {
+        let mut path = String::new();
+        for step in array(steps) {
+            match (&step["value"], &step["value"]["value"]) {
+                (Value::String(name), _) => {
+                    if !path.is_empty() {
+                        path.push('.');
+                    }
+                    path.push_str(name);
+                }
+                (_, Value::Number(index)) => path.push_str(&format!("[{}]", index)),
+                (_, Value::String(key)) => path.push_str(&format!(".{}", key)),
+                _ => {}
+            }
+        }
+        paths.insert(path);
+    }
+    paths
+}
+
+/// Returns true if the part of a value at `path` is, or is inside, one of `sensitive`
+fn is_sensitive(path: &str, sensitive: &SensitivePaths) -> bool {
+    sensitive.iter().any(|s| {
+        s.is_empty()
+            || path == s
+            || path.starts_with(&format!("{}.", s))
+            || path.starts_with(&format!("{}[", s))
+    })
+}
+
+/// The attributes found so far, and where each is in `attributes` to drop duplicates
+#[derive(Default)]
+struct Walk {
+    attributes: Vec<Attribute>,
+    positions: HashMap<(String, String), usize>,
+}
+
+impl Walk {
+    /// Adds every scalar of `value` under `address`. A scalar `value` itself is named `key`.
+    fn add(&mut self, address: &str, key: &str, value: &Value, sensitive: SensitivePaths) {
+        for (path, name, text) in structured_scanning::flatten(value) {
+            let full_path = if path.is_empty() || path.starts_with('[') {
+                format!("{}{}", address, path)
+            } else {
+                format!("{}.{}", address, path)
+            };
+            let attribute = Attribute {
+                sensitive: is_sensitive(&path, &sensitive),
+                key: if name.is_empty() {
+                    key.to_string()
+                } else {
+                    name
+                },
+                path: full_path,
+                value: text,
+            };
+            let position = (attribute.path.clone(), attribute.value.clone());
+            match self.positions.get(&position) {
+                Some(&i) => self.attributes[i].sensitive |= attribute.sensitive,
+                None => {
+                    self.positions.insert(position, self.attributes.len());
+                    self.attributes.push(attribute);
+                }
+            }
+        }
+    }
+
+    /// Adds the outputs of a state or plan, named `output.<name>` after the module `prefix`
+    fn outputs(&mut self, prefix: &str, outputs: &Value) {
+        if let Some(outputs) = outputs.as_object() {
+            for (name, output) in outputs {
+                let address = format!("{}output.{}", prefix, name);
+                let sensitive = sensitive_all(output["sensitive"] == true);
+                self.add(&address, name, &output["value"], sensitive);
+            }
+        }
+    }
+
+    /// Adds the instances of a resource of a version 4 state
+    fn state_resource(&mut self, resource: &Value) {
+        let (kind, name) = match (resource["type"].as_str(), resource["name"].as_str()) {
+            (Some(k), Some(n)) => (k, n),
+            _ => return,
+        };
+        let mut address = String::new();
+        if let Some(module) = resource["module"].as_str() {
+            address.push_str(module);
+            address.push('.');
+        }
+        if resource["mode"] == "data" {
+            address.push_str("data.");
+        }
+        address.push_str(&format!("{}.{}", kind, name));
+        for instance in array(&resource["instances"]) {
+            let instance_address = match &instance["index_key"] {
+                Value::Number(index) => format!("{}[{}]", address, index),
+                Value::String(key) => format!("{}[{:?}]", address, key),
+                _ => address.clone(),
+            };
+            let sensitive = state_sensitive_paths(&instance["sensitive_attributes"]);
+            self.add(&instance_address, "", &instance["attributes"], sensitive);
+        }
+    }
+
+    /// Adds the resources and outputs of a module of a version 3 state, whose attributes are
+    /// flattened into keys such as `tags.Name`, with counts under keys ending in `.#` and `.%`
+    fn legacy_module(&mut self, module: &Value) {
+        let prefix: String = array(&module["path"])
+            .skip(1)
+            .filter_map(Value::as_str)
+            .map(|name| format!("module.{}.", name))
+            .collect();
+        if let Some(resources) = module["resources"].as_object() {
+            for (address, resource) in resources {
+                let attributes = match resource["primary"]["attributes"].as_object() {
+                    Some(a) => a,
+                    None => continue,
+                };
+                for (path, value) in attributes {
+                    if path.ends_with(".#") || path.ends_with(".%") {
+                        continue;
+                    }
+                    let key = path.rsplit('.').next().unwrap_or(path);
+                    let attribute_path = format!("{}{}.{}", prefix, address, path);
+                    self.add(&attribute_path, key, value, SensitivePaths::new());
+                }
+            }
+        }
+        self.outputs(&prefix, &module["outputs"]);
+    }
+
+    /// Adds the resources of a module of a plan or of `terraform show -json`, and of its child
+    /// modules, which name each resource by its full address
+    fn module(&mut self, module: &Value) {
+        for resource in array(&module["resources"]) {
+            if let Some(address) = resource["address"].as_str() {
+                let sensitive = sensitive_paths(&resource["sensitive_values"]);
+                self.add(address, "", &resource["values"], sensitive);
+            }
+        }
+        for child in array(&module["child_modules"]) {
+            self.module(child);
+        }
+    }
+}
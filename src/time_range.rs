@@ -0,0 +1,127 @@
+//! A UTC time range for scanning only recent (or only old) content.
+//!
+//! Sources whose content carries a time (commit dates, object modification times, workflow run
+//! and build start times) take `--since` and `--until` options, which `SecretScannerBuilder`
+//! parses into the scanner's `time_range`. Each scanner then skips content outside the range.
+//!
+//! Times are given as:
+//!
+//! * a date, `2020-06-01`. As `until`, a date includes the whole day.
+//! * a date and time in UTC, `2020-06-01T12:00:00`, or with an offset as in RFC 3339,
+//!   `2020-06-01T12:00:00+02:00`
+//! * a time relative to now: `-30m`, `-12h`, `-7d` or `-2w` (seconds with `s`), or `now`
+//!
+//! # Examples
+//!
+//! ```
+//! use rusty_hogs::time_range::TimeRange;
+//!
+//! let range = TimeRange::parse(Some("2020-06-01"), Some("2020-06-30")).unwrap();
+//! assert!(range.contains_str("2020-06-30T23:00:00.000Z"));
+//! assert!(!range.contains_str("2020-07-01T00:00:00.000Z"));
+//!
+//! let recent = TimeRange::parse(Some("-7d"), None).unwrap();
+//! assert!(!recent.contains_str("2020-06-01T00:00:00Z"));
+//! assert!(TimeRange::parse(Some("last tuesday"), None).is_err());
+//! ```
+
+use chrono::{DateTime, Duration, NaiveDate, NaiveDateTime, Utc};
+use simple_error::SimpleError;
+
+/// An optional start and end time, both inclusive
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy, Default)]
+pub struct TimeRange {
+    pub since: Option<DateTime<Utc>>,
+    pub until: Option<DateTime<Utc>>,
+}
+
+impl TimeRange {
+    /// Parses the `--since` and `--until` values (see the module documentation for the formats)
+    pub fn parse(since: Option<&str>, until: Option<&str>) -> Result<Self, SimpleError> {
+        let now = Utc::now();
+        let since = match since {
+            Some(s) => Some(parse_time(s, now)?),
+            None => None,
+        };
+        let until = match until {
+            // a date includes the whole day
+            Some(s) => Some(match NaiveDate::parse_from_str(s, "%Y-%m-%d") {
+                Ok(d) => DateTime::from_utc(d.and_hms(23, 59, 59), Utc),
+                Err(_) => parse_time(s, now)?,
+            }),
+            None => None,
+        };
+        Ok(Self { since, until })
+    }
+
+    /// True if neither end of the range is set
+    pub fn is_unbounded(&self) -> bool {
+        self.since.is_none() && self.until.is_none()
+    }
+
+    /// True if `time` is within the range
+    pub fn contains(&self, time: &DateTime<Utc>) -> bool {
+        self.since.map_or(true, |s| *time >= s) && self.until.map_or(true, |u| *time <= u)
+    }
+
+    /// True if `timestamp`, in seconds since the Unix epoch, is within the range
+    pub fn contains_timestamp(&self, timestamp: i64) -> bool {
+        self.contains(&DateTime::from_utc(
+            NaiveDateTime::from_timestamp(timestamp, 0),
+            Utc,
+        ))
+    }
+
+    /// True if `time`, in RFC 3339 format, is within the range. Times that can't be parsed are
+    /// treated as within the range, so that nothing is skipped by mistake.
+    pub fn contains_str(&self, time: &str) -> bool {
+        match DateTime::parse_from_rfc3339(time) {
+            Ok(t) => self.contains(&t.with_timezone(&Utc)),
+            Err(_) => true,
+        }
+    }
+}
+
+/// Parses an absolute or relative time (see the module documentation), relative to `now`
+pub fn parse_time(value: &str, now: DateTime<Utc>) -> Result<DateTime<Utc>, SimpleError> {
+    let value = value.trim();
+    if value == "now" {
+        return Ok(now);
+    }
+    if value.starts_with('-') && value.len() > 2 && value.is_char_boundary(value.len() - 1) {
+        let amount = &value[1..value.len() - 1];
+        if let Ok(amount) = amount.parse::<i64>() {
+            let duration = match value.chars().last() {
+                Some('s') => Some(Duration::seconds(amount)),
+                Some('m') => Some(Duration::minutes(amount)),
+                Some('h') => Some(Duration::hours(amount)),
+                Some('d') => Some(Duration::days(amount)),
+                Some('w') => Some(Duration::weeks(amount)),
+                _ => None,
+            };
+            if let Some(d) = duration {
+                return Ok(now - d);
+            }
+        }
+    }
+    if let Ok(t) = DateTime::parse_from_rfc3339(value) {
+        return Ok(t.with_timezone(&Utc));
+    }
+    if let Ok(t) = NaiveDateTime::parse_from_str(value, "%Y-%m-%dT%H:%M:%S") {
+        return Ok(DateTime::from_utc(t, Utc));
+    }
+    if let Ok(d) = NaiveDate::parse_from_str(value, "%Y-%m-%d") {
+        return Ok(DateTime::from_utc(d.and_hms(0, 0, 0), Utc));
+    }
+    Err(SimpleError::new(format!(
+        "Invalid time {:?}, expected YYYY-MM-DD, an RFC 3339 time or a relative time such as -7d",
+        value
+    )))
+}
+
+/// Checks a `--since` or `--until` value, for use as a clap validator
+pub fn validate_time(value: String) -> Result<(), String> {
+    parse_time(&value, Utc::now())
+        .map(|_| ())
+        .map_err(|e| e.to_string())
+}
@@ -0,0 +1,156 @@
+//! Temporary directories for repository clones, downloads and unpacked images.
+//!
+//! Scanners that need disk space create a `Workspace` with `SecretScanner::workspace`. Each
+//! workspace is a uniquely named directory, so parallel scans (and other processes on a shared
+//! runner) never collide, under a configurable root (`--tmpdir`, the system temporary directory by
+//! default). A workspace is removed when it is dropped, including when a scan panics, and every
+//! workspace of the process is removed if it is stopped with SIGINT or SIGTERM (see the `shutdown`
+//! module).
+//!
+//! `--tmp-quota` limits how much disk space all the workspaces of a process may use together. It
+//! is checked after each clone, download or extraction with `check_quota`, which fails the scan of
+//! the repository or image that went over it.
+//!
+//! # Examples
+//!
+//! ```
+//! use rusty_hogs::workspace::{Workspace, WorkspaceOptions};
+//! use std::fs;
+//!
+//! let options = WorkspaceOptions {
+//!     root: None,
+//!     quota: Some(16),
+//! };
+//! let workspace = Workspace::new(&options).unwrap();
+//! let path = workspace.path().to_path_buf();
+//! assert!(workspace.check_quota().is_ok());
+//!
+//! fs::write(path.join("layer.tar"), vec![0; 32]).unwrap();
+//! assert!(workspace.check_quota().is_err());
+//!
+//! drop(workspace);
+//! assert!(!path.exists());
+//! ```
+
+use crate::shutdown;
+use lazy_static::lazy_static;
+use log::{self, debug, error};
+use simple_error::{try_with, SimpleError};
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use tempdir::TempDir;
+
+/// The prefix of workspace directory names
+const PREFIX: &str = "rusty_hogs";
+
+lazy_static! {
+    /// The directories of the workspaces that haven't been dropped yet
+    static ref LIVE: Mutex<HashSet<PathBuf>> = Mutex::new(HashSet::new());
+}
+
+/// Where workspaces are created and how much space they may use
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Default)]
+pub struct WorkspaceOptions {
+    /// The directory workspaces are created in (and created if needed), None for the system
+    /// temporary directory
+    pub root: Option<String>,
+    /// The most bytes the workspaces of this process may use together, None for unlimited
+    pub quota: Option<u64>,
+}
+
+/// A temporary directory that is removed when dropped, see the module documentation
+#[derive(Debug)]
+pub struct Workspace {
+    dir: TempDir,
+    quota: Option<u64>,
+}
+
+impl Workspace {
+    /// Creates a workspace, making sure SIGINT and SIGTERM remove it
+    pub fn new(options: &WorkspaceOptions) -> Result<Self, SimpleError> {
+        shutdown::install_handler();
+        let dir = match &options.root {
+            Some(root) => {
+                try_with!(fs::create_dir_all(root), "Failed to create {}", root);
+                TempDir::new_in(root, PREFIX)
+            }
+            None => TempDir::new(PREFIX),
+        };
+        let dir = try_with!(dir, "Failed to create a temporary directory");
+        if let Ok(mut live) = LIVE.lock() {
+            live.insert(dir.path().to_path_buf());
+        }
+        debug!("Created workspace {}", dir.path().display());
+        Ok(Self {
+            dir,
+            quota: options.quota,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        self.dir.path()
+    }
+
+    /// Fails if the workspaces of this process use more than the quota
+    pub fn check_quota(&self) -> Result<(), SimpleError> {
+        let quota = match self.quota {
+            Some(q) => q,
+            None => return Ok(()),
+        };
+        let used = usage();
+        if used > quota {
+            return Err(SimpleError::new(format!(
+                "Temporary directories use {} bytes, over the quota of {} bytes",
+                used, quota
+            )));
+        }
+        Ok(())
+    }
+}
+
+impl Drop for Workspace {
+    fn drop(&mut self) {
+        // the directory itself is removed when `dir` is dropped
+        if let Ok(mut live) = LIVE.lock() {
+            live.remove(self.dir.path());
+        }
+    }
+}
+
+/// The bytes used by every workspace of this process
+pub fn usage() -> u64 {
+    match LIVE.lock() {
+        Ok(live) => live.iter().map(|p| dir_size(p)).sum(),
+        Err(_) => 0,
+    }
+}
+
+/// Removes the directories of every workspace of this process, for when it is about to exit
+/// without dropping them
+pub fn remove_all() {
+    if let Ok(live) = LIVE.lock() {
+        for path in live.iter() {
+            if let Err(e) = fs::remove_dir_all(path) {
+                error!("Failed to remove {}: {}", path.display(), e);
+            }
+        }
+    }
+}
+
+/// The size of the files under a directory, not following symlinks
+fn dir_size(path: &Path) -> u64 {
+    let entries = match fs::read_dir(path) {
+        Ok(e) => e,
+        Err(_) => return 0,
+    };
+    entries
+        .filter_map(Result::ok)
+        .map(|entry| match entry.file_type() {
+            Ok(t) if t.is_dir() => dir_size(&entry.path()),
+            Ok(_) => entry.metadata().map(|m| m.len()).unwrap_or(0),
+            Err(_) => 0,
+        })
+        .sum()
+}
@@ -0,0 +1,364 @@
+//! Collection of tools for scanning Zendesk tickets for secrets.
+//!
+//! `ZendeskScanner` acts as a wrapper around a `SecretScanner` object and talks to the
+//! [Zendesk Support API](https://developer.zendesk.com/api-reference/) with an API token. It
+//! lists the tickets updated since a point in time with the incremental export API, then scans
+//! the subject and custom fields of each ticket along with the text of its comments, public
+//! replies and internal notes alike.
+//!
+//! The API token belongs to an admin, as the incremental export API is only open to admins.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use rusty_hogs::zendesk_scanning::{ZendeskConfig, ZendeskScanner};
+//!
+//! let zs = ZendeskScanner::new();
+//! let config = ZendeskConfig::new("acme", "admin@acme.com", "my-api-token");
+//! for ticket in zs.list_tickets(&config, 0).unwrap() {
+//!     let findings = zs.scan_ticket(&config, &ticket, false).unwrap();
+//!     zs.secret_scanner.output_findings(&findings);
+//! }
+//! ```
+
+use crate::network;
+use crate::retry;
+use crate::{Finding, SecretScanner, Span, TextFinding};
+use log::{self, debug, info};
+use reqwest::blocking::Response;
+use serde_derive::{Deserialize, Serialize};
+use serde_json::Value;
+use simple_error::{try_with, SimpleError};
+use std::collections::{HashMap, HashSet};
+
+/// The most comments Zendesk returns per page
+const PAGE_SIZE: usize = 100;
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Hash, Clone, Default)]
+/// `serde_json` object that represents a single found secret - finding
+pub struct ZendeskFinding {
+    pub diff: String,
+    #[serde(rename = "stringsFound")]
+    pub strings_found: Vec<String>,
+    /// The ticket number
+    pub ticket: u64,
+    pub subject: String,
+    /// `tickets/<ticket number>/` followed by `subject`, `fields/<field ID>` or
+    /// `comments/<comment ID>`
+    pub path: String,
+    /// A link to the ticket in the agent interface
+    pub url: String,
+    /// The email address (or else the name) of the comment author
+    pub user: String,
+    /// When the comment was posted, or the ticket was last updated
+    pub date: String,
+    pub reason: String,
+    /// Where each of `strings_found` is in the text, see `Span`
+    #[serde(default)]
+    pub spans: Vec<Span>,
+}
+
+impl TextFinding for ZendeskFinding {
+    fn with_match(&self, found: Finding) -> Self {
+        Self {
+            diff: found.diff,
+            strings_found: found.strings_found,
+            reason: found.reason,
+            spans: found.spans,
+            ..self.clone()
+        }
+    }
+}
+
+/// The Zendesk instance to talk to and the API token to authenticate with
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+pub struct ZendeskConfig {
+    /// The base URL of the instance, e.g. `https://acme.zendesk.com`
+    pub url: String,
+    /// The email address of the admin the API token belongs to
+    pub email: String,
+    pub token: String,
+}
+
+/// A brand of the instance. Only the fields we need are included.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Hash, Clone, Default)]
+pub struct Brand {
+    pub id: u64,
+    pub name: String,
+    pub subdomain: String,
+}
+
+/// A ticket. Only the fields we need are included.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Hash, Clone, Default)]
+pub struct Ticket {
+    pub id: u64,
+    pub subject: String,
+    pub status: String,
+    pub brand_id: Option<u64>,
+    /// When the ticket was last updated (RFC 3339)
+    pub updated_at: String,
+    /// The value of each custom field that has a text value, by field ID
+    pub fields: Vec<(u64, String)>,
+}
+
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+/// Contains helper functions for performing scans of Zendesk tickets
+pub struct ZendeskScanner {
+    pub secret_scanner: SecretScanner,
+}
+
+impl ZendeskConfig {
+    /// Creates the config of an instance given by its subdomain, host name or URL
+    ///
+    /// ```
+    /// use rusty_hogs::zendesk_scanning::ZendeskConfig;
+    ///
+    /// let url = |instance| ZendeskConfig::new(instance, "admin@acme.com", "token").url;
+    /// assert_eq!(url("acme"), "https://acme.zendesk.com");
+    /// assert_eq!(url("support.acme.com"), "https://support.acme.com");
+    /// assert_eq!(url("https://acme.zendesk.com/"), "https://acme.zendesk.com");
+    /// ```
+    pub fn new(instance: &str, email: &str, token: &str) -> Self {
+        let instance = instance.trim_end_matches('/');
+        let url = if instance.contains("://") {
+            instance.to_string()
+        } else if instance.contains('.') {
+            format!("https://{}", instance)
+        } else {
+            format!("https://{}.zendesk.com", instance)
+        };
+        Self {
+            url,
+            email: email.to_string(),
+            token: token.to_string(),
+        }
+    }
+}
+
+/// Acts as a wrapper around a `SecretScanner` object to provide helper functions for performing
+/// scanning against Zendesk. Relies on the [reqwest](https://docs.rs/reqwest/) library for HTTP
+/// access.
+impl ZendeskScanner {
+    /// Initialize the SecretScanner object first using the SecretScannerBuilder, then provide
+    /// it to this constructor method.
+    pub fn new_from_scanner(secret_scanner: SecretScanner) -> Self {
+        Self { secret_scanner }
+    }
+
+    pub fn new() -> Self {
+        Self {
+            secret_scanner: SecretScanner::default(),
+        }
+    }
+
+    /// Returns the brands of the instance
+    pub fn list_brands(&self, config: &ZendeskConfig) -> Result<Vec<Brand>, SimpleError> {
+        let mut brands: Vec<Brand> = Vec::new();
+        let mut next = Some(format!("{}/api/v2/brands.json", config.url));
+        while let Some(url) = next {
+            let page = get_json(config, &url)?;
+            brands.extend(
+                page["brands"]
+                    .as_array()
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|b| serde_json::from_value(b.clone()).ok()),
+            );
+            next = page["next_page"].as_str().map(String::from);
+        }
+        Ok(brands)
+    }
+
+    /// Returns the tickets updated at or after `start_time` (in seconds since the Unix epoch),
+    /// leaving out deleted tickets. A ticket updated while it is being listed is returned once,
+    /// as it was last listed.
+    pub fn list_tickets(
+        &self,
+        config: &ZendeskConfig,
+        start_time: i64,
+    ) -> Result<Vec<Ticket>, SimpleError> {
+        let mut tickets: Vec<Ticket> = Vec::new();
+        let mut positions: HashMap<u64, usize> = HashMap::new();
+        let mut url = format!(
+            "{}/api/v2/incremental/tickets/cursor.json?start_time={}",
+            config.url,
+            start_time.max(0)
+        );
+        loop {
+            let page = get_json(config, &url)?;
+            for ticket in page["tickets"].as_array().into_iter().flatten() {
+                if ticket["status"] == "deleted" {
+                    continue;
+                }
+                let ticket = Ticket {
+                    id: ticket["id"].as_u64().unwrap_or(0),
+                    subject: ticket["subject"].as_str().unwrap_or("").to_string(),
+                    status: ticket["status"].as_str().unwrap_or("").to_string(),
+                    brand_id: ticket["brand_id"].as_u64(),
+                    updated_at: ticket["updated_at"].as_str().unwrap_or("").to_string(),
+                    fields: ticket["custom_fields"]
+                        .as_array()
+                        .into_iter()
+                        .flatten()
+                        .filter_map(|f| match (f["id"].as_u64(), f["value"].as_str()) {
+                            (Some(id), Some(value)) if !value.is_empty() => {
+                                Some((id, value.to_string()))
+                            }
+                            _ => None,
+                        })
+                        .collect(),
+                };
+                match positions.get(&ticket.id) {
+                    Some(&i) => tickets[i] = ticket,
+                    None => {
+                        positions.insert(ticket.id, tickets.len());
+                        tickets.push(ticket);
+                    }
+                }
+            }
+            debug!("Listed {} tickets", tickets.len());
+            match page["after_url"].as_str() {
+                Some(after_url) if page["end_of_stream"] != true => url = after_url.to_string(),
+                _ => break,
+            }
+        }
+        Ok(tickets)
+    }
+
+    /// Scans the subject and custom fields of `ticket` and the comments on it that were posted
+    /// within the scanner's time range. Returns a HashSet of findings.
+    pub fn scan_ticket(
+        &self,
+        config: &ZendeskConfig,
+        ticket: &Ticket,
+        scan_entropy: bool,
+    ) -> Result<HashSet<ZendeskFinding>, SimpleError> {
+        info!("Scanning ticket {}", ticket.id);
+        let time_range = self.secret_scanner.time_range;
+        let template = ZendeskFinding {
+            ticket: ticket.id,
+            subject: ticket.subject.clone(),
+            path: format!("tickets/{}/subject", ticket.id),
+            url: format!("{}/agent/tickets/{}", config.url, ticket.id),
+            date: ticket.updated_at.clone(),
+            ..Default::default()
+        };
+        let mut findings: HashSet<ZendeskFinding> = HashSet::new();
+        self.secret_scanner.scan_text(
+            ticket.subject.as_bytes(),
+            &template,
+            scan_entropy,
+            &mut findings,
+        );
+        for (id, value) in &ticket.fields {
+            let template = ZendeskFinding {
+                path: format!("tickets/{}/fields/{}", ticket.id, id),
+                ..template.clone()
+            };
+            self.secret_scanner
+                .scan_text(value.as_bytes(), &template, scan_entropy, &mut findings);
+        }
+
+        let mut authors: HashMap<u64, String> = HashMap::new();
+        let mut next = Some(format!(
+            "{}/api/v2/tickets/{}/comments.json?page[size]={}&include=users",
+            config.url, ticket.id, PAGE_SIZE
+        ));
+        while let Some(url) = next {
+            let page = get_json(config, &url)?;
+            for user in page["users"].as_array().into_iter().flatten() {
+                let name = user["email"].as_str().or_else(|| user["name"].as_str());
+                if let (Some(id), Some(name)) = (user["id"].as_u64(), name) {
+                    authors.insert(id, name.to_string());
+                }
+            }
+            for comment in page["comments"].as_array().into_iter().flatten() {
+                let date = comment["created_at"].as_str().unwrap_or("");
+                if !time_range.contains_str(date) {
+                    continue;
+                }
+                let template = ZendeskFinding {
+                    path: format!(
+                        "tickets/{}/comments/{}",
+                        ticket.id,
+                        comment["id"].as_u64().unwrap_or(0)
+                    ),
+                    user: comment["author_id"]
+                        .as_u64()
+                        .and_then(|id| authors.get(&id))
+                        .cloned()
+                        .unwrap_or_default(),
+                    date: date.to_string(),
+                    ..template.clone()
+                };
+                let body = comment["plain_body"]
+                    .as_str()
+                    .or_else(|| comment["body"].as_str())
+                    .unwrap_or("");
+                self.secret_scanner.scan_text(
+                    body.as_bytes(),
+                    &template,
+                    scan_entropy,
+                    &mut findings,
+                );
+            }
+            next = if page["meta"]["has_more"] == true {
+                page["links"]["next"].as_str().map(String::from)
+            } else {
+                None
+            };
+        }
+        Ok(findings)
+    }
+}
+
+impl Default for ZendeskScanner {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Performs a GET of a full API URL, as the next page links are, waiting and retrying when rate
+/// limited, and parses the response as JSON
+fn get_json(config: &ZendeskConfig, url: &str) -> Result<Value, SimpleError> {
+    debug!("GET {}", url);
+    let response = try_with!(
+        retry::send(
+            network::client()
+                .get(url)
+                .basic_auth(format!("{}/token", config.email), Some(&config.token))
+        ),
+        "Zendesk request failed"
+    );
+    let response = check(response, url)?;
+    Ok(try_with!(
+        response.json(),
+        "Failed to parse Zendesk response"
+    ))
+}
+
+/// Turns an unsuccessful response into an error with the description Zendesk gives
+fn check(response: Response, url: &str) -> Result<Response, SimpleError> {
+    let status = response.status();
+    if status.is_success() {
+        return Ok(response);
+    }
+    let body = response.text().unwrap_or_default();
+    let description = match serde_json::from_str::<Value>(&body) {
+        Ok(error) => match &error["error"] {
+            Value::String(e) => e.clone(),
+            e => e["message"]
+                .as_str()
+                .or_else(|| error["description"].as_str())
+                .unwrap_or("")
+                .to_string(),
+        },
+        Err(_) => body,
+    };
+    Err(SimpleError::new(format!(
+        "Zendesk returned {} for {}: {}",
+        status,
+        url,
+        description.trim()
+    )))
+}